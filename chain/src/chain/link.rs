@@ -165,7 +165,11 @@ where
     /// If compatible then the submission can be associated with the link.
     pub fn compatible(&self, node: &LinkNode<R>) -> bool {
         // If queue the same and states are compatible.
-        self.family == node.sid.family() && !(self.access | node.state.access).exclusive()
+        // Reads in different layouts, e.g. sampling and storage image reads,
+        // are separated by layout transition.
+        self.family == node.sid.family()
+            && self.layout == node.state.layout
+            && !(self.access | node.state.access).exclusive()
     }
 
     /// Insert submission with specified state to the link.
@@ -39,6 +39,7 @@ struct ResolvedNode {
     id: usize,
     family: rendy_core::hal::queue::QueueFamilyId,
     queues: Range<usize>,
+    deps: Vec<usize>,
     rev_deps: Vec<usize>,
     buffers: Vec<(usize, State<Buffer>)>,
     images: Vec<(usize, State<Image>)>,
@@ -50,6 +51,7 @@ impl Default for ResolvedNode {
             id: 0,
             family: rendy_core::hal::queue::QueueFamilyId(0),
             queues: 0..0,
+            deps: Vec::new(),
             rev_deps: Vec::new(),
             buffers: Vec::new(),
             images: Vec::new(),
@@ -135,19 +137,30 @@ where
             scheduled += 1;
         }
     } else {
+        // Queue each node was scheduled to.
+        let mut node_queues = vec![None; nodes.nodes.len()];
+
         while !ready_nodes.is_empty() {
             // Among ready nodes find best fit.
             let (fitness, qid, index) = ready_nodes
                 .iter()
                 .enumerate()
                 .map(|(index, &node)| {
-                    let (fitness, qid) = fitness(node, &mut images, &mut buffers, &mut schedule);
+                    let (fitness, qid) = fitness(
+                        node,
+                        &nodes,
+                        &node_queues,
+                        &mut images,
+                        &mut buffers,
+                        &mut schedule,
+                    );
                     (fitness, qid, index)
                 })
                 .min()
                 .unwrap();
 
             let node = ready_nodes.swap_remove(index);
+            node_queues[node.id] = Some(qid);
             schedule_node(
                 &mut ready_nodes,
                 &mut unscheduled_nodes,
@@ -241,7 +254,9 @@ where
         for dep in node.dependencies {
             // Duplicated dependencies work fine, since they push two rev_deps entries and add two
             // to unscheduled_nodes.
-            reified_nodes[node_ids.forward(dep)].rev_deps.push(id);
+            let dep = node_ids.forward(dep);
+            reified_nodes[dep].rev_deps.push(id);
+            reified_nodes[id].deps.push(dep);
         }
         unscheduled_nodes[id] = unscheduled_count;
 
@@ -288,8 +303,36 @@ fn reify_schedule(vec: Vec<QueueData>) -> Schedule<Unsynchronized> {
     schedule
 }
 
+/// Find queue dependency of the `node` is scheduled to
+/// if they share no resources and therefore can't be synchronized with semaphores.
+fn pinned_queue(
+    node: &ResolvedNode,
+    nodes: &ResolvedNodeSet,
+    node_queues: &[Option<usize>],
+) -> Option<usize> {
+    node.deps.iter().find_map(|&dep| {
+        let dep_node = &nodes.nodes[dep];
+        let shares_resources = dep_node
+            .buffers
+            .iter()
+            .any(|&(id, _)| node.buffers.iter().any(|&(other, _)| id == other))
+            || dep_node
+                .images
+                .iter()
+                .any(|&(id, _)| node.images.iter().any(|&(other, _)| id == other));
+
+        if dep_node.family == node.family && !shares_resources {
+            node_queues[dep]
+        } else {
+            None
+        }
+    })
+}
+
 fn fitness(
     node: &ResolvedNode,
+    nodes: &ResolvedNodeSet,
+    node_queues: &[Option<usize>],
     images: &mut Vec<ChainData<Image>>,
     buffers: &mut Vec<ChainData<Buffer>>,
     schedule: &mut Vec<QueueData>,
@@ -320,9 +363,13 @@ fn fitness(
     }
 
     // Find best queue for node.
-    let (wait_factor_from_queue, queue) = node
-        .queues
-        .clone()
+    // Independent nodes spread among queues of the family
+    // as the least loaded queue is picked.
+    let queues = match pinned_queue(node, nodes, node_queues) {
+        Some(queue) => queue..queue + 1,
+        None => node.queues.clone(),
+    };
+    let (wait_factor_from_queue, queue) = queues
         .map(|index| (schedule[index].wait_factor, index))
         .min()
         .unwrap();
@@ -423,3 +470,76 @@ fn add_to_chain<R, S>(
         chain.add_link(link);
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use {super::*, rendy_core::hal};
+
+    fn compute_node(id: usize, dependencies: Vec<usize>, buffer: Option<usize>) -> Node {
+        let mut buffers = HashMap::new();
+        if let Some(buffer) = buffer {
+            buffers.insert(
+                Id(buffer),
+                State {
+                    access: hal::buffer::Access::SHADER_WRITE,
+                    layout: (),
+                    stages: hal::pso::PipelineStage::COMPUTE_SHADER,
+                    usage: hal::buffer::Usage::STORAGE,
+                },
+            );
+        }
+        Node {
+            id,
+            family: hal::queue::QueueFamilyId(0),
+            dependencies,
+            buffers,
+            images: HashMap::new(),
+        }
+    }
+
+    fn queue_of(chains: &Chains, node: usize) -> usize {
+        chains
+            .schedule
+            .iter()
+            .flat_map(|family| family.iter())
+            .find(|queue| queue.iter().any(|submission| submission.node() == node))
+            .unwrap()
+            .id()
+            .index()
+    }
+
+    #[test]
+    fn independent_nodes_use_different_queues() {
+        let nodes = vec![
+            compute_node(0, vec![], Some(0)),
+            compute_node(1, vec![], Some(1)),
+        ];
+        let chains = collect(nodes, |_| 2);
+        assert_ne!(queue_of(&chains, 0), queue_of(&chains, 1));
+    }
+
+    #[test]
+    fn dependency_without_resources_shares_queue() {
+        let nodes = vec![
+            compute_node(0, vec![], Some(0)),
+            compute_node(1, vec![], Some(1)),
+            compute_node(2, vec![0], None),
+            compute_node(3, vec![1], None),
+        ];
+        let chains = collect(nodes, |_| 2);
+        assert_eq!(queue_of(&chains, 0), queue_of(&chains, 2));
+        assert_eq!(queue_of(&chains, 1), queue_of(&chains, 3));
+    }
+
+    #[test]
+    fn dependency_through_resource_is_not_pinned() {
+        // Dependency is synchronized through the buffer only,
+        // so dependent node may run on the other queue.
+        let nodes = vec![
+            compute_node(0, vec![], Some(0)),
+            compute_node(1, vec![0], Some(0)),
+        ];
+        let chains = collect(nodes, |_| 2);
+        assert_ne!(queue_of(&chains, 0), queue_of(&chains, 1));
+    }
+}
@@ -29,5 +29,8 @@ pub use crate::{
     node::{BufferState, ImageState, Node, State},
     resource::{AccessFlags, Buffer, Image, Resource, UsageFlags},
     schedule::{Family, Queue, QueueId, Schedule, Submission, SubmissionId},
-    sync::{sync, Barrier, Barriers, BufferBarriers, Guard, ImageBarriers, Signal, SyncData, Wait},
+    sync::{
+        sync, sync_semaphores, Barrier, Barriers, BufferBarriers, Guard, ImageBarriers, Signal,
+        SyncData, Wait,
+    },
 };
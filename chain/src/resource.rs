@@ -62,7 +62,7 @@ pub trait Resource: 'static {
     type Usage: UsageFlags;
 
     /// Layout type for the resource.
-    type Layout: Copy + Debug + 'static;
+    type Layout: Copy + Debug + PartialEq + 'static;
 
     /// Empty usage.
     fn no_usage() -> Self::Usage;
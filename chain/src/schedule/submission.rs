@@ -62,6 +62,11 @@ impl<S> Submission<S> {
         &self.sync
     }
 
+    /// Get mutable synchronization for `Submission`.
+    pub fn sync_mut(&mut self) -> &mut S {
+        &mut self.sync
+    }
+
     /// Get wait factor for `Submission`
     pub fn wait_factor(&self) -> usize {
         self.wait_factor
@@ -77,6 +82,12 @@ impl<S> Submission<S> {
         self.buffer_links[&id]
     }
 
+    /// Get link index for resource by id.
+    /// Returns `None` if submission doesn't reference the resource.
+    pub fn try_buffer_link_index(&self, id: Id) -> Option<usize> {
+        self.buffer_links.get(&id).cloned()
+    }
+
     /// Set link index for given chain.
     pub fn set_buffer_link(&mut self, id: Id, link: usize) {
         assert!(self.buffer_links.insert(id, link).is_none());
@@ -87,6 +98,12 @@ impl<S> Submission<S> {
         self.image_links[&id]
     }
 
+    /// Get link index for resource by id.
+    /// Returns `None` if submission doesn't reference the resource.
+    pub fn try_image_link_index(&self, id: Id) -> Option<usize> {
+        self.image_links.get(&id).cloned()
+    }
+
     /// Set link index for given chain.
     pub fn set_image_link(&mut self, id: Id, link: usize) {
         assert!(self.image_links.insert(id, link).is_none());
@@ -96,45 +96,39 @@ where
         }
     }
 
-    fn transfer(
+    /// Release side of ownership transfer.
+    /// Makes writes in `prev` state available and releases resource from its family.
+    fn release(
         families: Range<rendy_core::hal::queue::QueueFamilyId>,
-        states: Range<(R::Access, R::Layout)>,
+        prev: State<R>,
+        next: RangeTo<R::Layout>,
     ) -> Self {
         Barrier {
             families: Some(families),
-            states: (
-                states.start.0,
-                states.start.1,
-                rendy_core::hal::pso::PipelineStage::TOP_OF_PIPE,
-            )
+            states: (prev.access, prev.layout, prev.stages)
                 ..(
-                    states.end.0,
-                    states.end.1,
+                    R::Access::empty(),
+                    next.end,
                     rendy_core::hal::pso::PipelineStage::BOTTOM_OF_PIPE,
                 ),
         }
     }
 
+    /// Acquire side of ownership transfer.
+    /// Acquires resource in the family and makes it visible for access in `next` state.
     fn acquire(
         families: Range<rendy_core::hal::queue::QueueFamilyId>,
-        left: RangeFrom<R::Layout>,
-        right: RangeTo<(R::Access, R::Layout)>,
-    ) -> Self {
-        Self::transfer(
-            families,
-            (R::Access::empty(), left.start)..(right.end.0, right.end.1),
-        )
-    }
-
-    fn release(
-        families: Range<rendy_core::hal::queue::QueueFamilyId>,
-        left: RangeFrom<(R::Access, R::Layout)>,
-        right: RangeTo<R::Layout>,
+        prev: RangeFrom<R::Layout>,
+        next: State<R>,
     ) -> Self {
-        Self::transfer(
-            families,
-            (left.start.0, left.start.1)..(R::Access::empty(), right.end),
-        )
+        Barrier {
+            families: Some(families),
+            states: (
+                R::Access::empty(),
+                prev.start,
+                rendy_core::hal::pso::PipelineStage::TOP_OF_PIPE,
+            )..(next.access, next.layout, next.stages),
+        }
     }
 }
 
@@ -319,6 +313,65 @@ where
     result
 }
 
+/// Add semaphores between submissions of nodes
+/// in addition to semaphores generated by `sync` for resources.
+/// Each dependency is `(signal, wait, stages)` where `signal` and `wait` are node ids
+/// and `wait` node waits for semaphore signaled by `signal` node at `stages`.
+///
+/// Node `wait` must be scheduled after node `signal`.
+/// Dependencies between nodes on the same queue are skipped
+/// since submissions of one queue are already executed in order.
+pub fn sync_semaphores<F, S, W>(
+    schedule: &mut Schedule<SyncData<S, W>>,
+    dependencies: impl IntoIterator<Item = (usize, usize, rendy_core::hal::pso::PipelineStage)>,
+    mut new_semaphore: F,
+) where
+    F: FnMut() -> (S, W),
+{
+    let find = |schedule: &Schedule<SyncData<S, W>>, node: usize| {
+        schedule
+            .iter()
+            .flat_map(|family| family.iter())
+            .flat_map(|queue| queue.iter())
+            .find(|submission| submission.node() == node)
+            .map(|submission| submission.id())
+            .expect("Node must be scheduled")
+    };
+
+    for (signal, wait, stages) in dependencies {
+        let signal_sid = find(schedule, signal);
+        let wait_sid = find(schedule, wait);
+        if signal_sid.queue() == wait_sid.queue() {
+            assert!(
+                signal_sid.index() < wait_sid.index(),
+                "Node {} must be scheduled after node {}",
+                wait,
+                signal
+            );
+            log::debug!(
+                "Node {} and {} are on the same queue, semaphore is not required",
+                signal,
+                wait
+            );
+            continue;
+        }
+
+        let (signal_semaphore, wait_semaphore) = new_semaphore();
+        schedule
+            .submission_mut(signal_sid)
+            .unwrap()
+            .sync_mut()
+            .signal
+            .push(Signal::new(signal_semaphore));
+        schedule
+            .submission_mut(wait_sid)
+            .unwrap()
+            .sync_mut()
+            .wait
+            .push(Wait::new(wait_semaphore, stages));
+    }
+}
+
 // submit_order creates a consistent direction in which semaphores are generated, avoiding issues
 // with deadlocks.
 fn latest<R, S>(link: &Link<R>, schedule: &Schedule<S>) -> SubmissionId
@@ -418,27 +471,37 @@ where
                     .pick()
                     .insert(id, Barrier::new(prev_link.state()..link.state()));
 
-                if !link.access().exclusive() {
-                    unimplemented!("This case is unimplemented");
+                // Consecutive reads on the same family are split only by layout change.
+                // The barrier makes the transition wait for preceding reads.
+                // Shared access may span multiple queues of the family,
+                // other queues wait for the submission that changes the layout.
+                for (queue_id, queue) in link.queues() {
+                    let head = SubmissionId::new(queue_id, queue.first);
+                    generate_semaphore_pair(sync, uid, link, wait_sid..head);
                 }
             }
         } else {
             let signal_sid = latest(prev_link, schedule);
             let wait_sid = earliest(link, schedule);
 
-            if !prev_link.access().exclusive() {
-                unimplemented!("This case is unimplemented");
+            // Shared access may span multiple queues of the source family.
+            // Resource is released after all of them.
+            for (queue_id, queue) in prev_link.queues() {
+                let tail = SubmissionId::new(queue_id, queue.last);
+                generate_semaphore_pair(sync, uid, prev_link, tail..signal_sid);
             }
 
             // Generate a semaphore between the signal and wait sides of the transfer.
             generate_semaphore_pair(sync, uid, link, signal_sid..wait_sid);
 
             // Generate barriers to transfer the resource to another family.
+            // Release must be recorded on the queue of the source family
+            // and acquire on the queue of the destination family.
             sync.get_sync(signal_sid).release.pick::<R>().insert(
                 id,
                 Barrier::release(
                     signal_sid.family()..wait_sid.family(),
-                    (prev_link.access(), prev_link.layout())..,
+                    prev_link.state(),
                     ..link.layout(),
                 ),
             );
@@ -447,12 +510,15 @@ where
                 Barrier::acquire(
                     signal_sid.family()..wait_sid.family(),
                     prev_link.layout()..,
-                    ..(link.access(), link.layout()),
+                    link.state(),
                 ),
             );
 
-            if !link.access().exclusive() {
-                unimplemented!("This case is unimplemented");
+            // Shared access may span multiple queues of the destination family.
+            // Resource is acquired once, other queues wait for the acquiring submission.
+            for (queue_id, queue) in link.queues() {
+                let head = SubmissionId::new(queue_id, queue.first);
+                generate_semaphore_pair(sync, uid, link, wait_sid..head);
             }
         }
     }
@@ -505,3 +571,308 @@ fn optimize<S>(schedule: &Schedule<S>, sync: &mut SyncTemp) {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use {
+        super::*,
+        crate::{collect::collect, node::Node},
+        rendy_core::hal,
+    };
+
+    fn buffer_node(
+        id: usize,
+        family: usize,
+        dependencies: Vec<usize>,
+        access: hal::buffer::Access,
+        stages: hal::pso::PipelineStage,
+    ) -> Node {
+        let mut buffers = HashMap::new();
+        buffers.insert(
+            Id(0),
+            State {
+                access,
+                layout: (),
+                stages,
+                usage: hal::buffer::Usage::STORAGE,
+            },
+        );
+        Node {
+            id,
+            family: hal::queue::QueueFamilyId(family),
+            dependencies,
+            buffers,
+            images: HashMap::new(),
+        }
+    }
+
+    #[test]
+    fn family_transfer_is_released_and_acquired() {
+        let nodes = vec![
+            buffer_node(
+                0,
+                0,
+                vec![],
+                hal::buffer::Access::SHADER_WRITE,
+                hal::pso::PipelineStage::COMPUTE_SHADER,
+            ),
+            buffer_node(
+                1,
+                1,
+                vec![0],
+                hal::buffer::Access::SHADER_READ,
+                hal::pso::PipelineStage::VERTEX_SHADER,
+            ),
+        ];
+
+        let chains = collect(nodes, |_| 1);
+        let schedule = sync(&chains, || ((), ()));
+        let sync_of = |node| {
+            schedule
+                .iter()
+                .flat_map(|family| family.iter())
+                .flat_map(|queue| queue.iter())
+                .find(|submission| submission.node() == node)
+                .unwrap()
+                .sync()
+        };
+
+        let families = hal::queue::QueueFamilyId(0)..hal::queue::QueueFamilyId(1);
+        let release = &sync_of(0).release.buffers[&Id(0)];
+        assert_eq!(release.families, Some(families.clone()));
+        assert_eq!(release.states.start.0, hal::buffer::Access::SHADER_WRITE);
+        assert_eq!(
+            release.states.start.2,
+            hal::pso::PipelineStage::COMPUTE_SHADER
+        );
+
+        let acquire = &sync_of(1).acquire.buffers[&Id(0)];
+        assert_eq!(acquire.families, Some(families));
+        assert_eq!(acquire.states.end.0, hal::buffer::Access::SHADER_READ);
+        assert_eq!(acquire.states.end.2, hal::pso::PipelineStage::VERTEX_SHADER);
+
+        assert_eq!(sync_of(0).signal.len(), 1);
+        assert_eq!(sync_of(1).wait.len(), 1);
+
+        // Ownership returns to the first family for the next frame.
+        let back = hal::queue::QueueFamilyId(1)..hal::queue::QueueFamilyId(0);
+        assert_eq!(
+            sync_of(1).release.buffers[&Id(0)].families,
+            Some(back.clone())
+        );
+        assert_eq!(sync_of(0).acquire.buffers[&Id(0)].families, Some(back));
+    }
+
+    fn image_node(
+        id: usize,
+        dependencies: Vec<usize>,
+        access: hal::image::Access,
+        layout: hal::image::Layout,
+        stages: hal::pso::PipelineStage,
+    ) -> Node {
+        let mut images = HashMap::new();
+        images.insert(
+            Id(0),
+            State {
+                access,
+                layout,
+                stages,
+                usage: hal::image::Usage::STORAGE | hal::image::Usage::SAMPLED,
+            },
+        );
+        Node {
+            id,
+            family: hal::queue::QueueFamilyId(0),
+            dependencies,
+            buffers: HashMap::new(),
+            images,
+        }
+    }
+
+    #[test]
+    fn storage_image_is_written_then_sampled() {
+        use hal::{image::Access, image::Layout, pso::PipelineStage};
+
+        // Two compute passes write storage image and fragment shader samples it.
+        let nodes = vec![
+            image_node(
+                0,
+                vec![],
+                Access::SHADER_WRITE,
+                Layout::General,
+                PipelineStage::COMPUTE_SHADER,
+            ),
+            image_node(
+                1,
+                vec![0],
+                Access::SHADER_READ | Access::SHADER_WRITE,
+                Layout::General,
+                PipelineStage::COMPUTE_SHADER,
+            ),
+            image_node(
+                2,
+                vec![1],
+                Access::SHADER_READ,
+                Layout::ShaderReadOnlyOptimal,
+                PipelineStage::FRAGMENT_SHADER,
+            ),
+            image_node(
+                3,
+                vec![2],
+                Access::SHADER_READ,
+                Layout::General,
+                PipelineStage::COMPUTE_SHADER,
+            ),
+        ];
+
+        let chains = collect(nodes, |_| 1);
+        assert_eq!(chains.images[&Id(0)].links().len(), 4);
+
+        let schedule = sync(&chains, || ((), ()));
+        let sync_of = |node| {
+            schedule
+                .iter()
+                .flat_map(|family| family.iter())
+                .flat_map(|queue| queue.iter())
+                .find(|submission| submission.node() == node)
+                .unwrap()
+                .sync()
+        };
+
+        // Write after write is ordered.
+        let barrier = &sync_of(1).acquire.images[&Id(0)];
+        assert_eq!(barrier.states.start.0, Access::SHADER_WRITE);
+        assert_eq!(barrier.states.start.1, Layout::General);
+        assert_eq!(barrier.states.end.1, Layout::General);
+
+        // Image is moved out of `General` before sampling.
+        let barrier = &sync_of(1).release.images[&Id(0)];
+        assert_eq!(
+            barrier.states.start.1..barrier.states.end.1,
+            Layout::General..Layout::ShaderReadOnlyOptimal
+        );
+        assert_eq!(barrier.states.end.2, PipelineStage::FRAGMENT_SHADER);
+
+        // Storage read after sampling needs `General` again.
+        let barrier = &sync_of(3).acquire.images[&Id(0)];
+        assert_eq!(
+            barrier.states.start.1..barrier.states.end.1,
+            Layout::ShaderReadOnlyOptimal..Layout::General
+        );
+    }
+
+    #[test]
+    fn layout_change_between_reads_is_waited_by_all_queues() {
+        use hal::{image::Access, image::Layout, pso::PipelineStage};
+
+        // Image is sampled and then read as storage image by two independent nodes.
+        let nodes = vec![
+            image_node(
+                0,
+                vec![],
+                Access::SHADER_READ,
+                Layout::ShaderReadOnlyOptimal,
+                PipelineStage::FRAGMENT_SHADER,
+            ),
+            image_node(
+                1,
+                vec![0],
+                Access::SHADER_READ,
+                Layout::General,
+                PipelineStage::COMPUTE_SHADER,
+            ),
+            image_node(
+                2,
+                vec![0],
+                Access::SHADER_READ,
+                Layout::General,
+                PipelineStage::COMPUTE_SHADER,
+            ),
+        ];
+
+        let chains = collect(nodes, |_| 2);
+        let link = &chains.images[&Id(0)].links()[1];
+        assert_eq!(link.queues().count(), 2);
+
+        let mut semaphores = 0..;
+        let schedule = sync(&chains, || {
+            let id = semaphores.next().unwrap();
+            (id, id)
+        });
+        let submission_of = |node| {
+            schedule
+                .iter()
+                .flat_map(|family| family.iter())
+                .flat_map(|queue| queue.iter())
+                .find(|submission| submission.node() == node)
+                .unwrap()
+        };
+
+        let (transition, other) = if submission_of(1).sync().acquire.images.contains_key(&Id(0)) {
+            (submission_of(1), submission_of(2))
+        } else {
+            (submission_of(2), submission_of(1))
+        };
+        let barrier = &transition.sync().acquire.images[&Id(0)];
+        assert_eq!(
+            barrier.states.start.1..barrier.states.end.1,
+            Layout::ShaderReadOnlyOptimal..Layout::General
+        );
+        assert!(!other.sync().acquire.images.contains_key(&Id(0)));
+
+        // Read on the other queue starts after the layout transition.
+        assert!(other.sync().wait.iter().any(|wait| transition
+            .sync()
+            .signal
+            .iter()
+            .any(|signal| signal.semaphore() == wait.semaphore())));
+    }
+
+    #[test]
+    fn explicit_semaphores_are_added() {
+        let node = |id, family, dependencies| Node {
+            id,
+            family: hal::queue::QueueFamilyId(family),
+            dependencies,
+            buffers: HashMap::new(),
+            images: HashMap::new(),
+        };
+        let nodes = vec![node(0, 0, vec![]), node(1, 1, vec![0]), node(2, 0, vec![0])];
+
+        let chains = collect(nodes, |_| 1);
+        let mut schedule: Schedule<SyncData<usize, usize>> =
+            sync(&chains, || unreachable!("No resources to synchronize"));
+
+        let mut semaphores = 0..;
+        sync_semaphores(
+            &mut schedule,
+            vec![
+                (0, 1, hal::pso::PipelineStage::VERTEX_INPUT),
+                (0, 2, hal::pso::PipelineStage::TOP_OF_PIPE),
+            ],
+            || {
+                let id = semaphores.next().unwrap();
+                (id, id)
+            },
+        );
+
+        let sync_of = |node| {
+            schedule
+                .iter()
+                .flat_map(|family| family.iter())
+                .flat_map(|queue| queue.iter())
+                .find(|submission| submission.node() == node)
+                .unwrap()
+                .sync()
+        };
+
+        // Node 2 is on the same queue as node 0.
+        assert_eq!(semaphores.start, 1);
+        assert_eq!(sync_of(0).signal, vec![Signal(0)]);
+        assert_eq!(
+            sync_of(1).wait,
+            vec![Wait(0, hal::pso::PipelineStage::VERTEX_INPUT)]
+        );
+        assert!(sync_of(2).wait.is_empty());
+    }
+}
@@ -12,6 +12,106 @@ use {
     },
 };
 
+/// Maximum size of data `update_buffer` command can write.
+pub const MAX_UPDATE_BUFFER_SIZE: usize = 65536;
+
+/// Data can't be written with `update_buffer` command.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum UpdateBufferError {
+    /// Offset is not multiple of 4.
+    UnalignedOffset(u64),
+
+    /// Size of data is not multiple of 4.
+    UnalignedSize(usize),
+
+    /// Size of data exceeds `MAX_UPDATE_BUFFER_SIZE`.
+    TooLarge(usize),
+}
+
+impl std::fmt::Display for UpdateBufferError {
+    fn fmt(&self, fmt: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            UpdateBufferError::UnalignedOffset(offset) => {
+                write!(fmt, "Buffer update offset {} is not multiple of 4", offset)
+            }
+            UpdateBufferError::UnalignedSize(size) => {
+                write!(fmt, "Buffer update size {} is not multiple of 4", size)
+            }
+            UpdateBufferError::TooLarge(size) => write!(
+                fmt,
+                "Buffer update size {} exceeds {} bytes. Use staging buffer instead",
+                size, MAX_UPDATE_BUFFER_SIZE
+            ),
+        }
+    }
+}
+
+impl std::error::Error for UpdateBufferError {}
+
+fn check_update_buffer(offset: u64, size: usize) -> Result<(), UpdateBufferError> {
+    if offset % 4 != 0 {
+        Err(UpdateBufferError::UnalignedOffset(offset))
+    } else if size % 4 != 0 {
+        Err(UpdateBufferError::UnalignedSize(size))
+    } else if size > MAX_UPDATE_BUFFER_SIZE {
+        Err(UpdateBufferError::TooLarge(size))
+    } else {
+        Ok(())
+    }
+}
+
+/// Panics if viewports `first..first + count` are not within `max_viewports` limit.
+fn assert_viewports_in_limits(first: u32, count: usize, max_viewports: usize) {
+    assert!(
+        first as usize + count <= max_viewports,
+        "Viewports {}..{} exceed device limit of {} viewports",
+        first,
+        first as usize + count,
+        max_viewports,
+    );
+}
+
+/// Reinterpret value as slice of words for pushing constants.
+fn constant_words<T: Copy>(value: &T) -> &[u32] {
+    let size = std::mem::size_of::<T>();
+    assert_eq!(size % 4, 0, "Push constants size must be multiple of 4");
+    assert_eq!(
+        value as *const T as usize % std::mem::align_of::<u32>(),
+        0,
+        "Push constants must be aligned to 4 bytes"
+    );
+
+    // Pointer is aligned and `T: Copy` has no drop glue,
+    // so its bytes can be viewed as words.
+    unsafe { std::slice::from_raw_parts(value as *const T as *const u32, size / 4) }
+}
+
+/// Pack debug label color into RGBA word with red in the highest byte.
+fn debug_color(color: [f32; 4]) -> u32 {
+    color.iter().fold(0, |word, &channel| {
+        (word << 8) | (channel.max(0.0).min(1.0) * 255.0).round() as u32
+    })
+}
+
+/// Check that `size` bytes at `offset` are within one of push constant `ranges`
+/// declared for each of `stages`.
+fn push_constants_fit(
+    ranges: &[(rendy_core::hal::pso::ShaderStageFlags, std::ops::Range<u32>)],
+    stages: rendy_core::hal::pso::ShaderStageFlags,
+    offset: u32,
+    size: u32,
+) -> bool {
+    let end = offset + size;
+    (0..32)
+        .map(|bit| rendy_core::hal::pso::ShaderStageFlags::from_bits_truncate(1 << bit))
+        .filter(|stage| !stage.is_empty() && stages.contains(*stage))
+        .all(|stage| {
+            ranges.iter().any(|(range_stages, range)| {
+                range_stages.contains(stage) && range.start <= offset && end <= range.end
+            })
+        })
+}
+
 /// Draw command for [`draw_indirect`].
 ///
 /// [`draw_indirect`]: ../struct.RenderPassEncoder.html#method.draw_indirect
@@ -260,6 +360,127 @@ where
         );
     }
 
+    /// Push constants of type `T`.
+    /// Value is pushed as raw bytes, number of words is computed from `T` size.
+    /// `ranges` are push constant ranges `layout` was created with.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `offset` or size of `T` is not multiple of 4,
+    /// or if `value` is not aligned to 4 bytes.
+    /// In debug builds also panics if constants are not within `ranges` for all `stages`.
+    ///
+    /// # Safety
+    ///
+    /// `ranges` must be the push constant ranges of the `layout`.
+    ///
+    /// See: https://www.khronos.org/registry/vulkan/specs/1.1-extensions/man/html/vkCmdPushConstants.html
+    pub unsafe fn push_typed_constants<T>(
+        &mut self,
+        layout: &B::PipelineLayout,
+        ranges: &[(rendy_core::hal::pso::ShaderStageFlags, std::ops::Range<u32>)],
+        stages: rendy_core::hal::pso::ShaderStageFlags,
+        offset: u32,
+        value: &T,
+    ) where
+        T: Copy,
+    {
+        assert_eq!(offset % 4, 0, "Push constants offset must be multiple of 4");
+        let words = constant_words(value);
+        debug_assert!(
+            push_constants_fit(ranges, stages, offset, words.len() as u32 * 4),
+            "Push constants {:?} for {:?} are out of ranges {:?}",
+            offset..offset + words.len() as u32 * 4,
+            stages,
+            ranges
+        );
+        self.push_constants(layout, stages, offset, words);
+    }
+
+    /// Write timestamp into the query after all previous commands complete `stage`.
+    ///
+    /// # Safety
+    ///
+    /// Query must be reset before this command and queue family must support timestamps.
+    ///
+    /// See: https://www.khronos.org/registry/vulkan/specs/1.1-extensions/man/html/vkCmdWriteTimestamp.html
+    pub unsafe fn write_timestamp(
+        &mut self,
+        stage: rendy_core::hal::pso::PipelineStage,
+        query: rendy_core::hal::query::Query<'_, B>,
+    ) {
+        rendy_core::hal::command::CommandBuffer::write_timestamp(self.raw, stage, query)
+    }
+
+    /// Begin query.
+    /// Occlusion query counts samples that pass depth and stencil tests
+    /// until query is ended.
+    /// Without `ControlFlags::PRECISE` non-zero count may be reported for any number of samples.
+    ///
+    /// # Safety
+    ///
+    /// Query must be reset before this command and must not be active.
+    /// `ControlFlags::PRECISE` requires `Features::PRECISE_OCCLUSION_QUERY`.
+    ///
+    /// See: https://www.khronos.org/registry/vulkan/specs/1.1-extensions/man/html/vkCmdBeginQuery.html
+    pub unsafe fn begin_query(
+        &mut self,
+        query: rendy_core::hal::query::Query<'_, B>,
+        flags: rendy_core::hal::query::ControlFlags,
+    ) {
+        rendy_core::hal::command::CommandBuffer::begin_query(self.raw, query, flags)
+    }
+
+    /// End query.
+    ///
+    /// # Safety
+    ///
+    /// Query must be active and begun in the same subpass.
+    ///
+    /// See: https://www.khronos.org/registry/vulkan/specs/1.1-extensions/man/html/vkCmdEndQuery.html
+    pub unsafe fn end_query(&mut self, query: rendy_core::hal::query::Query<'_, B>) {
+        rendy_core::hal::command::CommandBuffer::end_query(self.raw, query)
+    }
+
+    /// Insert debug label into command buffer.
+    /// Label is shown in graphics debuggers like RenderDoc.
+    ///
+    /// This is no-op if backend doesn't support debug labels (`VK_EXT_debug_utils` or equivalent).
+    pub fn debug_marker(&mut self, name: &str, color: [f32; 4]) {
+        unsafe {
+            rendy_core::hal::command::CommandBuffer::insert_debug_marker(
+                self.raw,
+                name,
+                debug_color(color),
+            )
+        }
+    }
+
+    /// Begin labeled region of commands.
+    /// Region is shown in graphics debuggers like RenderDoc.
+    ///
+    /// This is no-op if backend doesn't support debug labels (`VK_EXT_debug_utils` or equivalent).
+    ///
+    /// # Safety
+    ///
+    /// Region must be ended with `end_debug_region` before command buffer is finished.
+    pub unsafe fn begin_debug_region(&mut self, name: &str, color: [f32; 4]) {
+        rendy_core::hal::command::CommandBuffer::begin_debug_marker(
+            self.raw,
+            name,
+            debug_color(color),
+        )
+    }
+
+    /// End labeled region of commands.
+    ///
+    /// # Safety
+    ///
+    /// Region must be started with `begin_debug_region` in the same command buffer.
+    pub unsafe fn end_debug_region(&mut self) {
+        rendy_core::hal::command::CommandBuffer::end_debug_marker(self.raw)
+    }
+
     /// Set viewports
     ///
     /// See: https://www.khronos.org/registry/vulkan/specs/1.1-extensions/man/html/vkCmdSetViewport.html
@@ -293,6 +514,47 @@ where
         rendy_core::hal::command::CommandBuffer::set_scissors(self.raw, first_scissor, rects)
     }
 
+    /// Set viewports `first_viewport..first_viewport + viewports.len()`.
+    /// Shaders select viewport for primitive by writing its index to `gl_ViewportIndex`,
+    /// primitives with index not written use viewport 0.
+    ///
+    /// Panics if any viewport index is out of `max_viewports` device limit.
+    ///
+    /// # Safety
+    ///
+    /// Bound pipeline must have dynamic viewport state.
+    pub unsafe fn set_viewport_array(
+        &mut self,
+        first_viewport: u32,
+        viewports: &[rendy_core::hal::pso::Viewport],
+        limits: &rendy_core::hal::Limits,
+    ) where
+        C: Supports<Graphics>,
+    {
+        assert_viewports_in_limits(first_viewport, viewports.len(), limits.max_viewports);
+        self.set_viewports(first_viewport, viewports)
+    }
+
+    /// Set scissors `first_scissor..first_scissor + rects.len()`.
+    /// Scissor is paired with viewport of the same index.
+    ///
+    /// Panics if any scissor index is out of `max_viewports` device limit.
+    ///
+    /// # Safety
+    ///
+    /// Bound pipeline must have dynamic scissor state.
+    pub unsafe fn set_scissor_array(
+        &mut self,
+        first_scissor: u32,
+        rects: &[rendy_core::hal::pso::Rect],
+        limits: &rendy_core::hal::Limits,
+    ) where
+        C: Supports<Graphics>,
+    {
+        assert_viewports_in_limits(first_scissor, rects.len(), limits.max_viewports);
+        self.set_scissors(first_scissor, rects)
+    }
+
     /// Set the stencil reference dynamic state
     ///
     /// See: https://www.khronos.org/registry/vulkan/specs/1.1-extensions/man/html/vkCmdSetStencilReference.html
@@ -390,6 +652,21 @@ where
             family: self.family,
         }
     }
+
+    /// Get raw command buffer to record commands this encoder doesn't wrap.
+    /// Available through render pass encoders and encoders outside render pass alike.
+    ///
+    /// Recording raw commands is unsafe as for any gfx-hal command buffer.
+    /// Rendy doesn't track what is recorded this way,
+    /// so the caller is responsible to keep commands valid for the encoder state:
+    /// inside render pass only commands allowed within render pass may be recorded
+    /// and the render pass must not be ended or begun,
+    /// resources must be accessed only as declared by the node
+    /// and any additional synchronization must be recorded by the caller.
+    /// Queue family of the buffer supports capability `C`.
+    pub fn raw(&mut self) -> &mut B::CommandBuffer {
+        &mut *self.raw
+    }
 }
 
 /// Special encoder to record render-pass commands.
@@ -527,6 +804,70 @@ where
         )
     }
 
+    /// Draw indirect with number of draws read from `count_buffer`.
+    /// Similar to [`draw_indirect`] except number of draws is taken from `count_buffer` at `count_buffer_offset`
+    /// and clamped to `max_draw_count`.
+    ///
+    /// [`draw_indirect`]: #method.draw_indirect
+    ///
+    /// # Safety
+    ///
+    /// Similar to `draw_indirect()`.
+    /// Device must be created with `Features::DRAW_INDIRECT_COUNT`.
+    ///
+    /// See: https://www.khronos.org/registry/vulkan/specs/1.2-extensions/man/html/vkCmdDrawIndirectCount.html
+    pub unsafe fn draw_indirect_count(
+        &mut self,
+        buffer: &B::Buffer,
+        offset: u64,
+        count_buffer: &B::Buffer,
+        count_buffer_offset: u64,
+        max_draw_count: u32,
+        stride: u32,
+    ) {
+        rendy_core::hal::command::CommandBuffer::draw_indirect_count(
+            self.inner.raw,
+            buffer,
+            offset,
+            count_buffer,
+            count_buffer_offset,
+            max_draw_count,
+            stride,
+        )
+    }
+
+    /// Draw indirect with indices and number of draws read from `count_buffer`.
+    /// Similar to [`draw_indexed_indirect`] except number of draws is taken from `count_buffer` at `count_buffer_offset`
+    /// and clamped to `max_draw_count`.
+    ///
+    /// [`draw_indexed_indirect`]: #method.draw_indexed_indirect
+    ///
+    /// # Safety
+    ///
+    /// Similar to `draw_indexed_indirect()`.
+    /// Device must be created with `Features::DRAW_INDIRECT_COUNT`.
+    ///
+    /// See: https://www.khronos.org/registry/vulkan/specs/1.2-extensions/man/html/vkCmdDrawIndexedIndirectCount.html
+    pub unsafe fn draw_indexed_indirect_count(
+        &mut self,
+        buffer: &B::Buffer,
+        offset: u64,
+        count_buffer: &B::Buffer,
+        count_buffer_offset: u64,
+        max_draw_count: u32,
+        stride: u32,
+    ) {
+        rendy_core::hal::command::CommandBuffer::draw_indexed_indirect_count(
+            self.inner.raw,
+            buffer,
+            offset,
+            count_buffer,
+            count_buffer_offset,
+            max_draw_count,
+            stride,
+        )
+    }
+
     /// Reborrow encoder.
     pub fn reborrow(&mut self) -> RenderPassEncoder<'_, B> {
         RenderPassEncoder {
@@ -819,6 +1160,48 @@ where
         rendy_core::hal::command::CommandBuffer::copy_buffer(self.inner.raw, src, dst, regions)
     }
 
+    /// Write small amount of data directly to the buffer,
+    /// without staging buffer.
+    /// Data is copied into command buffer at recording,
+    /// so this is suitable for small frequent updates like per-frame constants.
+    ///
+    /// Graph node that updates buffer must declare it with
+    /// `Access::TRANSFER_WRITE` at `PipelineStage::TRANSFER` with `Usage::TRANSFER_DST`
+    /// so that barriers are inserted.
+    ///
+    /// Fails if `offset` or `data` size is not multiple of 4
+    /// or data is larger than `MAX_UPDATE_BUFFER_SIZE`.
+    /// Nothing is recorded for empty `data`.
+    ///
+    /// # Safety
+    ///
+    /// `offset + data.len()` must not exceed size of the `buffer`.
+    /// `buffer` must be created with `TRANSFER_DST` usage.
+    ///
+    /// See: https://www.khronos.org/registry/vulkan/specs/1.1-extensions/man/html/vkCmdUpdateBuffer.html
+    pub unsafe fn update_buffer(
+        &mut self,
+        buffer: &B::Buffer,
+        offset: u64,
+        data: &[u8],
+    ) -> Result<(), UpdateBufferError>
+    where
+        C: Supports<Transfer>,
+    {
+        self.capability.assert();
+        check_update_buffer(offset, data.len())?;
+
+        if !data.is_empty() {
+            rendy_core::hal::command::CommandBuffer::update_buffer(
+                self.inner.raw,
+                buffer,
+                offset,
+                data,
+            );
+        }
+        Ok(())
+    }
+
     /// Copy buffer region to image subresource range.
     ///
     /// # Safety
@@ -846,6 +1229,33 @@ where
         )
     }
 
+    /// Copy image subresource range to buffer region.
+    ///
+    /// # Safety
+    ///
+    /// Same as `copy_buffer()`
+    ///
+    /// See: https://www.khronos.org/registry/vulkan/specs/1.1-extensions/man/html/vkCmdCopyImageToBuffer.html
+    pub unsafe fn copy_image_to_buffer(
+        &mut self,
+        src: &B::Image,
+        src_layout: rendy_core::hal::image::Layout,
+        dst: &B::Buffer,
+        regions: impl IntoIterator<Item = rendy_core::hal::command::BufferImageCopy>,
+    ) where
+        C: Supports<Transfer>,
+    {
+        self.capability.assert();
+
+        rendy_core::hal::command::CommandBuffer::copy_image_to_buffer(
+            self.inner.raw,
+            src,
+            src_layout,
+            dst,
+            regions,
+        )
+    }
+
     /// Copy image regions.
     ///
     /// # Safety
@@ -875,6 +1285,35 @@ where
         )
     }
 
+    /// Clear subresources of the image outside of render pass.
+    /// `value` is interpreted as color or depth-stencil depending on the ranges aspects.
+    ///
+    /// # Safety
+    ///
+    /// Image must be in `layout` which is either `General` or `TransferDstOptimal`.
+    ///
+    /// See: https://www.khronos.org/registry/vulkan/specs/1.1-extensions/man/html/vkCmdClearColorImage.html
+    /// and https://www.khronos.org/registry/vulkan/specs/1.1-extensions/man/html/vkCmdClearDepthStencilImage.html
+    pub unsafe fn clear_image(
+        &mut self,
+        image: &B::Image,
+        layout: rendy_core::hal::image::Layout,
+        value: rendy_core::hal::command::ClearValue,
+        ranges: impl IntoIterator<Item = rendy_core::hal::image::SubresourceRange>,
+    ) where
+        C: Supports<Graphics>,
+    {
+        self.capability.assert();
+
+        rendy_core::hal::command::CommandBuffer::clear_image(
+            self.inner.raw,
+            image,
+            layout,
+            value,
+            ranges,
+        )
+    }
+
     /// Blit image regions, potentially using specified filter when resize is necessary.
     ///
     /// # Safety
@@ -938,6 +1377,21 @@ where
 
         rendy_core::hal::command::CommandBuffer::dispatch_indirect(self.inner.raw, buffer, offset)
     }
+
+    /// Reset queries in the query pool.
+    ///
+    /// # Safety
+    ///
+    /// `queries` must be in bounds of the `pool`.
+    ///
+    /// See: https://www.khronos.org/registry/vulkan/specs/1.1-extensions/man/html/vkCmdResetQueryPool.html
+    pub unsafe fn reset_query_pool(
+        &mut self,
+        pool: &B::QueryPool,
+        queries: std::ops::Range<rendy_core::hal::query::Id>,
+    ) {
+        rendy_core::hal::command::CommandBuffer::reset_query_pool(self.inner.raw, pool, queries)
+    }
 }
 
 impl<B, C, U, L, R> CommandBuffer<B, C, RecordingState<U>, L, R>
@@ -975,3 +1429,117 @@ where
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use {
+        super::{
+            assert_viewports_in_limits, check_update_buffer, constant_words, debug_color,
+            push_constants_fit, UpdateBufferError, MAX_UPDATE_BUFFER_SIZE,
+        },
+        rendy_core::hal::pso::ShaderStageFlags,
+    };
+
+    #[repr(C)]
+    #[derive(Clone, Copy)]
+    struct Constants {
+        scale: f32,
+        index: u32,
+        offset: [f32; 2],
+    }
+
+    #[test]
+    fn mixed_fields_as_words() {
+        let constants = Constants {
+            scale: 0.5,
+            index: 7,
+            offset: [1.0, -2.0],
+        };
+
+        assert_eq!(
+            constant_words(&constants),
+            &[0.5f32.to_bits(), 7, 1.0f32.to_bits(), (-2.0f32).to_bits()][..]
+        );
+    }
+
+    #[test]
+    fn constants_must_fit_declared_ranges() {
+        let size = std::mem::size_of::<Constants>() as u32;
+        let ranges = [
+            (ShaderStageFlags::VERTEX, 0..size),
+            (ShaderStageFlags::FRAGMENT, 0..size + 16),
+        ];
+
+        assert!(push_constants_fit(
+            &ranges,
+            ShaderStageFlags::VERTEX | ShaderStageFlags::FRAGMENT,
+            0,
+            size
+        ));
+        assert!(push_constants_fit(
+            &ranges,
+            ShaderStageFlags::FRAGMENT,
+            16,
+            size
+        ));
+        assert!(!push_constants_fit(
+            &ranges,
+            ShaderStageFlags::VERTEX,
+            16,
+            size
+        ));
+        assert!(!push_constants_fit(
+            &ranges,
+            ShaderStageFlags::COMPUTE,
+            0,
+            4
+        ));
+    }
+
+    #[test]
+    #[should_panic]
+    fn size_not_multiple_of_four() {
+        constant_words(&[0u8; 6]);
+    }
+
+    #[test]
+    fn debug_color_packing() {
+        assert_eq!(debug_color([1.0, 0.0, 0.0, 1.0]), 0xFF0000FF);
+        assert_eq!(debug_color([0.0, 0.5, 1.0, 0.0]), 0x0080FF00);
+        assert_eq!(debug_color([2.0, -1.0, 0.0, 1.0]), 0xFF0000FF);
+    }
+
+    #[test]
+    fn viewports_within_limit() {
+        assert_viewports_in_limits(0, 2, 16);
+        assert_viewports_in_limits(14, 2, 16);
+    }
+
+    #[test]
+    #[should_panic]
+    fn viewports_over_limit() {
+        assert_viewports_in_limits(15, 2, 16);
+    }
+
+    #[test]
+    fn uniform_update_is_accepted() {
+        assert_eq!(check_update_buffer(0, 64), Ok(()));
+        assert_eq!(check_update_buffer(256, MAX_UPDATE_BUFFER_SIZE), Ok(()));
+    }
+
+    #[test]
+    fn update_constraints_are_enforced() {
+        assert_eq!(
+            check_update_buffer(2, 64),
+            Err(UpdateBufferError::UnalignedOffset(2))
+        );
+        assert_eq!(
+            check_update_buffer(0, 6),
+            Err(UpdateBufferError::UnalignedSize(6))
+        );
+        assert_eq!(
+            check_update_buffer(0, MAX_UPDATE_BUFFER_SIZE + 4),
+            Err(UpdateBufferError::TooLarge(MAX_UPDATE_BUFFER_SIZE + 4))
+        );
+    }
+}
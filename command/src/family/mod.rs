@@ -182,6 +182,8 @@ pub struct Families<B: Backend> {
     families_indices: Vec<usize>,
 }
 
+device_owned!(Families<B>);
+
 impl<B> Families<B>
 where
     B: Backend,
@@ -235,6 +237,33 @@ where
     pub fn with_capability<C: Capability>(&self) -> Option<FamilyId> {
         self.find(|family| Supports::<C>::supports(&family.capability()).is_some())
     }
+
+    /// Get most specialized family id with specified capability.
+    /// See [`most_specialized`].
+    ///
+    /// [`most_specialized`]: fn.most_specialized.html
+    pub fn specialized_with_capability<C: Capability>(&self) -> Option<FamilyId> {
+        let queue_types: Vec<_> = self.families.iter().map(Family::capability).collect();
+        most_specialized::<C>(&queue_types).map(|index| self.families[index].id())
+    }
+}
+
+/// Find index of the most specialized queue type with specified capability.
+/// For example dedicated transfer family is preferred for `Transfer`
+/// and dedicated compute family for `Compute`,
+/// while general family is picked only if there is nothing better.
+/// First one is picked among equally specialized.
+pub fn most_specialized<C: Capability>(queue_types: &[QueueType]) -> Option<usize> {
+    queue_types
+        .iter()
+        .enumerate()
+        .filter(|(_, queue_type)| Supports::<C>::supports(*queue_type).is_some())
+        .min_by_key(|(_, queue_type)| match queue_type {
+            QueueType::Transfer => 0,
+            QueueType::Compute | QueueType::Graphics => 1,
+            QueueType::General => 2,
+        })
+        .map(|(index, _)| index)
 }
 
 /// Query queue families from device.
@@ -270,3 +299,26 @@ where
         families_indices,
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use {
+        super::most_specialized,
+        crate::capability::{Compute, Graphics, QueueType, Transfer},
+    };
+
+    const FAMILIES: &[QueueType] = &[QueueType::General, QueueType::Compute, QueueType::Transfer];
+
+    #[test]
+    fn transfer_prefers_dedicated_family() {
+        assert_eq!(most_specialized::<Transfer>(FAMILIES), Some(2));
+        assert_eq!(most_specialized::<Transfer>(&FAMILIES[..2]), Some(1));
+    }
+
+    #[test]
+    fn general_family_is_fallback() {
+        assert_eq!(most_specialized::<Compute>(FAMILIES), Some(1));
+        assert_eq!(most_specialized::<Graphics>(FAMILIES), Some(0));
+        assert_eq!(most_specialized::<Graphics>(&FAMILIES[1..]), None);
+    }
+}
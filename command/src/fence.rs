@@ -200,3 +200,175 @@ where
         }
     }
 }
+
+/// Pool of reusable fences.
+///
+/// Fences released to the pool may still be submitted.
+/// They are checked lazily with `get_fence_status` on `collect`
+/// and returned to the pool after they became signaled,
+/// so neither `acquire` nor `release` blocks.
+#[derive(Debug)]
+pub struct FencePool<B: Backend> {
+    device: DeviceId,
+    fences: Recycler<Fence<B>>,
+}
+
+/// Free and released objects of a pool.
+#[derive(Debug)]
+struct Recycler<F> {
+    free: Vec<F>,
+    released: Vec<F>,
+    created: usize,
+}
+
+impl<F> Recycler<F> {
+    fn new() -> Self {
+        Recycler {
+            free: Vec::new(),
+            released: Vec::new(),
+            created: 0,
+        }
+    }
+
+    fn acquire<E>(&mut self, create: impl FnOnce() -> Result<F, E>) -> Result<F, E> {
+        match self.free.pop() {
+            Some(object) => Ok(object),
+            None => {
+                let object = create()?;
+                self.created += 1;
+                Ok(object)
+            }
+        }
+    }
+
+    /// Move released objects for which `ready` returns `true` to free ones.
+    fn collect<E>(&mut self, mut ready: impl FnMut(&mut F) -> Result<bool, E>) -> Result<(), E> {
+        let mut index = 0;
+        while index < self.released.len() {
+            if ready(&mut self.released[index])? {
+                let object = self.released.swap_remove(index);
+                self.free.push(object);
+            } else {
+                index += 1;
+            }
+        }
+        Ok(())
+    }
+}
+
+device_owned!(FencePool<B>);
+
+impl<B> FencePool<B>
+where
+    B: Backend,
+{
+    /// Create empty pool.
+    pub fn new(device: &Device<B>) -> Self {
+        FencePool {
+            device: device.id(),
+            fences: Recycler::new(),
+        }
+    }
+
+    /// Get unsignaled fence from the pool.
+    /// Creates new fence if there are no free fences.
+    pub fn acquire(
+        &mut self,
+        device: &Device<B>,
+    ) -> Result<Fence<B>, rendy_core::hal::device::OutOfMemory> {
+        self.assert_device_owner(device);
+        self.fences.acquire(|| Fence::new(device, false))
+    }
+
+    /// Return fence to the pool.
+    /// Fence can be in any state.
+    /// Submitted fence becomes available again after it is signaled.
+    pub fn release(&mut self, fence: Fence<B>) {
+        assert_eq!(
+            fence.device, self.device,
+            "Fence is not owned by pool's device"
+        );
+        self.fences.released.push(fence);
+    }
+
+    /// Move released fences that are not pending anymore to the pool.
+    /// `signaled` is called with epoch of each submitted fence found signaled.
+    pub fn collect(
+        &mut self,
+        device: &Device<B>,
+        mut signaled: impl FnMut(FenceEpoch),
+    ) -> Result<(), rendy_core::hal::device::OomOrDeviceLost> {
+        self.assert_device_owner(device);
+
+        self.fences.collect(|fence| {
+            if fence.is_submitted() {
+                match fence.check_signaled(device)? {
+                    Some(epoch) => signaled(epoch),
+                    None => return Ok(false),
+                }
+            }
+            if fence.is_signaled() {
+                fence.reset(device)?;
+            }
+            Ok(true)
+        })
+    }
+
+    /// Number of fences created by this pool.
+    pub fn created(&self) -> usize {
+        self.fences.created
+    }
+
+    /// Number of fences ready to be acquired without creation.
+    pub fn free(&self) -> usize {
+        self.fences.free.len()
+    }
+
+    /// Destroy all fences of the pool.
+    /// Waits for released fences that are still submitted.
+    pub fn dispose(mut self, device: &Device<B>) {
+        self.assert_device_owner(device);
+        for mut fence in self.fences.released.drain(..) {
+            if fence.is_submitted() {
+                let _ = fence.wait_signaled(device, !0);
+            }
+            unsafe { device.destroy_fence(fence.into_inner()) };
+        }
+        for fence in self.fences.free.drain(..) {
+            unsafe { device.destroy_fence(fence.into_inner()) };
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Recycler;
+
+    #[test]
+    fn signaled_fences_are_reused() {
+        // Fences are modeled by the frame they are submitted with.
+        let mut pool = Recycler::<Option<u32>>::new();
+        for frame in 0..100 {
+            // Frame before the previous one is complete.
+            pool.collect(|fence| {
+                let complete = fence.map_or(true, |submitted| submitted + 2 <= frame);
+                if complete {
+                    *fence = None;
+                }
+                Ok::<_, ()>(complete)
+            })
+            .unwrap();
+
+            for _ in 0..3 {
+                let fence = pool.acquire(|| Ok::<_, ()>(None)).unwrap();
+                assert_eq!(fence, None);
+                pool.released.push(Some(frame));
+            }
+        }
+
+        // Fences of the previous and current frames are pending.
+        assert_eq!(pool.created, 6);
+        assert!(pool.free.is_empty());
+        assert_eq!(pool.released.len(), 6);
+    }
+}
@@ -177,6 +177,8 @@ impl VertexFormat {
     }
 
     /// Convert into gfx digestible type.
+    /// Use `VertexInputRate::Instance(1)` for bindings that advance once per instance,
+    /// e.g. per-instance transforms bound alongside mesh vertices.
     pub fn gfx_vertex_input_desc(
         &self,
         rate: crate::hal::pso::VertexInputRate,
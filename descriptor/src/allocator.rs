@@ -12,8 +12,8 @@ use {
     },
 };
 
-const MIN_SETS: u32 = 64;
-const MAX_SETS: u32 = 512;
+pub(crate) const MIN_SETS: u32 = 64;
+pub(crate) const MAX_SETS: u32 = 512;
 
 /// Descriptor set from allocator.
 #[derive(Debug)]
@@ -68,7 +68,7 @@ struct DescriptorPool<B: Backend> {
     freed: u32,
 }
 
-unsafe fn allocate_from_pool<B: Backend>(
+pub(crate) unsafe fn allocate_from_pool<B: Backend>(
     raw: &mut B::DescriptorPool,
     layout: &B::DescriptorSetLayout,
     count: u32,
@@ -1,4 +1,5 @@
 mod allocator;
 mod ranges;
+mod transient;
 
-pub use {allocator::*, ranges::*};
+pub use {allocator::*, ranges::*, transient::*};
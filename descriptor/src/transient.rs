@@ -0,0 +1,231 @@
+use {
+    crate::{
+        allocator::{allocate_from_pool, MAX_SETS, MIN_SETS},
+        ranges::*,
+    },
+    gfx_hal::{
+        device::{Device, OutOfMemory},
+        pso::{DescriptorPool as _, DescriptorPoolCreateFlags},
+        Backend,
+    },
+    smallvec::SmallVec,
+    std::collections::HashMap,
+};
+
+/// Pools of one frame for one layout
+/// and number of sets allocated from each pool since last reset.
+#[derive(Debug)]
+struct Bucket<P> {
+    pools: Vec<P>,
+    sizes: Vec<u32>,
+    used: Vec<u32>,
+}
+
+impl<P> Bucket<P> {
+    /// Find pool with at least one free set.
+    fn free_pool(&self) -> Option<usize> {
+        self.sizes
+            .iter()
+            .zip(&self.used)
+            .position(|(size, used)| used < size)
+    }
+
+    /// Size of the next pool. Each new pool is twice as large as previous one.
+    fn next_size(&self) -> u32 {
+        self.sizes
+            .last()
+            .map_or(MIN_SETS, |&size| (size * 2).min(MAX_SETS))
+    }
+}
+
+/// Pools of each frame in flight.
+/// Generic over pool type to keep bookkeeping apart from device calls.
+#[derive(Debug)]
+struct FramePools<P> {
+    frames: Vec<HashMap<DescriptorRanges, Bucket<P>>>,
+    current: usize,
+}
+
+impl<P> FramePools<P> {
+    fn new(frames_in_flight: u32) -> Self {
+        assert_ne!(frames_in_flight, 0, "At least one frame must be in flight");
+        FramePools {
+            frames: (0..frames_in_flight).map(|_| HashMap::new()).collect(),
+            current: 0,
+        }
+    }
+
+    /// Switch to slot of the frame and `reset` every pool of it.
+    fn next_frame(&mut self, frame_index: u64, mut reset: impl FnMut(&mut P)) {
+        self.current = (frame_index % self.frames.len() as u64) as usize;
+        for bucket in self.frames[self.current].values_mut() {
+            bucket.pools.iter_mut().for_each(&mut reset);
+            bucket.used.iter_mut().for_each(|used| *used = 0);
+        }
+    }
+
+    /// Allocate one set from a pool of the current frame with free sets.
+    /// New pool with specified number of sets is created by `create` if there is none.
+    fn allocate<S, E>(
+        &mut self,
+        ranges: DescriptorRanges,
+        create: impl FnOnce(u32) -> Result<P, E>,
+        allocate: impl FnOnce(&mut P) -> Result<S, E>,
+    ) -> Result<S, E> {
+        let bucket = self.frames[self.current]
+            .entry(ranges)
+            .or_insert_with(|| Bucket {
+                pools: Vec::new(),
+                sizes: Vec::new(),
+                used: Vec::new(),
+            });
+
+        let index = match bucket.free_pool() {
+            Some(index) => index,
+            None => {
+                let size = bucket.next_size();
+                bucket.pools.push(create(size)?);
+                bucket.sizes.push(size);
+                bucket.used.push(0);
+                bucket.pools.len() - 1
+            }
+        };
+
+        let set = allocate(&mut bucket.pools[index])?;
+        bucket.used[index] += 1;
+        Ok(set)
+    }
+
+    fn into_pools(self) -> impl Iterator<Item = P> {
+        self.frames
+            .into_iter()
+            .flat_map(|frame| frame.into_iter().flat_map(|(_, bucket)| bucket.pools))
+    }
+}
+
+/// Descriptor pool for sets used only during one frame.
+///
+/// Sets are never freed individually.
+/// Instead all sets of a frame are recycled at once when pools of that frame are reset,
+/// which is cheaper than freeing sets one by one.
+/// Each of `frames_in_flight` frames has its own pools,
+/// so sets of the frames still executing are not reset.
+///
+/// Graph owns one and resets it when the frame its pools were used by is complete,
+/// nodes allocate sets with `GraphContext::frame_descriptor_set`.
+#[derive(Debug)]
+pub struct TransientDescriptorPool<B: Backend> {
+    pools: FramePools<B::DescriptorPool>,
+    relevant: relevant::Relevant,
+}
+
+impl<B> TransientDescriptorPool<B>
+where
+    B: Backend,
+{
+    /// Create new pool for `frames_in_flight` frames.
+    pub fn new(frames_in_flight: u32) -> Self {
+        TransientDescriptorPool {
+            pools: FramePools::new(frames_in_flight),
+            relevant: relevant::Relevant,
+        }
+    }
+
+    /// Start frame with specified index.
+    /// Resets pools of the frame `frames_in_flight` frames before,
+    /// all sets allocated for that frame become invalid.
+    ///
+    /// # Safety
+    ///
+    /// Frame `frame_index - frames_in_flight` must be complete, its fences signaled.
+    pub unsafe fn next_frame(&mut self, frame_index: u64) {
+        self.pools.next_frame(frame_index, |pool| pool.reset());
+    }
+
+    /// Allocate descriptor set with specified layout for the current frame.
+    /// `DescriptorRanges` must match descriptor numbers of the layout.
+    ///
+    /// Set stays valid until pools of the current frame are reset
+    /// by `next_frame` `frames_in_flight` frames later.
+    pub unsafe fn frame_descriptor_set(
+        &mut self,
+        device: &B::Device,
+        layout: &B::DescriptorSetLayout,
+        layout_ranges: DescriptorRanges,
+    ) -> Result<B::DescriptorSet, OutOfMemory> {
+        self.pools.allocate(
+            layout_ranges,
+            |size| {
+                log::trace!(
+                    "Create new transient pool with {} sets and {:?} descriptors",
+                    size,
+                    layout_ranges * size,
+                );
+                // Sets are never freed individually, so `FREE_DESCRIPTOR_SET` is not needed.
+                device.create_descriptor_pool(
+                    size as usize,
+                    &(layout_ranges * size),
+                    DescriptorPoolCreateFlags::empty(),
+                )
+            },
+            |pool| {
+                let mut sets = SmallVec::<[B::DescriptorSet; 1]>::new();
+                allocate_from_pool::<B>(pool, layout, 1, &mut sets)?;
+                Ok(sets.pop().unwrap())
+            },
+        )
+    }
+
+    /// Destroy pool instance.
+    /// All sets allocated from this pool become invalid.
+    pub unsafe fn dispose(self, device: &B::Device) {
+        for pool in self.pools.into_pools() {
+            device.destroy_descriptor_pool(pool);
+        }
+        self.relevant.dispose();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Allocate `count` sets, returns number of pools created.
+    fn allocate(pools: &mut FramePools<u32>, count: u32) -> usize {
+        let ranges = DescriptorRanges::zero();
+        let mut created = 0;
+        for _ in 0..count {
+            pools
+                .allocate(
+                    ranges,
+                    |size| {
+                        created += 1;
+                        Ok::<_, ()>(size)
+                    },
+                    |_| Ok(()),
+                )
+                .unwrap();
+        }
+        created
+    }
+
+    #[test]
+    fn pools_are_reset_not_grown_next_frame() {
+        let mut pools = FramePools::new(2);
+
+        pools.next_frame(0, |_| panic!("Frame 0 has no pools yet"));
+        assert_eq!(allocate(&mut pools, 200), 3);
+
+        // Second frame in flight gets own pools.
+        pools.next_frame(1, |_| panic!("Frame 1 has no pools yet"));
+        assert_eq!(allocate(&mut pools, 10), 1);
+
+        // Frame 2 resets pools of frame 0 and allocates as many sets from them.
+        let mut reset = Vec::new();
+        pools.next_frame(2, |&mut size| reset.push(size));
+        assert_eq!(reset, vec![64, 128, 256]);
+        assert_eq!(allocate(&mut pools, 200), 0);
+
+        assert_eq!(pools.into_pools().count(), 4);
+    }
+}
@@ -0,0 +1,768 @@
+use {
+    crate::{
+        barriers::Barriers,
+        command::{
+            CommandBuffer, CommandPool, Family, Fence, IndividualReset, InitialState, OneShot,
+            PendingOnceState, PrimaryLevel, QueueId, Submission, Transfer,
+        },
+        factory::Factory,
+        memory,
+        resource::{Buffer, BufferInfo, Escape, Handle, Image},
+        upload::{BufferState, ImageState, ImageStateOrLayout, UploadError},
+    },
+    rendy_core::hal::{
+        adapter::PhysicalDevice as _,
+        device::{OomOrDeviceLost, OutOfMemory},
+    },
+    std::{
+        collections::VecDeque,
+        iter::once,
+        sync::{
+            atomic::{AtomicBool, Ordering},
+            Arc, Mutex,
+        },
+    },
+};
+
+/// Initial capacity of staging arena.
+const DEFAULT_ARENA_CAPACITY: u64 = 1 << 20;
+
+/// Sub-allocation of staging arena.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+struct ArenaRange {
+    used: u64,
+    capacity: u64,
+}
+
+impl ArenaRange {
+    fn new(capacity: u64) -> Self {
+        ArenaRange { used: 0, capacity }
+    }
+
+    /// Allocate `size` bytes aligned to `align`.
+    /// Returns `None` if arena doesn't have enough space left.
+    fn allocate(&mut self, size: u64, align: u64) -> Option<u64> {
+        let offset = (self.used + align - 1) / align * align;
+        if offset + size > self.capacity {
+            None
+        } else {
+            self.used = offset + size;
+            Some(offset)
+        }
+    }
+}
+
+/// Capacity of the next arena that fits at least `size` bytes.
+fn grown_capacity(capacity: u64, size: u64) -> u64 {
+    let mut capacity = capacity.max(1);
+    while capacity < size {
+        capacity *= 2;
+    }
+    capacity
+}
+
+/// Staging arenas of the batch being accumulated.
+#[derive(Debug)]
+struct Arenas<A> {
+    arenas: Vec<A>,
+    range: ArenaRange,
+    capacity: u64,
+}
+
+impl<A> Arenas<A> {
+    fn new(capacity: u64) -> Self {
+        Arenas {
+            arenas: Vec::new(),
+            range: ArenaRange::new(0),
+            capacity,
+        }
+    }
+
+    /// Allocate `size` bytes aligned to `align` in the last arena.
+    /// Creates new arena with `create` if there is no room left.
+    /// Returns index of the arena and offset in it.
+    fn allocate<E>(
+        &mut self,
+        size: u64,
+        align: u64,
+        create: impl FnOnce(u64) -> Result<A, E>,
+    ) -> Result<(usize, u64), E> {
+        let offset = match self.range.allocate(size, align) {
+            Some(offset) if !self.arenas.is_empty() => offset,
+            _ => {
+                if !self.arenas.is_empty() {
+                    // Batch doesn't fit into arena. Following batches start with larger one.
+                    self.capacity = grown_capacity(self.capacity * 2, size);
+                } else {
+                    self.capacity = grown_capacity(self.capacity, size);
+                }
+                self.arenas.push(create(self.capacity)?);
+                self.range = ArenaRange::new(self.capacity);
+                self.range
+                    .allocate(size, align)
+                    .expect("Fresh arena fits the content")
+            }
+        };
+        Ok((self.arenas.len() - 1, offset))
+    }
+
+    /// Take arenas of the batch. Next batch starts with fresh arena.
+    fn take(&mut self) -> Vec<A> {
+        self.range = ArenaRange::new(0);
+        self.arenas.drain(..).collect()
+    }
+}
+
+/// Copies waiting for next submission.
+#[derive(Debug)]
+struct Batch<C> {
+    copies: Vec<C>,
+    submissions: usize,
+}
+
+impl<C> Batch<C> {
+    fn new() -> Self {
+        Batch {
+            copies: Vec::new(),
+            submissions: 0,
+        }
+    }
+
+    fn push(&mut self, copy: C) {
+        self.copies.push(copy);
+    }
+
+    /// Take all pending copies to record them into one submission.
+    fn submit(&mut self) -> Vec<C> {
+        self.submissions += 1;
+        std::mem::replace(&mut self.copies, Vec::new())
+    }
+}
+
+#[derive(Debug)]
+enum PendingCopy<B: rendy_core::hal::Backend> {
+    Buffer {
+        arena: usize,
+        offset: u64,
+        size: u64,
+        target: Handle<Buffer<B>>,
+        target_offset: u64,
+    },
+    Image {
+        arena: usize,
+        offset: u64,
+        target: Handle<Image<B>>,
+        target_layout: rendy_core::hal::image::Layout,
+        data_width: u32,
+        data_height: u32,
+        image_layers: rendy_core::hal::image::SubresourceLayers,
+        image_offset: rendy_core::hal::image::Offset,
+        image_extent: rendy_core::hal::image::Extent,
+    },
+}
+
+/// Command buffer recorded by `BatchUploader::flush`
+/// and flag set once `UploadFuture` observes its completion.
+type SubmittedBuffer<B> = (
+    CommandBuffer<B, Transfer, PendingOnceState, PrimaryLevel, IndividualReset>,
+    Arc<AtomicBool>,
+);
+
+/// Fence and staging memory of submitted uploads.
+#[derive(Debug)]
+struct InFlight<B: rendy_core::hal::Backend> {
+    fence: Fence<B>,
+    staging: Vec<Escape<Buffer<B>>>,
+    complete: Arc<AtomicBool>,
+}
+
+/// Uploads which futures were dropped before completion.
+/// `BatchUploader` releases them once their fences are signaled.
+type Abandoned<B> = Arc<Mutex<Vec<InFlight<B>>>>;
+
+/// Accumulates buffer and image uploads and submits them all at once.
+///
+/// Contents are copied into shared staging arena when upload is requested,
+/// instead of creating staging buffer for each upload like `Factory::upload_buffer` does.
+/// When arena runs out of space a larger one is allocated for the rest of the batch.
+/// Each `flush` records all pending copies into a single command buffer
+/// and submits it to the queue uploader was created for.
+#[derive(Debug)]
+pub struct BatchUploader<B: rendy_core::hal::Backend> {
+    queue: QueueId,
+    pool: CommandPool<B, Transfer, IndividualReset>,
+    initial: Vec<CommandBuffer<B, Transfer, InitialState, PrimaryLevel, IndividualReset>>,
+    submitted: VecDeque<SubmittedBuffer<B>>,
+    abandoned: Abandoned<B>,
+    arenas: Arenas<Escape<Buffer<B>>>,
+    batch: Batch<PendingCopy<B>>,
+    barriers: Barriers<B>,
+}
+
+impl<B> BatchUploader<B>
+where
+    B: rendy_core::hal::Backend,
+{
+    /// Create uploader that submits to the first queue of the `family`.
+    pub fn new(factory: &Factory<B>, family: &Family<B>) -> Result<Self, OutOfMemory> {
+        Self::with_capacity(factory, family, DEFAULT_ARENA_CAPACITY)
+    }
+
+    /// Create uploader that submits to the first queue of the `family`
+    /// and starts with staging arena of `capacity` bytes.
+    pub fn with_capacity(
+        factory: &Factory<B>,
+        family: &Family<B>,
+        capacity: u64,
+    ) -> Result<Self, OutOfMemory> {
+        let pool = factory
+            .create_command_pool(family)?
+            .with_capability()
+            .expect("All queue families support transfer operations");
+
+        Ok(BatchUploader {
+            queue: QueueId {
+                family: family.id(),
+                index: 0,
+            },
+            pool,
+            initial: Vec::new(),
+            submitted: VecDeque::new(),
+            abandoned: Arc::new(Mutex::new(Vec::new())),
+            arenas: Arenas::new(capacity),
+            batch: Batch::new(),
+            barriers: Barriers::new(
+                rendy_core::hal::pso::PipelineStage::TRANSFER,
+                rendy_core::hal::buffer::Access::TRANSFER_WRITE,
+                rendy_core::hal::image::Access::TRANSFER_WRITE,
+            ),
+        })
+    }
+
+    /// Queue uploads are submitted to.
+    pub fn queue(&self) -> QueueId {
+        self.queue
+    }
+
+    /// Number of uploads waiting for `flush`.
+    pub fn pending(&self) -> usize {
+        self.batch.copies.len()
+    }
+
+    /// Number of submissions made by this uploader.
+    pub fn submissions(&self) -> usize {
+        self.batch.submissions
+    }
+
+    /// Copy `content` into staging arena, growing it if necessary.
+    unsafe fn stage<T>(
+        &mut self,
+        factory: &Factory<B>,
+        content: &[T],
+    ) -> Result<(usize, u64), UploadError>
+    where
+        T: 'static + Copy,
+    {
+        let size = content.len() as u64 * std::mem::size_of::<T>() as u64;
+        let align = factory
+            .physical()
+            .limits()
+            .optimal_buffer_copy_offset_alignment
+            .max(4);
+
+        let (arena, offset) = self.arenas.allocate(size, align, |capacity| {
+            factory
+                .create_buffer(
+                    BufferInfo {
+                        size: capacity,
+                        usage: rendy_core::hal::buffer::Usage::TRANSFER_SRC,
+                    },
+                    memory::Upload,
+                )
+                .map_err(UploadError::Create)
+        })?;
+
+        factory
+            .upload_visible_buffer(&mut self.arenas.arenas[arena], offset, content)
+            .map_err(UploadError::Map)?;
+
+        Ok((arena, offset))
+    }
+
+    /// Upload `content` into `buffer` at `offset`.
+    /// Copy is submitted on next `flush`.
+    ///
+    /// # Safety
+    ///
+    /// Same as for `Factory::upload_buffer`.
+    /// Both `last` and `next` states must be on queue of this uploader.
+    pub unsafe fn upload_buffer<T>(
+        &mut self,
+        factory: &Factory<B>,
+        buffer: Handle<Buffer<B>>,
+        offset: u64,
+        content: &[T],
+        last: Option<BufferState>,
+        next: BufferState,
+    ) -> Result<(), UploadError>
+    where
+        T: 'static + Copy,
+    {
+        assert!(buffer
+            .info()
+            .usage
+            .contains(rendy_core::hal::buffer::Usage::TRANSFER_DST));
+        assert_eq!(next.queue, self.queue);
+        if let Some(last) = last {
+            assert_eq!(last.queue, self.queue, "Can't sync resources across queues");
+        }
+
+        let (arena, staging_offset) = self.stage(factory, content)?;
+
+        self.barriers.add_buffer(
+            last.map_or(rendy_core::hal::pso::PipelineStage::empty(), |l| l.stage),
+            rendy_core::hal::buffer::Access::empty(),
+            next.stage,
+            next.access,
+        );
+
+        self.batch.push(PendingCopy::Buffer {
+            arena,
+            offset: staging_offset,
+            size: content.len() as u64 * std::mem::size_of::<T>() as u64,
+            target: buffer,
+            target_offset: offset,
+        });
+        Ok(())
+    }
+
+    /// Upload `content` into image layers.
+    /// Copy is submitted on next `flush`.
+    ///
+    /// # Safety
+    ///
+    /// Same as for `Factory::upload_image`.
+    /// Both `last` and `next` states must be on queue of this uploader.
+    pub unsafe fn upload_image<T>(
+        &mut self,
+        factory: &Factory<B>,
+        image: Handle<Image<B>>,
+        data_width: u32,
+        data_height: u32,
+        image_layers: rendy_core::hal::image::SubresourceLayers,
+        image_offset: rendy_core::hal::image::Offset,
+        image_extent: rendy_core::hal::image::Extent,
+        content: &[T],
+        last: impl Into<ImageStateOrLayout>,
+        next: ImageState,
+    ) -> Result<(), UploadError>
+    where
+        T: 'static + Copy,
+    {
+        use rendy_core::hal::image::{Access, Layout};
+
+        assert!(image
+            .info()
+            .usage
+            .contains(rendy_core::hal::image::Usage::TRANSFER_DST));
+        assert_eq!(image.format().surface_desc().aspects, image_layers.aspects);
+        assert_eq!(next.queue, self.queue);
+
+        let whole_extent = image.kind().level_extent(image_layers.level);
+        let whole_level =
+            image_offset == rendy_core::hal::image::Offset::ZERO && image_extent == whole_extent;
+
+        let (last_stage, mut last_access, last_layout) = match last.into() {
+            ImageStateOrLayout::State(last) => {
+                assert_eq!(last.queue, self.queue, "Can't sync resources across queues");
+                (last.stage, last.access, last.layout)
+            }
+            ImageStateOrLayout::Layout(layout) => (
+                rendy_core::hal::pso::PipelineStage::TOP_OF_PIPE,
+                Access::empty(),
+                layout,
+            ),
+        };
+        let last_layout = if whole_level {
+            Layout::Undefined
+        } else {
+            last_layout
+        };
+
+        let target_layout = match (last_layout, next.layout) {
+            (Layout::TransferDstOptimal, _) => Layout::TransferDstOptimal,
+            (_, Layout::General) => Layout::General,
+            (Layout::General, _) => Layout::General,
+            _ => Layout::TransferDstOptimal,
+        };
+
+        if last_layout == Layout::Undefined || last_layout == target_layout {
+            last_access = Access::empty();
+        }
+
+        let (arena, staging_offset) = self.stage(factory, content)?;
+
+        self.barriers.add_image(
+            image.clone(),
+            rendy_core::hal::image::SubresourceRange {
+                aspects: image_layers.aspects,
+                levels: image_layers.level..image_layers.level + 1,
+                layers: image_layers.layers.clone(),
+            },
+            last_stage,
+            last_access,
+            last_layout,
+            target_layout,
+            next.stage,
+            next.access,
+            next.layout,
+        );
+
+        self.batch.push(PendingCopy::Image {
+            arena,
+            offset: staging_offset,
+            target: image,
+            target_layout,
+            data_width,
+            data_height,
+            image_layers,
+            image_offset,
+            image_extent,
+        });
+        Ok(())
+    }
+
+    /// Record all pending uploads into one command buffer and submit it.
+    /// Returned future owns staging arenas until uploads complete.
+    ///
+    /// Command buffers of completed futures are reused.
+    /// Staging memory of dropped futures is released once their uploads complete.
+    pub fn flush(
+        &mut self,
+        factory: &Factory<B>,
+        family: &mut Family<B>,
+    ) -> Result<UploadFuture<B>, OutOfMemory> {
+        assert_eq!(family.id(), self.queue.family);
+
+        self.release_abandoned(factory)?;
+
+        while let Some((_, complete)) = self.submitted.front() {
+            if !complete.load(Ordering::Acquire) {
+                break;
+            }
+            let (buffer, _) = self.submitted.pop_front().unwrap();
+            self.initial.push(unsafe { buffer.mark_complete() }.reset());
+        }
+
+        let mut fence = factory.create_fence(false)?;
+        let complete = Arc::new(AtomicBool::new(false));
+
+        let initial = match self.initial.pop() {
+            Some(initial) => initial,
+            None => self.pool.allocate_buffers(1).remove(0),
+        };
+        let mut recording = initial.begin(OneShot, ());
+        let mut encoder = recording.encoder();
+
+        // Empty batch still signals the fence.
+        let copies = self.batch.submit();
+        let has_copies = !copies.is_empty();
+        if has_copies {
+            self.barriers.encode_before(&mut encoder);
+        }
+        for copy in copies {
+            unsafe {
+                match copy {
+                    PendingCopy::Buffer {
+                        arena,
+                        offset,
+                        size,
+                        target,
+                        target_offset,
+                    } => encoder.copy_buffer(
+                        self.arenas.arenas[arena].raw(),
+                        target.raw(),
+                        Some(rendy_core::hal::command::BufferCopy {
+                            src: offset,
+                            dst: target_offset,
+                            size,
+                        }),
+                    ),
+                    PendingCopy::Image {
+                        arena,
+                        offset,
+                        target,
+                        target_layout,
+                        data_width,
+                        data_height,
+                        image_layers,
+                        image_offset,
+                        image_extent,
+                    } => encoder.copy_buffer_to_image(
+                        self.arenas.arenas[arena].raw(),
+                        target.raw(),
+                        target_layout,
+                        Some(rendy_core::hal::command::BufferImageCopy {
+                            buffer_offset: offset,
+                            buffer_width: data_width,
+                            buffer_height: data_height,
+                            image_layers,
+                            image_offset,
+                            image_extent,
+                        }),
+                    ),
+                }
+            }
+        }
+        if has_copies {
+            self.barriers.encode_after(&mut encoder);
+        }
+
+        let (submit, pending) = recording.finish().submit_once();
+        unsafe {
+            family.queue_mut(self.queue.index).submit(
+                Some(Submission::new().submits(once(submit))),
+                Some(&mut fence),
+            );
+        }
+        self.submitted.push_back((pending, complete.clone()));
+
+        Ok(UploadFuture {
+            in_flight: Some(InFlight {
+                fence,
+                staging: self.arenas.take(),
+                complete,
+            }),
+            abandoned: self.abandoned.clone(),
+        })
+    }
+
+    /// Release fences and staging memory of dropped futures
+    /// which uploads are complete.
+    fn release_abandoned(&mut self, factory: &Factory<B>) -> Result<(), OutOfMemory> {
+        let mut abandoned = self.abandoned.lock().unwrap();
+        let mut index = 0;
+        while index < abandoned.len() {
+            let in_flight = &mut abandoned[index];
+            let complete = match factory.wait_for_fence(&mut in_flight.fence, 0) {
+                Ok(complete) => complete,
+                Err(OomOrDeviceLost::OutOfMemory(oom)) => return Err(oom),
+                // Lost device never signals the fence, keep staging memory until disposed.
+                Err(OomOrDeviceLost::DeviceLost(_)) => false,
+            };
+
+            if complete {
+                let in_flight = abandoned.swap_remove(index);
+                in_flight.complete.store(true, Ordering::Release);
+                factory.destroy_fence(in_flight.fence);
+            } else {
+                index += 1;
+            }
+        }
+        Ok(())
+    }
+
+    /// Dispose of the uploader.
+    ///
+    /// # Safety
+    ///
+    /// Uploads submitted by this uploader must be complete.
+    pub unsafe fn dispose(mut self, factory: &Factory<B>) {
+        for in_flight in self.abandoned.lock().unwrap().drain(..) {
+            factory.destroy_fence(in_flight.fence);
+        }
+        self.pool.free_buffers(self.initial.drain(..));
+        self.pool.free_buffers(
+            self.submitted
+                .drain(..)
+                .map(|(buffer, _)| buffer.mark_complete()),
+        );
+        factory.destroy_command_pool(self.pool);
+    }
+}
+
+/// Uploads submitted by `BatchUploader::flush`.
+///
+/// `UploadFuture::wait` releases staging memory and the fence.
+/// If future is dropped instead, they are handed back to the uploader
+/// which releases them once uploads are complete.
+#[derive(Debug)]
+#[must_use]
+pub struct UploadFuture<B: rendy_core::hal::Backend> {
+    in_flight: Option<InFlight<B>>,
+    abandoned: Abandoned<B>,
+}
+
+impl<B> UploadFuture<B>
+where
+    B: rendy_core::hal::Backend,
+{
+    /// Check if uploads are complete without blocking.
+    pub fn is_complete(&mut self, factory: &Factory<B>) -> Result<bool, OomOrDeviceLost> {
+        let in_flight = self.in_flight.as_mut().unwrap();
+        if !in_flight.fence.is_signaled() {
+            factory.wait_for_fence(&mut in_flight.fence, 0)?;
+        }
+        let complete = in_flight.fence.is_signaled();
+        in_flight.complete.store(complete, Ordering::Release);
+        Ok(complete)
+    }
+
+    /// Block until uploads are complete,
+    /// then release resources used for them.
+    pub fn wait(mut self, factory: &Factory<B>) -> Result<(), OomOrDeviceLost> {
+        let in_flight = self.in_flight.as_mut().unwrap();
+        if !in_flight.fence.is_signaled() {
+            factory.wait_for_fence(&mut in_flight.fence, !0)?;
+        }
+        let in_flight = self.in_flight.take().unwrap();
+        in_flight.complete.store(true, Ordering::Release);
+        factory.destroy_fence(in_flight.fence);
+        drop(in_flight.staging);
+        Ok(())
+    }
+}
+
+impl<B> Drop for UploadFuture<B>
+where
+    B: rendy_core::hal::Backend,
+{
+    fn drop(&mut self) {
+        if let Some(in_flight) = self.in_flight.take() {
+            self.abandoned.lock().unwrap().push(in_flight);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn small_uploads_share_arena() {
+        let mut range = ArenaRange::new(grown_capacity(DEFAULT_ARENA_CAPACITY, 64));
+        let offsets: Vec<_> = (0..100)
+            .map(|_| range.allocate(64, 256).expect("Fits in the arena"))
+            .collect();
+
+        assert_eq!(offsets[0], 0);
+        assert!(offsets.windows(2).all(|w| w[1] >= w[0] + 64));
+        assert!(offsets.iter().all(|o| o % 256 == 0));
+    }
+
+    #[test]
+    fn arena_overflow_is_detected() {
+        let mut range = ArenaRange::new(100);
+        assert_eq!(range.allocate(60, 4), Some(0));
+        assert_eq!(range.allocate(60, 4), None);
+        assert_eq!(range.allocate(40, 4), Some(60));
+    }
+
+    #[test]
+    fn small_uploads_are_staged_in_one_arena() {
+        let mut arenas = Arenas::new(DEFAULT_ARENA_CAPACITY);
+        let mut created = 0;
+
+        let copies: Vec<_> = (0..100)
+            .map(|_| {
+                arenas
+                    .allocate::<()>(64, 256, |capacity| {
+                        created += 1;
+                        Ok(capacity)
+                    })
+                    .unwrap()
+            })
+            .collect();
+
+        assert!(copies.iter().all(|&(arena, _)| arena == 0));
+        assert_eq!(created, 1);
+        assert_eq!(arenas.take(), vec![DEFAULT_ARENA_CAPACITY]);
+    }
+
+    #[test]
+    fn oversized_batch_grows_arena() {
+        let mut arenas = Arenas::new(256);
+        let created: Vec<_> = (0..3)
+            .map(|_| arenas.allocate::<()>(200, 4, Ok).unwrap())
+            .collect();
+
+        assert_eq!(created, vec![(0, 0), (1, 0), (1, 200)]);
+        assert_eq!(arenas.take(), vec![256, 512]);
+        assert_eq!(arenas.allocate::<()>(100, 4, Ok).unwrap(), (0, 0));
+        assert_eq!(arenas.take(), vec![512]);
+    }
+
+    #[test]
+    fn capacity_grows_to_fit_content() {
+        assert_eq!(grown_capacity(1024, 100), 1024);
+        assert_eq!(grown_capacity(1024, 3000), 4096);
+        assert_eq!(grown_capacity(0, 3), 4);
+    }
+}
+
+#[cfg(all(test, feature = "empty"))]
+mod device_tests {
+    use {
+        super::*,
+        crate::{
+            factory::{init_with_instance, Config},
+            memory::Data,
+        },
+        rendy_core::{
+            empty::Backend,
+            hal::{buffer::Usage, Instance as _},
+            Instance,
+        },
+    };
+
+    #[test]
+    fn small_uploads_are_submitted_once() {
+        let instance = rendy_core::empty::Instance::create("rendy", 1).unwrap();
+        let config: Config = Default::default();
+        let (factory, mut families) =
+            init_with_instance::<Backend>(Instance::new(instance), &config).unwrap();
+
+        let mut uploader = BatchUploader::new(&factory, families.family_by_index(0)).unwrap();
+        let buffer: Handle<Buffer<Backend>> = factory
+            .create_buffer(
+                BufferInfo {
+                    size: 1024,
+                    usage: Usage::TRANSFER_DST,
+                },
+                Data,
+            )
+            .unwrap()
+            .into();
+
+        let queue = uploader.queue();
+        let content = [0u32; 16];
+        for i in 0..16 {
+            unsafe {
+                uploader
+                    .upload_buffer(
+                        &factory,
+                        buffer.clone(),
+                        i * 64,
+                        &content,
+                        None,
+                        BufferState::new(queue),
+                    )
+                    .unwrap();
+            }
+        }
+        assert_eq!(uploader.pending(), 16);
+        assert_eq!(uploader.submissions(), 0);
+
+        let future = uploader
+            .flush(&factory, families.family_by_index_mut(0))
+            .unwrap();
+
+        assert_eq!(uploader.pending(), 0);
+        assert_eq!(uploader.submissions(), 1);
+
+        future.wait(&factory).unwrap();
+        unsafe {
+            uploader.dispose(&factory);
+        }
+    }
+}
@@ -4,7 +4,7 @@ use {
         command::{
             CommandBuffer, CommandPool, Encoder, Families, Family, Graphics, IndividualReset,
             InitialState, Level, OneShot, PendingOnceState, PrimaryLevel, QueueId, RecordingState,
-            Submission, Supports,
+            Submission, Supports, Transfer,
         },
         core::Device,
         resource::{Handle, Image},
@@ -134,6 +134,382 @@ impl From<BlitRegion> for rendy_core::hal::command::ImageBlit {
     }
 }
 
+/// A region to be copied including the source and destination images and states.
+/// Unlike blit, copy doesn't scale, so both regions have the same `extent`.
+#[derive(Debug, Clone)]
+pub struct CopyRegion {
+    /// Region to copy from
+    pub src: CopyImageState,
+    /// Region to copy to
+    pub dst: CopyImageState,
+    /// Extent of the region in texels of the source image
+    pub extent: rendy_core::hal::image::Extent,
+}
+
+impl From<CopyRegion> for rendy_core::hal::command::ImageCopy {
+    fn from(copy: CopyRegion) -> Self {
+        rendy_core::hal::command::ImageCopy {
+            src_subresource: copy.src.subresource,
+            src_offset: copy.src.offset,
+            dst_subresource: copy.dst.subresource,
+            dst_offset: copy.dst.offset,
+            extent: copy.extent,
+        }
+    }
+}
+
+/// A region and image states for one image in a copy.
+#[derive(Debug, Clone)]
+pub struct CopyImageState {
+    /// Subresource to use for copy
+    pub subresource: rendy_core::hal::image::SubresourceLayers,
+    /// Image offset to use for copy
+    pub offset: rendy_core::hal::image::Offset,
+    /// Last image stage before copy
+    pub last_stage: rendy_core::hal::pso::PipelineStage,
+    /// Last image access before copy
+    pub last_access: rendy_core::hal::image::Access,
+    /// Last image layout before copy
+    pub last_layout: rendy_core::hal::image::Layout,
+    /// Image stage after copy
+    pub next_stage: rendy_core::hal::pso::PipelineStage,
+    /// Image access after copy
+    pub next_access: rendy_core::hal::image::Access,
+    /// Image layout after copy
+    pub next_layout: rendy_core::hal::image::Layout,
+}
+
+/// Image of the copy.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum CopySide {
+    /// Image copied from.
+    Src,
+    /// Image copied to.
+    Dst,
+}
+
+impl std::fmt::Display for CopySide {
+    fn fmt(&self, fmt: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            CopySide::Src => write!(fmt, "source"),
+            CopySide::Dst => write!(fmt, "destination"),
+        }
+    }
+}
+
+/// Error returned by `Factory::copy_image` when region doesn't fit images
+/// or images can't be copied.
+#[derive(Clone, Debug, PartialEq)]
+pub enum ImageCopyError {
+    /// Formats have different texel block size or aspects.
+    Format {
+        /// Format of the source image.
+        src: rendy_core::hal::format::Format,
+        /// Format of the destination image.
+        dst: rendy_core::hal::format::Format,
+    },
+    /// Region references mip level image doesn't have.
+    Level {
+        /// Index of the region.
+        region: usize,
+        /// Image of the region.
+        side: CopySide,
+        /// Level referenced by the region.
+        level: rendy_core::hal::image::Level,
+        /// Number of mip levels in the image.
+        levels: rendy_core::hal::image::Level,
+    },
+    /// Region references array layers image doesn't have
+    /// or numbers of source and destination layers are different.
+    Layers {
+        /// Index of the region.
+        region: usize,
+        /// Image of the region.
+        side: CopySide,
+        /// Layers referenced by the region.
+        layers: Range<rendy_core::hal::image::Layer>,
+        /// Number of array layers in the image.
+        count: rendy_core::hal::image::Layer,
+    },
+    /// Region exceeds extent of the mip level.
+    Extent {
+        /// Index of the region.
+        region: usize,
+        /// Image of the region.
+        side: CopySide,
+        /// Offset of the region.
+        offset: rendy_core::hal::image::Offset,
+        /// Extent of the region in texels of the image.
+        extent: rendy_core::hal::image::Extent,
+        /// Extent of the mip level.
+        level_extent: rendy_core::hal::image::Extent,
+    },
+    /// Region of block-compressed image isn't aligned to blocks
+    /// and doesn't end at the edge of the mip level.
+    BlockAlignment {
+        /// Index of the region.
+        region: usize,
+        /// Image of the region.
+        side: CopySide,
+        /// Width and height of the block.
+        block: (u8, u8),
+    },
+    /// Region references aspects image format doesn't have
+    /// or source and destination aspects are different.
+    Aspects {
+        /// Index of the region.
+        region: usize,
+        /// Image of the region.
+        side: CopySide,
+        /// Aspects referenced by the region.
+        aspects: rendy_core::hal::format::Aspects,
+        /// Aspects of the image format.
+        format_aspects: rendy_core::hal::format::Aspects,
+    },
+    /// Queue family has no blitter commands pool.
+    /// Copies are recorded only on queues of graphics families.
+    Queue(QueueId),
+    /// Failed to record the copy.
+    OutOfMemory(OutOfMemory),
+}
+
+impl std::fmt::Display for ImageCopyError {
+    fn fmt(&self, fmt: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ImageCopyError::Format { src, dst } => write!(
+                fmt,
+                "Image of format {:?} can't be copied into image of format {:?}",
+                src, dst
+            ),
+            ImageCopyError::Level {
+                region,
+                side,
+                level,
+                levels,
+            } => write!(
+                fmt,
+                "Region {} references level {} of {} image with {} levels",
+                region, level, side, levels
+            ),
+            ImageCopyError::Layers {
+                region,
+                side,
+                layers,
+                count,
+            } => write!(
+                fmt,
+                "Region {} references layers {:?} of {} image with {} layers",
+                region, layers, side, count
+            ),
+            ImageCopyError::Extent {
+                region,
+                side,
+                offset,
+                extent,
+                level_extent,
+            } => write!(
+                fmt,
+                "Region {} at {:?} with extent {:?} exceeds {} image level extent {:?}",
+                region, offset, extent, side, level_extent
+            ),
+            ImageCopyError::BlockAlignment {
+                region,
+                side,
+                block,
+            } => write!(
+                fmt,
+                "Region {} of {} image is not aligned to {}x{} blocks",
+                region, side, block.0, block.1
+            ),
+            ImageCopyError::Aspects {
+                region,
+                side,
+                aspects,
+                format_aspects,
+            } => write!(
+                fmt,
+                "Region {} references aspects {:?} of {} image with aspects {:?}",
+                region, aspects, side, format_aspects
+            ),
+            ImageCopyError::Queue(queue) => {
+                write!(fmt, "Images can't be copied on queue {:?}", queue)
+            }
+            ImageCopyError::OutOfMemory(err) => write!(fmt, "Copy failed: {:?}", err),
+        }
+    }
+}
+
+impl std::error::Error for ImageCopyError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            ImageCopyError::OutOfMemory(err) => Some(err),
+            _ => None,
+        }
+    }
+}
+
+/// Properties of image that regions are validated against.
+#[derive(Clone, Copy, Debug)]
+pub(crate) struct CopyImageDesc {
+    pub(crate) kind: rendy_core::hal::image::Kind,
+    pub(crate) levels: rendy_core::hal::image::Level,
+    pub(crate) format: rendy_core::hal::format::Format,
+}
+
+impl CopyImageDesc {
+    pub(crate) fn of<B: rendy_core::hal::Backend>(image: &Image<B>) -> Self {
+        CopyImageDesc {
+            kind: image.kind(),
+            levels: image.levels(),
+            format: image.format(),
+        }
+    }
+}
+
+/// Check that formats can be copied and every region fits both images.
+pub(crate) fn validate_copy<'a>(
+    src: CopyImageDesc,
+    dst: CopyImageDesc,
+    regions: impl IntoIterator<Item = &'a rendy_core::hal::command::ImageCopy>,
+) -> Result<(), ImageCopyError> {
+    let src_desc = src.format.surface_desc();
+    let dst_desc = dst.format.surface_desc();
+
+    // Depth and stencil formats are copied only into the same format.
+    let color = rendy_core::hal::format::Aspects::COLOR;
+    let compatible = if src_desc.aspects == color && dst_desc.aspects == color {
+        src_desc.bits == dst_desc.bits
+    } else {
+        src.format == dst.format
+    };
+    if !compatible {
+        return Err(ImageCopyError::Format {
+            src: src.format,
+            dst: dst.format,
+        });
+    }
+
+    for (index, region) in regions.into_iter().enumerate() {
+        // Extent is specified in texels of the source image,
+        // destination covers the same number of blocks.
+        // Region ending at the edge of the level may cover a partial block.
+        let blocks = |size: u32, dim: u8| (size + dim as u32 - 1) / dim as u32;
+        let dst_extent = rendy_core::hal::image::Extent {
+            width: blocks(region.extent.width, src_desc.dim.0) * dst_desc.dim.0 as u32,
+            height: blocks(region.extent.height, src_desc.dim.1) * dst_desc.dim.1 as u32,
+            depth: region.extent.depth,
+        };
+
+        validate_region(
+            index,
+            CopySide::Src,
+            src,
+            &region.src_subresource,
+            region.src_offset,
+            region.extent,
+        )?;
+        validate_region(
+            index,
+            CopySide::Dst,
+            dst,
+            &region.dst_subresource,
+            region.dst_offset,
+            dst_extent,
+        )?;
+
+        if region.src_subresource.aspects != region.dst_subresource.aspects {
+            return Err(ImageCopyError::Aspects {
+                region: index,
+                side: CopySide::Dst,
+                aspects: region.dst_subresource.aspects,
+                format_aspects: dst_desc.aspects,
+            });
+        }
+
+        if region.src_subresource.layers.len() != region.dst_subresource.layers.len() {
+            return Err(ImageCopyError::Layers {
+                region: index,
+                side: CopySide::Dst,
+                layers: region.dst_subresource.layers.clone(),
+                count: dst.kind.num_layers(),
+            });
+        }
+    }
+    Ok(())
+}
+
+fn validate_region(
+    region: usize,
+    side: CopySide,
+    image: CopyImageDesc,
+    subresource: &rendy_core::hal::image::SubresourceLayers,
+    offset: rendy_core::hal::image::Offset,
+    extent: rendy_core::hal::image::Extent,
+) -> Result<(), ImageCopyError> {
+    let format_aspects = image.format.surface_desc().aspects;
+    if subresource.aspects.is_empty() || !format_aspects.contains(subresource.aspects) {
+        return Err(ImageCopyError::Aspects {
+            region,
+            side,
+            aspects: subresource.aspects,
+            format_aspects,
+        });
+    }
+
+    if subresource.level >= image.levels {
+        return Err(ImageCopyError::Level {
+            region,
+            side,
+            level: subresource.level,
+            levels: image.levels,
+        });
+    }
+
+    let count = image.kind.num_layers();
+    if subresource.layers.start >= subresource.layers.end || subresource.layers.end > count {
+        return Err(ImageCopyError::Layers {
+            region,
+            side,
+            layers: subresource.layers.clone(),
+            count,
+        });
+    }
+
+    let level_extent = image.kind.level_extent(subresource.level);
+    let fits = |offset: i32, size: u32, total: u32| {
+        offset >= 0 && (offset as u64 + size as u64) <= total as u64
+    };
+    if !fits(offset.x, extent.width, level_extent.width)
+        || !fits(offset.y, extent.height, level_extent.height)
+        || !fits(offset.z, extent.depth, level_extent.depth)
+    {
+        return Err(ImageCopyError::Extent {
+            region,
+            side,
+            offset,
+            extent,
+            level_extent,
+        });
+    }
+
+    let block = image.format.surface_desc().dim;
+    let aligned = |offset: i32, size: u32, total: u32, block: u8| {
+        let block = block as u32;
+        offset as u32 % block == 0 && (size % block == 0 || offset as u32 + size == total)
+    };
+    if !aligned(offset.x, extent.width, level_extent.width, block.0)
+        || !aligned(offset.y, extent.height, level_extent.height, block.1)
+    {
+        return Err(ImageCopyError::BlockAlignment {
+            region,
+            side,
+            block,
+        });
+    }
+
+    Ok(())
+}
+
 /// A region and image states for one image in a blit.
 #[derive(Debug, Clone)]
 pub struct BlitImageState {
@@ -172,10 +548,17 @@ where
                 family_ops.push(None);
             }
 
+            // Blits require graphics queue, other families are left without blitter.
+            let pool = match family.create_pool(device)?.with_capability() {
+                Ok(pool) => pool,
+                Err(pool) => {
+                    pool.dispose(device);
+                    continue;
+                }
+            };
+
             family_ops[family.id().index] = Some(parking_lot::Mutex::new(FamilyGraphicsOps {
-                pool: family
-                    .create_pool(device)
-                    .map(|pool| pool.with_capability().unwrap())?,
+                pool,
                 initial: Vec::new(),
                 next: Vec::new(),
                 pending: VecDeque::new(),
@@ -253,6 +636,45 @@ where
         Ok(())
     }
 
+    /// Copy provided regions of `src_image` to `dst_image`.
+    /// Regions are not validated, see `Factory::copy_image`.
+    /// Fails with `ImageCopyError::Queue` if queue family doesn't support graphics.
+    ///
+    /// # Safety
+    ///
+    /// `device` must be the same that was used to create this `Blitter`.
+    /// `src` and `dst` must belong to the `device`.
+    /// regions' `last_*` states must be valid at the time of command execution (after memory transfers).
+    /// All regions must have distinct subresource layer and level combination.
+    ///
+    pub unsafe fn copy_image(
+        &self,
+        device: &Device<B>,
+        queue_id: QueueId,
+        src_image: &Handle<Image<B>>,
+        dst_image: &Handle<Image<B>>,
+        regions: impl IntoIterator<Item = CopyRegion>,
+    ) -> Result<(), ImageCopyError> {
+        let mut family_ops = self
+            .family_ops
+            .get(queue_id.family.index)
+            .and_then(Option::as_ref)
+            .ok_or(ImageCopyError::Queue(queue_id))?
+            .lock();
+
+        family_ops
+            .next_ops(device, queue_id.index)
+            .map_err(ImageCopyError::OutOfMemory)?;
+
+        let FamilyGraphicsOps { next, .. } = family_ops.deref_mut();
+
+        let next_ops = next[queue_id.index].as_mut().unwrap();
+        let mut encoder = next_ops.command_buffer.encoder();
+
+        copy_image(&mut encoder, src_image, dst_image, regions);
+        Ok(())
+    }
+
     /// Cleanup pending updates.
     ///
     /// # Safety
@@ -275,10 +697,9 @@ where
     ///
     pub(crate) unsafe fn flush(&mut self, families: &mut Families<B>) {
         for family in families.as_slice_mut() {
-            let blitter = self.family_ops[family.id().index]
-                .as_mut()
-                .expect("Blitter must be initialized for all families");
-            blitter.get_mut().flush(family);
+            if let Some(blitter) = self.family_ops[family.id().index].as_mut() {
+                blitter.get_mut().flush(family);
+            }
         }
     }
 
@@ -374,6 +795,81 @@ pub unsafe fn blit_image<B, C, L>(
     write_barriers.encode_after(encoder);
 }
 
+/// Copies one or more regions from src_image into dst_image.
+///
+/// # Safety
+///
+/// * `src_image` and `dst_image` must have been created from the same `Device`
+/// as `encoder`
+/// * regions must fit both images, see `Factory::copy_image`
+pub unsafe fn copy_image<B, C, L>(
+    encoder: &mut Encoder<'_, B, C, L>,
+    src_image: &Handle<Image<B>>,
+    dst_image: &Handle<Image<B>>,
+    regions: impl IntoIterator<Item = CopyRegion>,
+) where
+    B: rendy_core::hal::Backend,
+    C: Supports<Transfer>,
+    L: Level,
+{
+    let mut read_barriers = Barriers::new(
+        rendy_core::hal::pso::PipelineStage::TRANSFER,
+        rendy_core::hal::buffer::Access::TRANSFER_READ,
+        rendy_core::hal::image::Access::TRANSFER_READ,
+    );
+
+    let mut write_barriers = Barriers::new(
+        rendy_core::hal::pso::PipelineStage::TRANSFER,
+        rendy_core::hal::buffer::Access::TRANSFER_WRITE,
+        rendy_core::hal::image::Access::TRANSFER_WRITE,
+    );
+
+    let regions = regions
+        .into_iter()
+        .map(|reg| {
+            read_barriers.add_image(
+                src_image.clone(),
+                subresource_to_range(&reg.src.subresource),
+                reg.src.last_stage,
+                reg.src.last_access,
+                reg.src.last_layout,
+                rendy_core::hal::image::Layout::TransferSrcOptimal,
+                reg.src.next_stage,
+                reg.src.next_access,
+                reg.src.next_layout,
+            );
+
+            write_barriers.add_image(
+                dst_image.clone(),
+                subresource_to_range(&reg.dst.subresource),
+                reg.dst.last_stage,
+                reg.dst.last_access,
+                reg.dst.last_layout,
+                rendy_core::hal::image::Layout::TransferDstOptimal,
+                reg.dst.next_stage,
+                reg.dst.next_access,
+                reg.dst.next_layout,
+            );
+
+            reg.into()
+        })
+        .collect::<SmallVec<[_; 1]>>();
+
+    read_barriers.encode_before(encoder);
+    write_barriers.encode_before(encoder);
+
+    encoder.copy_image(
+        src_image.raw(),
+        rendy_core::hal::image::Layout::TransferSrcOptimal,
+        dst_image.raw(),
+        rendy_core::hal::image::Layout::TransferDstOptimal,
+        regions,
+    );
+
+    read_barriers.encode_after(encoder);
+    write_barriers.encode_after(encoder);
+}
+
 #[derive(Debug)]
 pub(crate) struct FamilyGraphicsOps<B: rendy_core::hal::Backend> {
     pool: CommandPool<B, Graphics, IndividualReset>,
@@ -502,3 +998,219 @@ where
         self.pool.dispose(device);
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use {
+        super::{validate_copy, CopyImageDesc, CopySide, ImageCopyError},
+        rendy_core::hal::{
+            command::ImageCopy,
+            format::{Aspects, Format},
+            image::{Extent, Kind, Offset, SubresourceLayers},
+        },
+    };
+
+    fn desc(width: u32, height: u32, format: Format) -> CopyImageDesc {
+        CopyImageDesc {
+            kind: Kind::D2(width, height, 1, 1),
+            levels: 2,
+            format,
+        }
+    }
+
+    fn copy(dst_offset: Offset, extent: Extent) -> ImageCopy {
+        let subresource = SubresourceLayers {
+            aspects: Aspects::COLOR,
+            level: 0,
+            layers: 0..1,
+        };
+        ImageCopy {
+            src_subresource: subresource.clone(),
+            src_offset: Offset::ZERO,
+            dst_subresource: subresource,
+            dst_offset,
+            extent,
+        }
+    }
+
+    #[test]
+    fn region_exceeding_extent_is_rejected() {
+        let src = desc(256, 256, Format::Rgba8Unorm);
+        let dst = desc(128, 128, Format::Rgba8Srgb);
+        let extent = Extent {
+            width: 64,
+            height: 64,
+            depth: 1,
+        };
+
+        assert_eq!(
+            validate_copy(src, dst, &[copy(Offset { x: 64, y: 64, z: 0 }, extent)]),
+            Ok(())
+        );
+
+        let overrun = Offset { x: 96, y: 0, z: 0 };
+        let err = validate_copy(
+            src,
+            dst,
+            &[copy(Offset::ZERO, extent), copy(overrun, extent)],
+        )
+        .unwrap_err();
+        assert_eq!(
+            err,
+            ImageCopyError::Extent {
+                region: 1,
+                side: CopySide::Dst,
+                offset: overrun,
+                extent,
+                level_extent: Extent {
+                    width: 128,
+                    height: 128,
+                    depth: 1,
+                },
+            }
+        );
+        assert_eq!(
+            err.to_string(),
+            "Region 1 at Offset { x: 96, y: 0, z: 0 } with extent \
+             Extent { width: 64, height: 64, depth: 1 } exceeds destination image \
+             level extent Extent { width: 128, height: 128, depth: 1 }"
+        );
+    }
+
+    #[test]
+    fn incompatible_formats_are_rejected() {
+        let extent = Extent {
+            width: 16,
+            height: 16,
+            depth: 1,
+        };
+        let regions = [copy(Offset::ZERO, extent)];
+
+        let err = validate_copy(
+            desc(64, 64, Format::Rgba8Unorm),
+            desc(64, 64, Format::Rgba16Sfloat),
+            &regions,
+        )
+        .unwrap_err();
+        assert_eq!(
+            err,
+            ImageCopyError::Format {
+                src: Format::Rgba8Unorm,
+                dst: Format::Rgba16Sfloat,
+            }
+        );
+        assert_eq!(
+            err.to_string(),
+            "Image of format Rgba8Unorm can't be copied into image of format Rgba16Sfloat"
+        );
+
+        // 4x4 block of BC1 is 64 bits, same as one Rg32Uint texel.
+        assert_eq!(
+            validate_copy(
+                desc(64, 64, Format::Bc1RgbaUnorm),
+                desc(16, 16, Format::Rg32Uint),
+                &regions,
+            ),
+            Ok(())
+        );
+
+        let unaligned = ImageCopy {
+            src_offset: Offset { x: 2, y: 0, z: 0 },
+            ..copy(Offset::ZERO, extent)
+        };
+        assert_eq!(
+            validate_copy(
+                desc(64, 64, Format::Bc1RgbaUnorm),
+                desc(64, 64, Format::Bc1RgbaSrgb),
+                &[unaligned],
+            ),
+            Err(ImageCopyError::BlockAlignment {
+                region: 0,
+                side: CopySide::Src,
+                block: (4, 4),
+            })
+        );
+    }
+
+    #[test]
+    fn partial_edge_blocks_cover_whole_destination_texels() {
+        // 6x6 BC1 level is 2x2 blocks, the last ones are partial.
+        let extent = Extent {
+            width: 6,
+            height: 6,
+            depth: 1,
+        };
+        let regions = [copy(Offset::ZERO, extent)];
+        assert_eq!(
+            validate_copy(
+                desc(6, 6, Format::Bc1RgbaUnorm),
+                desc(2, 2, Format::Rg32Uint),
+                &regions,
+            ),
+            Ok(())
+        );
+        assert_eq!(
+            validate_copy(
+                desc(6, 6, Format::Bc1RgbaUnorm),
+                desc(1, 1, Format::Rg32Uint),
+                &regions,
+            ),
+            Err(ImageCopyError::Extent {
+                region: 0,
+                side: CopySide::Dst,
+                offset: Offset::ZERO,
+                extent: Extent {
+                    width: 2,
+                    height: 2,
+                    depth: 1,
+                },
+                level_extent: Extent {
+                    width: 1,
+                    height: 1,
+                    depth: 1,
+                },
+            })
+        );
+    }
+
+    #[test]
+    fn aspects_missing_from_format_are_rejected() {
+        let extent = Extent {
+            width: 16,
+            height: 16,
+            depth: 1,
+        };
+        let mut region = copy(Offset::ZERO, extent);
+        region.src_subresource.aspects = Aspects::DEPTH;
+        region.dst_subresource.aspects = Aspects::DEPTH;
+        assert_eq!(
+            validate_copy(
+                desc(64, 64, Format::Rgba8Unorm),
+                desc(64, 64, Format::Rgba8Unorm),
+                &[region.clone()],
+            ),
+            Err(ImageCopyError::Aspects {
+                region: 0,
+                side: CopySide::Src,
+                aspects: Aspects::DEPTH,
+                format_aspects: Aspects::COLOR,
+            })
+        );
+
+        // Depth can't be copied into stencil of the same image format.
+        region.dst_subresource.aspects = Aspects::STENCIL;
+        assert_eq!(
+            validate_copy(
+                desc(64, 64, Format::D24UnormS8Uint),
+                desc(64, 64, Format::D24UnormS8Uint),
+                &[region],
+            ),
+            Err(ImageCopyError::Aspects {
+                region: 0,
+                side: CopySide::Dst,
+                aspects: Aspects::STENCIL,
+                format_aspects: Aspects::DEPTH | Aspects::STENCIL,
+            })
+        );
+    }
+}
@@ -231,6 +231,17 @@ pub trait DevicesConfigure {
     fn pick<B>(&self, adapters: &[rendy_core::hal::adapter::Adapter<B>]) -> usize
     where
         B: rendy_core::hal::Backend;
+
+    /// Pick adapters to try in order of preference.
+    /// Device is created on the first adapter that succeeds.
+    ///
+    /// Default implementation tries only adapter returned by `pick`.
+    fn pick_order<B>(&self, adapters: &[rendy_core::hal::adapter::Adapter<B>]) -> Vec<usize>
+    where
+        B: rendy_core::hal::Backend,
+    {
+        vec![self.pick(adapters)]
+    }
 }
 
 /// Basics adapters config.
@@ -266,3 +277,129 @@ impl DevicesConfigure for BasicDevicesConfigure {
             .0
     }
 }
+
+/// Adapter selection policy.
+///
+/// Unlike [`BasicDevicesConfigure`] it can fall back to software adapter
+/// if device creation fails on hardware ones.
+///
+/// [`BasicDevicesConfigure`]: struct.BasicDevicesConfigure.html
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum AdapterSelection {
+    /// Pick discrete GPU if present, then integrated, virtual and CPU.
+    HighPerformance,
+
+    /// Pick integrated GPU if present, then discrete, virtual and CPU.
+    LowPower,
+
+    /// Pick adapter with specified index among enumerated adapters.
+    Specific(usize),
+
+    /// Try hardware adapters in `HighPerformance` order,
+    /// then CPU adapters (e.g. llvmpipe or WARP).
+    SoftwareFallback,
+}
+
+impl Default for AdapterSelection {
+    fn default() -> Self {
+        AdapterSelection::HighPerformance
+    }
+}
+
+impl AdapterSelection {
+    /// Get indices of adapters to try in order of preference.
+    /// `device_types` are types of enumerated adapters.
+    pub fn adapter_order(
+        &self,
+        device_types: &[rendy_core::hal::adapter::DeviceType],
+    ) -> Vec<usize> {
+        use rendy_core::hal::adapter::DeviceType;
+
+        let rank = |device_type: &DeviceType, low_power: bool| match device_type {
+            DeviceType::DiscreteGpu if low_power => 1,
+            DeviceType::DiscreteGpu => 0,
+            DeviceType::IntegratedGpu if low_power => 0,
+            DeviceType::IntegratedGpu => 1,
+            DeviceType::VirtualGpu => 2,
+            DeviceType::Cpu => 4,
+            _ => 3,
+        };
+
+        let ordered = |low_power: bool| {
+            let mut order: Vec<usize> = (0..device_types.len()).collect();
+            order.sort_by_key(|&index| rank(&device_types[index], low_power));
+            order
+        };
+
+        match *self {
+            AdapterSelection::HighPerformance => ordered(false).into_iter().take(1).collect(),
+            AdapterSelection::LowPower => ordered(true).into_iter().take(1).collect(),
+            AdapterSelection::Specific(index) if index < device_types.len() => vec![index],
+            AdapterSelection::Specific(_) => Vec::new(),
+            AdapterSelection::SoftwareFallback => ordered(false),
+        }
+    }
+}
+
+impl DevicesConfigure for AdapterSelection {
+    fn pick<B>(&self, adapters: &[rendy_core::hal::adapter::Adapter<B>]) -> usize
+    where
+        B: rendy_core::hal::Backend,
+    {
+        *self
+            .pick_order(adapters)
+            .first()
+            .expect("No suitable adapters present")
+    }
+
+    fn pick_order<B>(&self, adapters: &[rendy_core::hal::adapter::Adapter<B>]) -> Vec<usize>
+    where
+        B: rendy_core::hal::Backend,
+    {
+        let device_types: Vec<_> = adapters
+            .iter()
+            .map(|adapter| adapter.info.device_type.clone())
+            .collect();
+        self.adapter_order(&device_types)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use {super::AdapterSelection, rendy_core::hal::adapter::DeviceType};
+
+    const ADAPTERS: &[DeviceType] = &[
+        DeviceType::Cpu,
+        DeviceType::IntegratedGpu,
+        DeviceType::DiscreteGpu,
+    ];
+
+    #[test]
+    fn hardware_policies() {
+        assert_eq!(
+            AdapterSelection::HighPerformance.adapter_order(ADAPTERS),
+            vec![2]
+        );
+        assert_eq!(AdapterSelection::LowPower.adapter_order(ADAPTERS), vec![1]);
+        assert_eq!(
+            AdapterSelection::Specific(0).adapter_order(ADAPTERS),
+            vec![0]
+        );
+        assert!(AdapterSelection::Specific(3)
+            .adapter_order(ADAPTERS)
+            .is_empty());
+    }
+
+    #[test]
+    fn software_adapter_is_tried_last() {
+        assert_eq!(
+            AdapterSelection::SoftwareFallback.adapter_order(ADAPTERS),
+            vec![2, 1, 0]
+        );
+        assert_eq!(
+            AdapterSelection::HighPerformance.adapter_order(&[DeviceType::Cpu]),
+            vec![0]
+        );
+    }
+}
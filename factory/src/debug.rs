@@ -0,0 +1,74 @@
+use {
+    crate::{
+        command::{CommandBuffer, Resettable},
+        core::Device,
+        resource::{Buffer, DescriptorSet, DescriptorSetLayout, Image},
+    },
+    rendy_core::hal::{device::Device as _, Backend},
+};
+
+/// Object that can be named for debugging tools like RenderDoc.
+/// See `Factory::set_object_name`.
+///
+/// Pipelines can't be named yet as backend doesn't expose it.
+pub trait DebugName<B: Backend> {
+    /// Attach `name` to the object.
+    ///
+    /// # Safety
+    ///
+    /// Object must be created from the `device`.
+    unsafe fn set_debug_name(&mut self, device: &Device<B>, name: &str);
+}
+
+impl<B> DebugName<B> for Buffer<B>
+where
+    B: Backend,
+{
+    unsafe fn set_debug_name(&mut self, device: &Device<B>, name: &str) {
+        self.assert_device_owner(device);
+        device.raw().set_buffer_name(self.raw_mut(), name);
+    }
+}
+
+impl<B> DebugName<B> for Image<B>
+where
+    B: Backend,
+{
+    unsafe fn set_debug_name(&mut self, device: &Device<B>, name: &str) {
+        self.assert_device_owner(device);
+        device.raw().set_image_name(self.raw_mut(), name);
+    }
+}
+
+impl<B> DebugName<B> for DescriptorSetLayout<B>
+where
+    B: Backend,
+{
+    unsafe fn set_debug_name(&mut self, device: &Device<B>, name: &str) {
+        self.assert_device_owner(device);
+        device
+            .raw()
+            .set_descriptor_set_layout_name(self.raw_mut(), name);
+    }
+}
+
+impl<B> DebugName<B> for DescriptorSet<B>
+where
+    B: Backend,
+{
+    unsafe fn set_debug_name(&mut self, device: &Device<B>, name: &str) {
+        self.assert_device_owner(device);
+        device.raw().set_descriptor_set_name(self.raw_mut(), name);
+    }
+}
+
+impl<B, C, S, L, R> DebugName<B> for CommandBuffer<B, C, S, L, R>
+where
+    B: Backend,
+    S: Resettable,
+{
+    unsafe fn set_debug_name(&mut self, device: &Device<B>, name: &str) {
+        assert_eq!(self.family().device, device.id());
+        device.raw().set_command_buffer_name(self.raw(), name);
+    }
+}
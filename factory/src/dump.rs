@@ -0,0 +1,356 @@
+//! Saving buffer and image content to files for debugging.
+//! Enabled with `dump` feature.
+
+use {
+    crate::{
+        command::Families,
+        factory::Factory,
+        resource::{Buffer, Image},
+        upload::{BufferReadback, BufferState, ImageState, UploadError},
+    },
+    rendy_core::hal::{
+        device::{MapError, OutOfMemory},
+        format::{Aspects, Format},
+        image::{Extent, SubresourceLayers},
+        Backend,
+    },
+    std::path::Path,
+};
+
+/// Error occured while dumping a resource to a file.
+#[derive(Debug)]
+pub enum DumpError {
+    /// Failed to read the resource back.
+    Upload(UploadError),
+    /// Failed to wait for device idle.
+    OutOfMemory(OutOfMemory),
+    /// Failed to map staging memory.
+    Map(MapError),
+    /// Image format can't be converted to RGBA8.
+    UnsupportedFormat(Format),
+    /// Failed to encode PNG.
+    Encoding(png::EncodingError),
+    /// Failed to write the file.
+    Io(std::io::Error),
+}
+
+impl std::fmt::Display for DumpError {
+    fn fmt(&self, fmt: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            DumpError::Upload(err) => write!(fmt, "Failed to read resource back: {}", err),
+            DumpError::OutOfMemory(err) => write!(fmt, "Failed to wait for device: {:?}", err),
+            DumpError::Map(err) => write!(fmt, "Failed to map staging memory: {:?}", err),
+            DumpError::UnsupportedFormat(format) => {
+                write!(fmt, "Format {:?} can't be saved as PNG", format)
+            }
+            DumpError::Encoding(err) => write!(fmt, "Failed to encode PNG: {}", err),
+            DumpError::Io(err) => write!(fmt, "Failed to write dump: {}", err),
+        }
+    }
+}
+
+impl std::error::Error for DumpError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            DumpError::Upload(err) => Some(err),
+            DumpError::Encoding(err) => Some(err),
+            DumpError::Io(err) => Some(err),
+            _ => None,
+        }
+    }
+}
+
+impl<B> Factory<B>
+where
+    B: Backend,
+{
+    /// Save whole buffer content to a file as is.
+    ///
+    /// Submits the copy and waits for device idle,
+    /// so anything written by frames run before the call is in the file.
+    /// This is a debugging aid and stalls the device.
+    ///
+    /// # Safety
+    ///
+    /// Same as for [`read_buffer`].
+    ///
+    /// [`read_buffer`]: #method.read_buffer
+    pub unsafe fn dump_buffer_to_bin(
+        &mut self,
+        families: &mut Families<B>,
+        buffer: &Buffer<B>,
+        last: BufferState,
+        path: impl AsRef<Path>,
+    ) -> Result<(), DumpError> {
+        let readback = self
+            .read_buffer(buffer, 0..buffer.size(), last)
+            .map_err(DumpError::Upload)?;
+        let data = self.wait_readback(families, &readback)?;
+        std::fs::write(path, data).map_err(DumpError::Io)
+    }
+
+    /// Save first layer of image base level to a PNG file.
+    ///
+    /// `Rgba8` and `Bgra8` formats are saved as is.
+    /// Depth of depth formats is saved as grayscale with `0.0` black and `1.0` white.
+    /// Waits for device idle like [`dump_buffer_to_bin`].
+    ///
+    /// # Safety
+    ///
+    /// Same as for [`read_image`].
+    ///
+    /// [`dump_buffer_to_bin`]: #method.dump_buffer_to_bin
+    /// [`read_image`]: #method.read_image
+    pub unsafe fn dump_image_to_png(
+        &mut self,
+        families: &mut Families<B>,
+        image: &Image<B>,
+        last: ImageState,
+        path: impl AsRef<Path>,
+    ) -> Result<(), DumpError> {
+        let format = image.format();
+        let aspects = if format.is_depth() {
+            Aspects::DEPTH
+        } else {
+            Aspects::COLOR
+        };
+        if !can_convert_to_rgba8(format) {
+            return Err(DumpError::UnsupportedFormat(format));
+        }
+
+        let readback = self
+            .read_image(
+                image,
+                SubresourceLayers {
+                    aspects,
+                    level: 0,
+                    layers: 0..1,
+                },
+                last,
+            )
+            .map_err(DumpError::Upload)?;
+        let data = self.wait_readback(families, &readback)?;
+        write_png(path, format, image.kind().extent(), &data)
+    }
+
+    fn wait_readback(
+        &mut self,
+        families: &mut Families<B>,
+        readback: &BufferReadback<B>,
+    ) -> Result<Vec<u8>, DumpError> {
+        self.flush_uploads(families);
+        self.wait_idle().map_err(DumpError::OutOfMemory)?;
+        self.cleanup(families);
+        Ok(readback
+            .fetch()
+            .map_err(DumpError::Map)?
+            .expect("Copy is complete once device is idle"))
+    }
+}
+
+/// Write image texels read back from the device to a PNG file.
+fn write_png(
+    path: impl AsRef<Path>,
+    format: Format,
+    extent: Extent,
+    data: &[u8],
+) -> Result<(), DumpError> {
+    // Only the first slice of 3D image is saved.
+    let texels = (extent.width * extent.height) as usize;
+    let rgba = convert_to_rgba8(format, data).ok_or(DumpError::UnsupportedFormat(format))?;
+    let png = encode_png(extent.width, extent.height, &rgba[..texels * 4])
+        .map_err(DumpError::Encoding)?;
+    std::fs::write(path, png).map_err(DumpError::Io)
+}
+
+fn can_convert_to_rgba8(format: Format) -> bool {
+    convert_to_rgba8(format, &[]).is_some()
+}
+
+/// Convert tightly packed texels to RGBA8.
+/// Depth formats contain only depth aspect as it is copied to buffers.
+fn convert_to_rgba8(format: Format, data: &[u8]) -> Option<Vec<u8>> {
+    let gray = |value: u8| vec![value, value, value, 255];
+    Some(match format {
+        Format::Rgba8Unorm | Format::Rgba8Srgb => data.to_vec(),
+        Format::Bgra8Unorm | Format::Bgra8Srgb => data
+            .chunks_exact(4)
+            .flat_map(|texel| vec![texel[2], texel[1], texel[0], texel[3]])
+            .collect(),
+        Format::D16Unorm | Format::D16UnormS8Uint => data
+            .chunks_exact(2)
+            .flat_map(|texel| gray(texel[1]))
+            .collect(),
+        // Upper byte of 24-bit depth texels is undefined.
+        Format::X8D24Unorm | Format::D24UnormS8Uint => data
+            .chunks_exact(4)
+            .flat_map(|texel| gray(texel[2]))
+            .collect(),
+        Format::D32Sfloat | Format::D32SfloatS8Uint => data
+            .chunks_exact(4)
+            .flat_map(|texel| {
+                let mut bytes = [0; 4];
+                bytes.copy_from_slice(texel);
+                let depth = f32::from_le_bytes(bytes).max(0.0).min(1.0);
+                gray((depth * 255.0).round() as u8)
+            })
+            .collect(),
+        _ => return None,
+    })
+}
+
+/// Encode RGBA8 image as PNG.
+fn encode_png(width: u32, height: u32, rgba: &[u8]) -> Result<Vec<u8>, png::EncodingError> {
+    let mut data = Vec::new();
+    {
+        let mut encoder = png::Encoder::new(&mut data, width, height);
+        encoder.set_color(png::ColorType::RGBA);
+        encoder.set_depth(png::BitDepth::Eight);
+        encoder.write_header()?.write_image_data(rgba)?;
+    }
+    Ok(data)
+}
+
+#[cfg(test)]
+mod tests {
+    use {super::*, crate::factory::image_readback_size, rendy_core::hal::image::Kind};
+
+    fn decode(bytes: &[u8]) -> (u32, u32, Vec<u8>) {
+        let (info, mut reader) = png::Decoder::new(bytes).read_info().unwrap();
+        let mut data = vec![0; info.buffer_size()];
+        reader.next_frame(&mut data).unwrap();
+        (info.width, info.height, data)
+    }
+
+    #[test]
+    fn cleared_image_is_reopened() {
+        // Texels of image cleared to orange as stored in `Bgra8Unorm`.
+        let texels: Vec<u8> = std::iter::repeat(&[0u8, 128, 255, 255])
+            .take(3 * 2)
+            .flatten()
+            .cloned()
+            .collect();
+        let rgba = convert_to_rgba8(Format::Bgra8Unorm, &texels).unwrap();
+
+        let (width, height, data) = decode(&encode_png(3, 2, &rgba).unwrap());
+        assert_eq!((width, height), (3, 2));
+        for texel in data.chunks(4) {
+            assert_eq!(texel, &[255, 128, 0, 255]);
+        }
+    }
+
+    #[test]
+    fn cleared_image_dump_is_reopened_from_file() {
+        let kind = Kind::D2(4, 3, 1, 1);
+        let layers = SubresourceLayers {
+            aspects: Aspects::COLOR,
+            level: 0,
+            layers: 0..1,
+        };
+        let size = image_readback_size(kind, Format::Rgba8Unorm, &layers) as usize;
+        assert_eq!(size, 4 * 3 * 4);
+        // Content of image cleared to teal as copied to staging buffer.
+        let readback: Vec<u8> = std::iter::repeat(&[0u8, 128, 128, 255])
+            .take(size / 4)
+            .flatten()
+            .cloned()
+            .collect();
+
+        let path = std::env::temp_dir().join(format!("rendy-dump-{}.png", std::process::id()));
+        write_png(&path, Format::Rgba8Unorm, kind.extent(), &readback).unwrap();
+        let file = std::fs::read(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+
+        let (width, height, data) = decode(&file);
+        assert_eq!((width, height), (4, 3));
+        for texel in data.chunks(4) {
+            assert_eq!(texel, &[0, 128, 128, 255]);
+        }
+    }
+
+    #[test]
+    fn depth_readback_size_covers_only_depth_aspect() {
+        let layers = SubresourceLayers {
+            aspects: Aspects::DEPTH,
+            level: 1,
+            layers: 0..2,
+        };
+        let size = image_readback_size(Kind::D2(8, 8, 2, 1), Format::D24UnormS8Uint, &layers);
+        assert_eq!(size, 4 * 4 * 4 * 2);
+    }
+
+    #[test]
+    fn depth_is_grayscale() {
+        let texels: Vec<u8> = [0.0f32, 0.5, 1.0, 2.0]
+            .iter()
+            .flat_map(|depth| depth.to_le_bytes().to_vec())
+            .collect();
+        let rgba = convert_to_rgba8(Format::D32Sfloat, &texels).unwrap();
+        let gray: Vec<u8> = rgba.chunks(4).map(|texel| texel[0]).collect();
+        assert_eq!(gray, vec![0, 128, 255, 255]);
+
+        assert!(!can_convert_to_rgba8(Format::Bc1RgbaUnorm));
+    }
+
+    #[test]
+    fn large_image_roundtrips() {
+        let rgba: Vec<u8> = (0..256 * 128 * 4).map(|byte| byte as u8).collect();
+        let (_, _, data) = decode(&encode_png(256, 128, &rgba).unwrap());
+        assert_eq!(data, rgba);
+    }
+}
+
+#[cfg(all(test, feature = "empty"))]
+mod device_tests {
+    use {
+        crate::{
+            command::QueueId,
+            factory::{init_with_instance, Config},
+            memory::Dynamic,
+            resource::BufferInfo,
+            upload::BufferState,
+        },
+        rendy_core::{
+            empty::Backend,
+            hal::{buffer::Usage, Instance as _},
+            Instance,
+        },
+    };
+
+    #[test]
+    fn buffer_dump_contains_buffer_content() {
+        let instance = rendy_core::empty::Instance::create("rendy", 1).unwrap();
+        let config: Config = Default::default();
+        let (mut factory, mut families) =
+            init_with_instance::<Backend>(Instance::new(instance), &config).unwrap();
+
+        let content: Vec<u8> = (0..64).collect();
+        let mut buffer = factory
+            .create_buffer(
+                BufferInfo {
+                    size: content.len() as u64,
+                    usage: Usage::TRANSFER_SRC,
+                },
+                Dynamic,
+            )
+            .unwrap();
+        let queue = QueueId {
+            family: families.family_by_index(0).id(),
+            index: 0,
+        };
+
+        let path = std::env::temp_dir().join(format!("rendy-dump-{}.bin", std::process::id()));
+        unsafe {
+            factory
+                .upload_visible_buffer(&mut buffer, 0, &content)
+                .unwrap();
+            factory
+                .dump_buffer_to_bin(&mut families, &buffer, BufferState::new(queue), &path)
+                .unwrap();
+        }
+        let file = std::fs::read(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+
+        assert_eq!(file, content);
+    }
+}
@@ -1,20 +1,23 @@
 use {
     crate::{
-        blitter::Blitter,
+        blitter::{validate_copy, Blitter, CopyImageDesc, CopyRegion, ImageCopyError},
         command::{
-            families_from_device, CommandPool, Families, Family, FamilyId, Fence, QueueType, Reset,
+            families_from_device, CommandPool, Families, Family, FamilyId, Fence, FencePool,
+            QueueId, QueueType, Reset,
         },
         config::{Config, DevicesConfigure, HeapsConfigure, QueuesConfigure},
         core::{rendy_with_slow_safety_checks, Device, DeviceId, Instance, InstanceId},
+        debug::DebugName,
         descriptor::DescriptorAllocator,
         memory::{self, Heaps, MemoryUsage, TotalMemoryUtilization, Write},
+        pipeline_cache::{cache_blob, cache_header, PipelineCacheError},
         resource::*,
-        upload::{BufferState, ImageState, ImageStateOrLayout, Uploader},
+        upload::{BufferReadback, BufferState, ImageState, ImageStateOrLayout, Uploader},
         wsi::{Surface, SwapchainError, Target},
     },
     rendy_core::{
         hal::{
-            adapter::{Adapter, Gpu, PhysicalDevice},
+            adapter::{Adapter, AdapterInfo, Gpu, PhysicalDevice},
             buffer,
             device::{
                 AllocationError, CreationError, Device as _, MapError, OomOrDeviceLost,
@@ -22,6 +25,7 @@ use {
             },
             format, image,
             pso::DescriptorSetLayoutBinding,
+            query,
             window::{Extent2D, InitError, Surface as GfxSurface},
             Backend, Features, Instance as _, Limits,
         },
@@ -40,7 +44,13 @@ struct ResourceHub<B: Backend> {
     layouts: ResourceTracker<DescriptorSetLayout<B>>,
     sets: ResourceTracker<DescriptorSet<B>>,
     samplers: ResourceTracker<Sampler<B>>,
+    render_passes: ResourceTracker<RenderPass<B>>,
+    pipeline_layouts: ResourceTracker<PipelineLayout<B>>,
     samplers_cache: parking_lot::RwLock<SamplerCache<B>>,
+    layouts_cache: parking_lot::RwLock<ResourceCache<DescriptorSetInfo, DescriptorSetLayout<B>>>,
+    render_passes_cache: parking_lot::RwLock<ResourceCache<RenderPassInfo, RenderPass<B>>>,
+    pipeline_layouts_cache:
+        parking_lot::RwLock<ResourceCache<PipelineLayoutInfo, PipelineLayout<B>>>,
 }
 
 impl<B> Default for ResourceHub<B>
@@ -55,7 +65,12 @@ where
             layouts: ResourceTracker::default(),
             sets: ResourceTracker::default(),
             samplers: ResourceTracker::default(),
+            render_passes: ResourceTracker::default(),
+            pipeline_layouts: ResourceTracker::default(),
             samplers_cache: parking_lot::RwLock::new(SamplerCache::default()),
+            layouts_cache: parking_lot::RwLock::new(ResourceCache::default()),
+            render_passes_cache: parking_lot::RwLock::new(ResourceCache::default()),
+            pipeline_layouts_cache: parking_lot::RwLock::new(ResourceCache::default()),
         }
     }
 }
@@ -74,6 +89,10 @@ where
     ) {
         self.sets
             .cleanup(|s| s.dispose(allocator), &next, &complete);
+        self.pipeline_layouts
+            .cleanup(|l| l.dispose(device), &next, &complete);
+        self.render_passes
+            .cleanup(|r| r.dispose(device), &next, &complete);
         self.views.cleanup(|v| v.dispose(device), &next, &complete);
         self.layouts
             .cleanup(|l| l.dispose(device), &next, &complete);
@@ -91,8 +110,13 @@ where
         heaps: &mut Heaps<B>,
         allocator: &mut DescriptorAllocator<B>,
     ) {
+        drop(self.pipeline_layouts_cache);
+        drop(self.render_passes_cache);
+        drop(self.layouts_cache);
         drop(self.samplers_cache);
         self.sets.dispose(|s| s.dispose(allocator));
+        self.pipeline_layouts.dispose(|l| l.dispose(device));
+        self.render_passes.dispose(|r| r.dispose(device));
         self.views.dispose(|v| v.dispose(device));
         self.layouts.dispose(|l| l.dispose(device));
         self.buffers.dispose(|b| b.dispose(device, heaps));
@@ -110,6 +134,10 @@ pub enum UploadError {
     Map(MapError),
     /// Failed to upload the data.
     Upload(OutOfMemory),
+    /// Resource doesn't have usage required for the transfer.
+    Usage,
+    /// Range is empty or out of resource bounds.
+    Range(std::ops::Range<u64>),
 }
 
 impl std::fmt::Display for UploadError {
@@ -118,6 +146,71 @@ impl std::fmt::Display for UploadError {
             UploadError::Create(err) => write!(fmt, "Upload failed: {:?}", err),
             UploadError::Map(err) => write!(fmt, "Upload failed: {:?}", err),
             UploadError::Upload(err) => write!(fmt, "Upload failed: {:?}", err),
+            UploadError::Usage => write!(fmt, "Resource doesn't support transfer"),
+            UploadError::Range(range) => write!(fmt, "Invalid transfer range {:?}", range),
+        }
+    }
+}
+
+/// Physical device `Factory` initialization tried to create device on.
+#[derive(Clone, Debug, PartialEq)]
+pub struct PhysicalDeviceInfo {
+    /// Adapter name, vendor and type.
+    pub info: AdapterInfo,
+    /// Features supported by physical device.
+    pub features: Features,
+    /// Limits of physical device.
+    pub limits: Limits,
+}
+
+/// Failure initializing `Factory`.
+#[derive(Clone, Debug, PartialEq)]
+pub enum FactoryInitError {
+    /// Instance has no physical devices.
+    NoAdapters,
+    /// Devices config picked none of physical devices.
+    NoSuitableAdapters,
+    /// Failed to create device on each of physical devices picked by devices config.
+    /// Contains every device tried in order with the reason it was rejected.
+    Rejected(Vec<(PhysicalDeviceInfo, CreationError)>),
+    /// Device was created but ran out of memory creating factory resources.
+    OutOfMemory(OutOfMemory),
+}
+
+impl std::fmt::Display for FactoryInitError {
+    fn fmt(&self, fmt: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            FactoryInitError::NoAdapters => write!(fmt, "No physical devices found"),
+            FactoryInitError::NoSuitableAdapters => {
+                write!(fmt, "No suitable physical devices found")
+            }
+            FactoryInitError::Rejected(rejected) => {
+                write!(
+                    fmt,
+                    "Failed to create device on all picked physical devices:"
+                )?;
+                for (device, err) in rejected {
+                    write!(
+                        fmt,
+                        "\n{} ({:?}): {:?}",
+                        device.info.name, device.info.device_type, err
+                    )?;
+                }
+                Ok(())
+            }
+            FactoryInitError::OutOfMemory(err) => write!(fmt, "Factory init failed: {:?}", err),
+        }
+    }
+}
+
+impl std::error::Error for FactoryInitError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            FactoryInitError::Rejected(rejected) => rejected
+                .last()
+                .map(|(_, err)| err as &(dyn std::error::Error + 'static)),
+            FactoryInitError::OutOfMemory(err) => Some(err),
+            FactoryInitError::NoAdapters | FactoryInitError::NoSuitableAdapters => None,
         }
     }
 }
@@ -128,10 +221,45 @@ impl std::error::Error for UploadError {
             UploadError::Create(err) => Some(err),
             UploadError::Map(err) => Some(err),
             UploadError::Upload(err) => Some(err),
+            UploadError::Usage | UploadError::Range(_) => None,
         }
     }
 }
 
+/// Size of tightly packed copy of image layers.
+pub(crate) fn image_readback_size(
+    kind: image::Kind,
+    format: format::Format,
+    image_layers: &image::SubresourceLayers,
+) -> u64 {
+    let extent = kind.level_extent(image_layers.level);
+    let texels = extent.width as u64
+        * extent.height as u64
+        * extent.depth as u64
+        * (image_layers.layers.end - image_layers.layers.start) as u64;
+    texels * aspect_texel_size(format, image_layers.aspects) as u64
+}
+
+/// Round `size` up to multiple of `align`.
+fn align_size(size: u64, align: u64) -> u64 {
+    match align {
+        0 | 1 => size,
+        _ => ((size - 1) / align + 1) * align,
+    }
+}
+
+/// Size of texel of the aspect when it is copied to a buffer.
+/// Depth of packed depth-stencil formats is copied separately from stencil.
+pub(crate) fn aspect_texel_size(format: format::Format, aspects: format::Aspects) -> u32 {
+    use format::{Aspects, Format};
+    match (format, aspects) {
+        (_, Aspects::STENCIL) => 1,
+        (Format::D16Unorm, _) | (Format::D16UnormS8Uint, Aspects::DEPTH) => 2,
+        (Format::D24UnormS8Uint, Aspects::DEPTH) | (Format::D32SfloatS8Uint, Aspects::DEPTH) => 4,
+        _ => format.surface_desc().bits as u32 / 8,
+    }
+}
+
 #[derive(Debug)]
 enum InstanceOrId<B: Backend> {
     Instance(Instance<B>),
@@ -165,9 +293,14 @@ pub struct Factory<B: Backend> {
     heaps: ManuallyDrop<parking_lot::Mutex<Heaps<B>>>,
     resources: ManuallyDrop<ResourceHub<B>>,
     epochs: Vec<parking_lot::RwLock<Vec<u64>>>,
+    fence_pool: parking_lot::Mutex<FencePool<B>>,
     uploader: Uploader<B>,
     blitter: Blitter<B>,
+    pipeline_cache: ManuallyDrop<B::PipelineCache>,
     families_indices: Vec<usize>,
+    features: Features,
+    limits: Limits,
+    device_lost: std::sync::atomic::AtomicBool,
     device: Device<B>,
     adapter: Adapter<B>,
     instance: InstanceOrId<B>,
@@ -193,6 +326,12 @@ where
             log::trace!("Uploader disposed");
             self.blitter.dispose(&self.device);
             log::trace!("Blitter disposed");
+            let fence_pool = FencePool::new(&self.device);
+            std::mem::replace(self.fence_pool.get_mut(), fence_pool).dispose(&self.device);
+            log::trace!("Fence pool disposed");
+            self.device
+                .destroy_pipeline_cache(std::ptr::read(&*self.pipeline_cache));
+            log::trace!("Pipeline cache disposed");
             std::ptr::read(&mut *self.resources).dispose(
                 &self.device,
                 self.heaps.get_mut(),
@@ -220,6 +359,23 @@ where
     }
 }
 
+/// Proof that device is idle.
+/// Exists only inside `Factory::idle_scope` where no work can be submitted to the device.
+/// Functions that require idle device take it instead of being `unsafe`.
+// Copy would let the proof outlive the scope.
+#[allow(missing_copy_implementations)]
+#[derive(Debug)]
+pub struct DeviceIdle {
+    device: DeviceId,
+}
+
+impl DeviceIdle {
+    /// Get id of the idle device.
+    pub fn device(&self) -> DeviceId {
+        self.device
+    }
+}
+
 impl<B> Factory<B>
 where
     B: Backend,
@@ -236,6 +392,29 @@ where
         Ok(())
     }
 
+    /// Wait for device idle and call `f` with proof of it.
+    ///
+    /// `families` of the device stay borrowed until `f` returns,
+    /// so nothing can be submitted and device remains idle.
+    /// Teardown functions like `Graph::dispose_idle` take the proof
+    /// to be safe to call.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `families` belong to another device.
+    pub fn idle_scope<R>(
+        &mut self,
+        families: &mut Families<B>,
+        f: impl FnOnce(&mut Self, &DeviceIdle) -> R,
+    ) -> Result<R, OutOfMemory> {
+        families.assert_device_owner(&self.device);
+        self.wait_idle()?;
+        let idle = DeviceIdle {
+            device: self.device.id(),
+        };
+        Ok(f(self, &idle))
+    }
+
     /// Creates a buffer with the specified properties.
     ///
     /// This function returns relevant value, that is, the value cannot be dropped.
@@ -280,12 +459,37 @@ where
         Ok(self.resources.buffers.escape(buffer))
     }
 
+    /// Creates a host-visible uniform buffer with slot for `T` for each of `frames`.
+    /// Slots are aligned to `min_uniform_buffer_offset_alignment` of the device.
+    pub fn create_uniform_ring<T>(
+        &self,
+        frames: usize,
+    ) -> Result<UniformRing<B, T>, BufferCreationError>
+    where
+        T: Copy,
+    {
+        let align = self.limits.min_uniform_buffer_offset_alignment;
+        let buffer = self.create_buffer(
+            BufferInfo {
+                size: aligned_size_of::<T>(align) * frames as u64,
+                usage: buffer::Usage::UNIFORM,
+            },
+            memory::Dynamic,
+        )?;
+        Ok(UniformRing::new(buffer, frames, align))
+    }
+
     /// Creates an image with the specified properties.
     ///
     /// This function returns relevant value, that is, the value cannot be dropped.
     /// However image can be destroyed using [`destroy_relevant_image`] function.
     ///
+    /// Linearly tiled images are supported for a narrow set of formats and usages.
+    /// Fails with `image::CreationError::Format` if `info` with `Tiling::Linear`
+    /// is not supported according to [`image_format_properties`].
+    ///
     /// [`destroy_relevant_image`]: #method.destroy_relevant_image
+    /// [`image_format_properties`]: #method.image_format_properties
     pub fn create_relevant_image(
         &self,
         info: ImageInfo,
@@ -293,6 +497,15 @@ where
     ) -> Result<Image<B>, ImageCreationError> {
         profile_scope!("create_relevant_image");
 
+        if info.tiling == image::Tiling::Linear
+            && self.image_format_properties(info.clone()).is_none()
+        {
+            log::warn!("Linear tiling is not supported for {:#?}", info);
+            return Err(ImageCreationError::Create(image::CreationError::Format(
+                info.format,
+            )));
+        }
+
         unsafe { Image::create(&self.device, &mut self.heaps.lock(), info, memory_usage) }
     }
 
@@ -324,6 +537,29 @@ where
         Ok(self.resources.images.escape(image))
     }
 
+    /// Creates images that share one memory block.
+    /// Memory is owned by the first image and freed when it is destroyed.
+    ///
+    /// # Safety
+    ///
+    /// Images must not be used simultaneously,
+    /// content of the memory is undefined after it was written through another image,
+    /// and no image can be used after the first one is destroyed.
+    pub unsafe fn create_aliased_images(
+        &self,
+        infos: impl IntoIterator<Item = ImageInfo>,
+        memory_usage: impl MemoryUsage,
+    ) -> Result<Vec<Escape<Image<B>>>, ImageCreationError> {
+        profile_scope!("create_aliased_images");
+
+        let images =
+            Image::create_aliased(&self.device, &mut self.heaps.lock(), infos, memory_usage)?;
+        Ok(images
+            .into_iter()
+            .map(|image| self.resources.images.escape(image))
+            .collect())
+    }
+
     /// Fetch image format details for a particular `ImageInfo`.
     pub fn image_format_properties(&self, info: ImageInfo) -> Option<FormatProperties> {
         self.physical().image_format_properties(
@@ -559,6 +795,115 @@ where
             .upload_buffer(&self.device, buffer, offset, staging, last, next)
     }
 
+    /// Read buffer range content back to the host.
+    ///
+    /// Copy operation will actually be submitted to the graphics device queue
+    /// upon next [`flush_uploads`] or [`maintain`] call to this `Factory`, and
+    /// is guaranteed to take place after all previous operations that have been
+    /// submitted to the same graphics queue.
+    /// Returned handle is completed by one of following [`maintain`] calls
+    /// once device finishes the copy. Calling thread is never blocked.
+    /// The handle can be awaited as a `Future`.
+    ///
+    /// Fails with `UploadError::Usage` if buffer doesn't support transfer reads
+    /// and with `UploadError::Range` if range is empty or out of buffer bounds.
+    ///
+    /// Commands recorded by the graph for a frame are submitted when the frame is run.
+    /// To read data written in frame `N` call this function after `N` was run,
+    /// the copy is then submitted on the next `maintain` call and can't observe stale memory.
+    ///
+    /// # Safety
+    ///
+    /// If buffer is used by device then `last` state must match the last usage state of the buffer
+    /// before reading happen.
+    /// Device must not write to the buffer range until copy operation completes.
+    ///
+    /// [`flush_uploads`]: #method.flush_uploads
+    /// [`maintain`]: #method.maintain
+    pub unsafe fn read_buffer(
+        &self,
+        buffer: &Buffer<B>,
+        range: std::ops::Range<u64>,
+        last: BufferState,
+    ) -> Result<BufferReadback<B>, UploadError> {
+        if !buffer.info().usage.contains(buffer::Usage::TRANSFER_SRC) {
+            return Err(UploadError::Usage);
+        }
+        if range.start >= range.end || range.end > buffer.size() {
+            return Err(UploadError::Range(range));
+        }
+
+        // Non-coherent staging memory is invalidated in multiples of atom size.
+        let align = self.physical().limits().non_coherent_atom_size as u64;
+        let staging = self
+            .create_buffer(
+                BufferInfo {
+                    size: align_size(range.end - range.start, align),
+                    usage: buffer::Usage::TRANSFER_DST,
+                },
+                memory::Download,
+            )
+            .map_err(UploadError::Create)?;
+
+        self.uploader
+            .read_buffer(&self.device, buffer, range, staging, last)
+            .map_err(UploadError::Upload)
+    }
+
+    /// Read content of image layers back to the host.
+    ///
+    /// Whole extent of the mip level is copied with tightly packed texels
+    /// of the aspect in `image_layers`, layers one after another.
+    /// Copy is submitted and completed the same way as with [`read_buffer`].
+    /// Image is returned to the `last` state after the copy,
+    /// unless `last` layout is `Undefined`. Such image is left in `TransferSrcOptimal` layout
+    /// and `TRANSFER_READ` access.
+    /// Fails with `UploadError::Usage` if image doesn't support transfer reads.
+    ///
+    /// # Safety
+    ///
+    /// If image is used by device then `last` state must match the last usage state of the image
+    /// before reading happen.
+    /// Device must not write to the image layers until copy operation completes.
+    ///
+    /// # Panics
+    ///
+    /// Panics if image has compressed format.
+    ///
+    /// [`read_buffer`]: #method.read_buffer
+    pub unsafe fn read_image(
+        &self,
+        image: &Image<B>,
+        image_layers: image::SubresourceLayers,
+        last: ImageState,
+    ) -> Result<BufferReadback<B>, UploadError> {
+        if !image.info().usage.contains(image::Usage::TRANSFER_SRC) {
+            return Err(UploadError::Usage);
+        }
+        let format_desc = image.format().surface_desc();
+        assert_eq!(
+            format_desc.dim,
+            (1, 1),
+            "Compressed images can't be read back"
+        );
+
+        let size = image_readback_size(image.kind(), image.format(), &image_layers);
+        let align = self.physical().limits().non_coherent_atom_size as u64;
+        let staging = self
+            .create_buffer(
+                BufferInfo {
+                    size: align_size(size, align),
+                    usage: buffer::Usage::TRANSFER_DST,
+                },
+                memory::Download,
+            )
+            .map_err(UploadError::Create)?;
+
+        self.uploader
+            .read_image(&self.device, image, image_layers, staging, size, last)
+            .map_err(UploadError::Upload)
+    }
+
     /// Update image layers content with provided data.
     /// Transition part of image from one state to another.
     ///
@@ -628,8 +973,10 @@ where
 
         let content_size = content.len() as u64 * std::mem::size_of::<T>() as u64;
         let format_desc = image.format().surface_desc();
-        let texels_count = (image_extent.width / format_desc.dim.0 as u32) as u64
-            * (image_extent.height / format_desc.dim.1 as u32) as u64
+        // Compressed mips smaller than a block still take a whole block.
+        let blocks = |size: u32, dim: u8| ((size + dim as u32 - 1) / dim as u32) as u64;
+        let texels_count = blocks(image_extent.width, format_desc.dim.0)
+            * blocks(image_extent.height, format_desc.dim.1)
             * image_extent.depth as u64
             * (image_layers.layers.end - image_layers.layers.start) as u64;
         let total_bytes = (format_desc.bits as u64 / 8) * texels_count;
@@ -667,6 +1014,40 @@ where
             .map_err(UploadError::Upload)
     }
 
+    /// Copy regions of `src` image into `dst` image.
+    ///
+    /// Unlike `Blitter::copy_image` every region is checked to fit mip levels,
+    /// array layers and extents of both images,
+    /// block-compressed regions are checked to be aligned to blocks
+    /// and formats are checked to have texel blocks of the same size.
+    /// Nothing is recorded if any check fails.
+    ///
+    /// Copy operation will actually be submitted to the queue
+    /// upon next [`flush_blits`] or [`maintain`] call to this `Factory`.
+    ///
+    /// # Safety
+    ///
+    /// Images must be created by this `Factory`.
+    /// regions' `last_*` states must be valid at the time of command execution.
+    /// All regions must have distinct subresource layer and level combination.
+    ///
+    /// [`flush_blits`]: #method.flush_blits
+    /// [`maintain`]: #method.maintain
+    pub unsafe fn copy_image(
+        &self,
+        queue: QueueId,
+        src: &Handle<Image<B>>,
+        dst: &Handle<Image<B>>,
+        regions: Vec<CopyRegion>,
+    ) -> Result<(), ImageCopyError> {
+        let copies: Vec<rendy_core::hal::command::ImageCopy> =
+            regions.iter().cloned().map(Into::into).collect();
+        validate_copy(CopyImageDesc::of(src), CopyImageDesc::of(dst), &copies)?;
+
+        self.blitter
+            .copy_image(&self.device, queue, src, dst, regions)
+    }
+
     /// Get blitter instance
     pub fn blitter(&self) -> &Blitter<B> {
         &self.blitter
@@ -833,6 +1214,99 @@ where
         &self.adapter.physical_device
     }
 
+    /// Get features enabled for the device.
+    /// Device is created with all features supported by physical device.
+    pub fn features(&self) -> Features {
+        self.features
+    }
+
+    /// Get limits of the physical device.
+    /// Queried once when factory is created.
+    pub fn limits(&self) -> &Limits {
+        &self.limits
+    }
+
+    /// Check if device was lost.
+    /// Set when waiting for fences or presentation reports device loss.
+    /// Lost device can't be recovered, it should be disposed with all resources
+    /// and created again.
+    pub fn is_device_lost(&self) -> bool {
+        self.device_lost.load(std::sync::atomic::Ordering::Acquire)
+    }
+
+    /// Mark device as lost after an operation reported device loss.
+    pub fn mark_device_lost(&self) {
+        if !self
+            .device_lost
+            .swap(true, std::sync::atomic::Ordering::AcqRel)
+        {
+            log::error!("Device lost");
+        }
+    }
+
+    /// Check if device supports geometry shaders.
+    pub fn supports_geometry_shader(&self) -> bool {
+        self.features.contains(Features::GEOMETRY_SHADER)
+    }
+
+    /// Check if device supports tessellation shaders.
+    pub fn supports_tessellation_shader(&self) -> bool {
+        self.features.contains(Features::TESSELLATION_SHADER)
+    }
+
+    /// Check if device supports anisotropic filtering.
+    pub fn supports_sampler_anisotropy(&self) -> bool {
+        self.features.contains(Features::SAMPLER_ANISOTROPY)
+    }
+
+    /// Get pipeline cache of the factory.
+    /// Pipelines created with it are built faster
+    /// if the same pipelines were created before, possibly in previous run.
+    pub fn pipeline_cache(&self) -> &B::PipelineCache {
+        &self.pipeline_cache
+    }
+
+    /// Save pipeline cache to the file at `path`.
+    /// Data is prefixed with header that identifies the device.
+    pub fn save_pipeline_cache(
+        &self,
+        path: impl AsRef<std::path::Path>,
+    ) -> Result<(), PipelineCacheError> {
+        let blob = unsafe { self.device.get_pipeline_cache_data(&self.pipeline_cache) }
+            .map_err(PipelineCacheError::OutOfMemory)?;
+        let mut data = cache_header(std::any::type_name::<B>(), &self.adapter.info);
+        data.extend_from_slice(&blob);
+        std::fs::write(path, data).map_err(PipelineCacheError::Io)
+    }
+
+    /// Replace pipeline cache with one saved to the file at `path`.
+    /// Returns `false` and keeps current cache if there is no such file
+    /// or it was saved on another device or driver.
+    pub fn load_pipeline_cache(
+        &mut self,
+        path: impl AsRef<std::path::Path>,
+    ) -> Result<bool, PipelineCacheError> {
+        let data = match std::fs::read(path) {
+            Ok(data) => data,
+            Err(err) if err.kind() == std::io::ErrorKind::NotFound => return Ok(false),
+            Err(err) => return Err(PipelineCacheError::Io(err)),
+        };
+
+        let blob = match cache_blob(&data, std::any::type_name::<B>(), &self.adapter.info) {
+            Some(blob) => blob,
+            None => {
+                log::debug!("Pipeline cache was saved on another device. Ignored");
+                return Ok(false);
+            }
+        };
+
+        let cache = unsafe { self.device.create_pipeline_cache(Some(blob)) }
+            .map_err(PipelineCacheError::OutOfMemory)?;
+        let old = std::mem::replace(&mut *self.pipeline_cache, cache);
+        unsafe { self.device.destroy_pipeline_cache(old) };
+        Ok(true)
+    }
+
     /// Create new semaphore.
     pub fn create_semaphore(&self) -> Result<B::Semaphore, OutOfMemory> {
         profile_scope!("create_semaphore");
@@ -854,6 +1328,37 @@ where
         Fence::new(&self.device, signaled)
     }
 
+    /// Get unsignaled fence from the pool of reusable fences.
+    /// Fence must be returned with `release_fence` when it is not needed anymore.
+    ///
+    /// Released fences are checked for being signaled only when pool has no free fences,
+    /// this never blocks.
+    pub fn acquire_fence(&self) -> Result<Fence<B>, OomOrDeviceLost> {
+        let mut pool = self.fence_pool.lock();
+        if pool.free() == 0 {
+            pool.collect(&self.device, |fence_epoch| {
+                let family_index = self.families_indices[fence_epoch.queue.family.index];
+                let mut lock = self.epochs[family_index].write();
+                let epoch = &mut lock[fence_epoch.queue.index];
+                *epoch = max(*epoch, fence_epoch.epoch);
+            })?;
+        }
+        Ok(pool.acquire(&self.device)?)
+    }
+
+    /// Return fence acquired with `acquire_fence` to the pool.
+    /// Fence can be still submitted,
+    /// it will be reused after it becomes signaled.
+    pub fn release_fence(&self, fence: Fence<B>) {
+        fence.assert_device_owner(&self.device);
+        self.fence_pool.lock().release(fence);
+    }
+
+    /// Number of fences created by the pool of reusable fences.
+    pub fn pooled_fences_created(&self) -> usize {
+        self.fence_pool.lock().created()
+    }
+
     /// Wait for the fence become signeled.
     pub fn reset_fence(&self, fence: &mut Fence<B>) -> Result<(), OutOfMemory> {
         fence.reset(&self.device)
@@ -894,7 +1399,10 @@ where
 
         fence.assert_device_owner(&self.device);
 
-        if let Some(fence_epoch) = fence.wait_signaled(&self.device, timeout_ns)? {
+        let signaled = fence
+            .wait_signaled(&self.device, timeout_ns)
+            .map_err(|err| self.check_device_lost(err))?;
+        if let Some(fence_epoch) = signaled {
             // Now we can update epochs counter.
             let family_index = self.families_indices[fence_epoch.queue.family.index];
             let mut lock = self.epochs[family_index].write();
@@ -907,6 +1415,13 @@ where
         }
     }
 
+    fn check_device_lost(&self, err: OomOrDeviceLost) -> OomOrDeviceLost {
+        if let OomOrDeviceLost::DeviceLost(_) = err {
+            self.mark_device_lost();
+        }
+        err
+    }
+
     /// Wait for the fences become signeled.
     pub fn wait_for_fences<'a>(
         &self,
@@ -932,7 +1447,8 @@ where
                 wait_for.clone(),
                 timeout_ns,
             )
-        }?;
+        }
+        .map_err(|err| self.check_device_lost(err))?;
 
         if timeout {
             return Ok(false);
@@ -1016,6 +1532,58 @@ where
         pool.dispose(&self.device);
     }
 
+    /// Get number of nanoseconds required for a timestamp query to be incremented by 1.
+    /// Returns `None` if device doesn't support timestamps on graphics and compute queues.
+    pub fn timestamp_period(&self) -> Option<f32> {
+        let limits = &self.limits;
+        if limits.timestamp_compute_and_graphics {
+            Some(limits.timestamp_period)
+        } else {
+            None
+        }
+    }
+
+    /// Create new query pool.
+    pub fn create_query_pool(
+        &self,
+        ty: query::Type,
+        count: query::Id,
+    ) -> Result<B::QueryPool, query::CreationError> {
+        profile_scope!("create_query_pool");
+
+        unsafe { self.device.create_query_pool(ty, count) }
+    }
+
+    /// Destroy query pool.
+    ///
+    /// # Safety
+    ///
+    /// Query pool must be created by this `Factory`.
+    /// Commands that use queries from the pool must be complete.
+    pub unsafe fn destroy_query_pool(&self, pool: B::QueryPool) {
+        self.device.destroy_query_pool(pool);
+    }
+
+    /// Read results of the queries.
+    /// Returns `false` if some results are not available yet
+    /// and `query::ResultFlags::WAIT` is not specified.
+    ///
+    /// # Safety
+    ///
+    /// Query pool must be created by this `Factory`.
+    /// `data` must be large enough to hold all requested results with specified `stride`.
+    pub unsafe fn get_query_pool_results(
+        &self,
+        pool: &B::QueryPool,
+        queries: std::ops::Range<query::Id>,
+        data: &mut [u8],
+        stride: buffer::Offset,
+        flags: query::ResultFlags,
+    ) -> Result<bool, OomOrDeviceLost> {
+        self.device
+            .get_query_pool_results(pool, queries, data, stride, flags)
+    }
+
     fn next_epochs(&mut self, families: &Families<B>) -> Epochs {
         Epochs {
             values: families
@@ -1091,6 +1659,205 @@ where
         Ok(self.resources.layouts.escape(layout))
     }
 
+    /// Create descriptor set layout with specified bindings and immutable samplers.
+    /// Samplers are used by bindings with `immutable_samplers` flag in order of bindings,
+    /// sets of the layout don't need sampler writes for those bindings.
+    /// Layout keeps samplers alive.
+    ///
+    /// See `DescriptorSetLayout::create_with_immutable_samplers` for panics.
+    pub fn create_relevant_descriptor_set_layout_with_samplers(
+        &self,
+        bindings: Vec<DescriptorSetLayoutBinding>,
+        immutable_samplers: Vec<Handle<Sampler<B>>>,
+    ) -> Result<DescriptorSetLayout<B>, OutOfMemory> {
+        unsafe {
+            DescriptorSetLayout::create_with_immutable_samplers(
+                &self.device,
+                DescriptorSetInfo { bindings },
+                immutable_samplers,
+            )
+        }
+    }
+
+    /// Create descriptor set layout with specified bindings and immutable samplers.
+    ///
+    /// This function (unlike [`create_relevant_descriptor_set_layout_with_samplers`])
+    /// returns value that can be dropped.
+    ///
+    /// [`create_relevant_descriptor_set_layout_with_samplers`]: #method.create_relevant_descriptor_set_layout_with_samplers
+    pub fn create_descriptor_set_layout_with_samplers(
+        &self,
+        bindings: Vec<DescriptorSetLayoutBinding>,
+        immutable_samplers: Vec<Handle<Sampler<B>>>,
+    ) -> Result<Escape<DescriptorSetLayout<B>>, OutOfMemory> {
+        let layout =
+            self.create_relevant_descriptor_set_layout_with_samplers(bindings, immutable_samplers)?;
+        Ok(self.resources.layouts.escape(layout))
+    }
+
+    /// Get cached descriptor set layout or create new one.
+    /// Layouts with immutable samplers are not cached,
+    /// use [`create_descriptor_set_layout_with_samplers`] for them.
+    ///
+    /// # Panics
+    ///
+    /// Panics if any binding has `immutable_samplers` flag.
+    ///
+    /// [`create_descriptor_set_layout_with_samplers`]: #method.create_descriptor_set_layout_with_samplers
+    pub fn get_descriptor_set_layout(
+        &self,
+        info: DescriptorSetInfo,
+    ) -> Result<Handle<DescriptorSetLayout<B>>, OutOfMemory> {
+        assert_eq!(
+            info.immutable_sampler_count(),
+            0,
+            "Layouts with immutable samplers can't be cached"
+        );
+        let layouts = &self.resources.layouts;
+        let device = &self.device;
+
+        ResourceCache::get_with_upgradable_lock(
+            self.resources.layouts_cache.upgradable_read(),
+            parking_lot::RwLockUpgradableReadGuard::upgrade,
+            info.clone(),
+            || Ok(layouts.handle(unsafe { DescriptorSetLayout::create(device, info)? })),
+        )
+    }
+
+    /// Create render pass.
+    ///
+    /// This function returns relevant value, that is, the value cannot be dropped.
+    /// However render pass can be destroyed using [`destroy_relevant_render_pass`] function.
+    ///
+    /// [`destroy_relevant_render_pass`]: #method.destroy_relevant_render_pass
+    pub fn create_relevant_render_pass(
+        &self,
+        info: RenderPassInfo,
+    ) -> Result<RenderPass<B>, OutOfMemory> {
+        unsafe { RenderPass::create(&self.device, info) }
+    }
+
+    /// Destroy render pass.
+    ///
+    /// # Safety
+    ///
+    /// Render pass must not be used by any pending commands or referenced anywhere.
+    pub unsafe fn destroy_relevant_render_pass(&self, render_pass: RenderPass<B>) {
+        render_pass.dispose(&self.device);
+    }
+
+    /// Create render pass.
+    ///
+    /// This function (unlike [`create_relevant_render_pass`]) returns value that can be dropped.
+    ///
+    /// [`create_relevant_render_pass`]: #method.create_relevant_render_pass
+    pub fn create_render_pass(
+        &self,
+        info: RenderPassInfo,
+    ) -> Result<Escape<RenderPass<B>>, OutOfMemory> {
+        let render_pass = self.create_relevant_render_pass(info)?;
+        Ok(self.resources.render_passes.escape(render_pass))
+    }
+
+    /// Get cached render pass or create new one.
+    pub fn get_render_pass(
+        &self,
+        info: RenderPassInfo,
+    ) -> Result<Handle<RenderPass<B>>, OutOfMemory> {
+        let render_passes = &self.resources.render_passes;
+        let device = &self.device;
+
+        ResourceCache::get_with_upgradable_lock(
+            self.resources.render_passes_cache.upgradable_read(),
+            parking_lot::RwLockUpgradableReadGuard::upgrade,
+            info.clone(),
+            || Ok(render_passes.handle(unsafe { RenderPass::create(device, info)? })),
+        )
+    }
+
+    /// Create pipeline layout with specified descriptor set layouts and push constants.
+    ///
+    /// This function returns relevant value, that is, the value cannot be dropped.
+    /// However pipeline layout can be destroyed using [`destroy_relevant_pipeline_layout`] function.
+    ///
+    /// [`destroy_relevant_pipeline_layout`]: #method.destroy_relevant_pipeline_layout
+    pub fn create_relevant_pipeline_layout(
+        &self,
+        set_layouts: Vec<Handle<DescriptorSetLayout<B>>>,
+        push_constants: Vec<(rendy_core::hal::pso::ShaderStageFlags, std::ops::Range<u32>)>,
+    ) -> Result<PipelineLayout<B>, OutOfMemory> {
+        unsafe { PipelineLayout::create(&self.device, set_layouts, push_constants) }
+    }
+
+    /// Destroy pipeline layout.
+    ///
+    /// # Safety
+    ///
+    /// Pipeline layout must not be used by any pending commands or referenced anywhere.
+    pub unsafe fn destroy_relevant_pipeline_layout(&self, layout: PipelineLayout<B>) {
+        layout.dispose(&self.device);
+    }
+
+    /// Create pipeline layout with specified descriptor set layouts and push constants.
+    ///
+    /// This function (unlike [`create_relevant_pipeline_layout`]) returns value that can be dropped.
+    ///
+    /// [`create_relevant_pipeline_layout`]: #method.create_relevant_pipeline_layout
+    pub fn create_pipeline_layout(
+        &self,
+        set_layouts: Vec<Handle<DescriptorSetLayout<B>>>,
+        push_constants: Vec<(rendy_core::hal::pso::ShaderStageFlags, std::ops::Range<u32>)>,
+    ) -> Result<Escape<PipelineLayout<B>>, OutOfMemory> {
+        let layout = self.create_relevant_pipeline_layout(set_layouts, push_constants)?;
+        Ok(self.resources.pipeline_layouts.escape(layout))
+    }
+
+    /// Get cached pipeline layout or create new one.
+    /// Descriptor set layouts are taken from cache with [`get_descriptor_set_layout`],
+    /// so sets allocated with layouts from [`PipelineLayout::set_layouts`] are compatible
+    /// with every pipeline created with equal info.
+    ///
+    /// See [`get_descriptor_set_layout`] for panics.
+    ///
+    /// [`get_descriptor_set_layout`]: #method.get_descriptor_set_layout
+    /// [`PipelineLayout::set_layouts`]: ../rendy_resource/struct.PipelineLayout.html#method.set_layouts
+    pub fn get_pipeline_layout(
+        &self,
+        info: PipelineLayoutInfo,
+    ) -> Result<Handle<PipelineLayout<B>>, OutOfMemory> {
+        ResourceCache::get_with_upgradable_lock(
+            self.resources.pipeline_layouts_cache.upgradable_read(),
+            parking_lot::RwLockUpgradableReadGuard::upgrade,
+            info.clone(),
+            || {
+                let set_layouts = info
+                    .sets
+                    .into_iter()
+                    .map(|set| self.get_descriptor_set_layout(set))
+                    .collect::<Result<Vec<_>, _>>()?;
+                let layout =
+                    self.create_relevant_pipeline_layout(set_layouts, info.push_constants)?;
+                Ok(self.resources.pipeline_layouts.handle(layout))
+            },
+        )
+    }
+
+    /// Release cached samplers, descriptor set layouts, render passes and pipeline layouts
+    /// that are not used outside of the factory.
+    /// Returns number of released resources.
+    ///
+    /// Released resources are destroyed by [`cleanup`] once frames that could use them are complete.
+    /// Resources they keep alive, like descriptor set layouts of pipeline layouts,
+    /// can be released by following calls.
+    ///
+    /// [`cleanup`]: #method.cleanup
+    pub fn trim_caches(&self) -> usize {
+        self.resources.pipeline_layouts_cache.write().trim()
+            + self.resources.render_passes_cache.write().trim()
+            + self.resources.layouts_cache.write().trim()
+            + self.resources.samplers_cache.write().trim()
+    }
+
     /// Create descriptor sets with specified layout.
     pub fn create_relevant_descriptor_set(
         &self,
@@ -1145,10 +1912,46 @@ where
     }
 
     /// Query memory utilization.
+    ///
+    /// Reports bytes allocated from each heap, bytes used by resources and heap size
+    /// according to allocator bookkeeping.
+    /// Allocations made outside of the allocator are not accounted.
+    ///
+    /// Driver reported budget (`VK_EXT_memory_budget` or equivalent) is not exposed
+    /// by the backend yet, so `MemoryHeapUtilization::budget` is always `None`
+    /// and `MemoryHeapUtilization::available` falls back to bookkeeping.
     pub fn memory_utilization(&self) -> TotalMemoryUtilization {
         self.heaps.lock().utilization()
     }
 
+    /// Get allocator policy used for resource memory.
+    pub fn allocator_policy(&self) -> memory::AllocatorPolicy {
+        *self.heaps.lock().allocator_policy()
+    }
+
+    /// Set allocators to use for well-known memory usage types,
+    /// e.g. `memory::Kind::Linear` for short-lived `memory::Upload` buffers.
+    /// Usages without allocator in the policy pick allocator by their fitness.
+    /// Affects only resources created afterwards.
+    pub fn set_allocator_policy(&self, policy: memory::AllocatorPolicy) {
+        self.heaps.lock().set_allocator_policy(policy);
+    }
+
+    /// Name the object for debugging tools like RenderDoc.
+    /// Names are visible in captures and validation layers messages.
+    ///
+    /// Backend checks for `VK_EXT_debug_utils` or equivalent once when instance is created.
+    /// If it is not available this is no-op.
+    pub fn set_object_name<T>(&self, object: &mut T, name: &str)
+    where
+        T: DebugName<B>,
+    {
+        unsafe {
+            // `DebugName` implementations check the object is created from this device.
+            object.set_debug_name(&self.device, name)
+        }
+    }
+
     /// Get Factory's instance id.
     pub fn instance_id(&self) -> InstanceId {
         self.device.id().instance
@@ -1166,12 +1969,56 @@ where
     }
 }
 
+/// Open device on the adapter with queues picked by config
+/// and `features` enabled.
+fn open_device<B>(
+    device_id: DeviceId,
+    adapter: &Adapter<B>,
+    features: Features,
+    config: &Config<impl DevicesConfigure, impl HeapsConfigure, impl QueuesConfigure>,
+) -> Result<(B::Device, Families<B>), CreationError>
+where
+    B: Backend,
+{
+    let families = config
+        .queues
+        .configure(device_id, &adapter.queue_families)
+        .into_iter()
+        .collect::<SmallVec<[_; 16]>>();
+    let (create_queues, get_queues): (SmallVec<[_; 32]>, SmallVec<[_; 32]>) = families
+        .iter()
+        .map(|(index, priorities)| {
+            (
+                (&adapter.queue_families[index.index], priorities.as_ref()),
+                (*index, priorities.as_ref().len()),
+            )
+        })
+        .unzip();
+
+    log::debug!("Queues: {:#?}", get_queues);
+
+    let Gpu {
+        device,
+        mut queue_groups,
+    } = unsafe { adapter.physical_device.open(&create_queues, features) }?;
+
+    let families = unsafe {
+        families_from_device(
+            device_id,
+            &mut queue_groups,
+            get_queues,
+            &adapter.queue_families,
+        )
+    };
+    Ok((device, families))
+}
+
 /// Initialize `Factory` and Queue `Families` associated with Device
 /// using existing `Instance`.
 pub fn init_with_instance<B>(
     instance: Instance<B>,
     config: &Config<impl DevicesConfigure, impl HeapsConfigure, impl QueuesConfigure>,
-) -> Result<(Factory<B>, Families<B>), CreationError>
+) -> Result<(Factory<B>, Families<B>), FactoryInitError>
 where
     B: Backend,
 {
@@ -1185,7 +2032,7 @@ where
 pub fn init_with_instance_ref<B>(
     instance: &Instance<B>,
     config: &Config<impl DevicesConfigure, impl HeapsConfigure, impl QueuesConfigure>,
-) -> Result<(Factory<B>, Families<B>), CreationError>
+) -> Result<(Factory<B>, Families<B>), FactoryInitError>
 where
     B: Backend,
 {
@@ -1196,7 +2043,7 @@ where
 
     if adapters.is_empty() {
         log::warn!("No physical devices found");
-        return Err(rendy_core::hal::device::CreationError::InitializationFailed);
+        return Err(FactoryInitError::NoAdapters);
     }
 
     log::debug!(
@@ -1207,66 +2054,55 @@ where
             .collect::<SmallVec<[_; 32]>>()
     );
 
-    let picked = config.devices.pick(&adapters);
-    if picked >= adapters.len() {
+    let order = config.devices.pick_order(&adapters);
+    if order.iter().any(|&picked| picked >= adapters.len()) {
         panic!("Physical device pick config returned index out of bound");
     }
-    let adapter = adapters.swap_remove(picked);
-
-    #[derive(Debug)]
-    struct PhysicalDeviceInfo<'a> {
-        name: &'a str,
-        features: Features,
-        limits: Limits,
+    if order.is_empty() {
+        log::warn!("No suitable physical devices found");
+        return Err(FactoryInitError::NoSuitableAdapters);
     }
 
-    log::debug!(
-        "Physical device picked: {:#?}",
-        PhysicalDeviceInfo {
-            name: &adapter.info.name,
-            features: adapter.physical_device.features(),
-            limits: adapter.physical_device.limits(),
-        }
-    );
-
     let device_id = DeviceId::new(instance.id());
 
-    let (device, families) = {
-        let families = config
-            .queues
-            .configure(device_id, &adapter.queue_families)
-            .into_iter()
-            .collect::<SmallVec<[_; 16]>>();
-        let (create_queues, get_queues): (SmallVec<[_; 32]>, SmallVec<[_; 32]>) = families
-            .iter()
-            .map(|(index, priorities)| {
-                (
-                    (&adapter.queue_families[index.index], priorities.as_ref()),
-                    (*index, priorities.as_ref().len()),
-                )
-            })
-            .unzip();
-
-        log::debug!("Queues: {:#?}", get_queues);
+    let mut failed = Vec::new();
+    let mut opened = None;
+    for &picked in &order {
+        let adapter = &adapters[picked];
+        let info = PhysicalDeviceInfo {
+            info: adapter.info.clone(),
+            features: adapter.physical_device.features(),
+            limits: adapter.physical_device.limits(),
+        };
+        log::debug!("Physical device picked: {:#?}", info);
 
-        let Gpu {
-            device,
-            mut queue_groups,
-        } = unsafe {
-            adapter
-                .physical_device
-                .open(&create_queues, adapter.physical_device.features())
-        }?;
+        match open_device(device_id, adapter, info.features, config) {
+            Ok((device, families)) => {
+                opened = Some((picked, info.features, device, families));
+                break;
+            }
+            Err(err) => {
+                log::warn!(
+                    "Failed to create device on {}: {:?}",
+                    adapter.info.name,
+                    err
+                );
+                failed.push((info, err));
+            }
+        }
+    }
 
-        let families = unsafe {
-            families_from_device(
-                device_id,
-                &mut queue_groups,
-                get_queues,
-                &adapter.queue_families,
-            )
-        };
-        (device, families)
+    let (adapter, features, device, families) = match opened {
+        Some((picked, features, device, families)) => {
+            (adapters.swap_remove(picked), features, device, families)
+        }
+        None => {
+            log::error!(
+                "Failed to create device on all picked adapters: {:#?}",
+                failed
+            );
+            return Err(FactoryInitError::Rejected(failed));
+        }
     };
 
     let device = Device::from_raw(device, device_id);
@@ -1294,11 +2130,18 @@ where
         heaps: ManuallyDrop::new(parking_lot::Mutex::new(heaps)),
         resources: ManuallyDrop::new(ResourceHub::default()),
         uploader: unsafe { Uploader::new(&device, &families) }
-            .map_err(rendy_core::hal::device::CreationError::OutOfMemory)?,
+            .map_err(FactoryInitError::OutOfMemory)?,
         blitter: unsafe { Blitter::new(&device, &families) }
-            .map_err(rendy_core::hal::device::CreationError::OutOfMemory)?,
+            .map_err(FactoryInitError::OutOfMemory)?,
+        pipeline_cache: ManuallyDrop::new(
+            unsafe { device.create_pipeline_cache(None) }.map_err(FactoryInitError::OutOfMemory)?,
+        ),
         families_indices: families.indices().into(),
+        features,
+        limits: adapter.physical_device.limits(),
+        device_lost: std::sync::atomic::AtomicBool::new(false),
         epochs,
+        fence_pool: parking_lot::Mutex::new(FencePool::new(&device)),
         device,
         adapter,
         instance: InstanceOrId::Id(instance.id()),
@@ -1306,3 +2149,53 @@ where
 
     Ok((factory, families))
 }
+
+#[cfg(all(test, feature = "empty"))]
+mod tests {
+    use {
+        super::{init_with_instance, Config},
+        rendy_core::{
+            empty::Backend,
+            hal::{adapter::PhysicalDevice as _, Features, Instance as _},
+            Instance,
+        },
+    };
+
+    #[test]
+    fn features_and_limits_match_adapter() {
+        let adapter = rendy_core::empty::Instance::create("rendy", 1)
+            .unwrap()
+            .enumerate_adapters()
+            .remove(0);
+        let requested = adapter.physical_device.features();
+        let limits = adapter.physical_device.limits();
+
+        let instance = rendy_core::empty::Instance::create("rendy", 1).unwrap();
+        let config: Config = Default::default();
+        let (factory, _families) =
+            init_with_instance::<Backend>(Instance::new(instance), &config).unwrap();
+
+        assert_eq!(factory.features(), requested);
+        assert_eq!(factory.limits().max_image_2d_size, limits.max_image_2d_size);
+        assert_eq!(
+            factory.limits().max_compute_work_group_count,
+            limits.max_compute_work_group_count
+        );
+        assert_eq!(
+            factory.limits().max_compute_work_group_size,
+            limits.max_compute_work_group_size
+        );
+        assert_eq!(
+            factory.limits().min_uniform_buffer_offset_alignment,
+            limits.min_uniform_buffer_offset_alignment
+        );
+
+        // Mock adapter exposes none of the optional features.
+        assert!(!requested.contains(Features::GEOMETRY_SHADER));
+        assert!(!requested.contains(Features::TESSELLATION_SHADER));
+        assert!(!requested.contains(Features::SAMPLER_ANISOTROPY));
+        assert!(!factory.supports_geometry_shader());
+        assert!(!factory.supports_tessellation_shader());
+        assert!(!factory.supports_sampler_anisotropy());
+    }
+}
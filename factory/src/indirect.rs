@@ -0,0 +1,256 @@
+use {
+    crate::{
+        command::{Compute, Encoder, RenderPassEncoder, Supports},
+        factory::Factory,
+        resource::Buffer,
+    },
+    rendy_core::hal::{buffer::Usage, Backend, Features},
+};
+
+/// Buffer used as source of indirect commands wasn't created with `INDIRECT` usage.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct IndirectUsageError {
+    /// Usage buffer was created with.
+    pub usage: Usage,
+}
+
+impl std::fmt::Display for IndirectUsageError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "Buffer with usage {:?} can't be used for indirect commands. INDIRECT usage is required",
+            self.usage
+        )
+    }
+}
+impl std::error::Error for IndirectUsageError {}
+
+/// Token that proves device supports `Features::DRAW_INDIRECT_COUNT`.
+/// Nodes get it with `DrawIndirectCount::new` at build
+/// and pass it to count variants of `DrawIndirect` commands.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct DrawIndirectCount {
+    _private: (),
+}
+
+impl DrawIndirectCount {
+    /// Check that device created by `factory` supports `Features::DRAW_INDIRECT_COUNT`.
+    /// Returns `None` otherwise.
+    pub fn new<B: Backend>(factory: &Factory<B>) -> Option<Self> {
+        Self::from_features(factory.features())
+    }
+
+    fn from_features(features: Features) -> Option<Self> {
+        if features.contains(Features::DRAW_INDIRECT_COUNT) {
+            Some(DrawIndirectCount { _private: () })
+        } else {
+            None
+        }
+    }
+}
+
+fn check_indirect<B: Backend>(buffer: &Buffer<B>) -> Result<(), IndirectUsageError> {
+    let usage = buffer.info().usage;
+    if usage.contains(Usage::INDIRECT) {
+        Ok(())
+    } else {
+        Err(IndirectUsageError { usage })
+    }
+}
+
+/// Indirect draw commands that take buffers created by `Factory`
+/// and check that they can be used for indirect commands.
+///
+/// Buffers managed by the graph get `INDIRECT` usage
+/// when node declares `Access::INDIRECT_COMMAND_READ` at `PipelineStage::DRAW_INDIRECT` with `Usage::INDIRECT`.
+/// The same declaration makes graph insert barrier after command arguments are written.
+/// See `BufferAccess::indirect` in `rendy-graph`.
+pub trait DrawIndirect<B: Backend> {
+    /// Same as `RenderPassEncoder::draw_indirect`.
+    /// Fails if `buffer` lacks `INDIRECT` usage.
+    ///
+    /// # Safety
+    ///
+    /// See `RenderPassEncoder::draw_indirect`.
+    unsafe fn draw_buffer_indirect(
+        &mut self,
+        buffer: &Buffer<B>,
+        offset: u64,
+        draw_count: u32,
+        stride: u32,
+    ) -> Result<(), IndirectUsageError>;
+
+    /// Same as `RenderPassEncoder::draw_indexed_indirect`.
+    /// Fails if `buffer` lacks `INDIRECT` usage.
+    ///
+    /// # Safety
+    ///
+    /// See `RenderPassEncoder::draw_indexed_indirect`.
+    unsafe fn draw_indexed_buffer_indirect(
+        &mut self,
+        buffer: &Buffer<B>,
+        offset: u64,
+        draw_count: u32,
+        stride: u32,
+    ) -> Result<(), IndirectUsageError>;
+
+    /// Same as `RenderPassEncoder::draw_indirect_count`.
+    /// Fails if `buffer` or `count_buffer` lacks `INDIRECT` usage.
+    ///
+    /// # Safety
+    ///
+    /// See `RenderPassEncoder::draw_indirect_count`.
+    /// `supported` must be created with the factory that created the device.
+    unsafe fn draw_buffer_indirect_count(
+        &mut self,
+        supported: DrawIndirectCount,
+        buffer: &Buffer<B>,
+        offset: u64,
+        count_buffer: &Buffer<B>,
+        count_buffer_offset: u64,
+        max_draw_count: u32,
+        stride: u32,
+    ) -> Result<(), IndirectUsageError>;
+
+    /// Same as `RenderPassEncoder::draw_indexed_indirect_count`.
+    /// Fails if `buffer` or `count_buffer` lacks `INDIRECT` usage.
+    ///
+    /// # Safety
+    ///
+    /// See `RenderPassEncoder::draw_indexed_indirect_count`.
+    /// `supported` must be created with the factory that created the device.
+    unsafe fn draw_indexed_buffer_indirect_count(
+        &mut self,
+        supported: DrawIndirectCount,
+        buffer: &Buffer<B>,
+        offset: u64,
+        count_buffer: &Buffer<B>,
+        count_buffer_offset: u64,
+        max_draw_count: u32,
+        stride: u32,
+    ) -> Result<(), IndirectUsageError>;
+}
+
+impl<'a, B> DrawIndirect<B> for RenderPassEncoder<'a, B>
+where
+    B: Backend,
+{
+    unsafe fn draw_buffer_indirect(
+        &mut self,
+        buffer: &Buffer<B>,
+        offset: u64,
+        draw_count: u32,
+        stride: u32,
+    ) -> Result<(), IndirectUsageError> {
+        check_indirect(buffer)?;
+        self.draw_indirect(buffer.raw(), offset, draw_count, stride);
+        Ok(())
+    }
+
+    unsafe fn draw_indexed_buffer_indirect(
+        &mut self,
+        buffer: &Buffer<B>,
+        offset: u64,
+        draw_count: u32,
+        stride: u32,
+    ) -> Result<(), IndirectUsageError> {
+        check_indirect(buffer)?;
+        self.draw_indexed_indirect(buffer.raw(), offset, draw_count, stride);
+        Ok(())
+    }
+
+    unsafe fn draw_buffer_indirect_count(
+        &mut self,
+        _supported: DrawIndirectCount,
+        buffer: &Buffer<B>,
+        offset: u64,
+        count_buffer: &Buffer<B>,
+        count_buffer_offset: u64,
+        max_draw_count: u32,
+        stride: u32,
+    ) -> Result<(), IndirectUsageError> {
+        check_indirect(buffer)?;
+        check_indirect(count_buffer)?;
+        self.draw_indirect_count(
+            buffer.raw(),
+            offset,
+            count_buffer.raw(),
+            count_buffer_offset,
+            max_draw_count,
+            stride,
+        );
+        Ok(())
+    }
+
+    unsafe fn draw_indexed_buffer_indirect_count(
+        &mut self,
+        _supported: DrawIndirectCount,
+        buffer: &Buffer<B>,
+        offset: u64,
+        count_buffer: &Buffer<B>,
+        count_buffer_offset: u64,
+        max_draw_count: u32,
+        stride: u32,
+    ) -> Result<(), IndirectUsageError> {
+        check_indirect(buffer)?;
+        check_indirect(count_buffer)?;
+        self.draw_indexed_indirect_count(
+            buffer.raw(),
+            offset,
+            count_buffer.raw(),
+            count_buffer_offset,
+            max_draw_count,
+            stride,
+        );
+        Ok(())
+    }
+}
+
+/// Indirect dispatch that takes buffers created by `Factory`
+/// and checks that they can be used for indirect commands.
+///
+/// Buffers managed by the graph are declared the same way as for `DrawIndirect`,
+/// which lets one compute node write workgroup counts for another.
+pub trait DispatchIndirect<B: Backend> {
+    /// Same as `Encoder::dispatch_indirect`.
+    /// Fails if `buffer` lacks `INDIRECT` usage.
+    ///
+    /// # Safety
+    ///
+    /// See `Encoder::dispatch_indirect`.
+    unsafe fn dispatch_buffer_indirect(
+        &mut self,
+        buffer: &Buffer<B>,
+        offset: u64,
+    ) -> Result<(), IndirectUsageError>;
+}
+
+impl<'a, B, C, L> DispatchIndirect<B> for Encoder<'a, B, C, L>
+where
+    B: Backend,
+    C: Supports<Compute>,
+{
+    unsafe fn dispatch_buffer_indirect(
+        &mut self,
+        buffer: &Buffer<B>,
+        offset: u64,
+    ) -> Result<(), IndirectUsageError> {
+        check_indirect(buffer)?;
+        self.dispatch_indirect(buffer.raw(), offset);
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn draw_count_requires_feature() {
+        assert_eq!(DrawIndirectCount::from_features(Features::empty()), None);
+        assert!(DrawIndirectCount::from_features(
+            Features::DRAW_INDIRECT_COUNT | Features::MULTI_DRAW_INDIRECT
+        )
+        .is_some());
+    }
+}
@@ -18,9 +18,21 @@ use rendy_resource as resource;
 use rendy_wsi as wsi;
 
 mod barriers;
+mod batch;
 mod blitter;
 mod config;
+mod debug;
+#[cfg(feature = "dump")]
+mod dump;
 mod factory;
+mod indirect;
+mod pipeline_cache;
 mod upload;
 
-pub use crate::{barriers::*, blitter::*, config::*, factory::*, upload::*};
+pub use crate::{
+    barriers::*, batch::*, blitter::*, config::*, debug::*, factory::*, indirect::*,
+    pipeline_cache::*, upload::*,
+};
+
+#[cfg(feature = "dump")]
+pub use crate::dump::*;
@@ -0,0 +1,96 @@
+use rendy_core::hal::{adapter::AdapterInfo, device::OutOfMemory};
+
+/// Magic bytes that start pipeline cache file.
+const MAGIC: &[u8; 8] = b"RENDYPSO";
+
+/// Version of the cache file layout.
+const VERSION: u32 = 1;
+
+/// Error occured while loading or saving pipeline cache.
+#[derive(Debug)]
+pub enum PipelineCacheError {
+    /// Failed to read or write cache file.
+    Io(std::io::Error),
+    /// Failed to create cache or fetch its data.
+    OutOfMemory(OutOfMemory),
+}
+
+impl std::fmt::Display for PipelineCacheError {
+    fn fmt(&self, fmt: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            PipelineCacheError::Io(err) => write!(fmt, "Pipeline cache I/O failed: {}", err),
+            PipelineCacheError::OutOfMemory(err) => {
+                write!(fmt, "Pipeline cache operation failed: {:?}", err)
+            }
+        }
+    }
+}
+
+impl std::error::Error for PipelineCacheError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            PipelineCacheError::Io(err) => Some(err),
+            PipelineCacheError::OutOfMemory(err) => Some(err),
+        }
+    }
+}
+
+/// Header identifying backend and device the cache blob was created by.
+/// Drivers validate their own header too, but may crash on foreign blobs.
+pub(crate) fn cache_header(backend: &str, info: &AdapterInfo) -> Vec<u8> {
+    let mut header = Vec::new();
+    header.extend_from_slice(MAGIC);
+    header.extend_from_slice(&VERSION.to_le_bytes());
+    header.extend_from_slice(&(info.vendor as u64).to_le_bytes());
+    header.extend_from_slice(&(info.device as u64).to_le_bytes());
+    for string in &[backend, info.name.as_str()] {
+        header.extend_from_slice(&(string.len() as u32).to_le_bytes());
+        header.extend_from_slice(string.as_bytes());
+    }
+    header
+}
+
+/// Get cache blob from file `data`.
+/// Returns `None` if header doesn't match `backend` and device.
+pub(crate) fn cache_blob<'a>(
+    data: &'a [u8],
+    backend: &str,
+    info: &AdapterInfo,
+) -> Option<&'a [u8]> {
+    let header = cache_header(backend, info);
+    if data.starts_with(&header) {
+        Some(&data[header.len()..])
+    } else {
+        None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use {super::*, rendy_core::hal::adapter::DeviceType};
+
+    fn info(device: usize) -> AdapterInfo {
+        AdapterInfo {
+            name: "Test GPU".into(),
+            vendor: 0x1234,
+            device,
+            device_type: DeviceType::DiscreteGpu,
+        }
+    }
+
+    #[test]
+    fn blob_round_trips_on_same_device() {
+        let mut data = cache_header("vulkan", &info(1));
+        data.extend_from_slice(&[1, 2, 3]);
+        assert_eq!(cache_blob(&data, "vulkan", &info(1)), Some(&[1, 2, 3][..]));
+    }
+
+    #[test]
+    fn blob_from_other_device_is_rejected() {
+        let mut data = cache_header("vulkan", &info(1));
+        data.extend_from_slice(&[1, 2, 3]);
+        assert_eq!(cache_blob(&data, "vulkan", &info(2)), None);
+        assert_eq!(cache_blob(&data, "metal", &info(1)), None);
+        assert_eq!(cache_blob(&[1, 2, 3], "vulkan", &info(1)), None);
+    }
+}
@@ -6,10 +6,11 @@ use {
             PendingOnceState, PrimaryLevel, QueueId, RecordingState, Submission, Transfer,
         },
         core::Device,
+        factory::Factory,
         resource::{Buffer, Escape, Handle, Image},
     },
-    rendy_core::hal::device::{Device as _, OutOfMemory},
-    std::{collections::VecDeque, iter::once},
+    rendy_core::hal::device::{Device as _, MapError, OutOfMemory},
+    std::{collections::VecDeque, iter::once, ops::Range, sync::Arc},
 };
 
 /// State of the buffer on device.
@@ -120,6 +121,148 @@ impl From<rendy_core::hal::image::Layout> for ImageStateOrLayout {
     }
 }
 
+#[derive(Debug)]
+struct ReadbackState<B: rendy_core::hal::Backend> {
+    staging: Option<Escape<Buffer<B>>>,
+    size: u64,
+    result: ReadbackResult,
+}
+
+impl<B> ReadbackState<B>
+where
+    B: rendy_core::hal::Backend,
+{
+    fn new(staging: Escape<Buffer<B>>, size: u64) -> Arc<parking_lot::Mutex<Self>> {
+        Arc::new(parking_lot::Mutex::new(ReadbackState {
+            staging: Some(staging),
+            size,
+            result: ReadbackResult::default(),
+        }))
+    }
+
+    /// Read staging buffer once device finished copying into it.
+    ///
+    /// # Safety
+    ///
+    /// Staging buffer must belong to the `device`.
+    unsafe fn complete(&mut self, device: &Device<B>) {
+        if let Some(mut staging) = self.staging.take() {
+            self.result
+                .complete(read_mapped(device, &mut staging, self.size));
+        }
+    }
+}
+
+/// Data read back to the host and task waiting for it.
+#[derive(Debug, Default)]
+struct ReadbackResult {
+    data: Option<Result<Vec<u8>, MapError>>,
+    waker: Option<std::task::Waker>,
+}
+
+impl ReadbackResult {
+    fn complete(&mut self, data: Result<Vec<u8>, MapError>) {
+        self.data = Some(data);
+        if let Some(waker) = self.waker.take() {
+            waker.wake();
+        }
+    }
+
+    fn poll(
+        &mut self,
+        cx: &mut std::task::Context<'_>,
+    ) -> std::task::Poll<Result<Vec<u8>, MapError>> {
+        match self.data.take() {
+            Some(data) => std::task::Poll::Ready(data),
+            None => {
+                self.waker = Some(cx.waker().clone());
+                std::task::Poll::Pending
+            }
+        }
+    }
+}
+
+/// Read `size` bytes from the beginning of host-visible buffer.
+/// Memory is invalidated before reading if it is not coherent,
+/// so that device writes become visible to the host.
+///
+/// # Safety
+///
+/// `buffer` must belong to the `device`.
+/// Device must not write to the buffer and its writes must be made available to the host.
+pub unsafe fn read_mapped<B: rendy_core::hal::Backend>(
+    device: &Device<B>,
+    buffer: &mut Buffer<B>,
+    size: u64,
+) -> Result<Vec<u8>, MapError> {
+    use crate::memory::Block as _;
+
+    if !buffer
+        .properties()
+        .contains(rendy_core::hal::memory::Properties::COHERENT)
+    {
+        let block = buffer.block();
+        device.invalidate_mapped_memory_ranges(Some((block.memory(), block.range())))?;
+    }
+
+    let mapped = buffer.map(device, 0..size)?;
+    let data = std::slice::from_raw_parts(mapped.ptr().as_ptr(), size as usize).to_vec();
+    Ok(data)
+}
+
+/// Handle to buffer content being read back to the host.
+///
+/// Copy operation is submitted upon next [`Factory::flush_uploads`] or [`Factory::maintain`] call
+/// and completes when device finishes it.
+/// Completion is detected by [`Factory::maintain`] without waiting for the device,
+/// so the handle becomes complete one of the following `maintain` calls.
+///
+/// Handle is a `Future` resolving to the data, waken by the `maintain` call that completes it.
+///
+/// [`Factory::flush_uploads`]: struct.Factory.html#method.flush_uploads
+/// [`Factory::maintain`]: struct.Factory.html#method.maintain
+#[derive(Debug)]
+pub struct BufferReadback<B: rendy_core::hal::Backend> {
+    state: Arc<parking_lot::Mutex<ReadbackState<B>>>,
+    size: u64,
+}
+
+impl<B> BufferReadback<B>
+where
+    B: rendy_core::hal::Backend,
+{
+    /// Size of the data in bytes.
+    pub fn size(&self) -> u64 {
+        self.size
+    }
+
+    /// Check if device finished copying the data.
+    pub fn is_complete(&self) -> bool {
+        self.state.lock().result.data.is_some()
+    }
+
+    /// Fetch data if device finished copying it.
+    /// Returns `None` if copy operation is not complete yet.
+    /// This function never waits for the device.
+    pub fn fetch(&self) -> Result<Option<Vec<u8>>, MapError> {
+        self.state.lock().result.data.clone().transpose()
+    }
+}
+
+impl<B> std::future::Future for BufferReadback<B>
+where
+    B: rendy_core::hal::Backend,
+{
+    type Output = Result<Vec<u8>, MapError>;
+
+    fn poll(
+        self: std::pin::Pin<&mut Self>,
+        cx: &mut std::task::Context<'_>,
+    ) -> std::task::Poll<Self::Output> {
+        self.state.lock().result.poll(cx)
+    }
+}
+
 #[derive(Debug)]
 pub(crate) struct Uploader<B: rendy_core::hal::Backend> {
     family_uploads: Vec<Option<parking_lot::Mutex<FamilyUploads<B>>>>,
@@ -210,6 +353,156 @@ where
         Ok(())
     }
 
+    /// # Safety
+    ///
+    /// `device` must be the same that was used to create this `Uploader`.
+    /// `buffer` and `staging` must belong to the `device`.
+    ///
+    pub(crate) unsafe fn read_buffer(
+        &self,
+        device: &Device<B>,
+        buffer: &Buffer<B>,
+        range: Range<u64>,
+        staging: Escape<Buffer<B>>,
+        last: BufferState,
+    ) -> Result<BufferReadback<B>, OutOfMemory> {
+        let mut family_uploads = self.family_uploads[last.queue.family.index]
+            .as_ref()
+            .unwrap()
+            .lock();
+
+        let size = range.end - range.start;
+        let next_upload = family_uploads.next_upload(device, last.queue.index)?;
+
+        // Transfer reads are not covered by combined barriers which target transfer writes.
+        next_upload.barrier_buffer.encoder().pipeline_barrier(
+            last.stage..rendy_core::hal::pso::PipelineStage::TRANSFER,
+            rendy_core::hal::memory::Dependencies::empty(),
+            Some(rendy_core::hal::memory::Barrier::Buffer {
+                states: last.access..rendy_core::hal::buffer::Access::TRANSFER_READ,
+                target: buffer.raw(),
+                families: None,
+                range: Some(range.start)..Some(range.end),
+            }),
+        );
+
+        let mut encoder = next_upload.command_buffer.encoder();
+        encoder.copy_buffer(
+            buffer.raw(),
+            staging.raw(),
+            Some(rendy_core::hal::command::BufferCopy {
+                src: range.start,
+                dst: 0,
+                size,
+            }),
+        );
+        encoder.pipeline_barrier(
+            rendy_core::hal::pso::PipelineStage::TRANSFER
+                ..rendy_core::hal::pso::PipelineStage::HOST,
+            rendy_core::hal::memory::Dependencies::empty(),
+            Some(rendy_core::hal::memory::Barrier::Buffer {
+                states: rendy_core::hal::buffer::Access::TRANSFER_WRITE
+                    ..rendy_core::hal::buffer::Access::HOST_READ,
+                target: staging.raw(),
+                families: None,
+                range: None..None,
+            }),
+        );
+
+        let state = ReadbackState::new(staging, size);
+        next_upload.readbacks.push(state.clone());
+
+        Ok(BufferReadback { state, size })
+    }
+
+    /// # Safety
+    ///
+    /// `device` must be the same that was used to create this `Uploader`.
+    /// `image` and `staging` must belong to the `device`.
+    ///
+    pub(crate) unsafe fn read_image(
+        &self,
+        device: &Device<B>,
+        image: &Image<B>,
+        image_layers: rendy_core::hal::image::SubresourceLayers,
+        staging: Escape<Buffer<B>>,
+        size: u64,
+        last: ImageState,
+    ) -> Result<BufferReadback<B>, OutOfMemory> {
+        use rendy_core::hal::image::Access;
+
+        let mut family_uploads = self.family_uploads[last.queue.family.index]
+            .as_ref()
+            .unwrap()
+            .lock();
+
+        let next_upload = family_uploads.next_upload(device, last.queue.index)?;
+
+        let image_range = rendy_core::hal::image::SubresourceRange {
+            aspects: image_layers.aspects,
+            levels: image_layers.level..image_layers.level + 1,
+            layers: image_layers.layers.clone(),
+        };
+        let (src_layout, end_layout) = readback_layouts(last.layout);
+
+        next_upload.barrier_buffer.encoder().pipeline_barrier(
+            last.stage..rendy_core::hal::pso::PipelineStage::TRANSFER,
+            rendy_core::hal::memory::Dependencies::empty(),
+            Some(rendy_core::hal::memory::Barrier::Image {
+                states: (last.access, last.layout)..(Access::TRANSFER_READ, src_layout),
+                target: image.raw(),
+                families: None,
+                range: image_range.clone(),
+            }),
+        );
+
+        let mut encoder = next_upload.command_buffer.encoder();
+        encoder.copy_image_to_buffer(
+            image.raw(),
+            src_layout,
+            staging.raw(),
+            Some(rendy_core::hal::command::BufferImageCopy {
+                buffer_offset: 0,
+                buffer_width: 0,
+                buffer_height: 0,
+                image_layers: image_layers.clone(),
+                image_offset: rendy_core::hal::image::Offset::ZERO,
+                image_extent: image.kind().level_extent(image_layers.level),
+            }),
+        );
+        // Image is returned to the state it was in, so following commands need no changes.
+        // Image with undefined content is left in the layout it was read in.
+        let end_access = match end_layout {
+            layout if layout == last.layout => last.access,
+            _ => Access::TRANSFER_READ,
+        };
+        encoder.pipeline_barrier(
+            rendy_core::hal::pso::PipelineStage::TRANSFER
+                ..rendy_core::hal::pso::PipelineStage::HOST | last.stage,
+            rendy_core::hal::memory::Dependencies::empty(),
+            vec![
+                rendy_core::hal::memory::Barrier::Image {
+                    states: (Access::TRANSFER_READ, src_layout)..(end_access, end_layout),
+                    target: image.raw(),
+                    families: None,
+                    range: image_range,
+                },
+                rendy_core::hal::memory::Barrier::Buffer {
+                    states: rendy_core::hal::buffer::Access::TRANSFER_WRITE
+                        ..rendy_core::hal::buffer::Access::HOST_READ,
+                    target: staging.raw(),
+                    families: None,
+                    range: None..None,
+                },
+            ],
+        );
+
+        let state = ReadbackState::new(staging, size);
+        next_upload.readbacks.push(state.clone());
+
+        Ok(BufferReadback { state, size })
+    }
+
     /// # Safety
     ///
     /// `image` must belong to the `device` that was used to create this Uploader.
@@ -425,6 +718,7 @@ pub(crate) struct PendingUploads<B: rendy_core::hal::Backend> {
     barrier_buffer: CommandBuffer<B, Transfer, PendingOnceState, PrimaryLevel, IndividualReset>,
     command_buffer: CommandBuffer<B, Transfer, PendingOnceState, PrimaryLevel, IndividualReset>,
     staging_buffers: Vec<Escape<Buffer<B>>>,
+    readbacks: Vec<Arc<parking_lot::Mutex<ReadbackState<B>>>>,
     fence: B::Fence,
 }
 
@@ -435,6 +729,7 @@ struct NextUploads<B: rendy_core::hal::Backend> {
     command_buffer:
         CommandBuffer<B, Transfer, RecordingState<OneShot>, PrimaryLevel, IndividualReset>,
     staging_buffers: Vec<Escape<Buffer<B>>>,
+    readbacks: Vec<Arc<parking_lot::Mutex<ReadbackState<B>>>>,
     fence: B::Fence,
 }
 
@@ -467,6 +762,7 @@ where
                 barrier_buffer,
                 command_buffer,
                 staging_buffers: next.staging_buffers,
+                readbacks: next.readbacks,
                 fence: next.fence,
             });
         }
@@ -498,6 +794,7 @@ where
                     barrier_buffer: buf_a.begin(OneShot, ()),
                     command_buffer: buf_b.begin(OneShot, ()),
                     staging_buffers: Vec::new(),
+                    readbacks: Vec::new(),
                     fence,
                 });
 
@@ -526,6 +823,9 @@ where
                     device
                         .reset_fence(&pending.fence)
                         .expect("Can always reset signalled fence");
+                    for readback in pending.readbacks {
+                        readback.lock().complete(device);
+                    }
                     self.fences.push(pending.fence);
                     self.command_buffers.push([
                         pending.command_buffer.mark_complete().reset(),
@@ -565,3 +865,93 @@ where
         self.pool.dispose(device);
     }
 }
+
+/// Layout image is read back in and layout it is left in afterwards
+/// for image in `last` layout.
+/// Image is returned to `last` layout unless it is `Undefined`,
+/// which can't be transitioned to.
+pub(crate) fn readback_layouts(
+    last: rendy_core::hal::image::Layout,
+) -> (
+    rendy_core::hal::image::Layout,
+    rendy_core::hal::image::Layout,
+) {
+    use rendy_core::hal::image::Layout;
+
+    let src = match last {
+        Layout::General => Layout::General,
+        _ => Layout::TransferSrcOptimal,
+    };
+    match last {
+        Layout::Undefined => (src, src),
+        _ => (src, last),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use {
+        super::{readback_layouts, ReadbackResult},
+        rendy_core::hal::image::Layout,
+        std::{
+            sync::{
+                atomic::{AtomicUsize, Ordering},
+                Arc,
+            },
+            task::{Context, Poll, RawWaker, RawWakerVTable, Waker},
+        },
+    };
+
+    fn counting_waker(wakes: Arc<AtomicUsize>) -> Waker {
+        unsafe fn clone(data: *const ()) -> RawWaker {
+            let wakes = Arc::from_raw(data as *const AtomicUsize);
+            std::mem::forget(wakes.clone());
+            RawWaker::new(Arc::into_raw(wakes) as *const (), &VTABLE)
+        }
+        unsafe fn wake(data: *const ()) {
+            wake_by_ref(data);
+            drop_waker(data);
+        }
+        unsafe fn wake_by_ref(data: *const ()) {
+            (*(data as *const AtomicUsize)).fetch_add(1, Ordering::SeqCst);
+        }
+        unsafe fn drop_waker(data: *const ()) {
+            drop(Arc::from_raw(data as *const AtomicUsize));
+        }
+        static VTABLE: RawWakerVTable = RawWakerVTable::new(clone, wake, wake_by_ref, drop_waker);
+
+        unsafe { Waker::from_raw(RawWaker::new(Arc::into_raw(wakes) as *const (), &VTABLE)) }
+    }
+
+    #[test]
+    fn readback_resolves_with_copied_pattern() {
+        let wakes = Arc::new(AtomicUsize::new(0));
+        let waker = counting_waker(wakes.clone());
+        let mut cx = Context::from_waker(&waker);
+
+        let mut result = ReadbackResult::default();
+        assert_eq!(result.poll(&mut cx), Poll::Pending);
+        assert_eq!(wakes.load(Ordering::SeqCst), 0);
+
+        let pattern: Vec<u8> = (0..=255).collect();
+        result.complete(Ok(pattern.clone()));
+        assert_eq!(wakes.load(Ordering::SeqCst), 1);
+        assert_eq!(result.poll(&mut cx), Poll::Ready(Ok(pattern)));
+    }
+
+    #[test]
+    fn image_is_returned_to_defined_layout() {
+        assert_eq!(
+            readback_layouts(Layout::ShaderReadOnlyOptimal),
+            (Layout::TransferSrcOptimal, Layout::ShaderReadOnlyOptimal)
+        );
+        assert_eq!(
+            readback_layouts(Layout::General),
+            (Layout::General, Layout::General)
+        );
+        assert_eq!(
+            readback_layouts(Layout::Undefined),
+            (Layout::TransferSrcOptimal, Layout::TransferSrcOptimal)
+        );
+    }
+}
@@ -149,6 +149,13 @@ impl<T, I, P> Cirque<T, I, P> {
             .for_each(|(value, _)| dispose(either::Left(value)));
     }
 
+    /// Number of values allocated by this `Cirque`.
+    /// Values are reused once frame they were used in is complete,
+    /// so it doesn't exceed number of frames in flight when one value is used per frame.
+    pub fn allocated(&self) -> usize {
+        self.counter
+    }
+
     /// Get `CirqueRef` for specified frames range.
     /// Allocate new instance in initial state if no ready values exist.
     pub fn get<B: rendy_core::hal::Backend>(
@@ -156,9 +163,24 @@ impl<T, I, P> Cirque<T, I, P> {
         frames: &Frames<B>,
         alloc: impl FnOnce() -> I,
         complete: impl Fn(P) -> T,
+    ) -> CirqueRef<'_, T, I, P> {
+        self.get_with(
+            |frame| frames.is_complete(frame),
+            frames.next(),
+            alloc,
+            complete,
+        )
+    }
+
+    fn get_with(
+        &mut self,
+        is_complete: impl Fn(Frame) -> bool,
+        next: Frame,
+        alloc: impl FnOnce() -> I,
+        complete: impl Fn(P) -> T,
     ) -> CirqueRef<'_, T, I, P> {
         while let Some((value, index, frame)) = self.pending.pop_front() {
-            if frames.is_complete(frame) {
+            if is_complete(frame) {
                 self.ready.push_back((complete(value), index));
             } else {
                 self.pending.push_front((value, index, frame));
@@ -170,7 +192,7 @@ impl<T, I, P> Cirque<T, I, P> {
                 relevant: relevant::Relevant,
                 cirque: self,
                 value,
-                frame: frames.next(),
+                frame: next,
                 index,
             })
         } else {
@@ -182,7 +204,7 @@ impl<T, I, P> Cirque<T, I, P> {
                 index,
                 cirque: self,
                 value,
-                frame: frames.next(),
+                frame: next,
             })
         }
     }
@@ -205,3 +227,38 @@ impl<T, I, P> Default for DependentCirque<T, I, P> {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use {super::Cirque, crate::frame::Frame};
+
+    #[test]
+    fn values_are_reused_after_frame_completes() {
+        const FRAMES_IN_FLIGHT: u64 = 3;
+
+        let mut cirque = Cirque::<usize>::new();
+        let mut allocated = 0;
+
+        for frame in 0..100u64 {
+            // Frames older than `FRAMES_IN_FLIGHT` are complete.
+            let is_complete = |f: Frame| f.index() + FRAMES_IN_FLIGHT <= frame;
+            cirque
+                .get_with(
+                    is_complete,
+                    Frame::with_index(frame),
+                    || {
+                        allocated += 1;
+                        allocated
+                    },
+                    |value| value,
+                )
+                .or_reset(|value| value)
+                .init(|value| value)
+                .finish(|value| value);
+        }
+
+        assert_eq!(allocated, FRAMES_IN_FLIGHT as usize);
+        assert_eq!(cirque.allocated(), FRAMES_IN_FLIGHT as usize);
+        cirque.dispose(|_| {});
+    }
+}
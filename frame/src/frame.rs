@@ -67,11 +67,28 @@ impl FramesRange {
     }
 }
 
+/// Pop frames from the front of `pending` while `check` reports them complete.
+/// Stops at the first frame still in progress or the first error.
+fn pop_complete<F, E>(
+    pending: &mut std::collections::VecDeque<F>,
+    mut check: impl FnMut(&mut F) -> Result<bool, E>,
+    mut free: impl FnMut(F),
+) -> Result<(), E> {
+    while let Some(frame) = pending.front_mut() {
+        if !check(frame)? {
+            break;
+        }
+        free(pending.pop_front().unwrap());
+    }
+    Ok(())
+}
+
 /// Timeline of frames, complete, pending and next.
 #[derive(Debug)]
 pub struct Frames<B: rendy_core::hal::Backend> {
     pending: std::collections::VecDeque<Fences<B>>,
     next: u64,
+    node_timings: std::collections::HashMap<usize, u64>,
 }
 
 impl<B> Frames<B>
@@ -83,6 +100,7 @@ where
         Frames {
             pending: Default::default(),
             next: 0,
+            node_timings: Default::default(),
         }
     }
 
@@ -111,6 +129,18 @@ where
         self.complete_upper_bound() > frame.index
     }
 
+    /// Get fences of submitted frame that is not complete yet.
+    /// Returns `None` if frame is complete or wasn't submitted.
+    pub fn fences(&self, frame: Frame) -> Option<&Fences<B>> {
+        let complete_upper_bound = self.complete_upper_bound();
+        if frame.index < complete_upper_bound || frame.index >= self.next {
+            None
+        } else {
+            self.pending
+                .get((frame.index - complete_upper_bound) as usize)
+        }
+    }
+
     /// Check if frame with specified index is complete.
     pub fn complete(&self, frame: Frame) -> Option<CompleteFrame> {
         if self.complete_upper_bound() > frame.index {
@@ -137,9 +167,25 @@ where
         factory: &Factory<B>,
         free: impl FnMut(Fences<B>),
     ) -> CompleteFrame {
+        self.try_wait_complete(target, factory, free)
+            .expect("Failed to wait for frame fences")
+    }
+
+    /// Wait for completion of the frame.
+    /// Returns error instead of panicking if waiting fails, e.g. when device is lost.
+    ///
+    /// # Panics
+    ///
+    /// This function will panic if `target` is greater than or equal to next frame.
+    pub fn try_wait_complete(
+        &mut self,
+        target: Frame,
+        factory: &Factory<B>,
+        free: impl FnMut(Fences<B>),
+    ) -> Result<CompleteFrame, rendy_core::hal::device::OomOrDeviceLost> {
         assert!(target.index <= self.next);
         if let Some(complete) = self.complete(target) {
-            complete
+            Ok(complete)
         } else {
             // n - p <= t
             // p - n + t + 1 >= 1
@@ -149,15 +195,36 @@ where
                 self.pending.iter_mut().take(count).flatten(),
                 rendy_core::hal::device::WaitFor::All,
                 !0,
-            );
-            assert_eq!(ready, Ok(true));
+            )?;
+            assert!(ready, "Infinite wait for fences timed out");
             self.pending.drain(..count).for_each(free);
-            CompleteFrame {
+            Ok(CompleteFrame {
                 index: target.index,
-            }
+            })
         }
     }
 
+    /// Complete frames the device has finished without waiting.
+    /// Frames are checked in order until the first one still in progress.
+    /// Returns upper bound of complete frames.
+    ///
+    /// Returns error if checking fences fails, e.g. when device is lost.
+    /// Frames found complete before the failure are freed.
+    pub fn poll_complete(
+        &mut self,
+        factory: &Factory<B>,
+        free: impl FnMut(Fences<B>),
+    ) -> Result<u64, rendy_core::hal::device::OomOrDeviceLost> {
+        pop_complete(
+            &mut self.pending,
+            |fences| {
+                factory.wait_for_fences(fences.iter_mut(), rendy_core::hal::device::WaitFor::All, 0)
+            },
+            free,
+        )?;
+        Ok(self.complete_upper_bound())
+    }
+
     /// Dispose of the `Frames`
     pub fn dispose(mut self, factory: &mut Factory<B>) {
         let ready = factory.wait_for_fences(
@@ -165,7 +232,14 @@ where
             rendy_core::hal::device::WaitFor::All,
             !0,
         );
-        assert_eq!(ready, Ok(true));
+        match ready {
+            Ok(ready) => assert!(ready, "Infinite wait for fences timed out"),
+            // Commands of lost device are considered complete, fences can be destroyed.
+            Err(rendy_core::hal::device::OomOrDeviceLost::DeviceLost(_)) => {
+                log::warn!("Device lost while waiting for frames to complete")
+            }
+            Err(err) => panic!("Failed to wait for frame fences: {:?}", err),
+        }
 
         self.pending
             .drain(..)
@@ -173,6 +247,23 @@ where
             .for_each(|fence| factory.destroy_fence(fence));
     }
 
+    /// Get GPU time in nanoseconds each node took in the latest frame with resolved timestamps.
+    /// Keys are node indices in order nodes were added to the graph.
+    ///
+    /// Empty unless graph was built with node timings enabled
+    /// and device supports timestamp queries.
+    pub fn node_timings(&self) -> &std::collections::HashMap<usize, u64> {
+        &self.node_timings
+    }
+
+    /// Replace node timings with GPU times of the frame resolved by graph.
+    /// Nodes without resolved time in that frame are removed.
+    #[doc(hidden)]
+    pub fn set_node_timings(&mut self, timings: impl IntoIterator<Item = (usize, u64)>) {
+        self.node_timings.clear();
+        self.node_timings.extend(timings);
+    }
+
     /// Get range of frame indices in this form:
     /// `upper bound of finished frames .. next frame`.
     pub fn range(&self) -> FramesRange {
@@ -182,3 +273,51 @@ where
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use {super::pop_complete, std::collections::VecDeque};
+
+    #[test]
+    fn polling_stops_at_frame_in_progress() {
+        // Each frame is the number of polls left until its fences are signaled.
+        let mut pending: VecDeque<u32> = vec![0, 0, 2, 0].into();
+        let mut poll = |pending: &mut VecDeque<u32>| {
+            let mut freed = 0;
+            pop_complete(
+                pending,
+                |left| match left {
+                    0 => Ok::<_, ()>(true),
+                    left => {
+                        *left -= 1;
+                        Ok(false)
+                    }
+                },
+                |_| freed += 1,
+            )
+            .unwrap();
+            freed
+        };
+
+        // Frame 2 is still in progress and frame 3 behind it is not completed out of order.
+        assert_eq!(poll(&mut pending), 2);
+        assert_eq!(pending, vec![1, 0]);
+        assert_eq!(poll(&mut pending), 0);
+        assert_eq!(poll(&mut pending), 2);
+        assert!(pending.is_empty());
+    }
+
+    #[test]
+    fn fence_error_is_returned() {
+        let mut pending: VecDeque<u32> = vec![0, 1].into();
+        let mut freed = Vec::new();
+        let result = pop_complete(
+            &mut pending,
+            |&mut frame| if frame == 0 { Ok(true) } else { Err("lost") },
+            |frame| freed.push(frame),
+        );
+        assert_eq!(result, Err("lost"));
+        assert_eq!(freed, vec![0]);
+        assert_eq!(pending, vec![1]);
+    }
+}
@@ -12,8 +12,11 @@
 )]
 use rendy_command as command;
 use rendy_factory as factory;
+use rendy_memory as memory;
+use rendy_resource as resource;
 
 pub mod cirque;
 mod frame;
+mod scratch;
 
-pub use crate::frame::*;
+pub use crate::{frame::*, scratch::*};
@@ -0,0 +1,246 @@
+//! Per-frame scratch memory.
+
+use {
+    crate::{
+        factory::{Factory, UploadError},
+        frame::Frames,
+        memory::Dynamic,
+        resource::{Buffer, BufferInfo, Escape},
+    },
+    std::{collections::VecDeque, ops::Range},
+};
+
+/// Range of the scratch buffer written by `ScratchAllocator`.
+#[derive(Debug)]
+pub struct ScratchRange<'a, B: rendy_core::hal::Backend> {
+    /// Buffer that contains the data.
+    pub buffer: &'a Buffer<B>,
+
+    /// Range of the buffer with the data.
+    pub range: Range<u64>,
+}
+
+/// Scratch chunks used by one frame.
+#[derive(Debug)]
+struct FrameChunks<C> {
+    frame: u64,
+    chunks: Vec<C>,
+}
+
+/// Chunks of current, pending and complete frames.
+#[derive(Debug)]
+struct Chunks<C> {
+    current: Option<FrameChunks<C>>,
+    offset: u64,
+    pending: VecDeque<FrameChunks<C>>,
+    free: Vec<C>,
+}
+
+impl<C> Chunks<C> {
+    fn new() -> Self {
+        Chunks {
+            current: None,
+            offset: 0,
+            pending: VecDeque::new(),
+            free: Vec::new(),
+        }
+    }
+
+    fn len(&self) -> usize {
+        self.current
+            .as_ref()
+            .map_or(0, |current| current.chunks.len())
+            + self
+                .pending
+                .iter()
+                .map(|pending| pending.chunks.len())
+                .sum::<usize>()
+            + self.free.len()
+    }
+
+    /// Move chunks of the frame that was submitted to pending
+    /// and chunks of complete frames to free list.
+    fn reclaim(&mut self, next: u64, complete_upper_bound: u64) {
+        if self.current.as_ref().map_or(false, |c| c.frame != next) {
+            self.pending.push_back(self.current.take().unwrap());
+        }
+
+        while let Some(pending) = self.pending.front() {
+            if pending.frame >= complete_upper_bound {
+                break;
+            }
+            let pending = self.pending.pop_front().unwrap();
+            log::trace!("Reclaim scratch chunks of frame {}", pending.frame);
+            self.free.extend(pending.chunks);
+        }
+    }
+
+    /// Allocate `size` bytes aligned to `align` for `frame`.
+    /// Takes free chunk that fits `size` bytes or creates new one
+    /// if last chunk of the frame has no room left.
+    fn allocate<E>(
+        &mut self,
+        frame: u64,
+        size: u64,
+        align: u64,
+        chunk_size: impl Fn(&C) -> u64,
+        create: impl FnOnce(u64) -> Result<C, E>,
+    ) -> Result<(&mut C, u64), E> {
+        debug_assert!(align.is_power_of_two());
+        let offset = (self.offset + align - 1) & !(align - 1);
+        let fits = match &self.current {
+            Some(current) if current.frame == frame => current
+                .chunks
+                .last()
+                .map_or(false, |chunk| offset + size <= chunk_size(chunk)),
+            _ => false,
+        };
+
+        let offset = if fits {
+            offset
+        } else {
+            let free = self.free.iter().position(|chunk| chunk_size(chunk) >= size);
+            let chunk = match free {
+                Some(index) => self.free.swap_remove(index),
+                None => {
+                    log::trace!("Create scratch chunk");
+                    create(size)?
+                }
+            };
+            let current = self.current.get_or_insert_with(|| FrameChunks {
+                frame,
+                chunks: Vec::new(),
+            });
+            current.chunks.push(chunk);
+            0
+        };
+
+        self.offset = offset + size;
+        let chunk = self
+            .current
+            .as_mut()
+            .and_then(|current| current.chunks.last_mut())
+            .expect("Chunk was just ensured");
+        Ok((chunk, offset))
+    }
+}
+
+/// Bump allocator for data that lives for a single frame, like per-frame uniforms.
+///
+/// Data is written into host-visible chunks one after another.
+/// If a frame overflows current chunk, a fresh one is taken.
+/// Chunks are reclaimed once the frame that used them is complete
+/// and reused by following frames, so memory doesn't grow unbounded.
+///
+/// Allocator must be dropped only after all frames that used it are complete.
+#[derive(Debug)]
+pub struct ScratchAllocator<B: rendy_core::hal::Backend> {
+    chunk_size: u64,
+    usage: rendy_core::hal::buffer::Usage,
+    chunks: Chunks<Escape<Buffer<B>>>,
+}
+
+impl<B> ScratchAllocator<B>
+where
+    B: rendy_core::hal::Backend,
+{
+    /// Create new scratch allocator.
+    /// Chunks are created with `chunk_size` bytes and `usage`.
+    pub fn new(chunk_size: u64, usage: rendy_core::hal::buffer::Usage) -> Self {
+        ScratchAllocator {
+            chunk_size,
+            usage,
+            chunks: Chunks::new(),
+        }
+    }
+
+    /// Number of chunks created by this allocator.
+    pub fn chunks(&self) -> usize {
+        self.chunks.len()
+    }
+
+    /// Write `data` into scratch memory of the next frame.
+    /// Returned range is valid until the frame is complete.
+    pub fn upload<T>(
+        &mut self,
+        factory: &Factory<B>,
+        frames: &Frames<B>,
+        align: u64,
+        data: &[T],
+    ) -> Result<ScratchRange<'_, B>, UploadError>
+    where
+        T: 'static + Copy,
+    {
+        let size = (data.len() * std::mem::size_of::<T>()) as u64;
+        let frame = frames.next().index();
+
+        self.chunks.reclaim(frame, frames.complete_upper_bound());
+
+        let (chunk_size, usage) = (self.chunk_size, self.usage);
+        let (chunk, offset) = self.chunks.allocate(
+            frame,
+            size,
+            align,
+            |chunk| chunk.size(),
+            |size| {
+                factory
+                    .create_buffer(
+                        BufferInfo {
+                            size: chunk_size.max(size),
+                            usage,
+                        },
+                        Dynamic,
+                    )
+                    .map_err(UploadError::Create)
+            },
+        )?;
+
+        unsafe {
+            // Chunk is not used by any pending frame.
+            factory
+                .upload_visible_buffer(chunk, offset, data)
+                .map_err(UploadError::Map)?;
+        }
+
+        Ok(ScratchRange {
+            buffer: &*chunk,
+            range: offset..offset + size,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn allocate(chunks: &mut Chunks<u64>, frame: u64, size: u64) -> (u64, u64) {
+        let (chunk, offset) = chunks
+            .allocate::<()>(frame, size, 16, |&chunk| chunk, |size| Ok(size.max(256)))
+            .unwrap();
+        (*chunk, offset)
+    }
+
+    #[test]
+    fn frame_data_is_bumped_and_chunks_reused() {
+        let mut chunks = Chunks::new();
+
+        chunks.reclaim(0, 0);
+        assert_eq!(allocate(&mut chunks, 0, 100), (256, 0));
+        assert_eq!(allocate(&mut chunks, 0, 100), (256, 112));
+        assert_eq!(allocate(&mut chunks, 0, 100), (256, 0));
+        assert_eq!(chunks.len(), 2);
+
+        // Frame 0 is still in flight.
+        chunks.reclaim(1, 0);
+        assert_eq!(allocate(&mut chunks, 1, 100), (256, 0));
+        assert_eq!(chunks.len(), 3);
+
+        // Frame 0 is complete, its chunks are reset and reused.
+        chunks.reclaim(2, 1);
+        assert_eq!(chunks.free.len(), 2);
+        assert_eq!(allocate(&mut chunks, 2, 100), (256, 0));
+        assert_eq!(allocate(&mut chunks, 2, 200), (256, 0));
+        assert_eq!(allocate(&mut chunks, 2, 1000), (1000, 0));
+        assert_eq!(chunks.len(), 4);
+    }
+}
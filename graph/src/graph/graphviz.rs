@@ -0,0 +1,188 @@
+//! DOT representation of the graph for debugging synchronization.
+
+use {crate::chain, std::fmt::Write as _};
+
+/// Fill colors of nodes indexed by queue family.
+const FAMILY_COLORS: [&str; 6] = [
+    "lightblue",
+    "palegreen",
+    "lightpink",
+    "khaki",
+    "plum",
+    "lightgray",
+];
+
+type Submission = chain::Submission<chain::Unsynchronized>;
+
+/// Write DOT representation of scheduled `chains`.
+///
+/// Nodes are colored by queue family they are submitted to.
+/// Edge connects two nodes when the second one accesses a resource
+/// in the next link of resource's chain, which means graph synchronizes them.
+/// Edges are labeled with the resource and its state transition.
+pub(super) fn dump(chains: &chain::Chains) -> String {
+    let mut out = String::new();
+    writeln!(out, "digraph rendy {{").unwrap();
+    writeln!(out, "    node [shape=box, style=filled];").unwrap();
+
+    let submissions: Vec<&Submission> = chains
+        .schedule
+        .iter()
+        .flat_map(|family| family.iter())
+        .flat_map(|queue| queue.iter())
+        .collect();
+
+    for submission in &submissions {
+        let sid = submission.id();
+        writeln!(
+            out,
+            "    n{} [label=\"node {}\\nfamily {} queue {}\", fillcolor={}];",
+            submission.node(),
+            submission.node(),
+            sid.family().0,
+            sid.queue().index(),
+            FAMILY_COLORS[sid.family().0 % FAMILY_COLORS.len()],
+        )
+        .unwrap();
+    }
+
+    let mut buffers: Vec<_> = chains.buffers.iter().collect();
+    buffers.sort_by_key(|&(id, _)| *id);
+    for (&id, buffer) in buffers {
+        write_edges(
+            &mut out,
+            &submissions,
+            buffer.links(),
+            |submission| submission.try_buffer_link_index(id),
+            |prev, next| {
+                format!(
+                    "buffer {}\\n{:?} -> {:?}",
+                    id.0,
+                    prev.access(),
+                    next.access()
+                )
+            },
+        );
+    }
+
+    let mut images: Vec<_> = chains.images.iter().collect();
+    images.sort_by_key(|&(id, _)| *id);
+    for (&id, image) in images {
+        write_edges(
+            &mut out,
+            &submissions,
+            image.links(),
+            |submission| submission.try_image_link_index(id),
+            |prev, next| {
+                format!(
+                    "image {}\\n{:?} {:?} -> {:?} {:?}",
+                    id.0,
+                    prev.access(),
+                    prev.layout(),
+                    next.access(),
+                    next.layout(),
+                )
+            },
+        );
+    }
+
+    writeln!(out, "}}").unwrap();
+    out
+}
+
+/// Write edges between nodes of consecutive links.
+fn write_edges<R: chain::Resource>(
+    out: &mut String,
+    submissions: &[&Submission],
+    links: &[chain::Link<R>],
+    link_index: impl Fn(&Submission) -> Option<usize>,
+    label: impl Fn(&chain::Link<R>, &chain::Link<R>) -> String,
+) {
+    let mut nodes = vec![Vec::new(); links.len()];
+    for submission in submissions {
+        if let Some(index) = link_index(submission) {
+            nodes[index].push(submission.node());
+        }
+    }
+
+    for (index, pair) in links.windows(2).enumerate() {
+        let mut text = label(&pair[0], &pair[1]);
+        if pair[0].family() != pair[1].family() {
+            // Ownership transfer between families.
+            write!(
+                text,
+                "\\nfamily {} -> {}",
+                pair[0].family().0,
+                pair[1].family().0
+            )
+            .unwrap();
+        }
+
+        for &from in &nodes[index] {
+            for &to in &nodes[index + 1] {
+                writeln!(out, "    n{} -> n{} [label=\"{}\"];", from, to, text).unwrap();
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use {super::*, rendy_core::hal, std::collections::HashMap};
+
+    fn image_node(
+        id: usize,
+        dependencies: Vec<usize>,
+        access: hal::image::Access,
+        layout: hal::image::Layout,
+        usage: hal::image::Usage,
+    ) -> chain::Node {
+        let mut images = HashMap::new();
+        images.insert(
+            chain::Id(0),
+            chain::ImageState {
+                access,
+                layout,
+                stages: hal::pso::PipelineStage::BOTTOM_OF_PIPE,
+                usage,
+            },
+        );
+        chain::Node {
+            id,
+            family: hal::queue::QueueFamilyId(0),
+            dependencies,
+            buffers: HashMap::new(),
+            images,
+        }
+    }
+
+    #[test]
+    fn edges_follow_image_transitions() {
+        let nodes = vec![
+            image_node(
+                0,
+                vec![],
+                hal::image::Access::COLOR_ATTACHMENT_WRITE,
+                hal::image::Layout::ColorAttachmentOptimal,
+                hal::image::Usage::COLOR_ATTACHMENT,
+            ),
+            image_node(
+                1,
+                vec![0],
+                hal::image::Access::TRANSFER_READ,
+                hal::image::Layout::TransferSrcOptimal,
+                hal::image::Usage::TRANSFER_SRC,
+            ),
+        ];
+
+        let dot = dump(&chain::collect(nodes, |_| 1));
+
+        assert!(dot.starts_with("digraph rendy {"));
+        assert!(dot.contains("n0 [label=\"node 0\\nfamily 0 queue 0\", fillcolor=lightblue];"));
+        assert!(dot.contains("n1 [label=\"node 1\\nfamily 0 queue 0\", fillcolor=lightblue];"));
+        assert!(dot.contains("n0 -> n1 [label=\"image 0\\n"));
+        assert!(dot.contains("ColorAttachmentOptimal -> "));
+        assert!(dot.contains("TransferSrcOptimal\"];"));
+        assert!(!dot.contains("n1 -> n0"));
+    }
+}
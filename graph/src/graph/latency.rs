@@ -0,0 +1,97 @@
+//! Order of node runs for `LatencyMode`.
+
+/// Trade-off between throughput and input latency of `Graph::run`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum LatencyMode {
+    /// Nodes are run in schedule order.
+    /// Nodes that acquire swapchain images may do so early in the frame
+    /// and then wait for the image while recording is still possible for other nodes.
+    Throughput,
+
+    /// Nodes that acquire swapchain images, and nodes that must follow them,
+    /// are run after all other nodes are recorded and submitted.
+    /// This shortens time between sampling input for the frame and displaying it,
+    /// but the CPU may wait for the image at the end of `Graph::run`
+    /// instead of recording independent work meanwhile,
+    /// which can lower frame rate for CPU-bound frames.
+    LowLatency,
+}
+
+impl Default for LatencyMode {
+    fn default() -> Self {
+        LatencyMode::Throughput
+    }
+}
+
+/// Submission constraints for reordering.
+#[derive(Clone, Debug, Default)]
+pub(crate) struct SubmissionOrder {
+    /// Submission acquires swapchain image.
+    pub(crate) acquires: bool,
+
+    /// Earlier submissions in schedule order that must be submitted before this one.
+    /// Those it waits for and those accessing same resources or aliased memory.
+    pub(crate) after: Vec<usize>,
+}
+
+/// Order of submissions for `LatencyMode::LowLatency`.
+/// Submissions that acquire an image or must follow one that does are moved to the end,
+/// relative order is preserved otherwise.
+pub(crate) fn low_latency_order(submissions: &[SubmissionOrder]) -> Vec<usize> {
+    let mut deferred = vec![false; submissions.len()];
+    for (index, submission) in submissions.iter().enumerate() {
+        deferred[index] =
+            submission.acquires || submission.after.iter().any(|&before| deferred[before]);
+    }
+
+    let (mut order, late): (Vec<usize>, Vec<usize>) =
+        (0..submissions.len()).partition(|&index| !deferred[index]);
+    order.extend(late);
+    order
+}
+
+/// Check if submission at `position` of `order` is the last one on its queue.
+pub(crate) fn last_in_queue<Q: PartialEq>(order: &[usize], queues: &[Q], position: usize) -> bool {
+    let queue = &queues[order[position]];
+    order[position + 1..]
+        .iter()
+        .all(|&index| queues[index] != *queue)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn submission(acquires: bool, after: Vec<usize>) -> SubmissionOrder {
+        SubmissionOrder { acquires, after }
+    }
+
+    #[test]
+    fn acquire_follows_independent_recording() {
+        // Scene node renders to surface, then UI is drawn over it.
+        // Shadows and particle simulation come later in schedule but don't depend on it.
+        let submissions = [
+            submission(false, vec![]),
+            submission(true, vec![0]),
+            submission(false, vec![1]),
+            submission(false, vec![0]),
+            submission(false, vec![]),
+        ];
+        let order = low_latency_order(&submissions);
+        assert_eq!(order, vec![0, 3, 4, 1, 2]);
+
+        // Without acquiring submissions order is unchanged.
+        let submissions = [submission(false, vec![]), submission(false, vec![0])];
+        assert_eq!(low_latency_order(&submissions), vec![0, 1]);
+    }
+
+    #[test]
+    fn fence_goes_to_last_submission_on_queue() {
+        let queues = [0, 0, 1, 0];
+        let order = [0, 2, 3, 1];
+        assert!(!last_in_queue(&order, &queues, 0));
+        assert!(last_in_queue(&order, &queues, 1));
+        assert!(!last_in_queue(&order, &queues, 2));
+        assert!(last_in_queue(&order, &queues, 3));
+    }
+}
@@ -1,28 +1,51 @@
+mod graphviz;
+mod latency;
+mod occlusion;
+mod pacing;
+mod report;
+mod timings;
+
 use {
+    self::{
+        latency::{last_in_queue, low_latency_order, SubmissionOrder},
+        occlusion::GraphOcclusion,
+        pacing::{FramePacer, SystemClock},
+        timings::{NodeTimings, TimingsError},
+    },
     crate::{
         chain,
-        command::{Families, FamilyId, QueueId},
+        command::{Capability, Families, FamilyId, Graphics, QueueId},
         core::{device_owned, DeviceId},
-        factory::Factory,
+        descriptor::{DescriptorRanges, TransientDescriptorPool},
+        factory::{BufferState, DeviceIdle, Factory, ImageState, ImageStateOrLayout, UploadError},
         frame::{Fences, Frame, Frames},
-        memory::Data,
+        memory::{Block as _, Data},
         node::{
-            BufferBarrier, DynNode, ImageBarrier, NodeBuffer, NodeBuildError, NodeBuilder,
-            NodeImage,
+            BufferAccess, BufferBarrier, DynNode, ImageAccess, ImageBarrier, NodeBuffer,
+            NodeBuildError, NodeBuilder, NodeClear, NodeImage, PresentResult,
         },
         resource::{
-            Buffer, BufferCreationError, BufferInfo, Handle, Image, ImageCreationError, ImageInfo,
+            Buffer, BufferCreationError, BufferInfo, Escape, Handle, Image, ImageCreationError,
+            ImageInfo, ImageView, ImageViewCreationError, ImageViewInfo, Sampler,
         },
-        BufferId, ImageId, NodeId,
+        BufferId, ImageId, ImageViewId, NodeId, SurfaceId,
     },
-    rendy_core::hal::{queue::QueueFamilyId, Backend},
+    rendy_core::hal::{device::Device as _, queue::QueueFamilyId, Backend},
     thread_profiler::profile_scope,
 };
 
+pub use self::{
+    latency::LatencyMode,
+    occlusion::OcclusionQueries,
+    report::{GraphResource, NodeResourceUsage, ResourceUsage},
+};
+
 #[derive(Debug)]
 struct GraphNode<B: Backend, T: ?Sized> {
     node: Box<dyn DynNode<B, T>>,
     queue: (usize, usize),
+    buffers: Vec<BufferId>,
+    images: Vec<ImageId>,
 }
 
 /// Graph that renders whole frame.
@@ -31,26 +54,171 @@ pub struct Graph<B: Backend, T: ?Sized> {
     device: DeviceId,
     nodes: Vec<GraphNode<B, T>>,
     schedule: chain::Schedule<chain::SyncData<usize, usize>>,
+    chains: chain::Chains,
     semaphores: Vec<B::Semaphore>,
     frames: Frames<B>,
-    fences: Vec<Fences<B>>,
     inflight: u32,
     ctx: GraphContext<B>,
+    timings: Option<NodeTimings<B>>,
+    occlusion: Option<GraphOcclusion<B>>,
+    relative_images: Vec<ImageId>,
+    surface_images: Vec<(SurfaceId, ImageId)>,
+    /// Index of the node presenting to each surface.
+    surfaces: Vec<usize>,
+    /// Every node submission gets a fence, not only the last one in a queue.
+    fence_every_node: bool,
+    /// Index of each node's fence among fences of the latest frame.
+    node_fences: Vec<Option<usize>>,
+    pacer: FramePacer,
+    gpu_behind: u64,
+    latency_mode: LatencyMode,
+    /// Indices of submissions in schedule order to run with `LatencyMode::LowLatency`.
+    low_latency_order: Vec<usize>,
 }
 
 device_owned!(Graph<B, T: ?Sized>);
 
+/// Generation of the next `GraphBuilder`.
+/// Every builder gets its own, so ids of one graph are not valid in another.
+static NEXT_GENERATION: std::sync::atomic::AtomicU32 = std::sync::atomic::AtomicU32::new(0);
+
+fn next_generation() -> u32 {
+    NEXT_GENERATION.fetch_add(1, std::sync::atomic::Ordering::Relaxed)
+}
+
 /// Error building the graph itself or one of it's nodes.
 #[derive(Debug)]
 pub enum GraphBuildError {
     /// Failed to create a buffer.
     Buffer(BufferCreationError),
+    /// Failed to upload initial data of a buffer or an image.
+    Upload(UploadError),
+    /// Image with initial data is transferred to another queue family between frames.
+    /// Content uploaded before the first frame would not be released by the previous family.
+    InitialData(ImageId),
     /// Failed to create an image.
     Image(ImageCreationError),
+    /// Failed to create an image view.
+    View(ImageViewCreationError),
+    /// Failed to create default sampler for sampled images.
+    Sampler(rendy_core::hal::device::AllocationError),
     /// Failed to create a semaphore.
     Semaphore(rendy_core::hal::device::OutOfMemory),
     /// Failed to build a node.
     Node(NodeBuildError),
+    /// Failed to create a query pool for node timings.
+    QueryPool(rendy_core::hal::query::CreationError),
+    /// Failed to create a command pool for node timings.
+    CommandPool(rendy_core::hal::device::OutOfMemory),
+    /// Graph wiring is invalid. See `GraphBuilder::validate`.
+    Validation(Vec<GraphError>),
+}
+
+/// Problem in graph wiring found by `GraphBuilder::validate`.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum GraphError {
+    /// Nodes depend on each other in a loop.
+    /// Each node in the list depends on the next one and the last one on the first.
+    Cycle(Vec<NodeId>),
+    /// Buffer is not accessed by any node.
+    UnusedBuffer(BufferId),
+    /// Image is not accessed by any node.
+    UnusedImage(ImageId),
+    /// Image with clear value is accessed first by multiple nodes
+    /// that are not ordered by dependencies. Each of them may clear it.
+    MultipleClears {
+        /// Cleared image.
+        image: ImageId,
+        /// Nodes that may clear the image.
+        nodes: Vec<NodeId>,
+    },
+    /// No queue family provides capability the node requires.
+    NoFamily(NodeId),
+    /// Graph is built with zero frames in flight.
+    NoFramesInFlight,
+    /// Node accesses buffer created by another graph builder.
+    ForeignBuffer {
+        /// Node that accesses the buffer.
+        node: NodeId,
+        /// Buffer of another graph.
+        buffer: BufferId,
+    },
+    /// Node accesses image created by another graph builder.
+    ForeignImage {
+        /// Node that accesses the image.
+        node: NodeId,
+        /// Image of another graph.
+        image: ImageId,
+    },
+    /// Node of another graph builder is used as dependency
+    /// or in semaphore dependency.
+    ForeignNode(NodeId),
+}
+
+impl GraphError {
+    /// Unused resources are wasteful but don't break the graph.
+    fn is_fatal(&self) -> bool {
+        match self {
+            GraphError::UnusedBuffer(_) | GraphError::UnusedImage(_) => false,
+            _ => true,
+        }
+    }
+}
+
+impl std::fmt::Display for GraphError {
+    fn fmt(&self, fmt: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            GraphError::Cycle(nodes) => write!(fmt, "Nodes {:?} depend on each other", nodes),
+            GraphError::UnusedBuffer(id) => write!(fmt, "Buffer {:?} is not used by any node", id),
+            GraphError::UnusedImage(id) => write!(fmt, "Image {:?} is not used by any node", id),
+            GraphError::MultipleClears { image, nodes } => write!(
+                fmt,
+                "Image {:?} may be cleared by any of unordered nodes {:?}",
+                image, nodes
+            ),
+            GraphError::NoFamily(id) => {
+                write!(fmt, "No queue family is suitable for node {:?}", id)
+            }
+            GraphError::NoFramesInFlight => write!(fmt, "Graph needs at least one frame in flight"),
+            GraphError::ForeignBuffer { node, buffer } => write!(
+                fmt,
+                "Node {:?} accesses buffer {:?} of another graph",
+                node, buffer
+            ),
+            GraphError::ForeignImage { node, image } => write!(
+                fmt,
+                "Node {:?} accesses image {:?} of another graph",
+                node, image
+            ),
+            GraphError::ForeignNode(id) => write!(fmt, "Node {:?} belongs to another graph", id),
+        }
+    }
+}
+
+impl std::error::Error for GraphError {}
+
+/// Error resizing the graph.
+#[derive(Debug)]
+pub enum GraphResizeError {
+    /// Failed to wait for device idle.
+    OutOfMemory(rendy_core::hal::device::OutOfMemory),
+    /// Failed to create an image.
+    Image(ImageCreationError),
+    /// Failed to create an image view.
+    View(ImageViewCreationError),
+    /// Failed to update a node.
+    Node(NodeBuildError),
+    /// Node doesn't support resizing.
+    Unsupported(NodeId),
+}
+
+impl From<TimingsError> for GraphBuildError {
+    fn from(error: TimingsError) -> Self {
+        match error {
+            TimingsError::QueryPool(error) => GraphBuildError::QueryPool(error),
+            TimingsError::CommandPool(error) => GraphBuildError::CommandPool(error),
+        }
+    }
 }
 
 /// Graphics context contains all transient resources managed by graph.
@@ -63,8 +231,17 @@ pub struct GraphContext<B: Backend> {
             Option<rendy_core::hal::command::ClearValue>,
         )>,
     >,
+    views: Vec<(
+        ImageId,
+        rendy_core::hal::image::SubresourceRange,
+        Handle<ImageView<B>>,
+    )>,
     /// Number of potential frames in flight
     pub frames_in_flight: u32,
+    occlusion: Option<OcclusionQueries<B>>,
+    default_sampler: Option<Handle<Sampler<B>>>,
+    descriptors: std::sync::Mutex<TransientDescriptorPool<B>>,
+    generation: u32,
 }
 
 impl<B: Backend> GraphContext<B> {
@@ -72,8 +249,10 @@ impl<B: Backend> GraphContext<B> {
         factory: &Factory<B>,
         chains: &chain::Chains,
         buffers: impl IntoIterator<Item = &'a BufferInfo>,
-        images: impl IntoIterator<Item = &'a (ImageInfo, Option<rendy_core::hal::command::ClearValue>)>,
+        images: &[(ImageInfo, Option<rendy_core::hal::command::ClearValue>)],
+        aliased: &[Vec<usize>],
         frames_in_flight: u32,
+        generation: u32,
     ) -> Result<Self, GraphBuildError> {
         profile_scope!("alloc");
 
@@ -89,7 +268,8 @@ impl<B: Backend> GraphContext<B> {
                         factory
                             .create_buffer(
                                 BufferInfo {
-                                    usage: buffer.usage(),
+                                    // Buffers with initial data are also transfer destinations.
+                                    usage: buffer.usage() | info.usage,
                                     ..info.clone()
                                 },
                                 Data,
@@ -101,43 +281,156 @@ impl<B: Backend> GraphContext<B> {
             .collect::<Result<_, _>>()
             .map_err(GraphBuildError::Buffer)?;
 
-        log::trace!("Allocate images");
-        let images: Vec<Option<(Handle<Image<B>>, _)>> = images
-            .into_iter()
-            .enumerate()
-            .map(|(index, (info, clear))| {
-                chains
-                    .images
-                    .get(&chain::Id(index))
-                    .map(|image| {
-                        factory
-                            .create_image(
-                                ImageInfo {
-                                    usage: image.usage(),
-                                    ..info.clone()
-                                },
-                                Data,
-                            )
-                            .map(|image| Some((image.into(), *clear)))
-                    })
-                    .unwrap_or(Ok(None))
+        let image_info = |index: usize| {
+            chains.images.get(&chain::Id(index)).map(|image| ImageInfo {
+                // Images with initial data are also transfer destinations.
+                usage: image.usage() | images[index].0.usage,
+                ..images[index].0.clone()
             })
-            .collect::<Result<_, _>>()
-            .map_err(GraphBuildError::Image)?;
+        };
+
+        let mut allocated: Vec<Option<(Handle<Image<B>>, _)>> =
+            images.iter().map(|_| None).collect();
+
+        for group in aliased {
+            log::trace!("Allocate aliased images {:?}", group);
+            let created = unsafe {
+                // Aliased images are never accessed at the same time.
+                factory.create_aliased_images(
+                    group.iter().map(|&index| image_info(index).unwrap()),
+                    Data,
+                )
+            };
+            match created {
+                Ok(created) => {
+                    for (&index, image) in group.iter().zip(created) {
+                        allocated[index] = Some((image.into(), images[index].1));
+                    }
+                }
+                Err(err) => {
+                    // Aliasing barriers only add synchronization, images can be allocated separately.
+                    log::warn!("Failed to alias images {:?}: {:?}", group, err);
+                }
+            }
+        }
+
+        log::trace!("Allocate images");
+        for (index, slot) in allocated.iter_mut().enumerate() {
+            if slot.is_some() {
+                continue;
+            }
+            if let Some(info) = image_info(index) {
+                let image = factory
+                    .create_image(info, Data)
+                    .map_err(GraphBuildError::Image)?;
+                *slot = Some((image.into(), images[index].1));
+            }
+        }
 
         Ok(Self {
             buffers,
-            images,
+            images: allocated,
+            views: Vec::new(),
             frames_in_flight,
+            occlusion: None,
+            default_sampler: None,
+            descriptors: std::sync::Mutex::new(TransientDescriptorPool::new(frames_in_flight)),
+            generation,
         })
     }
 
+    /// Destroy descriptor pools and release resources of the context.
+    /// Must be called after waiting for device idle.
+    unsafe fn dispose(self, factory: &Factory<B>) {
+        self.descriptors
+            .into_inner()
+            .unwrap()
+            .dispose(factory.device());
+    }
+
+    /// Create views for image subresources accessed by nodes.
+    /// Same subresource used by multiple nodes shares one view.
+    fn create_views(
+        &mut self,
+        factory: &Factory<B>,
+        subresources: impl IntoIterator<Item = (ImageId, rendy_core::hal::image::SubresourceRange)>,
+    ) -> Result<(), ImageViewCreationError> {
+        profile_scope!("create_views");
+
+        for (id, range) in subresources {
+            if self.find_image_view(id, &range).is_some() {
+                continue;
+            }
+            let image = match self.get_image(id) {
+                Some(image) => image,
+                None => continue,
+            };
+            assert!(
+                range.levels.end <= image.levels() && range.layers.end <= image.layers(),
+                "Subresource {:?} is out of image {:?} bounds",
+                range,
+                id
+            );
+            let view = create_subresource_view(factory, image, range.clone())?;
+            self.views.push((id, range, view.into()));
+        }
+        Ok(())
+    }
+
+    /// Recreate views of the image after it was reallocated.
+    fn recreate_views(
+        &mut self,
+        factory: &Factory<B>,
+        id: ImageId,
+    ) -> Result<(), ImageViewCreationError> {
+        let GraphContext { images, views, .. } = self;
+        let image = match images.get(id.0).and_then(|x| x.as_ref()) {
+            Some((image, _)) => image,
+            None => return Ok(()),
+        };
+
+        for (_, range, view) in views.iter_mut().filter(|(view_id, _, _)| *view_id == id) {
+            *view = create_subresource_view(factory, image, range.clone())?.into();
+        }
+        Ok(())
+    }
+
+    fn find_image_view(
+        &self,
+        id: ImageId,
+        range: &rendy_core::hal::image::SubresourceRange,
+    ) -> Option<ImageViewId> {
+        self.views
+            .iter()
+            .position(|(view_id, view_range, _)| *view_id == id && view_range == range)
+            .map(|index| ImageViewId(index, self.generation))
+    }
+
+    /// Check that id with `generation` was created for this graph.
+    fn owns(&self, generation: u32, id: impl std::fmt::Debug) -> bool {
+        if generation != self.generation {
+            log::error!("{:?} belongs to another graph", id);
+            return false;
+        }
+        true
+    }
+
+    /// Get reference to view of transient image subresource by id.
+    /// Returns `None` for view of another graph.
+    pub fn get_image_view(&self, id: ImageViewId) -> Option<&Handle<ImageView<B>>> {
+        if !self.owns(id.1, id) {
+            return None;
+        }
+        self.views.get(id.0).map(|(_, _, view)| view)
+    }
+
     /// Get reference to transient image by id.
     pub fn get_image(&self, id: ImageId) -> Option<&Handle<Image<B>>> {
         self.get_image_with_clear(id).map(|(i, _)| i)
     }
 
     /// Get reference to transient image and clear value by id.
+    /// Returns `None` for image of another graph.
     pub fn get_image_with_clear(
         &self,
         id: ImageId,
@@ -145,14 +438,55 @@ impl<B: Backend> GraphContext<B> {
         &Handle<Image<B>>,
         Option<rendy_core::hal::command::ClearValue>,
     )> {
+        if !self.owns(id.1, id) {
+            return None;
+        }
         self.images
             .get(id.0)
             .and_then(|x| x.as_ref())
             .map(|&(ref x, ref y)| (&*x, *y))
     }
 
+    /// Get sampler for images added to nodes with `add_sampled_image`.
+    /// Sampler filters linearly and clamps coordinates to edge.
+    /// Available only if at least one node samples an image.
+    pub fn default_sampler(&self) -> Option<&Handle<Sampler<B>>> {
+        self.default_sampler.as_ref()
+    }
+
+    /// Get occlusion queries enabled with `GraphBuilder::with_occlusion_queries`.
+    /// Only nodes on the queue of the first graphics node may use them.
+    pub fn occlusion_queries(&self) -> Option<&OcclusionQueries<B>> {
+        self.occlusion.as_ref()
+    }
+
+    /// Allocate descriptor set used only by the current frame.
+    /// Sets of a frame are recycled at once when the graph starts
+    /// the frame `frames_in_flight` later, so they are never freed.
+    ///
+    /// # Safety
+    ///
+    /// `layout` must be created by the device of `factory`
+    /// and `ranges` must match descriptor numbers of the layout.
+    /// Set must not be used after the frame it was allocated in is complete.
+    pub unsafe fn frame_descriptor_set(
+        &self,
+        factory: &Factory<B>,
+        layout: &B::DescriptorSetLayout,
+        ranges: DescriptorRanges,
+    ) -> Result<B::DescriptorSet, rendy_core::hal::device::OutOfMemory> {
+        self.descriptors
+            .lock()
+            .unwrap()
+            .frame_descriptor_set(factory.device(), layout, ranges)
+    }
+
     /// Get reference to transient buffer by id.
+    /// Returns `None` for buffer of another graph.
     pub fn get_buffer(&self, id: BufferId) -> Option<&Handle<Buffer<B>>> {
+        if !self.owns(id.1, id) {
+            return None;
+        }
         self.buffers.get(id.0).and_then(|x| x.as_ref()).map(|x| &*x)
     }
 }
@@ -164,30 +498,148 @@ where
 {
     /// Perform graph execution.
     /// Run every node of the graph and submit resulting command buffers to the queues.
-    pub fn run(&mut self, factory: &mut Factory<B>, families: &mut Families<B>, aux: &T) {
+    ///
+    /// Returns the worst outcome of presentation among nodes that present to surfaces.
+    /// Frames that failed to present are still submitted
+    /// and swapchains are recreated in the next frame.
+    /// On `PresentResult::NeedsRecreation` the surface may have changed its extent,
+    /// `Graph::resize` should be called if it did.
+    /// On `PresentResult::DeviceLost` nothing is submitted,
+    /// graph should be disposed and recreated along with the factory.
+    ///
+    /// Returns error if device runs out of memory waiting for previous frames
+    /// or acquiring fences for this one, nothing is submitted then either.
+    pub fn run(
+        &mut self,
+        factory: &mut Factory<B>,
+        families: &mut Families<B>,
+        aux: &T,
+    ) -> Result<PresentResult, rendy_core::hal::device::OutOfMemory> {
+        self.run_with_sync(factory, families, aux, &[], &[])
+    }
+
+    /// Perform graph execution synchronized with submissions made outside of the graph,
+    /// e.g. by video decoder or XR runtime.
+    ///
+    /// First submission of the frame waits for `waits`
+    /// and last submission of the frame signals `signals`, in addition to graph's own semaphores.
+    /// Submissions on other queues that don't depend on the first one may start before waits
+    /// are signaled, and ones that the last submission doesn't depend on may complete after
+    /// signals are, so graphs using several queues should make these nodes dependencies.
+    ///
+    /// See `run` for the returned value.
+    /// Waits and signals are not submitted if nothing is, e.g. on `PresentResult::DeviceLost`.
+    pub fn run_with_sync(
+        &mut self,
+        factory: &mut Factory<B>,
+        families: &mut Families<B>,
+        aux: &T,
+        waits: &[(&B::Semaphore, rendy_core::hal::pso::PipelineStage)],
+        signals: &[&B::Semaphore],
+    ) -> Result<PresentResult, rendy_core::hal::device::OutOfMemory> {
         profile_scope!("run");
 
         self.assert_device_owner(factory.device());
 
-        if self.frames.next().index() >= self.inflight as _ {
-            let wait = Frame::with_index(self.frames.next().index() - self.inflight as u64);
-            let ref mut self_fences = self.fences;
-            self.frames.wait_complete(wait, factory, |mut fences| {
-                factory.reset_fences(&mut fences).unwrap();
-                self_fences.push(fences);
+        if factory.is_device_lost() {
+            return Ok(PresentResult::DeviceLost);
+        }
+
+        let polled = self.frames.poll_complete(factory, |fences| {
+            fences
+                .into_iter()
+                .for_each(|fence| factory.release_fence(fence));
+        });
+        let complete = match polled {
+            Ok(complete) => complete,
+            Err(err) => return lost_or_oom(factory, err),
+        };
+        self.gpu_behind = self.frames.next().index() - complete;
+
+        // Resources of the frame `frames_in_flight` ago are reused by this one.
+        // Waiting for it also keeps CPU from submitting more frames than device can drain.
+        if let Some(wait) = throttle_frame(self.frames.next(), self.inflight) {
+            if !self.frames.is_complete(wait) {
+                log::trace!("Device is {} frames behind, wait", self.gpu_behind);
+            }
+            let waited = self.frames.try_wait_complete(wait, factory, |fences| {
+                fences
+                    .into_iter()
+                    .for_each(|fence| factory.release_fence(fence));
             });
+            if let Err(err) = waited {
+                return lost_or_oom(factory, err);
+            }
+        }
+
+        if factory.is_device_lost() {
+            return Ok(PresentResult::DeviceLost);
+        }
+
+        let submissions: smallvec::SmallVec<[_; 32]> = self.schedule.ordered().collect();
+        let queues: smallvec::SmallVec<[_; 32]> = submissions
+            .iter()
+            .map(|submission| submission.id().queue())
+            .collect();
+        let order: smallvec::SmallVec<[usize; 32]> = match self.latency_mode {
+            LatencyMode::Throughput => (0..submissions.len()).collect(),
+            LatencyMode::LowLatency => self.low_latency_order.iter().cloned().collect(),
+        };
+
+        let fenced = fence_slots(&order, &queues, self.fence_every_node);
+
+        // Fences are acquired before anything is submitted,
+        // so failure doesn't leave the frame submitted partially.
+        let mut fences = Fences::<B>::default();
+        for _ in fenced.iter().flatten() {
+            match factory.acquire_fence() {
+                Ok(fence) => fences.push(fence),
+                Err(err) => {
+                    fences
+                        .into_iter()
+                        .for_each(|fence| factory.release_fence(fence));
+                    return lost_or_oom(factory, err);
+                }
+            }
+        }
+
+        if let Some(timings) = &mut self.timings {
+            timings.resolve(factory, &mut self.frames);
+        }
+
+        if let Some(occlusion) = &mut self.occlusion {
+            occlusion.resolve(factory, &self.frames);
+            unsafe {
+                // Frame that used the same query pool is complete.
+                occlusion.begin_frame(self.frames.next(), families);
+            }
+        }
+
+        unsafe {
+            // Frame that used the same descriptor pools is complete.
+            self.ctx
+                .descriptors
+                .get_mut()
+                .unwrap()
+                .next_frame(self.frames.next().index());
         }
 
-        let mut fences = self.fences.pop().unwrap_or_else(Fences::<B>::default);
-        let mut fences_used = 0;
         let ref semaphores = self.semaphores;
+        let timings = self.timings.as_ref();
+        let frame = self.frames.next();
+        let mut result = PresentResult::Ok;
+
+        if order.is_empty() && !(waits.is_empty() && signals.is_empty()) {
+            log::warn!("Graph has no submissions, external semaphores are not waited or signaled");
+        }
 
-        for submission in self.schedule.ordered() {
+        for (position, &index) in order.iter().enumerate() {
+            let submission = submissions[index];
             log::trace!("Run node {}", submission.node());
             let sid = submission.id();
             let qid = sid.queue();
 
-            let GraphNode { node, queue } = self
+            let GraphNode { node, queue, .. } = self
                 .nodes
                 .get_mut(submission.node())
                 .expect("Submission references node with out of bound index");
@@ -197,160 +649,600 @@ where
                 "Node's queue doesn't match schedule"
             );
 
-            let last_in_queue = sid.index() + 1 == self.schedule.queue(qid).unwrap().len();
-            let fence = if last_in_queue {
-                if fences_used >= fences.len() {
-                    fences.push(factory.create_fence(false).unwrap());
-                }
-                fences_used += 1;
-                Some(&mut fences[fences_used - 1])
-            } else {
-                None
+            #[cfg(feature = "debug-layout-tracking")]
+            track_layouts(&self.ctx, submission);
+
+            self.node_fences[submission.node()] = fenced[position];
+            let fence = match fenced[position] {
+                Some(slot) => Some(&mut fences[slot]),
+                None => None,
             };
 
-            unsafe {
+            let (node_waits, node_signals) = with_external_sync(
+                position,
+                order.len(),
+                submission.sync().wait.iter().map(|wait| {
+                    log::trace!("Node {} waits for {}", submission.node(), *wait.semaphore());
+                    (&semaphores[*wait.semaphore()], wait.stage())
+                }),
+                submission.sync().signal.iter().map(|signal| {
+                    log::trace!("Node {} signals {}", submission.node(), *signal.semaphore());
+                    &semaphores[*signal.semaphore()]
+                }),
+                waits,
+                signals,
+            );
+
+            let node_result = unsafe {
                 node.run(
                     &self.ctx,
                     factory,
                     families.family_by_index_mut(queue.0).queue_mut(queue.1),
                     aux,
                     &self.frames,
-                    &submission
-                        .sync()
-                        .wait
-                        .iter()
-                        .map(|wait| {
-                            log::trace!(
-                                "Node {} waits for {}",
-                                submission.node(),
-                                *wait.semaphore()
-                            );
-                            (&semaphores[*wait.semaphore()], wait.stage())
-                        })
-                        .collect::<smallvec::SmallVec<[_; 16]>>(),
-                    &submission
-                        .sync()
-                        .signal
-                        .iter()
-                        .map(|signal| {
-                            log::trace!(
-                                "Node {} signals {}",
-                                submission.node(),
-                                *signal.semaphore()
-                            );
-                            &semaphores[*signal.semaphore()]
-                        })
-                        .collect::<smallvec::SmallVec<[_; 16]>>(),
+                    &node_waits,
+                    &node_signals,
                     fence,
+                    timings.and_then(|timings| timings.node(frame, submission.node())),
                 )
-            }
+            };
+            result = result.max(node_result);
+        }
+
+        if let Some(timings) = &mut self.timings {
+            timings.submitted(frame);
         }
 
-        fences.truncate(fences_used);
         self.frames.advance(fences);
+
+        // Frame is submitted,
+        // waiting here doesn't stall the device.
+        self.pacer.pace(&mut SystemClock);
+        Ok(result)
     }
 
-    /// Get queue that will exeute given node.
-    pub fn node_queue(&self, node: NodeId) -> QueueId {
-        let (f, i) = self.nodes[node.0].queue;
-        QueueId {
-            family: FamilyId {
-                device: self.device,
-                index: f,
-            },
-            index: i,
-        }
+    /// Get number of submitted frames the device hadn't completed
+    /// when the latest `run` started.
+    /// Reaching `frames_in_flight` means `run` waited for the device,
+    /// frames are submitted faster than they are rendered.
+    pub fn gpu_behind_frames(&self) -> u64 {
+        self.gpu_behind
     }
 
-    /// Dispose of the `Graph`.
-    pub fn dispose(self, factory: &mut Factory<B>, data: &T) {
-        profile_scope!("dispose");
+    /// Limit frame rate by waiting at the end of `run`
+    /// until `target` has passed since the end of previous `run`.
+    /// Sleeps until shortly before deadline and then spins to reduce jitter.
+    /// Frames that end late shorten the wait for the next one,
+    /// so average interval stays close to `target`.
+    ///
+    /// Wait starts after all nodes are submitted, so device keeps working.
+    /// Zero duration disables the limit, which is the default.
+    pub fn set_target_frame_time(&mut self, target: std::time::Duration) {
+        self.pacer.set_target(target);
+    }
+
+    /// Get frame time limit set by `set_target_frame_time`.
+    pub fn target_frame_time(&self) -> std::time::Duration {
+        self.pacer.target()
+    }
+
+    /// Set order in which `run` records and submits nodes.
+    /// See `LatencyMode` for the trade-off.
+    pub fn set_latency_mode(&mut self, mode: LatencyMode) {
+        self.latency_mode = mode;
+    }
+
+    /// Get order in which `run` records and submits nodes.
+    pub fn latency_mode(&self) -> LatencyMode {
+        self.latency_mode
+    }
+
+    /// Get time between ends of two latest `run` calls.
+    pub fn frame_time(&self) -> Option<std::time::Duration> {
+        self.pacer.frame_time()
+    }
+
+    /// Get fence that is signaled when submission of the node in the latest frame completes.
+    ///
+    /// Returns `None` if the latest frame is already complete
+    /// or the node has no own fence.
+    /// By default only the last node on each queue has a fence,
+    /// build graph with `GraphBuilder::with_node_fences` to give every node one.
+    ///
+    /// Fence is reused by later frames after the frame completes,
+    /// so it should not be kept past next `run`.
+    pub fn node_fence(&self, node: NodeId) -> Option<&B::Fence> {
+        if !self.ctx.owns(node.1, node) {
+            return None;
+        }
+        let index = self.node_fences[node.0]?;
+        let frame = Frame::with_index(self.frames.next().index().checked_sub(1)?);
+        let fences = self.frames.fences(frame)?;
+        Some(fences[index].raw())
+    }
 
+    /// Wait until submission of the node in the latest frame completes.
+    /// Waits for the whole latest frame if the node has no own fence.
+    /// Returns `Ok(false)` on timeout.
+    ///
+    /// All nodes of a frame are submitted by `run`,
+    /// so waiting never depends on work that wasn't submitted yet.
+    pub fn wait_node(
+        &self,
+        factory: &Factory<B>,
+        node: NodeId,
+        timeout_ns: u64,
+    ) -> Result<bool, rendy_core::hal::device::OomOrDeviceLost> {
         self.assert_device_owner(factory.device());
+        self.assert_node_owner(node);
 
-        assert!(factory.wait_idle().is_ok());
-        self.frames.dispose(factory);
+        let frame = match self.frames.next().index().checked_sub(1) {
+            Some(index) => Frame::with_index(index),
+            None => return Ok(true),
+        };
+        let fences = match self.frames.fences(frame) {
+            Some(fences) => fences,
+            None => return Ok(true),
+        };
 
+        // Fence states are updated by `Frames` when frame is waited upon.
         unsafe {
-            // Device is idle.
-            for node in self.nodes {
-                node.node.dispose(factory, data);
+            match self.node_fences[node.0] {
+                Some(index) => factory
+                    .device()
+                    .wait_for_fence(fences[index].raw(), timeout_ns),
+                None => factory.device().wait_for_fences(
+                    fences.iter().map(|fence| fence.raw()),
+                    rendy_core::hal::device::WaitFor::All,
+                    timeout_ns,
+                ),
             }
+        }
+    }
 
-            for semaphore in self.semaphores {
-                factory.destroy_semaphore(semaphore);
-            }
+    /// Get GPU time in nanoseconds the node took in the latest frame with resolved timestamps.
+    /// Returns `None` if node timings are disabled or not supported.
+    pub fn node_timing(&self, node: NodeId) -> Option<u64> {
+        if !self.ctx.owns(node.1, node) {
+            return None;
         }
-        drop(self.device);
-        drop(self.schedule);
-        drop(self.fences);
-        drop(self.inflight);
-        drop(self.ctx);
+        self.frames.node_timings().get(&node.0).cloned()
     }
-}
 
-/// Build graph from nodes and resource.
-pub struct GraphBuilder<B: Backend, T: ?Sized> {
-    nodes: Vec<Box<dyn NodeBuilder<B, T>>>,
-    buffers: Vec<BufferInfo>,
-    images: Vec<(ImageInfo, Option<rendy_core::hal::command::ClearValue>)>,
-    frames_in_flight: u32,
-}
+    /// Get number of samples counted by occlusion query `id`
+    /// in the latest frame with resolved queries.
+    /// Returns `None` if occlusion queries are disabled
+    /// or the query wasn't used in that frame.
+    pub fn occlusion_result(&self, id: u32) -> Option<u64> {
+        self.occlusion
+            .as_ref()
+            .and_then(|occlusion| occlusion.result(id))
+    }
 
-impl<B, T> Default for GraphBuilder<B, T>
-where
-    B: Backend,
-    T: ?Sized,
-{
-    fn default() -> Self {
-        GraphBuilder {
-            nodes: Vec::default(),
-            buffers: Vec::default(),
-            images: Vec::default(),
-            frames_in_flight: u32::default(),
-        }
+    /// Report memory used by transient buffers and images of each node.
+    /// Resource is attributed to the node that accesses it first in the frame,
+    /// which is usually the node that produces its content.
+    /// Resources kept alive through most of the frame are marked as long-lived.
+    pub fn resource_report(&self) -> Vec<NodeResourceUsage> {
+        let submissions: Vec<(usize, Vec<GraphResource>)> = self
+            .schedule
+            .ordered()
+            .map(|submission| {
+                let node = &self.nodes[submission.node()];
+                let resources = node
+                    .buffers
+                    .iter()
+                    .map(|&id| GraphResource::Buffer(id))
+                    .chain(node.images.iter().map(|&id| GraphResource::Image(id)))
+                    .collect();
+                (submission.node(), resources)
+            })
+            .collect();
+
+        report::attribute_resources(
+            self.nodes.len(),
+            self.ctx.generation,
+            &submissions,
+            |resource| self.resource_size(resource),
+        )
     }
-}
 
-impl<B, T> std::fmt::Debug for GraphBuilder<B, T>
-where
-    B: Backend,
-    T: ?Sized,
-{
-    fn fmt(&self, fmt: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        fmt.debug_struct("GraphBuilder")
-            .field("nodes", &self.nodes)
-            .field("buffers", &self.buffers)
-            .field("images", &self.images)
-            .field("frames_in_flight", &self.frames_in_flight)
-            .finish()
+    /// Get total size of memory bound to transient buffers and images.
+    /// Aliased images are counted once.
+    pub fn transient_memory(&self) -> u64 {
+        let buffers = (0..self.ctx.buffers.len())
+            .map(|index| GraphResource::Buffer(BufferId(index, self.ctx.generation)));
+        let images = (0..self.ctx.images.len())
+            .map(|index| GraphResource::Image(ImageId(index, self.ctx.generation)));
+        buffers
+            .chain(images)
+            .map(|resource| self.resource_size(resource))
+            .sum()
     }
-}
 
-impl<B, T> GraphBuilder<B, T>
-where
-    B: Backend,
-    T: ?Sized,
-{
-    /// Create new `GraphBuilder`
-    pub fn new() -> Self {
-        GraphBuilder {
-            nodes: Vec::new(),
-            buffers: Vec::new(),
-            images: Vec::new(),
-            frames_in_flight: 3,
+    fn resource_size(&self, resource: GraphResource) -> u64 {
+        match resource {
+            GraphResource::Buffer(id) => self
+                .ctx
+                .get_buffer(id)
+                .map_or(0, |buffer| buffer.block().size()),
+            GraphResource::Image(id) => self
+                .ctx
+                .get_image(id)
+                .and_then(|image| image.block())
+                .map_or(0, |block| block.size()),
         }
     }
 
-    /// Create new buffer owned by graph.
-    pub fn create_buffer(&mut self, size: u64) -> BufferId {
+    /// Reallocate swapchain-relative images with new extent
+    /// and update nodes that use them.
+    /// Nodes that acquire swapchain images recreate their swapchains.
+    /// Other resources, nodes, execution schedule and synchronization are reused
+    /// since they don't depend on images extent.
+    ///
+    /// All relative images are resized, including those relative to surfaces
+    /// added with `GraphBuilder::add_surface`.
+    /// Use `resize_surface` when graph renders to multiple windows.
+    ///
+    /// Waits for device idle.
+    /// If error is returned the graph must be disposed and rebuilt.
+    pub fn resize(
+        &mut self,
+        factory: &mut Factory<B>,
+        aux: &T,
+        extent: rendy_core::hal::window::Extent2D,
+    ) -> Result<(), GraphResizeError> {
+        profile_scope!("resize");
+
+        let images = self.relative_images.clone();
+        self.resize_images(factory, aux, &images, |_, acquires| acquires, extent)
+    }
+
+    /// Reallocate images relative to the `surface` with new extent
+    /// and update nodes that use them, like `resize` does.
+    /// Node presenting to the surface recreates its swapchain.
+    /// Images relative to other surfaces and nodes that don't use resized images,
+    /// e.g. present nodes of other windows, are not affected.
+    ///
+    /// # Panics
+    ///
+    /// Panics if surface is not added to the builder of this graph.
+    ///
+    /// Waits for device idle.
+    /// If error is returned the graph must be disposed and rebuilt.
+    pub fn resize_surface(
+        &mut self,
+        factory: &mut Factory<B>,
+        aux: &T,
+        surface: SurfaceId,
+        extent: rendy_core::hal::window::Extent2D,
+    ) -> Result<(), GraphResizeError> {
+        profile_scope!("resize_surface");
+
+        let presenter = *self
+            .surfaces
+            .get(surface.0)
+            .expect("Surface is not added to this graph");
+        let images = images_of_surface(&self.surface_images, surface);
+        self.resize_images(factory, aux, &images, |index, _| index == presenter, extent)
+    }
+
+    /// Resize `images` and nodes using them.
+    /// Swapchains of nodes for which `swapchain` returns `true`
+    /// given node index and whether it acquires images are recreated too.
+    fn resize_images(
+        &mut self,
+        factory: &mut Factory<B>,
+        aux: &T,
+        images: &[ImageId],
+        swapchain: impl Fn(usize, bool) -> bool,
+        extent: rendy_core::hal::window::Extent2D,
+    ) -> Result<(), GraphResizeError> {
+        self.assert_device_owner(factory.device());
+
+        let nodes = resized_nodes(
+            self.nodes.iter().enumerate().map(|(index, node)| {
+                (
+                    &node.images[..],
+                    swapchain(index, node.node.acquires_image()),
+                )
+            }),
+            images,
+        );
+
+        if nodes.is_empty() {
+            return Ok(());
+        }
+
+        factory.wait_idle().map_err(GraphResizeError::OutOfMemory)?;
+
+        for &id in images {
+            let slot = match &mut self.ctx.images[id.0] {
+                Some(slot) => slot,
+                None => continue,
+            };
+
+            log::trace!("Reallocate image {:?} with extent {:?}", id, extent);
+            let image = factory
+                .create_image(
+                    ImageInfo {
+                        kind: resized_kind(slot.0.kind(), extent),
+                        ..slot.0.info().clone()
+                    },
+                    Data,
+                )
+                .map_err(GraphResizeError::Image)?;
+            slot.0 = image.into();
+            self.ctx
+                .recreate_views(factory, id)
+                .map_err(GraphResizeError::View)?;
+        }
+
+        for index in nodes {
+            let node = &mut self.nodes[index];
+            log::trace!("Resize node {}", index);
+            let resized = unsafe {
+                // Device is idle.
+                node.node.resize(&self.ctx, factory, aux)
+            }
+            .map_err(GraphResizeError::Node)?;
+
+            if !resized {
+                return Err(GraphResizeError::Unsupported(NodeId(
+                    index,
+                    self.ctx.generation,
+                )));
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Get queue that will exeute given node.
+    ///
+    /// # Panics
+    ///
+    /// Panics if node belongs to another graph.
+    pub fn node_queue(&self, node: NodeId) -> QueueId {
+        self.assert_node_owner(node);
+        let (f, i) = self.nodes[node.0].queue;
+        QueueId {
+            family: FamilyId {
+                device: self.device,
+                index: f,
+            },
+            index: i,
+        }
+    }
+
+    fn assert_node_owner(&self, node: NodeId) {
+        assert_eq!(
+            node.1, self.ctx.generation,
+            "Node {:?} belongs to another graph",
+            node
+        );
+    }
+
+    /// Get DOT representation of the graph.
+    /// It shows nodes colored by queue family they run on.
+    /// Edges between nodes are labeled with the resource and its state transition
+    /// which requires graph to insert barriers or semaphores between them.
+    ///
+    /// Render it with `dot -Tsvg graph.dot -o graph.svg`.
+    pub fn dump_graphviz(&self) -> String {
+        graphviz::dump(&self.chains)
+    }
+
+    /// Dispose of the `Graph`.
+    pub fn dispose(self, factory: &mut Factory<B>, data: &T) {
+        profile_scope!("dispose");
+
+        self.assert_device_owner(factory.device());
+
+        // Work of lost device never completes, resources are destroyed without waiting.
+        if !factory.is_device_lost() {
+            factory.wait_idle().expect("Failed to wait for device idle");
+        }
+        unsafe {
+            // Device is idle.
+            self.dispose_unchecked(factory, data);
+        }
+    }
+
+    /// Dispose of the `Graph` inside `Factory::idle_scope`
+    /// without waiting for device idle again.
+    pub fn dispose_idle(self, factory: &mut Factory<B>, idle: &DeviceIdle, data: &T) {
+        profile_scope!("dispose_idle");
+
+        self.assert_device_owner(factory.device());
+        assert_eq!(
+            idle.device(),
+            factory.device().id(),
+            "Idle scope belongs to another device"
+        );
+
+        unsafe {
+            // Device is idle.
+            self.dispose_unchecked(factory, data);
+        }
+    }
+
+    /// Must be called after waiting for device idle.
+    unsafe fn dispose_unchecked(self, factory: &mut Factory<B>, data: &T) {
+        self.frames.dispose(factory);
+
+        for node in self.nodes {
+            node.node.dispose(factory, data);
+        }
+
+        for semaphore in self.semaphores {
+            factory.destroy_semaphore(semaphore);
+        }
+
+        if let Some(timings) = self.timings {
+            timings.dispose(factory);
+        }
+
+        // Context holds reference to occlusion query pools.
+        self.ctx.dispose(factory);
+        if let Some(occlusion) = self.occlusion {
+            occlusion.dispose(factory);
+        }
+        drop(self.device);
+        drop(self.schedule);
+        drop(self.inflight);
+        drop(self.relative_images);
+        drop(self.surface_images);
+        drop(self.surfaces);
+    }
+}
+
+/// Build graph from nodes and resource.
+pub struct GraphBuilder<B: Backend, T: ?Sized> {
+    nodes: Vec<Box<dyn NodeBuilder<B, T>>>,
+    buffers: Vec<BufferInfo>,
+    buffer_data: Vec<(usize, Vec<u8>)>,
+    images: Vec<(ImageInfo, Option<rendy_core::hal::command::ClearValue>)>,
+    image_data: Vec<(usize, Vec<u8>)>,
+    relative_images: Vec<ImageId>,
+    surfaces: Vec<NodeId>,
+    surface_images: Vec<(SurfaceId, ImageId)>,
+    frames_in_flight: u32,
+    node_timings: bool,
+    node_fences: bool,
+    aliasing: bool,
+    occlusion_queries: Option<(u32, bool)>,
+    semaphores: Vec<(NodeId, NodeId, rendy_core::hal::pso::PipelineStage)>,
+    latency_mode: LatencyMode,
+    generation: u32,
+}
+
+impl<B, T> Default for GraphBuilder<B, T>
+where
+    B: Backend,
+    T: ?Sized,
+{
+    fn default() -> Self {
+        GraphBuilder {
+            nodes: Vec::default(),
+            buffers: Vec::default(),
+            buffer_data: Vec::default(),
+            images: Vec::default(),
+            image_data: Vec::default(),
+            relative_images: Vec::default(),
+            surfaces: Vec::new(),
+            surface_images: Vec::default(),
+            frames_in_flight: 3,
+            node_timings: false,
+            node_fences: false,
+            aliasing: false,
+            occlusion_queries: None,
+            semaphores: Vec::default(),
+            latency_mode: LatencyMode::default(),
+            generation: next_generation(),
+        }
+    }
+}
+
+impl<B, T> std::fmt::Debug for GraphBuilder<B, T>
+where
+    B: Backend,
+    T: ?Sized,
+{
+    fn fmt(&self, fmt: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        fmt.debug_struct("GraphBuilder")
+            .field("nodes", &self.nodes)
+            .field("buffers", &self.buffers)
+            .field(
+                "buffer_data",
+                &self
+                    .buffer_data
+                    .iter()
+                    .map(|(index, data)| (index, data.len()))
+                    .collect::<Vec<_>>(),
+            )
+            .field("images", &self.images)
+            .field(
+                "image_data",
+                &self
+                    .image_data
+                    .iter()
+                    .map(|(index, data)| (index, data.len()))
+                    .collect::<Vec<_>>(),
+            )
+            .field("relative_images", &self.relative_images)
+            .field("surfaces", &self.surfaces)
+            .field("surface_images", &self.surface_images)
+            .field("frames_in_flight", &self.frames_in_flight)
+            .field("node_timings", &self.node_timings)
+            .field("node_fences", &self.node_fences)
+            .field("aliasing", &self.aliasing)
+            .field("occlusion_queries", &self.occlusion_queries)
+            .field("semaphores", &self.semaphores)
+            .field("latency_mode", &self.latency_mode)
+            .field("generation", &self.generation)
+            .finish()
+    }
+}
+
+impl<B, T> GraphBuilder<B, T>
+where
+    B: Backend,
+    T: ?Sized,
+{
+    /// Create new `GraphBuilder`
+    pub fn new() -> Self {
+        GraphBuilder {
+            nodes: Vec::new(),
+            buffers: Vec::new(),
+            buffer_data: Vec::new(),
+            images: Vec::new(),
+            image_data: Vec::new(),
+            relative_images: Vec::new(),
+            surfaces: Vec::new(),
+            surface_images: Vec::new(),
+            frames_in_flight: 3,
+            node_timings: false,
+            node_fences: false,
+            aliasing: false,
+            occlusion_queries: None,
+            semaphores: Vec::new(),
+            latency_mode: LatencyMode::default(),
+            generation: next_generation(),
+        }
+    }
+
+    /// Create new buffer owned by graph.
+    pub fn create_buffer(&mut self, size: u64) -> BufferId {
         profile_scope!("create_buffer");
 
         self.buffers.push(BufferInfo {
             size,
             usage: rendy_core::hal::buffer::Usage::empty(),
         });
-        BufferId(self.buffers.len() - 1)
+        BufferId(self.buffers.len() - 1, self.generation)
+    }
+
+    /// Create new buffer owned by graph with initial content.
+    /// `data` is uploaded to the beginning of the buffer when graph is built.
+    /// Upload is flushed by `Factory::maintain`, which must be called before graph runs.
+    /// Nodes see the content in first frame, nodes that write the buffer overwrite it for following frames.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `data` is larger than `size`.
+    pub fn create_buffer_with_data(&mut self, size: u64, data: &[u8]) -> BufferId {
+        assert!(
+            data.len() as u64 <= size,
+            "Initial data of {} bytes doesn't fit buffer of {} bytes",
+            data.len(),
+            size
+        );
+
+        let id = self.create_buffer(size);
+        self.buffers[id.0].usage = rendy_core::hal::buffer::Usage::TRANSFER_DST;
+        self.buffer_data.push((id.0, data.to_vec()));
+        id
     }
 
     /// Create new image owned by graph.
@@ -374,7 +1266,130 @@ where
             },
             clear,
         ));
-        ImageId(self.images.len() - 1)
+        ImageId(self.images.len() - 1, self.generation)
+    }
+
+    /// Create new single level image owned by graph with initial content.
+    /// `data` fills all layers of the image, tightly packed, and is uploaded when graph is built.
+    /// Upload is flushed by `Factory::maintain`, which must be called before graph runs.
+    ///
+    /// Unlike other graph images, content isn't discarded at the beginning of a frame.
+    /// Nodes see the content in first frame, nodes that write the image overwrite it for following frames.
+    /// Graph fails to build with `GraphBuildError::InitialData` if the image
+    /// is transferred to another queue family between frames.
+    ///
+    /// # Panics
+    ///
+    /// Panics if size of `data` doesn't match size of the image.
+    pub fn create_image_with_data(
+        &mut self,
+        kind: rendy_core::hal::image::Kind,
+        format: rendy_core::hal::format::Format,
+        data: &[u8],
+    ) -> ImageId {
+        let size = image_data_size(kind, format);
+        assert_eq!(
+            data.len() as u64,
+            size,
+            "Initial data of {} bytes doesn't match image of {} bytes",
+            data.len(),
+            size
+        );
+
+        let id = self.create_image(kind, 1, format, None);
+        self.images[id.0].0.usage = rendy_core::hal::image::Usage::TRANSFER_DST;
+        self.image_data.push((id.0, data.to_vec()));
+        id
+    }
+
+    /// Create new cube-compatible image owned by graph.
+    /// Image has 6 layers, one per face.
+    /// Nodes can access single face with `add_image_subresource`.
+    pub fn create_cube_image(
+        &mut self,
+        size: rendy_core::hal::image::Size,
+        levels: rendy_core::hal::image::Level,
+        format: rendy_core::hal::format::Format,
+        clear: Option<rendy_core::hal::command::ClearValue>,
+    ) -> ImageId {
+        let id = self.create_image(
+            rendy_core::hal::image::Kind::D2(size, size, 6, 1),
+            levels,
+            format,
+            clear,
+        );
+        self.images[id.0].0.view_caps = rendy_core::hal::image::ViewCapabilities::KIND_CUBE;
+        id
+    }
+
+    /// Create new swapchain-relative image owned by graph.
+    /// Image is reallocated with new extent by `Graph::resize`.
+    ///
+    /// `extent` is initial extent of the image, usually current surface extent.
+    pub fn create_relative_image(
+        &mut self,
+        extent: rendy_core::hal::window::Extent2D,
+        levels: rendy_core::hal::image::Level,
+        format: rendy_core::hal::format::Format,
+        clear: Option<rendy_core::hal::command::ClearValue>,
+    ) -> ImageId {
+        let id = self.create_image(
+            rendy_core::hal::image::Kind::D2(extent.width, extent.height, 1, 1),
+            levels,
+            format,
+            clear,
+        );
+        self.relative_images.push(id);
+        id
+    }
+
+    /// Add window surface the graph renders to.
+    /// Each window needs its own present node or render pass with surface,
+    /// those acquire and present images of their swapchains independently.
+    /// `presenter` is that node, its swapchain is recreated by `Graph::resize_surface`
+    /// together with images created with `create_surface_relative_image` for the surface.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `presenter` is not added to this builder
+    /// or already presents to another surface.
+    /// Graph building panics if `presenter` doesn't acquire swapchain images.
+    pub fn add_surface(&mut self, presenter: NodeId) -> SurfaceId {
+        assert!(
+            presenter.1 == self.generation && presenter.0 < self.nodes.len(),
+            "Node {:?} is not added to this builder",
+            presenter
+        );
+        assert!(
+            !self.surfaces.contains(&presenter),
+            "Node {:?} already presents to a surface",
+            presenter
+        );
+        self.surfaces.push(presenter);
+        SurfaceId(self.surfaces.len() - 1)
+    }
+
+    /// Create new image relative to the `surface` owned by graph.
+    /// Image is reallocated with new extent by `Graph::resize_surface` for that surface,
+    /// or by `Graph::resize`.
+    ///
+    /// `extent` is initial extent of the image, usually current surface extent.
+    pub fn create_surface_relative_image(
+        &mut self,
+        surface: SurfaceId,
+        extent: rendy_core::hal::window::Extent2D,
+        levels: rendy_core::hal::image::Level,
+        format: rendy_core::hal::format::Format,
+        clear: Option<rendy_core::hal::command::ClearValue>,
+    ) -> ImageId {
+        assert!(
+            surface.0 < self.surfaces.len(),
+            "Surface {:?} is not added",
+            surface
+        );
+        let id = self.create_relative_image(extent, levels, format, clear);
+        self.surface_images.push((surface, id));
+        id
     }
 
     /// Add node to the graph.
@@ -385,17 +1400,271 @@ where
     /// Add boxed node to the graph.
     pub fn add_dyn_node(&mut self, builder: Box<dyn NodeBuilder<B, T> + 'static>) -> NodeId {
         self.nodes.push(builder);
-        NodeId(self.nodes.len() - 1)
+        NodeId(self.nodes.len() - 1, self.generation)
+    }
+
+    /// Make `wait` node wait for semaphore signaled by `signal` node at `stages`.
+    /// `wait` node is scheduled after `signal` node, as if it depended on it.
+    ///
+    /// Graph synchronizes nodes that access same resources on its own,
+    /// this is for nodes that run on different queues and synchronize
+    /// through resources the graph doesn't know about,
+    /// e.g. async compute writing into buffer that graphics node reads.
+    /// Nothing is added if both nodes run on the same queue.
+    ///
+    /// Semaphore is created once with the graph and reused every frame.
+    /// It is signaled and waited within the same frame,
+    /// and all nodes of a frame are submitted by one `Graph::run` call,
+    /// so it is unsignaled again before the next frame signals it.
+    ///
+    /// Only nodes of the same frame are synchronized.
+    /// Waiting in one frame for a node of a previous frame is not supported,
+    /// the first frame would wait for semaphore nobody signals.
+    /// Graph already waits for frame `frames_in_flight` before with fences,
+    /// work of later frames can be ordered after it through resources.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `signal` and `wait` are the same node,
+    /// which could only wait for the semaphore of previous frame.
+    pub fn add_semaphore_dependency(
+        &mut self,
+        signal: NodeId,
+        wait: NodeId,
+        stages: rendy_core::hal::pso::PipelineStage,
+    ) -> &mut Self {
+        assert_ne!(
+            signal, wait,
+            "Node can't wait for own semaphore, semaphores don't cross frames"
+        );
+        self.semaphores.push((signal, wait, stages));
+        self
     }
 
-    /// Choose number of frames in flight for the graph
+    /// Choose number of frames in flight for the graph.
+    /// Three by default.
+    ///
+    /// More frames let CPU record ahead of GPU at cost of latency,
+    /// fewer frames make CPU wait for GPU more often.
+    /// Nodes size per-frame resources with `GraphContext::frames_in_flight`.
+    /// Presenting nodes fail to build with `NodeBuildError::FramesInFlight`
+    /// if swapchain has fewer images, zero frames fails validation.
     pub fn with_frames_in_flight(mut self, frames_in_flight: u32) -> Self {
         self.frames_in_flight = frames_in_flight;
         self
     }
 
+    /// Enable measuring GPU time of each node with timestamp queries.
+    /// Resolved timings are available through `Frames::node_timings` and `Graph::node_timing`.
+    /// Does nothing if device doesn't support timestamps.
+    pub fn with_node_timings(mut self, enabled: bool) -> Self {
+        self.node_timings = enabled;
+        self
+    }
+
+    /// Give every node submission its own fence.
+    /// Otherwise only the last node on each queue has one.
+    /// Fences are available through `Graph::node_fence` and `Graph::wait_node`.
+    pub fn with_node_fences(mut self, enabled: bool) -> Self {
+        self.node_fences = enabled;
+        self
+    }
+
+    /// Let images with disjoint lifetimes share memory.
+    /// Images are aliased if all their accesses are on the same queue,
+    /// the first access writes the image and accesses of one image
+    /// are all submitted before accesses of another.
+    /// Swapchain-relative images are never aliased.
+    ///
+    /// Content of aliased images doesn't survive between frames,
+    /// so they must not be read by the first node that accesses them
+    /// or outside of the graph.
+    /// Disabled by default as some drivers handle aliasing poorly.
+    pub fn with_aliasing(mut self, enabled: bool) -> Self {
+        self.aliasing = enabled;
+        self
+    }
+
+    /// Set order in which `Graph::run` records and submits nodes.
+    /// `LatencyMode::Throughput` by default.
+    /// Can be changed later with `Graph::set_latency_mode`.
+    pub fn with_latency_mode(mut self, mode: LatencyMode) -> Self {
+        self.latency_mode = mode;
+        self
+    }
+
+    /// Create `count` occlusion queries for each frame.
+    /// Nodes get them from `GraphContext::occlusion_queries`
+    /// and results are available through `Graph::occlusion_result`.
+    ///
+    /// `precise` queries count exact number of samples,
+    /// otherwise result only tells if any sample passed, which may be faster.
+    /// Falls back to non-precise queries if device doesn't support them.
+    pub fn with_occlusion_queries(mut self, count: u32, precise: bool) -> Self {
+        self.occlusion_queries = Some((count, precise));
+        self
+    }
+
+    /// Check wiring of the graph without building it.
+    ///
+    /// Reports dependency cycles, buffers and images not used by any node,
+    /// cleared images accessed first by multiple unordered nodes
+    /// and nodes for which no queue family is suitable.
+    /// `build` fails on the same problems except unused resources, those are only logged.
+    ///
+    /// Ids created by another builder are reported before any other checks.
+    pub fn validate(
+        &self,
+        factory: &mut Factory<B>,
+        families: &Families<B>,
+    ) -> Result<(), Vec<GraphError>> {
+        let foreign = self.foreign_ids();
+        if !foreign.is_empty() {
+            return Err(foreign);
+        }
+
+        let errors = self.check(factory, families, &resolve_nodes(&self.nodes));
+        if errors.is_empty() {
+            Ok(())
+        } else {
+            Err(errors)
+        }
+    }
+
+    /// Problems `validate` reports, given nodes resolved without foreign ids.
+    fn check(
+        &self,
+        factory: &mut Factory<B>,
+        families: &Families<B>,
+        resolved: &[ResolvedNode],
+    ) -> Vec<GraphError> {
+        let mut errors = Vec::new();
+
+        if self.frames_in_flight == 0 {
+            errors.push(GraphError::NoFramesInFlight);
+        }
+
+        for (index, node) in self.nodes.iter().enumerate() {
+            if node.family(factory, families).is_none() {
+                errors.push(GraphError::NoFamily(NodeId(index, self.generation)));
+            }
+        }
+
+        let dependencies = self.node_dependencies(resolved);
+        errors.extend(find_cycles(&dependencies).into_iter().map(|cycle| {
+            GraphError::Cycle(
+                cycle
+                    .into_iter()
+                    .map(|index| NodeId(index, self.generation))
+                    .collect(),
+            )
+        }));
+
+        let accesses = image_accesses(&self.nodes, resolved);
+        errors.extend(
+            unused(
+                self.buffers.len(),
+                self.nodes
+                    .iter()
+                    .flat_map(|node| node.buffers())
+                    .map(|(id, _)| id.0),
+            )
+            .into_iter()
+            .map(|index| GraphError::UnusedBuffer(BufferId(index, self.generation))),
+        );
+        errors.extend(
+            unused(
+                self.images.len(),
+                accesses.iter().flatten().map(|(id, _)| id.0),
+            )
+            .into_iter()
+            .map(|index| GraphError::UnusedImage(ImageId(index, self.generation))),
+        );
+
+        errors.extend(
+            multiple_clears(&accesses, &dependencies, |id| self.images[id.0].1.is_some())
+                .into_iter()
+                .map(|(image, nodes)| GraphError::MultipleClears {
+                    image,
+                    nodes: nodes
+                        .into_iter()
+                        .map(|index| NodeId(index, self.generation))
+                        .collect(),
+                }),
+        );
+
+        errors
+    }
+
+    /// Ids used by nodes and semaphore dependencies that were created by another builder.
+    fn foreign_ids(&self) -> Vec<GraphError> {
+        let mut errors: Vec<GraphError> = self
+            .nodes
+            .iter()
+            .enumerate()
+            .flat_map(|(index, node)| {
+                let buffers = node.buffers().into_iter().map(|(id, _)| id);
+                let images = node
+                    .images()
+                    .into_iter()
+                    .chain(node.optional_images())
+                    .map(|(id, _)| id)
+                    .chain(node.image_subresources().into_iter().map(|(id, _)| id))
+                    .chain(node.storage_images())
+                    .chain(node.sampled_images())
+                    .chain(node.image_clears().into_iter().map(|(id, _)| id));
+                let nodes = node.dependencies().into_iter();
+                foreign_ids(
+                    self.generation,
+                    NodeId(index, self.generation),
+                    buffers,
+                    images,
+                    nodes,
+                )
+            })
+            .collect();
+        for &(signal, wait, _) in &self.semaphores {
+            for &node in &[signal, wait] {
+                if node.1 != self.generation && !errors.contains(&GraphError::ForeignNode(node)) {
+                    errors.push(GraphError::ForeignNode(node));
+                }
+            }
+        }
+        errors
+    }
+
+    /// All dependencies of each node, including semaphore dependencies.
+    fn node_dependencies(&self, resolved: &[ResolvedNode]) -> Vec<Vec<usize>> {
+        let count = self.nodes.len();
+        let mut dependencies: Vec<Vec<usize>> = self
+            .nodes
+            .iter()
+            .zip(resolved)
+            .map(|(node, resolved)| {
+                node.dependencies()
+                    .into_iter()
+                    .map(|id| id.0)
+                    .chain(resolved.dependencies.iter().cloned())
+                    .filter(|&dependency| dependency < count)
+                    .collect()
+            })
+            .collect();
+        for &(signal, wait, _) in &self.semaphores {
+            dependencies[wait.0].push(signal.0);
+        }
+        for dependencies in &mut dependencies {
+            dependencies.sort();
+            dependencies.dedup();
+        }
+        dependencies
+    }
+
     /// Build `Graph`.
     ///
+    /// Nodes create their pipelines when built, `Graph::run` never creates them.
+    /// Load pipeline cache with `Factory::load_pipeline_cache` before building
+    /// to make their creation faster.
+    ///
     /// # Parameters
     ///
     /// `frames`        - maximum number of frames `Graph` will render simultaneously.
@@ -413,13 +1682,40 @@ where
     ) -> Result<Graph<B, T>, GraphBuildError> {
         profile_scope!("build");
 
+        let foreign = self.foreign_ids();
+        if !foreign.is_empty() {
+            return Err(GraphBuildError::Validation(foreign));
+        }
+
+        log::trace!("Resolve nodes inputs");
+        let mut resolved: Vec<ResolvedNode> = {
+            profile_scope!("resolve_nodes");
+            resolve_nodes(&self.nodes)
+        };
+
+        let (fatal, warnings): (Vec<_>, Vec<_>) = self
+            .check(factory, families, &resolved)
+            .into_iter()
+            .partition(GraphError::is_fatal);
+        for warning in warnings {
+            log::warn!("{}", warning);
+        }
+        if !fatal.is_empty() {
+            return Err(GraphBuildError::Validation(fatal));
+        }
+
+        for &(signal, wait, _) in &self.semaphores {
+            resolved[wait.0].dependencies.push(signal.0);
+        }
+
         log::trace!("Schedule nodes execution");
         let chain_nodes: Vec<chain::Node> = {
             profile_scope!("schedule_nodes");
             self.nodes
                 .iter()
+                .zip(&resolved)
                 .enumerate()
-                .map(|(i, b)| make_chain_node(&**b, i, factory, families))
+                .map(|(i, (b, r))| make_chain_node(&**b, i, r, factory, families))
                 .collect()
         };
 
@@ -428,14 +1724,99 @@ where
         });
         log::trace!("Scheduled nodes execution {:#?}", chains);
 
+        let aliased = if self.aliasing {
+            // Relative images are reallocated on resize
+            // and images with initial data keep content between frames.
+            let excluded: Vec<_> = self
+                .relative_images
+                .iter()
+                .cloned()
+                .chain(
+                    self.image_data
+                        .iter()
+                        .map(|&(index, _)| ImageId(index, self.generation)),
+                )
+                .collect();
+            alias_groups(image_lifetimes(&chains, &excluded))
+        } else {
+            Vec::new()
+        };
+        log::trace!("Aliased images {:?}", aliased);
+
         let mut ctx = GraphContext::alloc(
             factory,
             &chains,
             &self.buffers,
             &self.images,
+            &aliased,
             self.frames_in_flight,
+            self.generation,
         )?;
 
+        for (index, data) in &self.buffer_data {
+            let buffer = match ctx.buffers[*index].as_ref() {
+                Some(buffer) => buffer,
+                // Not accessed by any node.
+                None => continue,
+            };
+            let (queue, stage, access) = upload_target(&chains.buffers[&chain::Id(*index)]);
+            log::trace!("Upload {} bytes to buffer {}", data.len(), index);
+            unsafe {
+                // Buffer is just created by the same factory and isn't used yet.
+                factory.upload_buffer(
+                    buffer,
+                    0,
+                    &data[..],
+                    None,
+                    BufferState {
+                        queue: QueueId {
+                            family: FamilyId {
+                                device: factory.device().id(),
+                                index: queue.family().0,
+                            },
+                            index: queue.index(),
+                        },
+                        stage,
+                        access,
+                    },
+                )
+            }
+            .map_err(GraphBuildError::Upload)?;
+        }
+
+        let storage_views: Vec<_> = self
+            .nodes
+            .iter()
+            .flat_map(|node| node.storage_images())
+            .filter_map(|id| ctx.get_image(id).map(|image| (id, storage_range(image))))
+            .collect();
+        let sampled_views: Vec<_> = self
+            .nodes
+            .iter()
+            .flat_map(|node| node.sampled_images())
+            .filter_map(|id| ctx.get_image(id).map(|image| (id, sampled_range(image))))
+            .collect();
+        ctx.create_views(
+            factory,
+            self.nodes
+                .iter()
+                .flat_map(|node| node.image_subresources())
+                .chain(storage_views)
+                .chain(sampled_views.iter().cloned()),
+        )
+        .map_err(GraphBuildError::View)?;
+
+        if !sampled_views.is_empty() {
+            // Samplers are cached by the factory, graphs share the same one.
+            let sampler = factory
+                .get_sampler(rendy_core::hal::image::SamplerDesc::new(
+                    rendy_core::hal::image::Filter::Linear,
+                    rendy_core::hal::image::WrapMode::Clamp,
+                ))
+                .map_err(GraphBuildError::Sampler)?;
+            ctx.default_sampler = Some(sampler);
+        }
+
         log::trace!("Synchronize");
 
         let mut semaphores = 0..;
@@ -444,10 +1825,106 @@ where
             (id, id)
         });
         schedule.build_order();
+        alias_barriers(&mut schedule, &chains, &aliased);
+        chain::sync_semaphores(
+            &mut schedule,
+            self.semaphores
+                .iter()
+                .map(|&(signal, wait, stages)| (signal.0, wait.0, stages)),
+            || {
+                let id = semaphores.next().unwrap();
+                (id, id)
+            },
+        );
         log::trace!("Schedule: {:#?}", schedule);
 
+        for (index, data) in &self.image_data {
+            let image = match ctx.images[*index].as_ref() {
+                Some((image, _)) => image,
+                // Not accessed by any node.
+                None => continue,
+            };
+            let id = chain::Id(*index);
+            let (queue, stage, access, layout) =
+                image_upload_target(id, &chains.images[&id], &schedule).ok_or(
+                    GraphBuildError::InitialData(ImageId(*index, self.generation)),
+                )?;
+            log::trace!("Upload {} bytes to image {}", data.len(), index);
+            unsafe {
+                // Image is just created by the same factory and isn't used yet.
+                factory.upload_image(
+                    image.clone(),
+                    image.kind().extent().width,
+                    image.kind().extent().height,
+                    rendy_core::hal::image::SubresourceLayers {
+                        aspects: image.format().surface_desc().aspects,
+                        level: 0,
+                        layers: 0..image.layers(),
+                    },
+                    rendy_core::hal::image::Offset::ZERO,
+                    image.kind().extent(),
+                    &data[..],
+                    ImageStateOrLayout::undefined(),
+                    ImageState {
+                        queue: QueueId {
+                            family: FamilyId {
+                                device: factory.device().id(),
+                                index: queue.family().0,
+                            },
+                            index: queue.index(),
+                        },
+                        stage,
+                        access,
+                        layout,
+                    },
+                )
+            }
+            .map_err(GraphBuildError::Upload)?;
+        }
+
+        let occlusion = match self.occlusion_queries {
+            Some((count, precise)) => {
+                let queue = schedule
+                    .ordered()
+                    .map(|submission| submission.id().queue())
+                    .find(|qid| {
+                        Graphics::from_queue_type(
+                            families.family_by_index(qid.family().0).capability(),
+                        )
+                        .is_some()
+                    });
+                match queue {
+                    Some(qid) => {
+                        log::debug!("Create {} occlusion queries on {:?}", count, qid);
+                        Some(GraphOcclusion::new(
+                            factory,
+                            families,
+                            (qid.family().0, qid.index()),
+                            count,
+                            precise,
+                            self.frames_in_flight,
+                        )?)
+                    }
+                    None => {
+                        log::warn!("No graphics nodes. Occlusion queries are disabled");
+                        None
+                    }
+                }
+            }
+            None => None,
+        };
+        ctx.occlusion = occlusion
+            .as_ref()
+            .map(|occlusion| occlusion.queries().clone());
+
         log::trace!("Build nodes");
-        let mut built_nodes: Vec<_> = (0..self.nodes.len()).map(|_| None).collect();
+        let initialized: Vec<_> = self
+            .image_data
+            .iter()
+            .map(|&(index, _)| ImageId(index, self.generation))
+            .collect();
+        let node_count = self.nodes.len();
+        let mut built_nodes: Vec<_> = (0..node_count).map(|_| None).collect();
         let mut node_descs: Vec<_> = self.nodes.into_iter().map(Some).collect();
 
         {
@@ -461,19 +1938,30 @@ where
                         log::trace!("For submission {:#?}", submission.id());
                         let builder = node_descs[submission.node()].take().unwrap();
                         log::trace!("Build node {:#?}", builder);
+                        let buffers: Vec<BufferId> =
+                            builder.buffers().into_iter().map(|(id, _)| id).collect();
+                        let images: Vec<ImageId> = builder
+                            .images()
+                            .into_iter()
+                            .chain(resolved[submission.node()].optional_images.iter().cloned())
+                            .map(|(id, _)| id)
+                            .collect();
                         let node = build_node(
                             &mut ctx,
                             builder,
+                            &resolved[submission.node()],
                             factory,
                             families.family_by_index_mut(family.id().0),
                             queue.id().index(),
                             aux,
                             &chains,
                             &submission,
+                            &initialized,
                         )
                         .map_err(GraphBuildError::Node)?;
                         log::debug!("Node built: {:#?}", node);
-                        built_nodes[submission.node()] = Some((node, submission.id().queue()));
+                        built_nodes[submission.node()] =
+                            Some((node, submission.id().queue(), buffers, images));
                     }
                 }
             }
@@ -485,35 +1973,626 @@ where
             .collect::<Result<_, _>>()
             .map_err(GraphBuildError::Semaphore)?;
 
-        Ok(Graph {
-            device: factory.device().id(),
-            ctx,
-            nodes: built_nodes
-                .into_iter()
-                .map(Option::unwrap)
-                .map(|(node, qid)| GraphNode {
-                    node,
-                    queue: (qid.family().0, qid.index()),
-                })
-                .collect(),
-            schedule,
-            semaphores,
-            inflight: self.frames_in_flight,
-            frames: Frames::new(),
-            fences: Vec::new(),
-        })
+        let low_latency_order = {
+            let submissions: Vec<_> = schedule.ordered().collect();
+            let resources: Vec<Vec<GraphResource>> = submissions
+                .iter()
+                .map(|submission| {
+                    let (_, _, buffers, images) = built_nodes[submission.node()].as_ref().unwrap();
+                    // Aliased images are one resource as they share memory.
+                    let image = |id: ImageId| {
+                        aliased
+                            .iter()
+                            .find(|group| group.contains(&id.0))
+                            .map_or(id, |group| ImageId(group[0], id.1))
+                    };
+                    buffers
+                        .iter()
+                        .map(|&id| GraphResource::Buffer(id))
+                        .chain(images.iter().map(|&id| GraphResource::Image(image(id))))
+                        .collect()
+                })
+                .collect();
+            let orders: Vec<_> = submissions
+                .iter()
+                .enumerate()
+                .map(|(index, submission)| {
+                    let waits: Vec<usize> = submission
+                        .sync()
+                        .wait
+                        .iter()
+                        .map(|wait| *wait.semaphore())
+                        .collect();
+                    SubmissionOrder {
+                        acquires: built_nodes[submission.node()]
+                            .as_ref()
+                            .unwrap()
+                            .0
+                            .acquires_image(),
+                        after: (0..index)
+                            .filter(|&before| {
+                                submissions[before]
+                                    .sync()
+                                    .signal
+                                    .iter()
+                                    .any(|signal| waits.contains(signal.semaphore()))
+                                    || resources[before]
+                                        .iter()
+                                        .any(|resource| resources[index].contains(resource))
+                            })
+                            .collect(),
+                    }
+                })
+                .collect();
+            low_latency_order(&orders)
+        };
+        log::trace!("Low latency order {:?}", low_latency_order);
+
+        let surfaces: Vec<usize> = self
+            .surfaces
+            .iter()
+            .map(|presenter| {
+                assert!(
+                    built_nodes[presenter.0]
+                        .as_ref()
+                        .unwrap()
+                        .0
+                        .acquires_image(),
+                    "Node {:?} of the surface doesn't present",
+                    presenter
+                );
+                presenter.0
+            })
+            .collect();
+
+        let timings = if self.node_timings {
+            match factory.timestamp_period() {
+                Some(period) => {
+                    log::debug!("Create node timings");
+                    let queues: Vec<usize> = built_nodes
+                        .iter()
+                        .map(|node| node.as_ref().unwrap().1.family().0)
+                        .collect();
+                    Some(NodeTimings::new(
+                        factory,
+                        families,
+                        period,
+                        self.frames_in_flight,
+                        &queues,
+                    )?)
+                }
+                None => {
+                    log::warn!("Timestamps are not supported. Node timings are disabled");
+                    None
+                }
+            }
+        } else {
+            None
+        };
+
+        Ok(Graph {
+            device: factory.device().id(),
+            ctx,
+            nodes: built_nodes
+                .into_iter()
+                .map(Option::unwrap)
+                .map(|(node, qid, buffers, images)| GraphNode {
+                    node,
+                    queue: (qid.family().0, qid.index()),
+                    buffers,
+                    images,
+                })
+                .collect(),
+            schedule,
+            chains,
+            semaphores,
+            inflight: self.frames_in_flight,
+            frames: Frames::new(),
+            timings,
+            occlusion,
+            relative_images: self.relative_images,
+            surface_images: self.surface_images,
+            surfaces,
+            fence_every_node: self.node_fences,
+            node_fences: vec![None; node_count],
+            pacer: FramePacer::default(),
+            gpu_behind: 0,
+            latency_mode: self.latency_mode,
+            low_latency_order,
+        })
+    }
+}
+
+/// Report ids the node refers to that don't have builder's `generation`.
+fn foreign_ids(
+    generation: u32,
+    node: NodeId,
+    buffers: impl IntoIterator<Item = BufferId>,
+    images: impl IntoIterator<Item = ImageId>,
+    nodes: impl IntoIterator<Item = NodeId>,
+) -> Vec<GraphError> {
+    let found = buffers
+        .into_iter()
+        .filter(|buffer| buffer.1 != generation)
+        .map(|buffer| GraphError::ForeignBuffer { node, buffer })
+        .chain(
+            images
+                .into_iter()
+                .filter(|image| image.1 != generation)
+                .map(|image| GraphError::ForeignImage { node, image }),
+        )
+        .chain(
+            nodes
+                .into_iter()
+                .filter(|other| other.1 != generation)
+                .map(GraphError::ForeignNode),
+        );
+
+    // Same id is usually reported by several accessors of the node.
+    let mut errors = Vec::new();
+    for error in found {
+        if !errors.contains(&error) {
+            errors.push(error);
+        }
+    }
+    errors
+}
+
+/// Node inputs resolved against other nodes of the graph.
+#[derive(Debug, Default)]
+struct ResolvedNode {
+    optional_images: Vec<(ImageId, ImageAccess)>,
+    dependencies: Vec<usize>,
+    /// Images whose accessed subresources are not accessed by nodes added before.
+    initial_images: Vec<ImageId>,
+}
+
+/// Clear value and aspects of image range node must clear.
+/// Clear requested by the node is applied regardless of preceding accesses,
+/// clear value image is created with only by the node accessing image first.
+fn node_image_clear(
+    node_clear: Option<NodeClear>,
+    initial: bool,
+    image_clear: Option<rendy_core::hal::command::ClearValue>,
+    aspects: rendy_core::hal::format::Aspects,
+) -> (
+    Option<rendy_core::hal::command::ClearValue>,
+    rendy_core::hal::format::Aspects,
+) {
+    match node_clear {
+        Some(node_clear) => (Some(node_clear.raw()), node_clear.aspects() & aspects),
+        None if initial && image_clear.is_some() => (image_clear, aspects),
+        None => (None, rendy_core::hal::format::Aspects::empty()),
+    }
+}
+
+/// Optional image is resolved if one of the nodes added before uses it.
+/// Those nodes become dependencies of the node.
+fn resolve_nodes<B: Backend, T: ?Sized>(nodes: &[Box<dyn NodeBuilder<B, T>>]) -> Vec<ResolvedNode> {
+    let mut resolved: Vec<ResolvedNode> = nodes
+        .iter()
+        .enumerate()
+        .map(|(index, builder)| {
+            let mut resolved = ResolvedNode::default();
+            for (id, access) in builder.optional_images() {
+                let producers: Vec<usize> = nodes[..index]
+                    .iter()
+                    .enumerate()
+                    .filter(|(_, other)| other.images().iter().any(|&(other, _)| other == id))
+                    .map(|(producer, _)| producer)
+                    .collect();
+
+                if producers.is_empty() {
+                    log::debug!("Optional image {:?} of node {} has no producer", id, index);
+                    continue;
+                }
+
+                resolved.optional_images.push((id, access));
+                resolved.dependencies.extend(producers);
+            }
+
+            resolved.dependencies.sort();
+            resolved.dependencies.dedup();
+            resolved
+        })
+        .collect();
+
+    let accesses = image_accesses(nodes, &resolved);
+    for (resolved, initial) in resolved.iter_mut().zip(initial_accesses(&accesses)) {
+        resolved.initial_images = initial;
+    }
+
+    resolved
+}
+
+/// Images each node accesses with accessed subresources.
+/// `None` range stands for whole image.
+fn image_accesses<B: Backend, T: ?Sized>(
+    nodes: &[Box<dyn NodeBuilder<B, T>>],
+    resolved: &[ResolvedNode],
+) -> Vec<Vec<(ImageId, Option<rendy_core::hal::image::SubresourceRange>)>> {
+    nodes
+        .iter()
+        .zip(resolved)
+        .map(|(builder, resolved)| {
+            let subresources = builder.image_subresources();
+            builder
+                .images()
+                .into_iter()
+                .chain(resolved.optional_images.iter().cloned())
+                .map(|(id, _)| {
+                    let range = subresources
+                        .iter()
+                        .find(|&&(other, _)| other == id)
+                        .map(|(_, range)| range.clone());
+                    (id, range)
+                })
+                .collect()
+        })
+        .collect()
+}
+
+/// Check if two subresource ranges have common subresources.
+fn subresources_overlap(
+    a: &rendy_core::hal::image::SubresourceRange,
+    b: &rendy_core::hal::image::SubresourceRange,
+) -> bool {
+    a.aspects.intersects(b.aspects)
+        && a.levels.start < b.levels.end
+        && b.levels.start < a.levels.end
+        && a.layers.start < b.layers.end
+        && b.layers.start < a.layers.end
+}
+
+/// Find images that each node accesses first.
+/// Node accesses image first if no node before accesses overlapping subresources.
+/// `None` range stands for whole image.
+fn initial_accesses(
+    nodes: &[Vec<(ImageId, Option<rendy_core::hal::image::SubresourceRange>)>],
+) -> Vec<Vec<ImageId>> {
+    nodes
+        .iter()
+        .enumerate()
+        .map(|(index, images)| {
+            images
+                .iter()
+                .filter(|access| {
+                    !nodes[..index]
+                        .iter()
+                        .flatten()
+                        .any(|other| accesses_overlap(access, other))
+                })
+                .map(|&(id, _)| id)
+                .collect()
+        })
+        .collect()
+}
+
+/// Check if two accesses touch common subresources of the same image.
+fn accesses_overlap(
+    (id, range): &(ImageId, Option<rendy_core::hal::image::SubresourceRange>),
+    (other, other_range): &(ImageId, Option<rendy_core::hal::image::SubresourceRange>),
+) -> bool {
+    id == other
+        && match (range, other_range) {
+            (Some(a), Some(b)) => subresources_overlap(a, b),
+            _ => true,
+        }
+}
+
+/// Find dependency cycles. `dependencies[i]` lists nodes that node `i` depends on.
+/// In each cycle a node depends on the next one and the last node on the first.
+fn find_cycles(dependencies: &[Vec<usize>]) -> Vec<Vec<usize>> {
+    #[derive(Clone, Copy, PartialEq)]
+    enum Mark {
+        New,
+        Visiting,
+        Done,
+    }
+
+    fn visit(
+        node: usize,
+        dependencies: &[Vec<usize>],
+        marks: &mut [Mark],
+        path: &mut Vec<usize>,
+        cycles: &mut Vec<Vec<usize>>,
+    ) {
+        marks[node] = Mark::Visiting;
+        path.push(node);
+        for &dependency in &dependencies[node] {
+            match marks[dependency] {
+                Mark::New => visit(dependency, dependencies, marks, path, cycles),
+                Mark::Visiting => {
+                    let start = path.iter().position(|&n| n == dependency).unwrap();
+                    cycles.push(path[start..].to_vec());
+                }
+                Mark::Done => {}
+            }
+        }
+        path.pop();
+        marks[node] = Mark::Done;
+    }
+
+    let mut marks = vec![Mark::New; dependencies.len()];
+    let mut cycles = Vec::new();
+    for node in 0..dependencies.len() {
+        if marks[node] == Mark::New {
+            visit(node, dependencies, &mut marks, &mut Vec::new(), &mut cycles);
+        }
+    }
+    cycles
+}
+
+/// Find indices in `0..count` that `used` doesn't yield.
+fn unused(count: usize, used: impl IntoIterator<Item = usize>) -> Vec<usize> {
+    let mut is_used = vec![false; count];
+    for index in used {
+        if let Some(is_used) = is_used.get_mut(index) {
+            *is_used = true;
+        }
+    }
+    (0..count).filter(|&index| !is_used[index]).collect()
+}
+
+/// Find cleared images which multiple nodes may access first.
+/// Node may access image first if none of the nodes it depends on, directly or not,
+/// accesses overlapping subresources.
+/// Such nodes aren't ordered and each of them would clear the image.
+fn multiple_clears(
+    accesses: &[Vec<(ImageId, Option<rendy_core::hal::image::SubresourceRange>)>],
+    dependencies: &[Vec<usize>],
+    cleared: impl Fn(ImageId) -> bool,
+) -> Vec<(ImageId, Vec<usize>)> {
+    // Nodes each node depends on through any number of other nodes.
+    let ancestors: Vec<Vec<bool>> = (0..dependencies.len())
+        .map(|node| {
+            let mut ancestors = vec![false; dependencies.len()];
+            let mut stack = dependencies[node].clone();
+            while let Some(dependency) = stack.pop() {
+                if !ancestors[dependency] {
+                    ancestors[dependency] = true;
+                    stack.extend(&dependencies[dependency]);
+                }
+            }
+            ancestors
+        })
+        .collect();
+
+    let first: Vec<(usize, &(ImageId, Option<_>))> = accesses
+        .iter()
+        .enumerate()
+        .flat_map(|(node, images)| images.iter().map(move |access| (node, access)))
+        .filter(|&(node, access)| {
+            cleared(access.0)
+                && !accesses.iter().enumerate().any(|(other, images)| {
+                    ancestors[node][other]
+                        && images
+                            .iter()
+                            .any(|other_access| accesses_overlap(access, other_access))
+                })
+        })
+        .collect();
+
+    let mut clears: Vec<(ImageId, Vec<usize>)> = Vec::new();
+    for &(node, access) in &first {
+        let ambiguous = first
+            .iter()
+            .any(|&(other, other_access)| other != node && accesses_overlap(access, other_access));
+        if !ambiguous {
+            continue;
+        }
+        match clears.iter_mut().find(|(image, _)| *image == access.0) {
+            Some((_, nodes)) => {
+                if !nodes.contains(&node) {
+                    nodes.push(node);
+                }
+            }
+            None => clears.push((access.0, vec![node])),
+        }
+    }
+    clears
+}
+
+/// Submissions that access the image within a frame.
+#[derive(Clone, Debug)]
+struct ImageLifetime {
+    image: usize,
+    queue: chain::QueueId,
+    first: usize,
+    last: usize,
+}
+
+/// Find lifetimes of images that can be aliased.
+/// Those are images accessed from single queue and written first.
+fn image_lifetimes(chains: &chain::Chains, relative_images: &[ImageId]) -> Vec<ImageLifetime> {
+    use chain::AccessFlags as _;
+
+    let mut lifetimes: Vec<_> = chains
+        .images
+        .iter()
+        .filter(|(id, _)| !relative_images.iter().any(|image| image.0 == id.0))
+        .filter_map(|(id, chain)| {
+            let links = chain.links();
+            if !links.first()?.access().exclusive() {
+                return None;
+            }
+
+            let mut queues = links.iter().flat_map(|link| link.queues());
+            let (queue, state) = queues.next()?;
+            let mut lifetime = ImageLifetime {
+                image: id.0,
+                queue,
+                first: state.first,
+                last: state.last,
+            };
+            for (other, state) in queues {
+                if other != queue {
+                    return None;
+                }
+                lifetime.first = lifetime.first.min(state.first);
+                lifetime.last = lifetime.last.max(state.last);
+            }
+            Some(lifetime)
+        })
+        .collect();
+    lifetimes.sort_by_key(|lifetime| lifetime.image);
+    lifetimes
+}
+
+/// Group images so that lifetimes of images in a group don't overlap.
+/// Returns groups of at least two images, ordered by submission.
+fn alias_groups(mut lifetimes: Vec<ImageLifetime>) -> Vec<Vec<usize>> {
+    lifetimes.sort_by_key(|lifetime| (lifetime.first, lifetime.image));
+
+    let mut groups: Vec<(chain::QueueId, usize, Vec<usize>)> = Vec::new();
+    for lifetime in lifetimes {
+        match groups
+            .iter_mut()
+            .find(|(queue, last, _)| *queue == lifetime.queue && *last < lifetime.first)
+        {
+            Some((_, last, images)) => {
+                *last = lifetime.last;
+                images.push(lifetime.image);
+            }
+            None => groups.push((lifetime.queue, lifetime.last, vec![lifetime.image])),
+        }
+    }
+
+    groups
+        .into_iter()
+        .map(|(_, _, images)| images)
+        .filter(|images| images.len() > 1)
+        .collect()
+}
+
+/// Make first access of each aliased image wait for the last access of the image
+/// that used the memory before it.
+/// The first image of a group follows the last one from the previous frame.
+fn alias_barriers(
+    schedule: &mut chain::Schedule<chain::SyncData<usize, usize>>,
+    chains: &chain::Chains,
+    aliased: &[Vec<usize>],
+) {
+    for group in aliased {
+        for (index, &image) in group.iter().enumerate() {
+            let prev = group[(index + group.len() - 1) % group.len()];
+            let prev_state = chains.images[&chain::Id(prev)]
+                .links()
+                .last()
+                .expect("Chain has at least one link")
+                .state();
+
+            let link = &chains.images[&chain::Id(image)].links()[0];
+            let (queue, state) = link.queues().next().expect("Link has at least one queue");
+            let sid = chain::SubmissionId::new(queue, state.first);
+            let next = link.submission_state(sid);
+
+            let barrier = schedule
+                .submission_mut(sid)
+                .expect("Submission exists")
+                .sync_mut()
+                .acquire
+                .images
+                .entry(chain::Id(image))
+                .or_insert_with(|| chain::Barrier {
+                    families: None,
+                    states: (
+                        rendy_core::hal::image::Access::empty(),
+                        rendy_core::hal::image::Layout::Undefined,
+                        rendy_core::hal::pso::PipelineStage::TOP_OF_PIPE,
+                    )..(next.access, next.layout, next.stages),
+                });
+
+            // Image is in undefined layout at the first access,
+            // only memory access of the previous image must be finished.
+            barrier.states.start.0 |= prev_state.access;
+            barrier.states.start.2 |= prev_state.stages;
+        }
+    }
+}
+
+/// Create view of the image subresource.
+fn create_subresource_view<B: Backend>(
+    factory: &Factory<B>,
+    image: &Handle<Image<B>>,
+    range: rendy_core::hal::image::SubresourceRange,
+) -> Result<Escape<ImageView<B>>, ImageViewCreationError> {
+    let single = range.layers.end - range.layers.start == 1;
+    let view_kind = match image.kind() {
+        rendy_core::hal::image::Kind::D1(..) if single => rendy_core::hal::image::ViewKind::D1,
+        rendy_core::hal::image::Kind::D1(..) => rendy_core::hal::image::ViewKind::D1Array,
+        rendy_core::hal::image::Kind::D2(..) if single => rendy_core::hal::image::ViewKind::D2,
+        rendy_core::hal::image::Kind::D2(..) => rendy_core::hal::image::ViewKind::D2Array,
+        rendy_core::hal::image::Kind::D3(..) => rendy_core::hal::image::ViewKind::D3,
+    };
+
+    factory.create_image_view(
+        image.clone(),
+        ImageViewInfo {
+            view_kind,
+            format: image.format(),
+            swizzle: rendy_core::hal::format::Swizzle::NO,
+            range,
+        },
+    )
+}
+
+/// Check image barriers of the submission against image layouts tracked so far.
+/// Panics on mismatch.
+#[cfg(feature = "debug-layout-tracking")]
+fn track_layouts<B: Backend>(
+    ctx: &GraphContext<B>,
+    submission: &chain::Submission<chain::SyncData<usize, usize>>,
+) {
+    use rendy_core::hal::image::Layout;
+
+    let track = |side: &str, id: chain::Id, layouts: std::ops::Range<Layout>| {
+        let image = ctx
+            .get_image(ImageId(id.0, ctx.generation))
+            .expect("Image referenced from at least one node must be instantiated");
+        let range = rendy_core::hal::image::SubresourceRange {
+            aspects: image.format().surface_desc().aspects,
+            levels: 0..image.levels(),
+            layers: 0..image.layers(),
+        };
+        if let Err(err) = image.track_layout_transition(&range, layouts) {
+            panic!(
+                "Node {} {} barrier of image {} doesn't match tracked layout. {}",
+                submission.node(),
+                side,
+                id.0,
+                err
+            );
+        }
+    };
+
+    let sync = submission.sync();
+    for (&id, barrier) in &sync.acquire.images {
+        let link = submission.image_link_index(id);
+        if link == 0 {
+            // Same as in `build_node`, first link discards content.
+            track("acquire", id, Layout::Undefined..barrier.states.end.1);
+        } else if barrier.families.is_none() {
+            track("acquire", id, barrier.states.start.1..barrier.states.end.1);
+        }
+        // Acquire side of ownership transfer repeats layout transition of release side.
+    }
+    for (&id, barrier) in &sync.release.images {
+        track("release", id, barrier.states.start.1..barrier.states.end.1);
     }
 }
 
 fn build_node<'a, B: Backend, T: ?Sized>(
     ctx: &GraphContext<B>,
     builder: Box<dyn NodeBuilder<B, T>>,
+    resolved: &ResolvedNode,
     factory: &mut Factory<B>,
     family: &mut rendy_command::Family<B>,
     queue: usize,
     aux: &T,
     chains: &chain::Chains,
     submission: &chain::Submission<chain::SyncData<usize, usize>>,
+    initialized: &[ImageId],
 ) -> Result<Box<dyn DynNode<B, T>>, NodeBuildError> {
     let mut buffer_ids: Vec<_> = builder.buffers().into_iter().map(|(id, _)| id).collect();
     buffer_ids.sort();
@@ -548,42 +2627,107 @@ fn build_node<'a, B: Backend, T: ?Sized>(
         })
         .collect();
 
-    let mut image_ids: Vec<_> = builder.images().into_iter().map(|(id, _)| id).collect();
+    let mut image_ids: Vec<_> = builder
+        .images()
+        .into_iter()
+        .chain(resolved.optional_images.iter().cloned())
+        .map(|(id, _)| id)
+        .collect();
     image_ids.sort();
     image_ids.dedup();
 
+    let subresources = builder.image_subresources();
+    let storage_images = builder.storage_images();
+    let sampled_images = builder.sampled_images();
+
+    // Clears requested by the node override clear values of images.
+    let clears = builder.image_clears();
+    for &(id, clear) in &clears {
+        let image = ctx
+            .get_image(id)
+            .expect("Image referenced from at least one node must be instantiated");
+        clear.check(image.format()).map_err(NodeBuildError::Clear)?;
+    }
+
+    let discarded = builder.discarded_images();
+
     let images: Vec<_> = image_ids
         .into_iter()
-        .map(|id| {
+        .filter_map(|id| {
             let chain_id = chain::Id(id.0);
             let sync = submission.sync();
-            let link = submission.image_link_index(chain_id);
+            let link = submission.try_image_link_index(chain_id)?;
+            if discarded.contains(&id) && link + 1 < chains.images[&chain_id].links().len() {
+                log::warn!(
+                    "Content of image {:?} is discarded by node {:?} but later nodes use it",
+                    id,
+                    submission.node()
+                );
+            }
             let (image, clear) = ctx
                 .get_image_with_clear(id)
                 .expect("Image referenced from at least one node must be instantiated");
-            NodeImage {
+            // Subresources not accessed by preceding nodes are in undefined state
+            // even if other subresources of the image were accessed.
+            // Content of images with initial data is kept.
+            let initial =
+                (link == 0 || resolved.initial_images.contains(&id)) && !initialized.contains(&id);
+            let subresource = subresources
+                .iter()
+                .find(|&&(other, _)| other == id)
+                .map(|(_, range)| range.clone());
+            let view = match &subresource {
+                Some(range) => Some(
+                    ctx.find_image_view(id, range)
+                        .expect("Views are created for all subresources"),
+                ),
+                None if storage_images.contains(&id) => Some(
+                    ctx.find_image_view(id, &storage_range(image))
+                        .expect("Views are created for all storage images"),
+                ),
+                None if sampled_images.contains(&id) => Some(
+                    ctx.find_image_view(id, &sampled_range(image))
+                        .expect("Views are created for all sampled images"),
+                ),
+                None => None,
+            };
+            let range = subresource.unwrap_or_else(|| rendy_core::hal::image::SubresourceRange {
+                aspects: image.format().surface_desc().aspects,
+                levels: 0..image.levels(),
+                layers: 0..image.layers(),
+            });
+            let (clear, clear_aspects) = node_image_clear(
+                clears
+                    .iter()
+                    .find(|&&(other, _)| other == id)
+                    .map(|&(_, clear)| clear),
+                initial,
+                clear,
+                range.aspects,
+            );
+            Some(NodeImage {
                 id,
-                range: rendy_core::hal::image::SubresourceRange {
-                    aspects: image.format().surface_desc().aspects,
-                    levels: 0..image.levels(),
-                    layers: 0..image.layers(),
-                },
+                view,
+                range,
                 layout: chains.images[&chain_id].links()[link]
                     .submission_state(submission.id())
                     .layout,
-                clear: if link == 0 { clear } else { None },
+                clear,
+                clear_aspects,
                 acquire: sync.acquire.images.get(&chain_id).map(
                     |chain::Barrier { states, families }| ImageBarrier {
                         states: (
                             states.start.0,
-                            if link == 0 {
+                            if initial {
                                 rendy_core::hal::image::Layout::Undefined
                             } else {
                                 states.start.1
                             },
                         )..(states.end.0, states.end.1),
                         stages: states.start.2..states.end.2,
-                        families: families.clone(),
+                        // Content of undefined image is discarded,
+                        // ownership needn't be transferred.
+                        families: if initial { None } else { families.clone() },
                     },
                 ),
                 release: sync.release.images.get(&chain_id).map(
@@ -593,15 +2737,263 @@ fn build_node<'a, B: Backend, T: ?Sized>(
                         families: families.clone(),
                     },
                 ),
-            }
+            })
         })
         .collect();
     builder.build(ctx, factory, family, queue, aux, buffers, images)
 }
 
+/// Kind of image reallocated with new `extent`.
+/// Layers and samples are preserved.
+fn resized_kind(
+    kind: rendy_core::hal::image::Kind,
+    extent: rendy_core::hal::window::Extent2D,
+) -> rendy_core::hal::image::Kind {
+    use rendy_core::hal::image::Kind;
+
+    match kind {
+        Kind::D1(_, layers) => Kind::D1(extent.width, layers),
+        Kind::D2(_, _, layers, samples) => Kind::D2(extent.width, extent.height, layers, samples),
+        Kind::D3(_, _, depth) => Kind::D3(extent.width, extent.height, depth),
+    }
+}
+
+/// Indices of nodes that must be updated after `images` are reallocated.
+/// Each node comes with its images and whether its swapchain must be recreated.
+/// Other nodes are left intact with their pipelines.
+fn resized_nodes<'a>(
+    nodes: impl IntoIterator<Item = (&'a [ImageId], bool)>,
+    images: &[ImageId],
+) -> Vec<usize> {
+    nodes
+        .into_iter()
+        .enumerate()
+        .filter(|(_, (node_images, swapchain))| {
+            *swapchain || node_images.iter().any(|id| images.contains(id))
+        })
+        .map(|(index, _)| index)
+        .collect()
+}
+
+/// Images relative to the `surface`.
+fn images_of_surface(surface_images: &[(SurfaceId, ImageId)], surface: SurfaceId) -> Vec<ImageId> {
+    surface_images
+        .iter()
+        .filter(|&&(other, _)| other == surface)
+        .map(|&(_, id)| id)
+        .collect()
+}
+
+/// Semaphores submission at `position` among `count` submissions of the frame
+/// waits and signals.
+/// External semaphores are waited by the first submission and signaled by the last one.
+fn with_external_sync<'a, S>(
+    position: usize,
+    count: usize,
+    waits: impl IntoIterator<Item = (&'a S, rendy_core::hal::pso::PipelineStage)>,
+    signals: impl IntoIterator<Item = &'a S>,
+    external_waits: &[(&'a S, rendy_core::hal::pso::PipelineStage)],
+    external_signals: &[&'a S],
+) -> (
+    smallvec::SmallVec<[(&'a S, rendy_core::hal::pso::PipelineStage); 16]>,
+    smallvec::SmallVec<[&'a S; 16]>,
+) {
+    let mut waits: smallvec::SmallVec<[_; 16]> = waits.into_iter().collect();
+    let mut signals: smallvec::SmallVec<[_; 16]> = signals.into_iter().collect();
+    if position == 0 {
+        waits.extend(external_waits.iter().cloned());
+    }
+    if position + 1 == count {
+        signals.extend(external_signals.iter().cloned());
+    }
+    (waits, signals)
+}
+
+/// Outcome of the frame that failed to wait for or acquire fences.
+/// Marks factory lost if device is lost.
+fn lost_or_oom<B: Backend>(
+    factory: &Factory<B>,
+    err: rendy_core::hal::device::OomOrDeviceLost,
+) -> Result<PresentResult, rendy_core::hal::device::OutOfMemory> {
+    match err {
+        rendy_core::hal::device::OomOrDeviceLost::DeviceLost(_) => {
+            factory.mark_device_lost();
+            Ok(PresentResult::DeviceLost)
+        }
+        rendy_core::hal::device::OomOrDeviceLost::OutOfMemory(err) => Err(err),
+    }
+}
+
+/// Index of each submission's fence among fences of the frame, in `order`.
+/// Fence of the last submission on the queue covers all previous ones,
+/// so others get `None` unless `fence_every_node` is set.
+fn fence_slots<Q: PartialEq>(
+    order: &[usize],
+    queues: &[Q],
+    fence_every_node: bool,
+) -> smallvec::SmallVec<[Option<usize>; 32]> {
+    let mut next = 0;
+    (0..order.len())
+        .map(|position| {
+            if fence_every_node || last_in_queue(order, queues, position) {
+                next += 1;
+                Some(next - 1)
+            } else {
+                None
+            }
+        })
+        .collect()
+}
+
+/// Frame that must be complete before `next` frame is run.
+fn throttle_frame(next: Frame, frames_in_flight: u32) -> Option<Frame> {
+    next.index()
+        .checked_sub(frames_in_flight as u64)
+        .map(Frame::with_index)
+}
+
+/// Slot of per-frame resources used by `frame`
+/// when there is one slot for each of `frames_in_flight`.
+/// Slot is reused only after frame that used it before is complete.
+fn frame_slot(frame: Frame, frames_in_flight: usize) -> usize {
+    (frame.index() % frames_in_flight as u64) as usize
+}
+
+/// Subresource of the image bound as storage image.
+/// Storage image views must have single level.
+fn storage_range<B: Backend>(image: &Image<B>) -> rendy_core::hal::image::SubresourceRange {
+    rendy_core::hal::image::SubresourceRange {
+        aspects: shader_view_aspects(image.format()),
+        levels: 0..1,
+        layers: 0..image.layers(),
+    }
+}
+
+/// Subresource of the image bound as sampled image.
+fn sampled_range<B: Backend>(image: &Image<B>) -> rendy_core::hal::image::SubresourceRange {
+    rendy_core::hal::image::SubresourceRange {
+        aspects: shader_view_aspects(image.format()),
+        levels: 0..image.levels(),
+        layers: 0..image.layers(),
+    }
+}
+
+/// Aspects of the image viewed by shaders.
+/// Shader views of depth-stencil images must have single aspect, depth is used.
+fn shader_view_aspects(
+    format: rendy_core::hal::format::Format,
+) -> rendy_core::hal::format::Aspects {
+    use rendy_core::hal::format::Aspects;
+
+    let aspects = format.surface_desc().aspects;
+    if aspects.contains(Aspects::DEPTH | Aspects::STENCIL) {
+        Aspects::DEPTH
+    } else {
+        aspects
+    }
+}
+
+/// State of the buffer for the chain node accessing it with `access`.
+fn buffer_state(access: &BufferAccess) -> chain::BufferState {
+    chain::BufferState {
+        access: access.access,
+        stages: access.stages,
+        layout: (),
+        usage: access.usage,
+    }
+}
+
+/// Queue, stages and access of the first nodes accessing the buffer.
+/// Initial data upload makes content available to them.
+fn upload_target(
+    chain: &chain::Chain<chain::Buffer>,
+) -> (
+    chain::QueueId,
+    rendy_core::hal::pso::PipelineStage,
+    rendy_core::hal::buffer::Access,
+) {
+    let link = &chain.links()[0];
+    let (queue, _) = link
+        .queues()
+        .next()
+        .expect("Link is associated with at least one queue");
+    let state = link.state();
+    (queue, state.stages, state.access)
+}
+
+/// Queue, stages, access and layout the image with initial data is uploaded into.
+/// This is the state the first nodes accessing the image expect at the beginning of each frame.
+///
+/// Returns `None` if the image is transferred from another queue family
+/// at the beginning of the frame, as there is no release of the image before the first frame.
+fn image_upload_target<S, W>(
+    id: chain::Id,
+    chain: &chain::Chain<chain::Image>,
+    schedule: &chain::Schedule<chain::SyncData<S, W>>,
+) -> Option<(
+    chain::QueueId,
+    rendy_core::hal::pso::PipelineStage,
+    rendy_core::hal::image::Access,
+    rendy_core::hal::image::Layout,
+)> {
+    let link = &chain.links()[0];
+    let acquire = link.queues().find_map(|(queue, state)| {
+        let sid = chain::SubmissionId::new(queue, state.first);
+        schedule[sid]
+            .sync()
+            .acquire
+            .images
+            .get(&id)
+            .map(|barrier| (queue, barrier))
+    });
+
+    match acquire {
+        Some((_, barrier)) if barrier.families.is_some() => None,
+        Some((queue, barrier)) => {
+            let (access, layout, stages) = barrier.states.start;
+            Some((queue, stages, access, layout))
+        }
+        None => {
+            // Previous frame transitions the image in its last submission.
+            let (queue, _) = link
+                .queues()
+                .next()
+                .expect("Link is associated with at least one queue");
+            let state = link.state();
+            Some((queue, state.stages, state.access, state.layout))
+        }
+    }
+}
+
+/// Size in bytes of tightly packed content of all layers of the image's first level.
+fn image_data_size(
+    kind: rendy_core::hal::image::Kind,
+    format: rendy_core::hal::format::Format,
+) -> u64 {
+    let desc = format.surface_desc();
+    let extent = kind.extent();
+    let blocks = |size: u32, dim: u8| ((size + dim as u32 - 1) / dim as u32) as u64;
+    blocks(extent.width, desc.dim.0)
+        * blocks(extent.height, desc.dim.1)
+        * extent.depth as u64
+        * kind.num_layers() as u64
+        * (desc.bits as u64 / 8)
+}
+
+/// State of the image for the chain node accessing it with `access`.
+fn image_state(access: &ImageAccess) -> chain::ImageState {
+    chain::ImageState {
+        access: access.access,
+        stages: access.stages,
+        layout: access.layout,
+        usage: access.usage,
+    }
+}
+
 fn make_chain_node<B, T>(
     builder: &dyn NodeBuilder<B, T>,
     id: usize,
+    resolved: &ResolvedNode,
     factory: &mut Factory<B>,
     families: &Families<B>,
 ) -> chain::Node
@@ -611,37 +3003,762 @@ where
 {
     let buffers = builder.buffers();
     let images = builder.images();
+    let mut dependencies: Vec<usize> = builder
+        .dependencies()
+        .into_iter()
+        .map(|id| id.0)
+        .chain(resolved.dependencies.iter().cloned())
+        .collect();
+    dependencies.sort();
+    dependencies.dedup();
+
     chain::Node {
         id,
-        family: QueueFamilyId(builder.family(factory, families).unwrap().index),
-        dependencies: builder.dependencies().into_iter().map(|id| id.0).collect(),
+        family: QueueFamilyId(
+            builder
+                .family(factory, families)
+                .expect("Graph is validated before scheduling")
+                .index,
+        ),
+        dependencies,
         buffers: buffers
             .into_iter()
-            .map(|(id, access)| {
-                (
-                    chain::Id(id.0),
-                    chain::BufferState {
-                        access: access.access,
-                        stages: access.stages,
-                        layout: (),
-                        usage: access.usage,
-                    },
-                )
-            })
+            .map(|(id, access)| (chain::Id(id.0), buffer_state(&access)))
             .collect(),
         images: images
             .into_iter()
-            .map(|(id, access)| {
-                (
-                    chain::Id(id.0),
-                    chain::ImageState {
-                        access: access.access,
-                        stages: access.stages,
-                        layout: access.layout,
-                        usage: access.usage,
-                    },
-                )
-            })
+            .chain(resolved.optional_images.iter().cloned())
+            .map(|(id, access)| (chain::Id(id.0), image_state(&access)))
             .collect(),
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use {
+        super::{
+            alias_groups, buffer_state, fence_slots, find_cycles, foreign_ids, frame_slot,
+            image_data_size, image_lifetimes, image_state, image_upload_target, images_of_surface,
+            initial_accesses, multiple_clears, next_generation, node_image_clear, resized_kind,
+            resized_nodes, shader_view_aspects, throttle_frame, unused, upload_target, GraphError,
+            ImageLifetime,
+        },
+        crate::{
+            chain,
+            frame::Frame,
+            node::{BufferAccess, ImageAccess, NodeClear},
+            BufferId, ImageId, NodeId, SurfaceId,
+        },
+        rendy_core::hal::{format::Aspects, image::SubresourceRange, queue::QueueFamilyId},
+    };
+
+    fn lifetime(image: usize, queue: usize, submissions: std::ops::Range<usize>) -> ImageLifetime {
+        ImageLifetime {
+            image,
+            queue: chain::QueueId::new(QueueFamilyId(0), queue),
+            first: submissions.start,
+            last: submissions.end - 1,
+        }
+    }
+
+    fn face(layer: u16) -> Option<SubresourceRange> {
+        Some(SubresourceRange {
+            aspects: Aspects::COLOR,
+            levels: 0..1,
+            layers: layer..layer + 1,
+        })
+    }
+
+    #[test]
+    fn cubemap_faces() {
+        let cube = ImageId(0, 0);
+
+        // Six nodes render into separate faces and the last one samples whole cubemap.
+        let mut nodes: Vec<_> = (0..6).map(|layer| vec![(cube, face(layer))]).collect();
+        nodes.push(vec![(cube, None)]);
+
+        let initial = initial_accesses(&nodes);
+        for faces in &initial[..6] {
+            assert_eq!(faces, &[cube]);
+        }
+        assert!(initial[6].is_empty());
+    }
+
+    #[test]
+    fn overlapping_subresources() {
+        let image = ImageId(0, 0);
+        let layers = Some(SubresourceRange {
+            aspects: Aspects::COLOR,
+            levels: 0..1,
+            layers: 0..3,
+        });
+
+        let initial = initial_accesses(&[
+            vec![(image, layers)],
+            vec![(image, face(2))],
+            vec![(image, face(3))],
+            vec![(ImageId(1, 0), face(0))],
+        ]);
+        assert_eq!(initial[0], &[image]);
+        assert!(initial[1].is_empty());
+        assert_eq!(initial[2], &[image]);
+        assert_eq!(initial[3], &[ImageId(1, 0)]);
+    }
+
+    #[test]
+    fn disjoint_images_are_aliased() {
+        // Image 1 is used before image 0 and both are done before image 2.
+        let groups = alias_groups(vec![
+            lifetime(0, 0, 2..4),
+            lifetime(1, 0, 0..2),
+            lifetime(2, 0, 4..5),
+        ]);
+        assert_eq!(groups, vec![vec![1, 0, 2]]);
+    }
+
+    #[test]
+    fn overlapping_images_are_not_aliased() {
+        let groups = alias_groups(vec![
+            lifetime(0, 0, 0..3),
+            lifetime(1, 0, 2..4),
+            // Disjoint but used on another queue.
+            lifetime(2, 1, 3..4),
+            lifetime(3, 0, 3..5),
+        ]);
+        assert_eq!(groups, vec![vec![0, 3]]);
+    }
+
+    #[test]
+    fn scheduled_images_with_disjoint_lifetimes_share_memory() {
+        use {
+            rendy_core::hal::{
+                image::{Access, Layout, Usage},
+                pso::PipelineStage,
+            },
+            std::collections::HashMap,
+        };
+
+        let write = ImageAccess {
+            access: Access::COLOR_ATTACHMENT_WRITE,
+            usage: Usage::COLOR_ATTACHMENT,
+            layout: Layout::ColorAttachmentOptimal,
+            stages: PipelineStage::COLOR_ATTACHMENT_OUTPUT,
+        };
+        let read = ImageAccess::sampled(PipelineStage::FRAGMENT_SHADER);
+        let node = |id, images: Vec<(usize, ImageAccess)>| chain::Node {
+            id,
+            family: QueueFamilyId(0),
+            dependencies: (0..id).collect(),
+            buffers: HashMap::new(),
+            images: images
+                .into_iter()
+                .map(|(image, access)| (chain::Id(image), image_state(&access)))
+                .collect(),
+        };
+
+        // Image 2 is written while image 0 is read and read while image 1 is written.
+        let nodes = vec![
+            node(0, vec![(0, write)]),
+            node(1, vec![(0, read), (2, write)]),
+            node(2, vec![(2, read), (1, write)]),
+            node(3, vec![(1, read)]),
+        ];
+        let chains = chain::collect(nodes, |_| 1);
+
+        let lifetimes = image_lifetimes(&chains, &[]);
+        assert_eq!(lifetimes.len(), 3);
+        assert_eq!(alias_groups(lifetimes), vec![vec![0, 1]]);
+
+        // Swapchain-relative images are not aliased.
+        let lifetimes = image_lifetimes(&chains, &[ImageId(1, 0)]);
+        assert!(alias_groups(lifetimes).is_empty());
+    }
+
+    #[test]
+    fn dependency_cycles_are_found() {
+        assert!(find_cycles(&[vec![], vec![0], vec![0, 1]]).is_empty());
+        assert_eq!(
+            find_cycles(&[vec![1], vec![2], vec![0]]),
+            vec![vec![0, 1, 2]]
+        );
+        assert_eq!(find_cycles(&[vec![], vec![1]]), vec![vec![1]]);
+    }
+
+    #[test]
+    fn unused_resources_are_found() {
+        assert_eq!(unused(4, vec![0, 2, 2]), vec![1, 3]);
+        assert!(unused(2, vec![1, 0]).is_empty());
+    }
+
+    #[test]
+    fn unordered_nodes_clear_image() {
+        let image = ImageId(0, 0);
+        let nodes = vec![
+            vec![(image, None)],
+            vec![(image, None)],
+            vec![(image, None)],
+        ];
+
+        // Third node is ordered after the second one, but the first one is unordered.
+        let clears = multiple_clears(&nodes, &[vec![], vec![], vec![1]], |_| true);
+        assert_eq!(clears, vec![(image, vec![0, 1])]);
+
+        // Dependencies through other nodes order accesses too.
+        assert!(multiple_clears(&nodes, &[vec![], vec![0], vec![1]], |_| true).is_empty());
+
+        // Image without clear value can't be cleared.
+        assert!(multiple_clears(&nodes, &[vec![], vec![], vec![]], |_| false).is_empty());
+    }
+
+    #[test]
+    fn mid_graph_clear_is_passed_to_node() {
+        let created_with = Some(NodeClear::Float([0.0; 4]).raw());
+        let color = Aspects::COLOR;
+
+        // Image accessed by preceding nodes keeps content unless node requests clear.
+        let (clear, aspects) = node_image_clear(None, false, created_with, color);
+        assert!(clear.is_none());
+        assert_eq!(aspects, Aspects::empty());
+
+        let (clear, aspects) =
+            node_image_clear(Some(NodeClear::Float([1.0; 4])), false, None, color);
+        assert!(clear.is_some());
+        assert_eq!(aspects, color);
+
+        let (clear, aspects) = node_image_clear(None, true, created_with, color);
+        assert!(clear.is_some());
+        assert_eq!(aspects, color);
+
+        // Stencil of depth-stencil image is kept.
+        let (_, aspects) = node_image_clear(
+            Some(NodeClear::Depth(1.0)),
+            false,
+            None,
+            Aspects::DEPTH | Aspects::STENCIL,
+        );
+        assert_eq!(aspects, Aspects::DEPTH);
+    }
+
+    #[test]
+    fn disjoint_subresources_are_cleared_separately() {
+        let cube = ImageId(0, 0);
+        let nodes: Vec<_> = (0..6).map(|layer| vec![(cube, face(layer))]).collect();
+        assert!(multiple_clears(&nodes, &vec![vec![]; 6], |_| true).is_empty());
+    }
+
+    #[test]
+    fn sampled_image_follows_render_target() {
+        use {
+            rendy_core::hal::{
+                image::{Access, Layout, Usage},
+                pso::PipelineStage,
+            },
+            std::collections::HashMap,
+        };
+
+        let node = |id, dependencies, access: ImageAccess| {
+            let mut images = HashMap::new();
+            images.insert(chain::Id(0), image_state(&access));
+            chain::Node {
+                id,
+                family: QueueFamilyId(0),
+                dependencies,
+                buffers: HashMap::new(),
+                images,
+            }
+        };
+
+        // Post-process node samples output of the scene node.
+        let nodes = vec![
+            node(
+                0,
+                vec![],
+                ImageAccess {
+                    access: Access::COLOR_ATTACHMENT_WRITE,
+                    usage: Usage::COLOR_ATTACHMENT,
+                    layout: Layout::ColorAttachmentOptimal,
+                    stages: PipelineStage::COLOR_ATTACHMENT_OUTPUT,
+                },
+            ),
+            node(
+                1,
+                vec![0],
+                ImageAccess::sampled(PipelineStage::FRAGMENT_SHADER),
+            ),
+        ];
+
+        let chains = chain::collect(nodes, |_| 1);
+        let image = &chains.images[&chain::Id(0)];
+        assert_eq!(image.usage(), Usage::COLOR_ATTACHMENT | Usage::SAMPLED);
+
+        let schedule = chain::sync(&chains, || ((), ()));
+        let barrier = schedule
+            .iter()
+            .flat_map(|family| family.iter())
+            .flat_map(|queue| queue.iter())
+            .find(|submission| submission.node() == 0)
+            .unwrap()
+            .sync()
+            .release
+            .images[&chain::Id(0)]
+            .clone();
+        assert_eq!(
+            barrier.states.start.1..barrier.states.end.1,
+            Layout::ColorAttachmentOptimal..Layout::ShaderReadOnlyOptimal
+        );
+        assert_eq!(barrier.states.end.0, Access::SHADER_READ);
+    }
+
+    #[test]
+    fn indirect_dispatch_waits_for_arguments() {
+        use {
+            rendy_core::hal::{
+                buffer::{Access, Usage},
+                pso::PipelineStage,
+            },
+            std::collections::HashMap,
+        };
+
+        let node = |id, dependencies, access: BufferAccess| {
+            let mut buffers = HashMap::new();
+            buffers.insert(chain::Id(0), buffer_state(&access));
+            chain::Node {
+                id,
+                family: QueueFamilyId(0),
+                dependencies,
+                buffers,
+                images: HashMap::new(),
+            }
+        };
+
+        // First compute node writes workgroup counts for the second one.
+        let nodes = vec![
+            node(
+                0,
+                vec![],
+                BufferAccess {
+                    access: Access::SHADER_WRITE,
+                    usage: Usage::STORAGE,
+                    stages: PipelineStage::COMPUTE_SHADER,
+                },
+            ),
+            node(1, vec![0], BufferAccess::indirect()),
+        ];
+
+        let chains = chain::collect(nodes, |_| 1);
+        let buffer = &chains.buffers[&chain::Id(0)];
+        assert_eq!(buffer.usage(), Usage::STORAGE | Usage::INDIRECT);
+
+        let schedule = chain::sync(&chains, || ((), ()));
+        let barrier = schedule
+            .iter()
+            .flat_map(|family| family.iter())
+            .flat_map(|queue| queue.iter())
+            .find(|submission| submission.node() == 0)
+            .unwrap()
+            .sync()
+            .release
+            .buffers[&chain::Id(0)]
+            .clone();
+        assert_eq!(barrier.states.start.0, Access::SHADER_WRITE);
+        assert_eq!(barrier.states.end.0, Access::INDIRECT_COMMAND_READ);
+        assert_eq!(barrier.states.end.2, PipelineStage::DRAW_INDIRECT);
+    }
+
+    #[test]
+    fn mid_graph_nodes_get_fences_on_request() {
+        // Submissions 0 and 2 run on the first queue, 1 on the second.
+        let queues = [0, 1, 0];
+        let slots = |order: &[usize], every| fence_slots(order, &queues, every).into_vec();
+
+        assert_eq!(slots(&[0, 1, 2], false), vec![None, Some(0), Some(1)]);
+        assert_eq!(slots(&[2, 1, 0], false), vec![None, Some(0), Some(1)]);
+        assert_eq!(slots(&[0, 1, 2], true), vec![Some(0), Some(1), Some(2)]);
+        assert!(slots(&[], true).is_empty());
+    }
+
+    #[test]
+    fn run_waits_for_frame_in_flight() {
+        let wait = |next, frames_in_flight| {
+            throttle_frame(Frame::with_index(next), frames_in_flight).map(|frame| frame.index())
+        };
+
+        // First frames are submitted without waiting.
+        assert_eq!(wait(0, 2), None);
+        assert_eq!(wait(1, 2), None);
+
+        // Then each frame waits for the one `frames_in_flight` before it,
+        // so no more than `frames_in_flight` frames are ever pending.
+        assert_eq!(wait(2, 2), Some(0));
+        assert_eq!(wait(7, 2), Some(5));
+        assert_eq!(wait(7, 1), Some(6));
+    }
+
+    #[test]
+    fn per_frame_pools_are_sized_to_frames_in_flight() {
+        let frames_in_flight = 3;
+        let slot = |frame| frame_slot(Frame::with_index(frame), frames_in_flight as usize);
+
+        // Frames that may be in flight together use distinct slots.
+        let mut slots: Vec<_> = (0..frames_in_flight).map(slot).collect();
+        slots.sort();
+        slots.dedup();
+        assert_eq!(slots, vec![0, 1, 2]);
+
+        // Next frame waits for the first one and reuses its slot.
+        let next = frames_in_flight;
+        assert_eq!(
+            throttle_frame(Frame::with_index(next), frames_in_flight as u32)
+                .map(|frame| frame.index()),
+            Some(0)
+        );
+        assert_eq!(slot(next), slot(0));
+    }
+
+    #[test]
+    fn surfaces_are_resized_separately() {
+        let (left, right) = (SurfaceId(0), SurfaceId(1));
+        let surface_images = [
+            (left, ImageId(0, 0)),
+            (right, ImageId(1, 0)),
+            (left, ImageId(3, 0)),
+        ];
+        assert_eq!(
+            images_of_surface(&surface_images, left),
+            vec![ImageId(0, 0), ImageId(3, 0)]
+        );
+        assert_eq!(
+            images_of_surface(&surface_images, right),
+            vec![ImageId(1, 0)]
+        );
+        assert!(images_of_surface(&surface_images, SurfaceId(2)).is_empty());
+    }
+
+    #[test]
+    fn two_windows_are_resized_independently() {
+        let (left, right) = (SurfaceId(0), SurfaceId(1));
+        let (left_color, right_color) = (ImageId(0, 0), ImageId(1, 0));
+        let (left_output, right_output) = (ImageId(2, 0), ImageId(3, 0));
+        let surface_images = [(left, left_color), (right, right_color)];
+
+        // Nodes 0 and 1 render into relative images of each window,
+        // nodes 2 and 3 present fixed-size outputs to the left and right windows.
+        let node_images = [
+            &[left_color][..],
+            &[right_color][..],
+            &[left_output][..],
+            &[right_output][..],
+        ];
+        let acquires = [false, false, true, true];
+        let surfaces = [2, 3];
+
+        let resize_surface = |surface: SurfaceId| {
+            let presenter = surfaces[surface.0];
+            resized_nodes(
+                node_images
+                    .iter()
+                    .enumerate()
+                    .map(|(index, &images)| (images, index == presenter)),
+                &images_of_surface(&surface_images, surface),
+            )
+        };
+        assert_eq!(resize_surface(left), vec![0, 2]);
+        assert_eq!(resize_surface(right), vec![1, 3]);
+
+        // Resizing all windows recreates every swapchain.
+        assert_eq!(
+            resized_nodes(
+                node_images.iter().cloned().zip(acquires.iter().cloned()),
+                &[left_color, right_color],
+            ),
+            vec![0, 1, 2, 3]
+        );
+    }
+
+    #[test]
+    fn resize_keeps_nodes_without_relative_images() {
+        use rendy_core::hal::{image::Kind, window::Extent2D};
+
+        // First node renders into relative image, second one processes fixed-size image.
+        let relative = ImageId(0, 0);
+        let fixed = ImageId(1, 0);
+        let nodes = [(&[relative][..], false), (&[fixed][..], false)];
+
+        assert_eq!(resized_nodes(nodes.iter().cloned(), &[relative]), vec![0]);
+        assert_eq!(
+            resized_nodes(nodes.iter().cloned(), &[]),
+            Vec::<usize>::new()
+        );
+
+        // Swapchain is recreated on window resize even if it has no relative images.
+        let present = [(&[fixed][..], true)];
+        assert_eq!(resized_nodes(present.iter().cloned(), &[relative]), vec![0]);
+        let other_window = [(&[fixed][..], false)];
+        assert_eq!(
+            resized_nodes(other_window.iter().cloned(), &[relative]),
+            Vec::<usize>::new()
+        );
+
+        let extent = Extent2D {
+            width: 640,
+            height: 480,
+        };
+        assert_eq!(
+            resized_kind(Kind::D2(800, 600, 6, 4), extent),
+            Kind::D2(640, 480, 6, 4)
+        );
+        assert_eq!(resized_kind(Kind::D1(800, 2), extent), Kind::D1(640, 2));
+    }
+
+    #[test]
+    fn ids_of_rebuilt_graph_are_foreign() {
+        let (old, new) = (next_generation(), next_generation());
+        assert_ne!(old, new);
+
+        // Node of the rebuilt graph still refers to resources of the old one.
+        let node = NodeId(0, new);
+        let errors = foreign_ids(
+            new,
+            node,
+            vec![BufferId(0, new), BufferId(1, old)],
+            vec![ImageId(0, old), ImageId(0, old), ImageId(2, new)],
+            vec![NodeId(3, old)],
+        );
+        assert_eq!(
+            errors,
+            vec![
+                GraphError::ForeignBuffer {
+                    node,
+                    buffer: BufferId(1, old),
+                },
+                GraphError::ForeignImage {
+                    node,
+                    image: ImageId(0, old),
+                },
+                GraphError::ForeignNode(NodeId(3, old)),
+            ]
+        );
+
+        assert!(foreign_ids(new, node, vec![BufferId(0, new)], vec![], vec![]).is_empty());
+    }
+
+    #[test]
+    fn initial_data_is_uploaded_for_first_access() {
+        use {
+            rendy_core::hal::{
+                buffer::{Access, Usage},
+                pso::PipelineStage,
+            },
+            std::collections::HashMap,
+        };
+
+        let vertices = BufferAccess {
+            access: Access::VERTEX_BUFFER_READ,
+            usage: Usage::VERTEX,
+            stages: PipelineStage::VERTEX_INPUT,
+        };
+        let mut buffers = HashMap::new();
+        buffers.insert(chain::Id(0), buffer_state(&vertices));
+        let chains = chain::collect(
+            vec![chain::Node {
+                id: 0,
+                family: QueueFamilyId(0),
+                dependencies: vec![],
+                buffers,
+                images: HashMap::new(),
+            }],
+            |_| 1,
+        );
+
+        let (queue, stage, access) = upload_target(&chains.buffers[&chain::Id(0)]);
+        assert_eq!(queue, chain::QueueId::new(QueueFamilyId(0), 0));
+        assert_eq!(stage, PipelineStage::VERTEX_INPUT);
+        assert_eq!(access, Access::VERTEX_BUFFER_READ);
+    }
+
+    #[test]
+    fn initial_image_data_is_uploaded_into_frame_start_state() {
+        use {
+            rendy_core::hal::{
+                image::{Access, Layout, Usage},
+                pso::PipelineStage,
+            },
+            std::collections::HashMap,
+        };
+
+        let sampled = ImageAccess {
+            access: Access::SHADER_READ,
+            usage: Usage::SAMPLED,
+            layout: Layout::ShaderReadOnlyOptimal,
+            stages: PipelineStage::FRAGMENT_SHADER,
+        };
+        let storage = ImageAccess {
+            access: Access::SHADER_READ | Access::SHADER_WRITE,
+            usage: Usage::STORAGE,
+            layout: Layout::General,
+            stages: PipelineStage::COMPUTE_SHADER,
+        };
+        let node = |id, family, dependencies, access: &ImageAccess| {
+            let mut images = HashMap::new();
+            images.insert(chain::Id(0), image_state(access));
+            chain::Node {
+                id,
+                family: QueueFamilyId(family),
+                dependencies,
+                buffers: HashMap::new(),
+                images,
+            }
+        };
+        let target = |nodes| {
+            let chains = chain::collect(nodes, |_| 1);
+            let schedule = chain::sync(&chains, || ((), ()));
+            image_upload_target(chain::Id(0), &chains.images[&chain::Id(0)], &schedule)
+                .map(|(_, stages, _, layout)| (stages, layout))
+        };
+
+        // Lookup texture is only sampled.
+        assert_eq!(
+            target(vec![node(0, 0, vec![], &sampled)]),
+            Some((
+                PipelineStage::FRAGMENT_SHADER,
+                Layout::ShaderReadOnlyOptimal
+            ))
+        );
+
+        // First node updates the image in place, it receives the image
+        // in the layout the last node of the previous frame left it in.
+        assert_eq!(
+            target(vec![
+                node(0, 0, vec![], &storage),
+                node(1, 0, vec![0], &sampled)
+            ]),
+            Some((
+                PipelineStage::FRAGMENT_SHADER,
+                Layout::ShaderReadOnlyOptimal
+            ))
+        );
+
+        // The last node transitions the image for the first one.
+        assert_eq!(
+            target(vec![
+                node(0, 0, vec![], &sampled),
+                node(1, 0, vec![0], &storage)
+            ]),
+            Some((
+                PipelineStage::FRAGMENT_SHADER,
+                Layout::ShaderReadOnlyOptimal
+            ))
+        );
+
+        // Ownership of the image is transferred at the beginning of the frame.
+        assert_eq!(
+            target(vec![
+                node(0, 0, vec![], &sampled),
+                node(1, 1, vec![0], &storage)
+            ]),
+            None
+        );
+    }
+
+    #[test]
+    fn shader_views_have_single_aspect() {
+        use rendy_core::hal::format::Format;
+
+        assert_eq!(shader_view_aspects(Format::Rgba8Unorm), Aspects::COLOR);
+        assert_eq!(shader_view_aspects(Format::D32Sfloat), Aspects::DEPTH);
+        assert_eq!(shader_view_aspects(Format::D24UnormS8Uint), Aspects::DEPTH);
+        assert_eq!(shader_view_aspects(Format::S8Uint), Aspects::STENCIL);
+    }
+
+    #[test]
+    fn initial_image_data_size() {
+        use rendy_core::hal::{format::Format, image::Kind};
+
+        assert_eq!(
+            image_data_size(Kind::D2(16, 8, 1, 1), Format::Rgba8Unorm),
+            16 * 8 * 4
+        );
+        assert_eq!(
+            image_data_size(Kind::D2(4, 4, 6, 1), Format::R32Sfloat),
+            4 * 4 * 6 * 4
+        );
+        // Partial blocks take whole block.
+        assert_eq!(
+            image_data_size(Kind::D2(6, 6, 1, 1), Format::Bc1RgbUnorm),
+            2 * 2 * 8
+        );
+        assert_eq!(image_data_size(Kind::D3(2, 2, 2), Format::R8Unorm), 8);
+    }
+
+    #[test]
+    fn external_semaphores_guard_frame_boundaries() {
+        use {super::with_external_sync, rendy_core::hal::pso::PipelineStage};
+
+        let (graph, external_wait, external_signal) = (0u32, 1u32, 2u32);
+        let external_waits = [(&external_wait, PipelineStage::TOP_OF_PIPE)];
+        let external_signals = [&external_signal];
+
+        let sync = |position| {
+            with_external_sync(
+                position,
+                3,
+                Some((&graph, PipelineStage::COLOR_ATTACHMENT_OUTPUT)),
+                Some(&graph),
+                &external_waits,
+                &external_signals,
+            )
+        };
+
+        let (waits, signals) = sync(0);
+        assert_eq!(
+            &waits[..],
+            &[
+                (&graph, PipelineStage::COLOR_ATTACHMENT_OUTPUT),
+                (&external_wait, PipelineStage::TOP_OF_PIPE),
+            ][..]
+        );
+        assert_eq!(&signals[..], &[&graph][..]);
+
+        let (waits, signals) = sync(1);
+        assert_eq!(waits.len(), 1);
+        assert_eq!(signals.len(), 1);
+
+        let (waits, signals) = sync(2);
+        assert_eq!(waits.len(), 1);
+        assert_eq!(&signals[..], &[&graph, &external_signal][..]);
+
+        // Single submission both waits and signals.
+        let (waits, signals) =
+            with_external_sync(0, 1, None, None, &external_waits, &external_signals);
+        assert_eq!(&waits[..], &external_waits[..]);
+        assert_eq!(&signals[..], &external_signals[..]);
+    }
+}
+
+#[cfg(all(test, feature = "empty"))]
+mod device_tests {
+    use {
+        super::GraphBuilder,
+        crate::factory::{init_with_instance, Config},
+        rendy_core::{empty::Backend, hal::Instance as _, Instance},
+    };
+
+    #[test]
+    fn graph_is_torn_down_in_idle_scope() {
+        let instance = rendy_core::empty::Instance::create("rendy", 1).unwrap();
+        let config: Config = Default::default();
+        let (mut factory, mut families) =
+            init_with_instance(Instance::new(instance), &config).unwrap();
+
+        let graph = GraphBuilder::<Backend, ()>::new()
+            .build(&mut factory, &mut families, &())
+            .unwrap();
+
+        factory
+            .idle_scope(&mut families, |factory, idle| {
+                assert_eq!(idle.device(), factory.device().id());
+                graph.dispose_idle(factory, idle, &());
+            })
+            .unwrap();
+    }
+}
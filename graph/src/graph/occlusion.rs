@@ -0,0 +1,347 @@
+use {
+    super::{frame_slot, GraphBuildError},
+    crate::{
+        command::{
+            CommandBuffer, CommandPool, ExecutableState, Families, Graphics, MultiShot,
+            NoIndividualReset, PendingState, PrimaryLevel, RenderPassEncoder, SimultaneousUse,
+            Submission, Submit,
+        },
+        factory::Factory,
+        frame::{Frame, Frames},
+    },
+    rendy_core::hal::{
+        query::{ControlFlags, Query, ResultFlags, Type},
+        Backend, Features,
+    },
+    std::sync::{
+        atomic::{AtomicUsize, Ordering},
+        Arc,
+    },
+};
+
+type ResetBuffer<B> = CommandBuffer<
+    B,
+    Graphics,
+    PendingState<ExecutableState<MultiShot<SimultaneousUse>>>,
+    PrimaryLevel,
+    NoIndividualReset,
+>;
+
+#[derive(Debug)]
+struct Pools<B: Backend> {
+    pools: Vec<B::QueryPool>,
+    count: u32,
+    flags: ControlFlags,
+    slot: AtomicUsize,
+}
+
+/// Occlusion queries managed by the graph.
+/// Render groups get it from `GraphContext::occlusion_queries` when built
+/// and surround draws with `begin` and `end`.
+/// Number of samples that passed depth and stencil tests is available
+/// through `Graph::occlusion_result` after the frame completes.
+///
+/// Each frame in flight uses its own query pool,
+/// so commands that use queries are valid only for the frame they were recorded for.
+/// Groups that use queries must record draw commands every frame.
+///
+/// Queries are reset on the queue of the first node on graphics capable family
+/// and must be used by nodes on that queue.
+#[derive(Clone, Debug)]
+pub struct OcclusionQueries<B: Backend> {
+    inner: Arc<Pools<B>>,
+}
+
+impl<B> OcclusionQueries<B>
+where
+    B: Backend,
+{
+    /// Number of queries available for each frame.
+    pub fn count(&self) -> u32 {
+        self.inner.count
+    }
+
+    /// Check if queries count exact number of samples.
+    /// Otherwise non-zero result only tells that some samples passed.
+    pub fn precise(&self) -> bool {
+        self.inner.flags.contains(ControlFlags::PRECISE)
+    }
+
+    /// Begin occlusion query `id` of the current frame.
+    /// Panics if `id` is out of bounds.
+    ///
+    /// # Safety
+    ///
+    /// Query can be begun only once per frame
+    /// and must be ended with `end` in the same subpass.
+    pub unsafe fn begin(&self, encoder: &mut RenderPassEncoder<'_, B>, id: u32) {
+        encoder.begin_query(self.query(id), self.inner.flags);
+    }
+
+    /// End occlusion query `id` of the current frame.
+    ///
+    /// # Safety
+    ///
+    /// Query must be begun with `begin`.
+    pub unsafe fn end(&self, encoder: &mut RenderPassEncoder<'_, B>, id: u32) {
+        encoder.end_query(self.query(id));
+    }
+
+    fn query(&self, id: u32) -> Query<'_, B> {
+        assert!(
+            id < self.inner.count,
+            "Occlusion query {} is out of bounds. Graph has {} queries",
+            id,
+            self.inner.count
+        );
+        Query {
+            pool: &self.inner.pools[self.inner.slot.load(Ordering::Acquire)],
+            id,
+        }
+    }
+}
+
+#[derive(Debug)]
+struct ResetSlot<B: Backend> {
+    submit: Submit<B, SimultaneousUse>,
+    buffer: ResetBuffer<B>,
+    pending: Option<u64>,
+}
+
+/// Resets occlusion queries each frame and reads results of complete frames.
+#[derive(Debug)]
+pub(crate) struct GraphOcclusion<B: Backend> {
+    queries: OcclusionQueries<B>,
+    queue: (usize, usize),
+    command_pool: CommandPool<B, Graphics>,
+    resets: Vec<ResetSlot<B>>,
+    results: Vec<Option<u64>>,
+}
+
+impl<B> GraphOcclusion<B>
+where
+    B: Backend,
+{
+    /// Create query pool for each frame in flight
+    /// and record commands that reset them on `queue`.
+    pub(crate) fn new(
+        factory: &Factory<B>,
+        families: &Families<B>,
+        queue: (usize, usize),
+        count: u32,
+        precise: bool,
+        frames_in_flight: u32,
+    ) -> Result<Self, GraphBuildError> {
+        let flags = control_flags(precise, factory.features());
+
+        let mut command_pool = factory
+            .create_command_pool(families.family_by_index(queue.0))
+            .map_err(GraphBuildError::CommandPool)?
+            .with_capability::<Graphics>()
+            .expect("Occlusion queries are reset on graphics capable family");
+
+        let mut pools = Vec::new();
+        for _ in 0..frames_in_flight {
+            match factory.create_query_pool(Type::Occlusion, count) {
+                Ok(pool) => pools.push(pool),
+                Err(err) => unsafe {
+                    for pool in pools {
+                        factory.destroy_query_pool(pool);
+                    }
+                    factory.destroy_command_pool(command_pool);
+                    return Err(GraphBuildError::QueryPool(err));
+                },
+            }
+        }
+
+        let resets = command_pool
+            .allocate_buffers::<PrimaryLevel>(pools.len())
+            .into_iter()
+            .zip(&pools)
+            .map(|(buffer, pool)| {
+                let mut recording = buffer.begin(MultiShot(SimultaneousUse), ());
+                unsafe {
+                    recording.encoder().reset_query_pool(pool, 0..count);
+                }
+                let (submit, buffer) = recording.finish().submit();
+                ResetSlot {
+                    submit,
+                    buffer,
+                    pending: None,
+                }
+            })
+            .collect();
+
+        Ok(GraphOcclusion {
+            queries: OcclusionQueries {
+                inner: Arc::new(Pools {
+                    pools,
+                    count,
+                    flags,
+                    slot: AtomicUsize::new(0),
+                }),
+            },
+            queue,
+            command_pool,
+            resets,
+            results: vec![None; count as usize],
+        })
+    }
+
+    pub(crate) fn queries(&self) -> &OcclusionQueries<B> {
+        &self.queries
+    }
+
+    /// Read results of the frame that used the same pool as `next`.
+    /// Only complete frames are read so this never waits for the device.
+    pub(crate) fn resolve(&mut self, factory: &Factory<B>, frames: &Frames<B>) {
+        let slot = frame_slot(frames.next(), self.resets.len());
+        let pool = &self.queries.inner.pools[slot];
+        read_complete(
+            &mut self.results,
+            &mut self.resets[slot].pending,
+            |frame| frames.is_complete(Frame::with_index(frame)),
+            |id| {
+                let mut samples = 0u64;
+                let ready = unsafe {
+                    factory.get_query_pool_results(
+                        pool,
+                        id..id + 1,
+                        std::slice::from_raw_parts_mut(&mut samples as *mut u64 as *mut u8, 8),
+                        8,
+                        ResultFlags::BITS_64,
+                    )
+                };
+                ready.map(|ready| if ready { Some(samples) } else { None })
+            },
+        );
+    }
+
+    /// Reset queries for the `frame` and make them current.
+    ///
+    /// # Safety
+    ///
+    /// Frame that used the same pool must be complete.
+    pub(crate) unsafe fn begin_frame(&mut self, frame: Frame, families: &mut Families<B>) {
+        let slot = frame_slot(frame, self.resets.len());
+        self.queries.inner.slot.store(slot, Ordering::Release);
+
+        let reset = &mut self.resets[slot];
+        families
+            .family_by_index_mut(self.queue.0)
+            .queue_mut(self.queue.1)
+            .submit(Some(Submission::new().submits(Some(&reset.submit))), None);
+        reset.pending = Some(frame.index());
+    }
+
+    /// Samples counted by query `id` in the latest frame with resolved queries.
+    pub(crate) fn result(&self, id: u32) -> Option<u64> {
+        self.results.get(id as usize).and_then(|result| *result)
+    }
+
+    /// Dispose of query pools and command buffers.
+    ///
+    /// # Safety
+    ///
+    /// Must be called after waiting for device idle.
+    pub(crate) unsafe fn dispose(self, factory: &Factory<B>) {
+        let GraphOcclusion {
+            queries,
+            mut command_pool,
+            resets,
+            ..
+        } = self;
+
+        command_pool.free_buffers(resets.into_iter().map(|reset| {
+            drop(reset.submit);
+            reset.buffer.mark_complete()
+        }));
+        factory.destroy_command_pool(command_pool);
+
+        match Arc::try_unwrap(queries.inner) {
+            Ok(pools) => {
+                for pool in pools.pools {
+                    factory.destroy_query_pool(pool);
+                }
+            }
+            Err(_) => log::error!("Occlusion queries are still referenced. Query pools leaked"),
+        }
+    }
+}
+
+/// Query control flags for requested precision.
+/// Falls back to non-precise queries when `features` lack precise ones.
+fn control_flags(precise: bool, features: Features) -> ControlFlags {
+    if !precise {
+        ControlFlags::empty()
+    } else if features.contains(Features::PRECISE_OCCLUSION_QUERY) {
+        ControlFlags::PRECISE
+    } else {
+        log::warn!("Precise occlusion queries are not supported. Results are not exact");
+        ControlFlags::empty()
+    }
+}
+
+/// Replace `results` with results of the `pending` frame once it is complete.
+/// `read` returns samples counted by a query, or `None` if it wasn't used in the frame.
+fn read_complete<E: std::fmt::Debug>(
+    results: &mut [Option<u64>],
+    pending: &mut Option<u64>,
+    is_complete: impl FnOnce(u64) -> bool,
+    mut read: impl FnMut(u32) -> Result<Option<u64>, E>,
+) {
+    let frame = match *pending {
+        Some(frame) if is_complete(frame) => frame,
+        _ => return,
+    };
+    *pending = None;
+    log::trace!("Resolve occlusion queries for frame {}", frame);
+
+    for (id, result) in results.iter_mut().enumerate() {
+        // Queries not used in the frame are never available.
+        *result = read(id as u32).unwrap_or_else(|err| {
+            log::warn!("Failed to read occlusion query {}: {:?}", id, err);
+            None
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{control_flags, read_complete, ControlFlags, Features};
+
+    #[test]
+    fn precise_queries_fall_back_when_unsupported() {
+        let supported = Features::PRECISE_OCCLUSION_QUERY;
+        assert_eq!(control_flags(true, supported), ControlFlags::PRECISE);
+        assert_eq!(
+            control_flags(true, Features::empty()),
+            ControlFlags::empty()
+        );
+        assert_eq!(control_flags(false, supported), ControlFlags::empty());
+    }
+
+    #[test]
+    fn results_are_readable_after_frame_completes() {
+        let mut results = vec![Some(7), None, None];
+        let mut pending = Some(4);
+        // Query 0 is hidden, 1 is visible and 2 isn't used in the frame.
+        let read = |id| match id {
+            0 => Ok(Some(0)),
+            1 => Ok(Some(120)),
+            _ => Ok::<_, ()>(None),
+        };
+
+        read_complete(&mut results, &mut pending, |frame| frame < 4, read);
+        assert_eq!(results, vec![Some(7), None, None]);
+        assert_eq!(pending, Some(4));
+
+        read_complete(&mut results, &mut pending, |frame| frame <= 4, read);
+        assert_eq!(results, vec![Some(0), Some(120), None]);
+        assert_eq!(pending, None);
+
+        // Results are read once per frame.
+        read_complete(&mut results, &mut pending, |_| true, |_| Err(()));
+        assert_eq!(results, vec![Some(0), Some(120), None]);
+    }
+}
@@ -0,0 +1,167 @@
+//! Frame pacing for `Graph::run`.
+
+use std::time::{Duration, Instant};
+
+/// Pacer spins instead of sleeping when deadline is closer than this,
+/// since sleep may overshoot by scheduler time slice.
+const SPIN_THRESHOLD: Duration = Duration::from_millis(2);
+
+/// Source of time for `FramePacer`.
+pub(crate) trait Clock {
+    /// Get current time.
+    fn now(&mut self) -> Instant;
+
+    /// Sleep at least `duration`.
+    fn sleep(&mut self, duration: Duration);
+
+    /// Let other threads run for a short while.
+    fn spin(&mut self);
+}
+
+/// Clock that actually sleeps.
+#[derive(Clone, Copy, Debug)]
+pub(crate) struct SystemClock;
+
+impl Clock for SystemClock {
+    fn now(&mut self) -> Instant {
+        Instant::now()
+    }
+
+    fn sleep(&mut self, duration: Duration) {
+        std::thread::sleep(duration)
+    }
+
+    fn spin(&mut self) {
+        std::thread::yield_now()
+    }
+}
+
+/// Waits after frames are submitted to keep frame interval close to target.
+#[derive(Debug, Default)]
+pub(crate) struct FramePacer {
+    target: Duration,
+    deadline: Option<Instant>,
+    last: Option<Instant>,
+    frame_time: Option<Duration>,
+}
+
+impl FramePacer {
+    pub(crate) fn set_target(&mut self, target: Duration) {
+        self.target = target;
+        self.deadline = None;
+    }
+
+    pub(crate) fn target(&self) -> Duration {
+        self.target
+    }
+
+    pub(crate) fn frame_time(&self) -> Option<Duration> {
+        self.frame_time
+    }
+
+    /// Wait until frame deadline and schedule the next one.
+    pub(crate) fn pace(&mut self, clock: &mut impl Clock) {
+        if self.target > Duration::from_secs(0) {
+            if let Some(deadline) = self.deadline {
+                wait_until(clock, deadline);
+            }
+        }
+
+        let end = clock.now();
+        self.deadline = match self.deadline {
+            // Keep cadence if frame is late for less than a whole frame,
+            // so that overshoot is compensated by the next frame.
+            Some(deadline) if end < deadline + self.target => Some(deadline + self.target),
+            _ => Some(end + self.target),
+        };
+        self.frame_time = self.last.map(|last| end - last);
+        self.last = Some(end);
+    }
+}
+
+fn wait_until(clock: &mut impl Clock, deadline: Instant) {
+    loop {
+        let now = clock.now();
+        if now >= deadline {
+            break;
+        }
+        let left = deadline - now;
+        if left > SPIN_THRESHOLD {
+            clock.sleep(left - SPIN_THRESHOLD);
+        } else {
+            clock.spin();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Clock that oversleeps and advances only when asked.
+    struct FakeClock {
+        now: Instant,
+    }
+
+    impl FakeClock {
+        fn advance(&mut self, duration: Duration) {
+            self.now += duration;
+        }
+    }
+
+    impl Clock for FakeClock {
+        fn now(&mut self) -> Instant {
+            self.now
+        }
+
+        fn sleep(&mut self, duration: Duration) {
+            self.advance(duration + Duration::from_micros(1500));
+        }
+
+        fn spin(&mut self) {
+            self.advance(Duration::from_micros(20));
+        }
+    }
+
+    fn run_frames(pacer: &mut FramePacer, clock: &mut FakeClock, work: &[u64]) -> Duration {
+        pacer.pace(clock);
+        let start = clock.now();
+        for frame in 0..100 {
+            clock.advance(Duration::from_micros(work[frame % work.len()]));
+            pacer.pace(clock);
+        }
+        (clock.now() - start) / 100
+    }
+
+    #[test]
+    fn average_interval_matches_target() {
+        let mut clock = FakeClock {
+            now: Instant::now(),
+        };
+        let mut pacer = FramePacer::default();
+        let target = Duration::from_micros(16_667);
+        pacer.set_target(target);
+
+        let average = run_frames(&mut pacer, &mut clock, &[3_000, 9_000, 15_000, 5_000]);
+        let error = if average > target {
+            average - target
+        } else {
+            target - average
+        };
+        assert!(error < Duration::from_micros(100), "{:?}", average);
+        assert!(pacer.frame_time().is_some());
+    }
+
+    #[test]
+    fn slow_frames_are_not_paced() {
+        let mut clock = FakeClock {
+            now: Instant::now(),
+        };
+        let mut pacer = FramePacer::default();
+        pacer.set_target(Duration::from_millis(10));
+
+        let average = run_frames(&mut pacer, &mut clock, &[25_000]);
+        assert_eq!(average, Duration::from_millis(25));
+        assert_eq!(pacer.frame_time(), Some(Duration::from_millis(25)));
+    }
+}
@@ -0,0 +1,140 @@
+//! Memory usage of transient resources for `Graph::resource_report`.
+
+use crate::{BufferId, ImageId, NodeId};
+
+/// Transient resource owned by the graph.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum GraphResource {
+    /// Buffer created by `GraphBuilder::create_buffer`.
+    Buffer(BufferId),
+    /// Image created by one of `GraphBuilder::create_*image` functions.
+    Image(ImageId),
+}
+
+/// Memory used by a transient resource.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct ResourceUsage {
+    /// The resource.
+    pub resource: GraphResource,
+
+    /// Size of memory block bound to the resource in bytes.
+    /// Aliased images share one block, it is counted for the first of them only.
+    pub size: u64,
+
+    /// Number of nodes that access the resource.
+    pub users: usize,
+
+    /// Number of submissions from the first access to the last one, both included.
+    /// Resource memory can't be reused during this time.
+    pub lifetime: usize,
+
+    /// Resource is kept alive through more than half of the frame's submissions.
+    pub long_lived: bool,
+}
+
+/// Transient resources attributed to a node.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct NodeResourceUsage {
+    /// The node.
+    pub node: NodeId,
+
+    /// Resources the node accesses first in the frame.
+    /// This is usually the node that produces their content.
+    pub resources: Vec<ResourceUsage>,
+
+    /// Total size of `resources` in bytes.
+    pub size: u64,
+}
+
+/// Attribute resources to nodes that access them first.
+/// `submissions` lists nodes with resources they access in order of submission.
+/// `generation` is the generation of node ids of the graph.
+pub(crate) fn attribute_resources(
+    node_count: usize,
+    generation: u32,
+    submissions: &[(usize, Vec<GraphResource>)],
+    size: impl Fn(GraphResource) -> u64,
+) -> Vec<NodeResourceUsage> {
+    // Owner, first and last submission, and users of each resource.
+    let mut resources: Vec<(GraphResource, usize, usize, usize, Vec<usize>)> = Vec::new();
+    for (index, (node, accessed)) in submissions.iter().enumerate() {
+        for &resource in accessed {
+            match resources.iter_mut().find(|entry| entry.0 == resource) {
+                Some((_, _, _, last, users)) => {
+                    *last = index;
+                    if !users.contains(node) {
+                        users.push(*node);
+                    }
+                }
+                None => resources.push((resource, *node, index, index, vec![*node])),
+            }
+        }
+    }
+
+    let mut report: Vec<_> = (0..node_count)
+        .map(|node| NodeResourceUsage {
+            node: NodeId(node, generation),
+            resources: Vec::new(),
+            size: 0,
+        })
+        .collect();
+
+    for (resource, owner, first, last, users) in resources {
+        let lifetime = last - first + 1;
+        let usage = ResourceUsage {
+            resource,
+            size: size(resource),
+            users: users.len(),
+            lifetime,
+            long_lived: lifetime * 2 > submissions.len(),
+        };
+        report[owner].size += usage.size;
+        report[owner].resources.push(usage);
+    }
+
+    report
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn resources_are_attributed_to_first_user() {
+        let gbuffer = GraphResource::Image(ImageId(0, 0));
+        let target = GraphResource::Image(ImageId(1, 0));
+        let lights = GraphResource::Buffer(BufferId(0, 0));
+
+        // Node 1 is submitted first and fills G-buffer that is read by node 0,
+        // which renders to target presented by node 2.
+        let report = attribute_resources(
+            3,
+            0,
+            &[
+                (1, vec![gbuffer]),
+                (0, vec![gbuffer, lights, target]),
+                (2, vec![target]),
+            ],
+            |resource| match resource {
+                GraphResource::Image(ImageId(0, 0)) => 64 << 20,
+                _ => 4 << 20,
+            },
+        );
+
+        assert_eq!(report[1].size, 64 << 20);
+        assert_eq!(
+            report[1].resources,
+            vec![ResourceUsage {
+                resource: gbuffer,
+                size: 64 << 20,
+                users: 2,
+                lifetime: 2,
+                long_lived: true,
+            }]
+        );
+        assert_eq!(report[0].size, 8 << 20);
+        assert_eq!(report[0].resources.len(), 2);
+        assert!(!report[0].resources[0].long_lived);
+        assert!(report[2].resources.is_empty());
+    }
+}
@@ -0,0 +1,282 @@
+use {
+    super::frame_slot,
+    crate::{
+        command::{
+            CommandBuffer, CommandPool, ExecutableState, Execute, Families, MultiShot,
+            NoIndividualReset, PendingState, PrimaryLevel, SimultaneousUse, Submit,
+        },
+        factory::Factory,
+        frame::{Frame, Frames},
+        node::NodeTimestamps,
+    },
+    rendy_core::hal::{
+        device::OutOfMemory,
+        pso::PipelineStage,
+        query::{CreationError, Query, ResultFlags, Type},
+        Backend,
+    },
+};
+
+type TimestampBuffer<B> = CommandBuffer<
+    B,
+    Execute,
+    PendingState<ExecutableState<MultiShot<SimultaneousUse>>>,
+    PrimaryLevel,
+    NoIndividualReset,
+>;
+
+#[derive(Debug)]
+struct Recorded<B: Backend> {
+    submit: Submit<B, SimultaneousUse>,
+    buffer: TimestampBuffer<B>,
+}
+
+/// Pre-recorded command buffers that write begin and end timestamps of one node.
+#[derive(Debug)]
+struct NodeSlot<B: Backend> {
+    family: usize,
+    begin: Recorded<B>,
+    end: Recorded<B>,
+}
+
+/// Query pool and timestamp buffers used by one frame in flight.
+#[derive(Debug)]
+struct FrameSlot<B: Backend> {
+    pool: B::QueryPool,
+    nodes: Vec<Option<NodeSlot<B>>>,
+    pending: Option<u64>,
+}
+
+/// Error creating timestamp queries.
+#[derive(Debug)]
+pub(crate) enum TimingsError {
+    QueryPool(CreationError),
+    CommandPool(OutOfMemory),
+}
+
+/// Writes timestamps around each node submission and resolves them into durations.
+#[derive(Debug)]
+pub(crate) struct NodeTimings<B: Backend> {
+    period: f32,
+    command_pools: Vec<(usize, CommandPool<B, Execute>)>,
+    frames: Vec<FrameSlot<B>>,
+}
+
+impl<B> NodeTimings<B>
+where
+    B: Backend,
+{
+    /// Create query pools and record timestamp buffers for nodes.
+    /// `queues` contains family index of each node.
+    /// Nodes executed on families that can't write timestamps are not timed.
+    pub(crate) fn new(
+        factory: &Factory<B>,
+        families: &Families<B>,
+        period: f32,
+        frames_in_flight: u32,
+        queues: &[usize],
+    ) -> Result<Self, TimingsError> {
+        let mut command_pools: Vec<(usize, CommandPool<B, Execute>)> = Vec::new();
+        for &family in queues {
+            if command_pools.iter().any(|&(index, _)| index == family) {
+                continue;
+            }
+            let pool = factory
+                .create_command_pool(families.family_by_index(family))
+                .map_err(TimingsError::CommandPool)?;
+            match pool.with_capability::<Execute>() {
+                Ok(pool) => command_pools.push((family, pool)),
+                Err(pool) => {
+                    log::debug!("Family {} doesn't support timestamps", family);
+                    unsafe { factory.destroy_command_pool(pool) };
+                }
+            }
+        }
+
+        let mut timings = NodeTimings {
+            period,
+            command_pools,
+            frames: Vec::new(),
+        };
+
+        for _ in 0..frames_in_flight {
+            let pool = match factory.create_query_pool(Type::Timestamp, queues.len() as u32 * 2) {
+                Ok(pool) => pool,
+                Err(err) => {
+                    unsafe { timings.dispose(factory) };
+                    return Err(TimingsError::QueryPool(err));
+                }
+            };
+
+            let nodes = queues
+                .iter()
+                .enumerate()
+                .map(|(node, &family)| {
+                    let command_pool = timings
+                        .command_pools
+                        .iter_mut()
+                        .find(|(index, _)| *index == family)
+                        .map(|(_, pool)| pool)?;
+                    Some(record_node(family, command_pool, &pool, node as u32))
+                })
+                .collect();
+
+            timings.frames.push(FrameSlot {
+                pool,
+                nodes,
+                pending: None,
+            });
+        }
+
+        Ok(timings)
+    }
+
+    /// Read timestamps of the frame that used the same slot as `next`
+    /// and publish node durations into `frames`.
+    /// Only complete frames are read so this never waits for the device.
+    pub(crate) fn resolve(&mut self, factory: &Factory<B>, frames: &mut Frames<B>) {
+        let slot_index = frame_slot(frames.next(), self.frames.len());
+        let slot = &mut self.frames[slot_index];
+
+        let pending = match slot.pending {
+            Some(frame) if frames.is_complete(Frame::with_index(frame)) => frame,
+            _ => return,
+        };
+        slot.pending = None;
+        log::trace!("Resolve node timings for frame {}", pending);
+
+        let mut timings = Vec::new();
+        for (node, _) in slot
+            .nodes
+            .iter()
+            .enumerate()
+            .filter(|(_, node)| node.is_some())
+        {
+            let mut data = [0u64; 2];
+            let ready = unsafe {
+                factory.get_query_pool_results(
+                    &slot.pool,
+                    node as u32 * 2..node as u32 * 2 + 2,
+                    std::slice::from_raw_parts_mut(data.as_mut_ptr() as *mut u8, 16),
+                    8,
+                    ResultFlags::BITS_64,
+                )
+            };
+
+            match ready {
+                Ok(true) => {
+                    let ticks = data[1].saturating_sub(data[0]);
+                    timings.push((node, (ticks as f64 * self.period as f64) as u64));
+                }
+                Ok(false) => log::trace!("Timestamps of node {} are not available", node),
+                Err(err) => log::warn!("Failed to read timestamps of node {}: {:?}", node, err),
+            }
+        }
+
+        // Timings of nodes not resolved in this frame would be stale.
+        frames.set_node_timings(timings);
+    }
+
+    /// Get timestamps buffers for the node in the frame.
+    pub(crate) fn node(&self, frame: Frame, node: usize) -> Option<NodeTimestamps<'_, B>> {
+        let slot = &self.frames[frame_slot(frame, self.frames.len())];
+        slot.nodes[node].as_ref().map(|node| NodeTimestamps {
+            begin: &node.begin.submit,
+            end: &node.end.submit,
+        })
+    }
+
+    /// Mark the frame as submitted so its timestamps are read when it completes.
+    pub(crate) fn submitted(&mut self, frame: Frame) {
+        let len = self.frames.len();
+        self.frames[frame_slot(frame, len)].pending = Some(frame.index());
+    }
+
+    /// Dispose of query pools and command buffers.
+    ///
+    /// # Safety
+    ///
+    /// Must be called after waiting for device idle.
+    pub(crate) unsafe fn dispose(self, factory: &Factory<B>) {
+        let NodeTimings {
+            mut command_pools,
+            frames,
+            ..
+        } = self;
+
+        for slot in frames {
+            for node in slot.nodes.into_iter().filter_map(|node| node) {
+                let pool = command_pools
+                    .iter_mut()
+                    .find(|(index, _)| *index == node.family)
+                    .map(|(_, pool)| pool)
+                    .expect("Timestamp buffers are allocated from graph's pools");
+                drop(node.begin.submit);
+                drop(node.end.submit);
+                pool.free_buffers(vec![
+                    node.begin.buffer.mark_complete(),
+                    node.end.buffer.mark_complete(),
+                ]);
+            }
+            factory.destroy_query_pool(slot.pool);
+        }
+
+        for (_, pool) in command_pools {
+            factory.destroy_command_pool(pool);
+        }
+    }
+}
+
+fn record_node<B: Backend>(
+    family: usize,
+    command_pool: &mut CommandPool<B, Execute>,
+    query_pool: &B::QueryPool,
+    node: u32,
+) -> NodeSlot<B> {
+    let mut buffers = command_pool.allocate_buffers::<PrimaryLevel>(2);
+    let end = buffers.pop().unwrap();
+    let begin = buffers.pop().unwrap();
+
+    let mut begin = begin.begin(MultiShot(SimultaneousUse), ());
+    {
+        let mut encoder = begin.encoder();
+        unsafe {
+            encoder.reset_query_pool(query_pool, node * 2..node * 2 + 2);
+            encoder.write_timestamp(
+                PipelineStage::TOP_OF_PIPE,
+                Query {
+                    pool: query_pool,
+                    id: node * 2,
+                },
+            );
+        }
+    }
+    let (begin_submit, begin_buffer) = begin.finish().submit();
+
+    let mut end = end.begin(MultiShot(SimultaneousUse), ());
+    {
+        let mut encoder = end.encoder();
+        unsafe {
+            encoder.write_timestamp(
+                PipelineStage::BOTTOM_OF_PIPE,
+                Query {
+                    pool: query_pool,
+                    id: node * 2 + 1,
+                },
+            );
+        }
+    }
+    let (end_submit, end_buffer) = end.finish().submit();
+
+    NodeSlot {
+        family,
+        begin: Recorded {
+            submit: begin_submit,
+            buffer: begin_buffer,
+        },
+        end: Recorded {
+            submit: end_submit,
+            buffer: end_buffer,
+        },
+    }
+}
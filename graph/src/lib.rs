@@ -13,23 +13,37 @@
 use rendy_chain as chain;
 use rendy_command as command;
 use rendy_core as core;
+use rendy_descriptor as descriptor;
 use rendy_factory as factory;
 use rendy_frame as frame;
 use rendy_memory as memory;
 use rendy_resource as resource;
 use rendy_wsi as wsi;
 
+// Ids carry generation of the `GraphBuilder` that created them
+// along with the index, so ids of another graph are detected
+// instead of referring to unrelated resources.
+
 /// Id of the buffer in graph.
 #[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
-pub struct BufferId(usize);
+pub struct BufferId(usize, u32);
 
 /// Id of the image (or target) in graph.
 #[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
-pub struct ImageId(usize);
+pub struct ImageId(usize, u32);
+
+/// Id of the image view managed by graph.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct ImageViewId(usize, u32);
 
 /// Id of the node in graph.
 #[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
-pub struct NodeId(usize);
+pub struct NodeId(usize, u32);
+
+/// Id of the window surface graph renders to.
+/// Images relative to the surface are resized only with it.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct SurfaceId(usize);
 
 mod graph;
 mod node;
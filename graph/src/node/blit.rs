@@ -0,0 +1,315 @@
+//! Defines blit node - node that copies and scales one image into another.
+
+use {
+    crate::{
+        command::{
+            CommandPool, Family, Graphics, IndividualReset, MultiShot, NoSimultaneousUse, Submit,
+        },
+        factory::Factory,
+        frame::{cirque::CommandCirque, Frames},
+        graph::GraphContext,
+        node::{
+            free_cirque, gfx_acquire_barriers, gfx_release_barriers, DescBuilder, ImageAccess,
+            Node, NodeBuffer, NodeBuildError, NodeDesc, NodeImage, NodeSubmittable,
+        },
+        GraphBuilder, ImageId, NodeId,
+    },
+    rendy_core::hal::Backend,
+};
+
+/// Descriptor for `BlitNode`.
+/// Blits source image into destination image.
+///
+/// Source image is used in `TransferSrcOptimal` layout
+/// and destination image in `TransferDstOptimal`.
+/// Barriers are inserted by the graph.
+#[derive(Clone, Debug)]
+pub struct BlitNodeDesc {
+    src: ImageId,
+    dst: ImageId,
+    regions: Vec<rendy_core::hal::command::ImageBlit>,
+    filter: rendy_core::hal::image::Filter,
+}
+
+impl BlitNodeDesc {
+    /// Create descriptor for node that blits whole first level of `src` into first level of `dst`.
+    pub fn new(src: ImageId, dst: ImageId, filter: rendy_core::hal::image::Filter) -> Self {
+        assert_ne!(src, dst, "Blit node requires distinct images");
+        BlitNodeDesc {
+            src,
+            dst,
+            regions: Vec::new(),
+            filter,
+        }
+    }
+
+    /// Add region to blit.
+    /// If no regions are added whole first level of `src` is blitted into first level of `dst`.
+    pub fn add_region(&mut self, region: rendy_core::hal::command::ImageBlit) -> &mut Self {
+        self.regions.push(region);
+        self
+    }
+
+    /// Add region to blit.
+    /// If no regions are added whole first level of `src` is blitted into first level of `dst`.
+    pub fn with_region(mut self, region: rendy_core::hal::command::ImageBlit) -> Self {
+        self.add_region(region);
+        self
+    }
+}
+
+/// Node that blits one image into another.
+#[derive(Debug)]
+pub struct BlitNode<B: Backend> {
+    pool: CommandPool<B, Graphics, IndividualReset>,
+    cirque: CommandCirque<B, Graphics>,
+    /// Source and destination images.
+    images: [NodeImage; 2],
+    regions: Vec<rendy_core::hal::command::ImageBlit>,
+    /// Regions cover whole first levels and follow images extent.
+    whole_level: bool,
+    filter: rendy_core::hal::image::Filter,
+}
+
+/// Region that covers whole first level of both images.
+pub(crate) fn whole_level_region<B: Backend>(
+    ctx: &GraphContext<B>,
+    src: &NodeImage,
+    dst: &NodeImage,
+) -> rendy_core::hal::command::ImageBlit {
+    let src_image = ctx.get_image(src.id).expect("Source image does not exist");
+    let dst_image = ctx
+        .get_image(dst.id)
+        .expect("Destination image does not exist");
+
+    rendy_core::hal::command::ImageBlit {
+        src_subresource: rendy_core::hal::image::SubresourceLayers {
+            aspects: src.range.aspects,
+            level: 0,
+            layers: src.range.layers.clone(),
+        },
+        src_bounds: rendy_core::hal::image::Offset::ZERO.into_bounds(&src_image.kind().extent()),
+        dst_subresource: rendy_core::hal::image::SubresourceLayers {
+            aspects: dst.range.aspects,
+            level: 0,
+            layers: dst.range.layers.clone(),
+        },
+        dst_bounds: rendy_core::hal::image::Offset::ZERO.into_bounds(&dst_image.kind().extent()),
+    }
+}
+
+impl<B, T> NodeDesc<B, T> for BlitNodeDesc
+where
+    B: Backend,
+    T: ?Sized,
+{
+    type Node = BlitNode<B>;
+
+    fn builder(self) -> DescBuilder<B, T, Self> {
+        let (src, dst) = (self.src, self.dst);
+        DescBuilder::new(self).with_image(src).with_image(dst)
+    }
+
+    fn images(&self) -> Vec<ImageAccess> {
+        vec![
+            ImageAccess {
+                access: rendy_core::hal::image::Access::TRANSFER_READ,
+                usage: rendy_core::hal::image::Usage::TRANSFER_SRC,
+                layout: rendy_core::hal::image::Layout::TransferSrcOptimal,
+                stages: rendy_core::hal::pso::PipelineStage::TRANSFER,
+            },
+            ImageAccess {
+                access: rendy_core::hal::image::Access::TRANSFER_WRITE,
+                usage: rendy_core::hal::image::Usage::TRANSFER_DST,
+                layout: rendy_core::hal::image::Layout::TransferDstOptimal,
+                stages: rendy_core::hal::pso::PipelineStage::TRANSFER,
+            },
+        ]
+    }
+
+    fn build<'a>(
+        self,
+        ctx: &GraphContext<B>,
+        factory: &mut Factory<B>,
+        family: &mut Family<B>,
+        _queue: usize,
+        _aux: &T,
+        buffers: Vec<NodeBuffer>,
+        images: Vec<NodeImage>,
+    ) -> Result<Self::Node, NodeBuildError> {
+        assert!(buffers.is_empty());
+        assert_eq!(images.len(), 2);
+
+        let src = images
+            .iter()
+            .find(|image| image.id == self.src)
+            .cloned()
+            .expect("Source image must be provided");
+        let dst = images
+            .iter()
+            .find(|image| image.id == self.dst)
+            .cloned()
+            .expect("Destination image must be provided");
+
+        let whole_level = self.regions.is_empty();
+        let regions = if whole_level {
+            vec![whole_level_region(ctx, &src, &dst)]
+        } else {
+            self.regions
+        };
+
+        let pool = factory
+            .create_command_pool(family)
+            .map_err(NodeBuildError::OutOfMemory)?
+            .with_capability()
+            .expect("Graph must specify family that supports `Graphics`");
+
+        Ok(BlitNode {
+            pool,
+            cirque: CommandCirque::new(),
+            images: [src, dst],
+            regions,
+            whole_level,
+            filter: self.filter,
+        })
+    }
+}
+
+impl<'a, B> NodeSubmittable<'a, B> for BlitNode<B>
+where
+    B: Backend,
+{
+    type Submittable = Submit<B, NoSimultaneousUse>;
+    type Submittables = Option<Submit<B, NoSimultaneousUse>>;
+}
+
+impl<B, T> Node<B, T> for BlitNode<B>
+where
+    B: Backend,
+    T: ?Sized,
+{
+    // Blit commands require graphics queue.
+    type Capability = Graphics;
+
+    fn run<'a>(
+        &'a mut self,
+        ctx: &GraphContext<B>,
+        factory: &Factory<B>,
+        _aux: &T,
+        frames: &'a Frames<B>,
+    ) -> Option<Submit<B, NoSimultaneousUse>> {
+        let BlitNode {
+            pool,
+            cirque,
+            images,
+            regions,
+            filter,
+            ..
+        } = self;
+
+        let [src, dst] = &*images;
+
+        let submit = cirque.encode(frames, pool, |cbuf| {
+            cbuf.or_init(|mut cbuf| {
+                factory.set_object_name(&mut cbuf, std::any::type_name::<Self>());
+                let mut cbuf = cbuf.begin(MultiShot(NoSimultaneousUse), ());
+                let mut encoder = cbuf.encoder();
+
+                {
+                    let (stages, barriers) = gfx_acquire_barriers(ctx, None, &*images);
+                    if !barriers.is_empty() {
+                        log::trace!("Acquire {:?} : {:#?}", stages, barriers);
+                        unsafe {
+                            encoder.pipeline_barrier(
+                                stages,
+                                rendy_core::hal::memory::Dependencies::empty(),
+                                barriers,
+                            );
+                        }
+                    }
+                }
+
+                unsafe {
+                    encoder.blit_image(
+                        ctx.get_image(src.id).expect("Image does not exist").raw(),
+                        src.layout,
+                        ctx.get_image(dst.id).expect("Image does not exist").raw(),
+                        dst.layout,
+                        *filter,
+                        regions.iter().cloned(),
+                    );
+                }
+
+                {
+                    let (stages, barriers) = gfx_release_barriers(ctx, None, &*images);
+                    if !barriers.is_empty() {
+                        log::trace!("Release {:?} : {:#?}", stages, barriers);
+                        unsafe {
+                            encoder.pipeline_barrier(
+                                stages,
+                                rendy_core::hal::memory::Dependencies::empty(),
+                                barriers,
+                            );
+                        }
+                    }
+                }
+
+                cbuf.finish()
+            })
+        });
+
+        Some(submit)
+    }
+
+    unsafe fn resize(
+        &mut self,
+        ctx: &GraphContext<B>,
+        _factory: &mut Factory<B>,
+        _aux: &T,
+    ) -> Result<bool, NodeBuildError> {
+        if self.whole_level {
+            let [src, dst] = &self.images;
+            self.regions = vec![whole_level_region(ctx, src, dst)];
+        }
+
+        free_cirque(
+            &mut self.pool,
+            std::mem::replace(&mut self.cirque, CommandCirque::new()),
+        );
+        Ok(true)
+    }
+
+    unsafe fn dispose(mut self, factory: &mut Factory<B>, _aux: &T) {
+        free_cirque(&mut self.pool, self.cirque);
+        factory.destroy_command_pool(self.pool);
+    }
+}
+
+/// Add chain of blit nodes that downsample `images` one after another.
+/// Each image in the chain is blitted from the previous one.
+/// First blit node is placed after `dependency` which should be the node that writes first image.
+/// Returns ids of added nodes.
+pub fn blit_chain<B, T>(
+    builder: &mut GraphBuilder<B, T>,
+    images: &[ImageId],
+    filter: rendy_core::hal::image::Filter,
+    dependency: Option<NodeId>,
+) -> Vec<NodeId>
+where
+    B: Backend,
+    T: ?Sized,
+{
+    let mut last = dependency;
+    images
+        .windows(2)
+        .map(|pair| {
+            let mut node = NodeDesc::<B, T>::builder(BlitNodeDesc::new(pair[0], pair[1], filter));
+            if let Some(last) = last {
+                node.add_dependency(last);
+            }
+            let id = builder.add_node(node);
+            last = Some(id);
+            id
+        })
+        .collect()
+}
@@ -0,0 +1,479 @@
+//! Defines capture node - node that copies image into host memory.
+//! Use it instead of present node to render without window.
+
+use {
+    crate::{
+        command::{
+            CommandPool, Family, IndividualReset, MultiShot, NoSimultaneousUse, Submit, Transfer,
+        },
+        factory::{read_mapped, Factory},
+        frame::{cirque::CommandCirque, Frame, Frames},
+        graph::GraphContext,
+        node::{
+            free_cirque, gfx_acquire_barriers, gfx_release_barriers, DescBuilder, ImageAccess,
+            Node, NodeBuffer, NodeBuildError, NodeDesc, NodeImage, NodeSubmittable,
+        },
+        resource::{Buffer, BufferInfo, Escape},
+        ImageId,
+    },
+    rendy_core::hal::{format::Format, image::Extent, Backend},
+    std::{
+        collections::VecDeque,
+        sync::{Arc, Mutex},
+    },
+};
+
+/// Pixels of the captured image.
+/// Rows are tightly packed and texels are in image format, no conversion is performed.
+#[derive(Clone, Debug)]
+pub struct CapturedImage {
+    pixels: Vec<u8>,
+    format: Format,
+    extent: Extent,
+    row_pitch: u32,
+    frame: u64,
+}
+
+impl CapturedImage {
+    /// Raw texel data.
+    pub fn pixels(&self) -> &[u8] {
+        &self.pixels
+    }
+
+    /// Format of texels.
+    pub fn format(&self) -> Format {
+        self.format
+    }
+
+    /// Extent of the captured subresource.
+    pub fn extent(&self) -> Extent {
+        self.extent
+    }
+
+    /// Distance in bytes between starts of consecutive rows.
+    pub fn row_pitch(&self) -> u32 {
+        self.row_pitch
+    }
+
+    /// Index of the frame image was captured at.
+    pub fn frame(&self) -> u64 {
+        self.frame
+    }
+
+    /// Get pixel at specified coordinates of the first depth slice.
+    pub fn texel(&self, x: u32, y: u32) -> &[u8] {
+        assert!(x < self.extent.width && y < self.extent.height);
+        let size = texel_size(self.format) as usize;
+        let start = y as usize * self.row_pitch as usize + x as usize * size;
+        &self.pixels[start..start + size]
+    }
+}
+
+/// Handle to the latest image captured by `CaptureNode`.
+/// Nodes are owned by the graph, so handle is shared between node and user.
+#[derive(Clone, Debug, Default)]
+pub struct Capture {
+    latest: Arc<Mutex<Option<CapturedImage>>>,
+}
+
+impl Capture {
+    /// Create handle without captured image.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Get copy of the latest complete capture.
+    /// Returns `None` until first frame with capture is complete.
+    pub fn latest(&self) -> Option<CapturedImage> {
+        self.latest.lock().unwrap().clone()
+    }
+
+    /// Take the latest complete capture.
+    pub fn take(&self) -> Option<CapturedImage> {
+        self.latest.lock().unwrap().take()
+    }
+
+    fn publish(&self, image: CapturedImage) {
+        *self.latest.lock().unwrap() = Some(image);
+    }
+}
+
+/// Size of one texel of the format in bytes.
+///
+/// # Panics
+///
+/// Panics for block-compressed formats.
+fn texel_size(format: Format) -> u32 {
+    let desc = format.surface_desc();
+    assert_eq!(
+        desc.dim,
+        (1, 1),
+        "Capture of block-compressed formats is not supported"
+    );
+    desc.bits as u32 / 8
+}
+
+/// Row pitch and total size of tightly packed copy of `extent`.
+fn capture_layout(extent: Extent, texel_size: u32) -> (u32, u64) {
+    let row_pitch = extent.width * texel_size;
+    let size = row_pitch as u64 * extent.height as u64 * extent.depth as u64;
+    (row_pitch, size)
+}
+
+/// Create readback buffer for each frame in flight.
+/// Command cirque of the node never holds more buffers than frames in flight.
+fn create_buffers<B: Backend>(
+    factory: &Factory<B>,
+    frames_in_flight: u32,
+    size: u64,
+) -> Result<Vec<Escape<Buffer<B>>>, NodeBuildError> {
+    (0..frames_in_flight)
+        .map(|_| {
+            factory
+                .create_buffer(
+                    BufferInfo {
+                        size,
+                        usage: rendy_core::hal::buffer::Usage::TRANSFER_DST,
+                    },
+                    crate::memory::Download,
+                )
+                .map_err(NodeBuildError::Buffer)
+        })
+        .collect()
+}
+
+/// Descriptor for `CaptureNode`.
+/// Copies first level and layer of the image into host-visible buffer each frame.
+///
+/// Image is used in `TransferSrcOptimal` layout.
+/// Copied pixels become available through `Capture` handle
+/// once the frame is complete.
+#[derive(Clone, Debug)]
+pub struct CaptureNodeDesc {
+    image: ImageId,
+    capture: Capture,
+}
+
+impl CaptureNodeDesc {
+    /// Create descriptor for node that captures `image`.
+    pub fn new(image: ImageId) -> Self {
+        CaptureNodeDesc {
+            image,
+            capture: Capture::new(),
+        }
+    }
+
+    /// Get handle to access captured pixels.
+    pub fn capture(&self) -> Capture {
+        self.capture.clone()
+    }
+}
+
+/// Node that copies image into host-visible buffers and publishes complete copies.
+#[derive(Debug)]
+pub struct CaptureNode<B: Backend> {
+    pool: CommandPool<B, Transfer, IndividualReset>,
+    cirque: CommandCirque<B, Transfer>,
+    image: NodeImage,
+    format: Format,
+    extent: Extent,
+    row_pitch: u32,
+    size: u64,
+    /// Readback buffer for each command buffer in the cirque.
+    buffers: Vec<Escape<Buffer<B>>>,
+    /// Buffers with copy submitted and frame that copied into them.
+    pending: VecDeque<(usize, Frame)>,
+    capture: Capture,
+}
+
+impl<B> CaptureNode<B>
+where
+    B: Backend,
+{
+    /// Get handle to access captured pixels.
+    pub fn capture(&self) -> &Capture {
+        &self.capture
+    }
+
+    /// Publish copies of complete frames.
+    fn read_complete(&mut self, factory: &Factory<B>, complete: impl Fn(Frame) -> bool) {
+        while let Some(&(index, frame)) = self.pending.front() {
+            if !complete(frame) {
+                break;
+            }
+            self.pending.pop_front();
+
+            // Frame is complete so device doesn't write to the buffer.
+            match unsafe { read_mapped(factory.device(), &mut self.buffers[index], self.size) } {
+                Ok(pixels) => self.capture.publish(CapturedImage {
+                    pixels,
+                    format: self.format,
+                    extent: self.extent,
+                    row_pitch: self.row_pitch,
+                    frame: frame.index(),
+                }),
+                Err(err) => log::error!("Failed to read captured image: {:?}", err),
+            }
+        }
+    }
+}
+
+impl<B, T> NodeDesc<B, T> for CaptureNodeDesc
+where
+    B: Backend,
+    T: ?Sized,
+{
+    type Node = CaptureNode<B>;
+
+    fn builder(self) -> DescBuilder<B, T, Self> {
+        let image = self.image;
+        DescBuilder::new(self).with_image(image)
+    }
+
+    fn images(&self) -> Vec<ImageAccess> {
+        vec![ImageAccess {
+            access: rendy_core::hal::image::Access::TRANSFER_READ,
+            usage: rendy_core::hal::image::Usage::TRANSFER_SRC,
+            layout: rendy_core::hal::image::Layout::TransferSrcOptimal,
+            stages: rendy_core::hal::pso::PipelineStage::TRANSFER,
+        }]
+    }
+
+    fn build<'a>(
+        self,
+        ctx: &GraphContext<B>,
+        factory: &mut Factory<B>,
+        family: &mut Family<B>,
+        _queue: usize,
+        _aux: &T,
+        buffers: Vec<NodeBuffer>,
+        images: Vec<NodeImage>,
+    ) -> Result<Self::Node, NodeBuildError> {
+        assert!(buffers.is_empty());
+        assert_eq!(images.len(), 1);
+
+        let image = images.into_iter().next().unwrap();
+        let (format, extent) = {
+            let raw = ctx.get_image(image.id).expect("Image does not exist");
+            (raw.format(), raw.kind().extent())
+        };
+        let (row_pitch, size) = capture_layout(extent, texel_size(format));
+
+        let pool = factory
+            .create_command_pool(family)
+            .map_err(NodeBuildError::OutOfMemory)?
+            .with_capability()
+            .expect("Graph must specify family that supports `Transfer`");
+
+        Ok(CaptureNode {
+            pool,
+            cirque: CommandCirque::new(),
+            image,
+            format,
+            extent,
+            row_pitch,
+            size,
+            buffers: create_buffers(factory, ctx.frames_in_flight, size)?,
+            pending: VecDeque::new(),
+            capture: self.capture,
+        })
+    }
+}
+
+impl<'a, B> NodeSubmittable<'a, B> for CaptureNode<B>
+where
+    B: Backend,
+{
+    type Submittable = Submit<B, NoSimultaneousUse>;
+    type Submittables = Option<Submit<B, NoSimultaneousUse>>;
+}
+
+impl<B, T> Node<B, T> for CaptureNode<B>
+where
+    B: Backend,
+    T: ?Sized,
+{
+    type Capability = Transfer;
+
+    fn run<'a>(
+        &'a mut self,
+        ctx: &GraphContext<B>,
+        factory: &Factory<B>,
+        _aux: &T,
+        frames: &'a Frames<B>,
+    ) -> Option<Submit<B, NoSimultaneousUse>> {
+        // Buffers are reused only after frame that copied into them is complete,
+        // so copies are read before being overwritten.
+        self.read_complete(factory, |frame| frames.is_complete(frame));
+
+        let CaptureNode {
+            pool,
+            cirque,
+            image,
+            extent,
+            buffers,
+            pending,
+            ..
+        } = self;
+
+        let mut used = None;
+        let submit = cirque.encode(frames, pool, |cbuf| {
+            let index = cbuf.index();
+            used = Some(index);
+
+            cbuf.or_init(|mut cbuf| {
+                factory.set_object_name(&mut cbuf, std::any::type_name::<Self>());
+                let mut cbuf = cbuf.begin(MultiShot(NoSimultaneousUse), ());
+                let mut encoder = cbuf.encoder();
+                let images = std::slice::from_ref(&*image);
+
+                {
+                    let (stages, barriers) = gfx_acquire_barriers(ctx, None, images);
+                    if !barriers.is_empty() {
+                        log::trace!("Acquire {:?} : {:#?}", stages, barriers);
+                        unsafe {
+                            encoder.pipeline_barrier(
+                                stages,
+                                rendy_core::hal::memory::Dependencies::empty(),
+                                barriers,
+                            );
+                        }
+                    }
+                }
+
+                unsafe {
+                    encoder.copy_image_to_buffer(
+                        ctx.get_image(image.id).expect("Image does not exist").raw(),
+                        image.layout,
+                        buffers[index].raw(),
+                        Some(rendy_core::hal::command::BufferImageCopy {
+                            buffer_offset: 0,
+                            buffer_width: 0,
+                            buffer_height: 0,
+                            image_layers: rendy_core::hal::image::SubresourceLayers {
+                                aspects: image.range.aspects,
+                                level: image.range.levels.start,
+                                layers: image.range.layers.start..image.range.layers.start + 1,
+                            },
+                            image_offset: rendy_core::hal::image::Offset::ZERO,
+                            image_extent: *extent,
+                        }),
+                    );
+                }
+
+                {
+                    let (stages, barriers) = gfx_release_barriers(ctx, None, images);
+                    if !barriers.is_empty() {
+                        log::trace!("Release {:?} : {:#?}", stages, barriers);
+                        unsafe {
+                            encoder.pipeline_barrier(
+                                stages,
+                                rendy_core::hal::memory::Dependencies::empty(),
+                                barriers,
+                            );
+                        }
+                    }
+                }
+
+                cbuf.finish()
+            })
+        });
+
+        pending.push_back((used.unwrap(), frames.next()));
+        Some(submit)
+    }
+
+    unsafe fn resize(
+        &mut self,
+        ctx: &GraphContext<B>,
+        factory: &mut Factory<B>,
+        _aux: &T,
+    ) -> Result<bool, NodeBuildError> {
+        // Graph waits for device before resizing.
+        self.read_complete(factory, |_| true);
+
+        let raw = ctx.get_image(self.image.id).expect("Image does not exist");
+        self.format = raw.format();
+        self.extent = raw.kind().extent();
+        let (row_pitch, size) = capture_layout(self.extent, texel_size(self.format));
+        self.row_pitch = row_pitch;
+        self.size = size;
+        self.buffers = create_buffers(factory, ctx.frames_in_flight, size)?;
+
+        free_cirque(
+            &mut self.pool,
+            std::mem::replace(&mut self.cirque, CommandCirque::new()),
+        );
+        Ok(true)
+    }
+
+    unsafe fn dispose(mut self, factory: &mut Factory<B>, _aux: &T) {
+        // Device is idle, copies of the last frames are complete.
+        self.read_complete(factory, |_| true);
+
+        free_cirque(&mut self.pool, self.cirque);
+        factory.destroy_command_pool(self.pool);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn rows_are_tightly_packed() {
+        let extent = Extent {
+            width: 3,
+            height: 2,
+            depth: 1,
+        };
+        assert_eq!(texel_size(Format::Rgba8Unorm), 4);
+        assert_eq!(texel_size(Format::Rgba32Sfloat), 16);
+        assert_eq!(capture_layout(extent, 4), (12, 24));
+    }
+
+    #[test]
+    fn texel_is_addressed_by_row_pitch() {
+        let pixels = (0..24).collect();
+        let image = CapturedImage {
+            pixels,
+            format: Format::Rgba8Unorm,
+            extent: Extent {
+                width: 3,
+                height: 2,
+                depth: 1,
+            },
+            row_pitch: 12,
+            frame: 0,
+        };
+        assert_eq!(image.texel(0, 0), &[0, 1, 2, 3]);
+        assert_eq!(image.texel(2, 1), &[20, 21, 22, 23]);
+    }
+
+    #[test]
+    fn solid_color_capture_matches_every_texel() {
+        let extent = Extent {
+            width: 5,
+            height: 3,
+            depth: 1,
+        };
+        let color = [255, 0, 128, 255];
+        let (row_pitch, size) = capture_layout(extent, texel_size(Format::Rgba8Unorm));
+
+        // Tightly packed copy of image cleared to `color`.
+        let pixels = color.iter().cloned().cycle().take(size as usize).collect();
+        let image = CapturedImage {
+            pixels,
+            format: Format::Rgba8Unorm,
+            extent,
+            row_pitch,
+            frame: 0,
+        };
+
+        for y in 0..extent.height {
+            for x in 0..extent.width {
+                assert_eq!(image.texel(x, y), &color);
+            }
+        }
+    }
+}
@@ -0,0 +1,312 @@
+//! Defines closure node - node that records commands with user provided closure.
+
+use {
+    crate::{
+        command::{
+            Capability, CommandPool, Encoder, Family, IndividualReset, MultiShot,
+            NoSimultaneousUse, PrimaryLevel, Submit,
+        },
+        factory::Factory,
+        frame::{cirque::CommandCirque, Frames},
+        graph::GraphContext,
+        node::{
+            free_cirque, gfx_acquire_barriers, gfx_release_barriers, BufferAccess, DescBuilder,
+            ImageAccess, Node, NodeBuffer, NodeBuildError, NodeDesc, NodeImage, NodeSubmittable,
+        },
+        BufferId, ImageId,
+    },
+    rendy_core::hal::Backend,
+    std::sync::Mutex,
+};
+
+/// Dispose function of closure node that does nothing.
+pub type NoDispose<B, T: ?Sized> = fn(&mut Factory<B>, &T);
+
+/// Descriptor for `ClosureNode`.
+pub struct ClosureNodeDesc<C, F, D> {
+    capability: C,
+    buffers: Vec<BufferAccess>,
+    images: Vec<ImageAccess>,
+    closure: F,
+    dispose: D,
+}
+
+impl<C, F, D> std::fmt::Debug for ClosureNodeDesc<C, F, D>
+where
+    C: std::fmt::Debug,
+{
+    fn fmt(&self, fmt: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        fmt.debug_struct("ClosureNodeDesc")
+            .field("capability", &self.capability)
+            .field("buffers", &self.buffers)
+            .field("images", &self.images)
+            .finish()
+    }
+}
+
+/// Node that records commands with a closure.
+/// Useful for prototyping and one-off work
+/// that doesn't deserve its own `Node` and `NodeDesc` implementations.
+///
+/// Resources and their states are declared up front.
+/// Graph inserts barriers for them around commands recorded by the closure.
+/// Commands are re-recorded each frame.
+pub struct ClosureNode<B: Backend, C, F, D> {
+    pool: CommandPool<B, C, IndividualReset>,
+    cirque: CommandCirque<B, C>,
+    buffers: Vec<NodeBuffer>,
+    images: Vec<NodeImage>,
+
+    // Closures are required to be `Send` only.
+    // They are never accessed through shared reference.
+    closure: Mutex<F>,
+    dispose: Mutex<D>,
+}
+
+impl<B, C, F, D> std::fmt::Debug for ClosureNode<B, C, F, D>
+where
+    B: Backend,
+    C: std::fmt::Debug,
+{
+    fn fmt(&self, fmt: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        fmt.debug_struct("ClosureNode")
+            .field("pool", &self.pool)
+            .field("cirque", &self.cirque)
+            .field("buffers", &self.buffers)
+            .field("images", &self.images)
+            .finish()
+    }
+}
+
+impl<B, T, C, F> ClosureNode<B, C, F, NoDispose<B, T>>
+where
+    B: Backend,
+    T: ?Sized,
+    C: Capability,
+    F: FnMut(&GraphContext<B>, &Factory<B>, &T, &Frames<B>, &mut Encoder<'_, B, C, PrimaryLevel>)
+        + Send
+        + 'static,
+{
+    /// Make builder for node that records commands with `closure`.
+    ///
+    /// # Parameters
+    ///
+    /// `capability` - capability of the queue to execute the node on, e.g. `Graphics`.
+    ///
+    /// `buffers`    - buffers the node uses and their states.
+    ///
+    /// `images`     - images the node uses and their states.
+    ///
+    /// `closure`    - records commands each frame.
+    ///                Receives graph context to access declared resources,
+    ///                factory, auxiliary data, frames and the encoder.
+    ///
+    pub fn new(
+        capability: C,
+        buffers: Vec<(BufferId, BufferAccess)>,
+        images: Vec<(ImageId, ImageAccess)>,
+        closure: F,
+    ) -> DescBuilder<B, T, ClosureNodeDesc<C, F, NoDispose<B, T>>> {
+        let dispose: NoDispose<B, T> = |_, _| {};
+        ClosureNode::with_dispose(capability, buffers, images, closure, dispose)
+    }
+}
+
+impl<B, C, F, D> ClosureNode<B, C, F, D>
+where
+    B: Backend,
+    C: Capability,
+{
+    /// Make builder for node that records commands with `closure`
+    /// and calls `dispose` when node is disposed.
+    /// Resources created by the closure should be destroyed there.
+    ///
+    /// See `ClosureNode::new`.
+    pub fn with_dispose<T>(
+        capability: C,
+        buffers: Vec<(BufferId, BufferAccess)>,
+        images: Vec<(ImageId, ImageAccess)>,
+        closure: F,
+        dispose: D,
+    ) -> DescBuilder<B, T, ClosureNodeDesc<C, F, D>>
+    where
+        T: ?Sized,
+        F: FnMut(
+                &GraphContext<B>,
+                &Factory<B>,
+                &T,
+                &Frames<B>,
+                &mut Encoder<'_, B, C, PrimaryLevel>,
+            ) + Send
+            + 'static,
+        D: FnOnce(&mut Factory<B>, &T) + Send + 'static,
+    {
+        let (buffer_ids, buffers): (Vec<_>, Vec<_>) = buffers.into_iter().unzip();
+        let (image_ids, images): (Vec<_>, Vec<_>) = images.into_iter().unzip();
+
+        let mut builder = DescBuilder::new(ClosureNodeDesc {
+            capability,
+            buffers,
+            images,
+            closure,
+            dispose,
+        });
+
+        for id in buffer_ids {
+            builder.add_buffer(id);
+        }
+        for id in image_ids {
+            builder.add_image(id);
+        }
+        builder
+    }
+}
+
+impl<B, T, C, F, D> NodeDesc<B, T> for ClosureNodeDesc<C, F, D>
+where
+    B: Backend,
+    T: ?Sized,
+    C: Capability,
+    F: FnMut(&GraphContext<B>, &Factory<B>, &T, &Frames<B>, &mut Encoder<'_, B, C, PrimaryLevel>)
+        + Send
+        + 'static,
+    D: FnOnce(&mut Factory<B>, &T) + Send + 'static,
+{
+    type Node = ClosureNode<B, C, F, D>;
+
+    fn buffers(&self) -> Vec<BufferAccess> {
+        self.buffers.clone()
+    }
+
+    fn images(&self) -> Vec<ImageAccess> {
+        self.images.clone()
+    }
+
+    fn build<'a>(
+        self,
+        _ctx: &GraphContext<B>,
+        factory: &mut Factory<B>,
+        family: &mut Family<B>,
+        _queue: usize,
+        _aux: &T,
+        buffers: Vec<NodeBuffer>,
+        images: Vec<NodeImage>,
+    ) -> Result<Self::Node, NodeBuildError> {
+        let pool = factory
+            .create_command_pool(family)
+            .map_err(NodeBuildError::OutOfMemory)?
+            .with_capability()
+            .expect("Graph must specify family that supports capability of the closure node");
+
+        Ok(ClosureNode {
+            pool,
+            cirque: CommandCirque::new(),
+            buffers,
+            images,
+            closure: Mutex::new(self.closure),
+            dispose: Mutex::new(self.dispose),
+        })
+    }
+}
+
+impl<'a, B, C, F, D> NodeSubmittable<'a, B> for ClosureNode<B, C, F, D>
+where
+    B: Backend,
+{
+    type Submittable = Submit<B, NoSimultaneousUse>;
+    type Submittables = Option<Submit<B, NoSimultaneousUse>>;
+}
+
+impl<B, T, C, F, D> Node<B, T> for ClosureNode<B, C, F, D>
+where
+    B: Backend,
+    T: ?Sized,
+    C: Capability,
+    F: FnMut(&GraphContext<B>, &Factory<B>, &T, &Frames<B>, &mut Encoder<'_, B, C, PrimaryLevel>)
+        + Send
+        + 'static,
+    D: FnOnce(&mut Factory<B>, &T) + Send + 'static,
+{
+    type Capability = C;
+
+    fn run<'a>(
+        &'a mut self,
+        ctx: &GraphContext<B>,
+        factory: &Factory<B>,
+        aux: &T,
+        frames: &'a Frames<B>,
+    ) -> Option<Submit<B, NoSimultaneousUse>> {
+        let ClosureNode {
+            pool,
+            cirque,
+            buffers,
+            images,
+            closure,
+            ..
+        } = self;
+
+        let closure = closure
+            .get_mut()
+            .expect("Closure panicked while recording commands");
+
+        let submit = cirque.encode(frames, pool, |cbuf| {
+            // Closure may record different commands each frame.
+            cbuf.or_reset(|cbuf| cbuf.reset()).init(|mut cbuf| {
+                factory.set_object_name(&mut cbuf, std::any::type_name::<F>());
+                let mut cbuf = cbuf.begin(MultiShot(NoSimultaneousUse), ());
+                let mut encoder = cbuf.encoder();
+
+                {
+                    let (stages, barriers) = gfx_acquire_barriers(ctx, &*buffers, &*images);
+                    if !barriers.is_empty() {
+                        log::trace!("Acquire {:?} : {:#?}", stages, barriers);
+                        unsafe {
+                            encoder.pipeline_barrier(
+                                stages,
+                                rendy_core::hal::memory::Dependencies::empty(),
+                                barriers,
+                            );
+                        }
+                    }
+                }
+
+                closure(ctx, factory, aux, frames, &mut encoder);
+
+                {
+                    let (stages, barriers) = gfx_release_barriers(ctx, &*buffers, &*images);
+                    if !barriers.is_empty() {
+                        log::trace!("Release {:?} : {:#?}", stages, barriers);
+                        unsafe {
+                            encoder.pipeline_barrier(
+                                stages,
+                                rendy_core::hal::memory::Dependencies::empty(),
+                                barriers,
+                            );
+                        }
+                    }
+                }
+
+                cbuf.finish()
+            })
+        });
+
+        Some(submit)
+    }
+
+    unsafe fn dispose(self, factory: &mut Factory<B>, aux: &T) {
+        let ClosureNode {
+            mut pool,
+            cirque,
+            dispose,
+            ..
+        } = self;
+
+        free_cirque(&mut pool, cirque);
+        factory.destroy_command_pool(pool);
+
+        let dispose = dispose
+            .into_inner()
+            .expect("Closure panicked while recording commands");
+        dispose(factory, aux);
+    }
+}
@@ -0,0 +1,641 @@
+//! Defines compute node - node that dispatches single compute pipeline.
+
+use {
+    crate::{
+        command::{
+            CommandPool, Compute, DispatchCommand, EncoderCommon, Family, IndividualReset,
+            MultiShot, NoSimultaneousUse, QueueId, Submit,
+        },
+        factory::Factory,
+        frame::{
+            cirque::{CirqueRef, CommandCirque},
+            Frames,
+        },
+        graph::GraphContext,
+        node::{
+            free_cirque, gfx_acquire_barriers, gfx_release_barriers, render::PrepareResult,
+            BufferAccess, DescBuilder, ImageAccess, Node, NodeBuffer, NodeBuildError, NodeDesc,
+            NodeImage, NodeSubmittable,
+        },
+        resource::{DescriptorSetLayout, Handle},
+    },
+    rendy_core::hal::{device::Device as _, Backend},
+};
+
+pub use crate::core::types::{Layout, SetLayout};
+
+/// Descriptor for simple compute node.
+/// Node dispatches single compute pipeline.
+pub trait ComputeNodeDesc<B: Backend, T: ?Sized>: std::fmt::Debug + Sized + 'static {
+    /// Compute node implementation.
+    type Node: ComputeNode<B, T>;
+
+    /// Make compute node builder.
+    fn builder(self) -> DescBuilder<B, T, SimpleComputeNodeDesc<Self>> {
+        SimpleComputeNodeDesc { inner: self }.builder()
+    }
+
+    /// Get set or buffer resources the node uses.
+    fn buffers(&self) -> Vec<BufferAccess> {
+        Vec::new()
+    }
+
+    /// Get set or image resources the node uses.
+    fn images(&self) -> Vec<ImageAccess> {
+        Vec::new()
+    }
+
+    /// Get set of optional image resources the node uses.
+    /// Optional image is provided to the node only if
+    /// one of the nodes added to the graph before this one uses it.
+    fn optional_images(&self) -> Vec<ImageAccess> {
+        Vec::new()
+    }
+
+    /// Layout for compute pipeline.
+    fn layout(&self) -> Layout {
+        Layout {
+            sets: Vec::new(),
+            push_constants: Vec::new(),
+        }
+    }
+
+    /// Load shader set.
+    /// Shader set must contain compute shader.
+    ///
+    /// # Parameters
+    ///
+    /// `factory`   - factory to create shader modules.
+    ///
+    /// `aux`       - auxiliary data container. May be anything the implementation desires.
+    ///
+    fn load_shader_set(&self, factory: &mut Factory<B>, aux: &T) -> rendy_shader::ShaderSet<B>;
+
+    /// Build compute node instance.
+    fn build<'a>(
+        self,
+        ctx: &GraphContext<B>,
+        factory: &mut Factory<B>,
+        queue: QueueId,
+        aux: &T,
+        buffers: Vec<NodeBuffer>,
+        images: Vec<NodeImage>,
+        set_layouts: &[Handle<DescriptorSetLayout<B>>],
+    ) -> Result<Self::Node, NodeBuildError>;
+
+    /// Build compute node instance with optional images.
+    /// `optional_images` has an element for each access returned by `optional_images`,
+    /// `None` is provided for images that have no producer in the graph.
+    ///
+    /// Default implementation ignores optional images and calls `build`.
+    fn build_with_optional<'a>(
+        self,
+        ctx: &GraphContext<B>,
+        factory: &mut Factory<B>,
+        queue: QueueId,
+        aux: &T,
+        buffers: Vec<NodeBuffer>,
+        images: Vec<NodeImage>,
+        optional_images: Vec<Option<NodeImage>>,
+        set_layouts: &[Handle<DescriptorSetLayout<B>>],
+    ) -> Result<Self::Node, NodeBuildError> {
+        let _ = optional_images;
+        self.build(ctx, factory, queue, aux, buffers, images, set_layouts)
+    }
+}
+
+/// Compute node that dispatches single compute pipeline.
+/// Pipeline binding, barriers and the dispatch itself are recorded by the graph.
+pub trait ComputeNode<B: Backend, T: ?Sized>:
+    std::fmt::Debug + Sized + Send + Sync + 'static
+{
+    /// This node descriptor.
+    type Desc: ComputeNodeDesc<B, T, Node = Self>;
+
+    /// Make compute node builder.
+    fn builder() -> DescBuilder<B, T, SimpleComputeNodeDesc<Self::Desc>>
+    where
+        Self::Desc: Default,
+    {
+        Self::Desc::default().builder()
+    }
+
+    /// Prepare to record dispatch commands.
+    ///
+    /// Should return `PrepareResult::DrawRecord` if commands must be re-recorded.
+    fn prepare(
+        &mut self,
+        _factory: &Factory<B>,
+        _queue: QueueId,
+        _set_layouts: &[Handle<DescriptorSetLayout<B>>],
+        _index: usize,
+        _aux: &T,
+    ) -> PrepareResult {
+        PrepareResult::DrawRecord
+    }
+
+    /// Bind descriptor sets and push constants required by dispatch.
+    /// Compute pipeline is already bound.
+    fn bind(
+        &mut self,
+        _layout: &B::PipelineLayout,
+        _encoder: EncoderCommon<'_, B, Compute>,
+        _index: usize,
+        _aux: &T,
+    ) {
+    }
+
+    /// Get number of local workgroups to dispatch.
+    fn dispatch(&self, index: usize, aux: &T) -> DispatchCommand;
+
+    /// Update resources that reference images reallocated by `Graph::resize`.
+    /// Dispatch commands are re-recorded after this call.
+    ///
+    /// Returns `false` if node doesn't support resizing and must be rebuilt instead.
+    fn resize(&mut self, _ctx: &GraphContext<B>, _factory: &Factory<B>, _aux: &T) -> bool {
+        false
+    }
+
+    /// Free all resources and destroy node instance.
+    fn dispose(self, factory: &mut Factory<B>, aux: &T);
+}
+
+/// Descriptor for `SimpleComputeNode`.
+#[derive(Debug)]
+pub struct SimpleComputeNodeDesc<D: std::fmt::Debug> {
+    inner: D,
+}
+
+/// Node that consist of compute pipeline dispatched by `ComputeNode`.
+#[derive(Debug)]
+pub struct SimpleComputeNode<B: Backend, N> {
+    set_layouts: Vec<Handle<DescriptorSetLayout<B>>>,
+    pipeline_layout: B::PipelineLayout,
+    pipeline: B::ComputePipeline,
+    command_pool: CommandPool<B, Compute, IndividualReset>,
+    command_cirque: CommandCirque<B, Compute>,
+    queue: QueueId,
+    buffers: Vec<NodeBuffer>,
+    images: Vec<NodeImage>,
+    node: N,
+}
+
+impl<B, T, D> NodeDesc<B, T> for SimpleComputeNodeDesc<D>
+where
+    B: Backend,
+    T: ?Sized,
+    D: ComputeNodeDesc<B, T>,
+{
+    type Node = SimpleComputeNode<B, D::Node>;
+
+    fn buffers(&self) -> Vec<BufferAccess> {
+        self.inner.buffers()
+    }
+
+    fn images(&self) -> Vec<ImageAccess> {
+        self.inner.images()
+    }
+
+    fn optional_images(&self) -> Vec<ImageAccess> {
+        self.inner.optional_images()
+    }
+
+    fn build<'a>(
+        self,
+        ctx: &GraphContext<B>,
+        factory: &mut Factory<B>,
+        family: &mut Family<B>,
+        queue: usize,
+        aux: &T,
+        buffers: Vec<NodeBuffer>,
+        images: Vec<NodeImage>,
+    ) -> Result<Self::Node, NodeBuildError> {
+        self.build_with_optional(
+            ctx,
+            factory,
+            family,
+            queue,
+            aux,
+            buffers,
+            images,
+            Vec::new(),
+        )
+    }
+
+    fn build_with_optional<'a>(
+        self,
+        ctx: &GraphContext<B>,
+        factory: &mut Factory<B>,
+        family: &mut Family<B>,
+        queue: usize,
+        aux: &T,
+        buffers: Vec<NodeBuffer>,
+        images: Vec<NodeImage>,
+        optional_images: Vec<Option<NodeImage>>,
+    ) -> Result<Self::Node, NodeBuildError> {
+        log::trace!("Load shader set for compute node");
+
+        let mut shader_set = self.inner.load_shader_set(factory, aux);
+
+        let layout = self.inner.layout();
+
+        let set_layouts = layout
+            .sets
+            .into_iter()
+            .map(|set| {
+                factory
+                    .create_descriptor_set_layout(set.bindings)
+                    .map(Handle::from)
+            })
+            .collect::<Result<Vec<_>, _>>()
+            .map_err(|e| {
+                shader_set.dispose(factory);
+                NodeBuildError::OutOfMemory(e)
+            })?;
+
+        let pipeline_layout = unsafe {
+            factory
+                .device()
+                .create_pipeline_layout(set_layouts.iter().map(|l| l.raw()), layout.push_constants)
+        }
+        .map_err(|e| {
+            shader_set.dispose(factory);
+            NodeBuildError::OutOfMemory(e)
+        })?;
+
+        let shader = match compute_shader(&shader_set) {
+            Err(e) => {
+                shader_set.dispose(factory);
+                unsafe { factory.device().destroy_pipeline_layout(pipeline_layout) };
+                return Err(e);
+            }
+            Ok(s) => s,
+        };
+
+        let pipeline = unsafe {
+            factory.device().create_compute_pipeline(
+                &rendy_core::hal::pso::ComputePipelineDesc {
+                    shader,
+                    layout: &pipeline_layout,
+                    flags: rendy_core::hal::pso::PipelineCreationFlags::empty(),
+                    parent: rendy_core::hal::pso::BasePipeline::None,
+                },
+                Some(factory.pipeline_cache()),
+            )
+        }
+        .map_err(|e| {
+            shader_set.dispose(factory);
+            NodeBuildError::Pipeline(e)
+        })?;
+
+        shader_set.dispose(factory);
+
+        let queue = QueueId {
+            family: family.id(),
+            index: queue,
+        };
+
+        // Barriers are recorded only for optional images that were provided.
+        let barrier_images = images
+            .iter()
+            .chain(optional_images.iter().flatten())
+            .cloned()
+            .collect();
+
+        let node = self.inner.build_with_optional(
+            ctx,
+            factory,
+            queue,
+            aux,
+            buffers.clone(),
+            images,
+            optional_images,
+            &set_layouts,
+        )?;
+
+        let command_pool = factory
+            .create_command_pool(family)
+            .map_err(NodeBuildError::OutOfMemory)?
+            .with_capability()
+            .expect("Graph must specify family that supports `Compute`");
+
+        Ok(SimpleComputeNode {
+            set_layouts,
+            pipeline_layout,
+            pipeline,
+            command_pool,
+            command_cirque: CommandCirque::new(),
+            queue,
+            buffers,
+            images: barrier_images,
+            node,
+        })
+    }
+}
+
+/// Get compute shader entry point of the set.
+fn compute_shader<B: Backend>(
+    shader_set: &rendy_shader::ShaderSet<B>,
+) -> Result<rendy_core::hal::pso::EntryPoint<'_, B>, NodeBuildError> {
+    match shader_set.raw_compute() {
+        Ok(Some(shader)) => Ok(shader),
+        Ok(None) => Err(NodeBuildError::MissingShader(
+            rendy_core::hal::pso::ShaderStageFlags::COMPUTE,
+        )),
+        Err(e) => match e {},
+    }
+}
+
+impl<'a, B, N> NodeSubmittable<'a, B> for SimpleComputeNode<B, N>
+where
+    B: Backend,
+{
+    type Submittable = Submit<B, NoSimultaneousUse>;
+    type Submittables = Option<Submit<B, NoSimultaneousUse>>;
+}
+
+impl<B, T, N> Node<B, T> for SimpleComputeNode<B, N>
+where
+    B: Backend,
+    T: ?Sized,
+    N: ComputeNode<B, T>,
+{
+    type Capability = Compute;
+
+    fn run<'a>(
+        &'a mut self,
+        ctx: &GraphContext<B>,
+        factory: &Factory<B>,
+        aux: &T,
+        frames: &'a Frames<B>,
+    ) -> Option<Submit<B, NoSimultaneousUse>> {
+        let SimpleComputeNode {
+            set_layouts,
+            pipeline_layout,
+            pipeline,
+            command_pool,
+            command_cirque,
+            queue,
+            buffers,
+            images,
+            node,
+        } = self;
+
+        let submit = command_cirque.encode(frames, command_pool, |mut cbuf| {
+            let index = cbuf.index();
+
+            if node
+                .prepare(factory, *queue, set_layouts, index, aux)
+                .force_record()
+            {
+                cbuf = CirqueRef::Initial(cbuf.or_reset(|cbuf| cbuf.reset()));
+            }
+
+            cbuf.or_init(|mut cbuf| {
+                factory.set_object_name(&mut cbuf, std::any::type_name::<N>());
+                let mut cbuf = cbuf.begin(MultiShot(NoSimultaneousUse), ());
+                let mut encoder = cbuf.encoder();
+
+                {
+                    let (stages, barriers) = gfx_acquire_barriers(ctx, &*buffers, &*images);
+                    if !barriers.is_empty() {
+                        log::trace!("Acquire {:?} : {:#?}", stages, barriers);
+                        unsafe {
+                            encoder.pipeline_barrier(
+                                stages,
+                                rendy_core::hal::memory::Dependencies::empty(),
+                                barriers,
+                            );
+                        }
+                    }
+                }
+
+                encoder.bind_compute_pipeline(pipeline);
+                node.bind(pipeline_layout, encoder.reborrow(), index, aux);
+
+                let DispatchCommand { x, y, z } = node.dispatch(index, aux);
+                unsafe {
+                    encoder.dispatch(x, y, z);
+                }
+
+                {
+                    let (stages, barriers) = gfx_release_barriers(ctx, &*buffers, &*images);
+                    if !barriers.is_empty() {
+                        log::trace!("Release {:?} : {:#?}", stages, barriers);
+                        unsafe {
+                            encoder.pipeline_barrier(
+                                stages,
+                                rendy_core::hal::memory::Dependencies::empty(),
+                                barriers,
+                            );
+                        }
+                    }
+                }
+
+                cbuf.finish()
+            })
+        });
+
+        Some(submit)
+    }
+
+    unsafe fn resize(
+        &mut self,
+        ctx: &GraphContext<B>,
+        factory: &mut Factory<B>,
+        aux: &T,
+    ) -> Result<bool, NodeBuildError> {
+        if !self.node.resize(ctx, factory, aux) {
+            return Ok(false);
+        }
+
+        free_cirque(
+            &mut self.command_pool,
+            std::mem::replace(&mut self.command_cirque, CommandCirque::new()),
+        );
+        Ok(true)
+    }
+
+    unsafe fn dispose(self, factory: &mut Factory<B>, aux: &T) {
+        let SimpleComputeNode {
+            set_layouts,
+            pipeline_layout,
+            pipeline,
+            mut command_pool,
+            command_cirque,
+            node,
+            ..
+        } = self;
+
+        node.dispose(factory, aux);
+
+        free_cirque(&mut command_pool, command_cirque);
+        factory.destroy_command_pool(command_pool);
+
+        factory.device().destroy_compute_pipeline(pipeline);
+        factory.device().destroy_pipeline_layout(pipeline_layout);
+        drop(set_layouts);
+    }
+}
+
+#[cfg(all(test, feature = "empty"))]
+mod tests {
+    use {
+        super::*,
+        crate::{
+            command::QueueType,
+            factory::{init_with_instance, Config},
+            node::NodeBuilder,
+            GraphBuilder, ImageId,
+        },
+        rendy_core::{
+            empty::Backend,
+            hal::{
+                buffer::{Access, Usage},
+                pso::{PipelineStage, ShaderStageFlags},
+                Instance as _,
+            },
+            Instance,
+        },
+    };
+
+    #[derive(Debug, Default)]
+    struct Desc;
+
+    #[derive(Debug)]
+    struct Dispatch;
+
+    impl ComputeNodeDesc<Backend, ()> for Desc {
+        type Node = Dispatch;
+
+        fn optional_images(&self) -> Vec<ImageAccess> {
+            vec![ImageAccess::sampled(PipelineStage::COMPUTE_SHADER)]
+        }
+
+        fn load_shader_set(
+            &self,
+            _factory: &mut Factory<Backend>,
+            _aux: &(),
+        ) -> rendy_shader::ShaderSet<Backend> {
+            rendy_shader::ShaderSet::default()
+        }
+
+        fn build<'a>(
+            self,
+            _ctx: &GraphContext<Backend>,
+            _factory: &mut Factory<Backend>,
+            _queue: QueueId,
+            _aux: &(),
+            _buffers: Vec<NodeBuffer>,
+            _images: Vec<NodeImage>,
+            _set_layouts: &[Handle<DescriptorSetLayout<Backend>>],
+        ) -> Result<Dispatch, NodeBuildError> {
+            Ok(Dispatch)
+        }
+    }
+
+    impl ComputeNode<Backend, ()> for Dispatch {
+        type Desc = Desc;
+
+        fn dispatch(&self, _index: usize, _aux: &()) -> DispatchCommand {
+            DispatchCommand { x: 1, y: 1, z: 1 }
+        }
+
+        fn dispose(self, _factory: &mut Factory<Backend>, _aux: &()) {}
+    }
+
+    #[derive(Debug, Default)]
+    struct FillDesc;
+
+    #[derive(Debug)]
+    struct Fill;
+
+    impl ComputeNodeDesc<Backend, ()> for FillDesc {
+        type Node = Fill;
+
+        fn buffers(&self) -> Vec<BufferAccess> {
+            vec![BufferAccess {
+                access: Access::SHADER_WRITE,
+                usage: Usage::STORAGE,
+                stages: PipelineStage::COMPUTE_SHADER,
+            }]
+        }
+
+        fn load_shader_set(
+            &self,
+            _factory: &mut Factory<Backend>,
+            _aux: &(),
+        ) -> rendy_shader::ShaderSet<Backend> {
+            rendy_shader::ShaderSet::default()
+        }
+
+        fn build<'a>(
+            self,
+            _ctx: &GraphContext<Backend>,
+            _factory: &mut Factory<Backend>,
+            _queue: QueueId,
+            _aux: &(),
+            _buffers: Vec<NodeBuffer>,
+            _images: Vec<NodeImage>,
+            _set_layouts: &[Handle<DescriptorSetLayout<Backend>>],
+        ) -> Result<Fill, NodeBuildError> {
+            Ok(Fill)
+        }
+    }
+
+    impl ComputeNode<Backend, ()> for Fill {
+        type Desc = FillDesc;
+
+        fn dispatch(&self, _index: usize, _aux: &()) -> DispatchCommand {
+            DispatchCommand { x: 1, y: 1, z: 1 }
+        }
+
+        fn dispose(self, _factory: &mut Factory<Backend>, _aux: &()) {}
+    }
+
+    #[test]
+    fn buffer_writing_node_lands_on_compute_family() {
+        let instance = rendy_core::empty::Instance::create("rendy", 1).unwrap();
+        let config: Config = Default::default();
+        let (mut factory, families) = init_with_instance(Instance::new(instance), &config).unwrap();
+
+        let mut graph = GraphBuilder::<Backend, ()>::new();
+        let buffer = graph.create_buffer(64);
+        let builder = Fill::builder().with_buffer(buffer);
+        let family = builder
+            .family(&mut factory, &families)
+            .expect("Device must have compute capable family");
+        graph.add_node(builder);
+        graph.validate(&mut factory, &families).unwrap();
+
+        match families.family(family).capability() {
+            QueueType::Compute | QueueType::General => {}
+            other => panic!("Compute node is placed on {:?} family", other),
+        }
+    }
+
+    #[test]
+    fn missing_compute_shader_is_build_error() {
+        let shader_set = rendy_shader::ShaderSet::<Backend>::default();
+        match compute_shader(&shader_set) {
+            Err(NodeBuildError::MissingShader(stages)) => {
+                assert_eq!(stages, ShaderStageFlags::COMPUTE)
+            }
+            other => panic!("Unexpected result {:?}", other.map(|_| ())),
+        }
+    }
+
+    #[test]
+    fn builder_forwards_desc_resources() {
+        let image = ImageId(0, 0);
+        let builder = Dispatch::builder().with_optional_image(image);
+
+        assert!(builder.buffers().is_empty());
+        assert!(builder.images().is_empty());
+        let optional = builder.optional_images();
+        assert_eq!(optional.len(), 1);
+        assert_eq!(optional[0].0, image);
+        assert_eq!(optional[0].1.stages, PipelineStage::COMPUTE_SHADER);
+        assert!(Desc.layout().sets.is_empty());
+    }
+}
@@ -0,0 +1,428 @@
+//! Defines format conversion node - node that converts image into another format.
+
+use {
+    crate::{
+        command::{
+            CommandPool, Family, Graphics, IndividualReset, MultiShot, NoSimultaneousUse, Submit,
+        },
+        factory::Factory,
+        frame::{cirque::CommandCirque, Frames},
+        graph::GraphContext,
+        node::{
+            blit::whole_level_region, free_cirque, gfx_acquire_barriers, gfx_release_barriers,
+            DescBuilder, ImageAccess, Node, NodeBuffer, NodeBuildError, NodeDesc, NodeImage,
+            NodeSubmittable,
+        },
+        ImageId,
+    },
+    rendy_core::hal::{
+        format::{Format, ImageFeature},
+        Backend,
+    },
+};
+
+/// How image is converted into another format.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ConvertPath {
+    /// Formats have identical bit layout.
+    /// Image is copied and its bits are reinterpreted as destination format,
+    /// e.g. `Rgba8Unorm` image copied into `Rgba8Srgb` one keeps the same bytes.
+    Copy,
+    /// Texels are read as source format and converted into destination format by blit.
+    Blit,
+}
+
+/// Choose how image of format `src` is converted into image of format `dst`.
+/// `src_features` and `dst_features` are optimal tiling features of the formats.
+/// Returns `None` if formats can be neither copied nor blitted.
+pub fn convert_path(
+    src: Format,
+    dst: Format,
+    src_features: ImageFeature,
+    dst_features: ImageFeature,
+) -> Option<ConvertPath> {
+    if src.base_format().0 == dst.base_format().0 {
+        Some(ConvertPath::Copy)
+    } else if src_features.contains(ImageFeature::BLIT_SRC)
+        && dst_features.contains(ImageFeature::BLIT_DST)
+    {
+        Some(ConvertPath::Blit)
+    } else {
+        None
+    }
+}
+
+/// Descriptor for `FormatConvertNode`.
+/// Converts first level of source image into first level of destination image of another format.
+///
+/// Images with identical bit layout are copied, others are blitted,
+/// which converts texels and scales image if extents differ.
+/// This is useful to bridge graph output and surface format mismatch.
+/// Formats that can be neither copied nor blitted aren't converted,
+/// node fails to build with `NodeBuildError::Conversion`.
+/// Both images must have the same number of layers.
+///
+/// Source image is used in `TransferSrcOptimal` layout
+/// and destination image in `TransferDstOptimal`.
+/// Barriers are inserted by the graph.
+#[derive(Clone, Debug)]
+pub struct FormatConvertNodeDesc {
+    src: ImageId,
+    dst: ImageId,
+    filter: rendy_core::hal::image::Filter,
+}
+
+impl FormatConvertNodeDesc {
+    /// Create descriptor for node that converts `src` image into `dst` image.
+    /// `filter` is used if images are blitted.
+    pub fn new(src: ImageId, dst: ImageId, filter: rendy_core::hal::image::Filter) -> Self {
+        assert_ne!(src, dst, "Format conversion node requires distinct images");
+        FormatConvertNodeDesc { src, dst, filter }
+    }
+}
+
+/// Node that converts image into another format.
+#[derive(Debug)]
+pub struct FormatConvertNode<B: Backend> {
+    pool: CommandPool<B, Graphics, IndividualReset>,
+    cirque: CommandCirque<B, Graphics>,
+    /// Source and destination images.
+    images: [NodeImage; 2],
+    path: ConvertPath,
+    filter: rendy_core::hal::image::Filter,
+}
+
+impl<B> FormatConvertNode<B>
+where
+    B: Backend,
+{
+    /// Get conversion path chosen for the images.
+    pub fn path(&self) -> ConvertPath {
+        self.path
+    }
+}
+
+/// Check that copy or blit between the ranges covers the same number of layers.
+fn check_layers(
+    src: &std::ops::Range<rendy_core::hal::image::Layer>,
+    dst: &std::ops::Range<rendy_core::hal::image::Layer>,
+) -> Result<(), NodeBuildError> {
+    let (src, dst) = (src.end - src.start, dst.end - dst.start);
+    if src != dst {
+        return Err(NodeBuildError::LayerCount { src, dst });
+    }
+    Ok(())
+}
+
+/// Region that copies whole first level of `src` into `dst`.
+/// Copies intersection of both extents.
+/// Layer counts are checked by `check_layers` when the node is built.
+fn whole_level_copy<B: Backend>(
+    ctx: &GraphContext<B>,
+    src: &NodeImage,
+    dst: &NodeImage,
+) -> rendy_core::hal::command::ImageCopy {
+    let src_extent = ctx
+        .get_image(src.id)
+        .expect("Source image does not exist")
+        .kind()
+        .extent();
+    let dst_extent = ctx
+        .get_image(dst.id)
+        .expect("Destination image does not exist")
+        .kind()
+        .extent();
+
+    rendy_core::hal::command::ImageCopy {
+        src_subresource: rendy_core::hal::image::SubresourceLayers {
+            aspects: src.range.aspects,
+            level: 0,
+            layers: src.range.layers.clone(),
+        },
+        src_offset: rendy_core::hal::image::Offset::ZERO,
+        dst_subresource: rendy_core::hal::image::SubresourceLayers {
+            aspects: dst.range.aspects,
+            level: 0,
+            layers: dst.range.layers.clone(),
+        },
+        dst_offset: rendy_core::hal::image::Offset::ZERO,
+        extent: rendy_core::hal::image::Extent {
+            width: src_extent.width.min(dst_extent.width),
+            height: src_extent.height.min(dst_extent.height),
+            depth: src_extent.depth.min(dst_extent.depth),
+        },
+    }
+}
+
+impl<B, T> NodeDesc<B, T> for FormatConvertNodeDesc
+where
+    B: Backend,
+    T: ?Sized,
+{
+    type Node = FormatConvertNode<B>;
+
+    fn builder(self) -> DescBuilder<B, T, Self> {
+        let (src, dst) = (self.src, self.dst);
+        DescBuilder::new(self).with_image(src).with_image(dst)
+    }
+
+    fn images(&self) -> Vec<ImageAccess> {
+        vec![
+            ImageAccess {
+                access: rendy_core::hal::image::Access::TRANSFER_READ,
+                usage: rendy_core::hal::image::Usage::TRANSFER_SRC,
+                layout: rendy_core::hal::image::Layout::TransferSrcOptimal,
+                stages: rendy_core::hal::pso::PipelineStage::TRANSFER,
+            },
+            ImageAccess {
+                access: rendy_core::hal::image::Access::TRANSFER_WRITE,
+                usage: rendy_core::hal::image::Usage::TRANSFER_DST,
+                layout: rendy_core::hal::image::Layout::TransferDstOptimal,
+                stages: rendy_core::hal::pso::PipelineStage::TRANSFER,
+            },
+        ]
+    }
+
+    fn build<'a>(
+        self,
+        ctx: &GraphContext<B>,
+        factory: &mut Factory<B>,
+        family: &mut Family<B>,
+        _queue: usize,
+        _aux: &T,
+        buffers: Vec<NodeBuffer>,
+        images: Vec<NodeImage>,
+    ) -> Result<Self::Node, NodeBuildError> {
+        assert!(buffers.is_empty());
+        assert_eq!(images.len(), 2);
+
+        let src = images
+            .iter()
+            .find(|image| image.id == self.src)
+            .cloned()
+            .expect("Source image must be provided");
+        let dst = images
+            .iter()
+            .find(|image| image.id == self.dst)
+            .cloned()
+            .expect("Destination image must be provided");
+        check_layers(&src.range.layers, &dst.range.layers)?;
+
+        let format = |image: &NodeImage| {
+            ctx.get_image(image.id)
+                .expect("Image does not exist")
+                .format()
+        };
+        let features = |format| {
+            rendy_core::hal::adapter::PhysicalDevice::format_properties(
+                factory.physical(),
+                Some(format),
+            )
+            .optimal_tiling
+        };
+        let (src_format, dst_format) = (format(&src), format(&dst));
+        let path = convert_path(
+            src_format,
+            dst_format,
+            features(src_format),
+            features(dst_format),
+        )
+        .ok_or(NodeBuildError::Conversion {
+            src: src_format,
+            dst: dst_format,
+        })?;
+        log::debug!(
+            "Convert {:?} image into {:?} with {:?}",
+            src_format,
+            dst_format,
+            path
+        );
+
+        let pool = factory
+            .create_command_pool(family)
+            .map_err(NodeBuildError::OutOfMemory)?
+            .with_capability()
+            .expect("Graph must specify family that supports `Graphics`");
+
+        Ok(FormatConvertNode {
+            pool,
+            cirque: CommandCirque::new(),
+            images: [src, dst],
+            path,
+            filter: self.filter,
+        })
+    }
+}
+
+impl<'a, B> NodeSubmittable<'a, B> for FormatConvertNode<B>
+where
+    B: Backend,
+{
+    type Submittable = Submit<B, NoSimultaneousUse>;
+    type Submittables = Option<Submit<B, NoSimultaneousUse>>;
+}
+
+impl<B, T> Node<B, T> for FormatConvertNode<B>
+where
+    B: Backend,
+    T: ?Sized,
+{
+    // Blit commands require graphics queue.
+    type Capability = Graphics;
+
+    fn run<'a>(
+        &'a mut self,
+        ctx: &GraphContext<B>,
+        factory: &Factory<B>,
+        _aux: &T,
+        frames: &'a Frames<B>,
+    ) -> Option<Submit<B, NoSimultaneousUse>> {
+        let FormatConvertNode {
+            pool,
+            cirque,
+            images,
+            path,
+            filter,
+        } = self;
+
+        let [src, dst] = &*images;
+
+        let submit = cirque.encode(frames, pool, |cbuf| {
+            cbuf.or_init(|mut cbuf| {
+                factory.set_object_name(&mut cbuf, std::any::type_name::<Self>());
+                let mut cbuf = cbuf.begin(MultiShot(NoSimultaneousUse), ());
+                let mut encoder = cbuf.encoder();
+
+                {
+                    let (stages, barriers) = gfx_acquire_barriers(ctx, None, &*images);
+                    if !barriers.is_empty() {
+                        log::trace!("Acquire {:?} : {:#?}", stages, barriers);
+                        unsafe {
+                            encoder.pipeline_barrier(
+                                stages,
+                                rendy_core::hal::memory::Dependencies::empty(),
+                                barriers,
+                            );
+                        }
+                    }
+                }
+
+                let src_image = ctx.get_image(src.id).expect("Image does not exist");
+                let dst_image = ctx.get_image(dst.id).expect("Image does not exist");
+                match path {
+                    ConvertPath::Copy => unsafe {
+                        encoder.copy_image(
+                            src_image.raw(),
+                            src.layout,
+                            dst_image.raw(),
+                            dst.layout,
+                            Some(whole_level_copy(ctx, src, dst)),
+                        );
+                    },
+                    ConvertPath::Blit => unsafe {
+                        encoder.blit_image(
+                            src_image.raw(),
+                            src.layout,
+                            dst_image.raw(),
+                            dst.layout,
+                            *filter,
+                            Some(whole_level_region(ctx, src, dst)),
+                        );
+                    },
+                }
+
+                {
+                    let (stages, barriers) = gfx_release_barriers(ctx, None, &*images);
+                    if !barriers.is_empty() {
+                        log::trace!("Release {:?} : {:#?}", stages, barriers);
+                        unsafe {
+                            encoder.pipeline_barrier(
+                                stages,
+                                rendy_core::hal::memory::Dependencies::empty(),
+                                barriers,
+                            );
+                        }
+                    }
+                }
+
+                cbuf.finish()
+            })
+        });
+
+        Some(submit)
+    }
+
+    unsafe fn resize(
+        &mut self,
+        _ctx: &GraphContext<B>,
+        _factory: &mut Factory<B>,
+        _aux: &T,
+    ) -> Result<bool, NodeBuildError> {
+        // Regions are computed from image extents when commands are recorded.
+        free_cirque(
+            &mut self.pool,
+            std::mem::replace(&mut self.cirque, CommandCirque::new()),
+        );
+        Ok(true)
+    }
+
+    unsafe fn dispose(mut self, factory: &mut Factory<B>, _aux: &T) {
+        free_cirque(&mut self.pool, self.cirque);
+        factory.destroy_command_pool(self.pool);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use {
+        super::{check_layers, convert_path, ConvertPath},
+        crate::node::NodeBuildError,
+        rendy_core::hal::format::{Format, ImageFeature},
+    };
+
+    #[test]
+    fn layer_counts_must_match() {
+        assert!(check_layers(&(0..1), &(3..4)).is_ok());
+        match check_layers(&(0..6), &(0..1)) {
+            Err(NodeBuildError::LayerCount { src: 6, dst: 1 }) => {}
+            other => panic!("Unexpected result {:?}", other),
+        }
+    }
+
+    #[test]
+    fn same_layout_is_copied() {
+        let features = ImageFeature::empty();
+        assert_eq!(
+            convert_path(Format::Rgba8Unorm, Format::Rgba8Srgb, features, features),
+            Some(ConvertPath::Copy)
+        );
+        assert_eq!(
+            convert_path(Format::Bgra8Srgb, Format::Bgra8Unorm, features, features),
+            Some(ConvertPath::Copy)
+        );
+    }
+
+    #[test]
+    fn different_layout_is_blitted() {
+        let blit = ImageFeature::BLIT_SRC | ImageFeature::BLIT_DST;
+        assert_eq!(
+            convert_path(Format::Rgba8Srgb, Format::Bgra8Srgb, blit, blit),
+            Some(ConvertPath::Blit)
+        );
+        assert_eq!(
+            convert_path(Format::Rgba16Sfloat, Format::Rgba8Unorm, blit, blit),
+            Some(ConvertPath::Blit)
+        );
+
+        // Destination format that can't be blitted into.
+        assert_eq!(
+            convert_path(
+                Format::Rgba16Sfloat,
+                Format::Rgba8Unorm,
+                blit,
+                ImageFeature::BLIT_SRC
+            ),
+            None
+        );
+    }
+}
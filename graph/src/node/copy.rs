@@ -0,0 +1,367 @@
+//! Defines copy node - node that copies regions of one buffer into another.
+
+use {
+    crate::{
+        command::{
+            CommandPool, Family, IndividualReset, MultiShot, NoSimultaneousUse, Submit, Transfer,
+        },
+        factory::Factory,
+        frame::{cirque::CommandCirque, Frames},
+        graph::GraphContext,
+        node::{
+            free_cirque, gfx_acquire_barriers, gfx_release_barriers, BufferAccess, DescBuilder,
+            Node, NodeBuffer, NodeBuildError, NodeDesc, NodeImage, NodeSubmittable,
+        },
+        BufferId,
+    },
+    rendy_core::hal::{command::BufferCopy, Backend},
+};
+
+/// Descriptor for `CopyBufferNode`.
+/// Copies regions of source buffer into destination buffer.
+///
+/// Source buffer is accessed with `TRANSFER_READ`
+/// and destination buffer with `TRANSFER_WRITE`.
+/// Barriers are inserted by the graph.
+#[derive(Clone, Debug)]
+pub struct CopyBufferNodeDesc {
+    src: BufferId,
+    dst: BufferId,
+    regions: Vec<BufferCopy>,
+}
+
+impl CopyBufferNodeDesc {
+    /// Create descriptor for node that copies regions of `src` into `dst`.
+    pub fn new(src: BufferId, dst: BufferId) -> Self {
+        assert_ne!(src, dst, "Copy node requires distinct buffers");
+        CopyBufferNodeDesc {
+            src,
+            dst,
+            regions: Vec::new(),
+        }
+    }
+
+    /// Add region to copy.
+    pub fn add_region(&mut self, region: BufferCopy) -> &mut Self {
+        self.regions.push(region);
+        self
+    }
+
+    /// Add region to copy.
+    pub fn with_region(mut self, region: BufferCopy) -> Self {
+        self.add_region(region);
+        self
+    }
+}
+
+/// Node that copies regions of one buffer into another.
+#[derive(Debug)]
+pub struct CopyBufferNode<B: Backend> {
+    pool: CommandPool<B, Transfer, IndividualReset>,
+    cirque: CommandCirque<B, Transfer>,
+    /// Source and destination buffers.
+    buffers: [NodeBuffer; 2],
+    regions: Vec<BufferCopy>,
+}
+
+/// Find first region that doesn't fit into source or destination buffer.
+fn invalid_region(regions: &[BufferCopy], src_size: u64, dst_size: u64) -> Option<usize> {
+    let fits = |offset: u64, size: u64, total: u64| {
+        offset.checked_add(size).map_or(false, |end| end <= total)
+    };
+    regions.iter().position(|region| {
+        !fits(region.src, region.size, src_size) || !fits(region.dst, region.size, dst_size)
+    })
+}
+
+impl<B, T> NodeDesc<B, T> for CopyBufferNodeDesc
+where
+    B: Backend,
+    T: ?Sized,
+{
+    type Node = CopyBufferNode<B>;
+
+    fn builder(self) -> DescBuilder<B, T, Self> {
+        let (src, dst) = (self.src, self.dst);
+        DescBuilder::new(self).with_buffer(src).with_buffer(dst)
+    }
+
+    fn buffers(&self) -> Vec<BufferAccess> {
+        vec![
+            BufferAccess {
+                access: rendy_core::hal::buffer::Access::TRANSFER_READ,
+                usage: rendy_core::hal::buffer::Usage::TRANSFER_SRC,
+                stages: rendy_core::hal::pso::PipelineStage::TRANSFER,
+            },
+            BufferAccess {
+                access: rendy_core::hal::buffer::Access::TRANSFER_WRITE,
+                usage: rendy_core::hal::buffer::Usage::TRANSFER_DST,
+                stages: rendy_core::hal::pso::PipelineStage::TRANSFER,
+            },
+        ]
+    }
+
+    fn build<'a>(
+        self,
+        ctx: &GraphContext<B>,
+        factory: &mut Factory<B>,
+        family: &mut Family<B>,
+        _queue: usize,
+        _aux: &T,
+        buffers: Vec<NodeBuffer>,
+        images: Vec<NodeImage>,
+    ) -> Result<Self::Node, NodeBuildError> {
+        assert_eq!(buffers.len(), 2);
+        assert!(images.is_empty());
+
+        let src = buffers
+            .iter()
+            .find(|buffer| buffer.id == self.src)
+            .cloned()
+            .expect("Source buffer must be provided");
+        let dst = buffers
+            .iter()
+            .find(|buffer| buffer.id == self.dst)
+            .cloned()
+            .expect("Destination buffer must be provided");
+
+        let size = |buffer: &NodeBuffer| {
+            ctx.get_buffer(buffer.id)
+                .expect("Buffer does not exist")
+                .size()
+        };
+        if let Some(index) = invalid_region(&self.regions, size(&src), size(&dst)) {
+            return Err(NodeBuildError::CopyRegion(index));
+        }
+
+        let pool = factory
+            .create_command_pool(family)
+            .map_err(NodeBuildError::OutOfMemory)?
+            .with_capability()
+            .expect("Graph must specify family that supports `Transfer`");
+
+        Ok(CopyBufferNode {
+            pool,
+            cirque: CommandCirque::new(),
+            buffers: [src, dst],
+            regions: self.regions,
+        })
+    }
+}
+
+impl<'a, B> NodeSubmittable<'a, B> for CopyBufferNode<B>
+where
+    B: Backend,
+{
+    type Submittable = Submit<B, NoSimultaneousUse>;
+    type Submittables = Option<Submit<B, NoSimultaneousUse>>;
+}
+
+impl<B, T> Node<B, T> for CopyBufferNode<B>
+where
+    B: Backend,
+    T: ?Sized,
+{
+    type Capability = Transfer;
+
+    fn run<'a>(
+        &'a mut self,
+        ctx: &GraphContext<B>,
+        factory: &Factory<B>,
+        _aux: &T,
+        frames: &'a Frames<B>,
+    ) -> Option<Submit<B, NoSimultaneousUse>> {
+        let CopyBufferNode {
+            pool,
+            cirque,
+            buffers,
+            regions,
+        } = self;
+
+        let [src, dst] = &*buffers;
+
+        let submit = cirque.encode(frames, pool, |cbuf| {
+            cbuf.or_init(|mut cbuf| {
+                factory.set_object_name(&mut cbuf, std::any::type_name::<Self>());
+                let mut cbuf = cbuf.begin(MultiShot(NoSimultaneousUse), ());
+                let mut encoder = cbuf.encoder();
+
+                {
+                    let (stages, barriers) = gfx_acquire_barriers(ctx, &*buffers, None);
+                    if !barriers.is_empty() {
+                        log::trace!("Acquire {:?} : {:#?}", stages, barriers);
+                        unsafe {
+                            encoder.pipeline_barrier(
+                                stages,
+                                rendy_core::hal::memory::Dependencies::empty(),
+                                barriers,
+                            );
+                        }
+                    }
+                }
+
+                unsafe {
+                    encoder.copy_buffer(
+                        ctx.get_buffer(src.id).expect("Buffer does not exist").raw(),
+                        ctx.get_buffer(dst.id).expect("Buffer does not exist").raw(),
+                        regions.iter().cloned(),
+                    );
+                }
+
+                {
+                    let (stages, barriers) = gfx_release_barriers(ctx, &*buffers, None);
+                    if !barriers.is_empty() {
+                        log::trace!("Release {:?} : {:#?}", stages, barriers);
+                        unsafe {
+                            encoder.pipeline_barrier(
+                                stages,
+                                rendy_core::hal::memory::Dependencies::empty(),
+                                barriers,
+                            );
+                        }
+                    }
+                }
+
+                cbuf.finish()
+            })
+        });
+
+        Some(submit)
+    }
+
+    unsafe fn dispose(mut self, factory: &mut Factory<B>, _aux: &T) {
+        free_cirque(&mut self.pool, self.cirque);
+        factory.destroy_command_pool(self.pool);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{invalid_region, BufferCopy};
+
+    #[test]
+    fn regions_must_fit_buffers() {
+        let region = |src, dst, size| BufferCopy { src, dst, size };
+
+        assert_eq!(
+            invalid_region(&[region(0, 0, 64), region(64, 192, 64)], 128, 256),
+            None
+        );
+        assert_eq!(
+            invalid_region(&[region(0, 0, 64), region(96, 0, 64)], 128, 256),
+            Some(1)
+        );
+        assert_eq!(invalid_region(&[region(0, 224, 64)], 128, 256), Some(0));
+        assert_eq!(invalid_region(&[region(!0, 0, 2)], 128, 256), Some(0));
+    }
+}
+
+#[cfg(all(test, feature = "empty"))]
+mod builder_tests {
+    use {
+        super::CopyBufferNodeDesc,
+        crate::{
+            chain,
+            node::{NodeBuilder, NodeDesc},
+            BufferId,
+        },
+        rendy_core::{
+            empty::Backend,
+            hal::{
+                buffer::{Access, Usage},
+                pso::PipelineStage,
+                queue::QueueFamilyId,
+            },
+        },
+        std::collections::HashMap,
+    };
+
+    #[test]
+    fn copy_is_surrounded_by_transfer_barriers() {
+        let (src, dst) = (BufferId(0, 0), BufferId(1, 0));
+        let builder = NodeDesc::<Backend, ()>::builder(CopyBufferNodeDesc::new(src, dst));
+
+        let state = |access, stages, usage| chain::BufferState {
+            access,
+            stages,
+            layout: (),
+            usage,
+        };
+        let node = |id, dependencies, buffers: Vec<(BufferId, chain::BufferState)>| chain::Node {
+            id,
+            family: QueueFamilyId(0),
+            dependencies,
+            buffers: buffers
+                .into_iter()
+                .map(|(id, state)| (chain::Id(id.0), state))
+                .collect(),
+            images: HashMap::new(),
+        };
+
+        // Compute node fills the source, copy node relocates it and vertices are read from the copy.
+        let nodes = vec![
+            node(
+                0,
+                vec![],
+                vec![(
+                    src,
+                    state(
+                        Access::SHADER_WRITE,
+                        PipelineStage::COMPUTE_SHADER,
+                        Usage::STORAGE,
+                    ),
+                )],
+            ),
+            node(
+                1,
+                vec![0],
+                NodeBuilder::<Backend, ()>::buffers(&builder)
+                    .into_iter()
+                    .map(|(id, access)| (id, state(access.access, access.stages, access.usage)))
+                    .collect(),
+            ),
+            node(
+                2,
+                vec![1],
+                vec![(
+                    dst,
+                    state(
+                        Access::VERTEX_BUFFER_READ,
+                        PipelineStage::VERTEX_INPUT,
+                        Usage::VERTEX,
+                    ),
+                )],
+            ),
+        ];
+
+        let chains = chain::collect(nodes, |_| 1);
+        assert_eq!(
+            chains.buffers[&chain::Id(0)].usage(),
+            Usage::STORAGE | Usage::TRANSFER_SRC
+        );
+        assert_eq!(
+            chains.buffers[&chain::Id(1)].usage(),
+            Usage::TRANSFER_DST | Usage::VERTEX
+        );
+
+        let schedule = chain::sync(&chains, || ((), ()));
+        let submission = |node| {
+            schedule
+                .iter()
+                .flat_map(|family| family.iter())
+                .flat_map(|queue| queue.iter())
+                .find(|submission| submission.node() == node)
+                .unwrap()
+        };
+
+        let read = &submission(0).sync().release.buffers[&chain::Id(0)];
+        assert_eq!(read.states.start.0, Access::SHADER_WRITE);
+        assert_eq!(read.states.end.0, Access::TRANSFER_READ);
+        assert_eq!(read.states.end.2, PipelineStage::TRANSFER);
+
+        let write = &submission(1).sync().release.buffers[&chain::Id(1)];
+        assert_eq!(write.states.start.0, Access::TRANSFER_WRITE);
+        assert_eq!(write.states.start.2, PipelineStage::TRANSFER);
+        assert_eq!(write.states.end.0, Access::VERTEX_BUFFER_READ);
+    }
+}
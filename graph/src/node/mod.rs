@@ -1,17 +1,27 @@
 //! Defines node - building block for framegraph.
 //!
 
+pub mod blit;
+pub mod capture;
+pub mod closure;
+pub mod compute;
+pub mod convert;
+pub mod copy;
 pub mod present;
 pub mod render;
 
 use {
     crate::{
-        command::{Capability, Families, Family, FamilyId, Fence, Queue, Submission, Submittable},
+        command::{
+            Capability, CommandPool, Families, Family, FamilyId, Fence, IndividualReset, Queue,
+            SimultaneousUse, Submission, Submit, Submittable, Supports,
+        },
         factory::{Factory, UploadError},
-        frame::Frames,
+        frame::{cirque::CommandCirque, Frames},
         graph::GraphContext,
+        resource::{BufferCreationError, ImageCreationError},
         wsi::SwapchainError,
-        BufferId, ImageId, NodeId,
+        BufferId, ImageId, ImageViewId, NodeId,
     },
     rendy_core::hal::{queue::QueueFamilyId, Backend},
 };
@@ -32,6 +42,18 @@ pub struct BufferAccess {
     pub stages: rendy_core::hal::pso::PipelineStage,
 }
 
+impl BufferAccess {
+    /// Access to buffer with arguments of indirect draws and dispatches.
+    /// Graph inserts barrier after nodes writing the arguments.
+    pub fn indirect() -> Self {
+        BufferAccess {
+            access: rendy_core::hal::buffer::Access::INDIRECT_COMMAND_READ,
+            usage: rendy_core::hal::buffer::Usage::INDIRECT,
+            stages: rendy_core::hal::pso::PipelineStage::DRAW_INDIRECT,
+        }
+    }
+}
+
 /// Buffer pipeline barrier.
 #[derive(Clone, Debug)]
 pub struct BufferBarrier {
@@ -88,6 +110,47 @@ pub struct ImageAccess {
     pub stages: rendy_core::hal::pso::PipelineStage,
 }
 
+impl ImageAccess {
+    /// Access to storage image from shaders at `stages`.
+    /// Storage images are accessed in `General` layout.
+    pub fn storage(
+        access: rendy_core::hal::image::Access,
+        stages: rendy_core::hal::pso::PipelineStage,
+    ) -> Self {
+        ImageAccess {
+            access,
+            usage: rendy_core::hal::image::Usage::STORAGE,
+            layout: rendy_core::hal::image::Layout::General,
+            stages,
+        }
+    }
+
+    /// Access to sampled image from shaders at `stages`.
+    /// Sampled images are read in `ShaderReadOnlyOptimal` layout.
+    pub fn sampled(stages: rendy_core::hal::pso::PipelineStage) -> Self {
+        ImageAccess {
+            access: rendy_core::hal::image::Access::SHADER_READ,
+            usage: rendy_core::hal::image::Usage::SAMPLED,
+            layout: rendy_core::hal::image::Layout::ShaderReadOnlyOptimal,
+            stages,
+        }
+    }
+}
+
+/// Shader stages nodes with capability `C` can run.
+fn shader_stages<C: Capability>() -> rendy_core::hal::pso::PipelineStage {
+    use rendy_core::hal::{pso::PipelineStage, queue::QueueType};
+
+    // Any capability can be created from general queue.
+    let graphics = PipelineStage::VERTEX_SHADER | PipelineStage::FRAGMENT_SHADER;
+    match C::from_queue_type(QueueType::General).map(Capability::into_queue_type) {
+        Some(QueueType::Compute) => PipelineStage::COMPUTE_SHADER,
+        Some(QueueType::Graphics) => graphics,
+        Some(QueueType::General) => graphics | PipelineStage::COMPUTE_SHADER,
+        _ => PipelineStage::empty(),
+    }
+}
+
 /// Image pipeline barrier.
 /// Node implementation must insert it before first command that uses the image.
 /// Barrier must be inserted even if this node doesn't use the image.
@@ -110,14 +173,31 @@ pub struct NodeImage {
     pub id: ImageId,
 
     /// Region of the image that is the transient resource.
+    /// Whole image unless node declared subresource with `add_image_subresource`.
     pub range: rendy_core::hal::image::SubresourceRange,
 
+    /// View of the `range` created by the graph.
+    /// Available only if node declared subresource with `add_image_subresource`,
+    /// storage image with `add_storage_image` or sampled image with `add_sampled_image`.
+    /// View of storage image covers first level of the `range`, as required for binding.
+    /// Sampled images can be bound with `GraphContext::default_sampler`.
+    /// Use `GraphContext::get_image_view` to get the view.
+    pub view: Option<ImageViewId>,
+
     /// Image state for node.
     pub layout: rendy_core::hal::image::Layout,
 
     /// Specify that node should clear image to this value.
+    /// Set for the first node using image created with clear value,
+    /// and for any node that requested clear with `NodeBuilder::image_clears`.
+    /// Render pass nodes clear attachments when the pass begins,
+    /// other nodes must record the clear, see `clear_range`.
     pub clear: Option<rendy_core::hal::command::ClearValue>,
 
+    /// Aspects of the `range` that must be cleared with `clear`.
+    /// Empty if `clear` is `None`.
+    pub clear_aspects: rendy_core::hal::format::Aspects,
+
     /// Acquire barrier.
     /// Node implementation must insert it before first command that uses the image.
     /// Barrier must be inserted even if this node doesn't use the image.
@@ -129,12 +209,174 @@ pub struct NodeImage {
     pub release: Option<ImageBarrier>,
 }
 
+impl NodeImage {
+    /// Specify that node should clear image to this value.
+    /// Fails if `clear` doesn't match the image `format`.
+    pub fn with_clear(
+        mut self,
+        format: rendy_core::hal::format::Format,
+        clear: NodeClear,
+    ) -> Result<Self, ClearMismatch> {
+        clear.check(format)?;
+        self.clear = Some(clear.raw());
+        self.clear_aspects = clear.aspects() & self.range.aspects;
+        Ok(self)
+    }
+
+    /// Get clear value and subresource range to clear.
+    /// Nodes that don't use the image as render pass attachment
+    /// must record clear with `Encoder::clear_image` before other commands using the image.
+    /// Image must be in `General` or `TransferDstOptimal` layout for that,
+    /// node transitions it from `layout` unless declared image access uses one of them.
+    pub fn clear_range(
+        &self,
+    ) -> Option<(
+        rendy_core::hal::command::ClearValue,
+        rendy_core::hal::image::SubresourceRange,
+    )> {
+        if self.clear_aspects.is_empty() {
+            return None;
+        }
+        let clear = self.clear?;
+        Some((
+            clear,
+            rendy_core::hal::image::SubresourceRange {
+                aspects: self.clear_aspects,
+                levels: self.range.levels.clone(),
+                layers: self.range.layers.clone(),
+            },
+        ))
+    }
+}
+
+/// Clear value with explicit kind.
+/// Unlike raw `ClearValue` it can be checked against image format.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum NodeClear {
+    /// Clear color image with floating-point or normalized format.
+    Float([f32; 4]),
+
+    /// Clear color image with signed integer format.
+    Sint([i32; 4]),
+
+    /// Clear color image with unsigned integer format.
+    Uint([u32; 4]),
+
+    /// Clear depth and/or stencil image.
+    DepthStencil {
+        /// Depth value.
+        depth: f32,
+
+        /// Stencil value.
+        stencil: u32,
+    },
+
+    /// Clear only depth aspect of depth or depth-stencil image.
+    /// Stencil is preserved.
+    Depth(f32),
+
+    /// Clear only stencil aspect of stencil or depth-stencil image.
+    /// Depth is preserved.
+    Stencil(u32),
+}
+
+/// Clear value doesn't match image format.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct ClearMismatch {
+    /// Format of the image.
+    pub format: rendy_core::hal::format::Format,
+
+    /// Clear value that doesn't match the format.
+    pub clear: NodeClear,
+}
+
+impl NodeClear {
+    /// Check that clear value can be used with image of specified format.
+    pub fn check(&self, format: rendy_core::hal::format::Format) -> Result<(), ClearMismatch> {
+        use rendy_core::hal::format::{Aspects, ChannelType};
+
+        let aspects = format.surface_desc().aspects;
+        let matches = if aspects.intersects(Aspects::DEPTH | Aspects::STENCIL) {
+            match self {
+                NodeClear::DepthStencil { .. } => true,
+                NodeClear::Depth(_) => aspects.contains(Aspects::DEPTH),
+                NodeClear::Stencil(_) => aspects.contains(Aspects::STENCIL),
+                _ => false,
+            }
+        } else {
+            match (self, format.base_format().1) {
+                (NodeClear::Uint(_), ChannelType::Uint) => true,
+                (NodeClear::Sint(_), ChannelType::Sint) => true,
+                (NodeClear::Float(_), ChannelType::Uint)
+                | (NodeClear::Float(_), ChannelType::Sint) => false,
+                (NodeClear::Float(_), _) => true,
+                _ => false,
+            }
+        };
+
+        if matches {
+            Ok(())
+        } else {
+            Err(ClearMismatch {
+                format,
+                clear: *self,
+            })
+        }
+    }
+
+    /// Get raw clear value.
+    pub fn raw(&self) -> rendy_core::hal::command::ClearValue {
+        use rendy_core::hal::command::{ClearColor, ClearDepthStencil, ClearValue};
+
+        match *self {
+            NodeClear::Float(float32) => ClearValue {
+                color: ClearColor { float32 },
+            },
+            NodeClear::Sint(sint32) => ClearValue {
+                color: ClearColor { sint32 },
+            },
+            NodeClear::Uint(uint32) => ClearValue {
+                color: ClearColor { uint32 },
+            },
+            NodeClear::DepthStencil { depth, stencil } => ClearValue {
+                depth_stencil: ClearDepthStencil { depth, stencil },
+            },
+            NodeClear::Depth(depth) => ClearValue {
+                depth_stencil: ClearDepthStencil { depth, stencil: 0 },
+            },
+            NodeClear::Stencil(stencil) => ClearValue {
+                depth_stencil: ClearDepthStencil {
+                    depth: 1.0,
+                    stencil,
+                },
+            },
+        }
+    }
+
+    /// Get aspects this value clears.
+    /// Only those present in image format are actually cleared.
+    pub fn aspects(&self) -> rendy_core::hal::format::Aspects {
+        use rendy_core::hal::format::Aspects;
+
+        match self {
+            NodeClear::Float(_) | NodeClear::Sint(_) | NodeClear::Uint(_) => Aspects::COLOR,
+            NodeClear::DepthStencil { .. } => Aspects::DEPTH | Aspects::STENCIL,
+            NodeClear::Depth(_) => Aspects::DEPTH,
+            NodeClear::Stencil(_) => Aspects::STENCIL,
+        }
+    }
+}
+
 /// NodeSubmittable
 pub trait NodeSubmittable<'a, B: Backend> {
     /// Submittable type returned from `Node`.
     type Submittable: Submittable<B> + 'a;
 
     /// Iterator over submittables returned from `Node`.
+    /// Node may return any number of submittables,
+    /// e.g. one per thread that recorded commands.
+    /// All of them are submitted in a single batch in iteration order.
+    /// Nodes with single submit may use `Option` or `std::iter::Once`.
     type Submittables: IntoIterator<Item = Self::Submittable>;
 }
 
@@ -164,6 +406,23 @@ pub trait Node<B: Backend, T: ?Sized>:
         frames: &'a Frames<B>,
     ) -> <Self as NodeSubmittable<'a, B>>::Submittables;
 
+    /// Update the node after images it uses were reallocated by `Graph::resize`.
+    /// Commands that reference old images must not be submitted after this call.
+    ///
+    /// Returns `false` if node doesn't support resizing and must be rebuilt instead.
+    ///
+    /// # Safety
+    ///
+    /// Must be called after waiting for device idle.
+    unsafe fn resize(
+        &mut self,
+        _ctx: &GraphContext<B>,
+        _factory: &mut Factory<B>,
+        _aux: &T,
+    ) -> Result<bool, NodeBuildError> {
+        Ok(false)
+    }
+
     /// Dispose of the node.
     ///
     /// # Safety
@@ -194,6 +453,24 @@ pub trait NodeDesc<B: Backend, T: ?Sized>: std::fmt::Debug + Sized + 'static {
         Vec::new()
     }
 
+    /// Pick family for the node to be executed onto.
+    /// Returned family must support node's capability.
+    ///
+    /// Default implementation returns `None` to pick the first family
+    /// supporting the capability, or the most specialized one
+    /// if builder is created `with_specialized_family`.
+    fn preferred_family(&self, families: &Families<B>) -> Option<FamilyId> {
+        let _ = families;
+        None
+    }
+
+    /// Get set of optional image resources the node uses.
+    /// Optional image is provided to the node only if
+    /// one of the nodes added to the graph before this one uses it.
+    fn optional_images(&self) -> Vec<ImageAccess> {
+        Vec::new()
+    }
+
     /// Build the node.
     ///
     /// # Parameters
@@ -214,12 +491,196 @@ pub trait NodeDesc<B: Backend, T: ?Sized>: std::fmt::Debug + Sized + 'static {
         buffers: Vec<NodeBuffer>,
         images: Vec<NodeImage>,
     ) -> Result<Self::Node, NodeBuildError>;
+
+    /// Build the node with optional images.
+    /// `optional_images` has an element for each access returned by `optional_images`,
+    /// `None` is provided for images that have no producer in the graph.
+    ///
+    /// Default implementation ignores optional images and calls `build`.
+    fn build_with_optional<'a>(
+        self,
+        ctx: &GraphContext<B>,
+        factory: &mut Factory<B>,
+        family: &mut Family<B>,
+        queue: usize,
+        aux: &T,
+        buffers: Vec<NodeBuffer>,
+        images: Vec<NodeImage>,
+        optional_images: Vec<Option<NodeImage>>,
+    ) -> Result<Self::Node, NodeBuildError> {
+        let _ = optional_images;
+        self.build(ctx, factory, family, queue, aux, buffers, images)
+    }
+}
+
+/// Pre-recorded command buffers that write timestamps
+/// before and after node's submits.
+#[derive(Clone, Copy, Debug)]
+pub struct NodeTimestamps<'a, B: Backend> {
+    /// Submitted before node's submits.
+    pub begin: &'a Submit<B, SimultaneousUse>,
+
+    /// Submitted after node's submits.
+    pub end: &'a Submit<B, SimultaneousUse>,
+}
+
+/// Submittable with erased type.
+/// Allows to submit node's submittables along with timestamps buffers.
+#[derive(Debug)]
+struct RawSubmit<'a, B: Backend> {
+    raw: &'a B::CommandBuffer,
+    family: FamilyId,
+}
+
+impl<'a, B> RawSubmit<'a, B>
+where
+    B: Backend,
+{
+    unsafe fn new(submittable: impl Submittable<B>) -> Self {
+        RawSubmit {
+            family: submittable.family(),
+            raw: submittable.raw(),
+        }
+    }
+}
+
+unsafe impl<'a, B> Submittable<B> for RawSubmit<'a, B>
+where
+    B: Backend,
+{
+    fn family(&self) -> FamilyId {
+        self.family
+    }
+
+    unsafe fn raw<'b>(self) -> &'b B::CommandBuffer {
+        &*(self.raw as *const _)
+    }
+}
+
+/// Surround node's submits with timestamps if provided.
+///
+/// # Safety
+///
+/// Submits must stay alive until returned values are submitted.
+unsafe fn timed_submits<'a, B, S>(
+    submits: impl IntoIterator<Item = S>,
+    timestamps: Option<NodeTimestamps<'a, B>>,
+) -> smallvec::SmallVec<[RawSubmit<'a, B>; 8]>
+where
+    B: Backend,
+    S: Submittable<B>,
+{
+    let (begin, end) = match timestamps {
+        Some(NodeTimestamps { begin, end }) => (Some(begin), Some(end)),
+        None => (None, None),
+    };
+
+    begin
+        .map(|begin| RawSubmit::new(begin))
+        .into_iter()
+        .chain(submits.into_iter().map(|s| RawSubmit::new(s)))
+        .chain(end.map(|end| RawSubmit::new(end)))
+        .collect()
+}
+
+/// Outcome of presenting to surfaces in a frame.
+/// Results of multiple nodes are combined by taking the maximum.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum PresentResult {
+    /// Image was presented or node doesn't present.
+    Ok,
+    /// Image was presented, but swapchain no longer matches the surface exactly.
+    /// Presentation continues to work.
+    Suboptimal,
+    /// Image wasn't presented because swapchain is out of date or presentation failed.
+    /// `PresentNode` recreates swapchain in the next frame,
+    /// but extent of the surface may have changed and graph may need `Graph::resize`.
+    NeedsRecreation,
+    /// Device is lost, nothing was submitted.
+    /// Graph and factory should be disposed and created again.
+    /// See `Factory::is_device_lost`.
+    DeviceLost,
+}
+
+/// Combine suboptimal flag of acquired image with result of its presentation.
+pub(crate) fn present_result(
+    acquired_suboptimal: bool,
+    result: Result<
+        Option<rendy_core::hal::window::Suboptimal>,
+        rendy_core::hal::window::PresentError,
+    >,
+) -> PresentResult {
+    match result {
+        Ok(None) if !acquired_suboptimal => PresentResult::Ok,
+        Ok(_) => PresentResult::Suboptimal,
+        Err(rendy_core::hal::window::PresentError::DeviceLost(_)) => PresentResult::DeviceLost,
+        Err(err) => {
+            log::debug!("Swapchain presentation error: {:?}", err);
+            PresentResult::NeedsRecreation
+        }
+    }
+}
+
+/// Check that swapchain with `images` can serve `frames` in flight.
+/// Each frame in flight holds an image until its presentation is done,
+/// so more frames than images would only stall on acquire.
+pub(crate) fn check_frames_in_flight(frames: u32, images: u32) -> Result<(), NodeBuildError> {
+    if frames > images {
+        log::warn!(
+            "{} frames in flight exceed {} swapchain images",
+            frames,
+            images
+        );
+        Err(NodeBuildError::FramesInFlight { frames, images })
+    } else {
+        Ok(())
+    }
+}
+
+/// Clamp `preferred` image count into range `supported` by the surface.
+/// Zero maximum means the surface doesn't limit image count.
+pub(crate) fn clamp_image_count(preferred: u32, supported: &std::ops::RangeInclusive<u32>) -> u32 {
+    let max = match *supported.end() {
+        0 => std::u32::MAX,
+        max => max,
+    };
+    preferred.min(max).max(*supported.start())
+}
+
+/// Image count preferred for swapchain when not requested explicitly.
+/// One image more than surface requires, but no less than `frames_in_flight`.
+pub(crate) fn default_image_count(min: u32, frames_in_flight: u32) -> u32 {
+    (min + 1).max(frames_in_flight)
+}
+
+/// Free command buffers of the cirque.
+/// Must be called when none of them is executed by device.
+pub(crate) unsafe fn free_cirque<B: Backend, C>(
+    pool: &mut CommandPool<B, C, IndividualReset>,
+    cirque: CommandCirque<B, C>,
+) {
+    cirque.dispose(|buffer| {
+        buffer.either_with(
+            &mut *pool,
+            |pool, executable| pool.free_buffers(Some(executable)),
+            |pool, pending| {
+                let executable = pending.mark_complete();
+                pool.free_buffers(Some(executable))
+            },
+        );
+    });
 }
 
 /// Trait-object safe `Node`.
 pub trait DynNode<B: Backend, T: ?Sized>: std::fmt::Debug + Sync + Send {
     /// Record commands required by node.
     /// Recorded buffers go into `submits`.
+    /// If `timestamps` are provided they are submitted around node's submits.
+    ///
+    /// Nodes that present to a surface report outcome of the presentation,
+    /// others return `PresentResult::Ok`.
+    /// Node must wait for `waits`, signal `signals` and `fence`
+    /// even if it fails to present.
     unsafe fn run<'a>(
         &mut self,
         ctx: &GraphContext<B>,
@@ -230,7 +691,29 @@ pub trait DynNode<B: Backend, T: ?Sized>: std::fmt::Debug + Sync + Send {
         waits: &[(&'a B::Semaphore, rendy_core::hal::pso::PipelineStage)],
         signals: &[&'a B::Semaphore],
         fence: Option<&mut Fence<B>>,
-    );
+        timestamps: Option<NodeTimestamps<'a, B>>,
+    ) -> PresentResult;
+
+    /// Check if node acquires swapchain image in `run`.
+    /// Such nodes are run last with `LatencyMode::LowLatency`.
+    fn acquires_image(&self) -> bool {
+        false
+    }
+
+    /// Update the node after images it uses were reallocated by `Graph::resize`.
+    /// Returns `false` if node doesn't support resizing and must be rebuilt instead.
+    ///
+    /// # Safety
+    ///
+    /// Must be called after waiting for device idle.
+    unsafe fn resize(
+        &mut self,
+        _ctx: &GraphContext<B>,
+        _factory: &mut Factory<B>,
+        _aux: &T,
+    ) -> Result<bool, NodeBuildError> {
+        Ok(false)
+    }
 
     /// Dispose of the node.
     ///
@@ -256,17 +739,28 @@ where
         waits: &[(&'a B::Semaphore, rendy_core::hal::pso::PipelineStage)],
         signals: &[&'a B::Semaphore],
         fence: Option<&mut Fence<B>>,
-    ) {
+        timestamps: Option<NodeTimestamps<'a, B>>,
+    ) -> PresentResult {
         let submittables = Node::run(&mut self.0, ctx, factory, aux, frames);
         queue.submit(
             Some(
                 Submission::new()
-                    .submits(submittables)
+                    .submits(timed_submits(submittables, timestamps))
                     .wait(waits.iter().cloned())
                     .signal(signals.iter().cloned()),
             ),
             fence,
-        )
+        );
+        PresentResult::Ok
+    }
+
+    unsafe fn resize(
+        &mut self,
+        ctx: &GraphContext<B>,
+        factory: &mut Factory<B>,
+        aux: &T,
+    ) -> Result<bool, NodeBuildError> {
+        Node::resize(&mut self.0, ctx, factory, aux)
     }
 
     unsafe fn dispose(self: Box<Self>, factory: &mut Factory<B>, aux: &T) {
@@ -281,6 +775,10 @@ pub enum NodeBuildError {
     Upload(UploadError),
     /// Mismatched queue family.
     QueueFamily(FamilyId),
+    /// Failed to create a buffer.
+    Buffer(BufferCreationError),
+    /// Failed to create an image.
+    Image(ImageCreationError),
     /// Failed to create an imate view.
     View(rendy_core::hal::image::ViewError),
     /// Failed to create a pipeline.
@@ -289,6 +787,46 @@ pub enum NodeBuildError {
     Swapchain(SwapchainError),
     /// Ran out of memory when creating something.
     OutOfMemory(rendy_core::hal::device::OutOfMemory),
+    /// Requested number of framebuffer layers is unsupported by device or attachments.
+    Layers(u16),
+    /// Subpass dependency at this index references subpass that doesn't exist.
+    SubpassDependency(usize),
+    /// Clear value requested by the node doesn't match the image format.
+    Clear(ClearMismatch),
+    /// Number of frames in flight exceeds number of swapchain images.
+    FramesInFlight {
+        /// Frames in flight of the graph.
+        frames: u32,
+        /// Swapchain images.
+        images: u32,
+    },
+    /// Image of format `src` can be neither copied nor blitted into image of format `dst`.
+    Conversion {
+        /// Format of the source image.
+        src: rendy_core::hal::format::Format,
+        /// Format of the destination image.
+        dst: rendy_core::hal::format::Format,
+    },
+    /// Buffer copy region at this index exceeds source or destination buffer.
+    CopyRegion(usize),
+    /// Node has storage or sampled images but its capability can't run shaders.
+    ShaderImages,
+    /// Source and destination images of a copy have different number of layers.
+    LayerCount {
+        /// Layers of the source image.
+        src: rendy_core::hal::image::Layer,
+        /// Layers of the destination image.
+        dst: rendy_core::hal::image::Layer,
+    },
+    /// Shader set loaded by the node lacks shader of required stage.
+    MissingShader(rendy_core::hal::pso::ShaderStageFlags),
+    /// Attachment of multisampled render pass has different number of samples.
+    Samples {
+        /// Samples of the render pass.
+        expected: rendy_core::hal::image::NumSamples,
+        /// Samples of the attachment.
+        found: rendy_core::hal::image::NumSamples,
+    },
 }
 
 /// Dynamic node builder that emits `DynNode`.
@@ -302,6 +840,48 @@ pub trait NodeBuilder<B: Backend, T: ?Sized>: std::fmt::Debug {
     /// Get images accessed by the node.
     fn images(&self) -> Vec<(ImageId, ImageAccess)>;
 
+    /// Get images optionally accessed by the node.
+    /// Those are provided to the node only if produced by preceding nodes.
+    fn optional_images(&self) -> Vec<(ImageId, ImageAccess)> {
+        Vec::new()
+    }
+
+    /// Subresources of images accessed by the node.
+    /// Images not listed here are accessed whole.
+    fn image_subresources(&self) -> Vec<(ImageId, rendy_core::hal::image::SubresourceRange)> {
+        Vec::new()
+    }
+
+    /// Images accessed by the node as storage images.
+    /// Those are included in `images` as well,
+    /// graph creates views suitable for storage binding for them.
+    fn storage_images(&self) -> Vec<ImageId> {
+        Vec::new()
+    }
+
+    /// Images sampled by the node.
+    /// Those are included in `images` as well,
+    /// graph creates views of whole images and default sampler for them.
+    fn sampled_images(&self) -> Vec<ImageId> {
+        Vec::new()
+    }
+
+    /// Images the node must clear before use.
+    /// Unlike clear value images are created with,
+    /// which is passed only to the first node accessing the image,
+    /// these are passed to this node regardless of preceding accesses.
+    /// Node records the clear itself, see `NodeImage::clear_range`.
+    fn image_clears(&self) -> Vec<(ImageId, NodeClear)> {
+        Vec::new()
+    }
+
+    /// Images which content the node leaves undefined after use,
+    /// e.g. attachments stored with `AttachmentStoreOp::DontCare`.
+    /// Graph warns if later nodes use those images.
+    fn discarded_images(&self) -> Vec<ImageId> {
+        Vec::new()
+    }
+
     /// Indices of nodes this one dependes on.
     fn dependencies(&self) -> Vec<NodeId>;
 
@@ -323,7 +903,13 @@ pub struct DescBuilder<B: Backend, T: ?Sized, N> {
     desc: N,
     buffers: Vec<BufferId>,
     images: Vec<ImageId>,
+    image_subresources: Vec<(ImageId, rendy_core::hal::image::SubresourceRange)>,
+    image_clears: Vec<(ImageId, NodeClear)>,
+    storage_images: Vec<(ImageId, rendy_core::hal::image::Access)>,
+    sampled_images: Vec<ImageId>,
+    optional_images: Vec<ImageId>,
     dependencies: Vec<NodeId>,
+    specialized_family: bool,
     marker: std::marker::PhantomData<fn(B, &T)>,
 }
 
@@ -338,7 +924,13 @@ where
             .field("desc", &self.desc)
             .field("buffers", &self.buffers)
             .field("images", &self.images)
+            .field("image_subresources", &self.image_subresources)
+            .field("image_clears", &self.image_clears)
+            .field("storage_images", &self.storage_images)
+            .field("sampled_images", &self.sampled_images)
+            .field("optional_images", &self.optional_images)
             .field("dependencies", &self.dependencies)
+            .field("specialized_family", &self.specialized_family)
             .finish()
     }
 }
@@ -354,10 +946,33 @@ where
             desc,
             buffers: Vec::new(),
             images: Vec::new(),
+            image_subresources: Vec::new(),
+            image_clears: Vec::new(),
+            storage_images: Vec::new(),
+            sampled_images: Vec::new(),
+            optional_images: Vec::new(),
             dependencies: Vec::new(),
+            specialized_family: false,
             marker: std::marker::PhantomData,
         }
     }
+
+    /// Run the node on the most specialized family supporting its capability
+    /// instead of the first one, e.g. on dedicated transfer family for transfer nodes,
+    /// which lets it overlap with work on general queues.
+    /// `NodeDesc::preferred_family` takes precedence.
+    pub fn set_specialized_family(&mut self) -> &mut Self {
+        self.specialized_family = true;
+        self
+    }
+
+    /// Run the node on the most specialized family supporting its capability.
+    /// See `set_specialized_family`.
+    pub fn with_specialized_family(mut self) -> Self {
+        self.set_specialized_family();
+        self
+    }
+
     /// Add buffer to the node.
     /// This method must be called for each buffer node uses.
     pub fn add_buffer(&mut self, buffer: BufferId) -> &mut Self {
@@ -386,6 +1001,133 @@ where
         self
     }
 
+    /// Add subresource of the image to the node.
+    /// Same as `add_image` but node accesses only `range` of the image.
+    /// Graph creates view of the subresource and synchronizes only the `range`,
+    /// so nodes can access distinct levels or layers of the same image,
+    /// e.g. render into separate faces of a cubemap.
+    pub fn add_image_subresource(
+        &mut self,
+        image: ImageId,
+        range: rendy_core::hal::image::SubresourceRange,
+    ) -> &mut Self {
+        self.images.push(image);
+        self.image_subresources.push((image, range));
+        self
+    }
+
+    /// Add subresource of the image to the node.
+    /// Same as `with_image` but node accesses only `range` of the image.
+    /// Graph creates view of the subresource and synchronizes only the `range`,
+    /// so nodes can access distinct levels or layers of the same image,
+    /// e.g. render into separate faces of a cubemap.
+    pub fn with_image_subresource(
+        mut self,
+        image: ImageId,
+        range: rendy_core::hal::image::SubresourceRange,
+    ) -> Self {
+        self.add_image_subresource(image, range);
+        self
+    }
+
+    /// Request the node to clear image before use.
+    /// Image must be added to the node as well.
+    /// Clear is requested even if preceding nodes accessed the image,
+    /// `NodeClear::Depth` and `NodeClear::Stencil` clear single aspect
+    /// of depth-stencil image and preserve the other.
+    ///
+    /// Graph doesn't record the clear for the node.
+    /// Clear is passed in `NodeImage::clear` and node desc must record it,
+    /// see `NodeImage::clear_range`.
+    /// Use `RenderPassNodeBuilder::add_image_clear` to clear attachments of render pass.
+    pub fn add_image_clear(&mut self, image: ImageId, clear: NodeClear) -> &mut Self {
+        self.image_clears.push((image, clear));
+        self
+    }
+
+    /// Request the node to clear image before use.
+    /// See `add_image_clear`.
+    pub fn with_image_clear(mut self, image: ImageId, clear: NodeClear) -> Self {
+        self.add_image_clear(image, clear);
+        self
+    }
+
+    /// Add storage image to the node.
+    /// Unlike `add_image` access isn't declared by the node desc,
+    /// the image is accessed with `access` by shader stages the node can run,
+    /// in `General` layout.
+    /// Graph creates view of the first level of the image for storage binding,
+    /// see `NodeImage::view`.
+    /// Only depth aspect of depth-stencil images is viewed.
+    /// Node fails to build with `NodeBuildError::ShaderImages` if it can't run shaders.
+    pub fn add_storage_image(
+        &mut self,
+        image: ImageId,
+        access: rendy_core::hal::image::Access,
+    ) -> &mut Self {
+        self.storage_images.push((image, access));
+        self
+    }
+
+    /// Add storage image to the node.
+    /// Unlike `with_image` access isn't declared by the node desc,
+    /// the image is accessed with `access` by shader stages the node can run,
+    /// in `General` layout.
+    /// Graph creates view of the first level of the image for storage binding,
+    /// see `NodeImage::view`.
+    /// Only depth aspect of depth-stencil images is viewed.
+    /// Node fails to build with `NodeBuildError::ShaderImages` if it can't run shaders.
+    pub fn with_storage_image(
+        mut self,
+        image: ImageId,
+        access: rendy_core::hal::image::Access,
+    ) -> Self {
+        self.add_storage_image(image, access);
+        self
+    }
+
+    /// Add sampled image to the node.
+    /// Unlike `add_image` access isn't declared by the node desc,
+    /// the image is read by shader stages the node can run
+    /// in `ShaderReadOnlyOptimal` layout.
+    /// Graph creates view of the whole image, see `NodeImage::view`,
+    /// and the node can sample it with `GraphContext::default_sampler`.
+    /// Only depth aspect of depth-stencil images is viewed.
+    /// Node fails to build with `NodeBuildError::ShaderImages` if it can't run shaders.
+    pub fn add_sampled_image(&mut self, image: ImageId) -> &mut Self {
+        self.sampled_images.push(image);
+        self
+    }
+
+    /// Add sampled image to the node.
+    /// Unlike `with_image` access isn't declared by the node desc,
+    /// the image is read by shader stages the node can run
+    /// in `ShaderReadOnlyOptimal` layout.
+    /// Graph creates view of the whole image, see `NodeImage::view`,
+    /// and the node can sample it with `GraphContext::default_sampler`.
+    /// Only depth aspect of depth-stencil images is viewed.
+    /// Node fails to build with `NodeBuildError::ShaderImages` if it can't run shaders.
+    pub fn with_sampled_image(mut self, image: ImageId) -> Self {
+        self.add_sampled_image(image);
+        self
+    }
+
+    /// Add optional image to the node.
+    /// This method must be called for each optional image node uses.
+    /// Image is provided to the node only if one of the nodes added before uses it.
+    pub fn add_optional_image(&mut self, image: ImageId) -> &mut Self {
+        self.optional_images.push(image);
+        self
+    }
+
+    /// Add optional image to the node.
+    /// This method must be called for each optional image node uses.
+    /// Image is provided to the node only if one of the nodes added before uses it.
+    pub fn with_optional_image(mut self, image: ImageId) -> Self {
+        self.add_optional_image(image);
+        self
+    }
+
     /// Add dependency.
     /// Node will be placed after its dependencies.
     pub fn add_dependency(&mut self, dependency: NodeId) -> &mut Self {
@@ -408,7 +1150,22 @@ where
     N: NodeDesc<B, T>,
 {
     fn family(&self, _factory: &mut Factory<B>, families: &Families<B>) -> Option<FamilyId> {
-        families.with_capability::<<N::Node as Node<B, T>>::Capability>()
+        match self.desc.preferred_family(families) {
+            Some(family) => {
+                assert!(
+                    Supports::<<N::Node as Node<B, T>>::Capability>::supports(
+                        &families.family(family).capability()
+                    )
+                    .is_some(),
+                    "Preferred family doesn't support node's capability"
+                );
+                Some(family)
+            }
+            None if self.specialized_family => {
+                families.specialized_with_capability::<<N::Node as Node<B, T>>::Capability>()
+            }
+            None => families.with_capability::<<N::Node as Node<B, T>>::Capability>(),
+        }
     }
 
     fn buffers(&self) -> Vec<(BufferId, BufferAccess)> {
@@ -422,7 +1179,49 @@ where
         let desc_images = self.desc.images();
         assert_eq!(self.images.len(), desc_images.len());
 
-        self.images.iter().cloned().zip(desc_images).collect()
+        let stages = shader_stages::<<N::Node as Node<B, T>>::Capability>();
+        self.images
+            .iter()
+            .cloned()
+            .zip(desc_images)
+            .chain(
+                self.storage_images
+                    .iter()
+                    .map(|&(id, access)| (id, ImageAccess::storage(access, stages))),
+            )
+            .chain(
+                self.sampled_images
+                    .iter()
+                    .map(|&id| (id, ImageAccess::sampled(stages))),
+            )
+            .collect()
+    }
+
+    fn optional_images(&self) -> Vec<(ImageId, ImageAccess)> {
+        let desc_images = self.desc.optional_images();
+        assert_eq!(self.optional_images.len(), desc_images.len());
+
+        self.optional_images
+            .iter()
+            .cloned()
+            .zip(desc_images)
+            .collect()
+    }
+
+    fn image_subresources(&self) -> Vec<(ImageId, rendy_core::hal::image::SubresourceRange)> {
+        self.image_subresources.clone()
+    }
+
+    fn storage_images(&self) -> Vec<ImageId> {
+        self.storage_images.iter().map(|&(id, _)| id).collect()
+    }
+
+    fn sampled_images(&self) -> Vec<ImageId> {
+        self.sampled_images.clone()
+    }
+
+    fn image_clears(&self) -> Vec<(ImageId, NodeClear)> {
+        self.image_clears.clone()
     }
 
     fn dependencies(&self) -> Vec<NodeId> {
@@ -439,8 +1238,44 @@ where
         buffers: Vec<NodeBuffer>,
         images: Vec<NodeImage>,
     ) -> Result<Box<dyn DynNode<B, T>>, NodeBuildError> {
-        Ok(Box::new((self.desc.build(
-            ctx, factory, family, queue, aux, buffers, images,
+        let DescBuilder {
+            desc,
+            images: image_ids,
+            storage_images,
+            sampled_images,
+            optional_images: optional_ids,
+            ..
+        } = *self;
+
+        if (!storage_images.is_empty() || !sampled_images.is_empty())
+            && shader_stages::<<N::Node as Node<B, T>>::Capability>().is_empty()
+        {
+            return Err(NodeBuildError::ShaderImages);
+        }
+
+        let optional_images = optional_ids
+            .iter()
+            .map(|&id| images.iter().find(|image| image.id == id).cloned())
+            .collect();
+
+        let images = images
+            .into_iter()
+            .filter(|image| {
+                image_ids.contains(&image.id)
+                    || storage_images.iter().any(|&(id, _)| id == image.id)
+                    || sampled_images.contains(&image.id)
+            })
+            .collect();
+
+        Ok(Box::new((desc.build_with_optional(
+            ctx,
+            factory,
+            family,
+            queue,
+            aux,
+            buffers,
+            images,
+            optional_images,
         )?,)))
     }
 }
@@ -454,46 +1289,13 @@ pub fn gfx_acquire_barriers<'a, 'b, B: Backend>(
     std::ops::Range<rendy_core::hal::pso::PipelineStage>,
     Vec<rendy_core::hal::memory::Barrier<'a, B>>,
 ) {
-    let mut bstart = rendy_core::hal::pso::PipelineStage::empty();
-    let mut bend = rendy_core::hal::pso::PipelineStage::empty();
-
-    let mut istart = rendy_core::hal::pso::PipelineStage::empty();
-    let mut iend = rendy_core::hal::pso::PipelineStage::empty();
-
-    let barriers: Vec<rendy_core::hal::memory::Barrier<'_, B>> = buffers
-        .into_iter()
-        .filter_map(|buffer| {
-            buffer.acquire.as_ref().map(|acquire| {
-                bstart |= acquire.stages.start;
-                bend |= acquire.stages.end;
-
-                rendy_core::hal::memory::Barrier::Buffer {
-                    states: acquire.states.clone(),
-                    families: acquire.families.clone(),
-                    target: ctx
-                        .get_buffer(buffer.id)
-                        .expect("Buffer does not exist")
-                        .raw(),
-                    range: Some(buffer.range.start)..Some(buffer.range.end),
-                }
-            })
-        })
-        .chain(images.into_iter().filter_map(|image| {
-            image.acquire.as_ref().map(|acquire| {
-                istart |= acquire.stages.start;
-                iend |= acquire.stages.end;
-
-                rendy_core::hal::memory::Barrier::Image {
-                    states: acquire.states.clone(),
-                    families: acquire.families.clone(),
-                    target: ctx.get_image(image.id).expect("Image does not exist").raw(),
-                    range: image.range.clone(),
-                }
-            })
-        }))
-        .collect();
-
-    (bstart | istart..bend | iend, barriers)
+    let (stages, buffers, images) = batch_barriers(
+        buffers,
+        images,
+        |buffer| buffer.acquire.as_ref(),
+        |image| image.acquire.as_ref(),
+    );
+    (stages, gfx_barriers(ctx, buffers, images))
 }
 
 /// Convert graph barriers into gfx barriers.
@@ -505,44 +1307,296 @@ pub fn gfx_release_barriers<'a, B: Backend>(
     std::ops::Range<rendy_core::hal::pso::PipelineStage>,
     Vec<rendy_core::hal::memory::Barrier<'a, B>>,
 ) {
-    let mut bstart = rendy_core::hal::pso::PipelineStage::empty();
-    let mut bend = rendy_core::hal::pso::PipelineStage::empty();
+    let (stages, buffers, images) = batch_barriers(
+        buffers,
+        images,
+        |buffer| buffer.release.as_ref(),
+        |image| image.release.as_ref(),
+    );
+    (stages, gfx_barriers(ctx, buffers, images))
+}
 
-    let mut istart = rendy_core::hal::pso::PipelineStage::empty();
-    let mut iend = rendy_core::hal::pso::PipelineStage::empty();
+/// Barriers of the resources batched into single pipeline barrier.
+/// Resources transitioning between the same stages don't need separate barrier commands,
+/// stages of the batch cover stages of all barriers.
+/// Each resource keeps its own barrier with its range.
+fn batch_barriers<'b>(
+    buffers: impl IntoIterator<Item = &'b NodeBuffer>,
+    images: impl IntoIterator<Item = &'b NodeImage>,
+    buffer_barrier: impl Fn(&'b NodeBuffer) -> Option<&'b BufferBarrier>,
+    image_barrier: impl Fn(&'b NodeImage) -> Option<&'b ImageBarrier>,
+) -> (
+    std::ops::Range<rendy_core::hal::pso::PipelineStage>,
+    Vec<(&'b NodeBuffer, &'b BufferBarrier)>,
+    Vec<(&'b NodeImage, &'b ImageBarrier)>,
+) {
+    let mut start = rendy_core::hal::pso::PipelineStage::empty();
+    let mut end = rendy_core::hal::pso::PipelineStage::empty();
 
-    let barriers: Vec<rendy_core::hal::memory::Barrier<'_, B>> = buffers
+    let buffers: Vec<_> = buffers
         .into_iter()
-        .filter_map(|buffer| {
-            buffer.release.as_ref().map(|release| {
-                bstart |= release.stages.start;
-                bend |= release.stages.end;
-
-                rendy_core::hal::memory::Barrier::Buffer {
-                    states: release.states.clone(),
-                    families: release.families.clone(),
-                    target: ctx
-                        .get_buffer(buffer.id)
-                        .expect("Buffer does not exist")
-                        .raw(),
-                    range: Some(buffer.range.start)..Some(buffer.range.end),
-                }
-            })
+        .filter_map(|buffer| buffer_barrier(buffer).map(|barrier| (buffer, barrier)))
+        .inspect(|(_, barrier)| {
+            start |= barrier.stages.start;
+            end |= barrier.stages.end;
         })
-        .chain(images.into_iter().filter_map(|image| {
-            image.release.as_ref().map(|release| {
-                istart |= release.stages.start;
-                iend |= release.stages.end;
-
-                rendy_core::hal::memory::Barrier::Image {
-                    states: release.states.clone(),
-                    families: release.families.clone(),
+        .collect();
+
+    let images: Vec<_> = images
+        .into_iter()
+        .filter_map(|image| image_barrier(image).map(|barrier| (image, barrier)))
+        .inspect(|(_, barrier)| {
+            start |= barrier.stages.start;
+            end |= barrier.stages.end;
+        })
+        .collect();
+
+    (start..end, buffers, images)
+}
+
+fn gfx_barriers<'a, 'b, B: Backend>(
+    ctx: &'a GraphContext<B>,
+    buffers: Vec<(&'b NodeBuffer, &'b BufferBarrier)>,
+    images: Vec<(&'b NodeImage, &'b ImageBarrier)>,
+) -> Vec<rendy_core::hal::memory::Barrier<'a, B>> {
+    buffers
+        .into_iter()
+        .map(
+            |(buffer, barrier)| rendy_core::hal::memory::Barrier::Buffer {
+                states: barrier.states.clone(),
+                families: barrier.families.clone(),
+                target: ctx
+                    .get_buffer(buffer.id)
+                    .expect("Buffer does not exist")
+                    .raw(),
+                range: Some(buffer.range.start)..Some(buffer.range.end),
+            },
+        )
+        .chain(
+            images
+                .into_iter()
+                .map(|(image, barrier)| rendy_core::hal::memory::Barrier::Image {
+                    states: barrier.states.clone(),
+                    families: barrier.families.clone(),
                     target: ctx.get_image(image.id).expect("Image does not exist").raw(),
                     range: image.range.clone(),
-                }
+                }),
+        )
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use {
+        super::{
+            batch_barriers, check_frames_in_flight, clamp_image_count, default_image_count,
+            present_result, ImageBarrier, NodeBuildError, NodeClear, NodeImage, PresentResult,
+        },
+        crate::ImageId,
+        rendy_core::hal::format::{Aspects, Format},
+    };
+
+    const DEPTH_STENCIL: NodeClear = NodeClear::DepthStencil {
+        depth: 1.0,
+        stencil: 0,
+    };
+
+    #[test]
+    fn depth_clear() {
+        assert!(DEPTH_STENCIL.check(Format::D32Sfloat).is_ok());
+        assert!(NodeClear::Float([0.0; 4]).check(Format::D32Sfloat).is_err());
+        assert!(NodeClear::Uint([0; 4]).check(Format::D16Unorm).is_err());
+    }
+
+    #[test]
+    fn stencil_clear() {
+        assert!(DEPTH_STENCIL.check(Format::S8Uint).is_ok());
+        assert!(DEPTH_STENCIL.check(Format::D24UnormS8Uint).is_ok());
+        assert!(NodeClear::Uint([0; 4]).check(Format::S8Uint).is_err());
+    }
+
+    #[test]
+    fn depth_only_clear() {
+        let clear = NodeClear::Depth(0.0);
+        assert!(clear.check(Format::D32Sfloat).is_ok());
+        assert!(clear.check(Format::D24UnormS8Uint).is_ok());
+        assert!(clear.check(Format::S8Uint).is_err());
+        assert!(clear.check(Format::Rgba8Unorm).is_err());
+        assert_eq!(clear.aspects(), Aspects::DEPTH);
+        assert_eq!(unsafe { clear.raw().depth_stencil.depth }, 0.0);
+    }
+
+    #[test]
+    fn stencil_only_clear() {
+        let clear = NodeClear::Stencil(0xFF);
+        assert!(clear.check(Format::S8Uint).is_ok());
+        assert!(clear.check(Format::D32SfloatS8Uint).is_ok());
+        assert!(clear.check(Format::D32Sfloat).is_err());
+        assert_eq!(clear.aspects(), Aspects::STENCIL);
+        assert_eq!(unsafe { clear.raw().depth_stencil.stencil }, 0xFF);
+
+        // Combined clear of depth-only image clears depth aspect only.
+        assert_eq!(
+            DEPTH_STENCIL.aspects() & Format::D32Sfloat.surface_desc().aspects,
+            Aspects::DEPTH
+        );
+    }
+
+    #[test]
+    fn uint_clear() {
+        assert!(NodeClear::Uint([0; 4]).check(Format::Rgba8Uint).is_ok());
+        assert!(NodeClear::Float([0.0; 4]).check(Format::Rgba8Uint).is_err());
+        assert!(NodeClear::Sint([0; 4]).check(Format::R32Uint).is_err());
+        assert!(DEPTH_STENCIL.check(Format::R32Uint).is_err());
+    }
+
+    #[test]
+    fn sint_clear() {
+        assert!(NodeClear::Sint([0; 4]).check(Format::Rgba16Sint).is_ok());
+        assert!(NodeClear::Float([0.0; 4])
+            .check(Format::Rgba16Sint)
+            .is_err());
+        assert!(NodeClear::Uint([0; 4]).check(Format::R32Sint).is_err());
+    }
+
+    #[test]
+    fn float_clear() {
+        assert!(NodeClear::Float([0.0; 4]).check(Format::Rgba8Unorm).is_ok());
+        assert!(NodeClear::Float([0.0; 4]).check(Format::Bgra8Srgb).is_ok());
+        assert!(NodeClear::Float([0.0; 4])
+            .check(Format::Rgba32Sfloat)
+            .is_ok());
+        assert!(NodeClear::Uint([0; 4]).check(Format::Rgba8Unorm).is_err());
+    }
+
+    #[test]
+    fn storage_images_are_accessed_by_node_shaders() {
+        use {
+            super::shader_stages,
+            crate::command::{Compute, General, Graphics, Transfer},
+            rendy_core::hal::pso::PipelineStage,
+        };
+
+        assert_eq!(shader_stages::<Compute>(), PipelineStage::COMPUTE_SHADER);
+        assert_eq!(
+            shader_stages::<Graphics>(),
+            PipelineStage::VERTEX_SHADER | PipelineStage::FRAGMENT_SHADER
+        );
+        assert!(shader_stages::<General>().contains(PipelineStage::COMPUTE_SHADER));
+        assert!(shader_stages::<Transfer>().is_empty());
+    }
+
+    #[test]
+    fn image_count_is_clamped_to_surface() {
+        assert_eq!(clamp_image_count(4, &(2..=3)), 3);
+        assert_eq!(clamp_image_count(1, &(2..=3)), 2);
+        assert_eq!(clamp_image_count(3, &(2..=3)), 3);
+
+        // Surface without upper limit.
+        assert_eq!(clamp_image_count(4, &(2..=0)), 4);
+        assert_eq!(clamp_image_count(1, &(2..=0)), 2);
+    }
+
+    #[test]
+    fn default_image_count_serves_frames_in_flight() {
+        // Surface requiring single image would get double buffering.
+        let count = clamp_image_count(default_image_count(1, 3), &(1..=0));
+        assert_eq!(count, 3);
+        assert!(check_frames_in_flight(3, count).is_ok());
+
+        assert_eq!(default_image_count(3, 2), 4);
+        assert_eq!(clamp_image_count(default_image_count(2, 3), &(2..=3)), 3);
+        assert_eq!(clamp_image_count(default_image_count(1, 4), &(1..=3)), 3);
+    }
+
+    #[test]
+    fn frames_in_flight_fit_swapchain() {
+        assert!(check_frames_in_flight(2, 3).is_ok());
+        assert!(check_frames_in_flight(3, 3).is_ok());
+        match check_frames_in_flight(3, 2) {
+            Err(NodeBuildError::FramesInFlight {
+                frames: 3,
+                images: 2,
+            }) => {}
+            other => panic!("Unexpected result {:?}", other),
+        }
+    }
+
+    #[test]
+    fn worst_present_result_wins() {
+        use rendy_core::hal::{
+            device::DeviceLost,
+            window::{PresentError, Suboptimal},
+        };
+
+        assert_eq!(present_result(false, Ok(None)), PresentResult::Ok);
+        assert_eq!(present_result(true, Ok(None)), PresentResult::Suboptimal);
+        assert_eq!(
+            present_result(false, Ok(Some(Suboptimal))),
+            PresentResult::Suboptimal
+        );
+        assert_eq!(
+            present_result(true, Err(PresentError::OutOfDate)),
+            PresentResult::NeedsRecreation
+        );
+        assert_eq!(
+            PresentResult::Suboptimal.max(PresentResult::NeedsRecreation),
+            PresentResult::NeedsRecreation
+        );
+        assert_eq!(
+            present_result(false, Err(PresentError::DeviceLost(DeviceLost))),
+            PresentResult::DeviceLost
+        );
+        assert_eq!(
+            PresentResult::NeedsRecreation.max(PresentResult::DeviceLost),
+            PresentResult::DeviceLost
+        );
+    }
+
+    #[test]
+    fn identical_transitions_are_batched() {
+        use rendy_core::hal::{
+            image::{Access, Layout, SubresourceRange},
+            pso::PipelineStage,
+        };
+
+        // Compute pass writes array layers that fragment shaders sample next.
+        let images: Vec<_> = (0..10)
+            .map(|index| NodeImage {
+                id: ImageId(index, 0),
+                range: SubresourceRange {
+                    aspects: Aspects::COLOR,
+                    levels: 0..1,
+                    layers: index as u16..index as u16 + 1,
+                },
+                view: None,
+                layout: Layout::ShaderReadOnlyOptimal,
+                clear: None,
+                clear_aspects: Aspects::empty(),
+                acquire: Some(ImageBarrier {
+                    states: (Access::SHADER_WRITE, Layout::General)
+                        ..(Access::SHADER_READ, Layout::ShaderReadOnlyOptimal),
+                    stages: PipelineStage::COMPUTE_SHADER..PipelineStage::FRAGMENT_SHADER,
+                    families: None,
+                }),
+                release: None,
             })
-        }))
-        .collect();
+            .collect();
+
+        let (stages, buffers, barriers) =
+            batch_barriers(None, &images, |_| None, |image| image.acquire.as_ref());
+        assert_eq!(
+            stages,
+            PipelineStage::COMPUTE_SHADER..PipelineStage::FRAGMENT_SHADER
+        );
+        assert!(buffers.is_empty());
+        assert_eq!(barriers.len(), 10);
+        for (index, (image, _)) in barriers.iter().enumerate() {
+            assert_eq!(image.range.layers, index as u16..index as u16 + 1);
+        }
 
-    (bstart | istart..bend | iend, barriers)
+        let (_, _, barriers) =
+            batch_barriers(None, &images, |_| None, |image| image.release.as_ref());
+        assert!(barriers.is_empty());
+    }
 }
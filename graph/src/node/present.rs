@@ -9,10 +9,11 @@ use crate::{
     frame::Frames,
     graph::GraphContext,
     node::{
-        gfx_acquire_barriers, gfx_release_barriers, BufferAccess, DynNode, ImageAccess, NodeBuffer,
-        NodeBuildError, NodeBuilder, NodeImage,
+        check_frames_in_flight, clamp_image_count, default_image_count, gfx_acquire_barriers,
+        gfx_release_barriers, present_result, timed_submits, BufferAccess, DynNode, ImageAccess,
+        NodeBuffer, NodeBuildError, NodeBuilder, NodeImage, NodeTimestamps, PresentResult,
     },
-    wsi::{Surface, Target},
+    wsi::{Surface, SwapchainError, Target},
     BufferId, ImageId, NodeId,
 };
 
@@ -50,6 +51,9 @@ pub struct PresentNode<B: rendy_core::hal::Backend> {
     pool: CommandPool<B, rendy_core::hal::queue::QueueType>,
     input_image: NodeImage,
     blit_filter: rendy_core::hal::image::Filter,
+    preferred_present_modes: Vec<rendy_core::hal::window::PresentMode>,
+    /// Swapchain went out of date and must be recreated before next acquisition.
+    needs_recreation: bool,
 }
 
 // Raw pointer destroys Send/Sync autoimpl, but it's always from the same graph.
@@ -61,7 +65,9 @@ where
     B: rendy_core::hal::Backend,
 {
     /// Node builder.
-    /// By default attempts to use 3 images in the swapchain with present mode priority:
+    /// By default attempts to use one image more than surface requires,
+    /// but no less than frames in flight of the graph,
+    /// in the swapchain with present mode priority:
     ///
     /// Mailbox > Fifo > Relaxed > Immediate.
     ///
@@ -71,9 +77,8 @@ where
         use rendy_core::hal::window::PresentMode;
 
         let caps = factory.get_surface_capabilities(&surface);
-        let image_count = 3
-            .min(*caps.image_count.end())
-            .max(*caps.image_count.start());
+        let image_count = clamp_image_count(caps.image_count.start() + 1, &caps.image_count);
+        log::debug!("Swapchain image count: {}", image_count);
 
         let present_mode = match () {
             _ if caps.present_modes.contains(PresentMode::FIFO) => PresentMode::FIFO,
@@ -83,18 +88,112 @@ where
             _ => panic!("No known present modes found"),
         };
 
+        let formats = factory.get_surface_formats(&surface);
+
         PresentBuilder {
             surface,
             image,
             dependencies: Vec::new(),
             image_count,
+            requested_image_count: false,
             present_mode,
             caps,
+            formats,
             blit_filter: rendy_core::hal::image::Filter::Nearest,
+            preferred_present_modes: Vec::new(),
+        }
+    }
+
+    /// Get present mode used by the swapchain.
+    pub fn present_mode(&self) -> rendy_core::hal::window::PresentMode {
+        self.target.present_mode()
+    }
+}
+
+/// Pick first of `preferred` modes that is `supported`.
+/// Falls back to `Fifo` which is always supported.
+fn pick_present_mode(
+    preferred: &[rendy_core::hal::window::PresentMode],
+    supported: rendy_core::hal::window::PresentMode,
+) -> rendy_core::hal::window::PresentMode {
+    preferred
+        .iter()
+        .cloned()
+        .find(|&mode| supported.contains(mode))
+        .unwrap_or(rendy_core::hal::window::PresentMode::FIFO)
+}
+
+impl<B> PresentNode<B>
+where
+    B: rendy_core::hal::Backend,
+{
+    /// Recreate swapchain to match input image extent and re-record blits.
+    ///
+    /// # Safety
+    ///
+    /// Must be called after waiting for device idle.
+    unsafe fn recreate(
+        &mut self,
+        ctx: &GraphContext<B>,
+        factory: &Factory<B>,
+    ) -> Result<(), SwapchainError> {
+        let extent = ctx
+            .get_image(self.input_image.id)
+            .expect("Context must contain node's image")
+            .kind()
+            .extent()
+            .into();
+
+        if !self.preferred_present_modes.is_empty() {
+            // Capabilities may change along with surface.
+            let caps = factory.get_surface_capabilities(self.target.surface());
+            let present_mode = pick_present_mode(&self.preferred_present_modes, caps.present_modes);
+            log::debug!("Present mode negotiated: {:?}", present_mode);
+            self.target.set_present_mode(present_mode);
+        }
+
+        self.target
+            .recreate(factory.physical(), factory.device(), extent)?;
+
+        for data in self.per_image.drain(..) {
+            data.dispose(factory, &mut self.pool);
         }
+
+        self.per_image = create_per_image_data(
+            ctx,
+            &self.input_image,
+            &mut self.pool,
+            factory,
+            &self.target,
+            self.blit_filter,
+        );
+        Ok(())
     }
 }
 
+/// Submit without command buffers when image isn't presented,
+/// so that semaphores and fence of the node are still waited and signaled.
+unsafe fn skip_present<'a, B: rendy_core::hal::Backend>(
+    queue: &mut Queue<B>,
+    waits: &[(&'a B::Semaphore, rendy_core::hal::pso::PipelineStage)],
+    signals: &[&'a B::Semaphore],
+    fence: Option<&mut Fence<B>>,
+    timestamps: Option<NodeTimestamps<'a, B>>,
+) {
+    queue.submit(
+        Some(
+            Submission::new()
+                .submits(timed_submits(
+                    None::<&Submit<B, SimultaneousUse>>,
+                    timestamps,
+                ))
+                .wait(waits.iter().cloned())
+                .signal(signals.iter().cloned()),
+        ),
+        fence,
+    );
+}
+
 fn create_per_image_data<B: rendy_core::hal::Backend>(
     ctx: &GraphContext<B>,
     input_image: &NodeImage,
@@ -110,7 +209,8 @@ fn create_per_image_data<B: rendy_core::hal::Backend>(
     target_images
         .iter()
         .zip(buffers)
-        .map(|(target_image, buf_initial)| {
+        .map(|(target_image, mut buf_initial)| {
+            factory.set_object_name(&mut buf_initial, std::any::type_name::<PresentNode<B>>());
             let mut buf_recording = buf_initial.begin(MultiShot(SimultaneousUse), ());
             let mut encoder = buf_recording.encoder();
             let (mut stages, mut barriers) =
@@ -261,10 +361,13 @@ pub struct PresentBuilder<B: rendy_core::hal::Backend> {
     surface: Surface<B>,
     image: ImageId,
     image_count: u32,
+    requested_image_count: bool,
     present_mode: rendy_core::hal::window::PresentMode,
     caps: rendy_core::hal::window::SurfaceCapabilities,
+    formats: Option<Vec<rendy_core::hal::format::Format>>,
     dependencies: Vec<NodeId>,
     blit_filter: rendy_core::hal::image::Filter,
+    preferred_present_modes: Vec<rendy_core::hal::window::PresentMode>,
 }
 
 impl<B> PresentBuilder<B>
@@ -291,10 +394,16 @@ where
     /// Check `PresentBuilder::image_count()` after calling this function but before
     /// building to see the final image count.
     pub fn with_image_count(mut self, image_count: u32) -> Self {
-        let image_count = image_count
-            .min(*self.caps.image_count.end())
-            .max(*self.caps.image_count.start());
-        self.image_count = image_count;
+        self.image_count = clamp_image_count(image_count, &self.caps.image_count);
+        self.requested_image_count = true;
+        if self.image_count != image_count {
+            log::debug!(
+                "Requested {} swapchain images, surface supports {:?}. Using {}",
+                image_count,
+                self.caps.image_count,
+                self.image_count
+            );
+        }
         self
     }
 
@@ -349,7 +458,40 @@ where
         self
     }
 
+    /// Request present modes in order of preference.
+    /// First supported mode is used, falling back to `Fifo` which is always supported.
+    ///
+    /// Unlike `with_present_modes_priority` the mode is negotiated again
+    /// when the node is built and whenever swapchain is recreated,
+    /// since surface capabilities can change.
+    pub fn with_preferred_present_modes(
+        mut self,
+        present_modes: &[rendy_core::hal::window::PresentMode],
+    ) -> Self {
+        self.preferred_present_modes = present_modes.to_vec();
+        self.present_mode = pick_present_mode(present_modes, self.caps.present_modes);
+        self
+    }
+
+    /// Request swapchain formats in order of preference.
+    /// First format supported by the surface is used,
+    /// falling back to the best supported sRGB format.
+    ///
+    /// Check `PresentBuilder::format()` to see the final format,
+    /// e.g. to render into an image of the same format and avoid conversion on blit.
+    pub fn with_format_preference(mut self, formats: &[rendy_core::hal::format::Format]) -> Self {
+        self.surface.set_preferred_formats(formats);
+        self
+    }
+
+    /// Get format of images in presentable swapchain.
+    pub fn format(&self) -> rendy_core::hal::format::Format {
+        crate::wsi::pick_format(self.surface.preferred_formats(), self.formats.as_deref())
+    }
+
     /// Get image count in presentable swapchain.
+    /// Unless requested with `with_image_count`, it is raised when the node is built
+    /// to serve all frames in flight of the graph, if surface allows.
     pub fn image_count(&self) -> u32 {
         self.image_count
     }
@@ -420,16 +562,44 @@ where
             return Err(NodeBuildError::QueueFamily(family.id()));
         }
 
+        let present_mode = if self.preferred_present_modes.is_empty() {
+            self.present_mode
+        } else {
+            let caps = factory.get_surface_capabilities(&self.surface);
+            pick_present_mode(&self.preferred_present_modes, caps.present_modes)
+        };
+        log::debug!("Present mode: {:?}", present_mode);
+
+        let image_count = if self.requested_image_count {
+            self.image_count
+        } else {
+            clamp_image_count(
+                default_image_count(*self.caps.image_count.start(), ctx.frames_in_flight),
+                &self.caps.image_count,
+            )
+        };
+        log::debug!("Swapchain image count: {}", image_count);
+
         let target = factory
             .create_target(
                 self.surface,
                 extent,
-                self.image_count,
-                self.present_mode,
+                image_count,
+                present_mode,
                 rendy_core::hal::image::Usage::TRANSFER_DST,
             )
             .map_err(NodeBuildError::Swapchain)?;
 
+        // Driver may create more images than requested.
+        if let Err(err) =
+            check_frames_in_flight(ctx.frames_in_flight, target.backbuffer().len() as u32)
+        {
+            unsafe {
+                factory.destroy_surface(factory.destroy_target(target));
+            }
+            return Err(err);
+        }
+
         let mut pool = factory
             .create_command_pool(family)
             .map_err(NodeBuildError::OutOfMemory)?;
@@ -450,6 +620,8 @@ where
             per_image,
             input_image,
             blit_filter: self.blit_filter,
+            preferred_present_modes: self.preferred_present_modes,
+            needs_recreation: false,
         }))
     }
 }
@@ -468,78 +640,85 @@ where
         _frames: &Frames<B>,
         waits: &[(&'a B::Semaphore, rendy_core::hal::pso::PipelineStage)],
         signals: &[&'a B::Semaphore],
-        mut fence: Option<&mut Fence<B>>,
-    ) {
-        loop {
-            match self.target.next_image(&self.free_acquire) {
-                Ok(next) => {
-                    log::trace!("Present: {:#?}", next);
-                    let ref mut for_image = self.per_image[next[0] as usize];
-                    core::mem::swap(&mut for_image.acquire, &mut self.free_acquire);
-
-                    queue.submit(
-                        Some(
-                            Submission::new()
-                                .submits(Some(&for_image.submit))
-                                .wait(waits.iter().cloned().chain(Some((
-                                    &for_image.acquire,
-                                    rendy_core::hal::pso::PipelineStage::TRANSFER,
-                                ))))
-                                .signal(signals.iter().cloned().chain(Some(&for_image.release))),
-                        ),
-                        fence.take(),
-                    );
+        fence: Option<&mut Fence<B>>,
+        timestamps: Option<NodeTimestamps<'a, B>>,
+    ) -> PresentResult {
+        if self.needs_recreation {
+            // TODO: use retired swapchains once available in hal and remove that wait
+            match factory.wait_idle() {
+                Ok(()) => match self.recreate(ctx, factory) {
+                    Ok(()) => self.needs_recreation = false,
+                    Err(err) => log::warn!("Failed recreating swapchain: {:?}", err),
+                },
+                Err(err) => log::warn!("Failed waiting for device idle: {:?}", err),
+            }
 
-                    match next.present(queue.raw(), Some(&for_image.release)) {
-                        Ok(_) => break,
-                        Err(e) => {
-                            log::debug!(
-                                "Swapchain present error after next_image is acquired: {:?}",
-                                e
-                            );
-                            // recreate swapchain on next frame.
-                            break;
-                        }
-                    }
-                }
-                Err(rendy_core::hal::window::AcquireError::OutOfDate) => {
-                    // recreate swapchain and try again.
-                }
-                e => {
-                    e.unwrap();
-                    break;
-                }
+            if self.needs_recreation {
+                skip_present(queue, waits, signals, fence, timestamps);
+                return PresentResult::NeedsRecreation;
             }
-            // Recreate swapchain when OutOfDate
-            // The code has to execute after match due to mutable aliasing issues.
+        }
 
-            // TODO: use retired swapchains once available in hal and remove that wait
-            factory.wait_idle().unwrap();
+        let next = match self.target.next_image(&self.free_acquire) {
+            Ok(next) => next,
+            Err(rendy_core::hal::window::AcquireError::DeviceLost(_)) => {
+                factory.mark_device_lost();
+                skip_present(queue, waits, signals, fence, timestamps);
+                return PresentResult::DeviceLost;
+            }
+            Err(err) => {
+                log::debug!("Swapchain acquisition error: {:?}", err);
+                // Failed acquisition doesn't signal the semaphore, it can be used again.
+                self.needs_recreation = true;
+                skip_present(queue, waits, signals, fence, timestamps);
+                return PresentResult::NeedsRecreation;
+            }
+        };
 
-            let extent = ctx
-                .get_image(self.input_image.id)
-                .expect("Context must contain node's image")
-                .kind()
-                .extent()
-                .into();
+        log::trace!("Present: {:#?}", next);
+        let suboptimal = next.is_suboptimal();
+        let ref mut for_image = self.per_image[next[0] as usize];
+        core::mem::swap(&mut for_image.acquire, &mut self.free_acquire);
+
+        queue.submit(
+            Some(
+                Submission::new()
+                    .submits(timed_submits(Some(&for_image.submit), timestamps))
+                    .wait(waits.iter().cloned().chain(Some((
+                        &for_image.acquire,
+                        rendy_core::hal::pso::PipelineStage::TRANSFER,
+                    ))))
+                    .signal(signals.iter().cloned().chain(Some(&for_image.release))),
+            ),
+            fence,
+        );
 
-            self.target
-                .recreate(factory.physical(), factory.device(), extent)
-                .expect("Failed recreating swapchain");
+        let result = present_result(
+            suboptimal,
+            next.present(queue.raw(), Some(&for_image.release)),
+        );
+        match result {
+            PresentResult::NeedsRecreation => self.needs_recreation = true,
+            PresentResult::DeviceLost => factory.mark_device_lost(),
+            _ => {}
+        }
+        result
+    }
 
-            for data in self.per_image.drain(..) {
-                data.dispose(factory, &mut self.pool);
-            }
+    fn acquires_image(&self) -> bool {
+        true
+    }
 
-            self.per_image = create_per_image_data(
-                ctx,
-                &self.input_image,
-                &mut self.pool,
-                factory,
-                &self.target,
-                self.blit_filter,
-            );
-        }
+    unsafe fn resize(
+        &mut self,
+        ctx: &GraphContext<B>,
+        factory: &mut Factory<B>,
+        _aux: &T,
+    ) -> Result<bool, NodeBuildError> {
+        self.recreate(ctx, factory)
+            .map_err(NodeBuildError::Swapchain)?;
+        self.needs_recreation = false;
+        Ok(true)
     }
 
     unsafe fn dispose(mut self: Box<Self>, factory: &mut Factory<B>, _aux: &T) {
@@ -552,3 +731,27 @@ where
         factory.destroy_target(self.target);
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use {super::pick_present_mode, rendy_core::hal::window::PresentMode};
+
+    #[test]
+    fn first_supported_mode_is_picked() {
+        let supported = PresentMode::FIFO | PresentMode::IMMEDIATE;
+        assert_eq!(
+            pick_present_mode(&[PresentMode::MAILBOX, PresentMode::IMMEDIATE], supported),
+            PresentMode::IMMEDIATE
+        );
+    }
+
+    #[test]
+    fn falls_back_to_fifo() {
+        let supported = PresentMode::FIFO;
+        assert_eq!(
+            pick_present_mode(&[PresentMode::MAILBOX, PresentMode::RELAXED], supported),
+            PresentMode::FIFO
+        );
+        assert_eq!(pick_present_mode(&[], supported), PresentMode::FIFO);
+    }
+}
@@ -0,0 +1,196 @@
+//! Batching of draws sharing pipeline, descriptor set and mesh.
+
+/// Commands recorded by `DrawBatcher`.
+///
+/// Implemented by the user, usually as a wrapper around `RenderPassEncoder`
+/// that looks pipelines, sets and meshes up by the keys
+/// and binds them with `bind_graphics_pipeline`, `bind_graphics_descriptor_sets`
+/// and `Mesh::bind`.
+pub trait BatchRecorder<P, S, M, D> {
+    /// Bind pipeline.
+    fn bind_pipeline(&mut self, pipeline: &P);
+
+    /// Bind descriptor set.
+    fn bind_set(&mut self, set: &S);
+
+    /// Bind vertex and index buffers of the mesh.
+    fn bind_mesh(&mut self, mesh: &M);
+
+    /// Draw bound mesh with per-draw data, e.g. instance range.
+    fn draw(&mut self, mesh: &M, draw: &D);
+}
+
+/// Statistics of recorded batch.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct BatchStats {
+    /// Number of draws recorded.
+    pub draws: usize,
+
+    /// Number of binds recorded.
+    pub binds: usize,
+
+    /// Number of binds avoided compared to binding all state before each draw.
+    pub binds_avoided: usize,
+}
+
+/// Collects draws and records them sorted by state to minimize state changes.
+///
+/// Draws are grouped by pipeline, then by descriptor set, then by mesh.
+/// Draws with the same state are recorded in the order they were pushed.
+/// Keys are usually small handles or indices owned by the engine.
+///
+/// Descriptor set is bound again after each pipeline change,
+/// as pipelines may have different layouts.
+#[derive(Debug)]
+pub struct DrawBatcher<P, S, M, D> {
+    draws: Vec<(P, S, M, D)>,
+}
+
+impl<P, S, M, D> Default for DrawBatcher<P, S, M, D> {
+    fn default() -> Self {
+        DrawBatcher { draws: Vec::new() }
+    }
+}
+
+impl<P, S, M, D> DrawBatcher<P, S, M, D>
+where
+    P: Ord,
+    S: Ord,
+    M: Ord,
+{
+    /// Create empty batcher.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Add draw to the batch.
+    pub fn push(&mut self, pipeline: P, set: S, mesh: M, draw: D) {
+        self.draws.push((pipeline, set, mesh, draw));
+    }
+
+    /// Number of draws in the batch.
+    pub fn len(&self) -> usize {
+        self.draws.len()
+    }
+
+    /// Check if batch has no draws.
+    pub fn is_empty(&self) -> bool {
+        self.draws.is_empty()
+    }
+
+    /// Record all draws and clear the batch.
+    /// Allocated storage is kept for the next batch.
+    pub fn record(&mut self, recorder: &mut impl BatchRecorder<P, S, M, D>) -> BatchStats {
+        // Stable sort keeps order of draws with the same state.
+        self.draws
+            .sort_by(|(lp, ls, lm, _), (rp, rs, rm, _)| (lp, ls, lm).cmp(&(rp, rs, rm)));
+
+        let mut stats = BatchStats::default();
+        let mut last: Option<(&P, &S, &M)> = None;
+
+        for (pipeline, set, mesh, draw) in &self.draws {
+            let (pipeline_changed, set_changed, mesh_changed) = match last {
+                None => (true, true, true),
+                Some((last_pipeline, last_set, last_mesh)) => {
+                    let pipeline_changed = last_pipeline != pipeline;
+                    (
+                        pipeline_changed,
+                        pipeline_changed || last_set != set,
+                        last_mesh != mesh,
+                    )
+                }
+            };
+
+            if pipeline_changed {
+                recorder.bind_pipeline(pipeline);
+                stats.binds += 1;
+            }
+            if set_changed {
+                recorder.bind_set(set);
+                stats.binds += 1;
+            }
+            if mesh_changed {
+                recorder.bind_mesh(mesh);
+                stats.binds += 1;
+            }
+            recorder.draw(mesh, draw);
+            stats.draws += 1;
+            last = Some((pipeline, set, mesh));
+        }
+
+        stats.binds_avoided = stats.draws * 3 - stats.binds;
+        self.draws.clear();
+        stats
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{BatchRecorder, BatchStats, DrawBatcher};
+
+    #[derive(Debug, PartialEq)]
+    enum Command {
+        Pipeline(u32),
+        Set(u32),
+        Mesh(u32),
+        Draw(u32, &'static str),
+    }
+
+    impl BatchRecorder<u32, u32, u32, &'static str> for Vec<Command> {
+        fn bind_pipeline(&mut self, pipeline: &u32) {
+            self.push(Command::Pipeline(*pipeline));
+        }
+
+        fn bind_set(&mut self, set: &u32) {
+            self.push(Command::Set(*set));
+        }
+
+        fn bind_mesh(&mut self, mesh: &u32) {
+            self.push(Command::Mesh(*mesh));
+        }
+
+        fn draw(&mut self, mesh: &u32, draw: &&'static str) {
+            self.push(Command::Draw(*mesh, *draw));
+        }
+    }
+
+    #[test]
+    fn each_pipeline_is_bound_once() {
+        let mut batcher = DrawBatcher::new();
+        batcher.push(1, 0, 0, "a");
+        batcher.push(0, 0, 0, "b");
+        batcher.push(1, 0, 1, "c");
+        batcher.push(0, 0, 0, "d");
+        batcher.push(1, 0, 0, "e");
+
+        let mut commands = Vec::new();
+        let stats = batcher.record(&mut commands);
+
+        assert_eq!(
+            commands,
+            vec![
+                Command::Pipeline(0),
+                Command::Set(0),
+                Command::Mesh(0),
+                Command::Draw(0, "b"),
+                Command::Draw(0, "d"),
+                // Vertex buffers stay bound after pipeline change.
+                Command::Pipeline(1),
+                Command::Set(0),
+                Command::Draw(0, "a"),
+                Command::Draw(0, "e"),
+                Command::Mesh(1),
+                Command::Draw(1, "c"),
+            ]
+        );
+        assert_eq!(
+            stats,
+            BatchStats {
+                draws: 5,
+                binds: 6,
+                binds_avoided: 9,
+            }
+        );
+        assert!(batcher.is_empty());
+    }
+}
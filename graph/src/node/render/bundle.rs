@@ -0,0 +1,88 @@
+//! Commands recorded once and replayed until invalidated.
+
+use super::PrepareResult;
+
+/// Tracks whether commands of render group are recorded for each frame index.
+///
+/// Render groups that record into secondary command buffers
+/// (see `RenderGroup::secondary` and `SimpleGraphicsPipeline::SECONDARY`)
+/// keep a buffer per frame index and replay it each frame.
+/// Return result of `RenderBundle::prepare` from `prepare` to record
+/// each buffer once and replay it until `invalidate` is called,
+/// e.g. when static geometry changes.
+#[derive(Clone, Debug, Default)]
+pub struct RenderBundle {
+    recorded: Vec<bool>,
+    recordings: u64,
+}
+
+impl RenderBundle {
+    /// Create bundle with no commands recorded.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Force re-recording commands for all frame indices.
+    pub fn invalidate(&mut self) {
+        self.recorded
+            .iter_mut()
+            .for_each(|recorded| *recorded = false);
+    }
+
+    /// Check if commands for frame `index` are recorded and still valid.
+    pub fn is_recorded(&self, index: usize) -> bool {
+        self.recorded.get(index).cloned().unwrap_or(false)
+    }
+
+    /// Prepare bundle for frame `index`.
+    /// Asks to record commands only if they weren't recorded for this index
+    /// since creation of the bundle or the last invalidation.
+    /// Commands are assumed to be recorded once this function asks for it.
+    pub fn prepare(&mut self, index: usize) -> PrepareResult {
+        if self.is_recorded(index) {
+            return PrepareResult::DrawReuse;
+        }
+
+        if self.recorded.len() <= index {
+            self.recorded.resize(index + 1, false);
+        }
+        self.recorded[index] = true;
+        self.recordings += 1;
+        PrepareResult::DrawRecord
+    }
+
+    /// Number of times commands were recorded.
+    pub fn recordings(&self) -> u64 {
+        self.recordings
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn unchanged_bundle_is_recorded_once_per_index() {
+        let mut bundle = RenderBundle::new();
+        for frame in 0..9 {
+            let record = bundle.prepare(frame % 3).force_record();
+            assert_eq!(record, frame < 3);
+        }
+        assert_eq!(bundle.recordings(), 3);
+    }
+
+    #[test]
+    fn invalidated_bundle_is_recorded_again() {
+        let mut bundle = RenderBundle::new();
+        assert!(bundle.prepare(0).force_record());
+        assert!(bundle.prepare(1).force_record());
+        assert!(!bundle.prepare(0).force_record());
+
+        bundle.invalidate();
+        assert!(!bundle.is_recorded(0));
+        assert!(bundle.prepare(0).force_record());
+        assert!(bundle.prepare(1).force_record());
+        assert!(!bundle.prepare(1).force_record());
+        assert_eq!(bundle.recordings(), 4);
+    }
+}
@@ -0,0 +1,429 @@
+//! Descriptor sets allocated and written on demand.
+
+use {
+    crate::{
+        factory::Factory,
+        resource::{
+            Buffer, DescriptorSet, DescriptorSetLayout, Escape, Handle, ImageView, Sampler,
+        },
+    },
+    rendy_core::hal::{
+        device::{Device as _, OutOfMemory},
+        image::Layout,
+        pso::{DescriptorSetLayoutBinding, DescriptorType, ShaderStageFlags},
+        Backend,
+    },
+    std::{collections::HashMap, hash::Hash, ops::Range},
+};
+
+/// Resource bound to descriptor set allocated by `DescriptorSetCache`.
+#[derive(Debug)]
+pub enum SetDescriptor<'a, B: Backend> {
+    /// Range of uniform or storage buffer.
+    Buffer(&'a Handle<Buffer<B>>, Range<u64>),
+
+    /// Sampled, storage or input attachment image.
+    Image(&'a Handle<ImageView<B>>, Layout),
+
+    /// Image with sampler.
+    CombinedImageSampler(&'a Handle<ImageView<B>>, Layout, &'a Handle<Sampler<B>>),
+
+    /// Sampler.
+    Sampler(&'a Handle<Sampler<B>>),
+
+    /// Array of sampled or storage images
+    /// bound to consecutive elements of the binding starting from 0.
+    /// Length must be equal to `count` of the binding,
+    /// as elements left unwritten are invalid to access
+    /// without `PARTIALLY_BOUND` binding flag.
+    ImageArray(&'a [(&'a Handle<ImageView<B>>, Layout)]),
+}
+
+/// Layout binding for array of `count` images of type `ty`,
+/// e.g. `DescriptorType::SampledImage` array indexed by material id in the shader.
+///
+/// Indexing the array with non-uniform value in shader
+/// requires `shaderSampledImageArrayDynamicIndexing` device feature.
+pub fn image_array_binding(
+    binding: u32,
+    ty: DescriptorType,
+    count: usize,
+    stage_flags: ShaderStageFlags,
+) -> DescriptorSetLayoutBinding {
+    DescriptorSetLayoutBinding {
+        binding,
+        ty,
+        count,
+        stage_flags,
+        immutable_samplers: false,
+    }
+}
+
+/// Contents of one binding.
+/// Resources are identified by address, which stays unique
+/// while cache keeps their handles alive.
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+enum DescriptorKey {
+    Buffer(usize, Range<u64>),
+    Image(usize, Layout),
+    CombinedImageSampler(usize, Layout, usize),
+    Sampler(usize),
+    ImageArray(Vec<(usize, Layout)>),
+}
+
+fn address<T>(handle: &Handle<T>) -> usize {
+    &**handle as *const T as usize
+}
+
+impl<'a, B> SetDescriptor<'a, B>
+where
+    B: Backend,
+{
+    fn key(&self) -> DescriptorKey {
+        match self {
+            SetDescriptor::Buffer(buffer, range) => {
+                DescriptorKey::Buffer(address(buffer), range.clone())
+            }
+            SetDescriptor::Image(view, layout) => DescriptorKey::Image(address(view), *layout),
+            SetDescriptor::CombinedImageSampler(view, layout, sampler) => {
+                DescriptorKey::CombinedImageSampler(address(view), *layout, address(sampler))
+            }
+            SetDescriptor::Sampler(sampler) => DescriptorKey::Sampler(address(sampler)),
+            SetDescriptor::ImageArray(views) => DescriptorKey::ImageArray(
+                views
+                    .iter()
+                    .map(|&(view, layout)| (address(view), layout))
+                    .collect(),
+            ),
+        }
+    }
+
+    /// Number of array elements written.
+    fn len(&self) -> usize {
+        match self {
+            SetDescriptor::ImageArray(views) => views.len(),
+            _ => 1,
+        }
+    }
+
+    fn is_array(&self) -> bool {
+        match self {
+            SetDescriptor::ImageArray(_) => true,
+            _ => false,
+        }
+    }
+
+    fn raw(&self) -> impl Iterator<Item = rendy_core::hal::pso::Descriptor<'a, B>> + 'a {
+        let (single, array) = match *self {
+            SetDescriptor::ImageArray(views) => (None, views),
+            _ => (Some(self.raw_single()), &[][..]),
+        };
+        single.into_iter().chain(
+            array
+                .iter()
+                .map(|&(view, layout)| rendy_core::hal::pso::Descriptor::Image(view.raw(), layout)),
+        )
+    }
+
+    fn raw_single(&self) -> rendy_core::hal::pso::Descriptor<'a, B> {
+        match *self {
+            SetDescriptor::Buffer(buffer, ref range) => rendy_core::hal::pso::Descriptor::Buffer(
+                buffer.raw(),
+                Some(range.start)..Some(range.end),
+            ),
+            SetDescriptor::Image(view, layout) => {
+                rendy_core::hal::pso::Descriptor::Image(view.raw(), layout)
+            }
+            SetDescriptor::CombinedImageSampler(view, layout, sampler) => {
+                rendy_core::hal::pso::Descriptor::CombinedImageSampler(
+                    view.raw(),
+                    layout,
+                    sampler.raw(),
+                )
+            }
+            SetDescriptor::Sampler(sampler) => {
+                rendy_core::hal::pso::Descriptor::Sampler(sampler.raw())
+            }
+            SetDescriptor::ImageArray(_) => unreachable!("Arrays are written element-wise"),
+        }
+    }
+}
+
+/// Check that `len` descriptors fit into the binding.
+/// Arrays must fill the binding entirely.
+fn check_binding_len(declared: Option<usize>, binding: u32, len: usize, array: bool) {
+    match declared {
+        None => panic!("Binding {} is not declared in the set layout", binding),
+        Some(count) if array => assert!(
+            len == count && len > 0,
+            "Binding {} declares {} descriptors, array of {} written",
+            binding,
+            count,
+            len
+        ),
+        Some(count) => assert!(
+            len <= count,
+            "Binding {} declares {} descriptors, {} written",
+            binding,
+            count,
+            len
+        ),
+    }
+}
+
+/// Set layout and contents of every binding.
+type SetKey = (usize, Vec<(u32, DescriptorKey)>);
+
+/// Set with resources it references.
+#[derive(Debug)]
+struct CachedSet<B: Backend> {
+    set: Escape<DescriptorSet<B>>,
+    _layout: Handle<DescriptorSetLayout<B>>,
+    _buffers: Vec<Handle<Buffer<B>>>,
+    _views: Vec<Handle<ImageView<B>>>,
+    _samplers: Vec<Handle<Sampler<B>>>,
+}
+
+/// Values keyed by contents and stamped with epoch they were last used at.
+#[derive(Debug)]
+struct Cache<K, V> {
+    entries: HashMap<K, (V, u64)>,
+    epoch: u64,
+}
+
+impl<K, V> Cache<K, V>
+where
+    K: Hash + Eq,
+{
+    fn new() -> Self {
+        Cache {
+            entries: HashMap::new(),
+            epoch: 0,
+        }
+    }
+
+    fn get_or_try_insert_with<E>(
+        &mut self,
+        key: K,
+        insert: impl FnOnce() -> Result<V, E>,
+    ) -> Result<&V, E> {
+        let epoch = self.epoch;
+        let entry = match self.entries.entry(key) {
+            std::collections::hash_map::Entry::Occupied(entry) => entry.into_mut(),
+            std::collections::hash_map::Entry::Vacant(entry) => entry.insert((insert()?, epoch)),
+        };
+        entry.1 = epoch;
+        Ok(&entry.0)
+    }
+
+    /// Start next epoch and remove values unused for more than `keep` epochs.
+    fn advance(&mut self, keep: u64) -> usize {
+        self.epoch += 1;
+        let epoch = self.epoch;
+        let before = self.entries.len();
+        self.entries
+            .retain(|_, &mut (_, used)| epoch - used <= keep);
+        before - self.entries.len()
+    }
+
+    fn len(&self) -> usize {
+        self.entries.len()
+    }
+}
+
+/// Cache of descriptor sets keyed by layout and bound resources.
+///
+/// Render groups that bind different resources each frame can request a set
+/// with `descriptor_set` instead of managing sets manually.
+/// Set is allocated and written only when no set with the same layout
+/// and bindings exists, otherwise existing set is returned.
+/// Descriptor pools are grown by the factory as needed.
+///
+/// Call `advance` once per frame to free sets that weren't requested recently.
+#[derive(Debug)]
+pub struct DescriptorSetCache<B: Backend> {
+    sets: Cache<SetKey, CachedSet<B>>,
+    keep: u64,
+}
+
+impl<B> DescriptorSetCache<B>
+where
+    B: Backend,
+{
+    /// Create empty cache.
+    /// Sets not requested during `keep` frames are freed.
+    /// Value should be no less than number of frames in flight.
+    pub fn new(keep: u64) -> Self {
+        DescriptorSetCache {
+            sets: Cache::new(),
+            keep,
+        }
+    }
+
+    /// Get descriptor set with `layout` and resources bound at specified bindings.
+    /// Array elements are bound starting from index 0.
+    ///
+    /// Allocates and writes new set only if there is no set with the same contents.
+    /// Returned set must not be updated as it may be returned again.
+    ///
+    /// # Panics
+    ///
+    /// Panics if binding isn't declared by the layout,
+    /// more descriptors are written than the binding declares
+    /// or image array length differs from the binding `count`.
+    pub fn descriptor_set(
+        &mut self,
+        factory: &Factory<B>,
+        layout: &Handle<DescriptorSetLayout<B>>,
+        bindings: &[(u32, SetDescriptor<'_, B>)],
+    ) -> Result<&B::DescriptorSet, OutOfMemory> {
+        let key = (
+            address(layout),
+            bindings
+                .iter()
+                .map(|(binding, descriptor)| (*binding, descriptor.key()))
+                .collect(),
+        );
+
+        let cached = self.sets.get_or_try_insert_with(key, || {
+            for (binding, descriptor) in bindings {
+                check_binding_len(
+                    layout.info().binding_count(*binding),
+                    *binding,
+                    descriptor.len(),
+                    descriptor.is_array(),
+                );
+            }
+
+            let set = factory.create_descriptor_set(layout.clone())?;
+            unsafe {
+                factory.device().write_descriptor_sets(bindings.iter().map(
+                    |(binding, descriptor)| rendy_core::hal::pso::DescriptorSetWrite {
+                        set: set.raw(),
+                        binding: *binding,
+                        array_offset: 0,
+                        descriptors: descriptor.raw(),
+                    },
+                ));
+            }
+
+            let mut cached = CachedSet {
+                set,
+                _layout: layout.clone(),
+                _buffers: Vec::new(),
+                _views: Vec::new(),
+                _samplers: Vec::new(),
+            };
+            for (_, descriptor) in bindings {
+                match *descriptor {
+                    SetDescriptor::Buffer(buffer, _) => cached._buffers.push(buffer.clone()),
+                    SetDescriptor::Image(view, _) => cached._views.push(view.clone()),
+                    SetDescriptor::CombinedImageSampler(view, _, sampler) => {
+                        cached._views.push(view.clone());
+                        cached._samplers.push(sampler.clone());
+                    }
+                    SetDescriptor::Sampler(sampler) => cached._samplers.push(sampler.clone()),
+                    SetDescriptor::ImageArray(views) => cached
+                        ._views
+                        .extend(views.iter().map(|&(view, _)| view.clone())),
+                }
+            }
+            Ok(cached)
+        })?;
+
+        Ok(cached.set.raw())
+    }
+
+    /// Start next frame.
+    /// Sets that weren't requested during last `keep` frames are freed
+    /// along with references to resources bound to them.
+    /// Factory destroys them once frames that could use them are complete.
+    pub fn advance(&mut self) {
+        let freed = self.sets.advance(self.keep);
+        if freed > 0 {
+            log::trace!("Freed {} cached descriptor sets", freed);
+        }
+    }
+
+    /// Number of sets currently cached.
+    pub fn len(&self) -> usize {
+        self.sets.len()
+    }
+
+    /// Check if cache has no sets.
+    pub fn is_empty(&self) -> bool {
+        self.sets.len() == 0
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn same_contents_allocate_once() {
+        let mut cache = Cache::new();
+        let mut allocated = 0;
+        let key = (1, vec![(0, DescriptorKey::Buffer(10, 0..64))]);
+
+        for _ in 0..2 {
+            let set = cache
+                .get_or_try_insert_with::<()>(key.clone(), || {
+                    allocated += 1;
+                    Ok(allocated)
+                })
+                .unwrap();
+            assert_eq!(*set, 1);
+        }
+        assert_eq!(allocated, 1);
+
+        let other = (1, vec![(0, DescriptorKey::Buffer(10, 64..128))]);
+        cache
+            .get_or_try_insert_with::<()>(other, || {
+                allocated += 1;
+                Ok(allocated)
+            })
+            .unwrap();
+        assert_eq!(allocated, 2);
+        assert_eq!(cache.len(), 2);
+    }
+
+    #[test]
+    fn unused_sets_are_evicted() {
+        let mut cache = Cache::new();
+        cache.get_or_try_insert_with::<()>(0, || Ok(())).unwrap();
+        cache.get_or_try_insert_with::<()>(1, || Ok(())).unwrap();
+
+        assert_eq!(cache.advance(1), 0);
+        cache.get_or_try_insert_with::<()>(1, || Ok(())).unwrap();
+        assert_eq!(cache.advance(1), 1);
+        assert_eq!(cache.len(), 1);
+    }
+
+    #[test]
+    fn image_arrays_fit_declared_count() {
+        check_binding_len(Some(16), 0, 16, true);
+        check_binding_len(Some(16), 0, 1, false);
+        assert!(std::panic::catch_unwind(|| check_binding_len(Some(16), 0, 17, true)).is_err());
+        assert!(std::panic::catch_unwind(|| check_binding_len(Some(16), 0, 4, true)).is_err());
+        assert!(std::panic::catch_unwind(|| check_binding_len(Some(0), 0, 0, true)).is_err());
+        assert!(std::panic::catch_unwind(|| check_binding_len(Some(1), 0, 2, false)).is_err());
+        assert!(std::panic::catch_unwind(|| check_binding_len(None, 1, 1, false)).is_err());
+
+        let binding = image_array_binding(
+            0,
+            DescriptorType::SampledImage,
+            16,
+            ShaderStageFlags::FRAGMENT,
+        );
+        assert_eq!(binding.count, 16);
+        assert!(!binding.immutable_samplers);
+    }
+
+    #[test]
+    fn failed_allocation_is_not_cached() {
+        let mut cache = Cache::<u32, u32>::new();
+        assert!(cache.get_or_try_insert_with(0, || Err(())).is_err());
+        assert_eq!(cache.len(), 0);
+    }
+}
@@ -1,6 +1,8 @@
+mod prepass;
 mod simple;
+mod state;
 
-pub use self::simple::*;
+pub use self::{prepass::*, simple::*, state::*};
 
 use {
     crate::{
@@ -23,13 +25,7 @@ pub trait RenderGroupDesc<B: Backend, T: ?Sized>: std::fmt::Debug {
     where
         Self: Sized,
     {
-        DescBuilder {
-            desc: self,
-            buffers: Vec::new(),
-            images: Vec::new(),
-            dependencies: Vec::new(),
-            marker: std::marker::PhantomData,
-        }
+        DescBuilder::new(self)
     }
 
     /// Get buffers used by the group. Empty by default.
@@ -52,6 +48,12 @@ pub trait RenderGroupDesc<B: Backend, T: ?Sized>: std::fmt::Debug {
         true
     }
 
+    /// Is stencil aspect of depth image used. False by default.
+    /// Depth image of the subpass must have stencil aspect if it is.
+    fn stencil(&self) -> bool {
+        false
+    }
+
     /// Build render group.
     fn build<'a>(
         self,
@@ -65,6 +67,47 @@ pub trait RenderGroupDesc<B: Backend, T: ?Sized>: std::fmt::Debug {
         buffers: Vec<NodeBuffer>,
         images: Vec<NodeImage>,
     ) -> Result<Box<dyn RenderGroup<B, T>>, rendy_core::hal::pso::CreationError>;
+
+    /// Build render group for subpass with attachments of `samples` samples.
+    /// Pipelines must be created with `samples` rasterization samples.
+    ///
+    /// Default implementation calls `build` for single-sampled subpass
+    /// and fails for multisampled one.
+    fn build_multisampled<'a>(
+        self,
+        ctx: &GraphContext<B>,
+        factory: &mut Factory<B>,
+        queue: QueueId,
+        aux: &T,
+        framebuffer_width: u32,
+        framebuffer_height: u32,
+        samples: rendy_core::hal::image::NumSamples,
+        subpass: rendy_core::hal::pass::Subpass<'_, B>,
+        buffers: Vec<NodeBuffer>,
+        images: Vec<NodeImage>,
+    ) -> Result<Box<dyn RenderGroup<B, T>>, rendy_core::hal::pso::CreationError>
+    where
+        Self: Sized,
+    {
+        if samples > 1 {
+            log::warn!(
+                "Render group {:?} doesn't support multisampled subpass",
+                self
+            );
+            return Err(rendy_core::hal::pso::CreationError::Other);
+        }
+        self.build(
+            ctx,
+            factory,
+            queue,
+            aux,
+            framebuffer_width,
+            framebuffer_height,
+            subpass,
+            buffers,
+            images,
+        )
+    }
 }
 
 /// One or more graphics pipelines to be called in subpass.
@@ -88,6 +131,29 @@ pub trait RenderGroup<B: Backend, T: ?Sized>: std::fmt::Debug + Send + Sync {
         aux: &T,
     );
 
+    /// Record commands of this group into cached secondary command buffers.
+    /// Those are re-recorded only when `prepare` of this group asks for it,
+    /// and executed from the render pass each frame otherwise.
+    ///
+    /// If any group of the subpass records into secondary buffers,
+    /// all groups of that subpass do.
+    fn secondary(&self) -> bool {
+        false
+    }
+
+    /// Adapt group to new framebuffer size after `Graph::resize`.
+    /// Pipelines are kept, so state baked for old size must be updated here.
+    ///
+    /// Returns `false` if group can't be resized and graph must be rebuilt instead.
+    fn resize(
+        &mut self,
+        _factory: &Factory<B>,
+        _framebuffer_width: u32,
+        _framebuffer_height: u32,
+    ) -> bool {
+        false
+    }
+
     /// Free all resources and destroy group instance.
     fn dispose(self: Box<Self>, factory: &mut Factory<B>, aux: &T);
 }
@@ -108,6 +174,11 @@ pub trait RenderGroupBuilder<B: Backend, T: ?Sized>: std::fmt::Debug {
     /// Is depth image used.
     fn depth(&self) -> bool;
 
+    /// Is stencil aspect of depth image used. False by default.
+    fn stencil(&self) -> bool {
+        false
+    }
+
     /// Get buffers used by the group
     fn buffers(&self) -> Vec<(BufferId, BufferAccess)>;
 
@@ -126,6 +197,7 @@ pub trait RenderGroupBuilder<B: Backend, T: ?Sized>: std::fmt::Debug {
         aux: &T,
         framebuffer_width: u32,
         framebuffer_height: u32,
+        samples: rendy_core::hal::image::NumSamples,
         subpass: rendy_core::hal::pass::Subpass<'_, B>,
         buffers: Vec<NodeBuffer>,
         images: Vec<NodeImage>,
@@ -146,6 +218,10 @@ where
         self.desc.depth()
     }
 
+    fn stencil(&self) -> bool {
+        self.desc.stencil()
+    }
+
     fn buffers(&self) -> Vec<(BufferId, BufferAccess)> {
         self.buffers
             .iter()
@@ -155,6 +231,10 @@ where
     }
 
     fn images(&self) -> Vec<(ImageId, ImageAccess)> {
+        assert!(
+            self.optional_images.is_empty(),
+            "Optional images are not supported by render groups"
+        );
         self.images
             .iter()
             .cloned()
@@ -174,17 +254,19 @@ where
         aux: &T,
         framebuffer_width: u32,
         framebuffer_height: u32,
+        samples: rendy_core::hal::image::NumSamples,
         subpass: rendy_core::hal::pass::Subpass<'_, B>,
         buffers: Vec<NodeBuffer>,
         images: Vec<NodeImage>,
     ) -> Result<Box<dyn RenderGroup<B, T>>, rendy_core::hal::pso::CreationError> {
-        self.desc.build(
+        self.desc.build_multisampled(
             ctx,
             factory,
             queue,
             aux,
             framebuffer_width,
             framebuffer_height,
+            samples,
             subpass,
             buffers,
             images,
@@ -0,0 +1,233 @@
+use {
+    super::simple::{Pipeline, SimpleGraphicsPipelineDesc},
+    crate::{
+        command::QueueId,
+        factory::Factory,
+        graph::GraphContext,
+        node::{BufferAccess, ImageAccess, NodeBuffer, NodeImage},
+        resource::{DescriptorSetLayout, Handle},
+    },
+    rendy_core::hal::Backend,
+};
+
+/// Depth-only variant of simple graphics pipeline.
+///
+/// Draws geometry of the wrapped pipeline without color outputs,
+/// writing depth of the closest surfaces.
+/// Subpass of the prepass must have a depth attachment and no color attachments:
+///
+/// ```ignore
+/// let depth_pass = graph_builder.add_node(
+///     DepthPrepass::new(desc.clone())
+///         .builder()
+///         .into_subpass()
+///         .with_depth_stencil(depth)
+///         .into_pass(),
+/// );
+/// ```
+///
+/// Main pass then draws the same geometry with `prepassed_depth_stencil`,
+/// so fragment shaders run only for visible surfaces.
+///
+/// Fragment shader of the wrapped pipeline is dropped from the prepass
+/// unless alpha testing is enabled with `with_alpha_test`.
+#[derive(Clone, Debug, Default)]
+pub struct DepthPrepass<P> {
+    inner: P,
+    alpha_test: bool,
+}
+
+impl<P> DepthPrepass<P> {
+    /// Wrap pipeline desc to draw depth only.
+    pub fn new(inner: P) -> Self {
+        DepthPrepass {
+            inner,
+            alpha_test: false,
+        }
+    }
+
+    /// Keep fragment shader of the wrapped pipeline in the prepass,
+    /// so fragments it discards don't write depth.
+    pub fn with_alpha_test(mut self) -> Self {
+        self.alpha_test = true;
+        self
+    }
+
+    /// Get wrapped pipeline desc.
+    pub fn inner(&self) -> &P {
+        &self.inner
+    }
+}
+
+/// Depth stencil state for main pass drawing geometry after `DepthPrepass`.
+/// Depth is already written by prepass, only surfaces with equal depth pass the test.
+pub fn prepassed_depth_stencil() -> rendy_core::hal::pso::DepthStencilDesc {
+    rendy_core::hal::pso::DepthStencilDesc {
+        depth: Some(rendy_core::hal::pso::DepthTest {
+            fun: rendy_core::hal::pso::Comparison::Equal,
+            write: false,
+        }),
+        depth_bounds: false,
+        stencil: None,
+    }
+}
+
+/// Make pipeline write depth without color outputs.
+fn depth_only(pipeline: Pipeline) -> Pipeline {
+    Pipeline {
+        colors: Vec::new(),
+        depth_stencil: rendy_core::hal::pso::DepthStencilDesc {
+            depth: Some(rendy_core::hal::pso::DepthTest {
+                fun: rendy_core::hal::pso::Comparison::Less,
+                write: true,
+            }),
+            ..pipeline.depth_stencil
+        },
+        ..pipeline
+    }
+}
+
+impl<B, T, P> SimpleGraphicsPipelineDesc<B, T> for DepthPrepass<P>
+where
+    B: Backend,
+    T: ?Sized,
+    P: SimpleGraphicsPipelineDesc<B, T>,
+{
+    type Pipeline = P::Pipeline;
+
+    fn buffers(&self) -> Vec<BufferAccess> {
+        self.inner.buffers()
+    }
+
+    fn images(&self) -> Vec<ImageAccess> {
+        self.inner.images()
+    }
+
+    fn colors(&self) -> Vec<rendy_core::hal::pso::ColorBlendDesc> {
+        Vec::new()
+    }
+
+    fn depth_stencil(&self) -> Option<rendy_core::hal::pso::DepthStencilDesc> {
+        Some(depth_only(self.pipeline()).depth_stencil)
+    }
+
+    fn rasterizer(&self) -> rendy_core::hal::pso::Rasterizer {
+        self.inner.rasterizer()
+    }
+
+    fn vertices(
+        &self,
+    ) -> Vec<(
+        Vec<rendy_core::hal::pso::Element<rendy_core::hal::format::Format>>,
+        rendy_core::hal::pso::ElemStride,
+        rendy_core::hal::pso::VertexInputRate,
+    )> {
+        self.inner.vertices()
+    }
+
+    fn layout(&self) -> super::simple::Layout {
+        self.inner.layout()
+    }
+
+    fn input_assembler(&self) -> rendy_core::hal::pso::InputAssemblerDesc {
+        self.inner.input_assembler()
+    }
+
+    fn patch_control_points(&self) -> rendy_core::hal::pso::PatchSize {
+        self.inner.patch_control_points()
+    }
+
+    fn pipeline(&self) -> Pipeline {
+        depth_only(self.inner.pipeline())
+    }
+
+    fn dynamic_viewports(&self) -> bool {
+        self.inner.dynamic_viewports()
+    }
+
+    fn multisample(&self) -> super::simple::MultisampleDesc {
+        self.inner.multisample()
+    }
+
+    fn spec_constants(&self) -> rendy_shader::SpecConstantSet {
+        self.inner.spec_constants()
+    }
+
+    fn load_shader_set(&self, factory: &mut Factory<B>, aux: &T) -> rendy_shader::ShaderSet<B> {
+        let mut shader_set = self.inner.load_shader_set(factory, aux);
+        if !self.alpha_test {
+            // Depth is written without fragment shader.
+            shader_set.remove_stage(rendy_core::hal::pso::ShaderStageFlags::FRAGMENT, factory);
+        }
+        shader_set
+    }
+
+    fn build<'a>(
+        self,
+        ctx: &GraphContext<B>,
+        factory: &mut Factory<B>,
+        queue: QueueId,
+        aux: &T,
+        buffers: Vec<NodeBuffer>,
+        images: Vec<NodeImage>,
+        set_layouts: &[Handle<DescriptorSetLayout<B>>],
+    ) -> Result<Self::Pipeline, rendy_core::hal::pso::CreationError> {
+        self.inner
+            .build(ctx, factory, queue, aux, buffers, images, set_layouts)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use {
+        super::{depth_only, prepassed_depth_stencil},
+        crate::node::render::{Layout, Pipeline},
+        rendy_core::hal::pso::{
+            BlendState, ColorBlendDesc, ColorMask, Comparison, DepthStencilDesc, DepthTest,
+            InputAssemblerDesc, Primitive, Rasterizer,
+        },
+    };
+
+    #[test]
+    fn prepass_has_no_color_attachments() {
+        let pipeline = Pipeline {
+            layout: Layout {
+                sets: Vec::new(),
+                push_constants: Vec::new(),
+            },
+            vertices: Vec::new(),
+            colors: vec![ColorBlendDesc {
+                mask: ColorMask::ALL,
+                blend: Some(BlendState::ALPHA),
+            }],
+            depth_stencil: DepthStencilDesc {
+                depth: Some(DepthTest {
+                    fun: Comparison::LessEqual,
+                    write: false,
+                }),
+                depth_bounds: false,
+                stencil: None,
+            },
+            rasterizer: Rasterizer::FILL,
+            input_assembler_desc: InputAssemblerDesc {
+                primitive: Primitive::TriangleList,
+                with_adjacency: false,
+                restart_index: None,
+            },
+        };
+
+        let prepass = depth_only(pipeline);
+        assert!(prepass.colors.is_empty());
+        assert_eq!(
+            prepass.depth_stencil.depth,
+            Some(DepthTest {
+                fun: Comparison::Less,
+                write: true,
+            })
+        );
+
+        let main = prepassed_depth_stencil().depth.unwrap();
+        assert_eq!(main.fun, Comparison::Equal);
+        assert!(!main.write);
+    }
+}
@@ -40,6 +40,31 @@ pub struct Pipeline {
     pub input_assembler_desc: rendy_core::hal::pso::InputAssemblerDesc,
 }
 
+/// Multisample state of the pipeline.
+/// Used only when subpass attachments have more than one sample.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct MultisampleDesc {
+    /// Generate coverage mask from alpha of the first color output.
+    /// Gives anti-aliased edges for alpha-tested geometry like foliage.
+    pub alpha_to_coverage: bool,
+
+    /// Replace alpha of the first color output with one.
+    pub alpha_to_one: bool,
+
+    /// Mask of samples that can be written.
+    pub sample_mask: u64,
+}
+
+impl Default for MultisampleDesc {
+    fn default() -> Self {
+        MultisampleDesc {
+            alpha_to_coverage: false,
+            alpha_to_one: false,
+            sample_mask: !0,
+        }
+    }
+}
+
 /// Descriptor for simple graphics pipeline implementation.
 pub trait SimpleGraphicsPipelineDesc<B: Backend, T: ?Sized>: std::fmt::Debug {
     /// Simple graphics pipeline implementation
@@ -117,6 +142,13 @@ pub trait SimpleGraphicsPipelineDesc<B: Backend, T: ?Sized>: std::fmt::Debug {
         }
     }
 
+    /// Number of control points per patch for pipelines with tessellation shaders.
+    /// Input assembler of such pipelines draws `PatchList` of this size
+    /// unless `input_assembler` returns `PatchList` already.
+    fn patch_control_points(&self) -> rendy_core::hal::pso::PatchSize {
+        3
+    }
+
     /// Graphics pipelines
     fn pipeline(&self) -> Pipeline {
         Pipeline {
@@ -131,6 +163,28 @@ pub trait SimpleGraphicsPipelineDesc<B: Backend, T: ?Sized>: std::fmt::Debug {
         }
     }
 
+    /// Make viewport and scissor dynamic pipeline state.
+    /// Pipeline must set them in `draw`, e.g. with `RenderPassEncoder::set_viewport_array`
+    /// to render into multiple viewports selected by `gl_ViewportIndex`.
+    ///
+    /// By default both cover whole framebuffer and are set before `draw`.
+    fn dynamic_viewports(&self) -> bool {
+        false
+    }
+
+    /// Multisample state of the pipeline.
+    /// Ignored if subpass is not multisampled.
+    fn multisample(&self) -> MultisampleDesc {
+        MultisampleDesc::default()
+    }
+
+    /// Specialization constants for shaders of the pipeline.
+    /// They replace specialization the shader set was built with.
+    /// Use it to build pipeline variants from the same shaders.
+    fn spec_constants(&self) -> rendy_shader::SpecConstantSet {
+        rendy_shader::SpecConstantSet::default()
+    }
+
     /// Load shader set.
     /// This function should utilize the provided `ShaderSetBuilder` reflection class and return the compiled `ShaderSet`.
     ///
@@ -162,6 +216,11 @@ pub trait SimpleGraphicsPipeline<B: Backend, T: ?Sized>:
     /// This pipeline descriptor.
     type Desc: SimpleGraphicsPipelineDesc<B, T, Pipeline = Self>;
 
+    /// Record drawing commands into cached secondary command buffers.
+    /// Useful for static geometry that is drawn the same way every frame.
+    /// See `RenderGroup::secondary`.
+    const SECONDARY: bool = false;
+
     /// Make simple render group builder.
     fn builder() -> DescBuilder<B, T, SimpleRenderGroupDesc<Self::Desc>>
     where
@@ -203,6 +262,10 @@ pub struct SimpleRenderGroup<B: Backend, P> {
     set_layouts: Vec<Handle<DescriptorSetLayout<B>>>,
     pipeline_layout: B::PipelineLayout,
     graphics_pipeline: B::GraphicsPipeline,
+
+    /// Viewport and scissor covering whole framebuffer.
+    /// `None` if pipeline sets them itself.
+    viewport: Option<rendy_core::hal::pso::Rect>,
     pipeline: P,
 }
 
@@ -212,6 +275,11 @@ pub struct SimpleRenderGroupDesc<P: std::fmt::Debug> {
     inner: P,
 }
 
+/// Check if pipeline with `depth_stencil` state tests or writes stencil.
+fn uses_stencil(depth_stencil: Option<&rendy_core::hal::pso::DepthStencilDesc>) -> bool {
+    depth_stencil.map_or(false, |depth_stencil| depth_stencil.stencil.is_some())
+}
+
 impl<B, T, P> RenderGroupDesc<B, T> for SimpleRenderGroupDesc<P>
 where
     B: Backend,
@@ -234,6 +302,10 @@ where
         self.inner.depth_stencil().is_some()
     }
 
+    fn stencil(&self) -> bool {
+        uses_stencil(self.inner.depth_stencil().as_ref())
+    }
+
     fn build<'a>(
         self,
         ctx: &GraphContext<B>,
@@ -245,12 +317,57 @@ where
         subpass: rendy_core::hal::pass::Subpass<'_, B>,
         buffers: Vec<NodeBuffer>,
         images: Vec<NodeImage>,
+    ) -> Result<Box<dyn RenderGroup<B, T>>, rendy_core::hal::pso::CreationError> {
+        self.build_multisampled(
+            ctx,
+            factory,
+            queue,
+            aux,
+            framebuffer_width,
+            framebuffer_height,
+            1,
+            subpass,
+            buffers,
+            images,
+        )
+    }
+
+    fn build_multisampled<'a>(
+        self,
+        ctx: &GraphContext<B>,
+        factory: &mut Factory<B>,
+        queue: QueueId,
+        aux: &T,
+        framebuffer_width: u32,
+        framebuffer_height: u32,
+        samples: rendy_core::hal::image::NumSamples,
+        subpass: rendy_core::hal::pass::Subpass<'_, B>,
+        buffers: Vec<NodeBuffer>,
+        images: Vec<NodeImage>,
     ) -> Result<Box<dyn RenderGroup<B, T>>, rendy_core::hal::pso::CreationError> {
         log::trace!("Load shader sets for");
 
         let mut shader_set = self.inner.load_shader_set(factory, aux);
+        shader_set.specialize(self.inner.spec_constants());
+
+        let stages = shader_set.stages();
+        if let Err(e) = check_shader_features(stages, factory.features()) {
+            shader_set.dispose(factory);
+            return Err(rendy_core::hal::pso::CreationError::Shader(e));
+        }
 
-        let pipeline = self.inner.pipeline();
+        let mut pipeline = self.inner.pipeline();
+        pipeline.input_assembler_desc = tessellation_input_assembler(
+            pipeline.input_assembler_desc,
+            stages,
+            self.inner.patch_control_points(),
+        );
+        let dynamic_viewports = self.inner.dynamic_viewports();
+
+        if let Err(e) = check_vertex_rates(pipeline.vertices.iter().map(|&(_, _, rate)| rate)) {
+            shader_set.dispose(factory);
+            return Err(e);
+        }
 
         let set_layouts = pipeline
             .layout
@@ -287,13 +404,6 @@ where
             push_vertex_desc(elemets, stride, rate, &mut vertex_buffers, &mut attributes);
         }
 
-        let rect = rendy_core::hal::pso::Rect {
-            x: 0,
-            y: 0,
-            w: framebuffer_width as i16,
-            h: framebuffer_height as i16,
-        };
-
         let shaders = match shader_set.raw() {
             Err(e) => {
                 shader_set.dispose(factory);
@@ -316,13 +426,11 @@ where
                         targets: pipeline.colors.clone(),
                     },
                     depth_stencil: pipeline.depth_stencil,
-                    multisampling: None,
+                    multisampling: multisampling(samples, self.inner.multisample()),
+                    // Viewport and scissor are dynamic so that pipeline survives resize.
                     baked_states: rendy_core::hal::pso::BakedStates {
-                        viewport: Some(rendy_core::hal::pso::Viewport {
-                            rect,
-                            depth: 0.0..1.0,
-                        }),
-                        scissor: Some(rect),
+                        viewport: None,
+                        scissor: None,
                         blend_color: None,
                         depth_bounds: None,
                     },
@@ -331,7 +439,7 @@ where
                     flags: rendy_core::hal::pso::PipelineCreationFlags::empty(),
                     parent: rendy_core::hal::pso::BasePipeline::None,
                 }),
-                None,
+                Some(factory.pipeline_cache()),
             )
         }
         .remove(0)
@@ -354,6 +462,11 @@ where
             set_layouts,
             pipeline_layout,
             graphics_pipeline,
+            viewport: if dynamic_viewports {
+                None
+            } else {
+                Some(framebuffer_rect(framebuffer_width, framebuffer_height))
+            },
             pipeline,
         }))
     }
@@ -385,10 +498,38 @@ where
         aux: &T,
     ) {
         encoder.bind_graphics_pipeline(&self.graphics_pipeline);
+        if let Some(rect) = self.viewport {
+            unsafe {
+                encoder.set_viewports(
+                    0,
+                    &[rendy_core::hal::pso::Viewport {
+                        rect,
+                        depth: 0.0..1.0,
+                    }],
+                );
+                encoder.set_scissors(0, &[rect]);
+            }
+        }
         self.pipeline
             .draw(&self.pipeline_layout, encoder, index, aux);
     }
 
+    fn secondary(&self) -> bool {
+        P::SECONDARY
+    }
+
+    fn resize(
+        &mut self,
+        _factory: &Factory<B>,
+        framebuffer_width: u32,
+        framebuffer_height: u32,
+    ) -> bool {
+        if let Some(viewport) = &mut self.viewport {
+            *viewport = framebuffer_rect(framebuffer_width, framebuffer_height);
+        }
+        true
+    }
+
     fn dispose(self: Box<Self>, factory: &mut Factory<B>, aux: &T) {
         self.pipeline.dispose(factory, aux);
 
@@ -404,6 +545,124 @@ where
     }
 }
 
+/// Rect covering whole framebuffer.
+fn framebuffer_rect(width: u32, height: u32) -> rendy_core::hal::pso::Rect {
+    rendy_core::hal::pso::Rect {
+        x: 0,
+        y: 0,
+        w: width as i16,
+        h: height as i16,
+    }
+}
+
+/// Check that device supports shader stages of the pipeline.
+fn check_shader_features(
+    stages: rendy_core::hal::pso::ShaderStageFlags,
+    features: rendy_core::hal::Features,
+) -> Result<(), rendy_core::hal::device::ShaderError> {
+    use rendy_core::hal::{
+        device::ShaderError,
+        pso::{ShaderStageFlags, Stage},
+        Features,
+    };
+
+    if stages.contains(ShaderStageFlags::GEOMETRY) && !features.contains(Features::GEOMETRY_SHADER)
+    {
+        log::warn!("Pipeline has geometry shader, but device doesn't support them");
+        return Err(ShaderError::UnsupportedStage(Stage::Geometry));
+    }
+
+    if !features.contains(Features::TESSELLATION_SHADER) {
+        for &(flag, stage) in &[
+            (ShaderStageFlags::HULL, Stage::Hull),
+            (ShaderStageFlags::DOMAIN, Stage::Domain),
+        ] {
+            if stages.contains(flag) {
+                log::warn!("Pipeline has tessellation shader, but device doesn't support them");
+                return Err(ShaderError::UnsupportedStage(stage));
+            }
+        }
+    }
+
+    Ok(())
+}
+
+/// Input assembler of pipeline with shaders of `stages`.
+/// Tessellation shaders consume patches of `control_points`.
+fn tessellation_input_assembler(
+    desc: rendy_core::hal::pso::InputAssemblerDesc,
+    stages: rendy_core::hal::pso::ShaderStageFlags,
+    control_points: rendy_core::hal::pso::PatchSize,
+) -> rendy_core::hal::pso::InputAssemblerDesc {
+    use rendy_core::hal::pso::{Primitive, ShaderStageFlags};
+
+    let tessellated = stages.intersects(ShaderStageFlags::HULL | ShaderStageFlags::DOMAIN);
+    match desc.primitive {
+        Primitive::PatchList(_) => desc,
+        _ if tessellated => {
+            assert!(
+                control_points > 0,
+                "Patches must have at least one control point"
+            );
+            rendy_core::hal::pso::InputAssemblerDesc {
+                primitive: Primitive::PatchList(control_points),
+                with_adjacency: false,
+                restart_index: None,
+            }
+        }
+        _ => desc,
+    }
+}
+
+/// Check that vertex bindings use input rates supported by the device.
+///
+/// Per-instance bindings with divisor other than 1 require `VK_EXT_vertex_attribute_divisor`,
+/// which is not exposed by the backend.
+fn check_vertex_rates(
+    rates: impl IntoIterator<Item = rendy_core::hal::pso::VertexInputRate>,
+) -> Result<(), rendy_core::hal::pso::CreationError> {
+    use rendy_core::hal::pso::VertexInputRate;
+
+    for rate in rates {
+        match rate {
+            VertexInputRate::Vertex | VertexInputRate::Instance(1) => {}
+            VertexInputRate::Instance(divisor) => {
+                log::warn!(
+                    "Vertex binding has instance divisor {}, but only divisor 1 is supported",
+                    divisor
+                );
+                return Err(rendy_core::hal::pso::CreationError::Other);
+            }
+        }
+    }
+
+    Ok(())
+}
+
+/// Multisample state for pipeline rendering into subpass with `samples`.
+fn multisampling(
+    samples: rendy_core::hal::image::NumSamples,
+    desc: MultisampleDesc,
+) -> Option<rendy_core::hal::pso::Multisampling> {
+    if samples > 1 {
+        Some(rendy_core::hal::pso::Multisampling {
+            rasterization_samples: samples,
+            sample_shading: None,
+            sample_mask: desc.sample_mask,
+            alpha_coverage: desc.alpha_to_coverage,
+            alpha_to_one: desc.alpha_to_one,
+        })
+    } else {
+        if desc != MultisampleDesc::default() {
+            log::warn!(
+                "Multisample state {:?} is ignored as subpass is not multisampled",
+                desc
+            );
+        }
+        None
+    }
+}
+
 fn push_vertex_desc(
     elements: &[rendy_core::hal::pso::Element<rendy_core::hal::format::Format>],
     stride: rendy_core::hal::pso::ElemStride,
@@ -429,3 +688,145 @@ fn push_vertex_desc(
         location += 1;
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use {
+        super::{
+            check_shader_features, check_vertex_rates, multisampling, push_vertex_desc,
+            tessellation_input_assembler, MultisampleDesc,
+        },
+        rendy_core::hal::{
+            device::ShaderError,
+            format::Format,
+            pso::{
+                CreationError, Element, InputAssemblerDesc, Primitive, ShaderStageFlags, Stage,
+                VertexInputRate,
+            },
+            Features,
+        },
+    };
+
+    fn triangles() -> InputAssemblerDesc {
+        InputAssemblerDesc {
+            primitive: Primitive::TriangleList,
+            with_adjacency: false,
+            restart_index: None,
+        }
+    }
+
+    #[test]
+    fn tessellation_draws_patches() {
+        let stages = ShaderStageFlags::VERTEX
+            | ShaderStageFlags::HULL
+            | ShaderStageFlags::DOMAIN
+            | ShaderStageFlags::FRAGMENT;
+        let desc = tessellation_input_assembler(triangles(), stages, 4);
+        assert_eq!(desc.primitive, Primitive::PatchList(4));
+
+        // Explicit patch list is kept.
+        let explicit = InputAssemblerDesc {
+            primitive: Primitive::PatchList(16),
+            ..triangles()
+        };
+        let desc = tessellation_input_assembler(explicit, stages, 3);
+        assert_eq!(desc.primitive, Primitive::PatchList(16));
+
+        let stages = ShaderStageFlags::VERTEX | ShaderStageFlags::FRAGMENT;
+        let desc = tessellation_input_assembler(triangles(), stages, 3);
+        assert_eq!(desc.primitive, Primitive::TriangleList);
+    }
+
+    #[test]
+    fn unsupported_stages_are_rejected() {
+        let graphics = ShaderStageFlags::VERTEX | ShaderStageFlags::FRAGMENT;
+        assert!(check_shader_features(graphics, Features::empty()).is_ok());
+
+        match check_shader_features(graphics | ShaderStageFlags::GEOMETRY, Features::empty()) {
+            Err(ShaderError::UnsupportedStage(Stage::Geometry)) => {}
+            other => panic!("Unexpected result {:?}", other),
+        }
+        match check_shader_features(
+            graphics | ShaderStageFlags::DOMAIN,
+            Features::GEOMETRY_SHADER,
+        ) {
+            Err(ShaderError::UnsupportedStage(Stage::Domain)) => {}
+            other => panic!("Unexpected result {:?}", other),
+        }
+        assert!(check_shader_features(
+            graphics | ShaderStageFlags::HULL | ShaderStageFlags::DOMAIN,
+            Features::TESSELLATION_SHADER,
+        )
+        .is_ok());
+    }
+
+    #[test]
+    fn instance_binding_advances_per_instance() {
+        let position = Element {
+            format: Format::Rgb32Sfloat,
+            offset: 0,
+        };
+        // Per-instance transform as four columns.
+        let transform: Vec<_> = (0..4)
+            .map(|column| Element {
+                format: Format::Rgba32Sfloat,
+                offset: column * 16,
+            })
+            .collect();
+
+        let mut vertex_buffers = Vec::new();
+        let mut attributes = Vec::new();
+        push_vertex_desc(
+            &[position],
+            12,
+            VertexInputRate::Vertex,
+            &mut vertex_buffers,
+            &mut attributes,
+        );
+        push_vertex_desc(
+            &transform,
+            64,
+            VertexInputRate::Instance(1),
+            &mut vertex_buffers,
+            &mut attributes,
+        );
+
+        assert_eq!(vertex_buffers.len(), 2);
+        assert_eq!(vertex_buffers[0].rate, VertexInputRate::Vertex);
+        assert_eq!(vertex_buffers[1].binding, 1);
+        assert_eq!(vertex_buffers[1].rate, VertexInputRate::Instance(1));
+
+        let instanced: Vec<_> = attributes.iter().filter(|a| a.binding == 1).collect();
+        assert_eq!(instanced.len(), 4);
+        assert_eq!(instanced[0].location, 1);
+
+        assert!(check_vertex_rates(vertex_buffers.iter().map(|b| b.rate)).is_ok());
+        match check_vertex_rates(Some(VertexInputRate::Instance(4))) {
+            Err(CreationError::Other) => {}
+            other => panic!("Unexpected result {:?}", other),
+        }
+    }
+
+    #[test]
+    fn alpha_to_coverage_is_enabled_for_msaa() {
+        let desc = MultisampleDesc {
+            alpha_to_coverage: true,
+            sample_mask: 0b0111,
+            ..MultisampleDesc::default()
+        };
+
+        let state = multisampling(4, desc).unwrap();
+        assert_eq!(state.rasterization_samples, 4);
+        assert!(state.alpha_coverage);
+        assert!(!state.alpha_to_one);
+        assert_eq!(state.sample_mask, 0b0111);
+
+        assert_eq!(
+            multisampling(4, MultisampleDesc::default())
+                .unwrap()
+                .sample_mask,
+            !0
+        );
+        assert!(multisampling(1, desc).is_none());
+    }
+}
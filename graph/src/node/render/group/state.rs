@@ -0,0 +1,376 @@
+use {
+    super::simple::{MultisampleDesc, Pipeline, SimpleGraphicsPipelineDesc},
+    crate::{
+        command::QueueId,
+        factory::Factory,
+        graph::GraphContext,
+        node::{BufferAccess, ImageAccess, NodeBuffer, NodeImage},
+        resource::{DescriptorSetLayout, Handle},
+    },
+    rendy_core::hal::{
+        pso::{ColorBlendDesc, DepthStencilDesc, Rasterizer, StencilTest},
+        Backend,
+    },
+};
+
+/// Pipeline state that overrides state of simple graphics pipeline.
+/// State that is not set is taken from the pipeline.
+///
+/// Lets one pipeline desc build variants that differ only in fixed-function state,
+/// e.g. opaque and additive blended ones:
+///
+/// ```ignore
+/// let additive = WithPipelineState::new(
+///     desc.clone(),
+///     PipelineStateDesc::new().with_color_blend(ColorBlendDesc {
+///         mask: ColorMask::ALL,
+///         blend: Some(BlendState::ADD),
+///     }),
+/// );
+/// ```
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct PipelineStateDesc {
+    /// Blending of all color attachments.
+    pub color_blend: Option<ColorBlendDesc>,
+
+    /// Depth and stencil tests.
+    pub depth_stencil: Option<DepthStencilDesc>,
+
+    /// Stencil test and operations.
+    /// Replaces stencil state of `depth_stencil` or of the pipeline, keeping depth test.
+    /// Use `State::Dynamic` reference values to set them with `set_stencil_reference`.
+    pub stencil: Option<StencilTest>,
+
+    /// Rasterizer state including cull mode, front face and polygon mode.
+    pub rasterizer: Option<Rasterizer>,
+
+    /// Multisample state, used only when subpass is multisampled.
+    pub multisample: Option<MultisampleDesc>,
+}
+
+impl PipelineStateDesc {
+    /// Create desc that overrides nothing.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Override blending of all color attachments.
+    pub fn with_color_blend(mut self, color_blend: ColorBlendDesc) -> Self {
+        self.color_blend = Some(color_blend);
+        self
+    }
+
+    /// Override depth and stencil tests.
+    pub fn with_depth_stencil(mut self, depth_stencil: DepthStencilDesc) -> Self {
+        self.depth_stencil = Some(depth_stencil);
+        self
+    }
+
+    /// Override stencil test and operations.
+    /// Depth-stencil attachment of the subpass must have stencil aspect.
+    pub fn with_stencil(mut self, stencil: StencilTest) -> Self {
+        self.stencil = Some(stencil);
+        self
+    }
+
+    /// Override rasterizer state.
+    pub fn with_rasterizer(mut self, rasterizer: Rasterizer) -> Self {
+        self.rasterizer = Some(rasterizer);
+        self
+    }
+
+    /// Override multisample state.
+    pub fn with_multisample(mut self, multisample: MultisampleDesc) -> Self {
+        self.multisample = Some(multisample);
+        self
+    }
+
+    /// Enable or disable alpha-to-coverage.
+    /// Other multisample state is default unless overridden.
+    pub fn with_alpha_to_coverage(mut self, enabled: bool) -> Self {
+        self.multisample_mut().alpha_to_coverage = enabled;
+        self
+    }
+
+    /// Enable or disable alpha-to-one.
+    /// Other multisample state is default unless overridden.
+    pub fn with_alpha_to_one(mut self, enabled: bool) -> Self {
+        self.multisample_mut().alpha_to_one = enabled;
+        self
+    }
+
+    /// Set mask of samples that can be written.
+    /// Other multisample state is default unless overridden.
+    pub fn with_sample_mask(mut self, sample_mask: u64) -> Self {
+        self.multisample_mut().sample_mask = sample_mask;
+        self
+    }
+
+    fn multisample_mut(&mut self) -> &mut MultisampleDesc {
+        self.multisample
+            .get_or_insert_with(MultisampleDesc::default)
+    }
+
+    /// Apply overrides to the pipeline.
+    pub fn apply(&self, pipeline: Pipeline) -> Pipeline {
+        Pipeline {
+            colors: self.colors(pipeline.colors.clone()),
+            depth_stencil: self
+                .depth_stencil(Some(pipeline.depth_stencil))
+                .unwrap_or_default(),
+            rasterizer: self.rasterizer.unwrap_or(pipeline.rasterizer),
+            ..pipeline
+        }
+    }
+
+    fn depth_stencil(&self, depth_stencil: Option<DepthStencilDesc>) -> Option<DepthStencilDesc> {
+        let depth_stencil = self.depth_stencil.or(depth_stencil);
+        match self.stencil {
+            Some(stencil) => Some(DepthStencilDesc {
+                stencil: Some(stencil),
+                ..depth_stencil.unwrap_or_default()
+            }),
+            None => depth_stencil,
+        }
+    }
+
+    fn colors(&self, colors: Vec<ColorBlendDesc>) -> Vec<ColorBlendDesc> {
+        match self.color_blend {
+            Some(color_blend) => colors.iter().map(|_| color_blend).collect(),
+            None => colors,
+        }
+    }
+}
+
+/// Simple graphics pipeline with state overridden by `PipelineStateDesc`.
+///
+/// If depth or stencil state is overridden the subpass must have depth attachment.
+#[derive(Clone, Debug, Default)]
+pub struct WithPipelineState<P> {
+    inner: P,
+    state: PipelineStateDesc,
+}
+
+impl<P> WithPipelineState<P> {
+    /// Wrap pipeline desc to override its state.
+    pub fn new(inner: P, state: PipelineStateDesc) -> Self {
+        WithPipelineState { inner, state }
+    }
+
+    /// Get wrapped pipeline desc.
+    pub fn inner(&self) -> &P {
+        &self.inner
+    }
+
+    /// Get state overrides.
+    pub fn state(&self) -> &PipelineStateDesc {
+        &self.state
+    }
+}
+
+impl<B, T, P> SimpleGraphicsPipelineDesc<B, T> for WithPipelineState<P>
+where
+    B: Backend,
+    T: ?Sized,
+    P: SimpleGraphicsPipelineDesc<B, T>,
+{
+    type Pipeline = P::Pipeline;
+
+    fn buffers(&self) -> Vec<BufferAccess> {
+        self.inner.buffers()
+    }
+
+    fn images(&self) -> Vec<ImageAccess> {
+        self.inner.images()
+    }
+
+    fn colors(&self) -> Vec<ColorBlendDesc> {
+        self.state.colors(self.inner.colors())
+    }
+
+    fn depth_stencil(&self) -> Option<DepthStencilDesc> {
+        self.state.depth_stencil(self.inner.depth_stencil())
+    }
+
+    fn rasterizer(&self) -> Rasterizer {
+        self.state
+            .rasterizer
+            .unwrap_or_else(|| self.inner.rasterizer())
+    }
+
+    fn vertices(
+        &self,
+    ) -> Vec<(
+        Vec<rendy_core::hal::pso::Element<rendy_core::hal::format::Format>>,
+        rendy_core::hal::pso::ElemStride,
+        rendy_core::hal::pso::VertexInputRate,
+    )> {
+        self.inner.vertices()
+    }
+
+    fn layout(&self) -> super::simple::Layout {
+        self.inner.layout()
+    }
+
+    fn input_assembler(&self) -> rendy_core::hal::pso::InputAssemblerDesc {
+        self.inner.input_assembler()
+    }
+
+    fn patch_control_points(&self) -> rendy_core::hal::pso::PatchSize {
+        self.inner.patch_control_points()
+    }
+
+    fn pipeline(&self) -> Pipeline {
+        self.state.apply(self.inner.pipeline())
+    }
+
+    fn dynamic_viewports(&self) -> bool {
+        self.inner.dynamic_viewports()
+    }
+
+    fn multisample(&self) -> MultisampleDesc {
+        self.state
+            .multisample
+            .unwrap_or_else(|| self.inner.multisample())
+    }
+
+    fn spec_constants(&self) -> rendy_shader::SpecConstantSet {
+        self.inner.spec_constants()
+    }
+
+    fn load_shader_set(&self, factory: &mut Factory<B>, aux: &T) -> rendy_shader::ShaderSet<B> {
+        self.inner.load_shader_set(factory, aux)
+    }
+
+    fn build<'a>(
+        self,
+        ctx: &GraphContext<B>,
+        factory: &mut Factory<B>,
+        queue: QueueId,
+        aux: &T,
+        buffers: Vec<NodeBuffer>,
+        images: Vec<NodeImage>,
+        set_layouts: &[Handle<DescriptorSetLayout<B>>],
+    ) -> Result<Self::Pipeline, rendy_core::hal::pso::CreationError> {
+        self.inner
+            .build(ctx, factory, queue, aux, buffers, images, set_layouts)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use {
+        super::PipelineStateDesc,
+        crate::node::render::{Layout, Pipeline},
+        rendy_core::hal::pso::{
+            BlendState, ColorBlendDesc, ColorMask, Comparison, DepthStencilDesc, DepthTest, Face,
+            InputAssemblerDesc, Primitive, Rasterizer, Sided, State, StencilFace, StencilOp,
+            StencilTest,
+        },
+    };
+
+    fn pipeline() -> Pipeline {
+        Pipeline {
+            layout: Layout {
+                sets: Vec::new(),
+                push_constants: Vec::new(),
+            },
+            vertices: Vec::new(),
+            colors: vec![
+                ColorBlendDesc {
+                    mask: ColorMask::ALL,
+                    blend: Some(BlendState::ALPHA),
+                };
+                2
+            ],
+            depth_stencil: DepthStencilDesc::default(),
+            rasterizer: Rasterizer::FILL,
+            input_assembler_desc: InputAssemblerDesc {
+                primitive: Primitive::TriangleList,
+                with_adjacency: false,
+                restart_index: None,
+            },
+        }
+    }
+
+    #[test]
+    fn variants_override_blend_state() {
+        let opaque = PipelineStateDesc::new().with_color_blend(ColorBlendDesc {
+            mask: ColorMask::ALL,
+            blend: None,
+        });
+        let additive = PipelineStateDesc::new()
+            .with_color_blend(ColorBlendDesc {
+                mask: ColorMask::ALL,
+                blend: Some(BlendState::ADD),
+            })
+            .with_rasterizer(Rasterizer {
+                cull_face: Face::BACK,
+                ..Rasterizer::FILL
+            });
+
+        let opaque = opaque.apply(pipeline());
+        let additive = additive.apply(pipeline());
+
+        assert!(opaque.colors.iter().all(|color| color.blend.is_none()));
+        assert!(additive
+            .colors
+            .iter()
+            .all(|color| color.blend == Some(BlendState::ADD)));
+        assert_eq!(opaque.colors.len(), 2);
+        assert_eq!(additive.colors.len(), 2);
+
+        // Only overridden state changes.
+        assert_eq!(opaque.rasterizer, Rasterizer::FILL);
+        assert_eq!(additive.rasterizer.cull_face, Face::BACK);
+        assert_eq!(opaque.depth_stencil, DepthStencilDesc::default());
+    }
+
+    #[test]
+    fn stencil_is_written_and_tested() {
+        let stencil = |fun, op_pass| StencilTest {
+            faces: Sided::new(StencilFace {
+                fun,
+                op_fail: StencilOp::Keep,
+                op_depth_fail: StencilOp::Keep,
+                op_pass,
+            }),
+            read_masks: State::Static(Sided::new(!0)),
+            write_masks: State::Static(Sided::new(!0)),
+            reference_values: State::Dynamic,
+        };
+
+        // Portal node marks its pixels, scene node draws only where they are marked.
+        let mark = PipelineStateDesc::new()
+            .with_stencil(stencil(Comparison::Always, StencilOp::Replace))
+            .apply(pipeline());
+        let masked = PipelineStateDesc::new()
+            .with_depth_stencil(DepthStencilDesc {
+                depth: Some(DepthTest {
+                    fun: Comparison::Less,
+                    write: true,
+                }),
+                depth_bounds: false,
+                stencil: None,
+            })
+            .with_stencil(stencil(Comparison::Equal, StencilOp::Keep))
+            .apply(pipeline());
+
+        let mark = mark.depth_stencil.stencil.unwrap();
+        assert_eq!(mark.faces.front.op_pass, StencilOp::Replace);
+        assert_eq!(mark.faces.back.fun, Comparison::Always);
+
+        // Depth test is kept, stencil is added on top of it.
+        assert_eq!(
+            masked.depth_stencil.depth,
+            Some(DepthTest {
+                fun: Comparison::Less,
+                write: true,
+            })
+        );
+        let masked = masked.depth_stencil.stencil.unwrap();
+        assert_eq!(masked.faces.front.fun, Comparison::Equal);
+        assert_eq!(masked.faces.front.op_pass, StencilOp::Keep);
+        assert_eq!(masked.reference_values, State::Dynamic);
+    }
+}
@@ -3,10 +3,13 @@
 //! Will replace render pass node when polished.
 //!
 
+mod batch;
+mod bundle;
+mod descriptors;
 mod group;
 mod pass;
 
-pub use self::{group::*, pass::*};
+pub use self::{batch::*, bundle::*, descriptors::*, group::*, pass::*};
 
 /// Result of draw preparation.
 #[derive(Clone, Copy, Debug)]
@@ -20,7 +23,7 @@ pub enum PrepareResult {
 }
 
 impl PrepareResult {
-    fn force_record(&self) -> bool {
+    pub(crate) fn force_record(&self) -> bool {
         match self {
             PrepareResult::DrawRecord => true,
             PrepareResult::DrawReuse => false,
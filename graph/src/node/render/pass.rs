@@ -1,12 +1,13 @@
 use {
     crate::{
         command::{
-            CommandBuffer, CommandPool, ExecutableState, Families, Family, FamilyId, Fence,
-            Graphics, IndividualReset, MultiShot, NoSimultaneousUse, PendingState, Queue, QueueId,
-            SecondaryLevel, SimultaneousUse, Submission, Submit,
+            CommandBuffer, CommandPool, Encoder, ExecutableState, Families, Family, FamilyId,
+            Fence, Graphics, IndividualReset, MultiShot, NoSimultaneousUse, PendingState,
+            PrimaryLevel, Queue, QueueId, RenderPassContinue, RenderPassInlineEncoder,
+            RenderPassSecondaryEncoder, SecondaryLevel, SimultaneousUse, Submission, Submit,
         },
         core::{
-            hal::{device::Device as _, image::Layout, Backend},
+            hal::{adapter::PhysicalDevice as _, device::Device as _, image::Layout, Backend},
             uses_pipeline_barriers,
         },
         factory::Factory,
@@ -15,11 +16,15 @@ use {
             Frames,
         },
         graph::GraphContext,
+        memory::Data,
         node::{
-            gfx_acquire_barriers, gfx_release_barriers,
+            check_frames_in_flight, clamp_image_count, default_image_count, free_cirque,
+            gfx_acquire_barriers, gfx_release_barriers, present_result,
             render::group::{RenderGroup, RenderGroupBuilder},
-            BufferAccess, DynNode, ImageAccess, NodeBuffer, NodeBuildError, NodeBuilder, NodeImage,
+            timed_submits, BufferAccess, DynNode, ImageAccess, NodeBuffer, NodeBuildError,
+            NodeBuilder, NodeClear, NodeImage, NodeTimestamps, PresentResult,
         },
+        resource::{Escape, Image, ImageInfo},
         wsi::{Surface, Target},
         BufferId, ImageId, NodeId,
     },
@@ -196,11 +201,18 @@ where
 /// Builder for render-pass node.
 pub struct RenderPassNodeBuilder<B: Backend, T: ?Sized> {
     subpasses: Vec<SubpassBuilder<B, T>>,
+    subresources: Vec<(ImageId, rendy_core::hal::image::SubresourceRange)>,
     surface: Option<(
         Surface<B>,
         rendy_core::hal::window::Extent2D,
         Option<rendy_core::hal::command::ClearValue>,
     )>,
+    samples: rendy_core::hal::image::NumSamples,
+    layers: Option<u16>,
+    dependencies: Vec<rendy_core::hal::pass::SubpassDependency>,
+    clears: Vec<(ImageId, NodeClear)>,
+    load_ops: Vec<(ImageId, rendy_core::hal::pass::AttachmentLoadOp)>,
+    store_ops: Vec<(ImageId, rendy_core::hal::pass::AttachmentStoreOp)>,
 }
 
 impl<B, T> std::fmt::Debug for RenderPassNodeBuilder<B, T>
@@ -211,7 +223,14 @@ where
     fn fmt(&self, fmt: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         fmt.debug_struct("RenderPassNodeBuilder")
             .field("subpasses", &self.subpasses)
+            .field("subresources", &self.subresources)
             .field("surface", &self.surface)
+            .field("samples", &self.samples)
+            .field("layers", &self.layers)
+            .field("dependencies", &self.dependencies)
+            .field("clears", &self.clears)
+            .field("load_ops", &self.load_ops)
+            .field("store_ops", &self.store_ops)
             .finish()
     }
 }
@@ -224,7 +243,14 @@ where
     fn default() -> Self {
         RenderPassNodeBuilder {
             subpasses: Vec::default(),
+            subresources: Vec::default(),
             surface: None,
+            samples: 1,
+            layers: None,
+            dependencies: Vec::default(),
+            clears: Vec::default(),
+            load_ops: Vec::default(),
+            store_ops: Vec::default(),
         }
     }
 }
@@ -240,17 +266,49 @@ where
     }
 
     /// Add sub-pass to the render-pass.
+    /// Sub-passes are executed in order they are added.
+    /// Attachments written by a sub-pass can be read by following ones as input attachments,
+    /// dependencies between them are generated.
     pub fn add_subpass(&mut self, subpass: SubpassBuilder<B, T>) -> &mut Self {
         self.subpasses.push(subpass);
         self
     }
 
     /// Add sub-pass to the render-pass.
+    /// Sub-passes are executed in order they are added.
+    /// Attachments written by a sub-pass can be read by following ones as input attachments,
+    /// dependencies between them are generated.
     pub fn with_subpass(mut self, subpass: SubpassBuilder<B, T>) -> Self {
         self.add_subpass(subpass);
         self
     }
 
+    /// Restrict image used by the render pass to the subresource.
+    /// Attachment view is created for the first level of the `range`.
+    /// Graph synchronizes only the `range`,
+    /// so render passes can render into distinct levels or layers of the same image.
+    pub fn add_image_subresource(
+        &mut self,
+        image: ImageId,
+        range: rendy_core::hal::image::SubresourceRange,
+    ) -> &mut Self {
+        self.subresources.push((image, range));
+        self
+    }
+
+    /// Restrict image used by the render pass to the subresource.
+    /// Attachment view is created for the first level of the `range`.
+    /// Graph synchronizes only the `range`,
+    /// so render passes can render into distinct levels or layers of the same image.
+    pub fn with_image_subresource(
+        mut self,
+        image: ImageId,
+        range: rendy_core::hal::image::SubresourceRange,
+    ) -> Self {
+        self.add_image_subresource(image, range);
+        self
+    }
+
     /// Add surface to the render pass.
     pub fn add_surface(
         &mut self,
@@ -276,6 +334,167 @@ where
         self.add_surface(surface, suggested_extent, clear);
         self
     }
+
+    /// Set number of samples to render color attachments with.
+    /// When greater than one, subpasses render into internal multisampled attachments
+    /// which are resolved into declared color attachments at the end of the render pass.
+    /// Depth-stencil attachment must have the same number of samples.
+    pub fn set_samples(&mut self, samples: rendy_core::hal::image::NumSamples) -> &mut Self {
+        assert!(
+            samples.is_power_of_two(),
+            "Number of samples must be power of two"
+        );
+        self.samples = samples;
+        self
+    }
+
+    /// Set number of samples to render color attachments with.
+    /// When greater than one, subpasses render into internal multisampled attachments
+    /// which are resolved into declared color attachments at the end of the render pass.
+    /// Depth-stencil attachment must have the same number of samples.
+    pub fn with_samples(mut self, samples: rendy_core::hal::image::NumSamples) -> Self {
+        self.set_samples(samples);
+        self
+    }
+
+    /// Render into `count` layers of image attachments at once.
+    /// Attachments are bound as 2D array views of `count` layers
+    /// starting from the first layer of the image range,
+    /// and shaders select the layer by writing `gl_Layer` in geometry shader.
+    /// Building the node fails with `NodeBuildError::Layers`
+    /// if device can't create framebuffer with that many layers,
+    /// an image attachment has fewer layers or the render pass renders to a surface.
+    /// Multiview render passes are not supported.
+    pub fn set_layers(&mut self, count: u16) -> &mut Self {
+        assert_ne!(count, 0, "Number of layers must be positive");
+        self.layers = Some(count);
+        self
+    }
+
+    /// Render into `count` layers of image attachments at once.
+    /// Attachments are bound as 2D array views of `count` layers
+    /// starting from the first layer of the image range,
+    /// and shaders select the layer by writing `gl_Layer` in geometry shader.
+    /// Building the node fails with `NodeBuildError::Layers`
+    /// if device can't create framebuffer with that many layers,
+    /// an image attachment has fewer layers or the render pass renders to a surface.
+    /// Multiview render passes are not supported.
+    pub fn with_layers(mut self, count: u16) -> Self {
+        self.set_layers(count);
+        self
+    }
+
+    /// Add dependency to the render pass in addition to generated ones.
+    /// This allows to tune stages, accesses and flags,
+    /// e.g. `Dependencies::BY_REGION` lets tile-based GPUs keep attachments in tile memory.
+    /// Building the node fails with `NodeBuildError::SubpassDependency`
+    /// if the dependency references subpass that doesn't exist.
+    pub fn add_subpass_dependency(
+        &mut self,
+        dependency: rendy_core::hal::pass::SubpassDependency,
+    ) -> &mut Self {
+        self.dependencies.push(dependency);
+        self
+    }
+
+    /// Add dependency to the render pass in addition to generated ones.
+    /// This allows to tune stages, accesses and flags,
+    /// e.g. `Dependencies::BY_REGION` lets tile-based GPUs keep attachments in tile memory.
+    /// Building the node fails with `NodeBuildError::SubpassDependency`
+    /// if the dependency references subpass that doesn't exist.
+    pub fn with_subpass_dependency(
+        mut self,
+        dependency: rendy_core::hal::pass::SubpassDependency,
+    ) -> Self {
+        self.add_subpass_dependency(dependency);
+        self
+    }
+
+    /// Clear attachment image when render pass begins.
+    /// Unlike clear value the image is created with
+    /// this is applied even if preceding nodes rendered to the image.
+    /// `NodeClear::Depth` and `NodeClear::Stencil` clear single aspect
+    /// of depth-stencil attachment and load the other.
+    ///
+    /// # Panics
+    ///
+    /// Building the node panics if the image is not an attachment of any subpass.
+    pub fn add_image_clear(&mut self, image: ImageId, clear: NodeClear) -> &mut Self {
+        self.clears.push((image, clear));
+        self
+    }
+
+    /// Clear attachment image when render pass begins.
+    /// See `add_image_clear`.
+    pub fn with_image_clear(mut self, image: ImageId, clear: NodeClear) -> Self {
+        self.add_image_clear(image, clear);
+        self
+    }
+
+    /// Override load operation inferred for attachment image.
+    /// `AttachmentLoadOp::DontCare` discards content left by preceding nodes,
+    /// `AttachmentLoadOp::Load` keeps it even if image would be cleared.
+    /// Applies to all aspects of the attachment.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `op` is `AttachmentLoadOp::Clear`, use `add_image_clear` to clear image.
+    /// Building the node panics if the image is not an attachment of any subpass.
+    pub fn set_image_load_op(
+        &mut self,
+        image: ImageId,
+        op: rendy_core::hal::pass::AttachmentLoadOp,
+    ) -> &mut Self {
+        assert_ne!(
+            op,
+            rendy_core::hal::pass::AttachmentLoadOp::Clear,
+            "Use `add_image_clear` to clear attachment"
+        );
+        self.load_ops.retain(|&(other, _)| other != image);
+        self.load_ops.push((image, op));
+        self
+    }
+
+    /// Override load operation inferred for attachment image.
+    /// See `set_image_load_op`.
+    pub fn with_image_load_op(
+        mut self,
+        image: ImageId,
+        op: rendy_core::hal::pass::AttachmentLoadOp,
+    ) -> Self {
+        self.set_image_load_op(image, op);
+        self
+    }
+
+    /// Override store operation of attachment image.
+    /// `AttachmentStoreOp::DontCare` lets tiled GPUs skip writing transient attachments,
+    /// e.g. depth buffer not used after the pass, back to memory.
+    /// Graph warns if image stored with `DontCare` is used by later nodes.
+    /// Applies to all aspects of the attachment.
+    ///
+    /// # Panics
+    ///
+    /// Building the node panics if the image is not an attachment of any subpass.
+    pub fn set_image_store_op(
+        &mut self,
+        image: ImageId,
+        op: rendy_core::hal::pass::AttachmentStoreOp,
+    ) -> &mut Self {
+        self.store_ops.retain(|&(other, _)| other != image);
+        self.store_ops.push((image, op));
+        self
+    }
+
+    /// Override store operation of attachment image.
+    /// See `set_image_store_op`.
+    pub fn with_image_store_op(
+        mut self,
+        image: ImageId,
+        op: rendy_core::hal::pass::AttachmentStoreOp,
+    ) -> Self {
+        self.set_image_store_op(image, op);
+        self
+    }
 }
 
 impl<B, T> NodeBuilder<B, T> for RenderPassNodeBuilder<B, T>
@@ -319,22 +538,20 @@ where
         let mut attachments = HashMap::new();
         let mut images = HashMap::new();
 
+        // Attachments used differently by subpasses, e.g. written as color
+        // and then read as input attachment, are kept in `General` layout.
         for subpass in &self.subpasses {
             for &id in subpass.inputs.iter().filter_map(|e| e.as_ref().left()) {
-                let entry = attachments.entry(id).or_insert(ImageAccess {
-                    layout: Layout::ShaderReadOnlyOptimal,
-                    ..empty
-                });
+                let entry = attachments.entry(id).or_insert(empty);
+                entry.layout = common_layout(entry.layout, Layout::ShaderReadOnlyOptimal);
                 entry.access |= rendy_core::hal::image::Access::INPUT_ATTACHMENT_READ;
                 entry.usage |= rendy_core::hal::image::Usage::INPUT_ATTACHMENT;
                 entry.stages |= rendy_core::hal::pso::PipelineStage::FRAGMENT_SHADER;
             }
 
             for &id in subpass.colors.iter().filter_map(|e| e.as_ref().left()) {
-                let entry = attachments.entry(id).or_insert(ImageAccess {
-                    layout: Layout::ColorAttachmentOptimal,
-                    ..empty
-                });
+                let entry = attachments.entry(id).or_insert(empty);
+                entry.layout = common_layout(entry.layout, Layout::ColorAttachmentOptimal);
                 entry.access |= rendy_core::hal::image::Access::COLOR_ATTACHMENT_READ
                     | rendy_core::hal::image::Access::COLOR_ATTACHMENT_WRITE;
                 entry.usage |= rendy_core::hal::image::Usage::COLOR_ATTACHMENT;
@@ -342,10 +559,8 @@ where
             }
 
             if let Some(id) = subpass.depth_stencil.and_then(Either::left) {
-                let entry = attachments.entry(id).or_insert(ImageAccess {
-                    layout: Layout::DepthStencilAttachmentOptimal,
-                    ..empty
-                });
+                let entry = attachments.entry(id).or_insert(empty);
+                entry.layout = common_layout(entry.layout, Layout::DepthStencilAttachmentOptimal);
                 entry.access |= rendy_core::hal::image::Access::DEPTH_STENCIL_ATTACHMENT_READ
                     | rendy_core::hal::image::Access::DEPTH_STENCIL_ATTACHMENT_WRITE;
                 entry.usage |= rendy_core::hal::image::Usage::DEPTH_STENCIL_ATTACHMENT;
@@ -371,6 +586,22 @@ where
         attachments.into_iter().chain(images.into_iter()).collect()
     }
 
+    fn image_subresources(&self) -> Vec<(ImageId, rendy_core::hal::image::SubresourceRange)> {
+        self.subresources.clone()
+    }
+
+    fn image_clears(&self) -> Vec<(ImageId, NodeClear)> {
+        self.clears.clone()
+    }
+
+    fn discarded_images(&self) -> Vec<ImageId> {
+        self.store_ops
+            .iter()
+            .filter(|&&(_, op)| op == rendy_core::hal::pass::AttachmentStoreOp::DontCare)
+            .map(|&(id, _)| id)
+            .collect()
+    }
+
     fn dependencies(&self) -> Vec<NodeId> {
         let mut dependencies: Vec<_> = self
             .subpasses
@@ -400,6 +631,10 @@ where
     ) -> Result<Box<dyn DynNode<B, T>>, NodeBuildError> {
         use rendy_core::hal::window::PresentMode;
 
+        let subpass_attachments: Vec<_> =
+            self.subpasses.iter().map(SubpassAttachments::new).collect();
+        let dependencies = subpass_dependencies(&subpass_attachments, &self.dependencies)?;
+
         let mut surface_color_usage = false;
         let mut surface_depth_usage = false;
 
@@ -446,6 +681,28 @@ where
         attachments.sort();
         attachments.dedup();
 
+        // Images sampled by render groups can't be attachments,
+        // so cleared image that is not an attachment would be left as is.
+        for &(id, _) in &self.clears {
+            assert!(
+                attachments.contains(&Either::Left(id)),
+                "Image {:?} cleared by render pass must be an attachment",
+                id
+            );
+        }
+        for id in self
+            .load_ops
+            .iter()
+            .map(|&(id, _)| id)
+            .chain(self.store_ops.iter().map(|&(id, _)| id))
+        {
+            assert!(
+                attachments.contains(&Either::Left(id)),
+                "Image {:?} with overridden attachment operations must be an attachment",
+                id
+            );
+        }
+
         let find_attachment_node_image = |id: ImageId| -> &NodeImage {
             images
                 .iter()
@@ -457,11 +714,25 @@ where
         let mut framebuffer_height = u32::max_value();
         let mut framebuffer_layers = u16::max_value();
 
+        let layers = self.layers;
+        if let Some(count) = layers {
+            let max_layers = factory.physical().limits().max_framebuffer_layers;
+            if count as usize > max_layers {
+                log::warn!(
+                    "Render pass requested {} layers while device supports up to {}",
+                    count,
+                    max_layers
+                );
+                return Err(NodeBuildError::Layers(count));
+            }
+        }
+
         let mut node_target = None;
+        let mut attachment_views = Vec::new();
 
         log::trace!("Configure attachments");
 
-        let views: Vec<_> = attachments
+        let mut views: Vec<_> = attachments
             .iter()
             .map(|&attachment| -> Result<Vec<_>, NodeBuildError> {
                 match attachment {
@@ -470,35 +741,44 @@ where
 
                         let node_image = find_attachment_node_image(image_id);
                         let image = ctx.get_image(image_id).expect("Image does not exist");
-                        let extent = image.kind().extent();
+                        let level = node_image.range.levels.start;
+                        let extent = image.kind().level_extent(level);
                         framebuffer_width = min(framebuffer_width, extent.width);
                         framebuffer_height = min(framebuffer_height, extent.height);
+                        let (view_kind, view_layers) = match attachment_layers(&node_image.range.layers, layers) {
+                            Some(view) => view,
+                            None => {
+                                log::warn!("Image {:?} has {:?} layers but render pass renders into {:?}", image_id, node_image.range.layers, layers);
+                                return Err(NodeBuildError::Layers(layers.unwrap_or(1)));
+                            }
+                        };
                         framebuffer_layers = min(
                             framebuffer_layers,
-                            node_image.range.layers.end - node_image.range.layers.start,
+                            view_layers.end - view_layers.start,
                         );
-                        Ok(vec![unsafe {
-                            factory
-                                .device()
-                                .create_image_view(
-                                    image.raw(),
-                                    rendy_core::hal::image::ViewKind::D2,
-                                    image.format(),
-                                    rendy_core::hal::format::Swizzle::NO,
-                                    rendy_core::hal::image::SubresourceRange {
-                                        // NOTE: Framebuffer must always be created with only one mip level. If image contains multiple levels,
-                                        // only the first one is bound as an attachment.
-                                        // TODO: Allow customizing this behaviour to choose which level to bind.
-                                        levels: 0 .. 1,
-                                        ..node_image.range.clone()
-                                    }
-                                )
-                                .map_err(NodeBuildError::View)?
-                            }])
+                        let view = AttachmentView {
+                            id: image_id,
+                            kind: view_kind,
+                            range: rendy_core::hal::image::SubresourceRange {
+                                // NOTE: Framebuffer must always be created with only one mip level. If image range contains multiple levels,
+                                // only the first one is bound as an attachment.
+                                levels: level .. level + 1,
+                                layers: view_layers,
+                                ..node_image.range.clone()
+                            },
+                        };
+                        let raw = unsafe { create_attachment_view(ctx, factory, &view)? };
+                        attachment_views.push(view);
+                        Ok(vec![raw])
                     },
                     Either::Right(RenderPassSurface) => {
                         log::trace!("Surface attachment");
 
+                        if let Some(count) = layers.filter(|&count| count > 1) {
+                            log::warn!("Surface can't be rendered into {} layers", count);
+                            return Err(NodeBuildError::Layers(count));
+                        }
+
                         let surface = surface.take().expect("Render pass should be configured with Surface instance if at least one subpass uses surface attachment");
                         let surface_extent = unsafe {
                             surface.extent(factory.physical()).unwrap_or(suggested_extent.expect("Must be set with surface"))
@@ -521,8 +801,11 @@ where
                             _ => panic!("No known present modes found"),
                         };
 
-                        let img_count_caps = caps.image_count;
-                        let image_count = 3.min(*img_count_caps.end()).max(*img_count_caps.start());
+                        let image_count = clamp_image_count(
+                            default_image_count(*caps.image_count.start(), ctx.frames_in_flight),
+                            &caps.image_count,
+                        );
+                        log::debug!("Swapchain image count: {}", image_count);
 
                         let target = factory
                             .create_target(
@@ -534,6 +817,14 @@ where
                             )
                             .map_err(NodeBuildError::Swapchain)?;
 
+                        // Driver may create more images than requested.
+                        if let Err(err) = check_frames_in_flight(ctx.frames_in_flight, target.backbuffer().len() as u32) {
+                            unsafe {
+                                factory.destroy_surface(factory.destroy_target(target));
+                            }
+                            return Err(err);
+                        }
+
                         framebuffer_width = min(framebuffer_width, target.extent().width);
                         framebuffer_height = min(framebuffer_height, target.extent().height);
                         framebuffer_layers = min(
@@ -541,22 +832,7 @@ where
                             target.backbuffer()[0].layers(),
                         );
 
-                        let views = target.backbuffer().iter().map(|image| unsafe {
-                            factory
-                                .device()
-                                .create_image_view(
-                                    image.raw(),
-                                    rendy_core::hal::image::ViewKind::D2,
-                                    image.format(),
-                                    rendy_core::hal::format::Swizzle::NO,
-                                    rendy_core::hal::image::SubresourceRange {
-                                        aspects: image.format().surface_desc().aspects,
-                                        levels: 0 .. 1,
-                                        layers: 0 .. 1,
-                                    },
-                                )
-                                .map_err(NodeBuildError::View)
-                        }).collect::<Result<Vec<_>, NodeBuildError>>()?;
+                        let views = unsafe { create_target_views(factory, &target)? };
 
                         node_target = Some(target);
                         Ok(views)
@@ -565,19 +841,89 @@ where
             }).collect::<Result<Vec<_>, _>>()?
             .into_iter().flatten().collect();
 
+        let attachment_format = |attachment: Attachment| match attachment {
+            Either::Left(image_id) => ctx
+                .get_image(image_id)
+                .expect("Image does not exist")
+                .format(),
+            Either::Right(RenderPassSurface) => node_target
+                .as_ref()
+                .expect("Expect target created")
+                .backbuffer()[0]
+                .format(),
+        };
+
+        let attachment_clear = |attachment: Attachment| match attachment {
+            Either::Left(image_id) => find_attachment_node_image(image_id).clear,
+            Either::Right(RenderPassSurface) => surface_clear,
+        };
+
+        let attachment_clear_aspects = |attachment: Attachment| match attachment {
+            Either::Left(image_id) => find_attachment_node_image(image_id).clear_aspects,
+            Either::Right(RenderPassSurface) if surface_clear.is_some() => {
+                rendy_core::hal::format::Aspects::COLOR
+            }
+            Either::Right(RenderPassSurface) => rendy_core::hal::format::Aspects::empty(),
+        };
+
+        let samples = self.samples;
+        let resolved = if samples > 1 {
+            resolved_colors(self.subpasses.iter().flat_map(|subpass| &subpass.colors))
+        } else {
+            Vec::new()
+        };
+
+        let attachment_samples = attachments
+            .iter()
+            .filter(|attachment| !resolved.contains(attachment))
+            .map(|&attachment| match attachment {
+                Either::Left(image_id) => ctx
+                    .get_image(image_id)
+                    .expect("Image does not exist")
+                    .kind()
+                    .num_samples(),
+                Either::Right(RenderPassSurface) => 1,
+            });
+        if let Err(err) = check_multisampled(samples, framebuffer_layers, attachment_samples) {
+            unsafe {
+                for view in views {
+                    factory.device().destroy_image_view(view);
+                }
+                if let Some(target) = node_target {
+                    factory.destroy_surface(factory.destroy_target(target));
+                }
+            }
+            return Err(err);
+        }
+
+        log::trace!("Create {} multisampled attachments", resolved.len());
+
+        let msaa_formats: Vec<_> = resolved
+            .iter()
+            .map(|&attachment| attachment_format(attachment))
+            .collect();
+        let (msaa_images, msaa_views) = unsafe {
+            create_msaa_attachments(
+                factory,
+                &msaa_formats,
+                framebuffer_width,
+                framebuffer_height,
+                samples,
+            )?
+        };
+
         log::trace!("Configure render pass instance");
 
         let render_pass: B::RenderPass = {
             let pass_attachments: Vec<_> = attachments
                 .iter()
                 .map(|&attachment| {
-                    let (format, clear, layout, samples) = match attachment {
+                    let (format, layout, image_samples) = match attachment {
                         Either::Left(image_id) => {
                             let node_image = find_attachment_node_image(image_id);
                             let image = ctx.get_image(image_id).expect("Image does not exist");
                             (
                                 image.format(),
-                                node_image.clear,
                                 node_image.layout,
                                 image.kind().num_samples(),
                             )
@@ -588,31 +934,52 @@ where
                                 .expect("Expect target created")
                                 .backbuffer()[0]
                                 .format(),
-                            surface_clear,
                             rendy_core::hal::image::Layout::Present,
                             1,
                         ),
                     };
 
+                    if resolved.contains(&attachment) {
+                        return resolve_target(format, layout, image_samples);
+                    }
+
+                    let format_aspects = format.surface_desc().aspects;
+                    let (ops, stencil_ops, discard) =
+                        attachment_ops(format_aspects, attachment_clear_aspects(attachment));
+                    let (ops, stencil_ops, discard) = match attachment {
+                        Either::Left(image_id) => override_ops(
+                            (ops, stencil_ops, discard),
+                            format_aspects,
+                            self.load_ops
+                                .iter()
+                                .find(|&&(id, _)| id == image_id)
+                                .map(|&(_, op)| op),
+                            self.store_ops
+                                .iter()
+                                .find(|&&(id, _)| id == image_id)
+                                .map(|&(_, op)| op),
+                        ),
+                        Either::Right(RenderPassSurface) => (ops, stencil_ops, discard),
+                    };
                     rendy_core::hal::pass::Attachment {
                         format: Some(format),
-                        ops: rendy_core::hal::pass::AttachmentOps {
-                            load: if clear.is_some() {
-                                rendy_core::hal::pass::AttachmentLoadOp::Clear
-                            } else {
-                                rendy_core::hal::pass::AttachmentLoadOp::Load
-                            },
-                            store: rendy_core::hal::pass::AttachmentStoreOp::Store,
-                        },
-                        stencil_ops: rendy_core::hal::pass::AttachmentOps::DONT_CARE,
-                        layouts: if clear.is_some() {
+                        ops,
+                        stencil_ops,
+                        layouts: if discard {
                             rendy_core::hal::image::Layout::Undefined..layout
                         } else {
                             layout..layout
                         },
-                        samples,
+                        samples: image_samples,
                     }
                 })
+                .chain(resolved.iter().map(|&attachment| {
+                    multisampled_color(
+                        attachment_format(attachment),
+                        attachment_clear(attachment).is_some(),
+                        samples,
+                    )
+                }))
                 .collect();
 
             log::debug!("Attachments {:#?}", pass_attachments);
@@ -622,12 +989,15 @@ where
                 inputs: Vec<(usize, Layout)>,
                 colors: Vec<(usize, Layout)>,
                 depth_stencil: Option<(usize, Layout)>,
+                resolves: Vec<(usize, Layout)>,
+                preserves: Vec<usize>,
             }
 
             let subpasses: Vec<_> = self
                 .subpasses
                 .iter()
-                .map(|subpass| OwningSubpassDesc {
+                .enumerate()
+                .map(|(index, subpass)| OwningSubpassDesc {
                     inputs: subpass
                         .inputs
                         .iter()
@@ -650,8 +1020,11 @@ where
                         .iter()
                         .map(|&c| {
                             (
-                                attachments.iter().position(|&a| a == c).unwrap(),
+                                color_attachment_index(&attachments, &resolved, c),
                                 match c {
+                                    _ if resolved.contains(&c) => {
+                                        rendy_core::hal::image::Layout::ColorAttachmentOptimal
+                                    }
                                     Either::Left(image_id) => {
                                         find_attachment_node_image(image_id).layout
                                     }
@@ -675,6 +1048,28 @@ where
                             },
                         )
                     }),
+                    resolves: subpass
+                        .colors
+                        .iter()
+                        .filter(|c| resolved.contains(c))
+                        .map(|&c| {
+                            (
+                                attachments.iter().position(|&a| a == c).unwrap(),
+                                match c {
+                                    Either::Left(image_id) => {
+                                        find_attachment_node_image(image_id).layout
+                                    }
+                                    Either::Right(RenderPassSurface) => {
+                                        rendy_core::hal::image::Layout::ColorAttachmentOptimal
+                                    }
+                                },
+                            )
+                        })
+                        .collect(),
+                    preserves: preserved_attachments(&subpass_attachments, index)
+                        .into_iter()
+                        .map(|p| attachments.iter().position(|&a| a == p).unwrap())
+                        .collect(),
                 })
                 .collect();
 
@@ -686,8 +1081,8 @@ where
                     inputs: &subpass.inputs[..],
                     colors: &subpass.colors[..],
                     depth_stencil: subpass.depth_stencil.as_ref(),
-                    resolves: &[],
-                    preserves: &[],
+                    resolves: &subpass.resolves[..],
+                    preserves: &subpass.preserves[..],
                 })
                 .collect();
 
@@ -695,12 +1090,8 @@ where
                 factory
                     .device()
                     .create_render_pass(pass_attachments, subpasses, {
-                        assert_eq!(
-                            self.subpasses.len(),
-                            1,
-                            "TODO: Implement subpass dependencies to allow more than one subpass"
-                        );
-                        std::iter::empty::<rendy_core::hal::pass::SubpassDependency>()
+                        log::debug!("Dependencies {:#?}", dependencies);
+                        dependencies
                     })
             }
             .unwrap();
@@ -709,46 +1100,37 @@ where
             result
         };
 
-        log::trace!(
-            "Create {} framebuffers",
-            views.len() - attachments.len() + 1
-        );
-
-        // Swapchain image views, if any, are last ones.
-        let mut framebuffers = (attachments.len() - 1..views.len())
-            .map(|i| unsafe {
-                log::trace!(
-                    "Create framebuffer for views {}..{} and {}",
-                    0,
-                    attachments.len() - 1,
-                    i,
-                );
-                factory
-                    .device()
-                    .create_framebuffer(
-                        &render_pass,
-                        views[..attachments.len() - 1].iter().chain(Some(&views[i])),
-                        rendy_core::hal::image::Extent {
-                            width: framebuffer_width,
-                            height: framebuffer_height,
-                            depth: framebuffer_layers as u32, // This is gfx-hal BUG as this parameter actually means framebuffer layers number,
-                        },
-                    )
-                    .map_err(NodeBuildError::OutOfMemory)
-            })
-            .collect::<Result<Vec<_>, _>>()?;
+        let mut framebuffers = unsafe {
+            create_framebuffers(
+                factory,
+                &render_pass,
+                attachments.len(),
+                &views,
+                &msaa_views,
+                rendy_core::hal::image::Extent {
+                    width: framebuffer_width,
+                    height: framebuffer_height,
+                    depth: framebuffer_layers as u32,
+                },
+            )?
+        };
 
         log::trace!("Collect clears for render pass");
 
+        // Clear values are indexed by attachment number.
         let clears: Vec<_> = attachments
             .iter()
-            .filter_map(|&a| match a {
-                Either::Left(image_id) => find_attachment_node_image(image_id).clear,
-                Either::Right(RenderPassSurface) => surface_clear,
+            .chain(&resolved)
+            .map(|&a| {
+                attachment_clear(a).unwrap_or(rendy_core::hal::command::ClearValue {
+                    color: rendy_core::hal::command::ClearColor { float32: [0.0; 4] },
+                })
             })
-            .map(Into::into)
             .collect();
 
+        // Multisampled views are destroyed along with attachment views.
+        views.extend(msaa_views);
+
         let mut command_pool = factory
             .create_command_pool(family)
             .map_err(NodeBuildError::OutOfMemory)?
@@ -757,57 +1139,8 @@ where
 
         let command_cirque = CommandCirque::new();
 
-        let acquire = if uses_pipeline_barriers::<B>(factory.device()) {
-            let (stages, barriers) = gfx_acquire_barriers(ctx, &buffers, &images);
-
-            if !barriers.is_empty() {
-                let initial = command_pool.allocate_buffers(1).pop().unwrap();
-                let mut recording = initial.begin(MultiShot(SimultaneousUse), ());
-                log::debug!("Acquire {:?} : {:#?}", stages, barriers);
-                unsafe {
-                    recording.encoder().pipeline_barrier(
-                        stages,
-                        rendy_core::hal::memory::Dependencies::empty(),
-                        barriers,
-                    );
-                }
-                let (acquire_submit, acquire_buffer) = recording.finish().submit();
-                Some(BarriersCommands {
-                    buffer: acquire_buffer,
-                    submit: acquire_submit,
-                })
-            } else {
-                None
-            }
-        } else {
-            None
-        };
-
-        let release = if uses_pipeline_barriers::<B>(factory.device()) {
-            let (stages, barriers) = gfx_release_barriers(ctx, &buffers, &images);
-
-            if !barriers.is_empty() {
-                let initial = command_pool.allocate_buffers(1).pop().unwrap();
-                let mut recording = initial.begin(MultiShot(SimultaneousUse), ());
-                log::debug!("Release {:?} : {:#?}", stages, barriers);
-                unsafe {
-                    recording.encoder().pipeline_barrier(
-                        stages,
-                        rendy_core::hal::memory::Dependencies::empty(),
-                        barriers,
-                    );
-                }
-                let (release_submit, release_buffer) = recording.finish().submit();
-                Some(BarriersCommands {
-                    buffer: release_buffer,
-                    submit: release_submit,
-                })
-            } else {
-                None
-            }
-        } else {
-            None
-        };
+        let (acquire, release) =
+            record_barriers(ctx, factory, &mut command_pool, &buffers, &images);
 
         let subpasses = self
             .subpasses
@@ -816,6 +1149,12 @@ where
             .map(|(index, subpass)| {
                 let subpass_colors = subpass.colors.len();
                 let subpass_depth = subpass.depth_stencil.is_some();
+                let subpass_stencil = subpass.depth_stencil.map_or(false, |attachment| {
+                    attachment_format(attachment)
+                        .surface_desc()
+                        .aspects
+                        .contains(rendy_core::hal::format::Aspects::STENCIL)
+                });
 
                 subpass
                     .groups
@@ -823,6 +1162,11 @@ where
                     .map(|group| {
                         assert_eq!(group.colors(), subpass_colors);
                         assert_eq!(group.depth(), subpass_depth);
+                        assert!(
+                            !group.stencil() || subpass_stencil,
+                            "Render group uses stencil, but depth attachment of subpass {} has no stencil aspect",
+                            index
+                        );
 
                         let buffers: Vec<_> = group
                             .buffers()
@@ -857,6 +1201,7 @@ where
                             aux,
                             framebuffer_width,
                             framebuffer_height,
+                            samples,
                             rendy_core::hal::pass::Subpass {
                                 index,
                                 main_pass: &render_pass,
@@ -866,11 +1211,30 @@ where
                         )
                     })
                     .collect::<Result<Vec<_>, _>>()
-                    .map(|groups| SubpassNode { groups })
+                    .map(|groups| {
+                        let secondary = if groups.iter().any(|group| group.secondary()) {
+                            Some(groups.iter().map(|_| Vec::new()).collect())
+                        } else {
+                            None
+                        };
+                        SubpassNode { groups, secondary }
+                    })
             })
             .collect::<Result<Vec<_>, _>>()
             .map_err(NodeBuildError::Pipeline)?;
 
+        let secondary_pool = if subpasses.iter().any(|subpass| subpass.secondary.is_some()) {
+            Some(
+                factory
+                    .create_command_pool(family)
+                    .map_err(NodeBuildError::OutOfMemory)?
+                    .with_capability()
+                    .expect("Graph must specify family that supports `Graphics`"),
+            )
+        } else {
+            None
+        };
+
         let node: Box<dyn DynNode<B, T>> = match node_target {
             Some(target) => {
                 log::debug!("Construct RenderPassNodeWithSurface");
@@ -880,30 +1244,30 @@ where
 
                         framebuffer_width,
                         framebuffer_height,
-                        _framebuffer_layers: framebuffer_layers,
+                        framebuffer_layers,
 
                         render_pass,
+                        attachments: attachment_views,
+                        attachment_count: attachments.len(),
                         views,
+                        msaa_formats,
+                        samples,
+                        msaa_images,
                         clears,
 
                         command_pool,
                         command_cirque,
+                        secondary_pool,
 
+                        buffers,
+                        images,
                         acquire,
                         release,
 
                         relevant: relevant::Relevant,
                     },
 
-                    per_image: framebuffers
-                        .into_iter()
-                        .map(|fb| PerImage {
-                            framebuffer: fb,
-                            acquire: factory.create_semaphore().unwrap(),
-                            release: factory.create_semaphore().unwrap(),
-                            index: 0,
-                        })
-                        .collect(),
+                    per_image: create_per_image(factory, framebuffers)?,
                     free_acquire: factory.create_semaphore().unwrap(),
                     target,
                 })
@@ -916,15 +1280,23 @@ where
 
                         framebuffer_width,
                         framebuffer_height,
-                        _framebuffer_layers: framebuffer_layers,
+                        framebuffer_layers,
 
                         render_pass,
+                        attachments: attachment_views,
+                        attachment_count: attachments.len(),
                         views,
+                        msaa_formats,
+                        samples,
+                        msaa_images,
                         clears,
 
                         command_pool,
                         command_cirque,
+                        secondary_pool,
 
+                        buffers,
+                        images,
                         acquire,
                         release,
 
@@ -946,6 +1318,10 @@ where
 struct SubpassNode<B: Backend, T: ?Sized> {
     /// RenderGroups of pipelines to exeucte withing subpass.
     groups: Vec<Box<dyn RenderGroup<B, T>>>,
+
+    /// Secondary command buffers of each group for each frame index.
+    /// `None` if groups are recorded inline.
+    secondary: Option<Vec<Vec<Option<SecondaryCommands<B>>>>>,
 }
 
 impl<B, T> std::fmt::Debug for SubpassNode<B, T>
@@ -956,6 +1332,7 @@ where
     fn fmt(&self, fmt: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         fmt.debug_struct("SubpassNode")
             .field("groups", &self.groups)
+            .field("secondary", &self.secondary)
             .finish()
     }
 }
@@ -983,20 +1360,67 @@ where
     }
 }
 
+/// Commands of render group recorded into secondary command buffer.
+struct SecondaryCommands<B: Backend> {
+    submit: Submit<B, SimultaneousUse, SecondaryLevel, RenderPassContinue>,
+    buffer: CommandBuffer<
+        B,
+        Graphics,
+        PendingState<ExecutableState<MultiShot<SimultaneousUse>, RenderPassContinue>>,
+        SecondaryLevel,
+        IndividualReset,
+    >,
+}
+
+impl<B> std::fmt::Debug for SecondaryCommands<B>
+where
+    B: Backend,
+{
+    fn fmt(&self, fmt: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        fmt.debug_struct("SecondaryCommands")
+            .field("submit", &self.submit)
+            .field("buffer", &self.buffer)
+            .finish()
+    }
+}
+
 struct RenderPassNodeCommon<B: Backend, T: ?Sized> {
     subpasses: Vec<SubpassNode<B, T>>,
 
     framebuffer_width: u32,
     framebuffer_height: u32,
-    _framebuffer_layers: u16,
+    framebuffer_layers: u16,
 
     render_pass: B::RenderPass,
+
+    /// Image attachments, in order of their views.
+    attachments: Vec<AttachmentView>,
+
+    /// Number of attachments including surface.
+    attachment_count: usize,
+
+    /// Views of image attachments, then swapchain images, then multisampled attachments.
     views: Vec<B::ImageView>,
+
+    /// Formats of internal multisampled color attachments.
+    msaa_formats: Vec<rendy_core::hal::format::Format>,
+    samples: rendy_core::hal::image::NumSamples,
+
+    /// Internal multisampled color attachments.
+    /// Empty if render pass is not multisampled.
+    msaa_images: Vec<Escape<Image<B>>>,
     clears: Vec<rendy_core::hal::command::ClearValue>,
 
     command_pool: CommandPool<B, Graphics, IndividualReset>,
     command_cirque: CommandCirque<B, Graphics>,
 
+    /// Pool for secondary command buffers of render groups.
+    /// `None` if all subpasses are recorded inline.
+    secondary_pool: Option<CommandPool<B, Graphics, IndividualReset>>,
+
+    /// Resources of the node to record barriers for after resize.
+    buffers: Vec<NodeBuffer>,
+    images: Vec<NodeImage>,
     acquire: Option<BarriersCommands<B>>,
     release: Option<BarriersCommands<B>>,
 
@@ -1013,12 +1437,20 @@ where
             .field("subpasses", &self.subpasses)
             .field("framebuffer_width", &self.framebuffer_width)
             .field("framebuffer_height", &self.framebuffer_height)
-            .field("_framebuffer_layers", &self._framebuffer_layers)
+            .field("framebuffer_layers", &self.framebuffer_layers)
             .field("render_pass", &self.render_pass)
+            .field("attachments", &self.attachments)
+            .field("attachment_count", &self.attachment_count)
             .field("views", &self.views)
+            .field("msaa_formats", &self.msaa_formats)
+            .field("samples", &self.samples)
+            .field("msaa_images", &self.msaa_images)
             .field("clears", &self.clears)
             .field("command_pool", &self.command_pool)
             .field("command_cirque", &self.command_cirque)
+            .field("secondary_pool", &self.secondary_pool)
+            .field("buffers", &self.buffers)
+            .field("images", &self.images)
             .field("acquire", &self.acquire)
             .field("release", &self.release)
             .field("relevant", &self.relevant)
@@ -1031,24 +1463,130 @@ where
     B: Backend,
     T: ?Sized,
 {
-    unsafe fn dispose(mut self, factory: &mut Factory<B>, aux: &T) {
-        self.relevant.dispose();
-        for subpass in self.subpasses {
-            for group in subpass.groups {
-                group.dispose(factory, aux)
+    /// Size of the framebuffer limited by image attachments.
+    /// `None` if all attachments are swapchain images.
+    fn attachments_extent(
+        &self,
+        ctx: &GraphContext<B>,
+    ) -> Option<rendy_core::hal::window::Extent2D> {
+        self.attachments
+            .iter()
+            .map(|attachment| {
+                ctx.get_image(attachment.id)
+                    .expect("Image does not exist")
+                    .kind()
+                    .level_extent(attachment.range.levels.start)
+            })
+            .fold(None, |acc, extent| {
+                let (width, height) = acc.map_or((extent.width, extent.height), |acc| {
+                    let rendy_core::hal::window::Extent2D { width, height } = acc;
+                    (min(width, extent.width), min(height, extent.height))
+                });
+                Some(rendy_core::hal::window::Extent2D { width, height })
+            })
+    }
+
+    /// Adapt render groups to new framebuffer size.
+    /// Pipelines and render pass are kept.
+    ///
+    /// Returns `false` if any group can't be resized.
+    fn resize_groups(&mut self, factory: &Factory<B>, width: u32, height: u32) -> bool {
+        self.framebuffer_width = width;
+        self.framebuffer_height = height;
+        self.subpasses
+            .iter_mut()
+            .flat_map(|subpass| &mut subpass.groups)
+            .fold(true, |resized, group| {
+                group.resize(factory, width, height) && resized
+            })
+    }
+
+    /// Recreate attachment views and multisampled attachments with new size
+    /// and framebuffers with them.
+    /// `target_views` are views of swapchain images, one framebuffer is created for each.
+    /// Commands recorded with old attachments are freed.
+    ///
+    /// # Safety
+    ///
+    /// Old views must not be in use by the device.
+    unsafe fn recreate_framebuffers(
+        &mut self,
+        ctx: &GraphContext<B>,
+        factory: &Factory<B>,
+        target_views: Vec<B::ImageView>,
+    ) -> Result<Vec<B::Framebuffer>, NodeBuildError> {
+        self.free_commands();
+
+        for view in self.views.drain(..) {
+            factory.device().destroy_image_view(view);
+        }
+        self.msaa_images.clear();
+
+        for attachment in &self.attachments {
+            let view = create_attachment_view(ctx, factory, attachment)?;
+            self.views.push(view);
+        }
+        self.views.extend(target_views);
+
+        let (msaa_images, msaa_views) = create_msaa_attachments(
+            factory,
+            &self.msaa_formats,
+            self.framebuffer_width,
+            self.framebuffer_height,
+            self.samples,
+        )?;
+        self.msaa_images = msaa_images;
+
+        let framebuffers = create_framebuffers(
+            factory,
+            &self.render_pass,
+            self.attachment_count,
+            &self.views,
+            &msaa_views,
+            rendy_core::hal::image::Extent {
+                width: self.framebuffer_width,
+                height: self.framebuffer_height,
+                depth: self.framebuffer_layers as u32,
+            },
+        );
+        self.views.extend(msaa_views);
+
+        let (acquire, release) = record_barriers(
+            ctx,
+            factory,
+            &mut self.command_pool,
+            &self.buffers,
+            &self.images,
+        );
+        self.acquire = acquire;
+        self.release = release;
+
+        framebuffers
+    }
+
+    /// Free all recorded command buffers,
+    /// so that they are recorded again when used.
+    unsafe fn free_commands(&mut self) {
+        if let Some(pool) = &mut self.secondary_pool {
+            for subpass in &mut self.subpasses {
+                if let Some(secondary) = &mut subpass.secondary {
+                    for SecondaryCommands { submit, buffer } in secondary
+                        .iter_mut()
+                        .flat_map(|group| group.drain(..))
+                        .flatten()
+                    {
+                        drop(submit);
+                        let executable = buffer.mark_complete();
+                        pool.free_buffers(Some(executable));
+                    }
+                }
             }
         }
+        free_cirque(
+            &mut self.command_pool,
+            std::mem::replace(&mut self.command_cirque, CommandCirque::new()),
+        );
         let pool = &mut self.command_pool;
-        self.command_cirque.dispose(|buffer| {
-            buffer.either_with(
-                &mut *pool,
-                |pool, executable| pool.free_buffers(Some(executable)),
-                |pool, pending| {
-                    let executable = pending.mark_complete();
-                    pool.free_buffers(Some(executable))
-                },
-            );
-        });
         if let Some(BarriersCommands { submit, buffer }) = self.acquire.take() {
             drop(submit);
             let executable = buffer.mark_complete();
@@ -1059,11 +1597,25 @@ where
             let executable = buffer.mark_complete();
             pool.free_buffers(Some(executable));
         }
-        factory.destroy_command_pool(self.command_pool.with_queue_type());
+    }
+
+    unsafe fn dispose(mut self, factory: &mut Factory<B>, aux: &T) {
+        self.relevant.dispose();
+        self.free_commands();
+        for subpass in self.subpasses {
+            for group in subpass.groups {
+                group.dispose(factory, aux)
+            }
+        }
+        if let Some(pool) = self.secondary_pool.take() {
+            factory.destroy_command_pool(pool.with_queue_type());
+        }
+        factory.destroy_command_pool(self.command_pool.with_queue_type());
 
         for view in self.views {
             factory.device().destroy_image_view(view);
         }
+        drop(self.msaa_images);
         factory.device().destroy_render_pass(self.render_pass);
     }
 }
@@ -1076,6 +1628,39 @@ struct PerImage<B: Backend> {
     index: usize,
 }
 
+impl<B> PerImage<B>
+where
+    B: Backend,
+{
+    unsafe fn dispose(self, factory: &Factory<B>) {
+        factory.device().destroy_framebuffer(self.framebuffer);
+        factory.destroy_semaphore(self.acquire);
+        factory.destroy_semaphore(self.release);
+    }
+}
+
+/// Create data for each swapchain image with its framebuffer.
+fn create_per_image<B: Backend>(
+    factory: &Factory<B>,
+    framebuffers: Vec<B::Framebuffer>,
+) -> Result<Vec<PerImage<B>>, NodeBuildError> {
+    framebuffers
+        .into_iter()
+        .map(|framebuffer| {
+            Ok(PerImage {
+                framebuffer,
+                acquire: factory
+                    .create_semaphore()
+                    .map_err(NodeBuildError::OutOfMemory)?,
+                release: factory
+                    .create_semaphore()
+                    .map_err(NodeBuildError::OutOfMemory)?,
+                index: 0,
+            })
+        })
+        .collect()
+}
+
 struct RenderPassNodeWithSurface<B: Backend, T: ?Sized> {
     common: RenderPassNodeCommon<B, T>,
     per_image: Vec<PerImage<B>>,
@@ -1113,7 +1698,8 @@ where
         waits: &[(&'a B::Semaphore, rendy_core::hal::pso::PipelineStage)],
         signals: &[&'a B::Semaphore],
         fence: Option<&mut Fence<B>>,
-    ) {
+        timestamps: Option<NodeTimestamps<'a, B>>,
+    ) -> PresentResult {
         let RenderPassNodeWithSurface {
             common:
                 RenderPassNodeCommon {
@@ -1127,6 +1713,7 @@ where
 
                     command_cirque,
                     command_pool,
+                    secondary_pool,
 
                     acquire,
                     release,
@@ -1137,6 +1724,8 @@ where
             per_image,
         } = self;
 
+        // Render pass is submitted without presentable image if acquisition fails,
+        // so attachments and semaphores are handled as usual.
         let next = match target.next_image(&free_acquire) {
             Ok(next) => {
                 log::trace!("Presentable image acquired: {:#?}", next);
@@ -1155,28 +1744,16 @@ where
             if let Some(next) = &next {
                 let ref mut for_image = per_image[next[0] as usize];
 
-                let force_record = subpasses.iter_mut().enumerate().fold(
-                    false,
-                    |force_record, (subpass_index, subpass)| {
-                        subpass
-                            .groups
-                            .iter_mut()
-                            .fold(force_record, |force_record, group| {
-                                group
-                                    .prepare(
-                                        factory,
-                                        queue.id(),
-                                        index,
-                                        rendy_core::hal::pass::Subpass {
-                                            index: subpass_index,
-                                            main_pass: &render_pass,
-                                        },
-                                        aux,
-                                    )
-                                    .force_record()
-                                    || force_record
-                            })
-                    },
+                // Secondary buffers are shared by framebuffers of all images.
+                let force_record = prepare_subpasses(
+                    subpasses,
+                    secondary_pool,
+                    factory,
+                    queue.id(),
+                    index,
+                    render_pass,
+                    None,
+                    aux,
                 );
 
                 if force_record || for_image.index != index {
@@ -1185,7 +1762,8 @@ where
                 }
             }
 
-            cbuf.or_init(|cbuf| {
+            cbuf.or_init(|mut cbuf| {
+                factory.set_object_name(&mut cbuf, std::any::type_name::<Self>());
                 let mut cbuf = cbuf.begin(MultiShot(NoSimultaneousUse), ());
                 let mut encoder = cbuf.encoder();
 
@@ -1203,31 +1781,16 @@ where
                         h: *framebuffer_height as _,
                     };
 
-                    let mut pass_encoder = encoder.begin_render_pass_inline(
-                        &render_pass,
+                    record_subpasses(
+                        &mut encoder,
+                        subpasses,
+                        index,
+                        render_pass,
                         &for_image.framebuffer,
                         area,
-                        &clears,
+                        clears,
+                        aux,
                     );
-
-                    subpasses
-                        .iter_mut()
-                        .enumerate()
-                        .for_each(|(subpass_index, subpass)| {
-                            subpass.groups.iter_mut().for_each(|group| {
-                                group.draw_inline(
-                                    pass_encoder.reborrow(),
-                                    index,
-                                    rendy_core::hal::pass::Subpass {
-                                        index: subpass_index,
-                                        main_pass: &render_pass,
-                                    },
-                                    aux,
-                                )
-                            })
-                        });
-
-                    drop(pass_encoder);
                 }
 
                 if let Some(barriers) = &release {
@@ -1242,7 +1805,7 @@ where
         queue.submit(
             Some(
                 Submission::new()
-                    .submits(Some(submit))
+                    .submits(timed_submits(Some(submit), timestamps))
                     .wait(waits.iter().cloned().chain(next.as_ref().map(|n| {
                         (
                             &per_image[n[0] as usize].acquire,
@@ -1259,20 +1822,66 @@ where
             fence,
         );
 
-        if let Some(next) = next {
-            log::trace!("Present");
-            let ref mut for_image = per_image[next[0] as usize];
-            if let Err(err) = next.present(queue.raw(), Some(&for_image.release)) {
-                log::debug!("Swapchain presentation error: {:#?}", err);
+        match next {
+            Some(next) => {
+                log::trace!("Present");
+                let ref mut for_image = per_image[next[0] as usize];
+                let suboptimal = next.is_suboptimal();
+                present_result(
+                    suboptimal,
+                    next.present(queue.raw(), Some(&for_image.release)),
+                )
             }
+            None => PresentResult::NeedsRecreation,
         }
     }
 
+    fn acquires_image(&self) -> bool {
+        true
+    }
+
+    unsafe fn resize(
+        &mut self,
+        ctx: &GraphContext<B>,
+        factory: &mut Factory<B>,
+        _aux: &T,
+    ) -> Result<bool, NodeBuildError> {
+        let extent = self
+            .common
+            .attachments_extent(ctx)
+            .unwrap_or_else(|| self.target.extent());
+        self.target
+            .recreate(factory.physical(), factory.device(), extent)
+            .map_err(NodeBuildError::Swapchain)?;
+
+        let extent = self.target.extent();
+        let (width, height) = self.common.attachments_extent(ctx).map_or(
+            (extent.width, extent.height),
+            |attachments| {
+                (
+                    min(attachments.width, extent.width),
+                    min(attachments.height, extent.height),
+                )
+            },
+        );
+        if !self.common.resize_groups(factory, width, height) {
+            return Ok(false);
+        }
+
+        for per_image in self.per_image.drain(..) {
+            per_image.dispose(factory);
+        }
+        let target_views = create_target_views(factory, &self.target)?;
+        let framebuffers = self
+            .common
+            .recreate_framebuffers(ctx, factory, target_views)?;
+        self.per_image = create_per_image(factory, framebuffers)?;
+        Ok(true)
+    }
+
     unsafe fn dispose(self: Box<Self>, factory: &mut Factory<B>, aux: &T) {
         for per_image in self.per_image {
-            factory.device().destroy_framebuffer(per_image.framebuffer);
-            factory.destroy_semaphore(per_image.acquire);
-            factory.destroy_semaphore(per_image.release);
+            per_image.dispose(factory);
         }
         self.common.dispose(factory, aux);
         factory.destroy_surface(factory.destroy_target(self.target));
@@ -1312,7 +1921,8 @@ where
         waits: &[(&'a B::Semaphore, rendy_core::hal::pso::PipelineStage)],
         signals: &[&'a B::Semaphore],
         fence: Option<&mut Fence<B>>,
-    ) {
+        timestamps: Option<NodeTimestamps<'a, B>>,
+    ) -> PresentResult {
         let RenderPassNodeWithoutSurface {
             common:
                 RenderPassNodeCommon {
@@ -1326,6 +1936,7 @@ where
 
                     command_cirque,
                     command_pool,
+                    secondary_pool,
 
                     acquire,
                     release,
@@ -1337,35 +1948,23 @@ where
         let submit = command_cirque.encode(frames, command_pool, |mut cbuf| {
             let index = cbuf.index();
 
-            let force_record = subpasses.iter_mut().enumerate().fold(
-                false,
-                |force_record, (subpass_index, subpass)| {
-                    subpass
-                        .groups
-                        .iter_mut()
-                        .fold(force_record, |force_record, group| {
-                            group
-                                .prepare(
-                                    factory,
-                                    queue.id(),
-                                    index,
-                                    rendy_core::hal::pass::Subpass {
-                                        index: subpass_index,
-                                        main_pass: &render_pass,
-                                    },
-                                    aux,
-                                )
-                                .force_record()
-                                || force_record
-                        })
-                },
+            let force_record = prepare_subpasses(
+                subpasses,
+                secondary_pool,
+                factory,
+                queue.id(),
+                index,
+                render_pass,
+                Some(&*framebuffer),
+                aux,
             );
 
             if force_record {
                 cbuf = CirqueRef::Initial(cbuf.or_reset(|cbuf| cbuf.reset()));
             }
 
-            cbuf.or_init(|cbuf| {
+            cbuf.or_init(|mut cbuf| {
+                factory.set_object_name(&mut cbuf, std::any::type_name::<Self>());
                 let mut cbuf = cbuf.begin(MultiShot(NoSimultaneousUse), ());
                 let mut encoder = cbuf.encoder();
 
@@ -1380,27 +1979,16 @@ where
                     h: *framebuffer_height as _,
                 };
 
-                let mut pass_encoder =
-                    encoder.begin_render_pass_inline(&render_pass, framebuffer, area, &clears);
-
-                subpasses
-                    .iter_mut()
-                    .enumerate()
-                    .for_each(|(subpass_index, subpass)| {
-                        subpass.groups.iter_mut().for_each(|group| {
-                            group.draw_inline(
-                                pass_encoder.reborrow(),
-                                index,
-                                rendy_core::hal::pass::Subpass {
-                                    index: subpass_index,
-                                    main_pass: &render_pass,
-                                },
-                                aux,
-                            )
-                        })
-                    });
-
-                drop(pass_encoder);
+                record_subpasses(
+                    &mut encoder,
+                    subpasses,
+                    index,
+                    render_pass,
+                    framebuffer,
+                    area,
+                    clears,
+                    aux,
+                );
 
                 if let Some(barriers) = &release {
                     encoder.execute_commands(std::iter::once(&barriers.submit));
@@ -1412,12 +2000,39 @@ where
         queue.submit(
             Some(
                 Submission::new()
-                    .submits(Some(submit))
+                    .submits(timed_submits(Some(submit), timestamps))
                     .wait(waits.iter().cloned())
                     .signal(signals.iter().cloned()),
             ),
             fence,
         );
+        PresentResult::Ok
+    }
+
+    unsafe fn resize(
+        &mut self,
+        ctx: &GraphContext<B>,
+        factory: &mut Factory<B>,
+        _aux: &T,
+    ) -> Result<bool, NodeBuildError> {
+        let extent = self
+            .common
+            .attachments_extent(ctx)
+            .expect("Render pass without surface must have image attachments");
+        if !self
+            .common
+            .resize_groups(factory, extent.width, extent.height)
+        {
+            return Ok(false);
+        }
+
+        let mut framebuffers = self
+            .common
+            .recreate_framebuffers(ctx, factory, Vec::new())?;
+        assert_eq!(framebuffers.len(), 1);
+        let framebuffer = std::mem::replace(&mut self.framebuffer, framebuffers.remove(0));
+        factory.device().destroy_framebuffer(framebuffer);
+        Ok(true)
     }
 
     unsafe fn dispose(self: Box<Self>, factory: &mut Factory<B>, aux: &T) {
@@ -1426,6 +2041,706 @@ where
     }
 }
 
+/// How view of image attachment is created.
+#[derive(Clone, Debug)]
+struct AttachmentView {
+    id: ImageId,
+    kind: rendy_core::hal::image::ViewKind,
+    range: rendy_core::hal::image::SubresourceRange,
+}
+
+/// Create view of the image attachment.
+unsafe fn create_attachment_view<B: Backend>(
+    ctx: &GraphContext<B>,
+    factory: &Factory<B>,
+    attachment: &AttachmentView,
+) -> Result<B::ImageView, NodeBuildError> {
+    let image = ctx.get_image(attachment.id).expect("Image does not exist");
+    factory
+        .device()
+        .create_image_view(
+            image.raw(),
+            attachment.kind,
+            image.format(),
+            rendy_core::hal::format::Swizzle::NO,
+            attachment.range.clone(),
+        )
+        .map_err(NodeBuildError::View)
+}
+
+/// Create views of all swapchain images.
+unsafe fn create_target_views<B: Backend>(
+    factory: &Factory<B>,
+    target: &Target<B>,
+) -> Result<Vec<B::ImageView>, NodeBuildError> {
+    target
+        .backbuffer()
+        .iter()
+        .map(|image| {
+            factory
+                .device()
+                .create_image_view(
+                    image.raw(),
+                    rendy_core::hal::image::ViewKind::D2,
+                    image.format(),
+                    rendy_core::hal::format::Swizzle::NO,
+                    rendy_core::hal::image::SubresourceRange {
+                        aspects: image.format().surface_desc().aspects,
+                        levels: 0..1,
+                        layers: 0..1,
+                    },
+                )
+                .map_err(NodeBuildError::View)
+        })
+        .collect()
+}
+
+/// Create internal multisampled color attachments and their views.
+unsafe fn create_msaa_attachments<B: Backend>(
+    factory: &Factory<B>,
+    formats: &[rendy_core::hal::format::Format],
+    width: u32,
+    height: u32,
+    samples: rendy_core::hal::image::NumSamples,
+) -> Result<(Vec<Escape<Image<B>>>, Vec<B::ImageView>), NodeBuildError> {
+    let images = formats
+        .iter()
+        .map(|&format| {
+            factory
+                .create_image(
+                    ImageInfo {
+                        kind: rendy_core::hal::image::Kind::D2(width, height, 1, samples),
+                        levels: 1,
+                        format,
+                        tiling: rendy_core::hal::image::Tiling::Optimal,
+                        view_caps: rendy_core::hal::image::ViewCapabilities::empty(),
+                        usage: rendy_core::hal::image::Usage::COLOR_ATTACHMENT
+                            | rendy_core::hal::image::Usage::TRANSIENT_ATTACHMENT,
+                    },
+                    Data,
+                )
+                .map_err(NodeBuildError::Image)
+        })
+        .collect::<Result<Vec<_>, _>>()?;
+
+    let views = images
+        .iter()
+        .map(|image| {
+            factory
+                .device()
+                .create_image_view(
+                    image.raw(),
+                    rendy_core::hal::image::ViewKind::D2,
+                    image.format(),
+                    rendy_core::hal::format::Swizzle::NO,
+                    rendy_core::hal::image::SubresourceRange {
+                        aspects: image.format().surface_desc().aspects,
+                        levels: 0..1,
+                        layers: 0..1,
+                    },
+                )
+                .map_err(NodeBuildError::View)
+        })
+        .collect::<Result<Vec<_>, _>>()?;
+
+    Ok((images, views))
+}
+
+/// Create framebuffers for `attachments` of the render pass.
+/// Last attachment view differs between framebuffers,
+/// one framebuffer is created for each view starting from `attachments - 1`.
+/// Swapchain image views, if any, are last ones.
+unsafe fn create_framebuffers<B: Backend>(
+    factory: &Factory<B>,
+    render_pass: &B::RenderPass,
+    attachments: usize,
+    views: &[B::ImageView],
+    msaa_views: &[B::ImageView],
+    extent: rendy_core::hal::image::Extent,
+) -> Result<Vec<B::Framebuffer>, NodeBuildError> {
+    log::trace!("Create {} framebuffers", views.len() - attachments + 1);
+
+    (attachments - 1..views.len())
+        .map(|i| {
+            log::trace!(
+                "Create framebuffer for views {}..{} and {}",
+                0,
+                attachments - 1,
+                i,
+            );
+            factory
+                .device()
+                .create_framebuffer(
+                    render_pass,
+                    views[..attachments - 1]
+                        .iter()
+                        .chain(Some(&views[i]))
+                        .chain(msaa_views),
+                    // This is gfx-hal BUG as `depth` parameter actually means framebuffer layers number.
+                    extent,
+                )
+                .map_err(NodeBuildError::OutOfMemory)
+        })
+        .collect()
+}
+
+/// Record acquire and release barriers of the node.
+fn record_barriers<B: Backend>(
+    ctx: &GraphContext<B>,
+    factory: &Factory<B>,
+    pool: &mut CommandPool<B, Graphics, IndividualReset>,
+    buffers: &[NodeBuffer],
+    images: &[NodeImage],
+) -> (Option<BarriersCommands<B>>, Option<BarriersCommands<B>>) {
+    if !uses_pipeline_barriers::<B>(factory.device()) {
+        return (None, None);
+    }
+
+    let (stages, barriers) = gfx_acquire_barriers(ctx, buffers, images);
+    let acquire = record_barrier_commands(pool, "Acquire", stages, barriers);
+    let (stages, barriers) = gfx_release_barriers(ctx, buffers, images);
+    let release = record_barrier_commands(pool, "Release", stages, barriers);
+    (acquire, release)
+}
+
+/// Record `barriers` into secondary command buffer.
+/// Returns `None` if there are no barriers.
+fn record_barrier_commands<B: Backend>(
+    pool: &mut CommandPool<B, Graphics, IndividualReset>,
+    name: &str,
+    stages: std::ops::Range<rendy_core::hal::pso::PipelineStage>,
+    barriers: Vec<rendy_core::hal::memory::Barrier<'_, B>>,
+) -> Option<BarriersCommands<B>> {
+    if barriers.is_empty() {
+        return None;
+    }
+
+    let initial = pool.allocate_buffers(1).pop().unwrap();
+    let mut recording = initial.begin(MultiShot(SimultaneousUse), ());
+    log::debug!("{} {:?} : {:#?}", name, stages, barriers);
+    unsafe {
+        recording.encoder().pipeline_barrier(
+            stages,
+            rendy_core::hal::memory::Dependencies::empty(),
+            barriers,
+        );
+    }
+    let (submit, buffer) = recording.finish().submit();
+    Some(BarriersCommands { buffer, submit })
+}
+
+/// Prepare groups of all subpasses for frame `index`.
+/// Groups of subpasses recorded into secondary command buffers are re-recorded here
+/// when they ask for it or have no buffer for this index yet.
+///
+/// Returns `true` if primary command buffer must be re-recorded.
+///
+/// # Safety
+///
+/// Primary command buffer of frame `index` must not be pending.
+unsafe fn prepare_subpasses<B, T>(
+    subpasses: &mut [SubpassNode<B, T>],
+    secondary_pool: &mut Option<CommandPool<B, Graphics, IndividualReset>>,
+    factory: &Factory<B>,
+    queue: QueueId,
+    index: usize,
+    render_pass: &B::RenderPass,
+    framebuffer: Option<&B::Framebuffer>,
+    aux: &T,
+) -> bool
+where
+    B: Backend,
+    T: ?Sized,
+{
+    let mut force_record = false;
+
+    for (subpass_index, subpass) in subpasses.iter_mut().enumerate() {
+        let subpass_info = || rendy_core::hal::pass::Subpass {
+            index: subpass_index,
+            main_pass: render_pass,
+        };
+
+        match &mut subpass.secondary {
+            None => {
+                for group in &mut subpass.groups {
+                    force_record = group
+                        .prepare(factory, queue, index, subpass_info(), aux)
+                        .force_record()
+                        || force_record;
+                }
+            }
+            Some(secondary) => {
+                let pool = secondary_pool.as_mut().expect(
+                    "Secondary command pool is created for subpasses with secondary groups",
+                );
+
+                for (group, buffers) in subpass.groups.iter_mut().zip(secondary) {
+                    let record = group
+                        .prepare(factory, queue, index, subpass_info(), aux)
+                        .force_record();
+
+                    if buffers.len() <= index {
+                        buffers.resize_with(index + 1, || None);
+                    }
+
+                    if !record && buffers[index].is_some() {
+                        continue;
+                    }
+
+                    let initial = match buffers[index].take() {
+                        Some(SecondaryCommands { submit, buffer }) => {
+                            // Only primary buffer of this frame index executes it.
+                            drop(submit);
+                            buffer.mark_complete().reset()
+                        }
+                        None => pool.allocate_buffers::<SecondaryLevel>(1).pop().unwrap(),
+                    };
+
+                    log::trace!(
+                        "Record secondary commands of subpass {} for frame index {}",
+                        subpass_index,
+                        index
+                    );
+
+                    let mut recording =
+                        initial.begin(MultiShot(SimultaneousUse), (subpass_info(), framebuffer));
+                    group.draw_inline(recording.render_pass_encoder(), index, subpass_info(), aux);
+                    let (submit, buffer) = recording.finish().submit();
+                    buffers[index] = Some(SecondaryCommands { submit, buffer });
+                    force_record = true;
+                }
+            }
+        }
+    }
+
+    force_record
+}
+
+/// Record render pass.
+/// Groups are drawn inline or their secondary command buffers are executed.
+fn record_subpasses<B, T>(
+    encoder: &mut Encoder<'_, B, Graphics, PrimaryLevel>,
+    subpasses: &mut [SubpassNode<B, T>],
+    index: usize,
+    render_pass: &B::RenderPass,
+    framebuffer: &B::Framebuffer,
+    area: rendy_core::hal::pso::Rect,
+    clears: &[rendy_core::hal::command::ClearValue],
+    aux: &T,
+) where
+    B: Backend,
+    T: ?Sized,
+{
+    let mut pass_encoder = match subpasses.first() {
+        Some(subpass) if subpass.secondary.is_some() => Either::Right(
+            encoder.begin_render_pass_secondary(render_pass, framebuffer, area, clears),
+        ),
+        _ => Either::Left(encoder.begin_render_pass_inline(render_pass, framebuffer, area, clears)),
+    };
+
+    for (subpass_index, subpass) in subpasses.iter_mut().enumerate() {
+        if subpass_index > 0 {
+            pass_encoder = match (pass_encoder, subpass.secondary.is_some()) {
+                (Either::Left(inline), false) => Either::Left(inline.next_subpass_inline()),
+                (Either::Left(inline), true) => Either::Right(inline.next_subpass_secondary()),
+                (Either::Right(secondary), false) => Either::Left(secondary.next_subpass_inline()),
+                (Either::Right(secondary), true) => {
+                    Either::Right(secondary.next_subpass_secondary())
+                }
+            };
+        }
+
+        match &mut pass_encoder {
+            Either::Left(inline) => {
+                for group in &mut subpass.groups {
+                    group.draw_inline(
+                        inline.reborrow(),
+                        index,
+                        rendy_core::hal::pass::Subpass {
+                            index: subpass_index,
+                            main_pass: render_pass,
+                        },
+                        aux,
+                    )
+                }
+            }
+            Either::Right(secondary) => secondary.execute_commands(
+                subpass
+                    .secondary
+                    .iter()
+                    .flatten()
+                    .filter_map(|buffers| buffers.get(index))
+                    .flatten()
+                    .map(|commands| &commands.submit),
+            ),
+        }
+    }
+}
+
+/// Distinct color attachments of multisampled render pass.
+/// Each one is a resolve target of internal multisampled attachment.
+fn resolved_colors<'a>(colors: impl IntoIterator<Item = &'a Attachment>) -> Vec<Attachment> {
+    let mut resolved: Vec<_> = colors.into_iter().cloned().collect();
+    resolved.sort();
+    resolved.dedup();
+    resolved
+}
+
+/// Check that multisampled render pass renders into single layer
+/// and attachments that are not resolved have the same number of samples.
+fn check_multisampled(
+    samples: rendy_core::hal::image::NumSamples,
+    framebuffer_layers: u16,
+    attachment_samples: impl IntoIterator<Item = rendy_core::hal::image::NumSamples>,
+) -> Result<(), NodeBuildError> {
+    if samples <= 1 {
+        return Ok(());
+    }
+    if framebuffer_layers != 1 {
+        log::warn!(
+            "Multisampled render pass can't render into {} layers",
+            framebuffer_layers
+        );
+        return Err(NodeBuildError::Layers(framebuffer_layers));
+    }
+    match attachment_samples
+        .into_iter()
+        .find(|&found| found != samples)
+    {
+        Some(found) => {
+            log::warn!(
+                "Attachment with {} samples is used in render pass with {} samples",
+                found,
+                samples
+            );
+            Err(NodeBuildError::Samples {
+                expected: samples,
+                found,
+            })
+        }
+        None => Ok(()),
+    }
+}
+
+/// View kind and layers of image attachment with `range` layers
+/// when render pass renders into `layers` at once.
+/// Returns `None` if range has fewer layers.
+fn attachment_layers(
+    range: &std::ops::Range<rendy_core::hal::image::Layer>,
+    layers: Option<u16>,
+) -> Option<(
+    rendy_core::hal::image::ViewKind,
+    std::ops::Range<rendy_core::hal::image::Layer>,
+)> {
+    match layers {
+        Some(count) if count > range.end - range.start => None,
+        Some(count) => Some((
+            rendy_core::hal::image::ViewKind::D2Array,
+            range.start..range.start + count,
+        )),
+        None => Some((rendy_core::hal::image::ViewKind::D2, range.clone())),
+    }
+}
+
+/// Attachment multisampled color is resolved into.
+/// Resolve target is overwritten entirely.
+fn resolve_target(
+    format: rendy_core::hal::format::Format,
+    layout: Layout,
+    samples: rendy_core::hal::image::NumSamples,
+) -> rendy_core::hal::pass::Attachment {
+    rendy_core::hal::pass::Attachment {
+        format: Some(format),
+        ops: rendy_core::hal::pass::AttachmentOps {
+            load: rendy_core::hal::pass::AttachmentLoadOp::DontCare,
+            store: rendy_core::hal::pass::AttachmentStoreOp::Store,
+        },
+        stencil_ops: rendy_core::hal::pass::AttachmentOps::DONT_CARE,
+        layouts: Layout::Undefined..layout,
+        samples,
+    }
+}
+
+/// Internal multisampled color attachment.
+/// It is discarded after resolve.
+fn multisampled_color(
+    format: rendy_core::hal::format::Format,
+    clear: bool,
+    samples: rendy_core::hal::image::NumSamples,
+) -> rendy_core::hal::pass::Attachment {
+    rendy_core::hal::pass::Attachment {
+        format: Some(format),
+        ops: rendy_core::hal::pass::AttachmentOps {
+            load: if clear {
+                rendy_core::hal::pass::AttachmentLoadOp::Clear
+            } else {
+                rendy_core::hal::pass::AttachmentLoadOp::DontCare
+            },
+            store: rendy_core::hal::pass::AttachmentStoreOp::DontCare,
+        },
+        stencil_ops: rendy_core::hal::pass::AttachmentOps::DONT_CARE,
+        layouts: Layout::Undefined..Layout::ColorAttachmentOptimal,
+        samples,
+    }
+}
+
+/// Index of the render pass attachment subpass renders `color` into.
+/// Multisampled attachments are placed after declared `attachments` in `resolved` order.
+fn color_attachment_index(
+    attachments: &[Attachment],
+    resolved: &[Attachment],
+    color: Attachment,
+) -> usize {
+    match resolved.iter().position(|&a| a == color) {
+        Some(index) => attachments.len() + index,
+        None => attachments.iter().position(|&a| a == color).unwrap(),
+    }
+}
+
+/// Attachments referenced by a subpass.
+#[derive(Clone, Debug, Default)]
+struct SubpassAttachments {
+    inputs: Vec<Attachment>,
+    colors: Vec<Attachment>,
+    depth_stencil: Option<Attachment>,
+}
+
+impl SubpassAttachments {
+    fn new<B: Backend, T: ?Sized>(subpass: &SubpassBuilder<B, T>) -> Self {
+        SubpassAttachments {
+            inputs: subpass.inputs.clone(),
+            colors: subpass.colors.clone(),
+            depth_stencil: subpass.depth_stencil,
+        }
+    }
+
+    fn uses(&self, attachment: Attachment) -> bool {
+        self.inputs.contains(&attachment)
+            || self.colors.contains(&attachment)
+            || self.depth_stencil == Some(attachment)
+    }
+
+    /// Stages and accesses of the subpass to the `attachment`,
+    /// and whether the subpass writes it.
+    fn access(
+        &self,
+        attachment: Attachment,
+    ) -> (
+        rendy_core::hal::pso::PipelineStage,
+        rendy_core::hal::image::Access,
+        bool,
+    ) {
+        use rendy_core::hal::{image::Access, pso::PipelineStage};
+
+        let mut stages = PipelineStage::empty();
+        let mut access = Access::empty();
+        let mut write = false;
+        if self.inputs.contains(&attachment) {
+            stages |= PipelineStage::FRAGMENT_SHADER;
+            access |= Access::INPUT_ATTACHMENT_READ;
+        }
+        if self.colors.contains(&attachment) {
+            stages |= PipelineStage::COLOR_ATTACHMENT_OUTPUT;
+            access |= Access::COLOR_ATTACHMENT_READ | Access::COLOR_ATTACHMENT_WRITE;
+            write = true;
+        }
+        if self.depth_stencil == Some(attachment) {
+            stages |= PipelineStage::EARLY_FRAGMENT_TESTS | PipelineStage::LATE_FRAGMENT_TESTS;
+            access |=
+                Access::DEPTH_STENCIL_ATTACHMENT_READ | Access::DEPTH_STENCIL_ATTACHMENT_WRITE;
+            write = true;
+        }
+        (stages, access, write)
+    }
+
+    fn attachments(&self) -> impl Iterator<Item = Attachment> + '_ {
+        self.inputs
+            .iter()
+            .chain(&self.colors)
+            .chain(&self.depth_stencil)
+            .cloned()
+    }
+}
+
+/// Dependencies of the render pass.
+/// Generates by-region dependency for each pair of subpasses accessing the same attachment
+/// if at least one of them writes it.
+/// Custom dependencies follow generated ones.
+/// Fails if custom dependency references subpass that doesn't exist.
+fn subpass_dependencies(
+    subpasses: &[SubpassAttachments],
+    custom: &[rendy_core::hal::pass::SubpassDependency],
+) -> Result<Vec<rendy_core::hal::pass::SubpassDependency>, NodeBuildError> {
+    use rendy_core::hal::{
+        image::Access, memory::Dependencies, pass::SubpassDependency, pso::PipelineStage,
+    };
+
+    for (index, dependency) in custom.iter().enumerate() {
+        let passes = &dependency.passes;
+        let exists = |pass: Option<rendy_core::hal::pass::SubpassId>| {
+            pass.map_or(true, |pass| pass < subpasses.len())
+        };
+        if !exists(passes.start) || !exists(passes.end) {
+            log::warn!(
+                "Subpass dependency {:?} references subpass out of {} subpasses",
+                dependency,
+                subpasses.len()
+            );
+            return Err(NodeBuildError::SubpassDependency(index));
+        }
+    }
+
+    let mut dependencies = Vec::new();
+    for (dst, dst_subpass) in subpasses.iter().enumerate() {
+        for (src, src_subpass) in subpasses[..dst].iter().enumerate() {
+            let mut stages = PipelineStage::empty()..PipelineStage::empty();
+            let mut accesses = Access::empty()..Access::empty();
+            let mut hazard = false;
+
+            for attachment in dst_subpass.attachments() {
+                if !src_subpass.uses(attachment) {
+                    continue;
+                }
+                let (src_stages, src_access, src_write) = src_subpass.access(attachment);
+                let (dst_stages, dst_access, dst_write) = dst_subpass.access(attachment);
+                if !src_write && !dst_write {
+                    continue;
+                }
+                hazard = true;
+                stages.start |= src_stages;
+                stages.end |= dst_stages;
+                // Only writes need to be made available.
+                if src_write {
+                    accesses.start |= src_access;
+                }
+                accesses.end |= dst_access;
+            }
+
+            if hazard {
+                dependencies.push(SubpassDependency {
+                    passes: Some(src)..Some(dst),
+                    stages,
+                    accesses,
+                    // Subpasses access attachments only at the same framebuffer location.
+                    flags: Dependencies::BY_REGION,
+                });
+            }
+        }
+    }
+
+    dependencies.extend(custom.iter().cloned());
+    Ok(dependencies)
+}
+
+/// Attachments the subpass at `index` doesn't use
+/// but which content must be preserved for following subpasses.
+fn preserved_attachments(subpasses: &[SubpassAttachments], index: usize) -> Vec<Attachment> {
+    let mut preserved: Vec<_> = subpasses[..index]
+        .iter()
+        .flat_map(SubpassAttachments::attachments)
+        .filter(|&attachment| {
+            !subpasses[index].uses(attachment)
+                && subpasses[index + 1..]
+                    .iter()
+                    .any(|subpass| subpass.uses(attachment))
+        })
+        .collect();
+    preserved.sort();
+    preserved.dedup();
+    preserved
+}
+
+/// Operations for attachment with `format_aspects` of which `clear_aspects` are cleared.
+/// Returns operations for color or depth aspect, for stencil aspect
+/// and whether previous content is discarded entirely.
+/// Aspects that are not cleared are loaded and all aspects are stored.
+fn attachment_ops(
+    format_aspects: rendy_core::hal::format::Aspects,
+    clear_aspects: rendy_core::hal::format::Aspects,
+) -> (
+    rendy_core::hal::pass::AttachmentOps,
+    rendy_core::hal::pass::AttachmentOps,
+    bool,
+) {
+    use rendy_core::hal::{
+        format::Aspects,
+        pass::{AttachmentLoadOp, AttachmentOps, AttachmentStoreOp},
+    };
+
+    let ops = |aspect| AttachmentOps {
+        load: if clear_aspects.contains(aspect) {
+            AttachmentLoadOp::Clear
+        } else {
+            AttachmentLoadOp::Load
+        },
+        store: AttachmentStoreOp::Store,
+    };
+
+    let main = if format_aspects.contains(Aspects::COLOR) {
+        ops(Aspects::COLOR)
+    } else if format_aspects.contains(Aspects::DEPTH) {
+        ops(Aspects::DEPTH)
+    } else {
+        AttachmentOps::DONT_CARE
+    };
+
+    let stencil = if format_aspects.contains(Aspects::STENCIL) {
+        ops(Aspects::STENCIL)
+    } else {
+        AttachmentOps::DONT_CARE
+    };
+
+    let discard = !clear_aspects.is_empty() && clear_aspects.contains(format_aspects);
+    (main, stencil, discard)
+}
+
+/// Apply operations set with `set_image_load_op` and `set_image_store_op`
+/// to operations returned by `attachment_ops`.
+/// Aspects missing in the format keep `DontCare` operations.
+fn override_ops(
+    (mut ops, mut stencil_ops, discard): (
+        rendy_core::hal::pass::AttachmentOps,
+        rendy_core::hal::pass::AttachmentOps,
+        bool,
+    ),
+    format_aspects: rendy_core::hal::format::Aspects,
+    load: Option<rendy_core::hal::pass::AttachmentLoadOp>,
+    store: Option<rendy_core::hal::pass::AttachmentStoreOp>,
+) -> (
+    rendy_core::hal::pass::AttachmentOps,
+    rendy_core::hal::pass::AttachmentOps,
+    bool,
+) {
+    use rendy_core::hal::{format::Aspects, pass::AttachmentLoadOp};
+
+    let has_main = format_aspects.intersects(Aspects::COLOR | Aspects::DEPTH);
+    let has_stencil = format_aspects.contains(Aspects::STENCIL);
+
+    if let Some(load) = load {
+        if has_main {
+            ops.load = load;
+        }
+        if has_stencil {
+            stencil_ops.load = load;
+        }
+    }
+    if let Some(store) = store {
+        if has_main {
+            ops.store = store;
+        }
+        if has_stencil {
+            stencil_ops.store = store;
+        }
+    }
+
+    let discard = match load {
+        Some(AttachmentLoadOp::DontCare) => true,
+        Some(_) => false,
+        None => discard,
+    };
+    (ops, stencil_ops, discard)
+}
+
 fn common_layout(acc: Layout, layout: Layout) -> Layout {
     match (acc, layout) {
         (Layout::Undefined, layout) => layout,
@@ -1439,3 +2754,292 @@ fn common_layout(acc: Layout, layout: Layout) -> Layout {
         (_, _) => Layout::General,
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use {super::*, rendy_core::hal::image::ViewKind};
+
+    #[test]
+    fn multisampled_pass_mismatch_is_build_error() {
+        assert!(check_multisampled(1, 6, vec![4, 1]).is_ok());
+        assert!(check_multisampled(4, 1, vec![4, 4]).is_ok());
+        match check_multisampled(4, 6, vec![4]) {
+            Err(NodeBuildError::Layers(6)) => {}
+            other => panic!("Unexpected result {:?}", other),
+        }
+        match check_multisampled(4, 1, vec![4, 1]) {
+            Err(NodeBuildError::Samples {
+                expected: 4,
+                found: 1,
+            }) => {}
+            other => panic!("Unexpected result {:?}", other),
+        }
+    }
+
+    #[test]
+    fn layered_attachments_are_array_views() {
+        // Four cascades of a shadow map rendered at once.
+        assert_eq!(
+            attachment_layers(&(0..4), Some(4)),
+            Some((ViewKind::D2Array, 0..4))
+        );
+        assert_eq!(
+            attachment_layers(&(2..8), Some(4)),
+            Some((ViewKind::D2Array, 2..6))
+        );
+        assert_eq!(attachment_layers(&(0..3), Some(4)), None);
+        assert_eq!(attachment_layers(&(1..2), None), Some((ViewKind::D2, 1..2)));
+    }
+
+    #[test]
+    fn resolved_colors_are_distinct() {
+        let colors = [
+            Either::Right(RenderPassSurface),
+            Either::Left(ImageId(3, 0)),
+            Either::Left(ImageId(3, 0)),
+        ];
+        assert_eq!(
+            resolved_colors(&colors),
+            vec![
+                Either::Left(ImageId(3, 0)),
+                Either::Right(RenderPassSurface)
+            ],
+        );
+    }
+
+    #[test]
+    fn multisampled_colors_follow_declared_attachments() {
+        let attachments = [
+            Either::Left(ImageId(0, 0)),
+            Either::Left(ImageId(1, 0)),
+            Either::Left(ImageId(2, 0)),
+            Either::Right(RenderPassSurface),
+        ];
+        let resolved = resolved_colors(&[
+            Either::Right(RenderPassSurface),
+            Either::Left(ImageId(1, 0)),
+        ]);
+
+        assert_eq!(
+            color_attachment_index(&attachments, &resolved, Either::Left(ImageId(1, 0))),
+            4
+        );
+        assert_eq!(
+            color_attachment_index(&attachments, &resolved, Either::Right(RenderPassSurface)),
+            5
+        );
+        assert_eq!(
+            color_attachment_index(&attachments, &[], Either::Left(ImageId(2, 0))),
+            2
+        );
+    }
+
+    #[test]
+    fn multisampled_colors_are_resolved_at_end_of_pass() {
+        use rendy_core::hal::{
+            format::Format,
+            pass::{AttachmentLoadOp, AttachmentStoreOp},
+        };
+
+        let target = resolve_target(Format::Rgba8Srgb, Layout::PresentSrc, 1);
+        assert_eq!(target.samples, 1);
+        assert_eq!(target.ops.load, AttachmentLoadOp::DontCare);
+        assert_eq!(target.ops.store, AttachmentStoreOp::Store);
+        assert_eq!(target.layouts, Layout::Undefined..Layout::PresentSrc);
+
+        let cleared = multisampled_color(Format::Rgba8Srgb, true, 4);
+        assert_eq!(cleared.format, Some(Format::Rgba8Srgb));
+        assert_eq!(cleared.samples, 4);
+        assert_eq!(cleared.ops.load, AttachmentLoadOp::Clear);
+        assert_eq!(cleared.ops.store, AttachmentStoreOp::DontCare);
+        assert_eq!(
+            cleared.layouts,
+            Layout::Undefined..Layout::ColorAttachmentOptimal
+        );
+
+        let loaded = multisampled_color(Format::Rgba8Srgb, false, 4);
+        assert_eq!(loaded.ops.load, AttachmentLoadOp::DontCare);
+    }
+
+    #[test]
+    fn custom_dependencies_are_passed_to_render_pass() {
+        use rendy_core::hal::{
+            image::Access, memory::Dependencies, pass::SubpassDependency, pso::PipelineStage,
+        };
+
+        let by_region = SubpassDependency {
+            passes: None..Some(0),
+            stages: PipelineStage::COLOR_ATTACHMENT_OUTPUT..PipelineStage::FRAGMENT_SHADER,
+            accesses: Access::COLOR_ATTACHMENT_WRITE..Access::INPUT_ATTACHMENT_READ,
+            flags: Dependencies::BY_REGION,
+        };
+        let dependencies =
+            subpass_dependencies(&[SubpassAttachments::default()], &[by_region.clone()]).unwrap();
+        assert_eq!(dependencies, vec![by_region.clone()]);
+
+        let missing = SubpassDependency {
+            passes: Some(0)..Some(1),
+            ..by_region
+        };
+        match subpass_dependencies(&[SubpassAttachments::default()], &[missing]) {
+            Err(NodeBuildError::SubpassDependency(0)) => {}
+            result => panic!("Unexpected result {:?}", result),
+        }
+    }
+
+    #[test]
+    fn gbuffer_is_read_by_lighting_subpass() {
+        use rendy_core::hal::{
+            image::Access, memory::Dependencies, pass::SubpassDependency, pso::PipelineStage,
+        };
+
+        let albedo = Either::Left(ImageId(0, 0));
+        let normal = Either::Left(ImageId(1, 0));
+        let depth = Either::Left(ImageId(2, 0));
+        let surface = Either::Right(RenderPassSurface);
+
+        let gbuffer = SubpassAttachments {
+            inputs: vec![],
+            colors: vec![albedo, normal],
+            depth_stencil: Some(depth),
+        };
+        let lighting = SubpassAttachments {
+            inputs: vec![albedo, normal, depth],
+            colors: vec![surface],
+            depth_stencil: None,
+        };
+
+        let dependencies = subpass_dependencies(&[gbuffer, lighting], &[]).unwrap();
+        assert_eq!(
+            dependencies,
+            vec![SubpassDependency {
+                passes: Some(0)..Some(1),
+                stages: PipelineStage::COLOR_ATTACHMENT_OUTPUT
+                    | PipelineStage::EARLY_FRAGMENT_TESTS
+                    | PipelineStage::LATE_FRAGMENT_TESTS
+                    ..PipelineStage::FRAGMENT_SHADER,
+                accesses: Access::COLOR_ATTACHMENT_READ
+                    | Access::COLOR_ATTACHMENT_WRITE
+                    | Access::DEPTH_STENCIL_ATTACHMENT_READ
+                    | Access::DEPTH_STENCIL_ATTACHMENT_WRITE
+                    ..Access::INPUT_ATTACHMENT_READ,
+                flags: Dependencies::BY_REGION,
+            }]
+        );
+    }
+
+    #[test]
+    fn unused_attachments_are_preserved_between_subpasses() {
+        let albedo = Either::Left(ImageId(0, 0));
+        let bloom = Either::Left(ImageId(1, 0));
+        let subpasses = [
+            SubpassAttachments {
+                colors: vec![albedo],
+                ..SubpassAttachments::default()
+            },
+            SubpassAttachments {
+                colors: vec![bloom],
+                ..SubpassAttachments::default()
+            },
+            SubpassAttachments {
+                inputs: vec![albedo, bloom],
+                ..SubpassAttachments::default()
+            },
+        ];
+
+        assert_eq!(preserved_attachments(&subpasses, 0), vec![]);
+        assert_eq!(preserved_attachments(&subpasses, 1), vec![albedo]);
+        assert_eq!(preserved_attachments(&subpasses, 2), vec![]);
+
+        // Subpasses writing distinct attachments don't depend on each other.
+        let dependencies = subpass_dependencies(&subpasses, &[]).unwrap();
+        assert_eq!(
+            dependencies
+                .iter()
+                .map(|dependency| dependency.passes.clone())
+                .collect::<Vec<_>>(),
+            vec![Some(0)..Some(2), Some(1)..Some(2)]
+        );
+    }
+
+    #[test]
+    fn depth_only_clear_loads_stencil() {
+        use rendy_core::hal::{
+            format::Aspects,
+            pass::{AttachmentLoadOp, AttachmentStoreOp},
+        };
+
+        let depth_stencil = Aspects::DEPTH | Aspects::STENCIL;
+        let (ops, stencil_ops, discard) = attachment_ops(depth_stencil, Aspects::DEPTH);
+        assert_eq!(ops.load, AttachmentLoadOp::Clear);
+        assert_eq!(stencil_ops.load, AttachmentLoadOp::Load);
+        assert_eq!(stencil_ops.store, AttachmentStoreOp::Store);
+        assert!(!discard);
+
+        // Depth-only format has no stencil to keep.
+        let (ops, stencil_ops, discard) = attachment_ops(Aspects::DEPTH, Aspects::DEPTH);
+        assert_eq!(ops.load, AttachmentLoadOp::Clear);
+        assert_eq!(stencil_ops.load, AttachmentLoadOp::DontCare);
+        assert!(discard);
+    }
+
+    #[test]
+    fn stencil_only_clear_loads_depth() {
+        use rendy_core::hal::{format::Aspects, pass::AttachmentLoadOp};
+
+        let depth_stencil = Aspects::DEPTH | Aspects::STENCIL;
+        let (ops, stencil_ops, discard) = attachment_ops(depth_stencil, Aspects::STENCIL);
+        assert_eq!(ops.load, AttachmentLoadOp::Load);
+        assert_eq!(stencil_ops.load, AttachmentLoadOp::Clear);
+        assert!(!discard);
+
+        let (_, stencil_ops, discard) = attachment_ops(depth_stencil, depth_stencil);
+        assert_eq!(stencil_ops.load, AttachmentLoadOp::Clear);
+        assert!(discard);
+
+        // Not cleared attachment keeps its content.
+        let (ops, _, discard) = attachment_ops(Aspects::COLOR, Aspects::empty());
+        assert_eq!(ops.load, AttachmentLoadOp::Load);
+        assert!(!discard);
+    }
+
+    #[test]
+    fn transient_depth_is_not_stored() {
+        use rendy_core::hal::{
+            format::Aspects,
+            pass::{AttachmentLoadOp, AttachmentStoreOp},
+        };
+
+        let depth_stencil = Aspects::DEPTH | Aspects::STENCIL;
+        let (ops, stencil_ops, discard) = override_ops(
+            attachment_ops(depth_stencil, depth_stencil),
+            depth_stencil,
+            None,
+            Some(AttachmentStoreOp::DontCare),
+        );
+        assert_eq!(ops.load, AttachmentLoadOp::Clear);
+        assert_eq!(ops.store, AttachmentStoreOp::DontCare);
+        assert_eq!(stencil_ops.store, AttachmentStoreOp::DontCare);
+        assert!(discard);
+
+        // Depth-only format has no stencil to override.
+        let (ops, stencil_ops, _) = override_ops(
+            attachment_ops(Aspects::DEPTH, Aspects::DEPTH),
+            Aspects::DEPTH,
+            None,
+            Some(AttachmentStoreOp::Store),
+        );
+        assert_eq!(ops.store, AttachmentStoreOp::Store);
+        assert_eq!(stencil_ops.store, AttachmentStoreOp::DontCare);
+
+        // Forced load keeps content of cleared image.
+        let (ops, _, discard) = override_ops(
+            attachment_ops(Aspects::COLOR, Aspects::COLOR),
+            Aspects::COLOR,
+            Some(AttachmentLoadOp::Load),
+            None,
+        );
+        assert_eq!(ops.load, AttachmentLoadOp::Load);
+        assert!(!discard);
+    }
+}
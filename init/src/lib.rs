@@ -9,11 +9,13 @@ use {
     rendy_command::Families,
     rendy_core::{
         backend_enum,
-        hal::{device::CreationError, Backend, Instance as _, UnsupportedBackend},
+        hal::{Backend, Instance as _, UnsupportedBackend},
         rendy_backend, rendy_with_dx12_backend, rendy_with_empty_backend, rendy_with_gl_backend,
         rendy_with_metal_backend, rendy_with_vulkan_backend, EnabledBackend, Instance,
     },
-    rendy_factory::{Config, DevicesConfigure, Factory, HeapsConfigure, QueuesConfigure},
+    rendy_factory::{
+        Config, DevicesConfigure, Factory, FactoryInitError, HeapsConfigure, QueuesConfigure,
+    },
 };
 
 #[cfg(feature = "winit")]
@@ -25,16 +27,16 @@ pub use windowed::*;
 /// Error during rendy initialization
 #[derive(Clone, Debug, PartialEq)]
 pub enum RendyInitError {
-    /// Gfx creation error.
-    CreationError(CreationError),
+    /// Factory creation error.
+    FactoryInitError(FactoryInitError),
 
     /// Backend is unsupported.
     UnsupportedBackend(UnsupportedBackend),
 }
 
-impl From<CreationError> for RendyInitError {
-    fn from(err: CreationError) -> Self {
-        RendyInitError::CreationError(err)
+impl From<FactoryInitError> for RendyInitError {
+    fn from(err: FactoryInitError) -> Self {
+        RendyInitError::FactoryInitError(err)
     }
 }
 
@@ -47,7 +49,7 @@ impl From<UnsupportedBackend> for RendyInitError {
 impl std::fmt::Display for RendyInitError {
     fn fmt(&self, fmt: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         match self {
-            RendyInitError::CreationError(err) => write!(fmt, "Cannot init rendy: {:#?}", err),
+            RendyInitError::FactoryInitError(err) => write!(fmt, "Cannot init rendy: {}", err),
             RendyInitError::UnsupportedBackend(err) => write!(fmt, "Cannot init rendy: {:#?}", err),
         }
     }
@@ -56,7 +58,7 @@ impl std::fmt::Display for RendyInitError {
 impl std::error::Error for RendyInitError {
     fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
         match self {
-            RendyInitError::CreationError(_err) => None, // Should be `Some(err)`
+            RendyInitError::FactoryInitError(err) => Some(err),
             RendyInitError::UnsupportedBackend(_err) => None, // Should be `Some(err)`
         }
     }
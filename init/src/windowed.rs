@@ -3,10 +3,12 @@ use {
     rendy_command::Families,
     rendy_core::{
         backend_enum,
-        hal::{self, device::CreationError, Backend, UnsupportedBackend},
+        hal::{self, Backend, UnsupportedBackend},
         rendy_backend, rendy_with_gl_backend, EnabledBackend,
     },
-    rendy_factory::{Config, DevicesConfigure, Factory, HeapsConfigure, QueuesConfigure},
+    rendy_factory::{
+        Config, DevicesConfigure, Factory, FactoryInitError, HeapsConfigure, QueuesConfigure,
+    },
     rendy_wsi::Surface,
     std::borrow::Cow,
     winit::{
@@ -42,9 +44,9 @@ impl From<RendyInitError> for WindowedRendyInitError {
     }
 }
 
-impl From<CreationError> for WindowedRendyInitError {
-    fn from(err: CreationError) -> Self {
-        WindowedRendyInitError::RendyInitError(RendyInitError::CreationError(err))
+impl From<FactoryInitError> for WindowedRendyInitError {
+    fn from(err: FactoryInitError) -> Self {
+        WindowedRendyInitError::RendyInitError(RendyInitError::FactoryInitError(err))
     }
 }
 
@@ -632,31 +632,12 @@ where
     }
 
     fn acquire_blocks(&mut self, count: u32, block_size: u64, align: u64) -> Option<u32> {
-        debug_assert!(count > 0 && count <= MAX_BLOCKS_PER_CHUNK);
-
-        // Holds a bit-array of all positions with `count` free blocks.
-        let mut blocks = !0;
-        for i in 0..count {
-            blocks &= self.blocks >> i;
-        }
-        // Find a position in `blocks` that is aligned.
-        while blocks != 0 {
-            let index = blocks.trailing_zeros();
-            blocks &= !(1 << index);
-
-            if (index as u64 * block_size) & (align - 1) == 0 {
-                let mask = ((1 << count) - 1) << index;
-                self.blocks &= !mask;
-                return Some(index);
-            }
-        }
-        None
+        let offset = self.range().start;
+        acquire_blocks(&mut self.blocks, offset, count, block_size, align)
     }
 
     fn release_blocks(&mut self, index: u32, count: u32) {
-        let mask = ((1 << count) - 1) << index;
-        debug_assert_eq!(self.blocks & mask, 0);
-        self.blocks |= mask;
+        release_blocks(&mut self.blocks, index, count)
     }
 
     fn mapping_ptr(&self) -> Option<NonNull<u8>> {
@@ -667,8 +648,93 @@ where
     }
 }
 
+/// Find `count` consecutive free blocks in `blocks` mask and mark them used.
+/// Returns index of the first block.
+///
+/// Free blocks are set bits of the mask. Released blocks are merged with free neighbors
+/// simply by setting their bits back, so contiguous free regions are found
+/// regardless of the order in which blocks were freed.
+///
+/// `offset` is offset of the chunk in memory object.
+/// Alignment is checked against offset in memory object
+/// since chunks allocated from bigger blocks may start at any block boundary.
+fn acquire_blocks(
+    blocks: &mut u64,
+    offset: u64,
+    count: u32,
+    block_size: u64,
+    align: u64,
+) -> Option<u32> {
+    debug_assert!(count > 0 && count <= MAX_BLOCKS_PER_CHUNK);
+
+    // Holds a bit-array of all positions with `count` free blocks.
+    let mut candidates = !0;
+    for i in 0..count {
+        candidates &= *blocks >> i;
+    }
+    // Find a position in `candidates` that is aligned.
+    while candidates != 0 {
+        let index = candidates.trailing_zeros();
+        candidates &= !(1 << index);
+
+        if (offset + index as u64 * block_size) & (align - 1) == 0 {
+            let mask = ((1 << count) - 1) << index;
+            *blocks &= !mask;
+            return Some(index);
+        }
+    }
+    None
+}
+
+/// Mark `count` blocks starting from `index` free.
+fn release_blocks(blocks: &mut u64, index: u32, count: u32) {
+    let mask = ((1 << count) - 1) << index;
+    debug_assert_eq!(*blocks & mask, 0);
+    *blocks |= mask;
+}
+
 fn max_chunks_per_size() -> usize {
     let value = (std::mem::size_of::<usize>() * 8).pow(4);
     debug_assert!(fits_u32(value));
     value
 }
+
+#[cfg(test)]
+mod tests {
+    use super::{acquire_blocks, release_blocks, MAX_BLOCKS_PER_CHUNK};
+
+    #[test]
+    fn freed_neighbors_coalesce() {
+        // Chunk of 64 free blocks.
+        let mut blocks = !0u64;
+
+        let indices: Vec<u32> = (0..MAX_BLOCKS_PER_CHUNK)
+            .map(|_| acquire_blocks(&mut blocks, 0, 1, 256, 256).unwrap())
+            .collect();
+        assert_eq!(blocks, 0);
+        assert_eq!(acquire_blocks(&mut blocks, 0, 1, 256, 256), None);
+
+        // Freeing every other block leaves no room for two consecutive blocks.
+        for &index in indices.iter().step_by(2) {
+            release_blocks(&mut blocks, index, 1);
+        }
+        assert_eq!(acquire_blocks(&mut blocks, 0, 2, 256, 256), None);
+
+        // Freeing the rest makes whole chunk available again.
+        for &index in indices.iter().skip(1).step_by(2) {
+            release_blocks(&mut blocks, index, 1);
+        }
+        assert_eq!(acquire_blocks(&mut blocks, 0, 7, 256, 256), Some(0));
+        assert_eq!(acquire_blocks(&mut blocks, 0, 7, 256, 256), Some(7));
+    }
+
+    #[test]
+    fn alignment_respects_chunk_offset() {
+        let mut blocks = 0xFFu64;
+
+        // Chunk starts at 256, so block 3 is the first one aligned to 1024.
+        assert_eq!(acquire_blocks(&mut blocks, 256, 1, 256, 1024), Some(3));
+        assert_eq!(acquire_blocks(&mut blocks, 256, 2, 256, 1024), None);
+        assert_eq!(acquire_blocks(&mut blocks, 256, 1, 256, 1024), Some(7));
+    }
+}
@@ -44,6 +44,7 @@ impl MemoryHeap {
                 effective: self.effective,
             },
             size: self.size,
+            budget: None,
         }
     }
 }
@@ -73,50 +73,27 @@ where
         size: u64,
         align: u64,
     ) -> Result<(BlockFlavor<B>, u64), gfx_hal::device::AllocationError> {
-        match (self.dynamic.as_mut(), self.linear.as_mut()) {
-            (Some(dynamic), Some(linear)) => {
-                if dynamic.max_allocation() >= size
-                    && usage.allocator_fitness(Kind::Dynamic)
-                        > usage.allocator_fitness(Kind::Linear)
-                {
-                    dynamic
-                        .alloc(device, size, align)
-                        .map(|(block, size)| (BlockFlavor::Dynamic(block), size))
-                } else if linear.max_allocation() >= size
-                    && usage.allocator_fitness(Kind::Linear) > 0
-                {
-                    linear
-                        .alloc(device, size, align)
-                        .map(|(block, size)| (BlockFlavor::Linear(block), size))
-                } else {
-                    self.dedicated
-                        .alloc(device, size, align)
-                        .map(|(block, size)| (BlockFlavor::Dedicated(block), size))
-                }
-            }
-            (Some(dynamic), None) => {
-                if dynamic.max_allocation() >= size && usage.allocator_fitness(Kind::Dynamic) > 0 {
-                    dynamic
-                        .alloc(device, size, align)
-                        .map(|(block, size)| (BlockFlavor::Dynamic(block), size))
-                } else {
-                    self.dedicated
-                        .alloc(device, size, align)
-                        .map(|(block, size)| (BlockFlavor::Dedicated(block), size))
-                }
-            }
-            (None, Some(linear)) => {
-                if linear.max_allocation() >= size && usage.allocator_fitness(Kind::Linear) > 0 {
-                    linear
-                        .alloc(device, size, align)
-                        .map(|(block, size)| (BlockFlavor::Linear(block), size))
-                } else {
-                    self.dedicated
-                        .alloc(device, size, align)
-                        .map(|(block, size)| (BlockFlavor::Dedicated(block), size))
-                }
-            }
-            (None, None) => self
+        let kind = choose_allocator(
+            &usage,
+            size,
+            self.dynamic.as_ref().map(DynamicAllocator::max_allocation),
+            self.linear.as_ref().map(LinearAllocator::max_allocation),
+        );
+
+        match kind {
+            Kind::Dynamic => self
+                .dynamic
+                .as_mut()
+                .unwrap()
+                .alloc(device, size, align)
+                .map(|(block, size)| (BlockFlavor::Dynamic(block), size)),
+            Kind::Linear => self
+                .linear
+                .as_mut()
+                .unwrap()
+                .alloc(device, size, align)
+                .map(|(block, size)| (BlockFlavor::Linear(block), size)),
+            Kind::Dedicated => self
                 .dedicated
                 .alloc(device, size, align)
                 .map(|(block, size)| (BlockFlavor::Dedicated(block), size)),
@@ -155,3 +132,63 @@ where
         }
     }
 }
+
+/// Pick allocator for `size` bytes by usage fitness.
+/// `dynamic` and `linear` are maximum allocation sizes of allocators present in memory type.
+/// Dedicated allocator is used when neither fits.
+fn choose_allocator(
+    usage: &impl MemoryUsage,
+    size: u64,
+    dynamic: Option<u64>,
+    linear: Option<u64>,
+) -> Kind {
+    let linear_fitness = linear.map_or(0, |_| usage.allocator_fitness(Kind::Linear));
+
+    if dynamic.map_or(false, |max| max >= size)
+        && usage.allocator_fitness(Kind::Dynamic) > linear_fitness
+    {
+        Kind::Dynamic
+    } else if linear.map_or(false, |max| max >= size) && linear_fitness > 0 {
+        Kind::Linear
+    } else {
+        Kind::Dedicated
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use {
+        super::choose_allocator,
+        crate::{allocator::Kind, usage::*},
+    };
+
+    #[test]
+    fn usages_route_to_allocators() {
+        let both =
+            |usage: &dyn MemoryUsage, size| choose_allocator(&usage, size, Some(1024), Some(1024));
+
+        assert_eq!(both(&Data, 256), Kind::Dynamic);
+        assert_eq!(both(&Dynamic, 256), Kind::Dynamic);
+        assert_eq!(both(&Upload, 256), Kind::Linear);
+        assert_eq!(both(&Download, 256), Kind::Linear);
+        assert_eq!(both(&Data, 4096), Kind::Dedicated);
+        assert_eq!(
+            choose_allocator(&Upload, 256, Some(1024), None),
+            Kind::Dynamic
+        );
+
+        let policy = AllocatorPolicy::default()
+            .with(MemoryUsageValue::Data, Kind::Dedicated)
+            .with(MemoryUsageValue::Upload, Kind::Dynamic);
+        let preferred = |usage: MemoryUsageValue, size| {
+            let kind = policy.get(usage).unwrap();
+            both(&PreferAllocator::new(usage, kind), size)
+        };
+        assert_eq!(preferred(MemoryUsageValue::Data, 256), Kind::Dedicated);
+        assert_eq!(preferred(MemoryUsageValue::Upload, 256), Kind::Dynamic);
+        assert_eq!(policy.get(MemoryUsageValue::Dynamic), None);
+
+        // Preferred allocator still must fit the allocation.
+        assert_eq!(preferred(MemoryUsageValue::Upload, 4096), Kind::Dedicated);
+    }
+}
@@ -3,7 +3,14 @@ mod memory_type;
 
 use {
     self::{heap::MemoryHeap, memory_type::MemoryType},
-    crate::{allocator::*, block::Block, mapping::*, usage::MemoryUsage, util::*, utilization::*},
+    crate::{
+        allocator::*,
+        block::Block,
+        mapping::*,
+        usage::{AllocatorPolicy, MemoryUsage, PreferAllocator},
+        util::*,
+        utilization::*,
+    },
     std::ops::Range,
 };
 
@@ -59,6 +66,7 @@ pub struct HeapsConfig {
 pub struct Heaps<B: gfx_hal::Backend> {
     types: Vec<MemoryType<B>>,
     heaps: Vec<MemoryHeap>,
+    policy: AllocatorPolicy,
 }
 
 impl<B> Heaps<B>
@@ -95,9 +103,22 @@ where
                 })
                 .collect(),
             heaps,
+            policy: AllocatorPolicy::default(),
         }
     }
 
+    /// Get allocator policy.
+    pub fn allocator_policy(&self) -> &AllocatorPolicy {
+        &self.policy
+    }
+
+    /// Set allocator policy.
+    /// Allocations with well-known usage types use allocators it specifies.
+    /// Allocated blocks are not affected.
+    pub fn set_allocator_policy(&mut self, policy: AllocatorPolicy) {
+        self.policy = policy;
+    }
+
     /// Allocate memory block
     /// from one of memory types specified by `mask`,
     /// for intended `usage`,
@@ -146,7 +167,16 @@ where
                 })?
         };
 
-        self.allocate_from(device, memory_index as u32, usage, size, align)
+        match usage.value().and_then(|value| self.policy.get(value)) {
+            Some(kind) => self.allocate_from(
+                device,
+                memory_index as u32,
+                PreferAllocator::new(usage, kind),
+                size,
+                align,
+            ),
+            None => self.allocate_from(device, memory_index as u32, usage, size, align),
+        }
     }
 
     /// Allocate memory block
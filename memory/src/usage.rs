@@ -17,6 +17,12 @@ pub trait MemoryUsage: std::fmt::Debug {
 
     /// Get comparable fitness value for memory allocator.
     fn allocator_fitness(&self, kind: Kind) -> u32;
+
+    /// Get well-known usage type this usage represents.
+    /// Used to look allocator up in `AllocatorPolicy`.
+    fn value(&self) -> Option<MemoryUsageValue> {
+        None
+    }
 }
 
 impl<T> MemoryUsage for T
@@ -33,6 +39,9 @@ where
     fn allocator_fitness(&self, kind: Kind) -> u32 {
         (&**self).allocator_fitness(kind)
     }
+    fn value(&self) -> Option<MemoryUsageValue> {
+        (&**self).value()
+    }
 }
 
 /// Full speed GPU access.
@@ -62,6 +71,10 @@ impl MemoryUsage for Data {
             Kind::Linear => 0,
         }
     }
+
+    fn value(&self) -> Option<MemoryUsageValue> {
+        Some(MemoryUsageValue::Data)
+    }
 }
 
 /// CPU to GPU data flow with update commands.
@@ -93,6 +106,10 @@ impl MemoryUsage for Dynamic {
             Kind::Linear => 0,
         }
     }
+
+    fn value(&self) -> Option<MemoryUsageValue> {
+        Some(MemoryUsageValue::Dynamic)
+    }
 }
 
 /// CPU to GPU data flow with mapping.
@@ -123,6 +140,10 @@ impl MemoryUsage for Upload {
             Kind::Linear => 2,
         }
     }
+
+    fn value(&self) -> Option<MemoryUsageValue> {
+        Some(MemoryUsageValue::Upload)
+    }
 }
 
 /// GPU to CPU data flow with mapping.
@@ -153,6 +174,10 @@ impl MemoryUsage for Download {
             Kind::Linear => 2,
         }
     }
+
+    fn value(&self) -> Option<MemoryUsageValue> {
+        Some(MemoryUsageValue::Download)
+    }
 }
 
 /// Well-known memory usage types.
@@ -207,4 +232,92 @@ impl MemoryUsage for MemoryUsageValue {
             MemoryUsageValue::Download => Download.allocator_fitness(kind),
         }
     }
+    fn value(&self) -> Option<MemoryUsageValue> {
+        Some(*self)
+    }
+}
+
+/// Allocator to use for well-known memory usage types
+/// instead of the one picked by usage's allocator fitness.
+///
+/// Not every allocator is available in every memory type,
+/// and allocation that doesn't fit preferred allocator is served by dedicated one.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct AllocatorPolicy {
+    /// Allocator for `Data` usage.
+    pub data: Option<Kind>,
+
+    /// Allocator for `Dynamic` usage.
+    pub dynamic: Option<Kind>,
+
+    /// Allocator for `Upload` usage.
+    pub upload: Option<Kind>,
+
+    /// Allocator for `Download` usage.
+    pub download: Option<Kind>,
+}
+
+impl AllocatorPolicy {
+    /// Get allocator preferred for the usage.
+    pub fn get(&self, usage: MemoryUsageValue) -> Option<Kind> {
+        match usage {
+            MemoryUsageValue::Data => self.data,
+            MemoryUsageValue::Dynamic => self.dynamic,
+            MemoryUsageValue::Upload => self.upload,
+            MemoryUsageValue::Download => self.download,
+        }
+    }
+
+    /// Set allocator preferred for the usage.
+    pub fn set(&mut self, usage: MemoryUsageValue, kind: Kind) -> &mut Self {
+        *match usage {
+            MemoryUsageValue::Data => &mut self.data,
+            MemoryUsageValue::Dynamic => &mut self.dynamic,
+            MemoryUsageValue::Upload => &mut self.upload,
+            MemoryUsageValue::Download => &mut self.download,
+        } = Some(kind);
+        self
+    }
+
+    /// Set allocator preferred for the usage.
+    pub fn with(mut self, usage: MemoryUsageValue, kind: Kind) -> Self {
+        self.set(usage, kind);
+        self
+    }
+}
+
+/// Usage that prefers specified allocator over all others.
+/// Memory type is still picked by the wrapped usage.
+#[derive(Clone, Copy, Debug)]
+pub struct PreferAllocator<U> {
+    usage: U,
+    kind: Kind,
+}
+
+impl<U> PreferAllocator<U> {
+    /// Wrap `usage` to allocate with `kind` allocator.
+    pub fn new(usage: U, kind: Kind) -> Self {
+        PreferAllocator { usage, kind }
+    }
+}
+
+impl<U> MemoryUsage for PreferAllocator<U>
+where
+    U: MemoryUsage,
+{
+    fn properties_required(&self) -> gfx_hal::memory::Properties {
+        self.usage.properties_required()
+    }
+
+    fn memory_fitness(&self, properties: gfx_hal::memory::Properties) -> u32 {
+        self.usage.memory_fitness(properties)
+    }
+
+    fn allocator_fitness(&self, kind: Kind) -> u32 {
+        (kind == self.kind) as u32
+    }
+
+    fn value(&self) -> Option<MemoryUsageValue> {
+        self.usage.value()
+    }
 }
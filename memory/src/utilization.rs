@@ -20,6 +20,34 @@ pub struct MemoryHeapUtilization {
 
     /// Memory heap size.
     pub size: u64,
+
+    /// Budget reported by the driver.
+    /// `None` if backend doesn't report memory budget.
+    pub budget: Option<MemoryHeapBudget>,
+}
+
+impl MemoryHeapUtilization {
+    /// Number of bytes that can still be allocated from the heap.
+    /// Uses driver reported budget if available and allocator bookkeeping otherwise.
+    pub fn available(&self) -> u64 {
+        match self.budget {
+            Some(budget) => budget.budget.saturating_sub(budget.usage),
+            None => self.size.saturating_sub(self.utilization.used),
+        }
+    }
+}
+
+/// Memory budget of one heap reported by the driver.
+///
+/// Budget figures are best-effort and may be stale by the time they are read.
+#[derive(Clone, Copy, Debug)]
+pub struct MemoryHeapBudget {
+    /// Number of bytes the process can allocate from the heap without degrading performance.
+    pub budget: u64,
+
+    /// Number of bytes the process currently uses from the heap,
+    /// including allocations made outside of the allocator.
+    pub usage: u64,
 }
 
 /// Memory utilization of one type.
@@ -79,6 +107,15 @@ impl std::fmt::Display for TotalMemoryUtilization {
                 line
             )?;
 
+            if let Some(MemoryHeapBudget { budget, usage }) = heap.budget {
+                writeln!(
+                    fmt,
+                    "         budget: {:>6} / {:<6}",
+                    format!("{}MB", usage / MB),
+                    format!("{}MB", budget / MB),
+                )?;
+            }
+
             for ty in self.types.iter().filter(|ty| ty.heap_index == index) {
                 let properties = ty.properties;
                 let MemoryUtilization { used, effective } = ty.utilization;
@@ -0,0 +1,260 @@
+//!
+//! Derive macro for `rendy_mesh::AsVertex`.
+//!
+//! Vertex format is generated from `#[repr(C)]` struct fields
+//! with offsets taken from the actual struct layout and stride equal to struct size.
+//!
+//! Field attributes:
+//!
+//! * No attribute - field type implements `AsVertex`, e.g. `Position` or another derived vertex.
+//!   Its attributes are flattened into the vertex with offsets shifted by the field offset.
+//! * `#[rendy(format = "Rgb32Sfloat")]` - field is single attribute with specified `Format`.
+//!   Attribute is named after the field, `#[rendy(format = "...", name = "...")]` overrides it.
+//! * `#[rendy(skip)]` - field is not an attribute, e.g. padding.
+//!
+//! Generated code refers to `rendy_mesh` crate.
+//! Use `#[rendy(crate = "rendy::mesh")]` on the struct if it is accessed through another path.
+//!
+//! ```ignore
+//! #[repr(C)]
+//! #[derive(Clone, Copy, Debug, PartialEq, PartialOrd, AsVertex)]
+//! struct Vertex {
+//!     position: Position,
+//!     #[rendy(format = "Rg32Sfloat", name = "tex_coord")]
+//!     uv: [f32; 2],
+//! }
+//! ```
+//!
+
+#![warn(
+    missing_debug_implementations,
+    missing_copy_implementations,
+    missing_docs,
+    trivial_casts,
+    trivial_numeric_casts,
+    unused_extern_crates,
+    unused_import_braces,
+    unused_qualifications
+)]
+
+extern crate proc_macro;
+
+use {
+    proc_macro2::{Span, TokenStream},
+    quote::quote,
+    syn::{
+        parse_macro_input, spanned::Spanned, Attribute, Data, DeriveInput, Error, Fields, Ident,
+        Index, Lit, LitStr, Member, Meta, NestedMeta, Path,
+    },
+};
+
+/// Derive `AsVertex` for `#[repr(C)]` struct.
+/// See crate level docs for supported attributes.
+#[proc_macro_derive(AsVertex, attributes(rendy))]
+pub fn derive_as_vertex(input: proc_macro::TokenStream) -> proc_macro::TokenStream {
+    let input = parse_macro_input!(input as DeriveInput);
+    match as_vertex(&input) {
+        Ok(tokens) => tokens.into(),
+        Err(err) => err.to_compile_error().into(),
+    }
+}
+
+/// How vertex attributes are produced from a field.
+enum FieldKind {
+    Nested,
+    Attribute { format: Ident, name: Option<LitStr> },
+    Skip,
+}
+
+fn as_vertex(input: &DeriveInput) -> Result<TokenStream, Error> {
+    check_repr(input)?;
+
+    let krate = crate_path(&input.attrs)?;
+
+    let fields = match &input.data {
+        Data::Struct(data) => &data.fields,
+        _ => {
+            return Err(Error::new_spanned(
+                input,
+                "AsVertex can be derived only for structs",
+            ))
+        }
+    };
+
+    let mut attributes = Vec::new();
+    for (index, field) in fields.iter().enumerate() {
+        let member = match &field.ident {
+            Some(ident) => Member::Named(ident.clone()),
+            None => Member::Unnamed(Index {
+                index: index as u32,
+                span: field.span(),
+            }),
+        };
+
+        let offset = quote! {
+            // Field address is taken without creating reference to uninitialized memory.
+            let offset = unsafe {
+                let field = std::ptr::addr_of!((*base).#member);
+                field as usize - base as usize
+            } as u32;
+        };
+
+        let tokens = match field_kind(&field.attrs)? {
+            FieldKind::Skip => continue,
+            FieldKind::Nested => {
+                let ty = &field.ty;
+                quote! {
+                    #offset
+                    for attribute in <#ty as #krate::AsVertex>::vertex().attributes {
+                        let element = attribute.element();
+                        attributes.push(#krate::Attribute::new(
+                            attribute.name().to_owned(),
+                            attribute.index(),
+                            #krate::__derive::Element {
+                                format: element.format,
+                                offset: element.offset + offset,
+                            },
+                        ));
+                    }
+                }
+            }
+            FieldKind::Attribute { format, name } => {
+                let name = match (name, &field.ident) {
+                    (Some(name), _) => name,
+                    (None, Some(ident)) => LitStr::new(&ident.to_string(), ident.span()),
+                    (None, None) => {
+                        return Err(Error::new_spanned(
+                            field,
+                            "Attribute name must be specified for tuple struct fields",
+                        ))
+                    }
+                };
+                quote! {
+                    #offset
+                    attributes.push(#krate::Attribute::new(
+                        #name,
+                        0,
+                        #krate::__derive::Element {
+                            format: #krate::__derive::Format::#format,
+                            offset,
+                        },
+                    ));
+                }
+            }
+        };
+        attributes.push(quote! { { #tokens } });
+    }
+
+    if attributes.is_empty() {
+        return Err(Error::new_spanned(
+            input,
+            "Vertex must have at least one attribute",
+        ));
+    }
+
+    let ident = &input.ident;
+    let (impl_generics, ty_generics, where_clause) = input.generics.split_for_impl();
+
+    Ok(quote! {
+        impl #impl_generics #krate::AsVertex for #ident #ty_generics #where_clause {
+            fn vertex() -> #krate::VertexFormat {
+                let uninit = ::std::mem::MaybeUninit::<Self>::uninit();
+                let base = uninit.as_ptr();
+                let mut attributes = ::std::vec::Vec::new();
+                #(#attributes)*
+                #krate::VertexFormat::with_stride(
+                    attributes,
+                    ::std::mem::size_of::<Self>() as u32,
+                )
+            }
+        }
+    })
+}
+
+/// Offsets are meaningful only if struct layout matches declaration.
+fn check_repr(input: &DeriveInput) -> Result<(), Error> {
+    for attr in input.attrs.iter().filter(|attr| attr.path.is_ident("repr")) {
+        if let Meta::List(list) = attr.parse_meta()? {
+            let stable = list.nested.iter().any(|nested| match nested {
+                NestedMeta::Meta(Meta::Path(path)) => {
+                    path.is_ident("C") || path.is_ident("transparent")
+                }
+                _ => false,
+            });
+            if stable {
+                return Ok(());
+            }
+        }
+    }
+
+    Err(Error::new_spanned(
+        &input.ident,
+        "AsVertex can be derived only for #[repr(C)] or #[repr(transparent)] structs",
+    ))
+}
+
+fn rendy_attributes(attrs: &[Attribute]) -> Result<Vec<NestedMeta>, Error> {
+    let mut nested = Vec::new();
+    for attr in attrs.iter().filter(|attr| attr.path.is_ident("rendy")) {
+        match attr.parse_meta()? {
+            Meta::List(list) => nested.extend(list.nested),
+            meta => return Err(Error::new_spanned(meta, "Expected #[rendy(...)]")),
+        }
+    }
+    Ok(nested)
+}
+
+fn crate_path(attrs: &[Attribute]) -> Result<Path, Error> {
+    let mut path = None;
+    for nested in rendy_attributes(attrs)? {
+        match nested {
+            NestedMeta::Meta(Meta::NameValue(ref value)) if value.path.is_ident("crate") => {
+                match &value.lit {
+                    Lit::Str(lit) => path = Some(lit.parse()?),
+                    lit => return Err(Error::new_spanned(lit, "Expected crate path string")),
+                }
+            }
+            nested => return Err(Error::new_spanned(nested, "Unknown struct attribute")),
+        }
+    }
+    Ok(path.unwrap_or_else(|| Ident::new("rendy_mesh", Span::call_site()).into()))
+}
+
+fn field_kind(attrs: &[Attribute]) -> Result<FieldKind, Error> {
+    let mut format = None;
+    let mut name = None;
+    let mut skip = false;
+
+    for nested in rendy_attributes(attrs)? {
+        match &nested {
+            NestedMeta::Meta(Meta::Path(path)) if path.is_ident("skip") => skip = true,
+            NestedMeta::Meta(Meta::NameValue(value)) if value.path.is_ident("format") => {
+                match &value.lit {
+                    Lit::Str(lit) => format = Some(lit.parse::<Ident>()?),
+                    lit => return Err(Error::new_spanned(lit, "Expected format name string")),
+                }
+            }
+            NestedMeta::Meta(Meta::NameValue(value)) if value.path.is_ident("name") => {
+                match &value.lit {
+                    Lit::Str(lit) => name = Some(lit.clone()),
+                    lit => return Err(Error::new_spanned(lit, "Expected attribute name string")),
+                }
+            }
+            _ => return Err(Error::new_spanned(nested, "Unknown field attribute")),
+        }
+    }
+
+    match (skip, format, name) {
+        (true, None, None) => Ok(FieldKind::Skip),
+        (true, _, _) => Err(Error::new(
+            Span::call_site(),
+            "Skipped field can't have format or name",
+        )),
+        (false, Some(format), name) => Ok(FieldKind::Attribute { format, name }),
+        (false, None, Some(name)) => Err(Error::new_spanned(
+            name,
+            "Attribute name requires format to be specified",
+        )),
+        (false, None, None) => Ok(FieldKind::Nested),
+    }
+}
@@ -0,0 +1,56 @@
+use rendy_mesh::{AsVertex, Normal, PosNorm, PosNormTex, Position, TexCoord};
+
+#[repr(C)]
+#[derive(Clone, Copy, Debug, PartialEq, PartialOrd, AsVertex)]
+struct Attributes {
+    position: Position,
+    normal: Normal,
+    tex_coord: TexCoord,
+}
+
+#[repr(C)]
+#[derive(Clone, Copy, Debug, PartialEq, PartialOrd, AsVertex)]
+struct Formats {
+    #[rendy(format = "Rgb32Sfloat")]
+    position: [f32; 3],
+    #[rendy(format = "Rgb32Sfloat")]
+    normal: [f32; 3],
+    #[rendy(format = "Rg32Sfloat", name = "tex_coord")]
+    uv: [f32; 2],
+}
+
+#[repr(C)]
+#[derive(Clone, Copy, Debug, PartialEq, PartialOrd, AsVertex)]
+struct Nested {
+    base: PosNorm,
+    tex_coord: TexCoord,
+}
+
+#[repr(C)]
+#[derive(Clone, Copy, Debug, PartialEq, PartialOrd, AsVertex)]
+struct Padded {
+    position: Position,
+    #[rendy(skip)]
+    _padding: f32,
+    normal: Normal,
+}
+
+#[test]
+fn derived_format_matches_hand_written() {
+    assert_eq!(Attributes::vertex(), PosNormTex::vertex());
+    assert_eq!(Formats::vertex(), PosNormTex::vertex());
+    assert_eq!(Nested::vertex(), PosNormTex::vertex());
+}
+
+#[test]
+fn skipped_fields_keep_offsets() {
+    let format = Padded::vertex();
+    assert_eq!(format.stride, 28);
+
+    let attributes: Vec<_> = format
+        .attributes
+        .iter()
+        .map(|attribute| (attribute.name(), attribute.element().offset))
+        .collect();
+    assert_eq!(attributes, vec![("position", 0), ("normal", 16)]);
+}
@@ -0,0 +1,72 @@
+//!
+//! Bounding volumes of meshes for culling.
+//!
+
+/// Axis-aligned bounding box.
+#[derive(Clone, Copy, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct BoundingBox {
+    /// Minimal coordinates of the box.
+    pub min: [f32; 3],
+    /// Maximal coordinates of the box.
+    pub max: [f32; 3],
+}
+
+impl BoundingBox {
+    /// Get smallest box that contains all points.
+    /// Returns `None` if there are no points.
+    pub fn from_points(points: impl IntoIterator<Item = [f32; 3]>) -> Option<Self> {
+        let mut points = points.into_iter();
+        let first = points.next()?;
+        Some(points.fold(
+            BoundingBox {
+                min: first,
+                max: first,
+            },
+            |mut aabb, point| {
+                for axis in 0..3 {
+                    aabb.min[axis] = aabb.min[axis].min(point[axis]);
+                    aabb.max[axis] = aabb.max[axis].max(point[axis]);
+                }
+                aabb
+            },
+        ))
+    }
+
+    /// Center of the box.
+    pub fn center(&self) -> [f32; 3] {
+        [
+            (self.min[0] + self.max[0]) * 0.5,
+            (self.min[1] + self.max[1]) * 0.5,
+            (self.min[2] + self.max[2]) * 0.5,
+        ]
+    }
+
+    /// Size of the box along each axis.
+    pub fn extent(&self) -> [f32; 3] {
+        [
+            self.max[0] - self.min[0],
+            self.max[1] - self.min[1],
+            self.max[2] - self.min[2],
+        ]
+    }
+
+    /// Get sphere that contains the box.
+    pub fn bounding_sphere(&self) -> BoundingSphere {
+        let [x, y, z] = self.extent();
+        BoundingSphere {
+            center: self.center(),
+            radius: (x * x + y * y + z * z).sqrt() * 0.5,
+        }
+    }
+}
+
+/// Bounding sphere.
+#[derive(Clone, Copy, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct BoundingSphere {
+    /// Center of the sphere.
+    pub center: [f32; 3],
+    /// Radius of the sphere.
+    pub radius: f32,
+}
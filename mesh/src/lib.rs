@@ -22,8 +22,21 @@ use rendy_factory as factory;
 use rendy_memory as memory;
 use rendy_resource as resource;
 
+mod bounds;
 mod format;
 mod mesh;
 
-pub use crate::{format::*, mesh::*};
+pub use crate::{bounds::*, format::*, mesh::*};
 pub use rendy_core::types::vertex::*;
+
+/// Derive `AsVertex` for `#[repr(C)]` struct.
+/// See `rendy-mesh-derive` crate for field attributes.
+#[cfg(feature = "derive")]
+pub use rendy_mesh_derive::AsVertex;
+
+/// Items used by code generated with `#[derive(AsVertex)]`.
+#[cfg(feature = "derive")]
+#[doc(hidden)]
+pub mod __derive {
+    pub use rendy_core::hal::{format::Format, pso::Element};
+}
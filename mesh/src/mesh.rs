@@ -3,14 +3,15 @@
 //!
 
 use crate::{
+    bounds::{BoundingBox, BoundingSphere},
     command::{EncoderCommon, Graphics, QueueId, RenderPassEncoder, Supports},
     core::cast_cow,
     factory::{BufferState, Factory, UploadError},
     memory::{Data, Upload, Write},
     resource::{Buffer, BufferInfo, Escape},
-    AsVertex, VertexFormat,
+    AsAttribute, AsVertex, Position, VertexFormat,
 };
-use rendy_core::hal::adapter::PhysicalDevice;
+use rendy_core::hal::{adapter::PhysicalDevice, format::Format};
 use std::{borrow::Cow, mem::size_of};
 
 /// Vertex buffer with it's format
@@ -23,7 +24,9 @@ pub struct VertexBufferLayout {
 /// Index buffer with it's type
 #[derive(Debug)]
 pub struct IndexBuffer<B: rendy_core::hal::Backend> {
-    buffer: Escape<Buffer<B>>,
+    /// `None` if indices are packed into vertex buffer.
+    buffer: Option<Escape<Buffer<B>>>,
+    offset: u64,
     index_type: rendy_core::hal::IndexType,
 }
 
@@ -207,6 +210,53 @@ impl<'a> MeshBuilder<'a> {
         self
     }
 
+    /// Compute axis-aligned bounding box of vertex positions.
+    ///
+    /// Positions are taken from the first attribute named as `Position` attribute,
+    /// either interleaved with other attributes or in separate vertices.
+    /// Returns `None` if there is no such attribute with `Rgb32Sfloat` or `Rgba32Sfloat` format,
+    /// or there are no vertices.
+    pub fn aabb(&self) -> Option<BoundingBox> {
+        let len = self
+            .vertices
+            .iter()
+            .map(|v| v.vertices.len() as u32 / v.format.stride)
+            .min()
+            .unwrap_or(0);
+
+        self.vertices
+            .iter()
+            .find_map(|RawVertices { vertices, format }| {
+                let attribute = format.attributes.iter().find(|attribute| {
+                    attribute.name() == Position::NAME && attribute.index() == 0
+                })?;
+                let element = attribute.element();
+                match element.format {
+                    Format::Rgb32Sfloat | Format::Rgba32Sfloat => {}
+                    _ => return None,
+                }
+
+                let stride = format.stride as usize;
+                let offset = element.offset as usize;
+                let component = |vertex: &[u8], index: usize| {
+                    let mut bytes = [0; 4];
+                    let start = offset + index * size_of::<f32>();
+                    bytes.copy_from_slice(&vertex[start..start + size_of::<f32>()]);
+                    f32::from_ne_bytes(bytes)
+                };
+
+                BoundingBox::from_points(vertices.chunks_exact(stride).take(len as usize).map(
+                    |vertex| {
+                        [
+                            component(vertex, 0),
+                            component(vertex, 1),
+                            component(vertex, 2),
+                        ]
+                    },
+                ))
+            })
+    }
+
     /// Builds and returns the new mesh.
     ///
     /// A mesh expects all vertex buffers to have the same number of elements.
@@ -219,6 +269,7 @@ impl<'a> MeshBuilder<'a> {
         B: rendy_core::hal::Backend,
     {
         let align = factory.physical().limits().non_coherent_atom_size;
+        let aabb = self.aabb();
         let mut len = self
             .vertices
             .iter()
@@ -314,7 +365,11 @@ impl<'a> MeshBuilder<'a> {
                     )?;
                 }
 
-                Some(IndexBuffer { buffer, index_type })
+                Some(IndexBuffer {
+                    buffer: Some(buffer),
+                    offset: 0,
+                    index_type,
+                })
             }
         };
 
@@ -338,6 +393,142 @@ impl<'a> MeshBuilder<'a> {
             vertex_buffer: buffer,
             prim: self.prim,
             len,
+            aabb,
+        })
+    }
+
+    /// Builds and returns the new mesh with vertex and index data packed into single buffer.
+    /// This saves allocations and bindings when there are many small meshes.
+    ///
+    /// Vertex data goes first and indices follow at offset aligned to index size.
+    /// `Mesh::bind` and `Mesh::bind_and_draw` bind the buffer at those offsets.
+    ///
+    /// See `build` for details.
+    pub fn build_packed<B>(
+        &self,
+        queue: QueueId,
+        factory: &Factory<B>,
+    ) -> Result<Mesh<B>, UploadError>
+    where
+        B: rendy_core::hal::Backend,
+    {
+        let align = factory.physical().limits().non_coherent_atom_size;
+        let aabb = self.aabb();
+        let vertex_count = self
+            .vertices
+            .iter()
+            .map(|v| v.vertices.len() as u32 / v.format.stride)
+            .min()
+            .unwrap_or(0);
+
+        let vertex_sizes: Vec<_> = self
+            .vertices
+            .iter()
+            .map(|v| (v.format.stride * vertex_count) as usize)
+            .collect();
+
+        let (vertex_offsets, index_offset, buffer_size) = packed_offsets(
+            &vertex_sizes,
+            self.indices
+                .as_ref()
+                .map(|i| (i.indices.len(), index_stride(i.index_type))),
+        );
+
+        let aligned_size = align_by(align, buffer_size) as u64;
+
+        let mut staging = factory
+            .create_buffer(
+                BufferInfo {
+                    size: aligned_size,
+                    usage: rendy_core::hal::buffer::Usage::TRANSFER_SRC,
+                },
+                Upload,
+            )
+            .map_err(UploadError::Create)?;
+
+        let mut buffer = factory
+            .create_buffer(
+                BufferInfo {
+                    size: buffer_size as _,
+                    usage: rendy_core::hal::buffer::Usage::VERTEX
+                        | rendy_core::hal::buffer::Usage::INDEX
+                        | rendy_core::hal::buffer::Usage::TRANSFER_DST,
+                },
+                Data,
+            )
+            .map_err(UploadError::Create)?;
+
+        let mut mapped = staging
+            .map(factory, 0..aligned_size)
+            .map_err(UploadError::Map)?;
+        let mut writer =
+            unsafe { mapped.write(factory, 0..aligned_size) }.map_err(UploadError::Map)?;
+        let staging_slice = unsafe { writer.slice() };
+
+        let mut vertex_layouts: Vec<_> = self
+            .vertices
+            .iter()
+            .zip(vertex_offsets.iter().zip(&vertex_sizes))
+            .map(|(RawVertices { vertices, format }, (&offset, &size))| {
+                staging_slice[offset..offset + size].copy_from_slice(&vertices[0..size]);
+                VertexBufferLayout {
+                    offset: offset as u64,
+                    format: format.clone(),
+                }
+            })
+            .collect();
+
+        let (index_buffer, len) = match (&self.indices, index_offset) {
+            (
+                Some(RawIndices {
+                    indices,
+                    index_type,
+                }),
+                Some(offset),
+            ) => {
+                staging_slice[offset..offset + indices.len()].copy_from_slice(indices);
+                (
+                    Some(IndexBuffer {
+                        buffer: None,
+                        offset: offset as u64,
+                        index_type: *index_type,
+                    }),
+                    (indices.len() / index_stride(*index_type)) as u32,
+                )
+            }
+            _ => (None, vertex_count),
+        };
+
+        drop(staging_slice);
+        drop(writer);
+        drop(mapped);
+
+        vertex_layouts.sort_unstable_by(|a, b| a.format.cmp(&b.format));
+
+        unsafe {
+            factory
+                .upload_from_staging_buffer(
+                    &mut buffer,
+                    0,
+                    staging,
+                    None,
+                    BufferState::new(queue)
+                        .with_access(
+                            rendy_core::hal::buffer::Access::VERTEX_BUFFER_READ
+                                | rendy_core::hal::buffer::Access::INDEX_BUFFER_READ,
+                        )
+                        .with_stage(rendy_core::hal::pso::PipelineStage::VERTEX_INPUT),
+                )
+                .map_err(UploadError::Upload)?;
+        }
+
+        Ok(Mesh {
+            vertex_layouts,
+            index_buffer,
+            vertex_buffer: buffer,
+            prim: self.prim,
+            len,
+            aabb,
         })
     }
 }
@@ -346,6 +537,33 @@ fn align_by(align: usize, value: usize) -> usize {
     ((value + align - 1) / align) * align
 }
 
+/// Offsets of vertex data and indices packed into single buffer.
+/// `index` is size and stride of index data.
+/// Returns offsets of vertex data, offset of index data and size of the buffer.
+fn packed_offsets(
+    vertex_sizes: &[usize],
+    index: Option<(usize, usize)>,
+) -> (Vec<usize>, Option<usize>, usize) {
+    let mut offset = 0;
+    let vertex_offsets = vertex_sizes
+        .iter()
+        .map(|&size| {
+            let this_offset = offset;
+            offset += size;
+            this_offset
+        })
+        .collect();
+
+    // Index buffer offset must be a multiple of index size.
+    let index_offset = index.map(|(size, stride)| {
+        let this_offset = align_by(stride, offset);
+        offset = this_offset + size;
+        this_offset
+    });
+
+    (vertex_offsets, index_offset, offset)
+}
+
 /// Single mesh is a collection of buffer ranges that provides available attributes.
 /// Usually exactly one mesh is used per draw call.
 #[derive(Debug)]
@@ -355,6 +573,7 @@ pub struct Mesh<B: rendy_core::hal::Backend> {
     index_buffer: Option<IndexBuffer<B>>,
     prim: rendy_core::hal::pso::Primitive,
     len: u32,
+    aabb: Option<BoundingBox>,
 }
 
 impl<B> Mesh<B>
@@ -380,6 +599,17 @@ where
         self.len
     }
 
+    /// Axis-aligned bounding box of the mesh vertices computed on build.
+    /// See `MeshBuilder::aabb`.
+    pub fn aabb(&self) -> Option<BoundingBox> {
+        self.aabb
+    }
+
+    /// Sphere that contains the mesh bounding box.
+    pub fn bounding_sphere(&self) -> Option<BoundingSphere> {
+        self.aabb.as_ref().map(BoundingBox::bounding_sphere)
+    }
+
     fn get_vertex_iter<'a>(
         &'a self,
         formats: &[VertexFormat],
@@ -411,6 +641,14 @@ where
         Ok(vertex.into_iter().map(move |offset| (buffer, offset)))
     }
 
+    /// Get index buffer and offset of index data in it.
+    fn get_index(&self) -> Option<(&B::Buffer, u64, rendy_core::hal::IndexType)> {
+        self.index_buffer.as_ref().map(|index_buffer| {
+            let buffer = index_buffer.buffer.as_ref().unwrap_or(&self.vertex_buffer);
+            (buffer.raw(), index_buffer.offset, index_buffer.index_type)
+        })
+    }
+
     /// Bind buffers to specified attribute locations.
     pub fn bind<C>(
         &self,
@@ -422,9 +660,9 @@ where
         C: Supports<Graphics>,
     {
         let vertex_iter = self.get_vertex_iter(formats)?;
-        match self.index_buffer.as_ref() {
-            Some(index_buffer) => unsafe {
-                encoder.bind_index_buffer(index_buffer.buffer.raw(), 0, index_buffer.index_type);
+        match self.get_index() {
+            Some((buffer, offset, index_type)) => unsafe {
+                encoder.bind_index_buffer(buffer, offset, index_type);
                 encoder.bind_vertex_buffers(first_binding, vertex_iter);
             },
             None => unsafe {
@@ -445,13 +683,9 @@ where
     ) -> Result<u32, Incompatible> {
         let vertex_iter = self.get_vertex_iter(formats)?;
         unsafe {
-            match self.index_buffer.as_ref() {
-                Some(index_buffer) => {
-                    encoder.bind_index_buffer(
-                        index_buffer.buffer.raw(),
-                        0,
-                        index_buffer.index_type,
-                    );
+            match self.get_index() {
+                Some((buffer, offset, index_type)) => {
+                    encoder.bind_index_buffer(buffer, offset, index_type);
                     encoder.bind_vertex_buffers(first_binding, vertex_iter);
                     encoder.draw_indexed(0..self.len, 0, instance_range);
                 }
@@ -575,3 +809,83 @@ macro_rules! impl_builder_from_vec {
 }
 
 impl_builder_from_vec!(A, B, C, D, E, F, G, H, I, J, K, L, M, N, O, P);
+
+#[cfg(test)]
+mod tests {
+    use {
+        super::{packed_offsets, MeshBuilder},
+        crate::{BoundingBox, Color, Normal, PosNorm, Position},
+    };
+
+    fn cube() -> Vec<[f32; 3]> {
+        let mut corners = Vec::new();
+        for &x in &[-1.0, 2.0] {
+            for &y in &[-0.5, 0.5] {
+                for &z in &[0.0, 3.0] {
+                    corners.push([x, y, z]);
+                }
+            }
+        }
+        corners
+    }
+
+    #[test]
+    fn cube_aabb() {
+        let expected = Some(BoundingBox {
+            min: [-1.0, -0.5, 0.0],
+            max: [2.0, 0.5, 3.0],
+        });
+
+        // Positions in separate vertices.
+        let positions: Vec<Position> = cube().into_iter().map(Position).collect();
+        let colors = vec![Color([1.0; 4]); 8];
+        let builder = MeshBuilder::new()
+            .with_vertices(colors)
+            .with_vertices(positions);
+        assert_eq!(builder.aabb(), expected);
+
+        // Positions interleaved with normals.
+        let vertices: Vec<PosNorm> = cube()
+            .into_iter()
+            .map(|position| PosNorm {
+                position: Position(position),
+                normal: Normal([0.0, 1.0, 0.0]),
+            })
+            .collect();
+        let builder = MeshBuilder::new().with_vertices(vertices);
+        assert_eq!(builder.aabb(), expected);
+
+        let sphere = expected.unwrap().bounding_sphere();
+        assert_eq!(sphere.center, [0.5, 0.0, 1.5]);
+        assert!((sphere.radius - (9.0f32 + 1.0 + 9.0).sqrt() * 0.5).abs() < 1e-6);
+    }
+
+    #[test]
+    fn no_positions_no_aabb() {
+        let builder = MeshBuilder::new().with_vertices(vec![Color([1.0; 4]); 8]);
+        assert_eq!(builder.aabb(), None);
+        assert_eq!(MeshBuilder::new().aabb(), None);
+    }
+
+    #[test]
+    fn packed_indices_are_aligned() {
+        // Two vertex streams of 3 vertices with strides 12 and 3.
+        let (vertices, indices, size) = packed_offsets(&[36, 9], Some((12, 4)));
+        assert_eq!(vertices, vec![0, 36]);
+        assert_eq!(indices, Some(48));
+        assert_eq!(size, 60);
+
+        let (vertices, indices, size) = packed_offsets(&[36, 9], Some((6, 2)));
+        assert_eq!(vertices, vec![0, 36]);
+        assert_eq!(indices, Some(46));
+        assert_eq!(size, 52);
+    }
+
+    #[test]
+    fn packed_without_indices() {
+        let (vertices, indices, size) = packed_offsets(&[36, 9], None);
+        assert_eq!(vertices, vec![0, 36]);
+        assert_eq!(indices, None);
+        assert_eq!(size, 45);
+    }
+}
@@ -0,0 +1,319 @@
+//!
+//! Render bundle example.
+//! This example draws static triangle that is recorded into secondary command buffers once
+//! and replayed each frame, and dynamic triangle that is re-recorded every frame.
+//! Static triangle moves every two seconds, invalidating its bundle.
+//!
+
+use rendy::{
+    command::{Families, QueueId, RenderPassEncoder},
+    factory::{Config, Factory},
+    graph::{render::*, Graph, GraphBuilder, GraphContext, NodeBuffer, NodeImage},
+    hal::{self, Backend},
+    init::winit::{
+        event::{Event, WindowEvent},
+        event_loop::{ControlFlow, EventLoop},
+        window::WindowBuilder,
+    },
+    init::AnyWindowedRendy,
+    memory::Dynamic,
+    mesh::{AsVertex, PosColor},
+    resource::{Buffer, BufferInfo, DescriptorSetLayout, Escape, Handle},
+    shader::{ShaderKind, SourceLanguage, SourceShaderInfo, SpirvShader},
+};
+
+lazy_static::lazy_static! {
+    static ref VERTEX: SpirvShader = SourceShaderInfo::new(
+        include_str!("shader.vert"),
+        concat!(env!("CARGO_MANIFEST_DIR"), "/examples/bundle/shader.vert").into(),
+        ShaderKind::Vertex,
+        SourceLanguage::GLSL,
+        "main",
+    ).precompile().unwrap();
+
+    static ref FRAGMENT: SpirvShader = SourceShaderInfo::new(
+        include_str!("../triangle/shader.frag"),
+        concat!(env!("CARGO_MANIFEST_DIR"), "/examples/triangle/shader.frag").into(),
+        ShaderKind::Fragment,
+        SourceLanguage::GLSL,
+        "main",
+    ).precompile().unwrap();
+
+    static ref SHADERS: rendy::shader::ShaderSetBuilder = rendy::shader::ShaderSetBuilder::default()
+        .with_vertex(&*VERTEX).unwrap()
+        .with_fragment(&*FRAGMENT).unwrap();
+}
+
+#[derive(Debug)]
+struct TriangleRenderPipelineDesc {
+    /// Triangle moves every frame.
+    dynamic: bool,
+}
+
+#[derive(Debug)]
+struct TriangleRenderPipeline<B: hal::Backend> {
+    vertex: Option<Escape<Buffer<B>>>,
+    dynamic: bool,
+    offset: [f32; 2],
+    bundle: RenderBundle,
+    started: std::time::Instant,
+}
+
+impl<B, T> SimpleGraphicsPipelineDesc<B, T> for TriangleRenderPipelineDesc
+where
+    B: hal::Backend,
+    T: ?Sized,
+{
+    type Pipeline = TriangleRenderPipeline<B>;
+
+    fn depth_stencil(&self) -> Option<hal::pso::DepthStencilDesc> {
+        None
+    }
+
+    fn load_shader_set(&self, factory: &mut Factory<B>, _aux: &T) -> rendy_shader::ShaderSet<B> {
+        SHADERS.build(factory, Default::default()).unwrap()
+    }
+
+    fn vertices(
+        &self,
+    ) -> Vec<(
+        Vec<hal::pso::Element<hal::format::Format>>,
+        hal::pso::ElemStride,
+        hal::pso::VertexInputRate,
+    )> {
+        vec![PosColor::vertex().gfx_vertex_input_desc(hal::pso::VertexInputRate::Vertex)]
+    }
+
+    fn layout(&self) -> Layout {
+        Layout {
+            sets: Vec::new(),
+            push_constants: vec![(hal::pso::ShaderStageFlags::VERTEX, 0..8)],
+        }
+    }
+
+    fn build<'a>(
+        self,
+        _ctx: &GraphContext<B>,
+        _factory: &mut Factory<B>,
+        _queue: QueueId,
+        _aux: &T,
+        buffers: Vec<NodeBuffer>,
+        images: Vec<NodeImage>,
+        set_layouts: &[Handle<DescriptorSetLayout<B>>],
+    ) -> Result<TriangleRenderPipeline<B>, rendy_core::hal::pso::CreationError> {
+        assert!(buffers.is_empty());
+        assert!(images.is_empty());
+        assert!(set_layouts.is_empty());
+
+        Ok(TriangleRenderPipeline {
+            vertex: None,
+            dynamic: self.dynamic,
+            offset: [-0.5, 0.0],
+            bundle: RenderBundle::new(),
+            started: std::time::Instant::now(),
+        })
+    }
+}
+
+impl<B, T> SimpleGraphicsPipeline<B, T> for TriangleRenderPipeline<B>
+where
+    B: hal::Backend,
+    T: ?Sized,
+{
+    type Desc = TriangleRenderPipelineDesc;
+
+    // Every group of the subpass gets its own secondary command buffers.
+    const SECONDARY: bool = true;
+
+    fn prepare(
+        &mut self,
+        factory: &Factory<B>,
+        _queue: QueueId,
+        _set_layouts: &[Handle<DescriptorSetLayout<B>>],
+        index: usize,
+        _aux: &T,
+    ) -> PrepareResult {
+        if self.vertex.is_none() {
+            let mut vbuf = factory
+                .create_buffer(
+                    BufferInfo {
+                        size: PosColor::vertex().stride as u64 * 3,
+                        usage: hal::buffer::Usage::VERTEX,
+                    },
+                    Dynamic,
+                )
+                .unwrap();
+
+            unsafe {
+                // Fresh buffer.
+                factory
+                    .upload_visible_buffer(
+                        &mut vbuf,
+                        0,
+                        &[
+                            PosColor {
+                                position: [0.0, -0.5, 0.0].into(),
+                                color: [1.0, 0.0, 0.0, 1.0].into(),
+                            },
+                            PosColor {
+                                position: [0.5, 0.5, 0.0].into(),
+                                color: [0.0, 1.0, 0.0, 1.0].into(),
+                            },
+                            PosColor {
+                                position: [-0.5, 0.5, 0.0].into(),
+                                color: [0.0, 0.0, 1.0, 1.0].into(),
+                            },
+                        ],
+                    )
+                    .unwrap();
+            }
+
+            self.vertex = Some(vbuf);
+        }
+
+        let elapsed = self.started.elapsed().as_secs_f32();
+        if self.dynamic {
+            self.offset = [0.5, elapsed.sin() * 0.5];
+            PrepareResult::DrawRecord
+        } else {
+            // Jump between two positions every two seconds.
+            let offset = if (elapsed / 2.0) as u32 % 2 == 0 {
+                [-0.5, -0.25]
+            } else {
+                [-0.5, 0.25]
+            };
+            if offset != self.offset {
+                self.offset = offset;
+                self.bundle.invalidate();
+            }
+            self.bundle.prepare(index)
+        }
+    }
+
+    fn draw(
+        &mut self,
+        layout: &B::PipelineLayout,
+        mut encoder: RenderPassEncoder<'_, B>,
+        index: usize,
+        _aux: &T,
+    ) {
+        if !self.dynamic {
+            log::info!(
+                "Record static triangle for frame index {}. Recorded {} times",
+                index,
+                self.bundle.recordings()
+            );
+        }
+
+        let vbuf = self.vertex.as_ref().unwrap();
+        unsafe {
+            encoder.push_constants(
+                layout,
+                hal::pso::ShaderStageFlags::VERTEX,
+                0,
+                &[self.offset[0].to_bits(), self.offset[1].to_bits()],
+            );
+            encoder.bind_vertex_buffers(0, Some((vbuf.raw(), 0)));
+            encoder.draw(0..3, 0..1);
+        }
+    }
+
+    fn dispose(self, _factory: &mut Factory<B>, _aux: &T) {}
+}
+
+fn run<B: Backend>(
+    event_loop: EventLoop<()>,
+    mut factory: Factory<B>,
+    mut families: Families<B>,
+    graph: Graph<B, ()>,
+) {
+    let started = std::time::Instant::now();
+
+    let mut frame = 0u64;
+    let mut elapsed = started.elapsed();
+    let mut graph = Some(graph);
+
+    event_loop.run(move |event, _, control_flow| {
+        *control_flow = ControlFlow::Poll;
+        match event {
+            Event::WindowEvent { event, .. } => match event {
+                WindowEvent::CloseRequested => *control_flow = ControlFlow::Exit,
+                _ => {}
+            },
+            Event::EventsCleared => {
+                factory.maintain(&mut families);
+                if let Some(ref mut graph) = graph {
+                    graph
+                        .run(&mut factory, &mut families, &())
+                        .expect("Failed to run graph");
+                    frame += 1;
+                }
+
+                elapsed = started.elapsed();
+                if elapsed >= std::time::Duration::new(5, 0) {
+                    *control_flow = ControlFlow::Exit
+                }
+            }
+            _ => {}
+        }
+
+        if *control_flow == ControlFlow::Exit && graph.is_some() {
+            let elapsed_ns = elapsed.as_secs() * 1_000_000_000 + elapsed.subsec_nanos() as u64;
+
+            log::info!(
+                "Elapsed: {:?}. Frames: {}. FPS: {}",
+                elapsed,
+                frame,
+                frame * 1_000_000_000 / elapsed_ns
+            );
+
+            graph.take().unwrap().dispose(&mut factory, &());
+        }
+    });
+}
+
+fn main() {
+    env_logger::Builder::from_default_env()
+        .filter_module("bundle", log::LevelFilter::Trace)
+        .init();
+
+    let config: Config = Default::default();
+    let event_loop = EventLoop::new();
+    let window = WindowBuilder::new()
+        .with_inner_size((960, 640).into())
+        .with_title("Rendy example");
+
+    let rendy = AnyWindowedRendy::init_auto(&config, window, &event_loop).unwrap();
+    rendy::with_any_windowed_rendy!((rendy)
+        (mut factory, mut families, surface, window) => {
+            let mut graph_builder = GraphBuilder::<_, ()>::new();
+            let (width, height) = window.inner_size().to_physical(window.hidpi_factor()).into();
+
+            graph_builder.add_node(
+                SubpassBuilder::new()
+                    .with_group(TriangleRenderPipelineDesc { dynamic: false }.builder())
+                    .with_group(TriangleRenderPipelineDesc { dynamic: true }.builder())
+                    .with_color_surface()
+                    .into_pass()
+                    .with_surface(
+                        surface,
+                        hal::window::Extent2D {
+                            width,
+                            height,
+                        },
+                        Some(hal::command::ClearValue {
+                            color: hal::command::ClearColor {
+                                float32: [1.0, 1.0, 1.0, 1.0],
+                            },
+                        }),
+                    ),
+            );
+
+            let graph = graph_builder
+                .build(&mut factory, &mut families, &())
+                .unwrap();
+
+            run(event_loop, factory, families, graph);
+        }
+    );
+}
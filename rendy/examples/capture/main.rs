@@ -0,0 +1,82 @@
+//!
+//! Capture example.
+//! This example renders graph without window and reads resulting pixels back.
+//! Render pass clears the image with solid color
+//! and `CaptureNode` copies it into host memory.
+//!
+
+use rendy::{
+    factory::Config,
+    graph::{capture::CaptureNodeDesc, render::*, GraphBuilder, NodeDesc},
+    hal,
+    init::AnyRendy,
+};
+
+const WIDTH: u32 = 64;
+const HEIGHT: u32 = 48;
+
+fn main() {
+    env_logger::Builder::from_default_env()
+        .filter_module("capture", log::LevelFilter::Trace)
+        .init();
+
+    let config: Config = Default::default();
+    let rendy = AnyRendy::init_auto(&config).unwrap();
+    rendy::with_any_rendy!((rendy)
+        (mut factory, mut families) => {
+            let mut graph_builder = GraphBuilder::<_, ()>::new();
+
+            let color = graph_builder.create_image(
+                hal::image::Kind::D2(WIDTH, HEIGHT, 1, 1),
+                1,
+                hal::format::Format::Rgba8Unorm,
+                Some(hal::command::ClearValue {
+                    color: hal::command::ClearColor {
+                        float32: [1.0, 0.0, 0.0, 1.0],
+                    },
+                }),
+            );
+
+            let pass = graph_builder.add_node(
+                SubpassBuilder::new()
+                    .with_color(color)
+                    .into_pass(),
+            );
+
+            let capture = CaptureNodeDesc::new(color);
+            let captured = capture.capture();
+            graph_builder.add_node(NodeDesc::builder(capture).with_dependency(pass));
+
+            let mut graph = graph_builder
+                .build(&mut factory, &mut families, &())
+                .unwrap();
+
+            for _ in 0..3 {
+                factory.maintain(&mut families);
+                graph
+                    .run(&mut factory, &mut families, &())
+                    .expect("Failed to run graph");
+            }
+
+            // Waits for device and reads copies of the last frames.
+            graph.dispose(&mut factory, &());
+
+            let image = captured.take().expect("No image captured");
+            log::info!(
+                "Captured frame {} in {:?}, row pitch {}",
+                image.frame(),
+                image.format(),
+                image.row_pitch()
+            );
+
+            assert_eq!(image.row_pitch(), WIDTH * 4);
+            assert_eq!(image.pixels().len(), (WIDTH * HEIGHT * 4) as usize);
+            for y in 0..HEIGHT {
+                for x in 0..WIDTH {
+                    assert_eq!(image.texel(x, y), &[255, 0, 0, 255], "Texel at {}x{}", x, y);
+                }
+            }
+            log::info!("All pixels match clear color");
+        }
+    );
+}
@@ -0,0 +1,380 @@
+//!
+//! Closure node example.
+//! This examples shows the same triangle as triangle example
+//! but draws it with a closure node instead of render pass node.
+//! Closure creates render pass and pipeline on first run
+//! and records the draw into offscreen image that is presented to the screen.
+//!
+
+use {
+    rendy::{
+        command::{Families, Graphics},
+        factory::{Config, Factory},
+        graph::{
+            closure::ClosureNode, present::PresentNode, Graph, GraphBuilder, GraphContext,
+            ImageAccess, ImageId,
+        },
+        hal::{self, device::Device as _, Backend},
+        init::winit::{
+            event::{Event, WindowEvent},
+            event_loop::{ControlFlow, EventLoop},
+            window::WindowBuilder,
+        },
+        init::AnyWindowedRendy,
+        memory::Dynamic,
+        mesh::{AsVertex, PosColor},
+        resource::{Buffer, BufferInfo, Escape},
+        shader::{ShaderKind, SourceLanguage, SourceShaderInfo, SpirvShader},
+    },
+    std::sync::{Arc, Mutex},
+};
+
+lazy_static::lazy_static! {
+    static ref VERTEX: SpirvShader = SourceShaderInfo::new(
+        include_str!("../triangle/shader.vert"),
+        concat!(env!("CARGO_MANIFEST_DIR"), "/examples/triangle/shader.vert").into(),
+        ShaderKind::Vertex,
+        SourceLanguage::GLSL,
+        "main",
+    ).precompile().unwrap();
+
+    static ref FRAGMENT: SpirvShader = SourceShaderInfo::new(
+        include_str!("../triangle/shader.frag"),
+        concat!(env!("CARGO_MANIFEST_DIR"), "/examples/triangle/shader.frag").into(),
+        ShaderKind::Fragment,
+        SourceLanguage::GLSL,
+        "main",
+    ).precompile().unwrap();
+
+    static ref SHADERS: rendy::shader::ShaderSetBuilder = rendy::shader::ShaderSetBuilder::default()
+        .with_vertex(&*VERTEX).unwrap()
+        .with_fragment(&*FRAGMENT).unwrap();
+}
+
+/// Objects created by the closure on first run.
+#[derive(Debug)]
+struct Triangle<B: hal::Backend> {
+    render_pass: B::RenderPass,
+    view: B::ImageView,
+    framebuffer: B::Framebuffer,
+    layout: B::PipelineLayout,
+    pipeline: B::GraphicsPipeline,
+    vertex: Escape<Buffer<B>>,
+    area: hal::pso::Rect,
+}
+
+impl<B> Triangle<B>
+where
+    B: hal::Backend,
+{
+    fn new(ctx: &GraphContext<B>, factory: &Factory<B>, color: ImageId) -> Self {
+        let image = ctx.get_image(color).expect("Image does not exist");
+        let extent = image.kind().extent();
+        let area = hal::pso::Rect {
+            x: 0,
+            y: 0,
+            w: extent.width as i16,
+            h: extent.height as i16,
+        };
+
+        unsafe {
+            let render_pass = factory
+                .device()
+                .create_render_pass(
+                    Some(hal::pass::Attachment {
+                        format: Some(image.format()),
+                        samples: 1,
+                        ops: hal::pass::AttachmentOps {
+                            load: hal::pass::AttachmentLoadOp::Clear,
+                            store: hal::pass::AttachmentStoreOp::Store,
+                        },
+                        stencil_ops: hal::pass::AttachmentOps::DONT_CARE,
+                        layouts: hal::image::Layout::Undefined
+                            ..hal::image::Layout::ColorAttachmentOptimal,
+                    }),
+                    Some(hal::pass::SubpassDesc {
+                        colors: &[(0, hal::image::Layout::ColorAttachmentOptimal)],
+                        depth_stencil: None,
+                        inputs: &[],
+                        resolves: &[],
+                        preserves: &[],
+                    }),
+                    std::iter::empty::<hal::pass::SubpassDependency>(),
+                )
+                .unwrap();
+
+            let view = factory
+                .device()
+                .create_image_view(
+                    image.raw(),
+                    hal::image::ViewKind::D2,
+                    image.format(),
+                    hal::format::Swizzle::NO,
+                    hal::image::SubresourceRange {
+                        aspects: hal::format::Aspects::COLOR,
+                        levels: 0..1,
+                        layers: 0..1,
+                    },
+                )
+                .unwrap();
+
+            let framebuffer = factory
+                .device()
+                .create_framebuffer(&render_pass, Some(&view), extent)
+                .unwrap();
+
+            let layout = factory
+                .device()
+                .create_pipeline_layout(
+                    std::iter::empty::<&B::DescriptorSetLayout>(),
+                    std::iter::empty::<(hal::pso::ShaderStageFlags, std::ops::Range<u32>)>(),
+                )
+                .unwrap();
+
+            let mut shader_set = SHADERS.build(factory, Default::default()).unwrap();
+            let (elements, stride, rate) =
+                PosColor::vertex().gfx_vertex_input_desc(hal::pso::VertexInputRate::Vertex);
+
+            let pipeline = factory
+                .device()
+                .create_graphics_pipelines(
+                    Some(hal::pso::GraphicsPipelineDesc {
+                        shaders: shader_set.raw().unwrap(),
+                        rasterizer: hal::pso::Rasterizer::FILL,
+                        vertex_buffers: vec![hal::pso::VertexBufferDesc {
+                            binding: 0,
+                            stride,
+                            rate,
+                        }],
+                        attributes: elements
+                            .into_iter()
+                            .enumerate()
+                            .map(|(location, element)| hal::pso::AttributeDesc {
+                                location: location as u32,
+                                binding: 0,
+                                element,
+                            })
+                            .collect(),
+                        input_assembler: hal::pso::InputAssemblerDesc {
+                            primitive: hal::pso::Primitive::TriangleList,
+                            with_adjacency: false,
+                            restart_index: None,
+                        },
+                        blender: hal::pso::BlendDesc {
+                            logic_op: None,
+                            targets: vec![hal::pso::ColorBlendDesc {
+                                mask: hal::pso::ColorMask::ALL,
+                                blend: Some(hal::pso::BlendState::ALPHA),
+                            }],
+                        },
+                        depth_stencil: hal::pso::DepthStencilDesc::default(),
+                        multisampling: None,
+                        baked_states: hal::pso::BakedStates {
+                            viewport: Some(hal::pso::Viewport {
+                                rect: area,
+                                depth: 0.0..1.0,
+                            }),
+                            scissor: Some(area),
+                            blend_color: None,
+                            depth_bounds: None,
+                        },
+                        layout: &layout,
+                        subpass: hal::pass::Subpass {
+                            index: 0,
+                            main_pass: &render_pass,
+                        },
+                        flags: hal::pso::PipelineCreationFlags::empty(),
+                        parent: hal::pso::BasePipeline::None,
+                    }),
+                    None,
+                )
+                .remove(0)
+                .unwrap();
+
+            shader_set.dispose(factory);
+
+            let mut vertex = factory
+                .create_buffer(
+                    BufferInfo {
+                        size: stride as u64 * 3,
+                        usage: hal::buffer::Usage::VERTEX,
+                    },
+                    Dynamic,
+                )
+                .unwrap();
+
+            // Fresh buffer.
+            factory
+                .upload_visible_buffer(
+                    &mut vertex,
+                    0,
+                    &[
+                        PosColor {
+                            position: [0.0, -0.5, 0.0].into(),
+                            color: [1.0, 0.0, 0.0, 1.0].into(),
+                        },
+                        PosColor {
+                            position: [0.5, 0.5, 0.0].into(),
+                            color: [0.0, 1.0, 0.0, 1.0].into(),
+                        },
+                        PosColor {
+                            position: [-0.5, 0.5, 0.0].into(),
+                            color: [0.0, 0.0, 1.0, 1.0].into(),
+                        },
+                    ],
+                )
+                .unwrap();
+
+            Triangle {
+                render_pass,
+                view,
+                framebuffer,
+                layout,
+                pipeline,
+                vertex,
+                area,
+            }
+        }
+    }
+
+    unsafe fn dispose(self, factory: &Factory<B>) {
+        factory.device().destroy_graphics_pipeline(self.pipeline);
+        factory.device().destroy_pipeline_layout(self.layout);
+        factory.device().destroy_framebuffer(self.framebuffer);
+        factory.device().destroy_image_view(self.view);
+        factory.device().destroy_render_pass(self.render_pass);
+    }
+}
+
+fn run<B: Backend>(
+    event_loop: EventLoop<()>,
+    mut factory: Factory<B>,
+    mut families: Families<B>,
+    graph: Graph<B, ()>,
+) {
+    let started = std::time::Instant::now();
+
+    let mut frame = 0u64;
+    let mut elapsed = started.elapsed();
+    let mut graph = Some(graph);
+
+    event_loop.run(move |event, _, control_flow| {
+        *control_flow = ControlFlow::Poll;
+        match event {
+            Event::WindowEvent { event, .. } => match event {
+                WindowEvent::CloseRequested => *control_flow = ControlFlow::Exit,
+                _ => {}
+            },
+            Event::EventsCleared => {
+                factory.maintain(&mut families);
+                if let Some(ref mut graph) = graph {
+                    graph
+                        .run(&mut factory, &mut families, &())
+                        .expect("Failed to run graph");
+                    frame += 1;
+                }
+
+                elapsed = started.elapsed();
+                if elapsed >= std::time::Duration::new(5, 0) {
+                    *control_flow = ControlFlow::Exit
+                }
+            }
+            _ => {}
+        }
+
+        if *control_flow == ControlFlow::Exit && graph.is_some() {
+            let elapsed_ns = elapsed.as_secs() * 1_000_000_000 + elapsed.subsec_nanos() as u64;
+
+            log::info!(
+                "Elapsed: {:?}. Frames: {}. FPS: {}",
+                elapsed,
+                frame,
+                frame * 1_000_000_000 / elapsed_ns
+            );
+
+            graph.take().unwrap().dispose(&mut factory, &());
+        }
+    });
+}
+
+fn main() {
+    env_logger::Builder::from_default_env()
+        .filter_module("closure", log::LevelFilter::Trace)
+        .init();
+
+    let config: Config = Default::default();
+    let event_loop = EventLoop::new();
+    let window = WindowBuilder::new()
+        .with_inner_size((960, 640).into())
+        .with_title("Rendy example");
+
+    let rendy = AnyWindowedRendy::init_auto(&config, window, &event_loop).unwrap();
+    rendy::with_any_windowed_rendy!((rendy)
+        (mut factory, mut families, surface, window) => {
+            let mut graph_builder = GraphBuilder::<_, ()>::new();
+            let size = window.inner_size().to_physical(window.hidpi_factor());
+
+            let color = graph_builder.create_image(
+                hal::image::Kind::D2(size.width as u32, size.height as u32, 1, 1),
+                1,
+                factory.get_surface_format(&surface),
+                None,
+            );
+
+            // Shared with dispose function to destroy objects created by the closure.
+            let triangle = Arc::new(Mutex::new(None));
+            let dispose_triangle = triangle.clone();
+
+            let pass = graph_builder.add_node(ClosureNode::with_dispose(
+                Graphics,
+                Vec::new(),
+                vec![(
+                    color,
+                    ImageAccess {
+                        access: hal::image::Access::COLOR_ATTACHMENT_WRITE,
+                        usage: hal::image::Usage::COLOR_ATTACHMENT,
+                        layout: hal::image::Layout::ColorAttachmentOptimal,
+                        stages: hal::pso::PipelineStage::COLOR_ATTACHMENT_OUTPUT,
+                    },
+                )],
+                move |ctx, factory, _aux, _frames, encoder| {
+                    let mut triangle = triangle.lock().unwrap();
+                    let triangle = triangle.get_or_insert_with(|| Triangle::new(ctx, factory, color));
+
+                    let mut pass = encoder.begin_render_pass_inline(
+                        &triangle.render_pass,
+                        &triangle.framebuffer,
+                        triangle.area,
+                        &[hal::command::ClearValue {
+                            color: hal::command::ClearColor {
+                                float32: [1.0, 1.0, 1.0, 1.0],
+                            },
+                        }],
+                    );
+                    pass.bind_graphics_pipeline(&triangle.pipeline);
+                    unsafe {
+                        pass.bind_vertex_buffers(0, Some((triangle.vertex.raw(), 0)));
+                        pass.draw(0..3, 0..1);
+                    }
+                },
+                move |factory, _aux| {
+                    if let Some(triangle) = dispose_triangle.lock().unwrap().take() {
+                        unsafe {
+                            Triangle::dispose(triangle, factory);
+                        }
+                    }
+                },
+            ));
+
+            graph_builder.add_node(
+                PresentNode::builder(&factory, surface, color).with_dependency(pass),
+            );
+
+            let graph = graph_builder
+                .build(&mut factory, &mut families, &())
+                .unwrap();
+
+            run(event_loop, factory, families, graph);
+        }
+    );
+}
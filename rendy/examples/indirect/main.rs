@@ -0,0 +1,550 @@
+//!
+//! Indirect draw example.
+//! Compute node writes draw arguments into the buffer
+//! and render pass draws the triangle with arguments read from that buffer.
+//! Graph inserts barrier between writing and reading the arguments.
+//!
+
+use rendy::{
+    command::{
+        CommandBuffer, CommandPool, Compute, DrawCommand, ExecutableState, Families, Family,
+        MultiShot, PendingState, QueueId, RenderPassEncoder, SimultaneousUse, Submit,
+    },
+    factory::{Config, DrawIndirect, DrawIndirectCount, Factory},
+    frame::Frames,
+    graph::{
+        gfx_acquire_barriers, gfx_release_barriers, render::*, BufferAccess, Graph, GraphBuilder,
+        GraphContext, Node, NodeBuffer, NodeBuildError, NodeDesc, NodeImage, NodeSubmittable,
+    },
+    hal::{self, device::Device as _, Backend},
+    init::winit::{
+        event::{Event, WindowEvent},
+        event_loop::{ControlFlow, EventLoop},
+        window::WindowBuilder,
+    },
+    init::AnyWindowedRendy,
+    memory::Dynamic,
+    mesh::PosColor,
+    resource::{Buffer, BufferInfo, DescriptorSet, DescriptorSetLayout, Escape, Handle},
+    shader::{Shader, ShaderKind, SourceLanguage, SourceShaderInfo, SpirvShader},
+};
+
+#[cfg(feature = "spirv-reflection")]
+use rendy::shader::SpirvReflection;
+
+#[cfg(not(feature = "spirv-reflection"))]
+use rendy::mesh::AsVertex;
+
+lazy_static::lazy_static! {
+    static ref VERTEX: SpirvShader = SourceShaderInfo::new(
+        include_str!("../triangle/shader.vert"),
+        concat!(env!("CARGO_MANIFEST_DIR"), "/examples/triangle/shader.vert").into(),
+        ShaderKind::Vertex,
+        SourceLanguage::GLSL,
+        "main",
+    ).precompile().unwrap();
+
+    static ref FRAGMENT: SpirvShader = SourceShaderInfo::new(
+        include_str!("../triangle/shader.frag"),
+        concat!(env!("CARGO_MANIFEST_DIR"), "/examples/triangle/shader.frag").into(),
+        ShaderKind::Fragment,
+        SourceLanguage::GLSL,
+        "main",
+    ).precompile().unwrap();
+
+    static ref ARGS_COMPUTE: SpirvShader = SourceShaderInfo::new(
+        include_str!("args.comp"),
+        concat!(env!("CARGO_MANIFEST_DIR"), "/examples/indirect/args.comp").into(),
+        ShaderKind::Compute,
+        SourceLanguage::GLSL,
+        "main",
+    ).precompile().unwrap();
+
+    static ref SHADERS: rendy::shader::ShaderSetBuilder = rendy::shader::ShaderSetBuilder::default()
+        .with_vertex(&*VERTEX).unwrap()
+        .with_fragment(&*FRAGMENT).unwrap();
+}
+
+#[cfg(feature = "spirv-reflection")]
+lazy_static::lazy_static! {
+    static ref SHADER_REFLECTION: SpirvReflection = SHADERS.reflect().unwrap();
+}
+
+/// Size of the draw command.
+/// Number of draws follows the command in the arguments buffer.
+const DRAW_SIZE: u64 = std::mem::size_of::<DrawCommand>() as u64;
+
+/// Size of the arguments buffer.
+const ARGS_SIZE: u64 = DRAW_SIZE + std::mem::size_of::<u32>() as u64;
+
+#[derive(Debug, Default)]
+struct TriangleRenderPipelineDesc;
+
+#[derive(Debug)]
+struct TriangleRenderPipeline<B: hal::Backend> {
+    vertex: Option<Escape<Buffer<B>>>,
+    args: Handle<Buffer<B>>,
+    draw_count: Option<DrawIndirectCount>,
+}
+
+impl<B, T> SimpleGraphicsPipelineDesc<B, T> for TriangleRenderPipelineDesc
+where
+    B: hal::Backend,
+    T: ?Sized,
+{
+    type Pipeline = TriangleRenderPipeline<B>;
+
+    fn buffers(&self) -> Vec<BufferAccess> {
+        vec![BufferAccess {
+            access: hal::buffer::Access::INDIRECT_COMMAND_READ,
+            stages: hal::pso::PipelineStage::DRAW_INDIRECT,
+            usage: hal::buffer::Usage::INDIRECT,
+        }]
+    }
+
+    fn depth_stencil(&self) -> Option<hal::pso::DepthStencilDesc> {
+        None
+    }
+
+    fn load_shader_set(&self, factory: &mut Factory<B>, _aux: &T) -> rendy_shader::ShaderSet<B> {
+        SHADERS.build(factory, Default::default()).unwrap()
+    }
+
+    fn vertices(
+        &self,
+    ) -> Vec<(
+        Vec<hal::pso::Element<hal::format::Format>>,
+        hal::pso::ElemStride,
+        hal::pso::VertexInputRate,
+    )> {
+        #[cfg(feature = "spirv-reflection")]
+        return vec![SHADER_REFLECTION
+            .attributes_range(..)
+            .unwrap()
+            .gfx_vertex_input_desc(hal::pso::VertexInputRate::Vertex)];
+
+        #[cfg(not(feature = "spirv-reflection"))]
+        return vec![PosColor::vertex().gfx_vertex_input_desc(hal::pso::VertexInputRate::Vertex)];
+    }
+
+    fn build<'a>(
+        self,
+        ctx: &GraphContext<B>,
+        factory: &mut Factory<B>,
+        _queue: QueueId,
+        _aux: &T,
+        buffers: Vec<NodeBuffer>,
+        images: Vec<NodeImage>,
+        set_layouts: &[Handle<DescriptorSetLayout<B>>],
+    ) -> Result<TriangleRenderPipeline<B>, rendy_core::hal::pso::CreationError> {
+        assert_eq!(buffers.len(), 1);
+        assert!(images.is_empty());
+        assert!(set_layouts.is_empty());
+
+        let draw_count = DrawIndirectCount::new(factory);
+        log::info!(
+            "Draw {} number of draws from the buffer",
+            if draw_count.is_some() {
+                "with"
+            } else {
+                "without"
+            }
+        );
+
+        Ok(TriangleRenderPipeline {
+            vertex: None,
+            args: ctx.get_buffer(buffers[0].id).unwrap().clone(),
+            draw_count,
+        })
+    }
+}
+
+impl<B, T> SimpleGraphicsPipeline<B, T> for TriangleRenderPipeline<B>
+where
+    B: hal::Backend,
+    T: ?Sized,
+{
+    type Desc = TriangleRenderPipelineDesc;
+
+    fn prepare(
+        &mut self,
+        factory: &Factory<B>,
+        _queue: QueueId,
+        _set_layouts: &[Handle<DescriptorSetLayout<B>>],
+        _index: usize,
+        _aux: &T,
+    ) -> PrepareResult {
+        if self.vertex.is_none() {
+            #[cfg(feature = "spirv-reflection")]
+            let vbuf_size = SHADER_REFLECTION.attributes_range(..).unwrap().stride as u64 * 3;
+
+            #[cfg(not(feature = "spirv-reflection"))]
+            let vbuf_size = PosColor::vertex().stride as u64 * 3;
+
+            let mut vbuf = factory
+                .create_buffer(
+                    BufferInfo {
+                        size: vbuf_size,
+                        usage: hal::buffer::Usage::VERTEX,
+                    },
+                    Dynamic,
+                )
+                .unwrap();
+
+            unsafe {
+                // Fresh buffer.
+                factory
+                    .upload_visible_buffer(
+                        &mut vbuf,
+                        0,
+                        &[
+                            PosColor {
+                                position: [0.0, -0.5, 0.0].into(),
+                                color: [1.0, 0.0, 0.0, 1.0].into(),
+                            },
+                            PosColor {
+                                position: [0.5, 0.5, 0.0].into(),
+                                color: [0.0, 1.0, 0.0, 1.0].into(),
+                            },
+                            PosColor {
+                                position: [-0.5, 0.5, 0.0].into(),
+                                color: [0.0, 0.0, 1.0, 1.0].into(),
+                            },
+                        ],
+                    )
+                    .unwrap();
+            }
+
+            self.vertex = Some(vbuf);
+        }
+
+        PrepareResult::DrawReuse
+    }
+
+    fn draw(
+        &mut self,
+        _layout: &B::PipelineLayout,
+        mut encoder: RenderPassEncoder<'_, B>,
+        _index: usize,
+        _aux: &T,
+    ) {
+        let vbuf = self.vertex.as_ref().unwrap();
+        unsafe {
+            encoder.bind_vertex_buffers(0, Some((vbuf.raw(), 0)));
+            if let Some(supported) = self.draw_count {
+                encoder
+                    .draw_buffer_indirect_count(
+                        supported,
+                        &self.args,
+                        0,
+                        &self.args,
+                        DRAW_SIZE,
+                        1,
+                        DRAW_SIZE as u32,
+                    )
+                    .unwrap();
+            } else {
+                encoder
+                    .draw_buffer_indirect(&self.args, 0, 1, DRAW_SIZE as u32)
+                    .unwrap();
+            }
+        }
+    }
+
+    fn dispose(self, _factory: &mut Factory<B>, _aux: &T) {}
+}
+
+#[derive(Debug)]
+struct BuildArgs<B: hal::Backend> {
+    set_layout: Handle<DescriptorSetLayout<B>>,
+    pipeline_layout: B::PipelineLayout,
+    pipeline: B::ComputePipeline,
+
+    descriptor_set: Escape<DescriptorSet<B>>,
+
+    command_pool: CommandPool<B, Compute>,
+    command_buffer:
+        CommandBuffer<B, Compute, PendingState<ExecutableState<MultiShot<SimultaneousUse>>>>,
+    submit: Submit<B, SimultaneousUse>,
+}
+
+impl<'a, B> NodeSubmittable<'a, B> for BuildArgs<B>
+where
+    B: hal::Backend,
+{
+    type Submittable = &'a Submit<B, SimultaneousUse>;
+    type Submittables = &'a [Submit<B, SimultaneousUse>];
+}
+
+impl<B, T> Node<B, T> for BuildArgs<B>
+where
+    B: hal::Backend,
+    T: ?Sized,
+{
+    type Capability = Compute;
+
+    fn run<'a>(
+        &'a mut self,
+        _ctx: &GraphContext<B>,
+        _factory: &Factory<B>,
+        _aux: &T,
+        _frames: &'a Frames<B>,
+    ) -> &'a [Submit<B, SimultaneousUse>] {
+        std::slice::from_ref(&self.submit)
+    }
+
+    unsafe fn dispose(mut self, factory: &mut Factory<B>, _aux: &T) {
+        drop(self.submit);
+        self.command_pool
+            .free_buffers(Some(self.command_buffer.mark_complete()));
+        factory.destroy_command_pool(self.command_pool);
+        factory.destroy_compute_pipeline(self.pipeline);
+        factory.destroy_pipeline_layout(self.pipeline_layout);
+    }
+}
+
+#[derive(Debug, Default)]
+struct BuildArgsDesc;
+
+impl<B, T> NodeDesc<B, T> for BuildArgsDesc
+where
+    B: hal::Backend,
+    T: ?Sized,
+{
+    type Node = BuildArgs<B>;
+
+    fn buffers(&self) -> Vec<BufferAccess> {
+        vec![BufferAccess {
+            access: hal::buffer::Access::SHADER_WRITE,
+            stages: hal::pso::PipelineStage::COMPUTE_SHADER,
+            usage: hal::buffer::Usage::STORAGE,
+        }]
+    }
+
+    fn build<'a>(
+        self,
+        ctx: &GraphContext<B>,
+        factory: &mut Factory<B>,
+        family: &mut Family<B>,
+        _queue: usize,
+        _aux: &T,
+        buffers: Vec<NodeBuffer>,
+        images: Vec<NodeImage>,
+    ) -> Result<Self::Node, NodeBuildError> {
+        assert!(images.is_empty());
+        assert_eq!(buffers.len(), 1);
+
+        let args = ctx.get_buffer(buffers[0].id).unwrap();
+
+        log::trace!("Load shader module ARGS_COMPUTE");
+        let module = unsafe { ARGS_COMPUTE.module(factory) }
+            .map_err(rendy_core::hal::pso::CreationError::Shader)
+            .map_err(NodeBuildError::Pipeline)?;
+
+        let set_layout = Handle::from(
+            factory
+                .create_descriptor_set_layout(vec![hal::pso::DescriptorSetLayoutBinding {
+                    binding: 0,
+                    ty: hal::pso::DescriptorType::StorageBuffer,
+                    count: 1,
+                    stage_flags: hal::pso::ShaderStageFlags::COMPUTE,
+                    immutable_samplers: false,
+                }])
+                .map_err(NodeBuildError::OutOfMemory)?,
+        );
+
+        let pipeline_layout = unsafe {
+            factory
+                .device()
+                .create_pipeline_layout(
+                    std::iter::once(set_layout.raw()),
+                    std::iter::empty::<(hal::pso::ShaderStageFlags, std::ops::Range<u32>)>(),
+                )
+                .map_err(NodeBuildError::OutOfMemory)?
+        };
+
+        let pipeline = unsafe {
+            factory
+                .device()
+                .create_compute_pipeline(
+                    &hal::pso::ComputePipelineDesc {
+                        shader: hal::pso::EntryPoint {
+                            entry: "main",
+                            module: &module,
+                            specialization: hal::pso::Specialization::default(),
+                        },
+                        layout: &pipeline_layout,
+                        flags: hal::pso::PipelineCreationFlags::empty(),
+                        parent: hal::pso::BasePipeline::None,
+                    },
+                    None,
+                )
+                .map_err(NodeBuildError::Pipeline)?
+        };
+
+        unsafe { factory.destroy_shader_module(module) };
+
+        let descriptor_set = factory
+            .create_descriptor_set(set_layout.clone())
+            .map_err(NodeBuildError::OutOfMemory)?;
+
+        unsafe {
+            factory
+                .device()
+                .write_descriptor_sets(std::iter::once(hal::pso::DescriptorSetWrite {
+                    set: descriptor_set.raw(),
+                    binding: 0,
+                    array_offset: 0,
+                    descriptors: std::iter::once(hal::pso::Descriptor::Buffer(
+                        args.raw(),
+                        Some(0)..Some(args.size()),
+                    )),
+                }));
+        }
+
+        let mut command_pool = factory
+            .create_command_pool(family)
+            .map_err(NodeBuildError::OutOfMemory)?
+            .with_capability::<Compute>()
+            .expect("Graph builder must provide family with Compute capability");
+        let initial = command_pool.allocate_buffers(1).remove(0);
+        let mut recording = initial.begin(MultiShot(SimultaneousUse), ());
+        let mut encoder = recording.encoder();
+        encoder.bind_compute_pipeline(&pipeline);
+        unsafe {
+            encoder.bind_compute_descriptor_sets(
+                &pipeline_layout,
+                0,
+                std::iter::once(descriptor_set.raw()),
+                std::iter::empty::<u32>(),
+            );
+
+            {
+                let (stages, barriers) = gfx_acquire_barriers(ctx, &*buffers, None);
+                log::info!("Acquire {:?} : {:#?}", stages, barriers);
+                encoder.pipeline_barrier(stages, hal::memory::Dependencies::empty(), barriers);
+            }
+            encoder.dispatch(1, 1, 1);
+
+            {
+                let (stages, barriers) = gfx_release_barriers(ctx, &*buffers, None);
+                log::info!("Release {:?} : {:#?}", stages, barriers);
+                encoder.pipeline_barrier(stages, hal::memory::Dependencies::empty(), barriers);
+            }
+        }
+
+        let (submit, command_buffer) = recording.finish().submit();
+
+        Ok(BuildArgs {
+            set_layout,
+            pipeline_layout,
+            pipeline,
+            descriptor_set,
+            command_pool,
+            command_buffer,
+            submit,
+        })
+    }
+}
+
+fn run<B: Backend>(
+    event_loop: EventLoop<()>,
+    mut factory: Factory<B>,
+    mut families: Families<B>,
+    graph: Graph<B, ()>,
+) {
+    let started = std::time::Instant::now();
+
+    let mut frame = 0u64;
+    let mut elapsed = started.elapsed();
+    let mut graph = Some(graph);
+
+    event_loop.run(move |event, _, control_flow| {
+        *control_flow = ControlFlow::Poll;
+        match event {
+            Event::WindowEvent { event, .. } => match event {
+                WindowEvent::CloseRequested => *control_flow = ControlFlow::Exit,
+                _ => {}
+            },
+            Event::EventsCleared => {
+                factory.maintain(&mut families);
+                if let Some(ref mut graph) = graph {
+                    graph
+                        .run(&mut factory, &mut families, &())
+                        .expect("Failed to run graph");
+                    frame += 1;
+                }
+
+                elapsed = started.elapsed();
+                if elapsed >= std::time::Duration::new(5, 0) {
+                    *control_flow = ControlFlow::Exit
+                }
+            }
+            _ => {}
+        }
+
+        if *control_flow == ControlFlow::Exit && graph.is_some() {
+            let elapsed_ns = elapsed.as_secs() * 1_000_000_000 + elapsed.subsec_nanos() as u64;
+
+            log::info!(
+                "Elapsed: {:?}. Frames: {}. FPS: {}",
+                elapsed,
+                frame,
+                frame * 1_000_000_000 / elapsed_ns
+            );
+
+            graph.take().unwrap().dispose(&mut factory, &());
+        }
+    });
+}
+
+fn main() {
+    env_logger::Builder::from_default_env()
+        .filter_module("indirect", log::LevelFilter::Trace)
+        .init();
+
+    let config: Config = Default::default();
+    let event_loop = EventLoop::new();
+    let window = WindowBuilder::new()
+        .with_inner_size((960, 640).into())
+        .with_title("Rendy example");
+
+    let rendy = AnyWindowedRendy::init_auto(&config, window, &event_loop).unwrap();
+    rendy::with_any_windowed_rendy!((rendy)
+        (mut factory, mut families, surface, window) => {
+            let mut graph_builder = GraphBuilder::<_, ()>::new();
+            let (width, height) = window.inner_size().to_physical(window.hidpi_factor()).into();
+
+            let args = graph_builder.create_buffer(ARGS_SIZE);
+
+            let build_args = graph_builder.add_node(BuildArgsDesc.builder().with_buffer(args));
+
+            graph_builder.add_node(
+                TriangleRenderPipeline::builder()
+                    .with_buffer(args)
+                    .with_dependency(build_args)
+                    .into_subpass()
+                    .with_color_surface()
+                    .into_pass()
+                    .with_surface(
+                        surface,
+                        hal::window::Extent2D {
+                            width,
+                            height,
+                        },
+                        Some(hal::command::ClearValue {
+                            color: hal::command::ClearColor {
+                                float32: [1.0, 1.0, 1.0, 1.0],
+                            },
+                        }),
+                    ),
+            );
+
+            let graph = graph_builder
+                .build(&mut factory, &mut families, &())
+                .unwrap();
+
+            run(event_loop, factory, families, graph);
+        }
+    );
+}
@@ -503,7 +503,9 @@ fn main() {
                 Event::EventsCleared => {
                     factory.maintain(&mut families);
                     if let Some(ref mut graph) = graph {
-                        graph.run(&mut factory, &mut families, &scene);
+                        graph
+                            .run(&mut factory, &mut families, &scene)
+                            .expect("Failed to run graph");
                         frame += 1;
                     }
 
@@ -0,0 +1,333 @@
+//!
+//! Occlusion queries example.
+//! Large triangle occludes some of the small triangles behind it.
+//! Each small triangle is drawn inside occlusion query,
+//! and triangles with no visible samples in the previous frame are skipped.
+//! Skipped triangles are tested again periodically.
+//!
+
+use rendy::{
+    command::{Families, QueueId, RenderPassEncoder},
+    factory::{Config, Factory},
+    graph::{
+        render::*, Graph, GraphBuilder, GraphContext, NodeBuffer, NodeImage, OcclusionQueries,
+    },
+    hal::{self, Backend},
+    init::winit::{
+        event::{Event, WindowEvent},
+        event_loop::{ControlFlow, EventLoop},
+        window::WindowBuilder,
+    },
+    init::AnyWindowedRendy,
+    memory::Dynamic,
+    mesh::{AsVertex, PosColor},
+    resource::{Buffer, BufferInfo, DescriptorSetLayout, Escape, Handle},
+    shader::{ShaderKind, SourceLanguage, SourceShaderInfo, SpirvShader},
+};
+
+/// Hidden objects are tested again every this many frames.
+const RETEST_INTERVAL: u64 = 30;
+
+lazy_static::lazy_static! {
+    static ref VERTEX: SpirvShader = SourceShaderInfo::new(
+        include_str!("../triangle/shader.vert"),
+        concat!(env!("CARGO_MANIFEST_DIR"), "/examples/triangle/shader.vert").into(),
+        ShaderKind::Vertex,
+        SourceLanguage::GLSL,
+        "main",
+    ).precompile().unwrap();
+
+    static ref FRAGMENT: SpirvShader = SourceShaderInfo::new(
+        include_str!("../triangle/shader.frag"),
+        concat!(env!("CARGO_MANIFEST_DIR"), "/examples/triangle/shader.frag").into(),
+        ShaderKind::Fragment,
+        SourceLanguage::GLSL,
+        "main",
+    ).precompile().unwrap();
+
+    static ref SHADERS: rendy::shader::ShaderSetBuilder = rendy::shader::ShaderSetBuilder::default()
+        .with_vertex(&*VERTEX).unwrap()
+        .with_fragment(&*FRAGMENT).unwrap();
+}
+
+/// Centers of small triangles. Two of them are behind the occluder.
+const OBJECTS: [[f32; 2]; 4] = [[-0.7, 0.0], [-0.1, 0.1], [0.1, 0.2], [0.7, 0.0]];
+
+/// Visibility of objects according to the latest resolved queries.
+#[derive(Debug)]
+struct Scene {
+    frame: u64,
+    visible: Vec<bool>,
+}
+
+impl Scene {
+    fn should_draw(&self, object: usize) -> bool {
+        self.visible[object] || self.frame % RETEST_INTERVAL == 0
+    }
+}
+
+#[derive(Debug, Default)]
+struct OcclusionPipelineDesc;
+
+#[derive(Debug)]
+struct OcclusionPipeline<B: hal::Backend> {
+    vertex: Option<Escape<Buffer<B>>>,
+    queries: OcclusionQueries<B>,
+}
+
+impl<B> SimpleGraphicsPipelineDesc<B, Scene> for OcclusionPipelineDesc
+where
+    B: hal::Backend,
+{
+    type Pipeline = OcclusionPipeline<B>;
+
+    fn load_shader_set(
+        &self,
+        factory: &mut Factory<B>,
+        _scene: &Scene,
+    ) -> rendy_shader::ShaderSet<B> {
+        SHADERS.build(factory, Default::default()).unwrap()
+    }
+
+    fn vertices(
+        &self,
+    ) -> Vec<(
+        Vec<hal::pso::Element<hal::format::Format>>,
+        hal::pso::ElemStride,
+        hal::pso::VertexInputRate,
+    )> {
+        vec![PosColor::vertex().gfx_vertex_input_desc(hal::pso::VertexInputRate::Vertex)]
+    }
+
+    fn build<'a>(
+        self,
+        ctx: &GraphContext<B>,
+        _factory: &mut Factory<B>,
+        _queue: QueueId,
+        _scene: &Scene,
+        buffers: Vec<NodeBuffer>,
+        images: Vec<NodeImage>,
+        set_layouts: &[Handle<DescriptorSetLayout<B>>],
+    ) -> Result<OcclusionPipeline<B>, rendy_core::hal::pso::CreationError> {
+        assert!(buffers.is_empty());
+        assert!(images.is_empty());
+        assert!(set_layouts.is_empty());
+
+        Ok(OcclusionPipeline {
+            vertex: None,
+            queries: ctx
+                .occlusion_queries()
+                .expect("Graph is built with occlusion queries")
+                .clone(),
+        })
+    }
+}
+
+fn triangle(center: [f32; 2], size: f32, depth: f32, color: [f32; 4]) -> [PosColor; 3] {
+    let [x, y] = center;
+    [
+        PosColor {
+            position: [x, y - size, depth].into(),
+            color: color.into(),
+        },
+        PosColor {
+            position: [x + size, y + size, depth].into(),
+            color: color.into(),
+        },
+        PosColor {
+            position: [x - size, y + size, depth].into(),
+            color: color.into(),
+        },
+    ]
+}
+
+impl<B> SimpleGraphicsPipeline<B, Scene> for OcclusionPipeline<B>
+where
+    B: hal::Backend,
+{
+    type Desc = OcclusionPipelineDesc;
+
+    fn prepare(
+        &mut self,
+        factory: &Factory<B>,
+        _queue: QueueId,
+        _set_layouts: &[Handle<DescriptorSetLayout<B>>],
+        _index: usize,
+        _scene: &Scene,
+    ) -> PrepareResult {
+        if self.vertex.is_none() {
+            let mut vertices = triangle([0.0, 0.1], 0.4, 0.2, [0.3, 0.3, 0.3, 1.0]).to_vec();
+            for &center in &OBJECTS {
+                vertices.extend_from_slice(&triangle(center, 0.1, 0.6, [1.0, 0.5, 0.0, 1.0]));
+            }
+
+            let mut vbuf = factory
+                .create_buffer(
+                    BufferInfo {
+                        size: PosColor::vertex().stride as u64 * vertices.len() as u64,
+                        usage: hal::buffer::Usage::VERTEX,
+                    },
+                    Dynamic,
+                )
+                .unwrap();
+
+            unsafe {
+                // Fresh buffer.
+                factory
+                    .upload_visible_buffer(&mut vbuf, 0, &vertices)
+                    .unwrap();
+            }
+
+            self.vertex = Some(vbuf);
+        }
+
+        // Queries must be recorded for each frame.
+        PrepareResult::DrawRecord
+    }
+
+    fn draw(
+        &mut self,
+        _layout: &B::PipelineLayout,
+        mut encoder: RenderPassEncoder<'_, B>,
+        _index: usize,
+        scene: &Scene,
+    ) {
+        let vbuf = self.vertex.as_ref().unwrap();
+        unsafe {
+            encoder.bind_vertex_buffers(0, Some((vbuf.raw(), 0)));
+
+            // Occluder must be drawn first to fill depth buffer.
+            encoder.draw(0..3, 0..1);
+
+            for object in 0..OBJECTS.len() {
+                if !scene.should_draw(object) {
+                    continue;
+                }
+                let first = 3 + object as u32 * 3;
+                self.queries.begin(&mut encoder, object as u32);
+                encoder.draw(first..first + 3, 0..1);
+                self.queries.end(&mut encoder, object as u32);
+            }
+        }
+    }
+
+    fn dispose(self, _factory: &mut Factory<B>, _scene: &Scene) {}
+}
+
+fn run<B: Backend>(
+    event_loop: EventLoop<()>,
+    mut factory: Factory<B>,
+    mut families: Families<B>,
+    graph: Graph<B, Scene>,
+) {
+    let started = std::time::Instant::now();
+
+    let mut scene = Scene {
+        frame: 0,
+        visible: vec![true; OBJECTS.len()],
+    };
+    let mut graph = Some(graph);
+
+    event_loop.run(move |event, _, control_flow| {
+        *control_flow = ControlFlow::Poll;
+        match event {
+            Event::WindowEvent { event, .. } => match event {
+                WindowEvent::CloseRequested => *control_flow = ControlFlow::Exit,
+                _ => {}
+            },
+            Event::EventsCleared => {
+                factory.maintain(&mut families);
+                if let Some(ref mut graph) = graph {
+                    graph
+                        .run(&mut factory, &mut families, &scene)
+                        .expect("Failed to run graph");
+                    scene.frame += 1;
+
+                    // Objects that weren't drawn keep their visibility.
+                    for object in 0..OBJECTS.len() {
+                        if let Some(samples) = graph.occlusion_result(object as u32) {
+                            if scene.visible[object] != (samples > 0) {
+                                log::info!("Object {} visible: {}", object, samples > 0);
+                            }
+                            scene.visible[object] = samples > 0;
+                        }
+                    }
+                }
+
+                if started.elapsed() >= std::time::Duration::new(5, 0) {
+                    *control_flow = ControlFlow::Exit
+                }
+            }
+            _ => {}
+        }
+
+        if *control_flow == ControlFlow::Exit && graph.is_some() {
+            log::info!("Frames: {}. Visible: {:?}", scene.frame, scene.visible);
+            graph.take().unwrap().dispose(&mut factory, &scene);
+        }
+    });
+}
+
+fn main() {
+    env_logger::Builder::from_default_env()
+        .filter_module("occlusion", log::LevelFilter::Trace)
+        .init();
+
+    let config: Config = Default::default();
+    let event_loop = EventLoop::new();
+    let window = WindowBuilder::new()
+        .with_inner_size((960, 640).into())
+        .with_title("Rendy example");
+
+    let rendy = AnyWindowedRendy::init_auto(&config, window, &event_loop).unwrap();
+    rendy::with_any_windowed_rendy!((rendy)
+        (mut factory, mut families, surface, window) => {
+            let mut graph_builder = GraphBuilder::<_, Scene>::new()
+                .with_occlusion_queries(OBJECTS.len() as u32, false);
+            let size = window.inner_size().to_physical(window.hidpi_factor());
+
+            let depth = graph_builder.create_image(
+                hal::image::Kind::D2(size.width as u32, size.height as u32, 1, 1),
+                1,
+                hal::format::Format::D32Sfloat,
+                Some(hal::command::ClearValue {
+                    depth_stencil: hal::command::ClearDepthStencil {
+                        depth: 1.0,
+                        stencil: 0,
+                    },
+                }),
+            );
+
+            graph_builder.add_node(
+                OcclusionPipelineDesc
+                    .builder()
+                    .into_subpass()
+                    .with_color_surface()
+                    .with_depth_stencil(depth)
+                    .into_pass()
+                    .with_surface(
+                        surface,
+                        hal::window::Extent2D {
+                            width: size.width as _,
+                            height: size.height as _,
+                        },
+                        Some(hal::command::ClearValue {
+                            color: hal::command::ClearColor {
+                                float32: [1.0, 1.0, 1.0, 1.0],
+                            },
+                        }),
+                    ),
+            );
+
+            let scene = Scene {
+                frame: 0,
+                visible: vec![true; OBJECTS.len()],
+            };
+            let graph = graph_builder
+                .build(&mut factory, &mut families, &scene)
+                .unwrap();
+
+            run(event_loop, factory, families, graph);
+        }
+    );
+}
@@ -366,8 +366,15 @@ where
         _ctx: &GraphContext<B>,
         _factory: &Factory<B>,
         _aux: &T,
-        _frames: &'a Frames<B>,
+        frames: &'a Frames<B>,
     ) -> &'a [Submit<B, SimultaneousUse>] {
+        // `GravBounce` is the first node added to the graph.
+        if let Some(nanoseconds) = frames.node_timings().get(&0) {
+            if frames.next().index() % 100 == 0 {
+                log::info!("GravBounce GPU time: {} us", nanoseconds / 1000);
+            }
+        }
+
         std::slice::from_ref(&self.submit)
     }
 
@@ -552,7 +559,7 @@ fn build_graph<B: hal::Backend>(
     surface: rendy::wsi::Surface<B>,
     window: &Window,
 ) -> Graph<B, ()> {
-    let mut graph_builder = GraphBuilder::<B, ()>::new();
+    let mut graph_builder = GraphBuilder::<B, ()>::new().with_node_timings(true);
 
     let posvel = graph_builder.create_buffer(QUADS as u64 * std::mem::size_of::<[f32; 4]>() as u64);
 
@@ -634,7 +641,9 @@ fn main() {
                     Event::EventsCleared => {
                         factory.maintain(&mut families);
                         if let Some(ref mut graph) = graph {
-                            graph.run(&mut factory, &mut families, &());
+                            graph
+                                .run(&mut factory, &mut families, &())
+                                .expect("Failed to run graph");
                             frame += 1;
                         }
 
@@ -0,0 +1,20 @@
+#version 450
+#extension GL_ARB_separate_shader_objects : enable
+
+layout(constant_id = 0) const float OFFSET = 0.0;
+layout(constant_id = 1) const uint SCALE = 1;
+layout(constant_id = 2) const bool GRAYSCALE = false;
+
+layout(location = 0) in vec3 pos;
+layout(location = 1) in vec4 color;
+layout(location = 0) out vec4 frag_color;
+
+void main() {
+    if (GRAYSCALE) {
+        float luma = dot(color.rgb, vec3(0.299, 0.587, 0.114));
+        frag_color = vec4(vec3(luma), color.a);
+    } else {
+        frag_color = color;
+    }
+    gl_Position = vec4(pos.x / float(SCALE) + OFFSET, pos.y / float(SCALE), pos.z, 1.0);
+}
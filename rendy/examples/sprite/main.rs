@@ -332,7 +332,9 @@ fn run<B: hal::Backend>(
             Event::EventsCleared => {
                 factory.maintain(&mut families);
                 if let Some(ref mut graph) = graph {
-                    graph.run(&mut factory, &mut families, &());
+                    graph
+                        .run(&mut factory, &mut families, &())
+                        .expect("Failed to run graph");
                     frame += 1;
                 }
 
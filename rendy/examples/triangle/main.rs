@@ -213,7 +213,9 @@ fn run<B: Backend>(
             Event::EventsCleared => {
                 factory.maintain(&mut families);
                 if let Some(ref mut graph) = graph {
-                    graph.run(&mut factory, &mut families, &());
+                    graph
+                        .run(&mut factory, &mut families, &())
+                        .expect("Failed to run graph");
                     frame += 1;
                 }
 
@@ -235,7 +237,12 @@ fn run<B: Backend>(
                 frame * 1_000_000_000 / elapsed_ns
             );
 
-            graph.take().unwrap().dispose(&mut factory, &());
+            let graph = graph.take().unwrap();
+            factory
+                .idle_scope(&mut families, |factory, idle| {
+                    graph.dispose_idle(factory, idle, &())
+                })
+                .unwrap();
         }
     });
 }
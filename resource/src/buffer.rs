@@ -123,14 +123,20 @@ where
         &self.info
     }
 
+    /// Get properties of the memory type buffer is bound to.
+    /// Those are properties of the memory type chosen by allocator,
+    /// which may have more flags than requested by memory usage.
+    pub fn properties(&self) -> rendy_core::hal::memory::Properties {
+        self.block.properties()
+    }
+
     /// Check if this buffer could is bound to CPU visible memory and therefore mappable.
     /// If this function returns `false` `map` will always return `InvalidAccess`.
     ///
     /// [`map`]: #method.map
     /// [`InvalidAccess`]: https://docs.rs/gfx-hal/0.1/rendy_core::hal/mapping/enum.Error.html#InvalidAccess
     pub fn visible(&self) -> bool {
-        self.block
-            .properties()
+        self.properties()
             .contains(rendy_core::hal::memory::Properties::CPU_VISIBLE)
     }
 
@@ -0,0 +1,136 @@
+//! A cache to store and retrieve resources by their creation info.
+
+use {
+    crate::escape::Handle,
+    std::{
+        collections::hash_map::{Entry, HashMap},
+        hash::Hash,
+        ops::{Deref, DerefMut},
+    },
+};
+
+/// Resource cache holds handles to resources created with equal info.
+/// Cached resources are released with [`trim`] or when cache is dropped,
+/// and destroyed when the last handle is dropped.
+///
+/// [`trim`]: #method.trim
+#[derive(Debug)]
+pub struct ResourceCache<K, T> {
+    resources: HashMap<K, Handle<T>>,
+}
+
+impl<K, T> Default for ResourceCache<K, T>
+where
+    K: Hash + Eq,
+{
+    fn default() -> Self {
+        ResourceCache {
+            resources: HashMap::default(),
+        }
+    }
+}
+
+impl<K, T> ResourceCache<K, T>
+where
+    K: Hash + Eq,
+{
+    /// Get resource created with specified info.
+    /// Create new one using closure provided.
+    pub fn get<E>(
+        &mut self,
+        info: K,
+        create: impl FnOnce() -> Result<Handle<T>, E>,
+    ) -> Result<Handle<T>, E> {
+        Ok(match self.resources.entry(info) {
+            Entry::Occupied(occupied) => occupied.get().clone(),
+            Entry::Vacant(vacant) => {
+                let resource = create()?;
+                vacant.insert(resource).clone()
+            }
+        })
+    }
+
+    /// Get resource created with specified info.
+    /// Create new one using closure provided.
+    /// Does not lock for writing if resource exists.
+    pub fn get_with_upgradable_lock<R, W, U, E>(
+        read: R,
+        upgrade: U,
+        info: K,
+        create: impl FnOnce() -> Result<Handle<T>, E>,
+    ) -> Result<Handle<T>, E>
+    where
+        R: Deref<Target = Self>,
+        W: DerefMut<Target = Self>,
+        U: FnOnce(R) -> W,
+    {
+        if let Some(resource) = read.resources.get(&info) {
+            return Ok(resource.clone());
+        }
+        let resource = create()?;
+        {
+            upgrade(read)
+                .resources
+                .entry(info)
+                .or_insert_with(|| resource.clone());
+        }
+        Ok(resource)
+    }
+
+    /// Number of cached resources.
+    pub fn len(&self) -> usize {
+        self.resources.len()
+    }
+
+    /// Check if cache is empty.
+    pub fn is_empty(&self) -> bool {
+        self.resources.is_empty()
+    }
+
+    /// Release cached resources that have no handles outside of the cache.
+    /// Returns number of released resources.
+    pub fn trim(&mut self) -> usize {
+        let before = self.resources.len();
+        self.resources
+            .retain(|_, resource| Handle::strong_count(resource) > 1);
+        before - self.resources.len()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use {
+        super::*,
+        crate::escape::Terminal,
+        rendy_core::hal::image::{Filter, SamplerDesc, WrapMode},
+        std::cell::Cell,
+    };
+
+    #[test]
+    fn equal_sampler_info_shares_sampler() {
+        let linear = SamplerDesc::new(Filter::Linear, WrapMode::Tile);
+        let nearest = SamplerDesc::new(Filter::Nearest, WrapMode::Tile);
+        let mut terminal = Terminal::new();
+        let mut cache = ResourceCache::default();
+        let created = Cell::new(0);
+        let create = || -> Result<_, ()> {
+            created.set(created.get() + 1);
+            Ok(terminal.escape(created.get()).into())
+        };
+
+        let first = cache.get(linear.clone(), create).unwrap();
+        let second = cache.get(linear, create).unwrap();
+        let other = cache.get(nearest, create).unwrap();
+        assert_eq!(created.get(), 2);
+        assert!(std::ptr::eq(&*first, &*second));
+        assert!(!std::ptr::eq(&*first, &*other));
+
+        drop((first, other));
+        assert_eq!(cache.trim(), 1);
+        assert_eq!(cache.len(), 1);
+        assert_eq!(terminal.drain().collect::<Vec<_>>(), vec![2]);
+
+        drop((second, cache));
+        assert_eq!(terminal.drain().collect::<Vec<_>>(), vec![1]);
+    }
+}
@@ -158,6 +158,13 @@ pub struct Handle<T> {
     inner: Arc<Escape<T>>,
 }
 
+impl<T> Handle<T> {
+    /// Get number of handles to the value.
+    pub fn strong_count(handle: &Self) -> usize {
+        Arc::strong_count(&handle.inner)
+    }
+}
+
 impl<T> Clone for Handle<T> {
     fn clone(&self) -> Self {
         Handle {
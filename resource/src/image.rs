@@ -6,11 +6,12 @@ use {
     crate::{
         core::{device_owned, Device, DeviceId},
         escape::Handle,
+        layout::LayoutMismatch,
         memory::{Block, Heaps, MemoryBlock, MemoryUsage},
         CreationError,
     },
     relevant::Relevant,
-    rendy_core::hal::{device::Device as _, format, Backend},
+    rendy_core::hal::{device::Device as _, format, memory::Requirements, Backend},
 };
 
 /// Image info.
@@ -45,7 +46,10 @@ pub struct Image<B: Backend> {
     device: DeviceId,
     raw: B::Image,
     block: Option<MemoryBlock<B>>,
+    properties: Option<rendy_core::hal::memory::Properties>,
     info: ImageInfo,
+    #[cfg(feature = "debug-layout-tracking")]
+    layouts: crate::layout::LayoutTracker,
     relevant: Relevant,
 }
 
@@ -110,19 +114,120 @@ where
         Ok(Image {
             device: device.id(),
             raw: img,
+            properties: Some(block.properties()),
             block: Some(block),
             info,
+            #[cfg(feature = "debug-layout-tracking")]
+            layouts: crate::layout::LayoutTracker::new(info.levels, info.kind.num_layers()),
             relevant: Relevant,
         })
     }
 
+    /// Create images that share one memory block.
+    /// Memory is allocated once to fit all images and is owned by the first one.
+    ///
+    /// # Safety
+    ///
+    /// Same as for `create`.
+    /// Additionally images must not be used simultaneously,
+    /// content of the memory is undefined after it was written through another image,
+    /// and no image can be used after the first one is disposed.
+    pub unsafe fn create_aliased(
+        device: &Device<B>,
+        heaps: &mut Heaps<B>,
+        infos: impl IntoIterator<Item = ImageInfo>,
+        memory_usage: impl MemoryUsage,
+    ) -> Result<Vec<Self>, ImageCreationError> {
+        let mut images: Vec<(B::Image, ImageInfo)> = Vec::new();
+
+        let destroy = |images: Vec<(B::Image, ImageInfo)>| {
+            for (img, _) in images {
+                device.destroy_image(img);
+            }
+        };
+
+        for info in infos {
+            assert!(
+                info.levels <= info.kind.num_levels(),
+                "Number of mip leves ({}) cannot be greater than {} for given kind {:?}",
+                info.levels,
+                info.kind.num_levels(),
+                info.kind,
+            );
+
+            log::trace!("{:#?}@{:#?} aliased", info, memory_usage);
+
+            match device.create_image(
+                info.kind,
+                info.levels,
+                info.format,
+                info.tiling,
+                info.usage,
+                info.view_caps,
+            ) {
+                Ok(img) => images.push((img, info)),
+                Err(err) => {
+                    destroy(images);
+                    return Err(CreationError::Create(err));
+                }
+            }
+        }
+
+        if images.is_empty() {
+            return Ok(Vec::new());
+        }
+
+        let (type_mask, size, alignment) = aliased_requirements(
+            images
+                .iter()
+                .map(|(img, _)| device.get_image_requirements(img)),
+        );
+
+        let block = match heaps.allocate(device, type_mask, memory_usage, size, alignment) {
+            Ok(block) => block,
+            Err(err) => {
+                destroy(images);
+                return Err(CreationError::Allocate(err));
+            }
+        };
+
+        for index in 0..images.len() {
+            if let Err(err) =
+                device.bind_image_memory(block.memory(), block.range().start, &mut images[index].0)
+            {
+                destroy(images);
+                heaps.free(device, block);
+                return Err(CreationError::Bind(err));
+            }
+        }
+
+        let properties = block.properties();
+        let mut block = Some(block);
+        Ok(images
+            .into_iter()
+            .map(|(img, info)| Image {
+                device: device.id(),
+                raw: img,
+                block: block.take(),
+                properties: Some(properties),
+                info,
+                #[cfg(feature = "debug-layout-tracking")]
+                layouts: crate::layout::LayoutTracker::new(info.levels, info.kind.num_layers()),
+                relevant: Relevant,
+            })
+            .collect())
+    }
+
     /// Create image handler for swapchain image.
     pub unsafe fn create_from_swapchain(device: DeviceId, info: ImageInfo, raw: B::Image) -> Self {
         Image {
             device,
             raw,
             block: None,
+            properties: None,
             info,
+            #[cfg(feature = "debug-layout-tracking")]
+            layouts: crate::layout::LayoutTracker::new(info.levels, info.kind.num_layers()),
             relevant: Relevant,
         }
     }
@@ -153,10 +258,20 @@ where
     }
 
     /// Get reference to memory block occupied by image.
+    /// Aliased images except the first one don't own memory block.
     pub fn block(&self) -> Option<&MemoryBlock<B>> {
         self.block.as_ref()
     }
 
+    /// Get properties of the memory type image is bound to.
+    /// Those are properties of the memory type chosen by allocator,
+    /// which may have more flags than requested by memory usage.
+    /// Aliased images report properties of the memory block they share.
+    /// Returns `None` for swapchain images that are not bound by rendy.
+    pub fn properties(&self) -> Option<rendy_core::hal::memory::Properties> {
+        self.properties
+    }
+
     /// Get mutable reference to memory block occupied by image.
     pub unsafe fn block_mut(&mut self) -> Option<&mut MemoryBlock<B>> {
         self.block.as_mut()
@@ -190,6 +305,30 @@ where
     pub fn layers(&self) -> u16 {
         self.info.kind.num_layers()
     }
+
+    /// Check that barrier transitions subresources in `range` from the layout they are in
+    /// and record them to be in `layouts.end`.
+    /// `Undefined` start layout matches any since content is discarded.
+    ///
+    /// Layouts are tracked only with `debug-layout-tracking` feature,
+    /// otherwise this function always succeeds and costs nothing.
+    #[inline]
+    pub fn track_layout_transition(
+        &self,
+        range: &SubresourceRange,
+        layouts: std::ops::Range<Layout>,
+    ) -> Result<(), LayoutMismatch> {
+        #[cfg(feature = "debug-layout-tracking")]
+        {
+            self.layouts.transition(range, layouts)
+        }
+
+        #[cfg(not(feature = "debug-layout-tracking"))]
+        {
+            let _ = (range, layouts);
+            Ok(())
+        }
+    }
 }
 
 /// Image view info
@@ -324,3 +463,45 @@ fn match_kind(kind: Kind, view_kind: ViewKind, view_caps: ViewCapabilities) -> b
         }
     }
 }
+
+/// Memory type mask, size and alignment of the block that fits every image with `requirements`.
+fn aliased_requirements(requirements: impl IntoIterator<Item = Requirements>) -> (u32, u64, u64) {
+    requirements
+        .into_iter()
+        .fold((!0u32, 0u64, 1u64), |(type_mask, size, alignment), reqs| {
+            (
+                type_mask & reqs.type_mask as u32,
+                size.max(reqs.size),
+                alignment.max(reqs.alignment),
+            )
+        })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{aliased_requirements, Requirements};
+
+    #[test]
+    fn aliased_images_fit_one_block() {
+        let color = Requirements {
+            size: 1024,
+            alignment: 256,
+            type_mask: 0b0111,
+        };
+        let depth = Requirements {
+            size: 4096,
+            alignment: 64,
+            type_mask: 0b0110,
+        };
+
+        // Both images are bound at the start of one block.
+        assert_eq!(
+            aliased_requirements(vec![color, depth]),
+            (0b0110, 4096, 256)
+        );
+        assert_eq!(
+            aliased_requirements(vec![depth, color]),
+            (0b0110, 4096, 256)
+        );
+    }
+}
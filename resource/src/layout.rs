@@ -0,0 +1,166 @@
+//! Layout tracking of image subresources for debugging.
+
+use {
+    rendy_core::hal::image::{Layer, Layout, Level, SubresourceRange},
+    std::{ops::Range, sync::Mutex},
+};
+
+/// Layout of subresource doesn't match one barrier transitions it from.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct LayoutMismatch {
+    /// Array layer of mismatched subresource.
+    pub layer: Layer,
+
+    /// Mip level of mismatched subresource.
+    pub level: Level,
+
+    /// Layout subresource is in.
+    pub tracked: Layout,
+
+    /// Layout barrier transitions subresource from.
+    pub expected: Layout,
+}
+
+impl std::fmt::Display for LayoutMismatch {
+    fn fmt(&self, fmt: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            fmt,
+            "Subresource (layer: {}, level: {}) is in {:?} layout, but barrier transitions it from {:?}",
+            self.layer, self.level, self.tracked, self.expected,
+        )
+    }
+}
+
+impl std::error::Error for LayoutMismatch {}
+
+/// Current layouts of each subresource of an image.
+#[derive(Debug)]
+#[cfg_attr(not(feature = "debug-layout-tracking"), allow(dead_code))]
+pub(crate) struct LayoutTracker {
+    levels: Level,
+    layers: Layer,
+    layouts: Mutex<Vec<Layout>>,
+}
+
+#[cfg_attr(not(feature = "debug-layout-tracking"), allow(dead_code))]
+impl LayoutTracker {
+    /// Create tracker for image with all subresources in `Undefined` layout.
+    pub(crate) fn new(levels: Level, layers: Layer) -> Self {
+        LayoutTracker {
+            levels,
+            layers,
+            layouts: Mutex::new(vec![Layout::Undefined; levels as usize * layers as usize]),
+        }
+    }
+
+    /// Check that subresources in `range` are in `layouts.start` layout
+    /// and record them to be in `layouts.end`.
+    /// `Undefined` matches any layout since content is discarded.
+    ///
+    /// Nothing is recorded on mismatch.
+    pub(crate) fn transition(
+        &self,
+        range: &SubresourceRange,
+        layouts: Range<Layout>,
+    ) -> Result<(), LayoutMismatch> {
+        let mut tracked = self.layouts.lock().unwrap();
+        let levels = range.levels.start..range.levels.end.min(self.levels);
+        let layers = range.layers.start..range.layers.end.min(self.layers);
+
+        if layouts.start != Layout::Undefined {
+            for layer in layers.clone() {
+                for level in levels.clone() {
+                    let index = layer as usize * self.levels as usize + level as usize;
+                    if tracked[index] != layouts.start {
+                        return Err(LayoutMismatch {
+                            layer,
+                            level,
+                            tracked: tracked[index],
+                            expected: layouts.start,
+                        });
+                    }
+                }
+            }
+        }
+
+        for layer in layers {
+            for level in levels.clone() {
+                tracked[layer as usize * self.levels as usize + level as usize] = layouts.end;
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use {
+        super::{LayoutMismatch, LayoutTracker},
+        rendy_core::hal::{
+            format::Aspects,
+            image::{Layout, SubresourceRange},
+        },
+    };
+
+    fn range(levels: std::ops::Range<u8>, layers: std::ops::Range<u16>) -> SubresourceRange {
+        SubresourceRange {
+            aspects: Aspects::COLOR,
+            levels,
+            layers,
+        }
+    }
+
+    #[test]
+    fn matching_barriers_pass() {
+        let tracker = LayoutTracker::new(2, 2);
+        let all = range(0..2, 0..2);
+        tracker
+            .transition(&all, Layout::Undefined..Layout::TransferDstOptimal)
+            .unwrap();
+        tracker
+            .transition(
+                &all,
+                Layout::TransferDstOptimal..Layout::ShaderReadOnlyOptimal,
+            )
+            .unwrap();
+        tracker
+            .transition(&range(1..2, 0..1), Layout::Undefined..Layout::General)
+            .unwrap();
+        tracker
+            .transition(
+                &range(1..2, 0..1),
+                Layout::General..Layout::ShaderReadOnlyOptimal,
+            )
+            .unwrap();
+    }
+
+    #[test]
+    fn mismatched_barrier_is_caught() {
+        let tracker = LayoutTracker::new(1, 3);
+        tracker
+            .transition(
+                &range(0..1, 0..3),
+                Layout::Undefined..Layout::ColorAttachmentOptimal,
+            )
+            .unwrap();
+        tracker
+            .transition(
+                &range(0..1, 2..3),
+                Layout::ColorAttachmentOptimal..Layout::TransferSrcOptimal,
+            )
+            .unwrap();
+
+        assert_eq!(
+            tracker.transition(
+                &range(0..1, 0..3),
+                Layout::ColorAttachmentOptimal..Layout::ShaderReadOnlyOptimal,
+            ),
+            Err(LayoutMismatch {
+                layer: 2,
+                level: 0,
+                tracked: Layout::TransferSrcOptimal,
+                expected: Layout::ColorAttachmentOptimal,
+            })
+        );
+    }
+}
@@ -16,14 +16,22 @@ use rendy_descriptor as descriptor;
 use rendy_memory as memory;
 
 mod buffer;
+mod cache;
 mod escape;
 mod image;
+mod layout;
+mod pipeline_layout;
+mod render_pass;
 mod set;
 
 mod resources;
 mod sampler;
+mod uniform;
 
-pub use crate::{buffer::*, escape::*, image::*, resources::*, sampler::*, set::*};
+pub use crate::{
+    buffer::*, cache::*, escape::*, image::*, layout::LayoutMismatch, pipeline_layout::*,
+    render_pass::*, resources::*, sampler::*, set::*, uniform::*,
+};
 
 /// Error creating a resource.
 #[derive(Clone, Debug, PartialEq)]
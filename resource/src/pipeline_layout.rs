@@ -0,0 +1,92 @@
+//! Pipeline layout creation-info and wrappers.
+
+use {
+    crate::{
+        core::{device_owned, Device, DeviceId},
+        escape::Handle,
+        set::{DescriptorSetInfo, DescriptorSetLayout},
+    },
+    relevant::Relevant,
+    rendy_core::hal::{device::Device as _, pso::ShaderStageFlags, Backend},
+    std::ops::Range,
+};
+
+/// Pipeline layout info.
+#[derive(Clone, Debug, Default, PartialEq, Eq, Hash)]
+pub struct PipelineLayoutInfo {
+    /// Layouts of descriptor sets.
+    pub sets: Vec<DescriptorSetInfo>,
+    /// Push constant ranges.
+    pub push_constants: Vec<(ShaderStageFlags, Range<u32>)>,
+}
+
+/// Generic pipeline layout resource wrapper.
+#[derive(Debug)]
+pub struct PipelineLayout<B: Backend> {
+    device: DeviceId,
+    raw: B::PipelineLayout,
+    info: PipelineLayoutInfo,
+    set_layouts: Vec<Handle<DescriptorSetLayout<B>>>,
+    relevant: Relevant,
+}
+
+device_owned!(PipelineLayout<B>);
+
+impl<B> PipelineLayout<B>
+where
+    B: Backend,
+{
+    /// Create new pipeline layout.
+    /// Descriptor set layouts are kept alive as long as the pipeline layout.
+    pub unsafe fn create(
+        device: &Device<B>,
+        set_layouts: Vec<Handle<DescriptorSetLayout<B>>>,
+        push_constants: Vec<(ShaderStageFlags, Range<u32>)>,
+    ) -> Result<Self, rendy_core::hal::device::OutOfMemory> {
+        let raw = device.create_pipeline_layout(
+            set_layouts.iter().map(|layout| layout.raw()),
+            &push_constants,
+        )?;
+
+        Ok(PipelineLayout {
+            device: device.id(),
+            raw,
+            info: PipelineLayoutInfo {
+                sets: set_layouts
+                    .iter()
+                    .map(|layout| layout.info().clone())
+                    .collect(),
+                push_constants,
+            },
+            set_layouts,
+            relevant: Relevant,
+        })
+    }
+
+    /// Destroy pipeline layout resource.
+    pub unsafe fn dispose(self, device: &Device<B>) {
+        self.assert_device_owner(device);
+        device.destroy_pipeline_layout(self.raw);
+        self.relevant.dispose();
+    }
+
+    /// Get reference to raw pipeline layout resource.
+    pub fn raw(&self) -> &B::PipelineLayout {
+        &self.raw
+    }
+
+    /// Get mutable reference to raw pipeline layout resource.
+    pub unsafe fn raw_mut(&mut self) -> &mut B::PipelineLayout {
+        &mut self.raw
+    }
+
+    /// Get pipeline layout info.
+    pub fn info(&self) -> &PipelineLayoutInfo {
+        &self.info
+    }
+
+    /// Get descriptor set layouts of the pipeline layout.
+    pub fn set_layouts(&self) -> &[Handle<DescriptorSetLayout<B>>] {
+        &self.set_layouts
+    }
+}
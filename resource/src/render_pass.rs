@@ -0,0 +1,103 @@
+//! Render pass creation-info and wrappers.
+
+use {
+    crate::core::{device_owned, Device, DeviceId},
+    relevant::Relevant,
+    rendy_core::hal::{
+        device::Device as _,
+        pass::{Attachment, AttachmentId, AttachmentRef, SubpassDependency, SubpassDesc},
+        Backend,
+    },
+};
+
+/// Subpass of render pass info.
+/// Owning version of `SubpassDesc`.
+#[derive(Clone, Debug, Default, PartialEq, Eq, Hash)]
+pub struct SubpassInfo {
+    /// Color attachments.
+    pub colors: Vec<AttachmentRef>,
+    /// Depth-stencil attachment.
+    pub depth_stencil: Option<AttachmentRef>,
+    /// Input attachments.
+    pub inputs: Vec<AttachmentRef>,
+    /// Resolve attachments, one for each color attachment if any.
+    pub resolves: Vec<AttachmentRef>,
+    /// Attachments that are not used by the subpass but must be preserved.
+    pub preserves: Vec<AttachmentId>,
+}
+
+/// Render pass info.
+#[derive(Clone, Debug, Default, PartialEq, Hash)]
+pub struct RenderPassInfo {
+    /// Attachments of the render pass.
+    pub attachments: Vec<Attachment>,
+    /// Subpasses of the render pass.
+    pub subpasses: Vec<SubpassInfo>,
+    /// Dependencies between subpasses.
+    pub dependencies: Vec<SubpassDependency>,
+}
+
+impl Eq for RenderPassInfo {}
+
+/// Generic render pass resource wrapper.
+#[derive(Debug)]
+pub struct RenderPass<B: Backend> {
+    device: DeviceId,
+    raw: B::RenderPass,
+    info: RenderPassInfo,
+    relevant: Relevant,
+}
+
+device_owned!(RenderPass<B>);
+
+impl<B> RenderPass<B>
+where
+    B: Backend,
+{
+    /// Create new render pass.
+    pub unsafe fn create(
+        device: &Device<B>,
+        info: RenderPassInfo,
+    ) -> Result<Self, rendy_core::hal::device::OutOfMemory> {
+        let raw = device.create_render_pass(
+            &info.attachments,
+            info.subpasses.iter().map(|subpass| SubpassDesc {
+                colors: &subpass.colors,
+                depth_stencil: subpass.depth_stencil.as_ref(),
+                inputs: &subpass.inputs,
+                resolves: &subpass.resolves,
+                preserves: &subpass.preserves,
+            }),
+            &info.dependencies,
+        )?;
+
+        Ok(RenderPass {
+            device: device.id(),
+            raw,
+            info,
+            relevant: Relevant,
+        })
+    }
+
+    /// Destroy render pass resource.
+    pub unsafe fn dispose(self, device: &Device<B>) {
+        self.assert_device_owner(device);
+        device.destroy_render_pass(self.raw);
+        self.relevant.dispose();
+    }
+
+    /// Get reference to raw render pass resource.
+    pub fn raw(&self) -> &B::RenderPass {
+        &self.raw
+    }
+
+    /// Get mutable reference to raw render pass resource.
+    pub unsafe fn raw_mut(&mut self) -> &mut B::RenderPass {
+        &mut self.raw
+    }
+
+    /// Get render pass info.
+    pub fn info(&self) -> &RenderPassInfo {
+        &self.info
+    }
+}
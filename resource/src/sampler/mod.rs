@@ -1,14 +1,16 @@
 //! Sampler creation-info and wrappers.
 
-mod cache;
-
 use {
-    crate::core::{device_owned, Device, DeviceId},
+    crate::{
+        cache::ResourceCache,
+        core::{device_owned, Device, DeviceId},
+    },
     relevant::Relevant,
     rendy_core::hal::{device::Device as _, image::SamplerDesc, Backend},
 };
 
-pub use crate::sampler::cache::SamplerCache;
+/// Sampler cache holds handlers to created samplers.
+pub type SamplerCache<B> = ResourceCache<SamplerDesc, Sampler<B>>;
 
 /// Generic sampler resource wrapper.
 #[derive(Debug)]
@@ -3,9 +3,14 @@ use {
         core::{device_owned, Device, DeviceId},
         descriptor,
         escape::Handle,
+        sampler::Sampler,
     },
     relevant::Relevant,
-    rendy_core::hal::{device::Device as _, pso::DescriptorSetLayoutBinding, Backend},
+    rendy_core::hal::{
+        device::Device as _,
+        pso::{DescriptorBinding, DescriptorSetLayoutBinding, DescriptorType},
+        Backend,
+    },
     smallvec::SmallVec,
 };
 
@@ -16,11 +21,83 @@ pub struct DescriptorSetInfo {
     pub bindings: Vec<DescriptorSetLayoutBinding>,
 }
 
+// Bindings are compared field by field to allow caching layouts by their info.
+impl PartialEq for DescriptorSetInfo {
+    fn eq(&self, other: &Self) -> bool {
+        self.bindings.len() == other.bindings.len()
+            && self
+                .bindings
+                .iter()
+                .zip(&other.bindings)
+                .all(|(left, right)| binding_key(left) == binding_key(right))
+    }
+}
+
+impl Eq for DescriptorSetInfo {}
+
+impl std::hash::Hash for DescriptorSetInfo {
+    fn hash<H: std::hash::Hasher>(&self, state: &mut H) {
+        self.bindings.len().hash(state);
+        for binding in &self.bindings {
+            binding_key(binding).hash(state);
+        }
+    }
+}
+
+fn binding_key(
+    binding: &DescriptorSetLayoutBinding,
+) -> (
+    DescriptorBinding,
+    DescriptorType,
+    usize,
+    rendy_core::hal::pso::ShaderStageFlags,
+    bool,
+) {
+    (
+        binding.binding,
+        binding.ty,
+        binding.count,
+        binding.stage_flags,
+        binding.immutable_samplers,
+    )
+}
+
 impl DescriptorSetInfo {
     /// Get descriptor ranges of the layout.
     pub fn ranges(&self) -> descriptor::DescriptorRanges {
         descriptor::DescriptorRanges::from_bindings(&self.bindings)
     }
+
+    /// Get number of immutable samplers the layout is created with.
+    /// Those are consumed in order of bindings, `count` samplers per binding.
+    pub fn immutable_sampler_count(&self) -> usize {
+        self.bindings
+            .iter()
+            .filter(|binding| binding.immutable_samplers)
+            .map(|binding| binding.count)
+            .sum()
+    }
+
+    /// Get number of descriptors in the binding.
+    /// Returns `None` if layout has no such binding.
+    pub fn binding_count(&self, binding: DescriptorBinding) -> Option<usize> {
+        self.bindings
+            .iter()
+            .find(|other| other.binding == binding)
+            .map(|binding| binding.count)
+    }
+
+    /// Check if descriptors of the binding must be written before the set is used.
+    /// Sampler bindings with immutable samplers are initialized by the layout,
+    /// combined image samplers still need images to be written.
+    pub fn requires_write(&self, binding: DescriptorBinding) -> bool {
+        self.bindings
+            .iter()
+            .find(|other| other.binding == binding)
+            .map_or(false, |binding| {
+                !(binding.immutable_samplers && binding.ty == DescriptorType::Sampler)
+            })
+    }
 }
 
 /// Generic descriptor set layout resource wrapper.
@@ -29,6 +106,7 @@ pub struct DescriptorSetLayout<B: Backend> {
     device: DeviceId,
     raw: B::DescriptorSetLayout,
     info: DescriptorSetInfo,
+    immutable_samplers: Vec<Handle<Sampler<B>>>,
     relevant: Relevant,
 }
 
@@ -43,13 +121,52 @@ where
         device: &Device<B>,
         info: DescriptorSetInfo,
     ) -> Result<Self, rendy_core::hal::device::OutOfMemory> {
-        let raw = device
-            .create_descriptor_set_layout(&info.bindings, std::iter::empty::<B::Sampler>())?;
+        Self::create_with_immutable_samplers(device, info, Vec::new())
+    }
+
+    /// Create new descriptor set layout with samplers baked into it.
+    /// Samplers are used by bindings with `immutable_samplers` flag in order of bindings
+    /// and are kept alive as long as the layout.
+    ///
+    /// # Panics
+    ///
+    /// Panics if number of samplers doesn't match `DescriptorSetInfo::immutable_sampler_count`
+    /// or immutable samplers are set for bindings that are neither samplers
+    /// nor combined image samplers.
+    pub unsafe fn create_with_immutable_samplers(
+        device: &Device<B>,
+        info: DescriptorSetInfo,
+        immutable_samplers: Vec<Handle<Sampler<B>>>,
+    ) -> Result<Self, rendy_core::hal::device::OutOfMemory> {
+        assert_eq!(
+            immutable_samplers.len(),
+            info.immutable_sampler_count(),
+            "Number of immutable samplers must match bindings"
+        );
+        for binding in info
+            .bindings
+            .iter()
+            .filter(|binding| binding.immutable_samplers)
+        {
+            assert!(
+                binding.ty == DescriptorType::Sampler
+                    || binding.ty == DescriptorType::CombinedImageSampler,
+                "Binding {} of type {:?} can't have immutable samplers",
+                binding.binding,
+                binding.ty,
+            );
+        }
+
+        let raw = device.create_descriptor_set_layout(
+            &info.bindings,
+            immutable_samplers.iter().map(|sampler| sampler.raw()),
+        )?;
 
         Ok(DescriptorSetLayout {
             device: device.id(),
             raw,
             info,
+            immutable_samplers,
             relevant: Relevant,
         })
     }
@@ -75,6 +192,11 @@ where
     pub fn info(&self) -> &DescriptorSetInfo {
         &self.info
     }
+
+    /// Get immutable samplers baked into the layout.
+    pub fn immutable_samplers(&self) -> &[Handle<Sampler<B>>] {
+        &self.immutable_samplers
+    }
 }
 
 /// Generic descriptor set resource wrapper.
@@ -162,3 +284,59 @@ where
         &self.layout
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use {
+        super::DescriptorSetInfo,
+        rendy_core::hal::pso::{DescriptorSetLayoutBinding, DescriptorType, ShaderStageFlags},
+    };
+
+    fn binding(
+        binding: u32,
+        ty: DescriptorType,
+        count: usize,
+        immutable_samplers: bool,
+    ) -> DescriptorSetLayoutBinding {
+        DescriptorSetLayoutBinding {
+            binding,
+            ty,
+            count,
+            stage_flags: ShaderStageFlags::FRAGMENT,
+            immutable_samplers,
+        }
+    }
+
+    #[test]
+    fn immutable_samplers_need_no_writes() {
+        let info = DescriptorSetInfo {
+            bindings: vec![
+                binding(0, DescriptorType::Sampler, 2, true),
+                binding(1, DescriptorType::SampledImage, 1, false),
+                binding(2, DescriptorType::CombinedImageSampler, 1, true),
+                binding(3, DescriptorType::Sampler, 1, false),
+            ],
+        };
+
+        assert_eq!(info.immutable_sampler_count(), 3);
+        assert!(!info.requires_write(0));
+        assert!(info.requires_write(1));
+        assert!(info.requires_write(2));
+        assert!(info.requires_write(3));
+        assert!(!info.requires_write(4));
+    }
+
+    #[test]
+    fn array_bindings_report_count() {
+        let info = DescriptorSetInfo {
+            bindings: vec![
+                binding(0, DescriptorType::Sampler, 1, false),
+                binding(1, DescriptorType::SampledImage, 16, false),
+            ],
+        };
+
+        assert_eq!(info.binding_count(0), Some(1));
+        assert_eq!(info.binding_count(1), Some(16));
+        assert_eq!(info.binding_count(2), None);
+    }
+}
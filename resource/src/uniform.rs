@@ -0,0 +1,140 @@
+//! Ring of uniform values, one per frame in flight.
+
+use {
+    crate::{
+        buffer::{Buffer, Usage},
+        core::Device,
+        escape::Escape,
+        memory::Write as _,
+    },
+    rendy_core::hal::{device::MapError, Backend},
+    std::marker::PhantomData,
+};
+
+/// Size of `T` rounded up to multiple of `align`.
+/// Use `min_uniform_buffer_offset_alignment` limit for dynamic uniform offsets.
+pub fn aligned_size_of<T>(align: u64) -> u64 {
+    let size = std::mem::size_of::<T>() as u64;
+    let align = align.max(1);
+    ((size + align - 1) / align) * align
+}
+
+/// Buffer with slot for value of `T` for each frame in flight.
+///
+/// Slot of frame is selected by dynamic offset returned from `write`,
+/// so single descriptor set with `UniformBufferDynamic` binding
+/// covers all frames.
+#[derive(Debug)]
+pub struct UniformRing<B: Backend, T> {
+    buffer: Escape<Buffer<B>>,
+    stride: u64,
+    frames: usize,
+    marker: PhantomData<fn(T)>,
+}
+
+impl<B, T> UniformRing<B, T>
+where
+    B: Backend,
+    T: Copy,
+{
+    /// Wrap host-visible uniform buffer with `frames` slots aligned to `align`.
+    ///
+    /// # Panics
+    ///
+    /// Panics if buffer is not mappable, lacks `UNIFORM` usage
+    /// or smaller than `frames * aligned_size_of::<T>(align)`.
+    pub fn new(buffer: Escape<Buffer<B>>, frames: usize, align: u64) -> Self {
+        let stride = aligned_size_of::<T>(align);
+        assert!(frames > 0);
+        assert!(buffer.visible(), "Uniform ring must be host-visible");
+        assert!(buffer.info().usage.contains(Usage::UNIFORM));
+        assert!(buffer.size() >= stride * frames as u64);
+
+        UniformRing {
+            buffer,
+            stride,
+            frames,
+            marker: PhantomData,
+        }
+    }
+
+    /// Get buffer of the ring.
+    pub fn buffer(&self) -> &Buffer<B> {
+        &self.buffer
+    }
+
+    /// Number of frames the ring has slots for.
+    pub fn frames(&self) -> usize {
+        self.frames
+    }
+
+    /// Distance between slots of consecutive frames.
+    pub fn stride(&self) -> u64 {
+        self.stride
+    }
+
+    /// Range to bind with `UniformBufferDynamic` descriptor.
+    pub fn range(&self) -> std::ops::Range<u64> {
+        0..std::mem::size_of::<T>() as u64
+    }
+
+    /// Dynamic offset of `frame` slot.
+    pub fn offset(&self, frame: usize) -> u32 {
+        frame_offset(frame, self.frames, self.stride)
+    }
+
+    /// Write `value` into `frame` slot and return dynamic offset to bind it with.
+    /// Frame index wraps around number of frames.
+    ///
+    /// # Safety
+    ///
+    /// Device must not be reading the slot,
+    /// i.e. commands of the previous frame with the same index must be complete.
+    pub unsafe fn write(
+        &mut self,
+        device: &Device<B>,
+        frame: usize,
+        value: &T,
+    ) -> Result<u32, MapError> {
+        let offset = self.offset(frame);
+        let size = std::mem::size_of::<T>() as u64;
+        let start = offset as u64;
+        let mut mapped = self.buffer.map(device, start..start + size)?;
+        mapped
+            .write(device, 0..size)?
+            .write(std::slice::from_ref(value));
+        Ok(offset)
+    }
+}
+
+fn frame_offset(frame: usize, frames: usize, stride: u64) -> u32 {
+    let offset = (frame % frames) as u64 * stride;
+    assert!(offset <= u32::max_value() as u64);
+    offset as u32
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    type Camera = [[f32; 4]; 4];
+
+    #[test]
+    fn size_is_rounded_to_alignment() {
+        assert_eq!(aligned_size_of::<Camera>(256), 256);
+        assert_eq!(aligned_size_of::<Camera>(16), 64);
+        assert_eq!(aligned_size_of::<[f32; 3]>(16), 16);
+        assert_eq!(aligned_size_of::<[f32; 3]>(0), 12);
+    }
+
+    #[test]
+    fn offsets_are_aligned_and_distinct() {
+        let align = 256;
+        let stride = aligned_size_of::<Camera>(align);
+        let offsets: Vec<_> = (0..3).map(|frame| frame_offset(frame, 3, stride)).collect();
+
+        assert_eq!(offsets, vec![0, 256, 512]);
+        assert!(offsets.iter().all(|&o| o as u64 % align == 0));
+        assert_eq!(frame_offset(3, 3, stride), 0);
+    }
+}
@@ -14,6 +14,11 @@
 #[cfg(feature = "shader-compiler")]
 mod shaderc;
 
+#[cfg(feature = "shader-reload")]
+mod reload;
+
+mod spec;
+
 #[cfg(feature = "spirv-reflection")]
 #[allow(dead_code)]
 mod reflect;
@@ -21,6 +26,11 @@ mod reflect;
 #[cfg(feature = "shader-compiler")]
 pub use self::shaderc::*;
 
+#[cfg(feature = "shader-reload")]
+pub use self::reload::*;
+
+pub use self::spec::*;
+
 #[cfg(feature = "spirv-reflection")]
 pub use self::reflect::{ReflectError, ReflectTypeError, RetrievalKind, SpirvReflection};
 
@@ -164,6 +174,13 @@ where
 }
 
 impl<B: Backend> ShaderSet<B> {
+    /// Get stages of shaders in the set.
+    pub fn stages(&self) -> ShaderStageFlags {
+        self.shaders
+            .keys()
+            .fold(ShaderStageFlags::empty(), |stages, &stage| stages | stage)
+    }
+
     /// This function compiles and loads all shaders into B::ShaderModule objects which must be dropped later with `dispose`
     pub fn load(
         &mut self,
@@ -206,6 +223,54 @@ impl<B: Backend> ShaderSet<B> {
         })
     }
 
+    /// Returns the compute shader `EntryPoint` to use with `ComputePipelineDesc` in rendy_core::hal.
+    /// Returns `None` if set doesn't contain compute shader.
+    pub fn raw_compute<'a>(
+        &'a self,
+    ) -> Result<Option<rendy_core::hal::pso::EntryPoint<'a, B>>, ShaderError> {
+        match self.shaders.get(&ShaderStageFlags::COMPUTE) {
+            Some(compute) => compute.get_entry_point(),
+            None => Ok(None),
+        }
+    }
+
+    /// Replace specialization of shaders in this set.
+    /// Stages that are `None` in `constants` keep their specialization.
+    pub fn specialize(&mut self, constants: SpecConstantSet) {
+        let stages = vec![
+            (ShaderStageFlags::VERTEX, constants.vertex),
+            (ShaderStageFlags::FRAGMENT, constants.fragment),
+            (ShaderStageFlags::GEOMETRY, constants.geometry),
+            (ShaderStageFlags::HULL, constants.hull),
+            (ShaderStageFlags::DOMAIN, constants.domain),
+            (ShaderStageFlags::COMPUTE, constants.compute),
+        ];
+
+        for (stage, specialization) in stages {
+            if let (Some(shader), Some(specialization)) =
+                (self.shaders.get_mut(&stage), specialization)
+            {
+                shader.specialization = Some(specialization);
+            }
+        }
+    }
+
+    /// Remove shader of the `stage` from the set and destroy its module.
+    /// Returns `false` if set doesn't contain shader of the `stage`.
+    pub fn remove_stage(
+        &mut self,
+        stage: ShaderStageFlags,
+        factory: &rendy_factory::Factory<B>,
+    ) -> bool {
+        match self.shaders.remove(&stage) {
+            Some(mut shader) => {
+                shader.dispose(factory);
+                true
+            }
+            None => false,
+        }
+    }
+
     /// Must be called to perform a drop of the Backend ShaderModule object otherwise the shader will never be destroyed in memory.
     pub fn dispose(&mut self, factory: &rendy_factory::Factory<B>) {
         for (_, shader) in self.shaders.iter_mut() {
@@ -381,7 +446,9 @@ impl ShaderSetBuilder {
         Ok(self)
     }
 
-    /// Add a hull shader to this shader set
+    /// Add a hull shader to this shader set.
+    /// Also known as tessellation control shader.
+    /// Pipelines with tessellation shaders must draw `PatchList` primitives.
     #[inline(always)]
     pub fn with_hull<S: Shader>(mut self, shader: &S) -> Result<Self, S::Error> {
         let data = shader.spirv()?;
@@ -389,7 +456,9 @@ impl ShaderSetBuilder {
         Ok(self)
     }
 
-    /// Add a domain shader to this shader set
+    /// Add a domain shader to this shader set.
+    /// Also known as tessellation evaluation shader.
+    /// Pipelines with tessellation shaders must draw `PatchList` primitives.
     #[inline(always)]
     pub fn with_domain<S: Shader>(mut self, shader: &S) -> Result<Self, S::Error> {
         let data = shader.spirv()?;
@@ -493,3 +562,63 @@ impl<B: Backend> Drop for ShaderStorage<B> {
         }
     }
 }
+
+#[cfg(all(test, feature = "empty"))]
+mod tests {
+    use {
+        super::*,
+        rendy_core::{empty::Backend, hal::pso::Specialization},
+    };
+
+    fn shader_set(stages: &[ShaderStageFlags]) -> ShaderSet<Backend> {
+        let mut set = ShaderSet::default();
+        for &stage in stages {
+            set.shaders.insert(
+                stage,
+                ShaderStorage {
+                    stage,
+                    spirv: Vec::new(),
+                    module: None,
+                    entrypoint: "main".to_owned(),
+                    specialization: None,
+                },
+            );
+        }
+        set
+    }
+
+    fn data(set: &ShaderSet<Backend>, stage: ShaderStageFlags) -> Option<Vec<u8>> {
+        set.shaders[&stage]
+            .specialization
+            .as_ref()
+            .map(|specialization| specialization.data.to_vec())
+    }
+
+    #[test]
+    fn variants_of_one_shader_get_own_constants() {
+        let variant = |samples: u32| {
+            let mut set = shader_set(&[ShaderStageFlags::VERTEX, ShaderStageFlags::FRAGMENT]);
+            set.specialize(SpecConstantSet {
+                fragment: Some(SpecConstants::new().with(0, samples).into()),
+                compute: Some(Specialization::default()),
+                ..SpecConstantSet::default()
+            });
+            set
+        };
+
+        let single = variant(1);
+        let multi = variant(4);
+        assert_eq!(
+            data(&single, ShaderStageFlags::FRAGMENT),
+            Some(1u32.to_ne_bytes().to_vec())
+        );
+        assert_eq!(
+            data(&multi, ShaderStageFlags::FRAGMENT),
+            Some(4u32.to_ne_bytes().to_vec())
+        );
+
+        // Stages without constants keep their specialization, absent stages are not added.
+        assert_eq!(data(&single, ShaderStageFlags::VERTEX), None);
+        assert!(!multi.shaders.contains_key(&ShaderStageFlags::COMPUTE));
+    }
+}
@@ -203,22 +203,26 @@ impl SpirvReflection {
                     })?);
 
                 let output_attributes =
-                    generate_attributes(module.enumerate_input_variables(None).map_err(|e| {
+                    generate_attributes(module.enumerate_output_variables(None).map_err(|e| {
                         ReflectError::Retrieval(RetrievalKind::OutputAttrib, e.to_string())
                     })?);
 
-                let descriptor_sets: Result<Vec<_>, _> = module
-                    .enumerate_descriptor_sets(None)
-                    .map_err(|e| {
-                        ReflectError::Retrieval(RetrievalKind::DescriptorSets, e.to_string())
-                    })?
-                    .iter()
-                    .map(ReflectInto::<Vec<rendy_core::hal::pso::DescriptorSetLayoutBinding>>::reflect_into)
-                    .collect();
+                // Sets are placed at their indices, sets not used by this shader are left empty.
+                let mut descriptor_sets_final = Vec::new();
+                for set in module.enumerate_descriptor_sets(None).map_err(|e| {
+                    ReflectError::Retrieval(RetrievalKind::DescriptorSets, e.to_string())
+                })? {
+                    let index = set.set as usize;
+                    if descriptor_sets_final.len() <= index {
+                        descriptor_sets_final.resize(index + 1, Vec::new());
+                    }
+                    descriptor_sets_final[index] = ReflectInto::<
+                        Vec<rendy_core::hal::pso::DescriptorSetLayoutBinding>,
+                    >::reflect_into(&set)?;
+                }
 
                 // This is a fixup-step required because of our implementation. Because we dont pass the module around
                 // to the all the reflect_into API's, we need to fix up the shader stage here at the end. Kinda a hack
-                let mut descriptor_sets_final = descriptor_sets?;
                 descriptor_sets_final.iter_mut().for_each(|v| {
                     v.iter_mut()
                         .for_each(|mut set| set.stage_flags = stage_flag);
@@ -365,23 +369,23 @@ pub(crate) fn merge(reflections: &[SpirvReflection]) -> Result<SpirvReflection,
         }
 
         for (n, set) in current_layout.iter().enumerate() {
-            match descriptor_sets
-                .get(n)
-                .map(|existing| compare_set(set, existing))
-            {
-                None => descriptor_sets.push(set.clone()),
-                Some(SetEquality::NotEqual) => {
-                    return Err(ReflectError::BindingsMismatch(n));
-                }
-                Some(SetEquality::SupersetOf) => {
-                    descriptor_sets[n] = set.clone(); // Overwrite it
+            if descriptor_sets.len() <= n {
+                descriptor_sets.resize(n + 1, Vec::new());
+            }
+            // Sets used by several stages are united, bindings shared by stages must match.
+            let merged = &mut descriptor_sets[n];
+            for binding in set {
+                match merged.iter_mut().find(|m| m.binding == binding.binding) {
+                    Some(existing) => match compare_bindings(existing, binding) {
+                        BindingEquality::Equal => existing.stage_flags |= binding.stage_flags,
+                        BindingEquality::SameBindingNonEqual | BindingEquality::NotEqual => {
+                            return Err(ReflectError::BindingsMismatch(n));
+                        }
+                    },
+                    None => merged.push(binding.clone()),
                 }
-                Some(SetEquality::Equal) | Some(SetEquality::SubsetOf) => {
-                    for binding in descriptor_sets[n].iter_mut() {
-                        binding.stage_flags |= s.stage()
-                    }
-                } // We match, just skip it
             }
+            merged.sort_by_key(|b| b.binding);
         }
     }
 
@@ -430,63 +434,6 @@ pub fn compare_bindings(
     return BindingEquality::NotEqual;
 }
 
-/// This enum provides logical comparison results for sets. Because shaders can share bindings,
-/// we cannot do a strict equality check for exclusion - we must see if shaders match, or if they are the same bindings
-/// but mismatched descriptions.
-#[derive(Debug, Hash, Eq, PartialEq, Clone, Copy)]
-#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
-enum SetEquality {
-    /// The sets match
-    Equal,
-    /// The sets share a binding index, but have different values. This is usually an error case.
-    SubsetOf,
-    /// A superset set layout. This means LHV contains more data than RHV
-    SupersetOf,
-    /// Invalid Match
-    NotEqual,
-}
-
-fn compare_set(
-    lhv: &[rendy_core::hal::pso::DescriptorSetLayoutBinding],
-    rhv: &[rendy_core::hal::pso::DescriptorSetLayoutBinding],
-) -> SetEquality {
-    // Bindings may not be in order, so we need to make a copy and index them by binding.
-    let mut lhv_bindings = HashMap::new();
-    lhv.iter().for_each(|b| {
-        lhv_bindings.insert(b.binding, b);
-    });
-
-    let mut rhv_bindings = HashMap::new();
-    rhv.iter().for_each(|b| {
-        rhv_bindings.insert(b.binding, b);
-    });
-
-    let predicate = if lhv.len() == rhv.len() {
-        SetEquality::Equal
-    } else if lhv.len() > rhv.len() {
-        SetEquality::SupersetOf
-    } else {
-        SetEquality::SubsetOf
-    };
-
-    for (key, lhv_value) in lhv_bindings {
-        if let Some(rhv_value) = rhv_bindings.get(&key) {
-            match compare_bindings(lhv_value, rhv_value) {
-                BindingEquality::Equal => {}
-                BindingEquality::NotEqual | BindingEquality::SameBindingNonEqual => {
-                    return SetEquality::NotEqual;
-                }
-            }
-        } else {
-            if predicate == SetEquality::Equal || predicate == SetEquality::SubsetOf {
-                return SetEquality::NotEqual;
-            }
-        }
-    }
-
-    predicate
-}
-
 /// Function copied from range_contains RFC rust implementation in nightly
 fn range_contains<U, R>(range: &R, item: &U) -> bool
 where
@@ -503,3 +450,218 @@ where
         Bound::Unbounded => true,
     })
 }
+
+#[cfg(test)]
+mod tests {
+    use {
+        super::{merge, ReflectError, SpirvReflection},
+        rendy_core::hal::pso::{DescriptorSetLayoutBinding, DescriptorType, ShaderStageFlags},
+        std::collections::HashMap,
+    };
+
+    fn binding(
+        binding: u32,
+        ty: DescriptorType,
+        stage_flags: ShaderStageFlags,
+    ) -> DescriptorSetLayoutBinding {
+        DescriptorSetLayoutBinding {
+            binding,
+            ty,
+            count: 1,
+            stage_flags,
+            immutable_samplers: false,
+        }
+    }
+
+    fn reflection(
+        stage: ShaderStageFlags,
+        descriptor_sets: Vec<Vec<DescriptorSetLayoutBinding>>,
+    ) -> SpirvReflection {
+        SpirvReflection::new(
+            stage,
+            None,
+            vec![(stage, "main".to_string())],
+            HashMap::new(),
+            HashMap::new(),
+            descriptor_sets,
+            Vec::new(),
+        )
+        .unwrap()
+    }
+
+    /// Assemble SPIR-V module with empty `main` and a `vec4` buffer
+    /// in set 0 for each `(binding, storage)` pair.
+    fn spirv(stage: ShaderStageFlags, buffers: &[(u32, bool)]) -> Vec<u32> {
+        fn op(words: &mut Vec<u32>, opcode: u32, operands: &[u32]) {
+            words.push((operands.len() as u32 + 1) << 16 | opcode);
+            words.extend_from_slice(operands);
+        }
+
+        // Ids of `main`, `void`, function type, `float`, `vec4` and label,
+        // three ids of struct, pointer and variable per buffer follow.
+        let (main, void, function, float, vec4, label) = (1, 2, 3, 4, 5, 6);
+        let buffer_ids = |n: u32| (7 + 3 * n, 8 + 3 * n, 9 + 3 * n);
+        let bound = 7 + 3 * buffers.len() as u32;
+        // "main" with null terminator.
+        let name = [0x6e69_616d, 0];
+
+        let mut words = vec![0x0723_0203, 0x0001_0000, 0, bound, 0];
+        op(&mut words, 17, &[1]); // OpCapability Shader
+        op(&mut words, 14, &[0, 1]); // OpMemoryModel Logical GLSL450
+        if stage == ShaderStageFlags::FRAGMENT {
+            op(&mut words, 15, &[4, main, name[0], name[1]]); // OpEntryPoint Fragment
+            op(&mut words, 16, &[main, 7]); // OpExecutionMode OriginUpperLeft
+        } else {
+            op(&mut words, 15, &[0, main, name[0], name[1]]); // OpEntryPoint Vertex
+        }
+        for (n, &(binding, storage)) in buffers.iter().enumerate() {
+            let (ty, _, var) = buffer_ids(n as u32);
+            // OpDecorate Block or BufferBlock.
+            op(&mut words, 71, &[ty, if storage { 3 } else { 2 }]);
+            op(&mut words, 72, &[ty, 0, 35, 0]); // OpMemberDecorate Offset 0
+            op(&mut words, 71, &[var, 34, 0]); // OpDecorate DescriptorSet 0
+            op(&mut words, 71, &[var, 33, binding]); // OpDecorate Binding
+        }
+        op(&mut words, 19, &[void]); // OpTypeVoid
+        op(&mut words, 33, &[function, void]); // OpTypeFunction
+        op(&mut words, 22, &[float, 32]); // OpTypeFloat
+        op(&mut words, 23, &[vec4, float, 4]); // OpTypeVector
+        for n in 0..buffers.len() {
+            let (ty, ptr, var) = buffer_ids(n as u32);
+            op(&mut words, 30, &[ty, vec4]); // OpTypeStruct
+            op(&mut words, 32, &[ptr, 2, ty]); // OpTypePointer Uniform
+            op(&mut words, 59, &[ptr, var, 2]); // OpVariable Uniform
+        }
+        op(&mut words, 54, &[void, main, 0, function]); // OpFunction
+        op(&mut words, 248, &[label]); // OpLabel
+        op(&mut words, 253, &[]); // OpReturn
+        op(&mut words, 56, &[]); // OpFunctionEnd
+        words
+    }
+
+    fn reflect(stage: ShaderStageFlags, buffers: &[(u32, bool)]) -> SpirvReflection {
+        SpirvReflection::reflect(&spirv(stage, buffers), None).unwrap()
+    }
+
+    #[test]
+    fn disjoint_bindings_of_stages_are_united() {
+        let vertex = reflect(ShaderStageFlags::VERTEX, &[(0, false)]);
+        let fragment = reflect(ShaderStageFlags::FRAGMENT, &[(1, false)]);
+
+        let merged = merge(&[vertex, fragment]).unwrap();
+        let set = &merged.descriptor_sets[0];
+        assert_eq!(set.len(), 2);
+        assert_eq!(set[0].binding, 0);
+        assert_eq!(set[0].ty, DescriptorType::UniformBuffer);
+        assert_eq!(set[0].stage_flags, ShaderStageFlags::VERTEX);
+        assert_eq!(set[1].binding, 1);
+        assert_eq!(set[1].stage_flags, ShaderStageFlags::FRAGMENT);
+    }
+
+    #[test]
+    fn overlapping_bindings_of_stages_are_united() {
+        let vertex = reflect(ShaderStageFlags::VERTEX, &[(0, false), (2, false)]);
+        let fragment = reflect(ShaderStageFlags::FRAGMENT, &[(0, false), (1, true)]);
+
+        let merged = merge(&[vertex, fragment]).unwrap();
+        let set = &merged.descriptor_sets[0];
+        let bindings: Vec<_> = set.iter().map(|b| (b.binding, b.stage_flags)).collect();
+        assert_eq!(
+            bindings,
+            vec![
+                (0, ShaderStageFlags::VERTEX | ShaderStageFlags::FRAGMENT),
+                (1, ShaderStageFlags::FRAGMENT),
+                (2, ShaderStageFlags::VERTEX),
+            ]
+        );
+        assert_eq!(set[1].ty, DescriptorType::StorageBuffer);
+    }
+
+    #[test]
+    fn conflicting_binding_type_is_mismatch() {
+        let vertex = reflect(ShaderStageFlags::VERTEX, &[(0, false)]);
+        let fragment = reflect(ShaderStageFlags::FRAGMENT, &[(0, true)]);
+
+        assert_eq!(
+            merge(&[vertex, fragment]).err(),
+            Some(ReflectError::BindingsMismatch(0))
+        );
+    }
+
+    #[test]
+    fn bindings_are_merged_across_stages() {
+        let vertex = reflection(
+            ShaderStageFlags::VERTEX,
+            vec![vec![binding(
+                0,
+                DescriptorType::UniformBuffer,
+                ShaderStageFlags::VERTEX,
+            )]],
+        );
+        // Fragment shader uses the same uniform buffer and two sampled images in set 1.
+        let fragment = reflection(
+            ShaderStageFlags::FRAGMENT,
+            vec![
+                vec![binding(
+                    0,
+                    DescriptorType::UniformBuffer,
+                    ShaderStageFlags::FRAGMENT,
+                )],
+                vec![
+                    binding(0, DescriptorType::SampledImage, ShaderStageFlags::FRAGMENT),
+                    binding(1, DescriptorType::SampledImage, ShaderStageFlags::FRAGMENT),
+                ],
+            ],
+        );
+
+        let layout = merge(&[vertex, fragment])
+            .unwrap()
+            .compile_cache()
+            .unwrap()
+            .layout()
+            .unwrap();
+
+        assert_eq!(layout.sets.len(), 2);
+        assert_eq!(layout.sets[0].bindings.len(), 1);
+        assert_eq!(layout.sets[0].bindings[0].ty, DescriptorType::UniformBuffer);
+        assert_eq!(
+            layout.sets[0].bindings[0].stage_flags,
+            ShaderStageFlags::VERTEX | ShaderStageFlags::FRAGMENT
+        );
+
+        assert_eq!(layout.sets[1].bindings.len(), 2);
+        for (n, b) in layout.sets[1].bindings.iter().enumerate() {
+            assert_eq!(b.binding, n as u32);
+            assert_eq!(b.ty, DescriptorType::SampledImage);
+            assert_eq!(b.stage_flags, ShaderStageFlags::FRAGMENT);
+        }
+    }
+
+    #[test]
+    fn superset_keeps_stages_of_existing_bindings() {
+        let vertex = reflection(
+            ShaderStageFlags::VERTEX,
+            vec![vec![binding(
+                0,
+                DescriptorType::UniformBuffer,
+                ShaderStageFlags::VERTEX,
+            )]],
+        );
+        let fragment = reflection(
+            ShaderStageFlags::FRAGMENT,
+            vec![vec![
+                binding(0, DescriptorType::UniformBuffer, ShaderStageFlags::FRAGMENT),
+                binding(1, DescriptorType::SampledImage, ShaderStageFlags::FRAGMENT),
+            ]],
+        );
+
+        let merged = merge(&[vertex, fragment]).unwrap();
+        let set = &merged.descriptor_sets[0];
+        assert_eq!(set.len(), 2);
+        let ubo = set.iter().find(|b| b.binding == 0).unwrap();
+        assert_eq!(
+            ubo.stage_flags,
+            ShaderStageFlags::VERTEX | ShaderStageFlags::FRAGMENT
+        );
+    }
+}
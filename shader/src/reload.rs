@@ -0,0 +1,140 @@
+// This module is gated under "shader-reload" feature
+use crate::{FileShaderInfo, Shader, ShaderCError};
+
+/// Shader compiled from source file that can be recompiled when the file changes.
+///
+/// Intended for iterating on shaders in debug builds.
+/// Call [`reload`] periodically, e.g. once per frame, and rebuild the graph
+/// when it returns `Ok(true)`, so pipelines are created from the new Spir-V.
+/// When the changed source fails to compile the previous Spir-V is kept
+/// and the error is returned, so the running graph doesn't need to be touched.
+///
+/// [`reload`]: #method.reload
+#[derive(Debug)]
+pub struct ReloadableShaderInfo<P, E> {
+    info: FileShaderInfo<P, E>,
+    spirv: Vec<u32>,
+    modified: Option<std::time::SystemTime>,
+}
+
+impl<P, E> ReloadableShaderInfo<P, E>
+where
+    P: AsRef<std::path::Path> + std::fmt::Debug,
+    E: AsRef<str>,
+{
+    /// Compile shader from source file and start tracking its modification time.
+    pub fn new(info: FileShaderInfo<P, E>) -> Result<Self, ShaderCError> {
+        let modified = modified(info.path().as_ref())?;
+        let spirv = info.spirv()?.into_owned();
+        Ok(ReloadableShaderInfo {
+            info,
+            spirv,
+            modified: Some(modified),
+        })
+    }
+
+    /// Recompile shader if source file was modified since last check.
+    ///
+    /// Returns `Ok(true)` if the shader was recompiled
+    /// and `Ok(false)` if the file wasn't changed.
+    /// On compilation error the previous Spir-V is kept.
+    pub fn reload(&mut self) -> Result<bool, ShaderCError> {
+        let modified = modified(self.info.path().as_ref())?;
+        self.update(modified, |info| Ok(info.spirv()?.into_owned()))
+    }
+
+    fn update(
+        &mut self,
+        modified: std::time::SystemTime,
+        compile: impl FnOnce(&FileShaderInfo<P, E>) -> Result<Vec<u32>, ShaderCError>,
+    ) -> Result<bool, ShaderCError> {
+        if self.modified == Some(modified) {
+            return Ok(false);
+        }
+
+        // Failed source is not recompiled until it changes again.
+        self.modified = Some(modified);
+        match compile(&self.info) {
+            Ok(spirv) => {
+                log::info!("Shader {:?} reloaded", self.info.path());
+                self.spirv = spirv;
+                Ok(true)
+            }
+            Err(e) => {
+                log::error!("Failed to reload shader {:?}: {}", self.info.path(), e);
+                Err(e)
+            }
+        }
+    }
+}
+
+impl<P, E> Shader for ReloadableShaderInfo<P, E>
+where
+    P: AsRef<std::path::Path> + std::fmt::Debug,
+    E: AsRef<str>,
+{
+    type Error = ShaderCError;
+
+    fn spirv(&self) -> Result<std::borrow::Cow<'_, [u32]>, ShaderCError> {
+        Ok(std::borrow::Cow::Borrowed(&self.spirv))
+    }
+
+    fn entry(&self) -> &str {
+        self.info.entry()
+    }
+
+    fn stage(&self) -> rendy_core::hal::pso::ShaderStageFlags {
+        self.info.stage()
+    }
+}
+
+fn modified(path: &std::path::Path) -> Result<std::time::SystemTime, ShaderCError> {
+    Ok(std::fs::metadata(path)?.modified()?)
+}
+
+#[cfg(test)]
+mod tests {
+    use {
+        super::ReloadableShaderInfo,
+        crate::{FileShaderInfo, Shader, ShaderCError, ShaderKind, SourceLanguage},
+        std::time::{Duration, SystemTime},
+    };
+
+    #[test]
+    fn failed_reload_keeps_previous_spirv() {
+        let start = SystemTime::UNIX_EPOCH;
+        let mut shader = ReloadableShaderInfo {
+            info: FileShaderInfo::new(
+                "shader.vert",
+                ShaderKind::Vertex,
+                SourceLanguage::GLSL,
+                "main",
+            ),
+            spirv: vec![1],
+            modified: Some(start),
+        };
+
+        // File is unchanged.
+        assert!(!shader.update(start, |_| unreachable!()).unwrap());
+
+        let changed = start + Duration::from_secs(1);
+        assert!(shader.update(changed, |_| Ok(vec![2])).unwrap());
+        assert_eq!(&*shader.spirv().unwrap(), &[2][..]);
+
+        // Syntax error.
+        let broken = changed + Duration::from_secs(1);
+        assert!(shader
+            .update(broken, |_| Err(ShaderCError::NonUtf8Path(
+                "shader.vert".into()
+            )))
+            .is_err());
+        assert_eq!(&*shader.spirv().unwrap(), &[2][..]);
+
+        // Broken source isn't compiled again until it changes.
+        assert!(!shader.update(broken, |_| unreachable!()).unwrap());
+
+        let fixed = broken + Duration::from_secs(1);
+        assert!(shader.update(fixed, |_| Ok(vec![3])).unwrap());
+        assert_eq!(&*shader.spirv().unwrap(), &[3][..]);
+    }
+}
@@ -68,6 +68,11 @@ impl<P, E> FileShaderInfo<P, E> {
             entry,
         }
     }
+
+    /// Path to the shader source file.
+    pub fn path(&self) -> &P {
+        &self.path
+    }
 }
 
 impl<P, E> FileShaderInfo<P, E>
@@ -0,0 +1,140 @@
+//! Specialization constants builder.
+
+use rendy_core::hal::pso::{Specialization, SpecializationConstant};
+
+/// Value that can be used as specialization constant.
+/// Layout of the value bytes matches type of the constant in shader.
+pub trait SpecConstant: Copy {
+    /// Bytes of the value as shader reads them.
+    fn bytes(self) -> [u8; 4];
+}
+
+impl SpecConstant for u32 {
+    fn bytes(self) -> [u8; 4] {
+        self.to_ne_bytes()
+    }
+}
+
+impl SpecConstant for i32 {
+    fn bytes(self) -> [u8; 4] {
+        self.to_ne_bytes()
+    }
+}
+
+impl SpecConstant for f32 {
+    fn bytes(self) -> [u8; 4] {
+        self.to_bits().to_ne_bytes()
+    }
+}
+
+/// Boolean constants are 32-bit wide, `VK_TRUE` is 1 and `VK_FALSE` is 0.
+impl SpecConstant for bool {
+    fn bytes(self) -> [u8; 4] {
+        (self as u32).to_ne_bytes()
+    }
+}
+
+/// Builder for specialization constants of a shader.
+/// Maps constant ids to values.
+#[derive(Clone, Debug, Default)]
+pub struct SpecConstants {
+    constants: Vec<SpecializationConstant>,
+    data: Vec<u8>,
+}
+
+impl SpecConstants {
+    /// Create empty set of constants.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Set value of constant with `id`.
+    /// Setting same constant again replaces its value.
+    pub fn add(&mut self, id: u32, value: impl SpecConstant) -> &mut Self {
+        let bytes = value.bytes();
+        match self.constants.iter().find(|constant| constant.id == id) {
+            Some(constant) => {
+                let range = constant.range.start as usize..constant.range.end as usize;
+                self.data[range].copy_from_slice(&bytes);
+            }
+            None => {
+                let start = self.data.len();
+                assert!(start + bytes.len() <= u16::max_value() as usize);
+                self.data.extend_from_slice(&bytes);
+                self.constants.push(SpecializationConstant {
+                    id,
+                    range: start as u16..self.data.len() as u16,
+                });
+            }
+        }
+        self
+    }
+
+    /// Set value of constant with `id`.
+    /// Setting same constant again replaces its value.
+    pub fn with(mut self, id: u32, value: impl SpecConstant) -> Self {
+        self.add(id, value);
+        self
+    }
+
+    /// Check if no constants are set.
+    pub fn is_empty(&self) -> bool {
+        self.constants.is_empty()
+    }
+
+    /// Get specialization info to pass with shader entry point.
+    pub fn specialization(&self) -> Specialization<'static> {
+        Specialization {
+            constants: self.constants.clone().into(),
+            data: self.data.clone().into(),
+        }
+    }
+}
+
+impl From<SpecConstants> for Specialization<'static> {
+    fn from(constants: SpecConstants) -> Self {
+        Specialization {
+            constants: constants.constants.into(),
+            data: constants.data.into(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn constants_are_packed_by_id() {
+        let spec: Specialization<'_> = SpecConstants::new()
+            .with(0, 4u32)
+            .with(3, -1i32)
+            .with(1, 0.5f32)
+            .with(2, true)
+            .into();
+
+        let ids: Vec<_> = spec.constants.iter().map(|c| c.id).collect();
+        assert_eq!(ids, vec![0, 3, 1, 2]);
+
+        let value = |id: u32| {
+            let constant = spec.constants.iter().find(|c| c.id == id).unwrap();
+            let range = constant.range.start as usize..constant.range.end as usize;
+            let mut bytes = [0; 4];
+            bytes.copy_from_slice(&spec.data[range]);
+            bytes
+        };
+        assert_eq!(u32::from_ne_bytes(value(0)), 4);
+        assert_eq!(i32::from_ne_bytes(value(3)), -1);
+        assert_eq!(f32::from_bits(u32::from_ne_bytes(value(1))), 0.5);
+        assert_eq!(u32::from_ne_bytes(value(2)), 1);
+        assert_eq!(spec.data.len(), 16);
+    }
+
+    #[test]
+    fn setting_constant_again_replaces_value() {
+        let constants = SpecConstants::new().with(7, false).with(7, true);
+        let spec = constants.specialization();
+        assert_eq!(spec.constants.len(), 1);
+        assert_eq!(&*spec.data, &1u32.to_ne_bytes()[..]);
+    }
+}
@@ -1,6 +1,12 @@
 //! Exports the image and palette modules if the features
 //! are enabled
 
+mod container;
+mod dds;
+mod ktx2;
+
+pub use self::container::ContainerError;
+
 #[cfg(feature = "image")]
 pub mod image;
 #[cfg(feature = "palette")]
@@ -0,0 +1,126 @@
+//! Common parts of compressed texture container parsers.
+
+use {
+    crate::{texture::checked_level_size, MipLevels, TextureBuilder},
+    rendy_core::hal::{
+        format::Format,
+        image::{Kind, Layer, Level, ViewKind},
+    },
+    std::num::NonZeroU8,
+};
+
+/// Error parsing texture container.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum ContainerError {
+    /// Data doesn't start with container identifier.
+    InvalidMagic,
+
+    /// Data ends before all content declared in the header.
+    UnexpectedEof,
+
+    /// Pixel format has no matching `Format`.
+    /// Contains format code used by the container:
+    /// `VkFormat` for KTX2, `DXGI_FORMAT` or FourCC for DDS.
+    UnsupportedFormat(u32),
+
+    /// Container uses feature that isn't supported.
+    Unsupported(&'static str),
+}
+
+impl std::fmt::Display for ContainerError {
+    fn fmt(&self, fmt: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ContainerError::InvalidMagic => write!(fmt, "Data is not a texture container"),
+            ContainerError::UnexpectedEof => write!(fmt, "Texture container is truncated"),
+            ContainerError::UnsupportedFormat(code) => {
+                write!(fmt, "Pixel format {:#x} is not supported", code)
+            }
+            ContainerError::Unsupported(feature) => write!(fmt, "{} are not supported", feature),
+        }
+    }
+}
+
+impl std::error::Error for ContainerError {}
+
+pub(crate) fn read_u32(bytes: &[u8], offset: usize) -> Result<u32, ContainerError> {
+    let mut value = [0; 4];
+    value.copy_from_slice(
+        bytes
+            .get(offset..offset + 4)
+            .ok_or(ContainerError::UnexpectedEof)?,
+    );
+    Ok(u32::from_le_bytes(value))
+}
+
+pub(crate) fn read_u64(bytes: &[u8], offset: usize) -> Result<u64, ContainerError> {
+    let mut value = [0; 8];
+    value.copy_from_slice(
+        bytes
+            .get(offset..offset + 8)
+            .ok_or(ContainerError::UnexpectedEof)?,
+    );
+    Ok(u64::from_le_bytes(value))
+}
+
+/// Shape of 2D texture stored in a container.
+#[derive(Clone, Copy, Debug)]
+pub(crate) struct Layout {
+    pub(crate) format: Format,
+    pub(crate) width: u32,
+    pub(crate) height: u32,
+    /// Number of array elements, each of 6 layers for cubemaps.
+    pub(crate) elements: u32,
+    pub(crate) array: bool,
+    pub(crate) cube: bool,
+    pub(crate) levels: u32,
+}
+
+/// Size of level with all layers of `kind`.
+/// Fails if size doesn't fit in memory, as such content can't be in the container.
+pub(crate) fn level_size(
+    format: Format,
+    kind: Kind,
+    level: Level,
+) -> Result<usize, ContainerError> {
+    checked_level_size(format, kind, level).ok_or(ContainerError::UnexpectedEof)
+}
+
+impl Layout {
+    /// Kind of image with this layout.
+    /// Fails if layout has more levels or layers than image can have.
+    pub(crate) fn kind(&self) -> Result<Kind, ContainerError> {
+        if self.levels == 0 || self.levels > rendy_core::hal::image::MAX_LEVEL as u32 {
+            return Err(ContainerError::Unsupported(
+                "Textures with this many mip levels",
+            ));
+        }
+        let faces = if self.cube { 6 } else { 1 };
+        let layers = self.elements * faces;
+        if layers > Layer::max_value() as u32 {
+            return Err(ContainerError::Unsupported("Arrays with this many layers"));
+        }
+        Ok(Kind::D2(self.width, self.height, layers as Layer, 1))
+    }
+
+    /// Create builder that uploads all `data` levels without conversion.
+    /// `data` must contain tightly packed levels, each with all layers.
+    pub(crate) fn builder(self, data: Vec<u8>) -> Result<TextureBuilder<'static>, ContainerError> {
+        let kind = self.kind()?;
+        let view_kind = match (self.cube, self.array) {
+            (false, false) => ViewKind::D2,
+            (false, true) => ViewKind::D2Array,
+            (true, false) => ViewKind::Cube,
+            (true, true) => ViewKind::CubeArray,
+        };
+
+        Ok(TextureBuilder::new()
+            .with_raw_data(data, self.format)
+            .with_data_width(self.width)
+            .with_data_height(self.height)
+            .with_kind(kind)
+            .with_view_kind(view_kind)
+            .with_mip_levels(MipLevels::Provided(
+                NonZeroU8::new(self.levels as u8).unwrap(),
+            )))
+    }
+}
@@ -0,0 +1,288 @@
+//! Loading textures from DDS containers.
+
+use {
+    super::container::{level_size, read_u32, ContainerError, Layout},
+    crate::TextureBuilder,
+    rendy_core::hal::{
+        format::Format,
+        image::{Kind, Level},
+    },
+};
+
+const MAGIC: u32 = 0x2053_4444;
+const HEADER_SIZE: usize = 124;
+const DX10_HEADER_SIZE: usize = 20;
+
+const DDSD_MIPMAPCOUNT: u32 = 0x2_0000;
+const DDPF_FOURCC: u32 = 0x4;
+const DDPF_RGB: u32 = 0x40;
+const DDSCAPS2_CUBEMAP: u32 = 0x200;
+const DDSCAPS2_VOLUME: u32 = 0x20_0000;
+const DDS_DIMENSION_TEXTURE2D: u32 = 3;
+const DDS_RESOURCE_MISC_TEXTURECUBE: u32 = 0x4;
+
+fn four_cc(code: &[u8; 4]) -> u32 {
+    code[0] as u32 | (code[1] as u32) << 8 | (code[2] as u32) << 16 | (code[3] as u32) << 24
+}
+
+impl<'a> TextureBuilder<'a> {
+    /// Parse DDS container and upload its mip levels and layers without conversion.
+    ///
+    /// Supports 2D textures, arrays and cubemaps in BC formats and uncompressed 32-bit RGBA.
+    /// Formats are read from FourCC codes or `DXGI_FORMAT` of DX10 header.
+    /// Building texture fails with `BuildError::Format` if device doesn't support the format.
+    pub fn from_dds(bytes: &[u8]) -> Result<Self, ContainerError> {
+        if read_u32(bytes, 0).ok() != Some(MAGIC) {
+            return Err(ContainerError::InvalidMagic);
+        }
+
+        let header = |offset: usize| read_u32(bytes, 4 + offset);
+        let flags = header(4)?;
+        let height = header(8)?;
+        let width = header(12)?;
+        let levels = if flags & DDSD_MIPMAPCOUNT != 0 {
+            header(24)?.max(1)
+        } else {
+            1
+        };
+        let pixel_flags = header(76)?;
+        let code = header(80)?;
+        let caps2 = header(108)?;
+
+        let (format, elements, array, cube, data_offset) =
+            if pixel_flags & DDPF_FOURCC != 0 && code == four_cc(b"DX10") {
+                let dx10 = |offset: usize| read_u32(bytes, 4 + HEADER_SIZE + offset);
+                let dxgi_format = dx10(0)?;
+                if dx10(4)? != DDS_DIMENSION_TEXTURE2D {
+                    return Err(ContainerError::Unsupported("Non-2D textures"));
+                }
+                let elements = dx10(12)?.max(1);
+                (
+                    dxgi_format_to_format(dxgi_format)
+                        .ok_or(ContainerError::UnsupportedFormat(dxgi_format))?,
+                    elements,
+                    elements > 1,
+                    dx10(8)? & DDS_RESOURCE_MISC_TEXTURECUBE != 0,
+                    4 + HEADER_SIZE + DX10_HEADER_SIZE,
+                )
+            } else {
+                if caps2 & DDSCAPS2_VOLUME != 0 {
+                    return Err(ContainerError::Unsupported("3D textures"));
+                }
+                let format = if pixel_flags & DDPF_FOURCC != 0 {
+                    four_cc_to_format(code).ok_or(ContainerError::UnsupportedFormat(code))?
+                } else if pixel_flags & DDPF_RGB != 0 && header(84)? == 32 {
+                    match header(88)? {
+                        0x0000_00FF => Format::Rgba8Unorm,
+                        0x00FF_0000 => Format::Bgra8Unorm,
+                        _ => return Err(ContainerError::UnsupportedFormat(0)),
+                    }
+                } else {
+                    return Err(ContainerError::UnsupportedFormat(0));
+                };
+                // Legacy header can't describe cubemaps with missing faces or arrays.
+                (
+                    format,
+                    1,
+                    false,
+                    caps2 & DDSCAPS2_CUBEMAP != 0,
+                    4 + HEADER_SIZE,
+                )
+            };
+
+        let layout = Layout {
+            format,
+            width,
+            height,
+            elements,
+            array,
+            cube,
+            levels,
+        };
+        // Validates number of levels and layers.
+        let kind = layout.kind()?;
+        let levels = levels as Level;
+
+        // DDS stores all levels of a layer before the next layer,
+        // reorder to have all layers of a level together.
+        let layer_kind = Kind::D2(width, height, 1, 1);
+        let layer_size = (0..levels).try_fold(0usize, |sum, level| {
+            sum.checked_add(level_size(format, layer_kind, level)?)
+                .ok_or(ContainerError::UnexpectedEof)
+        })?;
+        let layers = kind.num_layers() as usize;
+        let content_end = layer_size
+            .checked_mul(layers)
+            .and_then(|size| size.checked_add(data_offset))
+            .ok_or(ContainerError::UnexpectedEof)?;
+        let content = bytes
+            .get(data_offset..content_end)
+            .ok_or(ContainerError::UnexpectedEof)?;
+
+        let mut data = Vec::with_capacity(content.len());
+        let mut level_offset = 0;
+        for level in 0..levels {
+            let size = level_size(format, layer_kind, level)?;
+            for layer in 0..layers {
+                let offset = layer * layer_size + level_offset;
+                data.extend_from_slice(&content[offset..offset + size]);
+            }
+            level_offset += size;
+        }
+
+        layout.builder(data)
+    }
+}
+
+fn four_cc_to_format(code: u32) -> Option<Format> {
+    Some(match code {
+        c if c == four_cc(b"DXT1") => Format::Bc1RgbaUnorm,
+        c if c == four_cc(b"DXT2") || c == four_cc(b"DXT3") => Format::Bc2Unorm,
+        c if c == four_cc(b"DXT4") || c == four_cc(b"DXT5") => Format::Bc3Unorm,
+        c if c == four_cc(b"ATI1") || c == four_cc(b"BC4U") => Format::Bc4Unorm,
+        c if c == four_cc(b"BC4S") => Format::Bc4Snorm,
+        c if c == four_cc(b"ATI2") || c == four_cc(b"BC5U") => Format::Bc5Unorm,
+        c if c == four_cc(b"BC5S") => Format::Bc5Snorm,
+        _ => return None,
+    })
+}
+
+fn dxgi_format_to_format(dxgi_format: u32) -> Option<Format> {
+    Some(match dxgi_format {
+        28 => Format::Rgba8Unorm,
+        29 => Format::Rgba8Srgb,
+        71 => Format::Bc1RgbaUnorm,
+        72 => Format::Bc1RgbaSrgb,
+        74 => Format::Bc2Unorm,
+        75 => Format::Bc2Srgb,
+        77 => Format::Bc3Unorm,
+        78 => Format::Bc3Srgb,
+        80 => Format::Bc4Unorm,
+        81 => Format::Bc4Snorm,
+        83 => Format::Bc5Unorm,
+        84 => Format::Bc5Snorm,
+        87 => Format::Bgra8Unorm,
+        91 => Format::Bgra8Srgb,
+        95 => Format::Bc6hUfloat,
+        96 => Format::Bc6hSfloat,
+        98 => Format::Bc7Unorm,
+        99 => Format::Bc7Srgb,
+        _ => return None,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use {super::*, crate::MipLevels, rendy_core::hal::image::ViewKind};
+
+    /// Build DDS file. `dx10` contains `DXGI_FORMAT`, misc flags and array size.
+    fn dds(
+        code: u32,
+        dx10: Option<(u32, u32, u32)>,
+        caps2: u32,
+        levels: u32,
+        data: &[u8],
+    ) -> Vec<u8> {
+        let mut header = vec![0u32; HEADER_SIZE / 4];
+        header[0] = HEADER_SIZE as u32;
+        header[1] = DDSD_MIPMAPCOUNT;
+        header[2] = 8;
+        header[3] = 8;
+        header[6] = levels;
+        header[18] = 32;
+        header[19] = DDPF_FOURCC;
+        header[20] = code;
+        header[27] = caps2;
+
+        let mut words = vec![MAGIC];
+        words.extend(header);
+        if let Some((format, misc, size)) = dx10 {
+            words.extend(&[format, DDS_DIMENSION_TEXTURE2D, misc, size, 0]);
+        }
+
+        let mut bytes: Vec<u8> = words
+            .iter()
+            .flat_map(|word| word.to_le_bytes().to_vec())
+            .collect();
+        bytes.extend_from_slice(data);
+        bytes
+    }
+
+    #[test]
+    fn bc1_mips_are_loaded() {
+        let data: Vec<u8> = (0..4 * 8 + 8 + 8 + 8).map(|byte| byte as u8).collect();
+        let builder = TextureBuilder::from_dds(&dds(four_cc(b"DXT1"), None, 0, 4, &data)).unwrap();
+
+        assert_eq!(builder.format(), Format::Bc1RgbaUnorm);
+        assert_eq!(builder.kind(), Kind::D2(8, 8, 1, 1));
+        assert_eq!(builder.view_kind(), ViewKind::D2);
+        match builder.mip_levels() {
+            MipLevels::Provided(levels) => assert_eq!(levels.get(), 4),
+            levels => panic!("Unexpected mip levels {:?}", levels),
+        }
+        assert_eq!(builder.data(), &data[..]);
+    }
+
+    #[test]
+    fn bc7_cubemap_is_reordered_by_level() {
+        // Each face has 4 blocks in first level and 1 in second,
+        // bytes hold face index and level.
+        let data: Vec<u8> = (0..6u8)
+            .flat_map(|face| {
+                std::iter::repeat(face * 2)
+                    .take(4 * 16)
+                    .chain(std::iter::repeat(face * 2 + 1).take(16))
+            })
+            .collect();
+        let bytes = dds(
+            four_cc(b"DX10"),
+            Some((98, DDS_RESOURCE_MISC_TEXTURECUBE, 1)),
+            0,
+            2,
+            &data,
+        );
+        let builder = TextureBuilder::from_dds(&bytes).unwrap();
+
+        assert_eq!(builder.format(), Format::Bc7Unorm);
+        assert_eq!(builder.kind(), Kind::D2(8, 8, 6, 1));
+        assert_eq!(builder.view_kind(), ViewKind::Cube);
+
+        let levels: Vec<u8> = builder.data().chunks(16).map(|block| block[0]).collect();
+        let first: Vec<u8> = (0..6u8).flat_map(|face| vec![face * 2; 4]).collect();
+        let second: Vec<u8> = (0..6u8).map(|face| face * 2 + 1).collect();
+        assert_eq!(levels, [first, second].concat());
+    }
+
+    #[test]
+    fn truncated_data_is_rejected() {
+        let bytes = dds(
+            four_cc(b"DXT5"),
+            None,
+            DDSCAPS2_CUBEMAP,
+            1,
+            &[0; 6 * 4 * 16 - 1],
+        );
+        assert_eq!(
+            TextureBuilder::from_dds(&bytes).unwrap_err(),
+            ContainerError::UnexpectedEof
+        );
+    }
+
+    #[test]
+    fn overflowing_header_values_are_rejected() {
+        // Level count would wrap to 4 if truncated to `u8`.
+        let bytes = dds(four_cc(b"DXT1"), None, 0, 260, &[0; 4 * 8 + 8 + 8 + 8]);
+        assert_eq!(
+            TextureBuilder::from_dds(&bytes).unwrap_err(),
+            ContainerError::Unsupported("Textures with this many mip levels")
+        );
+
+        // Size of cubemap this large doesn't fit in memory.
+        let mut bytes = dds(four_cc(b"DXT1"), None, DDSCAPS2_CUBEMAP, 1, &[]);
+        bytes[12..20].copy_from_slice(&[0xFF; 8]);
+        assert_eq!(
+            TextureBuilder::from_dds(&bytes).unwrap_err(),
+            ContainerError::UnexpectedEof
+        );
+    }
+}
@@ -0,0 +1,265 @@
+//! Loading textures from KTX2 containers.
+
+use {
+    super::container::{level_size, read_u32, read_u64, ContainerError, Layout},
+    crate::TextureBuilder,
+    rendy_core::hal::{format::Format, image::Level},
+    std::convert::TryFrom,
+};
+
+const IDENTIFIER: [u8; 12] = [
+    0xAB, 0x4B, 0x54, 0x58, 0x20, 0x32, 0x30, 0xBB, 0x0D, 0x0A, 0x1A, 0x0A,
+];
+
+/// Level index follows identifier, header and index of other data.
+const LEVEL_INDEX_OFFSET: usize = 80;
+
+impl<'a> TextureBuilder<'a> {
+    /// Parse KTX2 container and upload its mip levels and layers without conversion.
+    ///
+    /// Supports 2D textures, arrays and cubemaps in uncompressed RGBA8, BC, ETC2 and ASTC formats.
+    /// Basis Universal and supercompressed data is not supported.
+    /// Building texture fails with `BuildError::Format` if device doesn't support the format.
+    pub fn from_ktx2(bytes: &[u8]) -> Result<Self, ContainerError> {
+        if bytes.get(..IDENTIFIER.len()) != Some(&IDENTIFIER[..]) {
+            return Err(ContainerError::InvalidMagic);
+        }
+
+        let vk_format = read_u32(bytes, 12)?;
+        let width = read_u32(bytes, 20)?;
+        let height = read_u32(bytes, 24)?;
+        let depth = read_u32(bytes, 28)?;
+        let layers = read_u32(bytes, 32)?;
+        let faces = read_u32(bytes, 36)?;
+        let levels = read_u32(bytes, 40)?;
+        let supercompression = read_u32(bytes, 44)?;
+
+        if supercompression != 0 {
+            return Err(ContainerError::Unsupported("Supercompressed textures"));
+        }
+        if height == 0 {
+            return Err(ContainerError::Unsupported("1D textures"));
+        }
+        if depth != 0 {
+            return Err(ContainerError::Unsupported("3D textures"));
+        }
+        if faces != 1 && faces != 6 {
+            return Err(ContainerError::Unsupported("Cubemaps with missing faces"));
+        }
+
+        let layout = Layout {
+            format: vk_format_to_format(vk_format)
+                .ok_or(ContainerError::UnsupportedFormat(vk_format))?,
+            width,
+            height,
+            elements: layers.max(1),
+            array: layers != 0,
+            cube: faces == 6,
+            // Zero means mips should be generated, prebuilt data has only base level.
+            levels: levels.max(1),
+        };
+        // Validates number of levels and layers.
+        let kind = layout.kind()?;
+
+        // Levels are stored from the smallest, copy them in upload order.
+        let mut data = Vec::new();
+        for level in 0..layout.levels as Level {
+            let index = LEVEL_INDEX_OFFSET + level as usize * 24;
+            let offset = usize::try_from(read_u64(bytes, index)?)
+                .map_err(|_| ContainerError::UnexpectedEof)?;
+            let length = read_u64(bytes, index + 8)?;
+            let size = level_size(layout.format, kind, level)?;
+            if length < size as u64 {
+                return Err(ContainerError::UnexpectedEof);
+            }
+            let end = offset
+                .checked_add(size)
+                .ok_or(ContainerError::UnexpectedEof)?;
+            data.extend_from_slice(
+                bytes
+                    .get(offset..end)
+                    .ok_or(ContainerError::UnexpectedEof)?,
+            );
+        }
+
+        layout.builder(data)
+    }
+}
+
+fn vk_format_to_format(vk_format: u32) -> Option<Format> {
+    Some(match vk_format {
+        37 => Format::Rgba8Unorm,
+        43 => Format::Rgba8Srgb,
+        44 => Format::Bgra8Unorm,
+        50 => Format::Bgra8Srgb,
+        131 => Format::Bc1RgbUnorm,
+        132 => Format::Bc1RgbSrgb,
+        133 => Format::Bc1RgbaUnorm,
+        134 => Format::Bc1RgbaSrgb,
+        135 => Format::Bc2Unorm,
+        136 => Format::Bc2Srgb,
+        137 => Format::Bc3Unorm,
+        138 => Format::Bc3Srgb,
+        139 => Format::Bc4Unorm,
+        140 => Format::Bc4Snorm,
+        141 => Format::Bc5Unorm,
+        142 => Format::Bc5Snorm,
+        143 => Format::Bc6hUfloat,
+        144 => Format::Bc6hSfloat,
+        145 => Format::Bc7Unorm,
+        146 => Format::Bc7Srgb,
+        147 => Format::Etc2R8g8b8Unorm,
+        148 => Format::Etc2R8g8b8Srgb,
+        149 => Format::Etc2R8g8b8a1Unorm,
+        150 => Format::Etc2R8g8b8a1Srgb,
+        151 => Format::Etc2R8g8b8a8Unorm,
+        152 => Format::Etc2R8g8b8a8Srgb,
+        153 => Format::EacR11Unorm,
+        154 => Format::EacR11Snorm,
+        155 => Format::EacR11g11Unorm,
+        156 => Format::EacR11g11Snorm,
+        157 => Format::Astc4x4Unorm,
+        158 => Format::Astc4x4Srgb,
+        159 => Format::Astc5x4Unorm,
+        160 => Format::Astc5x4Srgb,
+        161 => Format::Astc5x5Unorm,
+        162 => Format::Astc5x5Srgb,
+        163 => Format::Astc6x5Unorm,
+        164 => Format::Astc6x5Srgb,
+        165 => Format::Astc6x6Unorm,
+        166 => Format::Astc6x6Srgb,
+        167 => Format::Astc8x5Unorm,
+        168 => Format::Astc8x5Srgb,
+        169 => Format::Astc8x6Unorm,
+        170 => Format::Astc8x6Srgb,
+        171 => Format::Astc8x8Unorm,
+        172 => Format::Astc8x8Srgb,
+        173 => Format::Astc10x5Unorm,
+        174 => Format::Astc10x5Srgb,
+        175 => Format::Astc10x6Unorm,
+        176 => Format::Astc10x6Srgb,
+        177 => Format::Astc10x8Unorm,
+        178 => Format::Astc10x8Srgb,
+        179 => Format::Astc10x10Unorm,
+        180 => Format::Astc10x10Srgb,
+        181 => Format::Astc12x10Unorm,
+        182 => Format::Astc12x10Srgb,
+        183 => Format::Astc12x12Unorm,
+        184 => Format::Astc12x12Srgb,
+        _ => return None,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use {
+        super::*,
+        crate::MipLevels,
+        rendy_core::hal::image::{Kind, ViewKind},
+    };
+
+    /// Build KTX2 file with levels stored from the smallest like tools do.
+    fn ktx2(
+        vk_format: u32,
+        width: u32,
+        height: u32,
+        layers: u32,
+        faces: u32,
+        levels: &[Vec<u8>],
+    ) -> Vec<u8> {
+        let mut bytes = IDENTIFIER.to_vec();
+        for value in &[
+            vk_format,
+            1,
+            width,
+            height,
+            0,
+            layers,
+            faces,
+            levels.len() as u32,
+            0,
+        ] {
+            bytes.extend_from_slice(&value.to_le_bytes());
+        }
+        bytes.resize(LEVEL_INDEX_OFFSET + levels.len() * 24, 0);
+
+        let mut offset = bytes.len();
+        let mut index = vec![(0, 0); levels.len()];
+        for (level, data) in levels.iter().enumerate().rev() {
+            index[level] = (offset, data.len());
+            offset += data.len();
+        }
+        for (level, &(offset, length)) in index.iter().enumerate() {
+            let entry = LEVEL_INDEX_OFFSET + level * 24;
+            bytes[entry..entry + 8].copy_from_slice(&(offset as u64).to_le_bytes());
+            bytes[entry + 8..entry + 16].copy_from_slice(&(length as u64).to_le_bytes());
+            bytes[entry + 16..entry + 24].copy_from_slice(&(length as u64).to_le_bytes());
+        }
+        for data in levels.iter().rev() {
+            bytes.extend_from_slice(data);
+        }
+        bytes
+    }
+
+    #[test]
+    fn bc7_mip_chain_is_loaded_largest_first() {
+        // 8x8 texture has 2x2 blocks in the first level and a single block in others.
+        let levels = vec![vec![1; 4 * 16], vec![2; 16], vec![3; 16], vec![4; 16]];
+        let builder = TextureBuilder::from_ktx2(&ktx2(145, 8, 8, 0, 1, &levels)).unwrap();
+
+        assert_eq!(builder.format(), Format::Bc7Unorm);
+        assert_eq!(builder.kind(), Kind::D2(8, 8, 1, 1));
+        assert_eq!(builder.view_kind(), ViewKind::D2);
+        match builder.mip_levels() {
+            MipLevels::Provided(levels) => assert_eq!(levels.get(), 4),
+            levels => panic!("Unexpected mip levels {:?}", levels),
+        }
+        assert_eq!(builder.data(), &levels.concat()[..]);
+    }
+
+    #[test]
+    fn bc1_cubemap_has_six_layers() {
+        let levels = vec![vec![0; 6 * 4 * 8], vec![0; 6 * 8]];
+        let builder = TextureBuilder::from_ktx2(&ktx2(133, 8, 8, 0, 6, &levels)).unwrap();
+
+        assert_eq!(builder.format(), Format::Bc1RgbaUnorm);
+        assert_eq!(builder.kind(), Kind::D2(8, 8, 6, 1));
+        assert_eq!(builder.view_kind(), ViewKind::Cube);
+    }
+
+    #[test]
+    fn truncated_and_unknown_data_is_rejected() {
+        let mut bytes = ktx2(145, 8, 8, 0, 1, &[vec![0; 4 * 16]]);
+        bytes.pop();
+        assert_eq!(
+            TextureBuilder::from_ktx2(&bytes).unwrap_err(),
+            ContainerError::UnexpectedEof
+        );
+        assert_eq!(
+            TextureBuilder::from_ktx2(&ktx2(1000, 8, 8, 0, 1, &[vec![0; 16]])).unwrap_err(),
+            ContainerError::UnsupportedFormat(1000)
+        );
+        assert_eq!(
+            TextureBuilder::from_ktx2(b"DDS ").unwrap_err(),
+            ContainerError::InvalidMagic
+        );
+    }
+
+    #[test]
+    fn overflowing_header_values_are_rejected() {
+        let mut bytes = ktx2(145, 8, 8, 0, 1, &[vec![0; 4 * 16]]);
+        bytes[LEVEL_INDEX_OFFSET..LEVEL_INDEX_OFFSET + 8].copy_from_slice(&u64::MAX.to_le_bytes());
+        assert_eq!(
+            TextureBuilder::from_ktx2(&bytes).unwrap_err(),
+            ContainerError::UnexpectedEof
+        );
+
+        // Level count beyond `MAX_LEVEL` is rejected before level index is read.
+        let mut bytes = ktx2(145, 8, 8, 0, 1, &[vec![0; 4 * 16]]);
+        bytes[40..44].copy_from_slice(&257u32.to_le_bytes());
+        assert_eq!(
+            TextureBuilder::from_ktx2(&bytes).unwrap_err(),
+            ContainerError::Unsupported("Textures with this many mip levels")
+        );
+    }
+}
@@ -11,7 +11,7 @@ use {
         },
     },
     rendy_core::hal::{
-        format::{Component, Format, Swizzle},
+        format::{self, Component, Format, Swizzle},
         image, Backend,
     },
     std::num::NonZeroU8,
@@ -71,6 +71,10 @@ pub enum MipLevels {
     /// Create the image with raw mip levels but without blitting the main
     /// texture data into them
     Levels(NonZeroU8),
+    /// Upload all mip levels from the data.
+    /// Levels are tightly packed one after another starting from the largest,
+    /// each level contains all layers.
+    Provided(NonZeroU8),
 }
 
 /// Calculate the number of mip levels for a 2D image with given dimensions
@@ -78,6 +82,28 @@ pub fn mip_levels_from_dims(width: u32, height: u32) -> u8 {
     ((32 - width.max(height).leading_zeros()).max(1) as u8).min(rendy_core::hal::image::MAX_LEVEL)
 }
 
+/// Size in bytes of tightly packed mip level with all layers of the image.
+/// Compressed levels smaller than a block still take a whole block.
+pub(crate) fn level_size(format: Format, kind: image::Kind, level: image::Level) -> usize {
+    checked_level_size(format, kind, level).expect("Level size overflows usize")
+}
+
+/// Same as `level_size` but returns `None` if size doesn't fit in `usize`.
+pub(crate) fn checked_level_size(
+    format: Format,
+    kind: image::Kind,
+    level: image::Level,
+) -> Option<usize> {
+    let desc = format.surface_desc();
+    let extent = kind.level_extent(level);
+    let blocks = |size: u32, dim: u8| (size as usize + dim as usize - 1) / dim as usize;
+    blocks(extent.width, desc.dim.0)
+        .checked_mul(blocks(extent.height, desc.dim.1))?
+        .checked_mul(extent.depth as usize)?
+        .checked_mul(kind.num_layers() as usize)?
+        .checked_mul(desc.bits as usize / 8)
+}
+
 #[derive(Debug)]
 pub enum BuildError {
     Format(Format),
@@ -85,9 +111,72 @@ pub enum BuildError {
     Upload(UploadError),
     ImageView(ImageViewCreationError),
     Mipmap(rendy_core::hal::device::OutOfMemory),
+    MipmapFormat(Format),
     Sampler(rendy_core::hal::device::AllocationError),
 }
 
+/// Error returned when cubemap faces can't be combined into a single texture.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum CubeError {
+    /// Face is not a single layer 2D image.
+    NotTwoDimensional {
+        /// Index of the face.
+        face: usize,
+    },
+    /// Face size, format, row pitch or mip levels differ from the first face.
+    Mismatch {
+        /// Index of the face.
+        face: usize,
+    },
+    /// Face data is smaller than its size and mip levels require.
+    NotEnoughData {
+        /// Index of the face.
+        face: usize,
+    },
+    /// Faces are not square.
+    NotSquare,
+    /// Face with provided mip levels has row pitch or height of data
+    /// different from its size. Provided levels must be tightly packed.
+    PaddedLevels {
+        /// Index of the face.
+        face: usize,
+    },
+}
+
+impl std::fmt::Display for CubeError {
+    fn fmt(&self, fmt: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            CubeError::NotTwoDimensional { face } => {
+                write!(fmt, "Cubemap face {} is not a single layer 2D image", face)
+            }
+            CubeError::Mismatch { face } => {
+                write!(fmt, "Cubemap face {} doesn't match the first face", face)
+            }
+            CubeError::NotEnoughData { face } => {
+                write!(fmt, "Cubemap face {} has not enough data", face)
+            }
+            CubeError::NotSquare => write!(fmt, "Cubemap faces are not square"),
+            CubeError::PaddedLevels { face } => write!(
+                fmt,
+                "Cubemap face {} has provided mip levels with padded rows",
+                face
+            ),
+        }
+    }
+}
+
+impl std::error::Error for CubeError {}
+
+fn same_mip_levels(lhs: MipLevels, rhs: MipLevels) -> bool {
+    match (lhs, rhs) {
+        (MipLevels::GenerateAuto, MipLevels::GenerateAuto) => true,
+        (MipLevels::GenerateLevels(l), MipLevels::GenerateLevels(r))
+        | (MipLevels::Levels(l), MipLevels::Levels(r))
+        | (MipLevels::Provided(l), MipLevels::Provided(r)) => l == r,
+        _ => false,
+    }
+}
+
 /// Generics-free texture builder.
 #[derive(Clone)]
 #[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
@@ -154,6 +243,87 @@ impl<'a> TextureBuilder<'a> {
         self
     }
 
+    /// Combine six faces into a cubemap texture with `Cube` view.
+    ///
+    /// Faces are placed into layers in order `+X`, `-X`, `+Y`, `-Y`, `+Z`, `-Z`
+    /// as is, without flipping.
+    /// All faces must be single layer square 2D images of the same size, format,
+    /// row pitch and mip levels.
+    /// Provided mip levels must be tightly packed, as `build` uploads them.
+    /// Sampler, swizzle and premultiplied alpha are taken from the first face.
+    pub fn cube(faces: [TextureBuilder<'_>; 6]) -> Result<Self, CubeError> {
+        let first = &faces[0];
+        let (width, height) = match first.kind {
+            image::Kind::D2(width, height, 1, 1) => (width, height),
+            _ => return Err(CubeError::NotTwoDimensional { face: 0 }),
+        };
+        if width != height {
+            return Err(CubeError::NotSquare);
+        }
+
+        for (index, face) in faces.iter().enumerate().skip(1) {
+            match face.kind {
+                image::Kind::D2(_, _, 1, 1) => {}
+                _ => return Err(CubeError::NotTwoDimensional { face: index }),
+            }
+            if face.kind != first.kind
+                || face.format != first.format
+                || face.data_width != first.data_width
+                || face.data_height != first.data_height
+                || !same_mip_levels(face.mip_levels, first.mip_levels)
+            {
+                return Err(CubeError::Mismatch { face: index });
+            }
+        }
+
+        // Single level is uploaded with row pitch and height of the data.
+        let pitch = |size: u32, data_size: u32| if data_size == 0 { size } else { data_size };
+        let data_kind = image::Kind::D2(
+            pitch(width, first.data_width),
+            pitch(height, first.data_height),
+            1,
+            1,
+        );
+
+        // Provided levels contain all layers of one level before the next level.
+        let levels = match first.mip_levels {
+            MipLevels::Provided(levels) => {
+                if data_kind != first.kind {
+                    return Err(CubeError::PaddedLevels { face: 0 });
+                }
+                levels.get()
+            }
+            _ => 1,
+        };
+
+        let mut data = Vec::with_capacity(faces.iter().map(|face| face.data.len()).sum());
+        let mut offset = 0;
+        for level in 0..levels {
+            let size = level_size(first.format, data_kind, level);
+            for (index, face) in faces.iter().enumerate() {
+                let level_data = face
+                    .data
+                    .get(offset..offset + size)
+                    .ok_or(CubeError::NotEnoughData { face: index })?;
+                data.extend_from_slice(level_data);
+            }
+            offset += size;
+        }
+
+        Ok(TextureBuilder {
+            kind: image::Kind::D2(width, height, 6, 1),
+            view_kind: image::ViewKind::Cube,
+            format: first.format,
+            data: std::borrow::Cow::Owned(data),
+            data_width: first.data_width,
+            data_height: first.data_height,
+            sampler_info: first.sampler_info.clone(),
+            swizzle: first.swizzle,
+            mip_levels: first.mip_levels,
+            premultiplied: first.premultiplied,
+        })
+    }
+
     /// Set pixel data.
     pub fn with_data<P: AsPixel>(mut self, data: impl Into<std::borrow::Cow<'a, [P]>>) -> Self {
         self.set_data(data);
@@ -191,6 +361,31 @@ impl<'a> TextureBuilder<'a> {
         self
     }
 
+    /// Get pixel data.
+    pub fn data(&self) -> &[u8] {
+        &self.data
+    }
+
+    /// Get format of the pixel data.
+    pub fn format(&self) -> Format {
+        self.format
+    }
+
+    /// Get image kind.
+    pub fn kind(&self) -> image::Kind {
+        self.kind
+    }
+
+    /// Get image view kind.
+    pub fn view_kind(&self) -> image::ViewKind {
+        self.view_kind
+    }
+
+    /// Get number of generated or raw mip levels.
+    pub fn mip_levels(&self) -> MipLevels {
+        self.mip_levels
+    }
+
     /// Set pixel data width.
     pub fn with_data_width(mut self, data_width: u32) -> Self {
         self.set_data_width(data_width);
@@ -227,6 +422,19 @@ impl<'a> TextureBuilder<'a> {
         self
     }
 
+    /// Generate full mip chain from uploaded first level.
+    /// Same as `with_mip_levels(MipLevels::GenerateAuto)`.
+    pub fn with_generated_mips(mut self) -> Self {
+        self.set_generated_mips();
+        self
+    }
+
+    /// Generate full mip chain from uploaded first level.
+    /// Same as `set_mip_levels(MipLevels::GenerateAuto)`.
+    pub fn set_generated_mips(&mut self) -> &mut Self {
+        self.set_mip_levels(MipLevels::GenerateAuto)
+    }
+
     /// Set image extent.
     pub fn with_kind(mut self, kind: image::Kind) -> Self {
         self.set_kind(kind);
@@ -252,12 +460,16 @@ impl<'a> TextureBuilder<'a> {
     }
 
     /// With image sampler info.
+    /// Configures LOD bias, LOD range and anisotropy of the texture's sampler.
+    /// Anisotropy is clamped to what device supports when texture is built.
     pub fn with_sampler_info(mut self, sampler_info: rendy_core::hal::image::SamplerDesc) -> Self {
         self.set_sampler_info(sampler_info);
         self
     }
 
     /// Set image sampler info.
+    /// Configures LOD bias, LOD range and anisotropy of the texture's sampler.
+    /// Anisotropy is clamped to what device supports when texture is built.
     pub fn set_sampler_info(
         &mut self,
         sampler_info: rendy_core::hal::image::SamplerDesc,
@@ -307,7 +519,7 @@ impl<'a> TextureBuilder<'a> {
 
         let (mip_levels, generate_mips) = match self.mip_levels {
             MipLevels::GenerateLevels(val) => (val.get(), true),
-            MipLevels::Levels(val) => (val.get(), false),
+            MipLevels::Levels(val) | MipLevels::Provided(val) => (val.get(), false),
             MipLevels::GenerateAuto => match self.kind {
                 rendy_core::hal::image::Kind::D1(_, _) => (1, false),
                 rendy_core::hal::image::Kind::D2(w, h, _, _) => (mip_levels_from_dims(w, h), true),
@@ -315,6 +527,11 @@ impl<'a> TextureBuilder<'a> {
             },
         };
 
+        let provided = match self.mip_levels {
+            MipLevels::Provided(_) => true,
+            _ => false,
+        };
+
         let (info, transform, transform_swizzle) = find_compatible_format(
             factory,
             ImageInfo {
@@ -330,6 +547,24 @@ impl<'a> TextureBuilder<'a> {
         )
         .ok_or(BuildError::Format(self.format))?;
 
+        let mip_filter = if generate_mips && mip_levels > 1 {
+            let features = rendy_core::hal::adapter::PhysicalDevice::format_properties(
+                factory.physical(),
+                Some(info.format),
+            )
+            .optimal_tiling;
+            let filter = mip_filter(features).ok_or(BuildError::MipmapFormat(info.format))?;
+            if filter == image::Filter::Nearest {
+                log::warn!(
+                    "Format {:?} doesn't support linear filtering, mips are generated with nearest filter",
+                    info.format
+                );
+            }
+            filter
+        } else {
+            image::Filter::Linear
+        };
+
         let image: Handle<Image<B>> = factory
             .create_image(info, Data)
             .map_err(BuildError::Image)?
@@ -375,30 +610,64 @@ impl<'a> TextureBuilder<'a> {
         // The reason that factory.upload_image is unsafe is that the image being uploaded
         // must have been created by the same factory and that it is not in use; we guarantee
         // that here because we just created the image on the same factory right before.
-        unsafe {
+        if provided {
+            profile_scope!("upload_levels");
+
+            let mut offset = 0;
+            for level in 0..mip_levels {
+                let size = level_size(info.format, info.kind, level);
+                assert!(
+                    offset + size <= buffer.len(),
+                    "Data is too small for {} mip levels",
+                    mip_levels
+                );
+                unsafe {
+                    factory
+                        .upload_image(
+                            image.clone(),
+                            0,
+                            0,
+                            image::SubresourceLayers {
+                                aspects: info.format.surface_desc().aspects,
+                                level,
+                                layers: 0..info.kind.num_layers(),
+                            },
+                            image::Offset::ZERO,
+                            info.kind.level_extent(level),
+                            &buffer[offset..offset + size],
+                            image::Layout::Undefined,
+                            next_state,
+                        )
+                        .map_err(BuildError::Upload)?;
+                }
+                offset += size;
+            }
+        } else {
             profile_scope!("upload_image");
 
-            factory
-                .upload_image(
-                    image.clone(),
-                    self.data_width,
-                    self.data_height,
-                    image::SubresourceLayers {
-                        aspects: info.format.surface_desc().aspects,
-                        level: 0,
-                        layers: 0..info.kind.num_layers(),
-                    },
-                    image::Offset::ZERO,
-                    info.kind.extent(),
-                    buffer,
-                    image::Layout::Undefined,
-                    if !generate_mips || mip_levels == 1 {
-                        next_state
-                    } else {
-                        mip_state
-                    },
-                )
-                .map_err(BuildError::Upload)?;
+            unsafe {
+                factory
+                    .upload_image(
+                        image.clone(),
+                        self.data_width,
+                        self.data_height,
+                        image::SubresourceLayers {
+                            aspects: info.format.surface_desc().aspects,
+                            level: 0,
+                            layers: 0..info.kind.num_layers(),
+                        },
+                        image::Offset::ZERO,
+                        info.kind.extent(),
+                        buffer,
+                        image::Layout::Undefined,
+                        if !generate_mips || mip_levels == 1 {
+                            next_state
+                        } else {
+                            mip_state
+                        },
+                    )
+                    .map_err(BuildError::Upload)?;
+            }
         }
 
         if mip_levels > 1 && generate_mips {
@@ -409,13 +678,13 @@ impl<'a> TextureBuilder<'a> {
                     .fill_mips(
                         factory.device(),
                         image.clone(),
-                        image::Filter::Linear,
+                        mip_filter,
                         std::iter::once(mip_state).chain(std::iter::repeat(undef_state)),
                         std::iter::repeat(next_state),
                     )
                     .map_err(BuildError::Mipmap)?;
             }
-        } else if mip_levels > 1 && !generate_mips {
+        } else if mip_levels > 1 && !provided {
             unsafe {
                 factory.transition_image(
                     image.clone(),
@@ -449,8 +718,14 @@ impl<'a> TextureBuilder<'a> {
                 .map_err(BuildError::ImageView)?
         };
 
+        let sampler_info = clamp_anisotropy(
+            self.sampler_info.clone(),
+            factory.features(),
+            rendy_core::hal::adapter::PhysicalDevice::limits(factory.physical())
+                .max_sampler_anisotropy,
+        );
         let sampler = factory
-            .get_sampler(self.sampler_info.clone())
+            .get_sampler(sampler_info)
             .map_err(BuildError::Sampler)?;
 
         Ok(Texture {
@@ -462,6 +737,42 @@ impl<'a> TextureBuilder<'a> {
     }
 }
 
+/// Clamp anisotropy of the sampler to `max_anisotropy`.
+/// Anisotropic filtering is disabled if device doesn't support it.
+fn clamp_anisotropy(
+    mut info: image::SamplerDesc,
+    features: rendy_core::hal::Features,
+    max_anisotropy: f32,
+) -> image::SamplerDesc {
+    if let image::Anisotropic::On(level) = info.anisotropic {
+        if !features.contains(rendy_core::hal::Features::SAMPLER_ANISOTROPY) {
+            log::warn!("Anisotropic filtering is not supported, sampler is created without it");
+            info.anisotropic = image::Anisotropic::Off;
+        } else if f32::from(level) > max_anisotropy {
+            let clamped = max_anisotropy.max(1.0) as u8;
+            log::warn!(
+                "Anisotropy {} exceeds device limit, clamped to {}",
+                level,
+                clamped
+            );
+            info.anisotropic = image::Anisotropic::On(clamped);
+        }
+    }
+    info
+}
+
+/// Filter to generate mips with blits for format with specified optimal tiling features.
+/// Returns `None` if format can't be blitted.
+fn mip_filter(features: format::ImageFeature) -> Option<image::Filter> {
+    if !features.contains(format::ImageFeature::BLIT_SRC | format::ImageFeature::BLIT_DST) {
+        None
+    } else if features.contains(format::ImageFeature::SAMPLED_LINEAR) {
+        Some(image::Filter::Linear)
+    } else {
+        Some(image::Filter::Nearest)
+    }
+}
+
 enum BufferTransform {
     Intact,
     AddPadding {
@@ -675,3 +986,176 @@ fn buf_add_padding(buffer: &[u8], dst_slice: &mut [u8], stride: usize, padding:
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn full_mip_chain_is_generated() {
+        assert_eq!(mip_levels_from_dims(256, 256), 9);
+        assert_eq!(mip_levels_from_dims(300, 17), 9);
+        assert_eq!(mip_levels_from_dims(1, 1), 1);
+
+        // Each level is `max(1, dim >> 1)` of the previous one.
+        let kind = image::Kind::D2(300, 17, 1, 1);
+        let last = kind.level_extent(mip_levels_from_dims(300, 17) - 1);
+        assert_eq!((last.width, last.height), (1, 1));
+        assert_eq!(kind.level_extent(5).height, 1);
+    }
+
+    #[test]
+    fn compressed_levels_round_up_to_blocks() {
+        let kind = image::Kind::D2(8, 4, 6, 1);
+        // 2x1 blocks of 8 bytes per face.
+        assert_eq!(level_size(Format::Bc1RgbaUnorm, kind, 0), 2 * 8 * 6);
+        // Smaller levels take one block.
+        assert_eq!(level_size(Format::Bc1RgbaUnorm, kind, 2), 8 * 6);
+        assert_eq!(level_size(Format::Bc7Unorm, kind, 3), 16 * 6);
+        assert_eq!(level_size(Format::Rgba8Unorm, kind, 1), 4 * 2 * 4 * 6);
+    }
+
+    #[test]
+    fn mip_filter_follows_format_features() {
+        let blit = format::ImageFeature::BLIT_SRC | format::ImageFeature::BLIT_DST;
+        assert_eq!(
+            mip_filter(blit | format::ImageFeature::SAMPLED_LINEAR),
+            Some(image::Filter::Linear)
+        );
+        assert_eq!(mip_filter(blit), Some(image::Filter::Nearest));
+        assert_eq!(mip_filter(format::ImageFeature::BLIT_SRC), None);
+    }
+
+    #[test]
+    fn anisotropy_is_clamped_to_device_limit() {
+        use rendy_core::hal::Features;
+
+        let mut info = image::SamplerDesc::new(image::Filter::Linear, image::WrapMode::Tile);
+        info.anisotropic = image::Anisotropic::On(8);
+
+        let clamped = clamp_anisotropy(info.clone(), Features::SAMPLER_ANISOTROPY, 4.0);
+        assert_eq!(clamped.anisotropic, image::Anisotropic::On(4));
+
+        let supported = clamp_anisotropy(info.clone(), Features::SAMPLER_ANISOTROPY, 16.0);
+        assert_eq!(supported.anisotropic, image::Anisotropic::On(8));
+
+        let disabled = clamp_anisotropy(info, Features::empty(), 16.0);
+        assert_eq!(disabled.anisotropic, image::Anisotropic::Off);
+    }
+
+    #[test]
+    fn cube_faces_are_placed_into_layers() {
+        use crate::pixel::Rgba8Unorm;
+
+        let colors: Vec<[u8; 4]> = (0..6).map(|n| [n * 40, 255 - n * 40, n, 255]).collect();
+        let face = |color: [u8; 4]| {
+            TextureBuilder::new()
+                .with_kind(image::Kind::D2(2, 2, 1, 1))
+                .with_data(vec![Rgba8Unorm { repr: color }; 4])
+        };
+
+        let cube = TextureBuilder::cube([
+            face(colors[0]),
+            face(colors[1]),
+            face(colors[2]),
+            face(colors[3]),
+            face(colors[4]),
+            face(colors[5]),
+        ])
+        .unwrap();
+
+        assert_eq!(cube.kind(), image::Kind::D2(2, 2, 6, 1));
+        assert_eq!(cube.view_kind(), image::ViewKind::Cube);
+        assert_eq!(cube.format(), Format::Rgba8Unorm);
+
+        // Layer `n` is sampled for face direction `n` in +X, -X, +Y, -Y, +Z, -Z order.
+        let layer_size = 2 * 2 * 4;
+        for (layer, color) in colors.iter().enumerate() {
+            let texels = &cube.data()[layer * layer_size..(layer + 1) * layer_size];
+            assert!(texels.chunks(4).all(|texel| texel == &color[..]));
+        }
+
+        // Texels keep their place in face, so faces are not flipped or rotated.
+        let marked = |face: u8| {
+            let texels: Vec<_> = (0..4u8)
+                .map(|texel| Rgba8Unorm {
+                    repr: [face, texel, 0, 255],
+                })
+                .collect();
+            TextureBuilder::new()
+                .with_kind(image::Kind::D2(2, 2, 1, 1))
+                .with_data(texels)
+        };
+        let cube = TextureBuilder::cube([
+            marked(0),
+            marked(1),
+            marked(2),
+            marked(3),
+            marked(4),
+            marked(5),
+        ])
+        .unwrap();
+        for (index, texel) in cube.data().chunks(4).enumerate() {
+            assert_eq!(texel, &[(index / 4) as u8, (index % 4) as u8, 0, 255]);
+        }
+
+        let small = TextureBuilder::new()
+            .with_kind(image::Kind::D2(1, 1, 1, 1))
+            .with_data(vec![Rgba8Unorm { repr: colors[0] }]);
+        match TextureBuilder::cube([
+            face(colors[0]),
+            face(colors[1]),
+            small,
+            face(colors[3]),
+            face(colors[4]),
+            face(colors[5]),
+        ]) {
+            Err(CubeError::Mismatch { face: 2 }) => {}
+            other => panic!("Unexpected result {:?}", other),
+        }
+
+        let wide = || {
+            TextureBuilder::new()
+                .with_kind(image::Kind::D2(2, 1, 1, 1))
+                .with_data(vec![Rgba8Unorm { repr: colors[0] }; 2])
+        };
+        match TextureBuilder::cube([wide(), wide(), wide(), wide(), wide(), wide()]) {
+            Err(CubeError::NotSquare) => {}
+            other => panic!("Unexpected result {:?}", other),
+        }
+    }
+
+    #[test]
+    fn cube_faces_keep_row_pitch() {
+        use crate::pixel::Rgba8Unorm;
+
+        // Rows of 2x2 faces are padded to 3 texels.
+        let face = |n: u8| {
+            TextureBuilder::new()
+                .with_kind(image::Kind::D2(2, 2, 1, 1))
+                .with_data_width(3)
+                .with_data(vec![Rgba8Unorm { repr: [n; 4] }; 3 * 2])
+        };
+        let cube =
+            TextureBuilder::cube([face(0), face(1), face(2), face(3), face(4), face(5)]).unwrap();
+        let layer_size = 3 * 2 * 4;
+        assert_eq!(cube.data().len(), 6 * layer_size);
+        for (layer, texels) in cube.data().chunks(layer_size).enumerate() {
+            assert!(texels.iter().all(|&byte| byte == layer as u8));
+        }
+
+        let provided =
+            |n: u8| face(n).with_mip_levels(MipLevels::Provided(NonZeroU8::new(2).unwrap()));
+        match TextureBuilder::cube([
+            provided(0),
+            provided(1),
+            provided(2),
+            provided(3),
+            provided(4),
+            provided(5),
+        ]) {
+            Err(CubeError::PaddedLevels { face: 0 }) => {}
+            other => panic!("Unexpected result {:?}", other),
+        }
+    }
+}
@@ -39,6 +39,7 @@ pub enum SwapchainError {
 pub struct Surface<B: Backend> {
     raw: B::Surface,
     instance: InstanceId,
+    preferred_formats: Vec<Format>,
 }
 
 impl<B> std::fmt::Debug for Surface<B>
@@ -48,6 +49,7 @@ where
     fn fmt(&self, fmt: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         fmt.debug_struct("Surface")
             .field("instance", &self.instance)
+            .field("preferred_formats", &self.preferred_formats)
             .finish()
     }
 }
@@ -67,6 +69,7 @@ where
         Ok(Surface {
             raw,
             instance: instance.id(),
+            preferred_formats: Vec::new(),
         })
     }
 
@@ -82,6 +85,7 @@ where
         Surface {
             raw: f(instance.raw()),
             instance: instance.id(),
+            preferred_formats: Vec::new(),
         }
     }
 
@@ -90,6 +94,7 @@ where
         Surface {
             raw: surface,
             instance,
+            preferred_formats: Vec::new(),
         }
     }
 }
@@ -109,23 +114,25 @@ where
     }
 
     /// Get surface ideal format.
+    /// This is the first preferred format supported by the surface
+    /// or the best supported format if none of preferred are.
+    /// Swapchains are created with this format.
     pub unsafe fn format(&self, physical_device: &B::PhysicalDevice) -> Format {
-        if let Some(formats) = self.raw.supported_formats(physical_device) {
-            *formats
-                .iter()
-                .max_by_key(|format| {
-                    let base = format.base_format();
-                    let desc = base.0.desc();
-                    (
-                        !desc.is_compressed(),
-                        base.1 == rendy_core::hal::format::ChannelType::Srgb,
-                        desc.bits,
-                    )
-                })
-                .expect("At least one format must be supported by the surface")
-        } else {
-            Format::Rgba8Srgb
-        }
+        pick_format(
+            &self.preferred_formats,
+            self.raw.supported_formats(physical_device).as_deref(),
+        )
+    }
+
+    /// Set formats to create swapchain with in order of preference.
+    /// E.g. `A2b10g10r10Unorm` for wider range than 8-bit formats provide.
+    pub fn set_preferred_formats(&mut self, formats: &[Format]) {
+        self.preferred_formats = formats.to_vec();
+    }
+
+    /// Get formats to create swapchain with in order of preference.
+    pub fn preferred_formats(&self) -> &[Format] {
+        &self.preferred_formats
     }
 
     /// Get formats supported by surface
@@ -188,6 +195,31 @@ where
     }
 }
 
+/// Pick first of `preferred` formats that is `supported`.
+/// Otherwise pick uncompressed sRGB format with most bits.
+/// `None` means the surface supports any format.
+pub fn pick_format(preferred: &[Format], supported: Option<&[Format]>) -> Format {
+    match supported {
+        Some(supported) => preferred
+            .iter()
+            .find(|format| supported.contains(format))
+            .or_else(|| {
+                supported.iter().max_by_key(|format| {
+                    let base = format.base_format();
+                    let desc = base.0.desc();
+                    (
+                        !desc.is_compressed(),
+                        base.1 == rendy_core::hal::format::ChannelType::Srgb,
+                        desc.bits,
+                    )
+                })
+            })
+            .cloned()
+            .expect("At least one format must be supported by the surface"),
+        None => preferred.first().cloned().unwrap_or(Format::Rgba8Srgb),
+    }
+}
+
 unsafe fn create_swapchain<B: Backend>(
     surface: &mut Surface<B>,
     physical_device: &B::PhysicalDevice,
@@ -217,8 +249,10 @@ unsafe fn create_swapchain<B: Backend>(
 
     log::trace!("Surface chosen format {:#?}", format);
 
+    // Zero maximum means image count is not limited.
+    let max_image_count = *capabilities.image_count.end();
     if image_count < *capabilities.image_count.start()
-        || image_count > *capabilities.image_count.end()
+        || (max_image_count != 0 && image_count > max_image_count)
     {
         log::warn!(
             "Image count not supported. Supported: {:#?}, requested: {:#?}",
@@ -420,12 +454,23 @@ where
         self.usage
     }
 
+    /// Get present mode of the swapchain.
+    pub fn present_mode(&self) -> rendy_core::hal::window::PresentMode {
+        self.present_mode
+    }
+
+    /// Set present mode to use.
+    /// Takes effect when swapchain is recreated.
+    pub fn set_present_mode(&mut self, present_mode: rendy_core::hal::window::PresentMode) {
+        self.present_mode = present_mode;
+    }
+
     /// Acquire next image.
     pub unsafe fn next_image(
         &mut self,
         signal: &B::Semaphore,
     ) -> Result<NextImages<'_, B>, rendy_core::hal::window::AcquireError> {
-        let index = rendy_core::hal::window::Swapchain::acquire_image(
+        let (index, suboptimal) = rendy_core::hal::window::Swapchain::acquire_image(
             // Missing swapchain is equivalent to OutOfDate, as it has to be recreated anyway.
             self.swapchain
                 .as_mut()
@@ -433,11 +478,11 @@ where
             !0,
             Some(signal),
             None,
-        )?
-        .0;
+        )?;
 
         Ok(NextImages {
             targets: std::iter::once((&*self, index)).collect(),
+            suboptimal: suboptimal.is_some(),
         })
     }
 }
@@ -446,6 +491,7 @@ where
 #[derive(Debug)]
 pub struct NextImages<'a, B: Backend> {
     targets: smallvec::SmallVec<[(&'a Target<B>, u32); 8]>,
+    suboptimal: bool,
 }
 
 impl<'a, B> NextImages<'a, B>
@@ -457,6 +503,12 @@ where
         self.targets.iter().map(|(_s, i)| *i)
     }
 
+    /// Check if swapchain no longer matches the surface exactly.
+    /// Images can still be presented, but swapchain should be recreated.
+    pub fn is_suboptimal(&self) -> bool {
+        self.suboptimal
+    }
+
     /// Present images by the queue.
     ///
     /// # TODO
@@ -495,3 +547,35 @@ where
         &self.targets[index].1
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use {super::pick_format, rendy_core::hal::format::Format};
+
+    const SUPPORTED: &[Format] = &[Format::Bgra8Srgb, Format::A2b10g10r10Unorm];
+
+    #[test]
+    fn preferred_format_is_picked() {
+        assert_eq!(
+            pick_format(
+                &[Format::Rgba16Sfloat, Format::A2b10g10r10Unorm],
+                Some(SUPPORTED)
+            ),
+            Format::A2b10g10r10Unorm
+        );
+    }
+
+    #[test]
+    fn srgb_is_picked_by_default() {
+        assert_eq!(pick_format(&[], Some(SUPPORTED)), Format::Bgra8Srgb);
+        assert_eq!(
+            pick_format(&[Format::Rgba16Sfloat], Some(SUPPORTED)),
+            Format::Bgra8Srgb
+        );
+        assert_eq!(pick_format(&[], None), Format::Rgba8Srgb);
+        assert_eq!(
+            pick_format(&[Format::Rgba16Sfloat], None),
+            Format::Rgba16Sfloat
+        );
+    }
+}